@@ -3,6 +3,7 @@
 # Make build scripts executable.
 chmod +x /workspaces/riscos/src/scripts/build-debug.sh
 chmod +x /workspaces/riscos/src/scripts/build-release.sh
+chmod +x /workspaces/riscos/src/scripts/run-integration-tests.sh
 
 # Check if the build dependencies are available.
 command -v riscv64-unknown-elf-ld >/dev/null 2>&1 || { echo "RISC-V toolchain not installed"; exit 1; }
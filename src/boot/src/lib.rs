@@ -1,17 +1,34 @@
 #![no_std]
 
-mod dtb;
-mod sbi;
 mod startup;
 
-use boot_lib::memory::{mmu::PageTable, physical_memory_allocator::PhysicalMemoryAllocator};
+use boot_lib::memory::{
+    mmu::{PageTable, set_paging_mode},
+    physical_memory_allocator::PhysicalMemoryAllocator,
+};
+use common_lib::{
+    backtrace::write_backtrace,
+    boot_info::{BootMilestone, BootTimeline},
+    capture_registers,
+    layout::Layout,
+};
 use core::arch::{asm, global_asm};
+use core::ops::Range;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use sbi_lib::{debug_console::DebugConsoleWriter, debug_println};
 use startup::memory::print_physical_memory_stats;
 use startup::{
-    dtb::{get_dtb_header, print_dtb_structure, print_reserved_memory_regions},
+    boot_info::create_boot_info,
+    dtb::{
+        copy_dtb_to_allocated_pages, get_dtb, print_dtb_structure, print_reserved_memory_regions,
+    },
+    kaslr::choose_kernel_slide,
+    kernel::load_kernel,
     memory::{create_memory_map, create_physical_memory_allocator, print_memory_regions},
     mmu::setup_mmu,
+    paging_mode::probe_highest_paging_mode,
+    timing::read_time,
 };
 
 /// Primary entry point for the boot process after any low level assembly is
@@ -21,57 +38,103 @@ use startup::{
 /// # Arguments
 ///
 /// * `hart_id` - The hardware thread ID that called this function.
-/// * `dtb_address` - Pointer to the device tree blob.
+/// * `dtb_physical_address` - Pointer to the device tree blob.
 #[unsafe(no_mangle)]
 pub fn boot_main(hart_id: usize, dtb_physical_address: usize) -> ! {
+    let mut timeline = BootTimeline::new();
+    timeline.record(BootMilestone::BootEntered, read_time());
+
     debug_println!("\nKernel booting on hart ID: {}\n", hart_id);
 
-    let dtb_header = get_dtb_header(dtb_physical_address);
+    let dtb = get_dtb(dtb_physical_address);
+    timeline.record(BootMilestone::DtbParsed, read_time());
 
-    print_reserved_memory_regions(dtb_header);
-    print_dtb_structure(dtb_header);
+    print_reserved_memory_regions(&dtb);
+    print_dtb_structure(&dtb);
 
-    let mut memory_map = create_memory_map(dtb_header);
-    print_memory_regions(&mut memory_map);
+    let mut memory_map = create_memory_map(&dtb);
+    timeline.record(BootMilestone::MemoryMapBuilt, read_time());
+    print_memory_regions(&memory_map);
 
     let mut physical_memory_allocator = create_physical_memory_allocator(&mut memory_map);
 
-    let root_page_table_pointer = physical_memory_allocator
+    // The kernel uses its own copy of the DTB so that the firmware's copy can
+    // be treated as free memory.
+    let dtb = copy_dtb_to_allocated_pages(&dtb, &mut physical_memory_allocator);
+
+    let root_page_table_address = physical_memory_allocator
         .allocate_page()
         .expect("Failed to allocate page for root page table.");
 
-    let mut root_page_table = unsafe { &mut *(root_page_table_pointer as *mut PageTable) };
+    let boot_info_address = physical_memory_allocator
+        .allocate_page()
+        .expect("Failed to allocate page for the boot information.");
+
+    let mut root_page_table =
+        unsafe { &mut *(root_page_table_address.raw_address() as *mut PageTable) };
     root_page_table.clear();
 
+    // The largest paging mode decides how many levels the page tables have
+    // and where the windows of the kernel half are, including the kernel's.
+    let paging_mode = probe_highest_paging_mode(root_page_table_address, root_page_table);
+    let layout = Layout::new(paging_mode);
+    set_paging_mode(paging_mode);
+
+    let kernel_slide = choose_kernel_slide(&dtb, &layout);
+    let kernel_image = load_kernel(&mut physical_memory_allocator, &layout, kernel_slide);
+
     setup_mmu(
-        root_page_table_pointer as usize,
+        root_page_table_address,
         &mut root_page_table,
+        &layout,
+        &kernel_image,
+        &dtb,
         &mut physical_memory_allocator,
+    )
+    .expect("Failed to set up the MMU.");
+
+    timeline.record(BootMilestone::MmuReady, read_time());
+
+    create_boot_info(
+        boot_info_address,
+        hart_id,
+        &kernel_image,
+        &dtb,
+        root_page_table_address,
+        &layout,
+        &timeline,
+        &memory_map,
+        &physical_memory_allocator,
     );
 
     print_physical_memory_stats(physical_memory_allocator);
 
-    // Jump to the kernel at virtual address 0xFFFF_FFC0_0000_0000.
-    // Pass hart_id in a0, dtb_address in a1, and root_page_table_pointer in a2.
+    // Jump to the entry point of the kernel ELF.
+    // Pass hart_id in a0 and the physical address of the BootInfo in a1.
     unsafe {
         asm!(
-            "
-            mv a0, {0}
-            mv a1, {1}
-            mv a2, {2}
-            li t0, 0xFFFFFFC000000000
-            jr t0
-            ",
-            in(reg) hart_id,
-            in(reg) dtb_physical_address,
-            in(reg) root_page_table_pointer as usize,
+            "jr {0}",
+            in(reg) kernel_image.entry_point.raw_address(),
+            in("a0") hart_id,
+            in("a1") boot_info_address.raw_address(),
             options(noreturn)
         );
     }
 }
 
+/// Set while the panic report is printed so a panic raised while printing it
+/// does not recurse.
+static IS_PANICKING: AtomicBool = AtomicBool::new(false);
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    let registers = capture_registers!();
+
+    if IS_PANICKING.swap(true, Ordering::AcqRel) {
+        debug_println!("\n===== NESTED BOOT PANIC =====\n{}", info);
+        loop {}
+    }
+
     debug_println!("\n\n===== BOOT PANIC =====");
 
     // Print location information if available.
@@ -89,12 +152,46 @@ fn panic(info: &PanicInfo) -> ! {
     // Print the panic message directly.
     debug_println!("Panic message: {}", info);
 
+    debug_println!("\nRegisters:\n{}", registers);
+
+    // The boot stage has no symbol table, so only addresses are printed.
+    debug_println!("\nBacktrace:");
+    let _ = unsafe {
+        write_backtrace(
+            &mut DebugConsoleWriter,
+            registers.frame_pointer,
+            boot_stack_range(),
+            &|_| None,
+        )
+    };
+
     debug_println!("=========================\n");
 
+    #[cfg(feature = "shutdown-on-panic")]
+    {
+        let error =
+            sbi_lib::system_reset::shutdown(sbi_lib::system_reset::RESET_REASON_SYSTEM_FAILURE);
+        debug_println!("SBI shutdown failed: {}.", error);
+    }
+
     // Halt the boot process.
     loop {}
 }
 
+/// Returns the addresses occupied by the boot stack, which the boot stage
+/// runs on from entry until it jumps to the kernel.
+fn boot_stack_range() -> Range<usize> {
+    unsafe extern "C" {
+        static _boot_stack_start: usize;
+        static _boot_stack_length: usize;
+    }
+
+    let boot_stack_start = unsafe { &_boot_stack_start as *const _ as usize };
+    let boot_stack_length = unsafe { &_boot_stack_length as *const _ as usize };
+
+    boot_stack_start..boot_stack_start + boot_stack_length
+}
+
 global_asm!(
     "
     .global _boot_entrypoint
@@ -108,10 +205,15 @@ global_asm!(
     .section .text.boot_entrypoint
     
     _boot_entrypoint:
-        // For now, all secondary harts (hart ID != 0) will loop forever. The
-        // riscv spec requires that there be at least one hart that has hart ID
-        // 0.
-        bnez a0, secondary_hart
+        // Only one hart may run the boot stage. With the SBI HSM extension the
+        // firmware only enters here on a single boot hart, which is not
+        // necessarily hart 0, and the kernel starts the remaining harts
+        // directly. Firmware without HSM may enter here on every hart at once,
+        // so the first hart to increment the lottery wins and the rest park.
+        la t0, boot_hart_lottery
+        li t1, 1
+        amoadd.w t1, t1, (t0)
+        bnez t1, secondary_hart
 
         // Disable all supervisor level interrupts globally.
         csrci sstatus, 2
@@ -146,5 +248,12 @@ global_asm!(
     secondary_hart:
         wfi
         j secondary_hart
+
+    // The lottery lives in .data rather than .bss since the winning hart
+    // clears .bss after the other harts have already read the lottery.
+    .section .data
+    .balign 4
+    boot_hart_lottery:
+        .word 0
     "
 );
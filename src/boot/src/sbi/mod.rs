@@ -1,2 +0,0 @@
-pub mod debug_console;
-pub mod sbi_calls;
@@ -0,0 +1,87 @@
+use super::kernel::KernelImage;
+use boot_lib::memory::{
+    memory_map::MemoryMap,
+    physical_memory_allocator::{PhysicalBumpAllocator, PhysicalMemoryAllocator},
+};
+use common_lib::{
+    boot_info::{BootConsole, BootInfo, BootTimeline},
+    dtb::Dtb,
+    layout::Layout,
+    memory::PhysicalAddress,
+};
+use sbi_lib::debug_println;
+
+/// Writes the information the kernel receives about the machine and the boot
+/// stage. Called with paging enabled, once the boot stage allocates nothing
+/// more.
+///
+/// # Arguments
+///
+/// * `boot_info_address` - The physical address of the page to write the
+///   information to.
+/// * `hart_id` - The hart the boot stage runs on.
+/// * `kernel_image` - The loaded kernel.
+/// * `dtb` - The copy of the DTB made for the kernel.
+/// * `root_page_table_address` - The physical address of the root page table
+///   the kernel starts with.
+/// * `layout` - The layout of the paging mode the root page table is active
+///   in, which is the largest mode the hart supports.
+/// * `timeline` - The times the boot stage reached its milestones.
+/// * `memory_map` - The usable memory, which the allocator hands out from.
+/// * `physical_memory_allocator` - The allocator, after its last allocation.
+///   The ranges it handed out are recorded so the kernel does not reuse them.
+#[allow(clippy::too_many_arguments)]
+pub fn create_boot_info(
+    boot_info_address: PhysicalAddress,
+    hart_id: usize,
+    kernel_image: &KernelImage,
+    dtb: &Dtb,
+    root_page_table_address: PhysicalAddress,
+    layout: &Layout,
+    timeline: &BootTimeline,
+    memory_map: &MemoryMap,
+    physical_memory_allocator: &PhysicalBumpAllocator,
+) {
+    unsafe extern "C" {
+        static _boot_start: usize;
+        static _boot_end: usize;
+    }
+
+    let boot_start = unsafe { &_boot_start as *const _ as usize };
+    let boot_end = unsafe { &_boot_end as *const _ as usize };
+
+    let mut boot_info = BootInfo::new();
+    boot_info.hart_id = hart_id;
+    boot_info.dtb_physical_address = PhysicalAddress::new(dtb.address());
+    boot_info.dtb_size = dtb.total_size();
+    boot_info.root_page_table_physical_address = root_page_table_address;
+    boot_info.set_paging_modes(layout.paging_mode, layout.paging_mode);
+    boot_info.boot_physical_base = PhysicalAddress::new(boot_start);
+    boot_info.boot_size = boot_end + 1 - boot_start;
+    boot_info.kernel_physical_base = kernel_image.physical_base;
+    boot_info.kernel_virtual_base = kernel_image.virtual_base;
+    boot_info.kernel_size = kernel_image.size;
+    boot_info.kernel_slide = kernel_image.slide;
+    boot_info.allocator_watermark = physical_memory_allocator.next_allocation_address();
+    boot_info.set_console(BootConsole::SbiDebugConsole);
+    boot_info.timeline = *timeline;
+
+    if let Err(error) = boot_info.set_memory_regions(memory_map.regions()) {
+        debug_println!("Recording the memory map for the kernel: {}.", error);
+    }
+
+    if let Err(error) =
+        boot_info.set_allocated_regions(physical_memory_allocator.allocated_regions())
+    {
+        debug_println!("Recording the allocated memory for the kernel: {}.", error);
+    }
+
+    // Only the boot stage itself is identity mapped, so the page is written
+    // through the direct physical memory mapping.
+    let boot_info_pointer =
+        (layout.direct_map_base_virtual_address + boot_info_address.raw_address()) as *mut BootInfo;
+
+    unsafe {
+        boot_info_pointer.write(boot_info);
+    }
+}
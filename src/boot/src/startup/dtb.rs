@@ -1,31 +1,103 @@
-use crate::{
-    debug_print, debug_println,
-    dtb::{DtbHeader, walk_memory_reservation_entries, walk_structure_block},
-};
+use boot_lib::memory::physical_memory_allocator::PhysicalMemoryAllocator;
+use common_lib::dtb::{Dtb, walk_memory_reservation_entries, walk_structure_block};
+use sbi_lib::{debug_print, debug_println};
 
-pub fn get_dtb_header(dtb_address: usize) -> &'static DtbHeader {
-    // Convert the DTB address to a DtbHeader reference.
-    let dtb_header = unsafe { &*(dtb_address as *const DtbHeader) };
+pub fn get_dtb(dtb_address: usize) -> Dtb<'static> {
+    // Validate the DTB before anything walks it, since a bad address from the
+    // firmware would otherwise send the parser through arbitrary memory. The
+    // firmware places the DTB in memory it does not touch again.
+    let dtb = match unsafe { Dtb::from_address(dtb_address) } {
+        Ok(dtb) => dtb,
+        Err(error) => panic!("Invalid DTB at {:#x}: {}.", dtb_address, error),
+    };
 
     debug_println!("DTB found at address: {:#x}", dtb_address);
-    debug_println!("{:#?}", dtb_header);
+    debug_println!("{:#?}", dtb.header());
     debug_println!();
 
-    dtb_header
+    dtb
 }
 
-pub fn print_reserved_memory_regions(dtb_header: &DtbHeader) {
+/// Copies the DTB into pages taken from the physical memory allocator.
+///
+/// The firmware leaves the DTB in memory that the kernel would otherwise treat
+/// as free. The copy lives in allocated pages, which are never handed out
+/// again, so the kernel can keep using it for as long as it runs.
+///
+/// # Arguments
+///
+/// * `dtb` - The DTB the firmware passed.
+/// * `physical_memory_allocator` - The allocator to take the pages from.
+///
+/// # Returns
+///
+/// The copy, which starts on a page boundary and occupies physically
+/// contiguous pages.
+pub fn copy_dtb_to_allocated_pages(
+    dtb: &Dtb,
+    physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
+) -> Dtb<'static> {
+    const PAGE_SIZE: usize = 4096;
+
+    let page_count = dtb.total_size().div_ceil(PAGE_SIZE);
+
+    // The allocator hands out one page at a time. Pages from a single region
+    // are consecutive, but the run restarts if the allocator moves on to the
+    // next region.
+    let mut allocate_page = || {
+        physical_memory_allocator
+            .allocate_page()
+            .expect("Failed to allocate pages for the DTB.")
+    };
+
+    let mut copy_start = allocate_page();
+    let mut contiguous_page_count = 1;
+
+    while contiguous_page_count < page_count {
+        let page = allocate_page();
+
+        if page == copy_start + contiguous_page_count * PAGE_SIZE {
+            contiguous_page_count += 1;
+        } else {
+            copy_start = page;
+            contiguous_page_count = 1;
+        }
+    }
+
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            dtb.as_bytes().as_ptr(),
+            copy_start.raw_address() as *mut u8,
+            dtb.total_size(),
+        );
+    }
+
+    debug_println!(
+        "Copied the DTB from {:#x} to {:#x} ({} bytes).",
+        dtb.address(),
+        copy_start,
+        dtb.total_size()
+    );
+    debug_println!();
+
+    match unsafe { Dtb::from_address(copy_start.raw_address()) } {
+        Ok(dtb_copy) => dtb_copy,
+        Err(error) => panic!("The DTB copy at {:#x} is invalid: {}.", copy_start, error),
+    }
+}
+
+pub fn print_reserved_memory_regions(dtb: &Dtb) {
     debug_println!("Reserved Memory Regions:");
-    walk_memory_reservation_entries(dtb_header, |entry| {
+    walk_memory_reservation_entries(dtb, |entry| {
         debug_println!("  {:#?}", entry);
     });
 
     debug_println!();
 }
 
-pub fn print_dtb_structure(dtb_header: &DtbHeader) {
+pub fn print_dtb_structure(dtb: &Dtb) {
     walk_structure_block(
-        dtb_header,
+        dtb,
         |node, depth| {
             for _ in 0..depth {
                 debug_print!("  ");
@@ -0,0 +1,82 @@
+//! Randomization of the kernel's virtual base.
+//!
+//! The kernel is linked as a position independent executable at
+//! `KERNEL_BASE_VIRTUAL_ADDRESS`, and the boot stage maps it at the kernel
+//! base of the layout of the paging mode it enables, a random number of
+//! `KASLR_ALIGNMENT` sized steps higher, within the layout's KASLR window.
+//! The boot stage has no virtio driver, so the only real entropy is the
+//! "rng-seed" property the firmware or QEMU put in the /chosen node. Without
+//! it the slide comes from the time, which is weak but better than a fixed
+//! base. "nokaslr" on the kernel command line keeps the kernel at the
+//! layout's kernel base.
+
+use super::timing::read_time;
+use common_lib::{
+    dtb::{self, Dtb},
+    layout::{KASLR_ALIGNMENT, Layout},
+};
+use sbi_lib::debug_println;
+
+/// Chooses how far from its link address the kernel is mapped.
+///
+/// # Arguments
+///
+/// * `dtb` - The Device Tree Blob, for the /chosen node.
+/// * `layout` - The layout of the paging mode the boot stage enables.
+///
+/// # Returns
+///
+/// The layout's `kernel_base_slide` plus a multiple of `KASLR_ALIGNMENT`
+/// below its `kaslr_window_size`, wrapping around, or the layout's
+/// `kernel_base_slide` alone if "nokaslr" is on the command line.
+pub fn choose_kernel_slide(dtb: &Dtb, layout: &Layout) -> usize {
+    let chosen = dtb::chosen(dtb).unwrap_or_default();
+
+    let is_disabled = chosen
+        .bootargs
+        .is_some_and(|bootargs| bootargs.split_whitespace().any(|arg| arg == "nokaslr"));
+
+    if is_disabled {
+        debug_println!("KASLR is disabled on the command line.");
+        return layout.kernel_base_slide();
+    }
+
+    // The time is mixed in even with a seed, since it costs nothing.
+    let mut seed = mix(read_time());
+
+    match chosen.rng_seed {
+        Some(rng_seed) => {
+            for chunk in rng_seed.chunks(8) {
+                let mut bytes = [0; 8];
+                bytes[..chunk.len()].copy_from_slice(chunk);
+
+                seed = mix(seed ^ u64::from_le_bytes(bytes));
+            }
+        }
+        None => debug_println!("The DTB has no rng-seed, so KASLR only has the time as entropy."),
+    }
+
+    let slot_count = (layout.kaslr_window_size / KASLR_ALIGNMENT) as u64;
+    let random_slide = (seed % slot_count) as usize * KASLR_ALIGNMENT;
+
+    debug_println!(
+        "Sliding the kernel by {:#x} above the kernel base.",
+        random_slide
+    );
+
+    layout.kernel_base_slide().wrapping_add(random_slide)
+}
+
+/// Scrambles a 64-bit value with the finalizer of SplitMix64, so that every
+/// input bit affects every output bit.
+///
+/// # Arguments
+///
+/// * `value` - The value to scramble.
+fn mix(value: u64) -> u64 {
+    let mut value = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    value ^ (value >> 31)
+}
@@ -0,0 +1,305 @@
+//! Loading of the kernel ELF embedded in the boot image.
+//!
+//! The build scripts assemble the linked kernel ELF into the boot stage's
+//! .rodata, between `_kernel_elf_start` and `_kernel_elf_end`. `load_kernel`
+//! copies its loadable segments into a single run of allocated pages, laid
+//! out the way the segments are laid out in virtual memory, and `map_kernel`
+//! maps each segment at the virtual address it asks for plus the kernel's
+//! slide, with only the permissions it asks for.
+//!
+//! The kernel is a position independent executable, so it runs at any slide
+//! once `load_kernel` applies its `R_RISCV_RELATIVE` relocations, which hold
+//! the absolute addresses in its data.
+
+use boot_lib::memory::{
+    mmu::{MapError, PageTable, PageTableEntryFlags, map_range},
+    physical_memory_allocator::PhysicalMemoryAllocator,
+};
+use common_lib::{
+    elf::{ElfFile, R_RISCV_NONE, R_RISCV_RELATIVE},
+    layout::Layout,
+    memory::{PhysicalAddress, VirtualAddress},
+};
+use sbi_lib::debug_println;
+
+/// The size of a page in bytes.
+const PAGE_SIZE: usize = 4096;
+
+/// The kernel once its segments are in memory.
+pub struct KernelImage {
+    /// The kernel ELF, which stays in the boot image.
+    elf: ElfFile<'static>,
+
+    /// The physical address of the first page of the image.
+    pub physical_base: PhysicalAddress,
+
+    /// The virtual address of the first page of the image, including the
+    /// slide.
+    pub virtual_base: VirtualAddress,
+
+    /// The number of bytes the image is mapped above its link address,
+    /// wrapping around if it is mapped below it.
+    pub slide: usize,
+
+    /// The number of bytes from the first page of the image to the end of
+    /// its last segment.
+    pub size: usize,
+
+    /// The virtual address the kernel starts executing at.
+    pub entry_point: VirtualAddress,
+}
+
+/// Copies the loadable segments of the embedded kernel ELF into allocated
+/// pages and relocates them for a slide. Called before paging is enabled, so
+/// the pages are written through their physical addresses.
+///
+/// # Arguments
+///
+/// * `physical_memory_allocator` - The allocator to take the pages from.
+/// * `layout` - The layout of the paging mode the boot stage enables, whose
+///   kernel image window the slid kernel must lie in.
+/// * `slide` - The page aligned number of bytes to map the kernel above its
+///   link address, wrapping around. Ignored if the kernel is not position
+///   independent.
+///
+/// # Returns
+///
+/// The loaded kernel.
+///
+/// # Panics
+///
+/// If the ELF is malformed, has no loadable segments, has a segment that does
+/// not start on a page boundary, lies outside the kernel image window of the
+/// virtual memory layout once slid, has a relocation other than
+/// `R_RISCV_RELATIVE` or one outside the image, or does not fit in memory.
+pub fn load_kernel(
+    physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
+    layout: &Layout,
+    slide: usize,
+) -> KernelImage {
+    let elf = match ElfFile::parse(embedded_kernel_elf()) {
+        Ok(elf) => elf,
+        Err(error) => panic!("Invalid kernel ELF: {}.", error),
+    };
+
+    let (start_address, end_address) =
+        elf.load_segments()
+            .fold((u64::MAX, 0), |(start_address, end_address), segment| {
+                (
+                    start_address.min(segment.virtual_address),
+                    end_address.max(segment.end_address()),
+                )
+            });
+
+    if start_address >= end_address {
+        panic!("The kernel ELF has no loadable segments.");
+    }
+
+    // Segments share no pages, so each page gets the permissions of exactly
+    // one segment.
+    if let Some(segment) = elf
+        .load_segments()
+        .find(|segment| !(segment.virtual_address as usize).is_multiple_of(PAGE_SIZE))
+    {
+        panic!(
+            "The kernel segment at {:#x} does not start on a page boundary.",
+            segment.virtual_address
+        );
+    }
+
+    let slide = if elf.is_position_independent() {
+        slide as u64
+    } else {
+        debug_println!("The kernel ELF is not position independent, so it is not slid.");
+        0
+    };
+
+    let kernel_window = layout.kernel_base_virtual_address as u64
+        ..(layout.kernel_base_virtual_address + layout.kernel_image_max_size) as u64;
+
+    if !kernel_window.contains(&start_address.wrapping_add(slide))
+        || end_address.wrapping_add(slide) > kernel_window.end
+    {
+        panic!(
+            "The kernel at {:#x}-{:#x} slid by {:#x} is outside the kernel image window.",
+            start_address, end_address, slide
+        );
+    }
+
+    if !(start_address..end_address).contains(&elf.entry_point()) {
+        panic!(
+            "The kernel entry point {:#x} is outside the kernel image.",
+            elf.entry_point()
+        );
+    }
+
+    let size = (end_address - start_address) as usize;
+    let page_count = size.div_ceil(PAGE_SIZE);
+
+    let physical_base = physical_memory_allocator
+        .allocate_contiguous(page_count, PAGE_SIZE)
+        .expect("Failed to allocate pages for the kernel image.");
+
+    debug_println!(
+        "Loading the kernel ELF into physical {:#x}-{:#x}.",
+        physical_base,
+        physical_base + size
+    );
+
+    // Zeroing the whole image covers .bss and any gaps between segments.
+    unsafe {
+        core::ptr::write_bytes(
+            physical_base.raw_address() as *mut u8,
+            0,
+            page_count * PAGE_SIZE,
+        );
+    }
+
+    for segment in elf.load_segments() {
+        let data = elf.segment_data(&segment);
+        let destination = physical_base + (segment.virtual_address - start_address) as usize;
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                destination.raw_address() as *mut u8,
+                data.len(),
+            );
+        }
+    }
+
+    let relocations = match elf.relocations() {
+        Ok(relocations) => relocations,
+        Err(error) => panic!("Invalid kernel relocations: {}.", error),
+    };
+
+    let mut relocation_count = 0;
+
+    for relocation in relocations {
+        match relocation.relocation_type {
+            R_RISCV_NONE => {}
+            R_RISCV_RELATIVE => {
+                if relocation.offset < start_address
+                    || relocation.offset + size_of::<u64>() as u64 > end_address
+                {
+                    panic!(
+                        "The kernel relocation at {:#x} is outside the kernel image.",
+                        relocation.offset
+                    );
+                }
+
+                let destination = physical_base + (relocation.offset - start_address) as usize;
+
+                unsafe {
+                    (destination.raw_address() as *mut u64)
+                        .write_unaligned((relocation.addend as u64).wrapping_add(slide));
+                }
+
+                relocation_count += 1;
+            }
+            relocation_type => panic!(
+                "The kernel relocation at {:#x} has unsupported type {}.",
+                relocation.offset, relocation_type
+            ),
+        }
+    }
+
+    debug_println!("Applied {} kernel relocations.", relocation_count);
+
+    KernelImage {
+        elf,
+        physical_base,
+        virtual_base: VirtualAddress::new(start_address.wrapping_add(slide) as usize),
+        slide: slide as usize,
+        size,
+        entry_point: VirtualAddress::new(elf.entry_point().wrapping_add(slide) as usize),
+    }
+}
+
+/// Maps every loadable segment of the kernel at its virtual address plus the
+/// slide with the permissions it asks for. The kernel's linker script keeps code, writable
+/// data, and read only data in separate segments.
+///
+/// # Arguments
+///
+/// * `root_page_table` - The root page table to add the mappings to.
+/// * `kernel_image` - The loaded kernel.
+/// * `physical_memory_allocator` - The allocator for intermediate page tables.
+///
+/// # Returns
+///
+/// * `Ok(())` - If every segment was mapped.
+/// * `Err(MapError)` - If any page of a segment could not be mapped.
+pub fn map_kernel(
+    root_page_table: &mut PageTable,
+    kernel_image: &KernelImage,
+    physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
+) -> Result<(), MapError> {
+    debug_println!(
+        "Mapping kernel from physical {:#x}-{:#x} to virtual {:#x}-{:#x}.",
+        kernel_image.physical_base,
+        kernel_image.physical_base + kernel_image.size,
+        kernel_image.virtual_base,
+        kernel_image.virtual_base + kernel_image.size
+    );
+
+    for segment in kernel_image.elf.load_segments() {
+        let number_of_pages = (segment.memory_size as usize).div_ceil(PAGE_SIZE);
+
+        if number_of_pages == 0 {
+            continue;
+        }
+
+        let mut flags = PageTableEntryFlags::default();
+        flags.set_readable(segment.flags.readable);
+        flags.set_writable(segment.flags.writable);
+        flags.set_executable(segment.flags.executable);
+
+        let virtual_address = VirtualAddress::new(
+            (segment.virtual_address as usize).wrapping_add(kernel_image.slide),
+        );
+
+        debug_println!(
+            "  Mapping kernel segment {:#x}-{:#x} as {}{}{}.",
+            virtual_address,
+            virtual_address + segment.memory_size as usize,
+            if flags.get_readable() { "R" } else { "-" },
+            if flags.get_writable() { "W" } else { "-" },
+            if flags.get_executable() { "X" } else { "-" }
+        );
+
+        let physical_address =
+            kernel_image.physical_base + (virtual_address - kernel_image.virtual_base);
+
+        // map_range takes an inclusive page count.
+        map_range(
+            root_page_table,
+            physical_address.ppn(),
+            virtual_address.vpn(),
+            number_of_pages - 1,
+            &flags,
+            physical_memory_allocator,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the bytes of the kernel ELF the build scripts embedded in the boot
+/// image.
+fn embedded_kernel_elf() -> &'static [u8] {
+    unsafe extern "C" {
+        static _kernel_elf_start: u8;
+        static _kernel_elf_end: u8;
+    }
+
+    let kernel_elf_start = &raw const _kernel_elf_start;
+    let kernel_elf_end = &raw const _kernel_elf_end;
+
+    unsafe {
+        core::slice::from_raw_parts(
+            kernel_elf_start,
+            kernel_elf_end as usize - kernel_elf_start as usize,
+        )
+    }
+}
@@ -1,66 +1,105 @@
-use crate::dtb;
-use crate::{
-    debug_println,
-    dtb::{adjust_memory_map_from_reserved_regions_in_dtb, populate_memory_map_from_dtb},
-};
 use boot_lib::memory::{
     memory_map::MemoryMap,
     physical_memory_allocator::{PhysicalBumpAllocator, PhysicalMemoryAllocator},
+    zone::MemoryZone,
+};
+use common_lib::{
+    dtb::{self, Dtb},
+    memory::PhysicalAddress,
 };
+use sbi_lib::debug_println;
 
-pub fn create_memory_map(dtb_header: &dtb::DtbHeader) -> MemoryMap {
+pub fn create_memory_map(dtb: &Dtb) -> MemoryMap {
     unsafe extern "C" {
         static _boot_start: usize;
         static _boot_end: usize;
-        static _kernel_size: usize;
     }
 
-    let boot_start = unsafe { &_boot_start as *const _ as usize };
-    let boot_end = unsafe { &_boot_end as *const _ as usize };
-    let kernel_size = unsafe { &_kernel_size as *const _ as usize };
+    let boot_start = PhysicalAddress::new(unsafe { &_boot_start as *const _ as usize });
+    let boot_end = PhysicalAddress::new(unsafe { &_boot_end as *const _ as usize });
 
     let boot_size = boot_end - boot_start + 1;
 
     // Populate the memory map using information from the device tree blob.
     let mut memory_map = MemoryMap::new();
 
-    populate_memory_map_from_dtb(&mut memory_map, dtb_header);
-    adjust_memory_map_from_reserved_regions_in_dtb(&mut memory_map, dtb_header);
+    populate_memory_map_from_dtb(&mut memory_map, dtb);
+    adjust_memory_map_from_reserved_regions_in_dtb(&mut memory_map, dtb);
+
+    // Carve out the boot image, which holds the kernel ELF until the kernel is
+    // loaded into allocated pages.
+    if let Err(error) = memory_map.carve_out_region(boot_start, boot_size) {
+        debug_println!("Carving out the boot image: {}.", error);
+    }
+
+    // Carve out the DTB the firmware passed so that no allocation overwrites
+    // it before it is copied into kernel owned memory. Whole pages are carved
+    // so that the remaining regions stay page aligned.
+    const PAGE_SIZE: usize = 4096;
 
-    // Carve out the kernel memory region from the memory map. The boot part of
-    // the kernel and the kernel itself are loaded sequentially in physical
-    // memory.
-    memory_map.carve_out_region(boot_start, boot_size + kernel_size);
+    let dtb_start = PhysicalAddress::new(dtb.address()).align_down(PAGE_SIZE);
+    let dtb_end = PhysicalAddress::new(dtb.address() + dtb.total_size())
+        .align_up(PAGE_SIZE)
+        .expect("The DTB ends in the last page of the address space.");
+
+    if let Err(error) = memory_map.carve_out_region(dtb_start, dtb_end - dtb_start) {
+        debug_println!("Carving out the DTB: {}.", error);
+    }
+
+    // Carve out the initial ramdisk too, which the kernel keeps using as its
+    // first root file system.
+    if let Some((initrd_start, initrd_end)) =
+        dtb::chosen(dtb).and_then(|chosen| chosen.initrd_range())
+    {
+        let initrd_start = PhysicalAddress::new(initrd_start as usize).align_down(PAGE_SIZE);
+        let initrd_end = PhysicalAddress::new(initrd_end as usize)
+            .align_up(PAGE_SIZE)
+            .expect("The initial ramdisk ends in the last page of the address space.");
+
+        if let Err(error) = memory_map.carve_out_region(initrd_start, initrd_end - initrd_start) {
+            debug_println!("Carving out the initial ramdisk: {}.", error);
+        }
+    }
 
     memory_map
 }
 
-pub fn print_memory_regions(memory_map: &mut MemoryMap) {
+pub fn print_memory_regions(memory_map: &MemoryMap) {
     debug_println!("Usable memory regions:");
 
-    memory_map.walk_regions(|region| {
+    for region in memory_map.regions() {
         debug_println!(
-            "  {:#x}-{:#x}, size: {:#x}",
+            "  {:#x}-{:#x}, size: {:#x}, zone: {}",
             region.start,
             region.end(),
-            region.size
+            region.size,
+            MemoryZone::for_address(PhysicalAddress::new(region.start))
         );
-    });
+    }
 
+    debug_println!("  Total size: {:#x}", memory_map.total_size());
     debug_println!();
 }
 
-pub fn create_physical_memory_allocator(
-    memory_map: &mut MemoryMap,
-) -> impl PhysicalMemoryAllocator {
+pub fn create_physical_memory_allocator(memory_map: &mut MemoryMap) -> PhysicalBumpAllocator {
     let mut physical_memory_allocator = PhysicalBumpAllocator::new();
     physical_memory_allocator.reset(memory_map.get_regions(), memory_map.get_region_count());
 
     debug_println!(
-        "Created a physical memory allocator with {} bytes of free memory.\n",
+        "Created a physical memory allocator with {} bytes of free memory.",
         physical_memory_allocator.total_memory_size()
     );
 
+    for zone in MemoryZone::ALL {
+        debug_println!(
+            "  {} zone: {} bytes",
+            zone,
+            physical_memory_allocator.zone_memory_size(zone)
+        );
+    }
+
+    debug_println!();
+
     physical_memory_allocator
 }
 
@@ -97,3 +136,129 @@ pub fn print_physical_memory_stats(physical_memory_allocator: impl PhysicalMemor
         physical_memory_allocator.available_memory_size() / 1024
     );
 }
+
+/// Populates a memory map with memory regions described in the Device Tree
+/// Blob.
+///
+/// This function walks through the DTB structure, looking for memory nodes and
+/// their "reg" properties which describe available memory ranges. Each memory
+/// region is aligned to 4KiB boundaries before being added to the memory map:
+/// - Start addresses are rounded up to the nearest 4KiB boundary.
+/// - End addresses are rounded down to the nearest 4KiB boundary.
+/// - Regions that become smaller than 4KiB after alignment are discarded.
+///
+/// # Parameters
+///
+/// * `memory_map` - The memory map to populate with memory regions.
+/// * `dtb` - The Device Tree Blob.
+pub fn populate_memory_map_from_dtb(memory_map: &mut MemoryMap, dtb: &Dtb) {
+    // Constants for 4KiB alignment in the Sv39 paging scheme.
+    const PAGE_SIZE: usize = 4096;
+    const PAGE_MASK: usize = !(PAGE_SIZE - 1);
+
+    let memory_nodes = dtb
+        .nodes()
+        .filter(|node| node.name == "memory" || node.name.starts_with("memory@"));
+
+    for node in memory_nodes {
+        // Only process "reg" properties that are inside memory nodes.
+        let Some(property) = node.property("reg") else {
+            continue;
+        };
+
+        // Extract memory regions from the reg property.
+        property.get_property_data_as_reg(&node.parent_cell_info(), |address, size| {
+            let original_start = PhysicalAddress::new(address as usize);
+            let original_size = size as usize;
+
+            // Align the start address up to the next 4KiB boundary. A region
+            // in the last page of the address space has no usable page.
+            let Some(aligned_start) = original_start.align_up(PAGE_SIZE) else {
+                return;
+            };
+
+            // Calculate how much the alignment changed the start position.
+            let start_adjustment = aligned_start - original_start;
+
+            // Adjust the size by subtracting the start adjustment.
+            let adjusted_size = if start_adjustment < original_size {
+                original_size - start_adjustment
+            } else {
+                // If start adjustment exceeded original size, region vanishes.
+                0
+            };
+
+            // Align the size down to a multiple of 4KiB.
+            let aligned_size = adjusted_size & PAGE_MASK;
+
+            // Only add regions that are at least 4KiB in size after alignment.
+            if aligned_size < PAGE_SIZE {
+                return;
+            }
+
+            if let Err(error) = memory_map.add_region(aligned_start, aligned_size) {
+                debug_println!(
+                    "Ignoring memory at {:#x}-{:#x}: {}.",
+                    aligned_start,
+                    aligned_start + aligned_size - 1,
+                    error
+                );
+            }
+        });
+    }
+}
+
+/// Adjusts a memory map by removing regions marked as reserved in the Device
+/// Tree Blob.
+///
+/// This function walks through the DTB structure looking for the
+/// "reserved-memory" node and its children. For each child node with a "reg"
+/// property, it extracts the address and size information of the reserved
+/// memory region and removes it from the available memory map by calling
+/// `remove_reserved_memory_region`.
+///
+/// Reserved memory regions are used by firmware, bootloaders, or other system
+/// components and should not be used by the operating system. This ensures that
+/// the memory map only contains memory that is safe to use.
+///
+/// # Parameters
+///
+/// * `memory_map` - The memory map to adjust by removing reserved regions.
+/// * `dtb` - The Device Tree Blob containing the reserved memory information.
+///
+/// # Side Effects
+///
+/// This function modifies the provided memory map by potentially removing
+/// regions, adjusting region boundaries, or adding new regions when splitting
+/// is required.
+pub fn adjust_memory_map_from_reserved_regions_in_dtb(memory_map: &mut MemoryMap, dtb: &Dtb) {
+    let Some(reserved_memory_node) = dtb
+        .root_node()
+        .and_then(|root_node| root_node.child("reserved-memory"))
+    else {
+        return;
+    };
+
+    // Each child of the reserved-memory node describes one reserved region.
+    for reserved_region_node in reserved_memory_node.children() {
+        let Some(property) = reserved_region_node.property("reg") else {
+            continue;
+        };
+
+        property.get_property_data_as_reg(
+            &reserved_region_node.parent_cell_info(),
+            |address, size| {
+                let reserved_start = PhysicalAddress::new(address as usize);
+                let reserved_size = size as usize;
+
+                if let Err(error) = memory_map.carve_out_region(reserved_start, reserved_size) {
+                    debug_println!(
+                        "Carving out reserved memory at {:#x}: {}.",
+                        reserved_start,
+                        error
+                    );
+                }
+            },
+        );
+    }
+}
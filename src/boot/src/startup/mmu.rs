@@ -1,22 +1,60 @@
-use crate::{debug_print, debug_println};
+use super::kernel::{KernelImage, map_kernel};
 use boot_lib::memory::{
-    mmu::{PageTable, PageTableEntryFlags, allocate_level_2_vpn, identity_map_range, map_range},
+    mmu::{
+        MapError, PageTable, PageTableEntryFlags, allocate_root_vpn, identity_map_range, map_range,
+    },
+    page_table_dump::write_leaf_ranges,
     physical_memory_allocator::PhysicalMemoryAllocator,
 };
-use common_lib::memory::{PhysicalPageNumber, VirtualPageNumber};
+use common_lib::{
+    dtb::Dtb,
+    layout::Layout,
+    memory::{PhysicalAddress, PhysicalPageNumber, VirtualPageNumber},
+};
+use sbi_lib::{debug_console::DebugConsoleWriter, debug_print, debug_println};
+
+/// The size of a page in bytes.
+const PAGE_SIZE: usize = 4096;
 
+/// True to print every entry of the boot page tables, instead of the runs of
+/// entries with the same size and flags.
+const PRINT_EVERY_PAGE_TABLE_ENTRY: bool = false;
+
+/// Builds the boot page tables and enables paging.
+///
+/// # Arguments
+///
+/// * `root_page_table_physical_address` - The physical address of the root
+///   page table.
+/// * `root_page_table` - The root page table to add the mappings to.
+/// * `layout` - The layout of the paging mode to enable, which must be the
+///   mode set with `mmu::set_paging_mode`.
+/// * `kernel_image` - The loaded kernel to map.
+/// * `dtb` - The copy of the DTB to map for the kernel. It must start on a page
+///   boundary.
+/// * `physical_memory_allocator` - The allocator for intermediate page tables.
+///
+/// # Returns
+///
+/// * `Ok(())` - If every mapping was created and paging is now active.
+/// * `Err(MapError)` - If any mapping could not be created. Paging is not
+///   enabled in this case.
 pub fn setup_mmu(
-    root_page_table_physical_address: usize,
+    root_page_table_physical_address: PhysicalAddress,
     root_page_table: &mut PageTable,
+    layout: &Layout,
+    kernel_image: &KernelImage,
+    dtb: &Dtb,
     physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
-) {
-    debug_println!("Setting up MMU with sv39 paging...");
+) -> Result<(), MapError> {
+    let paging_mode = layout.paging_mode;
+
+    debug_println!("Setting up MMU with {} paging...", paging_mode);
 
     // Create the recursive mapping for the root page table at index 511. This
     // allows the page tables to be accessed as virtual memory after paging is
     // enabled.
-    let root_page_table_ppn =
-        PhysicalPageNumber::from_physical_address(root_page_table_physical_address);
+    let root_page_table_ppn = root_page_table_physical_address.ppn();
 
     debug_println!(
         "Root page table physical address is {:#x}.",
@@ -28,19 +66,36 @@ pub fn setup_mmu(
         root_page_table_ppn.raw_ppn()
     );
 
-    identity_map_boot(root_page_table, physical_memory_allocator);
-    map_kernel_into_high_virtual_memory(root_page_table, physical_memory_allocator);
-    map_physical_memory(root_page_table);
+    identity_map_boot(root_page_table, physical_memory_allocator)?;
+    map_kernel(root_page_table, kernel_image, physical_memory_allocator)?;
+    map_dtb(root_page_table, layout, dtb, physical_memory_allocator)?;
+    map_physical_memory(root_page_table, layout)?;
+
+    // No page may be both writable and executable. Debug builds check the
+    // finished page tables, so that a new mapping cannot break this unnoticed.
+    #[cfg(debug_assertions)]
+    if let Some(vpn) = boot_lib::memory::mmu::find_writable_executable_page(root_page_table) {
+        panic!(
+            "The page at {:#x} is mapped both writable and executable.",
+            vpn.to_virtual_address()
+        );
+    }
 
     debug_println!();
-    print_page_table_entries(root_page_table, 2, 0);
+    if PRINT_EVERY_PAGE_TABLE_ENTRY {
+        let root_level = paging_mode.root_level() as u8;
+
+        print_page_table_entries(root_page_table, root_level, root_level, 0);
+    } else {
+        let _ = write_leaf_ranges(&mut DebugConsoleWriter, root_page_table, 0);
+    }
     debug_println!();
 
-    // Set up the satp register to enable paging. Format for RV64 with sv39:
-    // - MODE (bits 63:60) = 8 for sv39
+    // Set up the satp register to enable paging. Format for RV64:
+    // - MODE (bits 63:60) = the paging mode, such as 8 for sv39
     // - ASID (bits 59:44) = 0 for now (Address Space ID)
     // - PPN (bits 43:0) = physical page number of the root page table
-    let satp_value = (8usize << 60) | root_page_table_ppn.raw_ppn();
+    let satp_value = paging_mode.satp(root_page_table_ppn.raw_ppn());
 
     debug_println!("Setting satp register to {:#x}.", satp_value);
 
@@ -56,13 +111,15 @@ pub fn setup_mmu(
         );
     }
 
-    debug_println!("MMU activated with sv39 paging.");
+    debug_println!("MMU activated with {} paging.", paging_mode);
+
+    Ok(())
 }
 
 fn identity_map_boot(
     root_page_table: &mut PageTable,
     physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
-) {
+) -> Result<(), MapError> {
     // Identity map the .text, .data, .bss, .rodata, and stack sections.
     unsafe extern "C" {
         static _boot_text_start: usize;
@@ -101,7 +158,7 @@ fn identity_map_boot(
         text_end_ppn,
         &text_flags,
         physical_memory_allocator,
-    );
+    )?;
 
     // Identity map the .data section with readable and writable flags.
     let mut data_flags = PageTableEntryFlags::default();
@@ -117,7 +174,7 @@ fn identity_map_boot(
         data_end_ppn,
         &data_flags,
         physical_memory_allocator,
-    );
+    )?;
 
     // Identity map the .rodata section with the readable flag.
     let mut rodata_flags = PageTableEntryFlags::default();
@@ -132,7 +189,7 @@ fn identity_map_boot(
         rodata_end_ppn,
         &rodata_flags,
         physical_memory_allocator,
-    );
+    )?;
 
     // Identity map the .bss section with readable and writable flags.
     let mut bss_flags = PageTableEntryFlags::default();
@@ -148,7 +205,7 @@ fn identity_map_boot(
         bss_end_ppn,
         &bss_flags,
         physical_memory_allocator,
-    );
+    )?;
 
     // Identity map the stack data with readable and writable flags.
     let mut stack_page_flags = PageTableEntryFlags::default();
@@ -164,91 +221,63 @@ fn identity_map_boot(
         stack_end_ppn,
         &stack_page_flags,
         physical_memory_allocator,
-    );
+    )
 }
 
-/// Maps the kernel's physical memory to high virtual memory addresses.
+/// Maps the copy of the DTB read only at the layout's `dtb_virtual_address`.
 ///
-/// This function maps the kernel's physical memory (which starts at the
-/// provided physical address) to the high virtual memory address space of
-/// 0xFFFF_FFFF_8000_0000.
+/// The kernel finds the DTB at this address regardless of where the boot stage
+/// placed it in physical memory.
 ///
 /// # Arguments
 ///
-/// * `root_page_table` - A mutable reference to the root page table where
-///   mappings will be added.
-/// * `kernel_start` - The physical start address of the kernel in memory.
-/// * `kernel_size` - The total size of the kernel in bytes.
-/// * `physical_memory_allocator` - A mutable reference to a physical memory
-///   allocator used for creating page tables if needed.
-///
-/// # Notes
-///
-/// * This function creates the necessary page table entries to map the kernel's
-///   physical memory to high virtual addresses.
-/// * The virtual address where the kernel is mapped is determined by the
-///   `KERNEL_BASE_VIRTUAL_ADDRESS`.
-/// * Different memory regions of the kernel may receive different permissions
-///   based on their usage.
-fn map_kernel_into_high_virtual_memory(
+/// * `root_page_table` - The root page table to add the mapping to.
+/// * `layout` - The layout of the paging mode the page tables are built for.
+/// * `dtb` - The copy of the DTB. It must start on a page boundary.
+/// * `physical_memory_allocator` - The allocator for intermediate page tables.
+fn map_dtb(
     root_page_table: &mut PageTable,
+    layout: &Layout,
+    dtb: &Dtb,
     physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
-) {
-    unsafe extern "C" {
-        static _boot_end: usize;
-        static _kernel_size: usize;
-    }
-
-    let boot_end = unsafe { &_boot_end as *const _ as usize };
-    let kernel_size = unsafe { &_kernel_size as *const _ as usize };
-
-    let kernel_start = boot_end + 1;
-
-    // The base virtual address where we'll map the kernel.
-    const KERNEL_BASE_VIRTUAL_ADDRESS: usize = 0xFFFF_FFC0_0000_0000;
+) -> Result<(), MapError> {
+    let number_of_pages = dtb.total_size().div_ceil(PAGE_SIZE);
 
     debug_println!(
-        "Mapping kernel from physical {:#x}-{:#x} to virtual {:#x}-{:#x}.",
-        kernel_start,
-        kernel_start + kernel_size,
-        KERNEL_BASE_VIRTUAL_ADDRESS,
-        KERNEL_BASE_VIRTUAL_ADDRESS + kernel_size
+        "Mapping DTB from physical {:#x}-{:#x} to virtual {:#x}-{:#x}.",
+        dtb.address(),
+        dtb.address() + dtb.total_size(),
+        layout.dtb_virtual_address,
+        layout.dtb_virtual_address + dtb.total_size()
     );
 
-    // Calculate the number of pages needed to map the kernel. Round up to
-    // ensure all memory is covered.
-    const PAGE_SIZE: usize = 4096;
-    let number_of_pages = (kernel_size + PAGE_SIZE - 1) / PAGE_SIZE;
-
-    // Create the start physical and virtual page numbers.
-    let start_ppn = PhysicalPageNumber::from_physical_address(kernel_start);
-    let start_vpn = VirtualPageNumber::from_virtual_address(KERNEL_BASE_VIRTUAL_ADDRESS);
-
-    // Create the page flags for the kernel mapping. The kernel needs to be
-    // readable, writable, and executable.
-    let mut kernel_flags = PageTableEntryFlags::default();
-    kernel_flags.set_readable(true);
-    kernel_flags.set_writable(true);
-    kernel_flags.set_executable(true);
+    // The mapping is shared by every address space and never written.
+    let mut dtb_flags = PageTableEntryFlags::default();
+    dtb_flags.set_readable(true);
+    dtb_flags.set_global(true);
 
-    // Map the kernel's memory range.
+    // map_range takes an inclusive page count.
     map_range(
         root_page_table,
-        start_ppn,
-        start_vpn,
-        number_of_pages,
-        &kernel_flags,
+        PhysicalPageNumber::from_physical_address(dtb.address()),
+        VirtualPageNumber::from_virtual_address(layout.dtb_virtual_address),
+        number_of_pages - 1,
+        &dtb_flags,
         physical_memory_allocator,
-    );
+    )
 }
 
-/// Map the first 128GiB of physical memory to the top 128GiB of virtual memory.
-/// This will give the kernel the ability to access any physical memory address.
+/// Map the first `direct_map_size` bytes of physical memory to
+/// `direct_map_base_virtual_address`, the top of virtual memory, with a leaf
+/// entry in the root page table for each root entry of the window. This will
+/// give the kernel the ability to access any physical memory address.
 /// Importantly, this will allow the kernel to access every page table we have
 /// created and will create.
-fn map_physical_memory(root_page_table: &mut PageTable) {
-    // Define the number of gigabytes to map (128GiB).
-    const GIGABYTES_TO_MAP: usize = 128;
+fn map_physical_memory(root_page_table: &mut PageTable, layout: &Layout) -> Result<(), MapError> {
+    // Each root page table entry maps 1GiB in sv39, 512GiB in sv48, and
+    // 256TiB in sv57.
+    let root_entry_size = layout.paging_mode.root_entry_size();
+    let root_entries_to_map = layout.direct_map_size / root_entry_size;
 
     // Create page table entry flags for this direct mapping section. These
     // pages should be readable and writable, but not executable. Also mark
@@ -260,43 +289,50 @@ fn map_physical_memory(root_page_table: &mut PageTable) {
 
     debug_println!(
         "Mapping first {}GiB of physical memory to top of virtual memory.",
-        GIGABYTES_TO_MAP
+        layout.direct_map_size >> 30
     );
 
-    // Map each gigabyte individually.
-    for gib_index in 0..GIGABYTES_TO_MAP {
-        // Calculate the virtual page number for this mapping. For the top
-        // 128GiB, we start at index (512 - 128) = 384.
-        let vpn2_index = 512 - GIGABYTES_TO_MAP + gib_index;
-        let virtual_page_number = VirtualPageNumber::from_raw_virtual_page_number(vpn2_index << 18);
+    // Map each root entry individually.
+    for root_entry_index in 0..root_entries_to_map {
+        // Calculate the virtual page number for this mapping, starting at the
+        // direct map's base address.
+        let virtual_page_number = VirtualPageNumber::from_virtual_address(
+            layout.direct_map_base_virtual_address + root_entry_index * root_entry_size,
+        );
 
-        // The physical page number for this mapping is just the index * 1GiB
-        // since we're mapping 0..128GiB to the top of the address space.
+        // The physical page number for this mapping is just the index times
+        // the size of a root entry, since we're mapping physical memory from
+        // address zero to the top of the address space.
         let physical_page_number =
-            PhysicalPageNumber::from_raw_physical_page_number(gib_index << 18);
+            PhysicalPageNumber::from_physical_address(root_entry_index * root_entry_size);
 
-        // Create the mapping using the gigapage mapper.
-        let mapping_result = allocate_level_2_vpn(
+        // Create the mapping using the root level leaf mapper.
+        let mapping_result = allocate_root_vpn(
             root_page_table,
             virtual_page_number,
             physical_page_number,
             &direct_mapping_flags,
         );
 
-        if !mapping_result {
+        if let Err(error) = mapping_result {
             debug_println!(
-                "  Failed to map 1GiB at Virtual [{:#x}] -> Physical [{:#x}]",
+                "  Failed to map a root entry at Virtual [{:#x}] -> Physical [{:#x}]: {:?}",
                 virtual_page_number.to_virtual_address(),
-                physical_page_number.to_physical_address()
+                physical_page_number.to_physical_address(),
+                error
             );
+
+            return Err(error);
         }
     }
 
     debug_println!("Direct mapping of physical memory complete.");
+
+    Ok(())
 }
 
-fn print_page_table_entries(page_table: &PageTable, level: u8, base_vpn: usize) {
-    let indent = (2 - level) as usize * 2;
+fn print_page_table_entries(page_table: &PageTable, root_level: u8, level: u8, base_vpn: usize) {
+    let indent = (root_level - level) as usize * 2;
     let span = 512_usize.pow(level as u32);
 
     for i in 0..512 {
@@ -361,7 +397,7 @@ fn print_page_table_entries(page_table: &PageTable, level: u8, base_vpn: usize)
             let child_page_table =
                 unsafe { &*(entry.get_ppn().to_physical_address() as *const PageTable) };
 
-            print_page_table_entries(child_page_table, level - 1, entry_vpn);
+            print_page_table_entries(child_page_table, root_level, level - 1, entry_vpn);
         }
     }
 }
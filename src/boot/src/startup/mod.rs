@@ -1,3 +1,8 @@
+pub mod boot_info;
 pub mod dtb;
+pub mod kaslr;
+pub mod kernel;
 pub mod memory;
 pub mod mmu;
+pub mod paging_mode;
+pub mod timing;
@@ -0,0 +1,136 @@
+//! Probing of the paging modes the boot hart supports.
+//!
+//! The boot stage enables the largest mode the probe finds, falling back to
+//! sv39, which every hart with paging supports. The page tables get as many
+//! levels as the mode translates, and the windows of the kernel half are
+//! placed by the `Layout` of the mode.
+//!
+//! A hart ignores a write to satp that names a mode it does not implement, so
+//! the boot stage writes each mode, largest first, and reads satp back. For
+//! the probe to survive the mode it enables, the root page table identity
+//! maps the lower half of the address space with root level leaf entries,
+//! which every mode can translate. Paging is switched off again right after
+//! the read.
+
+use boot_lib::memory::mmu::{PageTable, PageTableEntry, PageTableEntryFlags};
+use common_lib::{
+    layout::PagingMode,
+    memory::{PhysicalAddress, PhysicalPageNumber},
+};
+use sbi_lib::debug_println;
+
+/// The number of entries in every page table.
+const PAGE_TABLE_ENTRY_COUNT: usize = 512;
+
+/// The size of a page in bytes.
+const PAGE_SIZE: usize = 4096;
+
+/// Finds the largest paging mode the hart accepts, trying sv57, sv48, and
+/// sv39 in that order. Called with paging disabled, before any mapping is
+/// added to the root page table, which is used for the probe and left empty
+/// again.
+///
+/// # Arguments
+///
+/// * `root_page_table_physical_address` - The physical address of the root
+///   page table.
+/// * `root_page_table` - The empty root page table.
+///
+/// # Returns
+///
+/// The largest mode the hart accepted.
+///
+/// # Panics
+///
+/// If the hart accepts none of the modes.
+pub fn probe_highest_paging_mode(
+    root_page_table_physical_address: PhysicalAddress,
+    root_page_table: &mut PageTable,
+) -> PagingMode {
+    let highest = PagingMode::PROBE_ORDER
+        .into_iter()
+        .find(|&mode| {
+            let is_supported =
+                probe_paging_mode(root_page_table_physical_address, root_page_table, mode);
+
+            debug_println!(
+                "The hart {} {} paging.",
+                if is_supported {
+                    "supports"
+                } else {
+                    "does not support"
+                },
+                mode
+            );
+
+            is_supported
+        })
+        .expect("The hart supports no paging mode.");
+
+    root_page_table.clear();
+
+    highest
+}
+
+/// Enables a paging mode for as long as it takes to read satp back.
+///
+/// # Arguments
+///
+/// * `root_page_table_physical_address` - The physical address of the root
+///   page table.
+/// * `root_page_table` - The root page table, which is rewritten with the
+///   identity mapping for the mode.
+/// * `mode` - The mode to try.
+///
+/// # Returns
+///
+/// True if the hart accepted the mode.
+fn probe_paging_mode(
+    root_page_table_physical_address: PhysicalAddress,
+    root_page_table: &mut PageTable,
+    mode: PagingMode,
+) -> bool {
+    root_page_table.clear();
+
+    let mut flags = PageTableEntryFlags::default();
+    flags.set_readable(true);
+    flags.set_writable(true);
+    flags.set_executable(true);
+
+    // The lower half ends where the translated address bits would set the
+    // sign bit, so only those entries map canonical addresses.
+    let pages_per_entry = mode.root_entry_size() / PAGE_SIZE;
+
+    for index in 0..PAGE_TABLE_ENTRY_COUNT / 2 {
+        let mut entry = PageTableEntry::new();
+        entry.set_flags(&flags);
+        entry.set_accessed(true);
+        entry.set_dirty(true);
+        entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(
+            index * pages_per_entry,
+        ));
+        entry.set_valid(true);
+
+        root_page_table.set_entry(index, entry);
+    }
+
+    let satp_value = mode.satp(root_page_table_physical_address.ppn().raw_ppn());
+    let read_back: usize;
+
+    // The first fence orders the stores to the page table before the walks of
+    // the probe, and the last discards the translations it cached.
+    unsafe {
+        core::arch::asm!(
+            "sfence.vma",
+            "csrw satp, {satp}",
+            "csrr {read_back}, satp",
+            "csrw satp, zero",
+            "sfence.vma",
+            satp = in(reg) satp_value,
+            read_back = out(reg) read_back,
+            options(nostack)
+        );
+    }
+
+    PagingMode::from_satp(read_back) == Some(mode)
+}
@@ -0,0 +1,15 @@
+//! Timing of the boot stage for the boot profiler.
+//!
+//! The boot stage records the `time` CSR at each of its `BootMilestone`s and
+//! hands the raw ticks to the kernel in the `BootInfo`, since only the kernel
+//! converts them to time once it knows the timebase frequency.
+
+/// Reads the current value of the `time` CSR.
+pub fn read_time() -> u64 {
+    let time: u64;
+    unsafe {
+        core::arch::asm!("rdtime {}", out(reg) time, options(nomem, nostack));
+    }
+
+    time
+}
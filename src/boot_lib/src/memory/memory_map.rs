@@ -1,6 +1,34 @@
 #![allow(dead_code)]
 
-use common_lib::memory::MemoryRegion;
+use common_lib::memory::{MemoryRegion, PhysicalAddress};
+use core::fmt;
+
+/// Errors that can occur while changing a memory map.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryMapError {
+    /// The memory map has no free slot for another region, so the region was
+    /// not added.
+    Full,
+
+    /// Carving out a reserved region split a region in two, and the memory
+    /// map had no free slot for the part after the reserved region, so that
+    /// usable memory was dropped. The reserved region was still removed.
+    RegionDropped(MemoryRegion),
+}
+
+impl fmt::Display for MemoryMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "the memory map is full"),
+            Self::RegionDropped(region) => write!(
+                f,
+                "the memory map is full, so {:#x}-{:#x} was dropped",
+                region.start,
+                region.end()
+            ),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct MemoryMap {
@@ -31,22 +59,103 @@ impl MemoryMap {
 
     /// Adds a new memory region to the memory map.
     ///
+    /// The regions are kept sorted by start address, and a region that
+    /// overlaps or touches regions already in the map is merged with them into
+    /// a single region. Empty regions are ignored.
+    ///
     /// # Parameters
     ///
     /// * `start` - The start address of the memory region.
     /// * `size` - The size of the memory region in bytes.
     ///
-    /// # Safety
+    /// # Returns
     ///
-    /// This function assumes that there is enough space in the memory map to
-    /// add the region.
+    /// `MemoryMapError::Full` if the region merges with no other region and
+    /// the memory map has no free slot for it. The memory map is unchanged.
     ///
     /// # Side Effects
     ///
-    /// This function modifies the memory map by adding a new region to it.
-    pub const fn add_region(&mut self, start: usize, size: usize) {
-        self.regions[self.current_size] = MemoryRegion::new(start, size);
+    /// This function modifies the memory map by adding a new region to it or
+    /// growing the regions it merges with.
+    pub fn add_region(
+        &mut self,
+        start: PhysicalAddress,
+        size: usize,
+    ) -> Result<(), MemoryMapError> {
+        if size == 0 {
+            return Ok(());
+        }
+
+        let mut merged_start = start.raw_address();
+        let mut merged_end = merged_start.saturating_add(size);
+
+        // The regions are sorted and never overlap or touch, so the regions
+        // to merge with are consecutive.
+        let first_index = self.regions[..self.current_size]
+            .iter()
+            .position(|region| region.start + region.size >= merged_start)
+            .unwrap_or(self.current_size);
+
+        let mut last_index = first_index;
+        while last_index < self.current_size && self.regions[last_index].start <= merged_end {
+            let region = self.regions[last_index];
+
+            merged_start = merged_start.min(region.start);
+            merged_end = merged_end.max(region.start + region.size);
+
+            last_index += 1;
+        }
+
+        let merged_region = MemoryRegion::new(merged_start, merged_end - merged_start);
+
+        if last_index == first_index {
+            self.insert_region(first_index, merged_region)?;
+        } else {
+            // Keep the merged region in the first slot and close the gap left
+            // by the others.
+            self.regions[first_index] = merged_region;
+            self.regions
+                .copy_within(last_index..self.current_size, first_index + 1);
+            self.current_size -= last_index - first_index - 1;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the total size of the memory regions in bytes.
+    pub fn total_size(&self) -> usize {
+        self.regions().map(|region| region.size).sum()
+    }
+
+    /// Returns true if an address lies within one of the memory regions.
+    ///
+    /// # Parameters
+    ///
+    /// * `address` - The physical address.
+    pub fn contains(&self, address: PhysicalAddress) -> bool {
+        let address = address.raw_address();
+
+        self.regions()
+            .any(|region| address >= region.start && address - region.start < region.size)
+    }
+
+    /// Inserts a region at an index, shifting the regions after it one slot
+    /// to the right.
+    ///
+    /// # Returns
+    ///
+    /// `MemoryMapError::Full` if the memory map has no free slot.
+    fn insert_region(&mut self, index: usize, region: MemoryRegion) -> Result<(), MemoryMapError> {
+        if self.current_size == self.regions.len() {
+            return Err(MemoryMapError::Full);
+        }
+
+        self.regions
+            .copy_within(index..self.current_size, index + 1);
+        self.regions[index] = region;
         self.current_size += 1;
+
+        Ok(())
     }
 
     /// Removes or adjusts memory regions in this memory map that overlap with a
@@ -71,17 +180,31 @@ impl MemoryMap {
     /// * `reserved_start` - The start address of the reserved memory region.
     /// * `reserved_size` - The size of the reserved memory region in bytes.
     ///
+    /// # Returns
+    ///
+    /// `MemoryMapError::RegionDropped` if a region had to be split and the
+    /// memory map had no free slot for the part after the reserved region.
+    /// The reserved region is removed either way.
+    ///
     /// # Side Effects
     ///
     /// This function modifies this memory map by potentially removing regions,
     /// adjusting region boundaries, or adding new regions when splitting is
     /// required.
-    pub fn carve_out_region(&mut self, reserved_start: usize, reserved_size: usize) {
+    pub fn carve_out_region(
+        &mut self,
+        reserved_start: PhysicalAddress,
+        reserved_size: usize,
+    ) -> Result<(), MemoryMapError> {
         // Skip if the reserved region is invalid.
         if reserved_size == 0 {
-            return;
+            return Ok(());
         }
 
+        let reserved_start = reserved_start.raw_address();
+
+        let mut result = Ok(());
+
         let mut i = 0;
         while i < self.current_size {
             let region = self.regions[i];
@@ -138,12 +261,10 @@ impl MemoryMap {
                     // Update the current region to be the beginning part.
                     self.regions[i].size = reserved_start - region.start;
 
-                    // Add the new region if there's space.
-                    if self.current_size < self.regions.len() {
-                        // Add the new region by inserting it at the end and
-                        // we'll process it later.
-                        self.regions[self.current_size] = end_region;
-                        self.current_size += 1;
+                    // Add the new region right after the beginning part, which
+                    // keeps the regions sorted.
+                    if self.insert_region(i + 1, end_region).is_err() {
+                        result = Err(MemoryMapError::RegionDropped(end_region));
                     }
 
                     i += 1;
@@ -153,12 +274,17 @@ impl MemoryMap {
                 i += 1;
             }
         }
+
+        result
     }
 
-    pub fn walk_regions(&self, callback: impl Fn(&MemoryRegion)) {
-        for i in 0..self.current_size {
-            callback(&self.regions[i]);
-        }
+    /// Returns an iterator over the memory regions, sorted by start address.
+    ///
+    /// # Returns
+    ///
+    /// An iterator yielding every region of the memory map.
+    pub fn regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        self.regions[..self.current_size].iter().copied()
     }
 }
 
@@ -171,7 +297,9 @@ mod tests {
         let mut memory_map = MemoryMap::new();
 
         // Add a region starting at 0x1000 with a size of 0x2000.
-        memory_map.add_region(0x1000, 0x2000);
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x2000)
+            .unwrap();
 
         assert_eq!(memory_map.current_size, 1);
         assert_eq!(memory_map.regions[0].start, 0x1000);
@@ -183,10 +311,14 @@ mod tests {
         let mut memory_map = MemoryMap::new();
 
         // Add a region starting at 0x1000 with a size of 0x2000.
-        memory_map.add_region(0x1000, 0x2000);
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x2000)
+            .unwrap();
 
         // Carve out a reserved region starting at 0x0 with a size of 0x1000.
-        memory_map.carve_out_region(0x0, 0x1000);
+        memory_map
+            .carve_out_region(PhysicalAddress::new(0x0), 0x1000)
+            .unwrap();
 
         assert_eq!(memory_map.current_size, 1);
         assert_eq!(memory_map.regions[0].start, 0x1000);
@@ -198,10 +330,14 @@ mod tests {
         let mut memory_map = MemoryMap::new();
 
         // Add a region starting at 0x1000 with a size of 0x2000.
-        memory_map.add_region(0x1000, 0x2000);
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x2000)
+            .unwrap();
 
         // Carve out a reserved region starting at 0x3000 with a size of 0x1000.
-        memory_map.carve_out_region(0x3000, 0x1000);
+        memory_map
+            .carve_out_region(PhysicalAddress::new(0x3000), 0x1000)
+            .unwrap();
 
         assert_eq!(memory_map.current_size, 1);
         assert_eq!(memory_map.regions[0].start, 0x1000);
@@ -213,10 +349,14 @@ mod tests {
         let mut memory_map = MemoryMap::new();
 
         // Add a region that will be completely reserved.
-        memory_map.add_region(4096, 4096);
+        memory_map
+            .add_region(PhysicalAddress::new(4096), 4096)
+            .unwrap();
 
         // Carve out a reserved region that completely covers the added region.
-        memory_map.carve_out_region(4096, 4096);
+        memory_map
+            .carve_out_region(PhysicalAddress::new(4096), 4096)
+            .unwrap();
 
         // Expect that the memory region is removed.
         assert_eq!(memory_map.current_size, 0);
@@ -227,11 +367,15 @@ mod tests {
         let mut memory_map = MemoryMap::new();
 
         // Add a region from 4096 with size 8192.
-        memory_map.add_region(4096, 8192);
+        memory_map
+            .add_region(PhysicalAddress::new(4096), 8192)
+            .unwrap();
 
         // Reserved region overlaps the start. For a 4KiB page,
         // aligned_reserved_start = 4096 and aligned_reserved_end = 8192.
-        memory_map.carve_out_region(4096, 4096);
+        memory_map
+            .carve_out_region(PhysicalAddress::new(4096), 4096)
+            .unwrap();
 
         // Expect the region now starts at 8192 and the new size is 4096.
         assert_eq!(memory_map.current_size, 1);
@@ -244,11 +388,15 @@ mod tests {
         let mut memory_map = MemoryMap::new();
 
         // Add a region from 4096 with size 8192.
-        memory_map.add_region(4096, 8192);
+        memory_map
+            .add_region(PhysicalAddress::new(4096), 8192)
+            .unwrap();
 
         // Reserved region overlaps the end. With reserved_start = 8192 and
         // reserved_size = 4096, aligned_reserved_start = 8192.
-        memory_map.carve_out_region(8192, 4096);
+        memory_map
+            .carve_out_region(PhysicalAddress::new(8192), 4096)
+            .unwrap();
 
         // Expect the region remains from 4096 to 8191 (size of 4096).
         assert_eq!(memory_map.current_size, 1);
@@ -261,12 +409,16 @@ mod tests {
         let mut memory_map = MemoryMap::new();
 
         // Add a region from 4096 with size 12288.
-        memory_map.add_region(4096, 12288);
+        memory_map
+            .add_region(PhysicalAddress::new(4096), 12288)
+            .unwrap();
 
         // Reserved region is in the middle. With reserved_start = 8192 and
         // reserved_size = 4096, aligned_reserved_start = 8192,
         // aligned_reserved_end = 12288.
-        memory_map.carve_out_region(8192, 4096);
+        memory_map
+            .carve_out_region(PhysicalAddress::new(8192), 4096)
+            .unwrap();
 
         // Expect the original region is split into two: First region: from 4096
         // to 8191 (4096 bytes). Second region: from 12288 to 16383 (4096
@@ -287,14 +439,412 @@ mod tests {
         let mut memory_map = MemoryMap::new();
 
         // Add a region.
-        memory_map.add_region(4096, 4096);
+        memory_map
+            .add_region(PhysicalAddress::new(4096), 4096)
+            .unwrap();
 
         // Call carve_out_region with reserved_size 0.
-        memory_map.carve_out_region(4096, 0);
+        memory_map
+            .carve_out_region(PhysicalAddress::new(4096), 0)
+            .unwrap();
 
         // Expect no changes.
         assert_eq!(memory_map.current_size, 1);
         assert_eq!(memory_map.regions[0].start, 4096);
         assert_eq!(memory_map.regions[0].size, 4096);
     }
+
+    #[test]
+    fn test_add_region_keeps_regions_sorted() {
+        let mut memory_map = MemoryMap::new();
+
+        memory_map
+            .add_region(PhysicalAddress::new(0x10000), 0x1000)
+            .unwrap();
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x1000)
+            .unwrap();
+        memory_map
+            .add_region(PhysicalAddress::new(0x5000), 0x1000)
+            .unwrap();
+
+        assert_eq!(memory_map.current_size, 3);
+        assert_eq!(memory_map.regions[0].start, 0x1000);
+        assert_eq!(memory_map.regions[1].start, 0x5000);
+        assert_eq!(memory_map.regions[2].start, 0x10000);
+    }
+
+    #[test]
+    fn test_add_region_merges_adjacent_regions() {
+        let mut memory_map = MemoryMap::new();
+
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x1000)
+            .unwrap();
+        memory_map
+            .add_region(PhysicalAddress::new(0x3000), 0x1000)
+            .unwrap();
+
+        // Fills the gap between the two regions and touches both.
+        memory_map
+            .add_region(PhysicalAddress::new(0x2000), 0x1000)
+            .unwrap();
+
+        assert_eq!(memory_map.current_size, 1);
+        assert_eq!(memory_map.regions[0], MemoryRegion::new(0x1000, 0x3000));
+    }
+
+    #[test]
+    fn test_add_region_merges_overlapping_regions() {
+        let mut memory_map = MemoryMap::new();
+
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x2000)
+            .unwrap();
+        memory_map
+            .add_region(PhysicalAddress::new(0x5000), 0x2000)
+            .unwrap();
+        memory_map
+            .add_region(PhysicalAddress::new(0x9000), 0x1000)
+            .unwrap();
+
+        // Overlaps the first two regions but not the third.
+        memory_map
+            .add_region(PhysicalAddress::new(0x2000), 0x4000)
+            .unwrap();
+
+        assert_eq!(memory_map.current_size, 2);
+        assert_eq!(memory_map.regions[0], MemoryRegion::new(0x1000, 0x6000));
+        assert_eq!(memory_map.regions[1], MemoryRegion::new(0x9000, 0x1000));
+
+        // A region inside an existing one changes nothing.
+        memory_map
+            .add_region(PhysicalAddress::new(0x2000), 0x1000)
+            .unwrap();
+
+        assert_eq!(memory_map.current_size, 2);
+        assert_eq!(memory_map.regions[0], MemoryRegion::new(0x1000, 0x6000));
+    }
+
+    #[test]
+    fn test_total_size_and_contains() {
+        let mut memory_map = MemoryMap::new();
+
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x2000)
+            .unwrap();
+        memory_map
+            .add_region(PhysicalAddress::new(0x8000), 0x1000)
+            .unwrap();
+
+        assert_eq!(memory_map.total_size(), 0x3000);
+
+        assert!(memory_map.contains(PhysicalAddress::new(0x1000)));
+        assert!(memory_map.contains(PhysicalAddress::new(0x2FFF)));
+        assert!(!memory_map.contains(PhysicalAddress::new(0x3000)));
+        assert!(memory_map.contains(PhysicalAddress::new(0x8800)));
+        assert!(!memory_map.contains(PhysicalAddress::new(0x0)));
+    }
+
+    #[test]
+    fn test_carve_out_region_case_middle_overlap_keeps_regions_sorted() {
+        let mut memory_map = MemoryMap::new();
+
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x3000)
+            .unwrap();
+        memory_map
+            .add_region(PhysicalAddress::new(0x10000), 0x1000)
+            .unwrap();
+
+        memory_map
+            .carve_out_region(PhysicalAddress::new(0x2000), 0x1000)
+            .unwrap();
+
+        assert_eq!(memory_map.current_size, 3);
+        assert_eq!(memory_map.regions[0], MemoryRegion::new(0x1000, 0x1000));
+        assert_eq!(memory_map.regions[1], MemoryRegion::new(0x3000, 0x1000));
+        assert_eq!(memory_map.regions[2], MemoryRegion::new(0x10000, 0x1000));
+    }
+
+    #[test]
+    fn test_add_region_when_full() {
+        let mut memory_map = MemoryMap::new();
+
+        // Leave a gap after every region so that none of them merge.
+        for index in 0..128 {
+            memory_map
+                .add_region(PhysicalAddress::new(index * 0x2000), 0x1000)
+                .unwrap();
+        }
+
+        assert_eq!(
+            memory_map.add_region(PhysicalAddress::new(0x1000_0000), 0x1000),
+            Err(MemoryMapError::Full)
+        );
+        assert_eq!(memory_map.current_size, 128);
+
+        // A region that merges with an existing one still fits.
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x1000)
+            .unwrap();
+        assert_eq!(memory_map.current_size, 127);
+    }
+
+    #[test]
+    fn test_carve_out_region_when_full_reports_dropped_region() {
+        let mut memory_map = MemoryMap::new();
+
+        for index in 0..128 {
+            memory_map
+                .add_region(PhysicalAddress::new(index * 0x4000), 0x3000)
+                .unwrap();
+        }
+
+        // Splitting the first region needs another slot.
+        assert_eq!(
+            memory_map.carve_out_region(PhysicalAddress::new(0x1000), 0x1000),
+            Err(MemoryMapError::RegionDropped(MemoryRegion::new(
+                0x2000, 0x1000
+            )))
+        );
+
+        // The reserved region is removed anyway.
+        assert_eq!(memory_map.regions[0], MemoryRegion::new(0, 0x1000));
+        assert!(!memory_map.contains(PhysicalAddress::new(0x1000)));
+        assert_eq!(memory_map.current_size, 128);
+    }
+
+    #[test]
+    fn test_regions_iterator() {
+        let mut memory_map = MemoryMap::new();
+
+        memory_map
+            .add_region(PhysicalAddress::new(0x8000), 0x1000)
+            .unwrap();
+        memory_map
+            .add_region(PhysicalAddress::new(0x1000), 0x2000)
+            .unwrap();
+
+        let mut regions = memory_map.regions();
+        assert_eq!(regions.next(), Some(MemoryRegion::new(0x1000, 0x2000)));
+        assert_eq!(regions.next(), Some(MemoryRegion::new(0x8000, 0x1000)));
+        assert_eq!(regions.next(), None);
+
+        let largest_region = memory_map.regions().max_by_key(|region| region.size);
+        assert_eq!(largest_region, Some(MemoryRegion::new(0x1000, 0x2000)));
+    }
+
+    /// A SplitMix64 generator, so that the property tests are random but
+    /// reproducible from their seed.
+    struct TestRandom(u64);
+
+    impl TestRandom {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+            let mut value = self.0;
+            value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+            value ^ (value >> 31)
+        }
+
+        /// Returns a value in `0..bound`.
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+    }
+
+    /// The address of the first unit of the address range the property tests
+    /// use, which is where RAM starts on QEMU's virt machine.
+    const PROPERTY_BASE_ADDRESS: usize = 0x8000_0000;
+
+    /// The number of units in the address range the property tests use.
+    const PROPERTY_UNIT_COUNT: usize = 256;
+
+    /// Applies random sequences of `add_region` and `carve_out_region` calls
+    /// whose addresses and sizes are multiples of `unit`, and checks the
+    /// memory map against a model that tracks every unit separately.
+    ///
+    /// After every call the regions must be sorted, non-empty, and neither
+    /// overlap nor touch, adding must never shrink the total size and carving
+    /// must never grow it, and the total size must match the model. After
+    /// each sequence the regions must be exactly the runs of units the model
+    /// holds, so a carve-out removes exactly the reserved bytes, no more and
+    /// no less, and splits a region where needed.
+    fn check_random_sequences(unit: usize, seed: u64) {
+        const CASE_COUNT: usize = 200;
+        const MAX_CALL_COUNT: usize = 48;
+
+        let mut random = TestRandom(seed);
+
+        for case in 0..CASE_COUNT {
+            let mut memory_map = MemoryMap::new();
+            let mut model = [false; PROPERTY_UNIT_COUNT];
+
+            for _ in 0..random.below(MAX_CALL_COUNT) + 1 {
+                let first_unit = random.below(PROPERTY_UNIT_COUNT);
+                let unit_count = random.below((PROPERTY_UNIT_COUNT - first_unit) / 2 + 1);
+
+                let start = PhysicalAddress::new(PROPERTY_BASE_ADDRESS + first_unit * unit);
+                let size = unit_count * unit;
+                let is_carve_out = random.below(3) != 0;
+
+                let total_size_before = memory_map.total_size();
+
+                if is_carve_out {
+                    memory_map.carve_out_region(start, size).unwrap();
+                    assert!(memory_map.total_size() <= total_size_before);
+                } else {
+                    memory_map.add_region(start, size).unwrap();
+                    assert!(memory_map.total_size() >= total_size_before);
+                }
+
+                model[first_unit..first_unit + unit_count].fill(!is_carve_out);
+
+                let regions: Vec<_> = memory_map.regions().collect();
+                for region in &regions {
+                    assert!(region.size > 0, "case {}: empty region", case);
+                }
+                for pair in regions.windows(2) {
+                    assert!(
+                        pair[0].end() + 1 < pair[1].start,
+                        "case {}: {:x?} overlaps or touches {:x?}",
+                        case,
+                        pair[0],
+                        pair[1]
+                    );
+                }
+
+                let model_size = model.iter().filter(|&&is_usable| is_usable).count() * unit;
+                assert_eq!(memory_map.total_size(), model_size, "case {}", case);
+
+                if is_carve_out && size > 0 {
+                    assert!(!memory_map.contains(start));
+                    assert!(
+                        !memory_map.contains(PhysicalAddress::new(start.raw_address() + size - 1))
+                    );
+                }
+            }
+
+            let mut expected_regions = Vec::new();
+            let mut index = 0;
+            while index < PROPERTY_UNIT_COUNT {
+                if !model[index] {
+                    index += 1;
+                    continue;
+                }
+
+                let run_start = index;
+                while index < PROPERTY_UNIT_COUNT && model[index] {
+                    index += 1;
+                }
+
+                expected_regions.push(MemoryRegion::new(
+                    PROPERTY_BASE_ADDRESS + run_start * unit,
+                    (index - run_start) * unit,
+                ));
+            }
+
+            assert_eq!(
+                memory_map.regions().collect::<Vec<_>>(),
+                expected_regions,
+                "case {}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn test_property_byte_granular_sequences() {
+        check_random_sequences(1, 0x1093);
+    }
+
+    #[test]
+    fn test_property_page_granular_sequences_stay_page_aligned() {
+        const PAGE_SIZE: usize = 4096;
+
+        check_random_sequences(PAGE_SIZE, 0x4096);
+
+        // The model comparison already pins every region to whole units, which
+        // this checks directly for a sequence that splits regions repeatedly.
+        let mut random = TestRandom(0x1000);
+        let mut memory_map = MemoryMap::new();
+        memory_map
+            .add_region(
+                PhysicalAddress::new(PROPERTY_BASE_ADDRESS),
+                PROPERTY_UNIT_COUNT * PAGE_SIZE,
+            )
+            .unwrap();
+
+        for _ in 0..64 {
+            let first_page = random.below(PROPERTY_UNIT_COUNT);
+            let page_count = random.below(4) + 1;
+
+            memory_map
+                .carve_out_region(
+                    PhysicalAddress::new(PROPERTY_BASE_ADDRESS + first_page * PAGE_SIZE),
+                    page_count * PAGE_SIZE,
+                )
+                .unwrap();
+
+            for region in memory_map.regions() {
+                assert_eq!(region.start % PAGE_SIZE, 0);
+                assert_eq!(region.size % PAGE_SIZE, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_property_carve_out_is_idempotent_and_order_independent() {
+        let mut random = TestRandom(0xCA4E);
+
+        for _ in 0..200 {
+            let mut memory_map = MemoryMap::new();
+            for _ in 0..random.below(8) + 1 {
+                memory_map
+                    .add_region(
+                        PhysicalAddress::new(PROPERTY_BASE_ADDRESS + random.below(0x1000)),
+                        random.below(0x400) + 1,
+                    )
+                    .unwrap();
+            }
+
+            let carve_outs: Vec<_> = (0..random.below(6) + 1)
+                .map(|_| {
+                    (
+                        PhysicalAddress::new(PROPERTY_BASE_ADDRESS + random.below(0x1000)),
+                        random.below(0x200),
+                    )
+                })
+                .collect();
+
+            let mut forward = memory_map.clone();
+            for &(start, size) in &carve_outs {
+                forward.carve_out_region(start, size).unwrap();
+            }
+
+            let mut backward = memory_map.clone();
+            for &(start, size) in carve_outs.iter().rev() {
+                backward.carve_out_region(start, size).unwrap();
+            }
+
+            assert_eq!(
+                forward.regions().collect::<Vec<_>>(),
+                backward.regions().collect::<Vec<_>>()
+            );
+
+            // Carving out the same regions again changes nothing.
+            let mut repeated = forward.clone();
+            for &(start, size) in &carve_outs {
+                repeated.carve_out_region(start, size).unwrap();
+            }
+
+            assert_eq!(
+                repeated.regions().collect::<Vec<_>>(),
+                forward.regions().collect::<Vec<_>>()
+            );
+        }
+    }
 }
@@ -1,5 +1,180 @@
 use super::physical_memory_allocator::PhysicalMemoryAllocator;
-use common_lib::memory::{PhysicalPageNumber, VirtualPageNumber};
+use common_lib::{
+    layout::PagingMode,
+    memory::{PhysicalAddress, PhysicalPageNumber, VirtualAddress, VirtualPageNumber},
+};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// The offset added to the physical address of a page table to obtain the
+/// address through which the page table is accessed.
+///
+/// The boot stage accesses page tables by their physical address, either
+/// before paging is enabled or through the identity mapping, and leaves this at
+/// zero. The kernel sets it to the base of the direct physical memory mapping
+/// since physical addresses are not identity mapped for it.
+static PHYSICAL_MEMORY_OFFSET: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the offset used to access page tables by their physical address.
+///
+/// # Arguments
+///
+/// * `offset` - The value to add to a physical address to get an address that
+///   is mapped to the same physical memory in the current address space.
+pub fn set_physical_memory_offset(offset: usize) {
+    PHYSICAL_MEMORY_OFFSET.store(offset, Ordering::Relaxed);
+}
+
+/// Returns the offset used to access page tables by their physical address.
+pub fn physical_memory_offset() -> usize {
+    PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed)
+}
+
+/// Returns a pointer through which the page table stored in the given physical
+/// page can be accessed, taking the physical memory offset into account.
+///
+/// # Arguments
+///
+/// * `ppn` - The physical page number of the page table.
+pub fn page_table_pointer(ppn: PhysicalPageNumber) -> *mut PageTable {
+    (ppn.to_physical_address() + physical_memory_offset()) as *mut PageTable
+}
+
+/// The paging mode the page tables are built and walked in, as the MODE field
+/// of satp.
+///
+/// It stays at sv39, which every hart supports, until the boot stage has
+/// chosen the mode to enable, or until the kernel has read the mode the boot
+/// stage enabled.
+static PAGING_MODE: AtomicUsize = AtomicUsize::new(PagingMode::Sv39.satp_mode());
+
+/// Sets the paging mode, which decides how many levels of page tables the
+/// functions of this module build and walk. Only to be called before any page
+/// table of the mode is built or walked.
+///
+/// # Arguments
+///
+/// * `paging_mode` - The paging mode satp selects the page tables in.
+pub fn set_paging_mode(paging_mode: PagingMode) {
+    PAGING_MODE.store(paging_mode.satp_mode(), Ordering::Relaxed);
+}
+
+/// Returns the paging mode the page tables are built and walked in.
+pub fn paging_mode() -> PagingMode {
+    // Only the MODE fields of paging modes are ever stored.
+    PagingMode::from_satp_mode(PAGING_MODE.load(Ordering::Relaxed)).unwrap_or(PagingMode::Sv39)
+}
+
+/// Returns the level of the root page table in the paging mode.
+fn root_level() -> u8 {
+    paging_mode().root_level() as u8
+}
+
+/// True if every hart that uses the page tables implements the Svpbmt
+/// extension, which gives the memory type field of leaf entries a meaning.
+/// Without it the field is reserved and must stay zero.
+static IS_SVPBMT_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Lets leaf entries created from then on carry the memory type of their
+/// flags. Only to be called once the ISA string shows that every hart
+/// implements Svpbmt.
+///
+/// # Arguments
+///
+/// * `is_enabled` - True if every hart implements Svpbmt.
+pub fn set_svpbmt_enabled(is_enabled: bool) {
+    IS_SVPBMT_ENABLED.store(is_enabled, Ordering::Relaxed);
+}
+
+/// Returns true if leaf entries carry the memory type of their flags.
+pub fn is_svpbmt_enabled() -> bool {
+    IS_SVPBMT_ENABLED.load(Ordering::Relaxed)
+}
+
+/// The memory attributes of a page under the Svpbmt extension, which override
+/// the attributes the platform gives the physical address.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum MemoryType {
+    /// The attributes the platform gives the physical address. The only
+    /// memory type without Svpbmt.
+    #[default]
+    Pma = 0,
+
+    /// Non-cacheable, idempotent, weakly ordered main memory, such as DMA
+    /// buffers shared with devices that do not snoop the caches.
+    NonCacheable = 1,
+
+    /// Non-cacheable, non-idempotent, strongly ordered I/O, such as device
+    /// registers.
+    Io = 2,
+}
+
+/// A function that invalidates cached translations for a range of virtual
+/// pages after their page table entries changed.
+///
+/// # Arguments
+///
+/// * `start_vpn` - The first virtual page whose translation changed.
+/// * `page_count` - The number of consecutive pages whose translations
+///   changed.
+pub type TlbFlushHandler = fn(start_vpn: VirtualPageNumber, page_count: usize);
+
+/// The registered `TlbFlushHandler`, or null if there is none.
+///
+/// The boot stage runs on a single hart and only adds mappings, so it leaves
+/// this unset. The kernel registers a handler that flushes the TLB on every
+/// hart.
+static TLB_FLUSH_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Registers the function called whenever an existing mapping is removed or
+/// changed.
+///
+/// # Arguments
+///
+/// * `handler` - The function that invalidates the stale translations.
+pub fn set_tlb_flush_handler(handler: TlbFlushHandler) {
+    TLB_FLUSH_HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+/// Calls the registered `TlbFlushHandler`, if any, for a range of pages.
+fn flush_tlb(start_vpn: VirtualPageNumber, page_count: usize) {
+    let handler = TLB_FLUSH_HANDLER.load(Ordering::Acquire);
+    if handler.is_null() || page_count == 0 {
+        return;
+    }
+
+    // The pointer was created from a `TlbFlushHandler` in
+    // `set_tlb_flush_handler`.
+    let handler: TlbFlushHandler = unsafe { core::mem::transmute(handler) };
+
+    handler(start_vpn, page_count);
+}
+
+/// Errors that can occur while creating page table mappings.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MapError {
+    /// The physical memory allocator ran out of pages while allocating an
+    /// intermediate page table or a backing page.
+    OutOfMemory,
+
+    /// The virtual page is already covered by a leaf entry that maps it to a
+    /// different physical page or with a larger page size.
+    AlreadyMapped,
+
+    /// The physical page number is not aligned to the size of the page being
+    /// mapped.
+    Misaligned,
+
+    /// The mapping would replace a valid entry that points to a lower level
+    /// page table, silently discarding every mapping beneath it.
+    WouldClobberTable,
+
+    /// The virtual page is not mapped.
+    NotMapped,
+
+    /// The virtual page is covered by a superpage leaf entry, which would
+    /// have to be split to change a single 4KiB page.
+    WouldSplitSuperpage,
+}
 
 #[derive(Clone)]
 #[repr(align(4096))]
@@ -55,6 +230,10 @@ impl PageTableEntry {
     const FLAG_GLOBAL: u64 = 1 << 5; // G bit - global mapping
     const FLAG_ACCESSED: u64 = 1 << 6; // A bit - page was accessed
     const FLAG_DIRTY: u64 = 1 << 7; // D bit - page was written to
+    const SOFTWARE_SHIFT: u32 = 8; // RSW bits - reserved for software
+    const SOFTWARE_MASK: u64 = 0b11 << Self::SOFTWARE_SHIFT;
+    const MEMORY_TYPE_SHIFT: u32 = 61; // PBMT bits - Svpbmt memory type
+    const MEMORY_TYPE_MASK: u64 = 0b11 << Self::MEMORY_TYPE_SHIFT;
 
     pub const fn new() -> Self {
         Self(0)
@@ -160,12 +339,60 @@ impl PageTableEntry {
         }
     }
 
-    pub const fn set_flags(&mut self, flags: &PageTableEntryFlags) {
+    /// Returns the two RSW bits, which the hardware ignores and software may
+    /// use for its own bookkeeping.
+    pub const fn get_software_bits(&self) -> u64 {
+        (self.0 & Self::SOFTWARE_MASK) >> Self::SOFTWARE_SHIFT
+    }
+
+    /// Sets the two RSW bits from the lowest two bits of `bits`.
+    pub const fn set_software_bits(&mut self, bits: u64) {
+        self.0 = (self.0 & !Self::SOFTWARE_MASK)
+            | ((bits << Self::SOFTWARE_SHIFT) & Self::SOFTWARE_MASK);
+    }
+
+    /// Returns the Svpbmt memory type of the page. Always `MemoryType::Pma`
+    /// for entries created without Svpbmt.
+    pub const fn get_memory_type(&self) -> MemoryType {
+        match (self.0 & Self::MEMORY_TYPE_MASK) >> Self::MEMORY_TYPE_SHIFT {
+            1 => MemoryType::NonCacheable,
+            2 => MemoryType::Io,
+            _ => MemoryType::Pma,
+        }
+    }
+
+    /// Sets the Svpbmt memory type of the page. Only valid on harts that
+    /// implement Svpbmt, where a nonzero memory type is not reserved.
+    pub const fn set_memory_type(&mut self, memory_type: MemoryType) {
+        self.0 =
+            (self.0 & !Self::MEMORY_TYPE_MASK) | ((memory_type as u64) << Self::MEMORY_TYPE_SHIFT);
+    }
+
+    /// Sets the permissions and global bit of the entry, and its memory type
+    /// if Svpbmt is enabled. Without Svpbmt the memory type stays
+    /// `MemoryType::Pma`.
+    pub fn set_flags(&mut self, flags: &PageTableEntryFlags) {
         self.set_readable(flags.readable);
         self.set_writable(flags.writable);
         self.set_executable(flags.executable);
         self.set_user(flags.user);
         self.set_global(flags.global);
+
+        if is_svpbmt_enabled() {
+            self.set_memory_type(flags.memory_type);
+        }
+    }
+
+    /// Returns the permissions, global bit, and memory type of the entry.
+    pub const fn get_flags(&self) -> PageTableEntryFlags {
+        PageTableEntryFlags {
+            readable: self.is_readable(),
+            writable: self.is_writable(),
+            executable: self.is_executable(),
+            user: self.is_user(),
+            global: self.is_global(),
+            memory_type: self.get_memory_type(),
+        }
     }
 
     pub const fn get_ppn(&self) -> PhysicalPageNumber {
@@ -175,8 +402,9 @@ impl PageTableEntry {
     }
 
     pub const fn set_ppn(&mut self, ppn: PhysicalPageNumber) {
-        // Clear the old PPN and set the new one.
-        self.0 = (self.0 & !0x0000_003F_FFFF_FFF0)
+        // Clear the old PPN in bits 10 through 53 and set the new one, keeping
+        // the flags below it.
+        self.0 = (self.0 & !0x003F_FFFF_FFFF_FC00)
             | ((ppn.raw_ppn() as u64 & 0x0000_0FFF_FFFF_FFFF) << 10);
     }
 
@@ -194,6 +422,9 @@ pub struct PageTableEntryFlags {
     pub executable: bool,
     pub user: bool,
     pub global: bool,
+
+    /// The Svpbmt memory type, which is ignored unless Svpbmt is enabled.
+    pub memory_type: MemoryType,
 }
 
 impl PageTableEntryFlags {
@@ -236,6 +467,14 @@ impl PageTableEntryFlags {
     pub const fn set_global(&mut self, global: bool) {
         self.global = global;
     }
+
+    pub const fn get_memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    pub const fn set_memory_type(&mut self, memory_type: MemoryType) {
+        self.memory_type = memory_type;
+    }
 }
 
 /// Assigns a new physical page to the specified virtual page number in the page
@@ -249,7 +488,8 @@ impl PageTableEntryFlags {
 /// leaf entry's valid, readable, writable, and executable permissions are set
 /// based on the flags argument. The accessed and dirty flags are initially
 /// cleared. If the page is already allocated, the function returns the existing
-/// physical page.
+/// physical page as long as it does not conflict with the requested physical
+/// page.
 ///
 /// # Arguments
 ///
@@ -262,80 +502,95 @@ impl PageTableEntryFlags {
 ///
 /// # Returns
 ///
-/// * `Some(PhysicalPageNumber)` - The physical page number that was mapped
+/// * `Ok(PhysicalPageNumber)` - The physical page number that was mapped
 ///   (either newly allocated or previously mapped).
-/// * `None` - If the allocation failed due to a lack of physical memory.
+/// * `Err(MapError::OutOfMemory)` - If a page table or backing page could not
+///   be allocated.
+/// * `Err(MapError::AlreadyMapped)` - If the virtual page is already mapped to
+///   a different physical page or is covered by a superpage leaf entry.
 pub fn allocate_vpn(
     page_table_root: &mut PageTable,
     vpn: VirtualPageNumber,
     ppn: Option<PhysicalPageNumber>,
     flags: &PageTableEntryFlags,
     physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
-) -> Option<PhysicalPageNumber> {
-    // Extract the 9-bit indices for each level of the page table.
-    let vpn2 = vpn.get_level_2_index();
-    let vpn1 = vpn.get_level_1_index();
-    let vpn0 = vpn.get_level_0_index();
-
-    // Get the level 2 (root) entry.
-    let mut page_table_level_2_entry = *page_table_root.get_entry(vpn2);
-
-    // If the level 2 entry is not valid, allocate a new level 1 page table.
-    if !page_table_level_2_entry.is_valid() {
-        let page_table_level_1_ptr = physical_memory_allocator.allocate_page()?;
-        let page_table_level_1_ppn =
-            PhysicalPageNumber::from_physical_address(page_table_level_1_ptr as usize);
-        let page_table_level_1 = unsafe { &mut *(page_table_level_1_ptr as *mut PageTable) };
-
-        // Initialize the new page table to all zeros.
-        page_table_level_1.clear();
-
-        // Set up the level 2 entry to point to the new level 1 page table.
-        page_table_level_2_entry.set_valid(true);
-        page_table_level_2_entry.set_ppn(page_table_level_1_ppn);
-
-        // Write the updated entry back to the root page table.
-        page_table_root.set_entry(vpn2, page_table_level_2_entry);
-    }
+) -> Result<PhysicalPageNumber, MapError> {
+    allocate_vpn_from_level(
+        page_table_root,
+        root_level(),
+        vpn,
+        ppn,
+        flags,
+        physical_memory_allocator,
+    )
+}
 
-    // Access the level 1 page table.
-    let page_table_level_1_ptr =
-        page_table_level_2_entry.get_ppn().to_physical_address() as *mut PageTable;
-    let page_table_level_1 = unsafe { &mut *page_table_level_1_ptr };
+/// Maps a virtual page like `allocate_vpn`, in page tables whose root is at
+/// the given level.
+fn allocate_vpn_from_level(
+    page_table_root: &mut PageTable,
+    root_level: u8,
+    vpn: VirtualPageNumber,
+    ppn: Option<PhysicalPageNumber>,
+    flags: &PageTableEntryFlags,
+    physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
+) -> Result<PhysicalPageNumber, MapError> {
+    let mut page_table = page_table_root;
+
+    // Descend from the root to the level 0 page table, creating the page
+    // tables on the way that do not exist yet.
+    for level in (1..=root_level).rev() {
+        let index = vpn.get_level_index(level as usize);
+        let mut entry = *page_table.get_entry(index);
+
+        // A leaf entry above level 0 is a superpage that already covers this
+        // virtual page.
+        if entry.is_leaf() {
+            return Err(MapError::AlreadyMapped);
+        }
 
-    // Get the level 1 entry.
-    let mut page_table_level_1_entry = *page_table_level_1.get_entry(vpn1);
+        // If the entry is not valid, allocate a new page table below it.
+        if !entry.is_valid() {
+            let child_page_table_ppn = physical_memory_allocator
+                .allocate_page()
+                .ok_or(MapError::OutOfMemory)?
+                .ppn();
+            let child_page_table = unsafe { &mut *page_table_pointer(child_page_table_ppn) };
 
-    // If the level 1 entry is not valid, allocate a new level 0 page table.
-    if !page_table_level_1_entry.is_valid() {
-        let page_table_level_0_ptr = physical_memory_allocator.allocate_page()?;
-        let page_table_level_0_ppn =
-            PhysicalPageNumber::from_physical_address(page_table_level_0_ptr as usize);
-        let page_table_level_0 = unsafe { &mut *(page_table_level_0_ptr as *mut PageTable) };
+            // Initialize the new page table to all zeros.
+            child_page_table.clear();
 
-        // Initialize the new page table to all zeros.
-        page_table_level_0.clear();
+            // Set up the entry to point to the new page table.
+            entry.set_valid(true);
+            entry.set_ppn(child_page_table_ppn);
 
-        // Set up the level 1 entry to point to the new level 0 page table.
-        page_table_level_1_entry.set_valid(true);
-        page_table_level_1_entry.set_ppn(page_table_level_0_ppn);
+            // Write the updated entry back to its page table.
+            page_table.set_entry(index, entry);
+        }
 
-        // Write the updated entry back to the level 1 page table.
-        page_table_level_1.set_entry(vpn1, page_table_level_1_entry);
+        page_table = unsafe { &mut *page_table_pointer(entry.get_ppn()) };
     }
 
-    // Access the level 0 page table.
-    let page_table_level_0_ptr =
-        page_table_level_1_entry.get_ppn().to_physical_address() as *mut PageTable;
-    let page_table_level_0 = unsafe { &mut *page_table_level_0_ptr };
+    let page_table_level_0 = page_table;
+    let vpn0 = vpn.get_level_0_index();
 
     // Get the level 0 entry.
     let mut page_table_level_0_entry = *page_table_level_0.get_entry(vpn0);
 
     // Check if the page is already allocated.
     if page_table_level_0_entry.is_valid() && page_table_level_0_entry.is_leaf() {
+        let existing_ppn = page_table_level_0_entry.get_ppn();
+
+        // Mapping the same physical page again is harmless, but silently
+        // keeping a mapping to a different physical page is not.
+        if let Some(requested_ppn) = ppn
+            && requested_ppn != existing_ppn
+        {
+            return Err(MapError::AlreadyMapped);
+        }
+
         // Page already allocated, return the physical page number.
-        return Some(page_table_level_0_entry.get_ppn());
+        return Ok(existing_ppn);
     }
 
     // Determine the physical page to map.
@@ -344,8 +599,10 @@ pub fn allocate_vpn(
         some_ppn
     } else {
         // Allocate a new physical page for the actual memory.
-        let physical_page_ptr = physical_memory_allocator.allocate_page()?;
-        PhysicalPageNumber::from_physical_address(physical_page_ptr as usize)
+        physical_memory_allocator
+            .allocate_page()
+            .ok_or(MapError::OutOfMemory)?
+            .ppn()
     };
 
     // Clear the entry to zeroes.
@@ -360,85 +617,95 @@ pub fn allocate_vpn(
     page_table_level_0.set_entry(vpn0, page_table_level_0_entry);
 
     // Return the physical page number that was allocated or provided.
-    Some(physical_page_ppn)
+    Ok(physical_page_ppn)
 }
 
-/// Maps a virtual page number directly to a physical page number using a level
-/// 2 (1 GiB) gigapage mapping in the sv39 paging mode.
+/// Maps a virtual page number directly to a physical page number with a leaf
+/// entry in the root page table, the largest page of the paging mode: 1GiB in
+/// sv39, 512GiB in sv48, and 256TiB in sv57.
 ///
-/// This function creates a single page table entry at the level 2 page table
-/// (the root) that maps an entire 1 GiB region of virtual memory to a
-/// corresponding 1 GiB region of physical memory. This is more efficient than
-/// using 4 KiB mappings for large memory regions as it requires fewer page
-/// table entries and TLB entries.
+/// This function creates a single page table entry in the root page table
+/// that maps the entire region of virtual memory the entry covers to a
+/// corresponding region of physical memory. This is more efficient than using
+/// 4 KiB mappings for large memory regions as it requires fewer page table
+/// entries and TLB entries.
 ///
 /// This function does not allocate memory to back the page table entry. It is
 /// assumed that the caller has already allocated the physical page number and
-/// ensured it is aligned to a 1 GiB boundary.
+/// ensured it is aligned to the size of a root entry.
 ///
 /// # Arguments
 ///
 /// * `page_table_root` - A mutable reference to the root page table.
-/// * `vpn` - The virtual page number to map. Only the level 2 index (bits
-///   26-18) is used.
-/// * `ppn` - The physical page number to map to. This should be aligned to a 1
-///   GiB boundary.
+/// * `vpn` - The virtual page number to map. Only the root index is used.
+/// * `ppn` - The physical page number to map to. This should be aligned to the
+///   size of a root entry.
 /// * `flags` - Page table entry flags to apply (readable, writable, executable,
 ///   etc.).
 ///
 /// # Returns
 ///
-/// * `true` - If the mapping was successfully created.
-/// * `false` - If the mapping could not be created because:
-///   - The entry already exists as a leaf entry.
-///   - The entry already points to a level 1 page table (has child pages).
-///
-/// # Notes
-///
-/// * This function creates a 1 GiB mapping (gigapage), so the physical page
-///   number should be aligned to a 1 GiB boundary for proper operation.
-/// * When using this function, the caller must ensure the provided physical
-///   page number is correctly aligned, as this function does not perform
-///   alignment checks.
-/// * In sv39 mode, this maps a single entry in the level 2 page table, covering
-///   the entire address range for that index (1 GiB).
-pub fn allocate_level_2_vpn(
+/// * `Ok(())` - If the mapping was successfully created.
+/// * `Err(MapError::Misaligned)` - If the physical page number is not aligned
+///   to the size of a root entry.
+/// * `Err(MapError::AlreadyMapped)` - If the entry already exists as a leaf
+///   entry.
+/// * `Err(MapError::WouldClobberTable)` - If the entry already points to a
+///   page table (has child pages).
+pub fn allocate_root_vpn(
+    page_table_root: &mut PageTable,
+    vpn: VirtualPageNumber,
+    ppn: PhysicalPageNumber,
+    flags: &PageTableEntryFlags,
+) -> Result<(), MapError> {
+    allocate_root_vpn_at_level(page_table_root, root_level(), vpn, ppn, flags)
+}
+
+/// Maps a virtual page like `allocate_root_vpn`, in page tables whose root is
+/// at the given level.
+fn allocate_root_vpn_at_level(
     page_table_root: &mut PageTable,
+    root_level: u8,
     vpn: VirtualPageNumber,
     ppn: PhysicalPageNumber,
     flags: &PageTableEntryFlags,
-) -> bool {
-    let vpn2 = vpn.get_level_2_index();
+) -> Result<(), MapError> {
+    // A root leaf must start on a boundary of its size, which means the PPN
+    // bits of every level below the root must be zero.
+    let root_leaf_ppn_mask = (1 << (9 * root_level as usize)) - 1;
+
+    if ppn.raw_ppn() & root_leaf_ppn_mask != 0 {
+        return Err(MapError::Misaligned);
+    }
+
+    let root_index = vpn.get_level_index(root_level as usize);
 
-    // Get the current level 2 entry.
-    let mut page_table_level_2_entry = *page_table_root.get_entry(vpn2);
+    // Get the current root entry.
+    let mut root_entry = *page_table_root.get_entry(root_index);
 
     // Check if the entry is already valid and is a leaf entry.
-    if page_table_level_2_entry.is_valid() && page_table_level_2_entry.is_leaf() {
-        // Entry is already allocated as a leaf, return the physical page
-        // number.
-        return false;
+    if root_entry.is_valid() && root_entry.is_leaf() {
+        return Err(MapError::AlreadyMapped);
     }
 
-    // If the entry is already valid but not a leaf (points to a level 1 page
-    // table), we cannot convert it to a leaf as it would invalidate existing
-    // mappings.
-    if page_table_level_2_entry.is_valid() {
-        return false;
+    // If the entry is already valid but not a leaf (points to a page table),
+    // we cannot convert it to a leaf as it would invalidate existing mappings.
+    if root_entry.is_valid() {
+        return Err(MapError::WouldClobberTable);
     }
 
     // Clear the entry.
-    page_table_level_2_entry.clear();
+    root_entry.clear();
 
-    // Set up the level 2 entry as a leaf entry.
-    page_table_level_2_entry.set_valid(true);
-    page_table_level_2_entry.set_flags(flags);
-    page_table_level_2_entry.set_ppn(ppn);
+    // Set up the root entry as a leaf entry.
+    root_entry.set_valid(true);
+    root_entry.set_flags(flags);
+    root_entry.set_ppn(ppn);
 
     // Write the updated entry back to the root page table.
-    page_table_root.set_entry(vpn2, page_table_level_2_entry);
+    page_table_root.set_entry(root_index, root_entry);
 
-    true
+    Ok(())
 }
 
 /// Maps a range of physical pages to the same virtual addresses in the page
@@ -462,22 +729,26 @@ pub fn allocate_level_2_vpn(
 /// * `physical_memory_allocator` - A mutable reference to a physical memory
 ///   allocator used for creating page tables if needed.
 ///
+/// # Returns
+///
+/// * `Ok(())` - If every page in the range was mapped.
+/// * `Err(MapError)` - The error from the first page that could not be mapped.
+///   Pages before the failing page remain mapped.
+///
 /// # Notes
 ///
 /// * If the start page number is greater than the end page number, the function
 ///   returns without doing anything.
 /// * This function may create intermediate page table entries as necessary.
-/// * Errors in allocation are silently ignored - if a page mapping fails, the
-///   function continues with the next page.
 pub fn identity_map_range(
     page_table_root: &mut PageTable,
     start_ppn_inclusive: PhysicalPageNumber,
     end_ppn_inclusive: PhysicalPageNumber,
     flags: &PageTableEntryFlags,
     physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
-) {
+) -> Result<(), MapError> {
     if start_ppn_inclusive > end_ppn_inclusive {
-        return;
+        return Ok(());
     }
 
     let mut current_ppn = start_ppn_inclusive;
@@ -489,10 +760,12 @@ pub fn identity_map_range(
             Some(current_ppn),
             flags,
             physical_memory_allocator,
-        );
+        )?;
 
         current_ppn = PhysicalPageNumber::from_raw_physical_page_number(current_ppn.raw_ppn() + 1);
     }
+
+    Ok(())
 }
 
 /// Maps a range of physical pages to a specified range of virtual pages in the
@@ -518,13 +791,17 @@ pub fn identity_map_range(
 /// * `physical_memory_allocator` - A mutable reference to a physical memory
 ///   allocator used for creating page tables if needed.
 ///
+/// # Returns
+///
+/// * `Ok(())` - If every page in the range was mapped.
+/// * `Err(MapError)` - The error from the first page that could not be mapped.
+///   Pages before the failing page remain mapped.
+///
 /// # Notes
 ///
 /// * This function creates a separate mapping for each page in the range.
 /// * If the number of pages to map is zero, the function returns without doing.
 /// * This function may create intermediate page table entries as necessary.
-/// * Errors in allocation are silently ignored - if a page mapping fails, the
-///   function continues with the next page.
 pub fn map_range(
     page_table_root: &mut PageTable,
     start_ppn_inclusive: PhysicalPageNumber,
@@ -532,7 +809,7 @@ pub fn map_range(
     number_of_pages_inclusive: usize,
     flags: &PageTableEntryFlags,
     physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
-) {
+) -> Result<(), MapError> {
     let mut current_ppn = start_ppn_inclusive;
     let mut current_vpn = start_vpn_inclusive;
 
@@ -543,166 +820,664 @@ pub fn map_range(
             Some(current_ppn),
             flags,
             physical_memory_allocator,
-        );
+        )?;
 
         current_ppn = PhysicalPageNumber::from_raw_physical_page_number(current_ppn.raw_ppn() + 1);
         current_vpn = VirtualPageNumber::from_raw_virtual_page_number(current_vpn.raw_vpn() + 1);
     }
+
+    Ok(())
 }
 
-/// Translates a virtual address to its corresponding physical address using the
-/// provided root page table.
+/// Maps a range of device registers with 4KiB pages.
 ///
-/// This function walks the three-level page table hierarchy to perform the
-/// address translation. It returns None if any page table entry in the
-/// translation path is invalid.
+/// The pages are readable, writable, global, and never executable, since
+/// device registers hold no code and the mapping is shared by every address
+/// space. They have the I/O memory type if Svpbmt is enabled, and otherwise
+/// keep the attributes the platform gives their physical addresses, which
+/// are uncached I/O on every platform with devices in its physical memory
+/// map.
 ///
 /// # Arguments
 ///
-/// * `page_table_root` - A reference to the root (level 2) page table.
-/// * `virtual_address` - The virtual address to translate.
+/// * `page_table_root` - A mutable reference to the root page table.
+/// * `physical_address` - The page aligned physical address of the registers.
+/// * `virtual_address` - The page aligned virtual address to map them at.
+/// * `length` - The number of bytes to map, rounded up to whole pages.
+/// * `physical_memory_allocator` - A mutable reference to a physical memory
+///   allocator used for creating page tables if needed.
 ///
 /// # Returns
 ///
-/// * `Some(usize)` - The physical address if translation succeeds.
-/// * `None` - If translation fails due to any invalid page table entries.
-pub fn translate_virtual_address(
-    page_table_root: &PageTable,
-    virtual_address: usize,
-) -> Option<usize> {
-    let vpn2: usize = ((virtual_address >> 30) & 0x1FF) as usize;
-    let vpn1: usize = ((virtual_address >> 21) & 0x1FF) as usize;
-    let vpn0: usize = ((virtual_address >> 12) & 0x1FF) as usize;
-    let offset: usize = virtual_address & 0x0000_0000_0000_0FFF;
+/// * `Ok(())` - If every page in the range was mapped.
+/// * `Err(MapError::Misaligned)` - If either address is not page aligned.
+/// * `Err(MapError)` - The error from the first page that could not be mapped.
+///   Pages before the failing page remain mapped.
+pub fn map_mmio(
+    page_table_root: &mut PageTable,
+    physical_address: PhysicalAddress,
+    virtual_address: VirtualAddress,
+    length: usize,
+    physical_memory_allocator: &mut impl PhysicalMemoryAllocator,
+) -> Result<(), MapError> {
+    const PAGE_SIZE: usize = 4096;
 
-    let page_table_level_2_entry = page_table_root.get_entry(vpn2);
-    if !page_table_level_2_entry.is_valid() {
-        return None;
+    if !physical_address.is_page_aligned() || !virtual_address.is_page_aligned() {
+        return Err(MapError::Misaligned);
     }
 
-    let page_table_level_1 =
-        unsafe { &*(page_table_level_2_entry.get_ppn().to_physical_address() as *const PageTable) };
-
-    let page_table_level_1_entry = page_table_level_1.get_entry(vpn1);
-    if !page_table_level_1_entry.is_valid() {
-        return None;
-    }
+    let flags = PageTableEntryFlags {
+        readable: true,
+        writable: true,
+        executable: false,
+        user: false,
+        global: true,
+        memory_type: MemoryType::Io,
+    };
 
-    let page_table_level_0 =
-        unsafe { &*(page_table_level_1_entry.get_ppn().to_physical_address() as *const PageTable) };
+    let start_vpn = virtual_address.vpn();
+    let page_count = length.div_ceil(PAGE_SIZE);
 
-    let page_table_level_0_entry = page_table_level_0.get_entry(vpn0);
-    if !page_table_level_0_entry.is_valid() {
-        return None;
+    for page_index in 0..page_count {
+        allocate_vpn(
+            page_table_root,
+            VirtualPageNumber::from_raw_virtual_page_number(start_vpn.raw_vpn() + page_index),
+            Some((physical_address + page_index * PAGE_SIZE).ppn()),
+            &flags,
+            physical_memory_allocator,
+        )?;
     }
 
-    let ppn = page_table_level_0_entry.get_ppn();
-    let physical_address = ppn.to_physical_address() | offset;
+    // Implementations may cache invalid entries, so the new mappings are
+    // flushed like changed ones.
+    flush_tlb(start_vpn, page_count);
 
-    Some(physical_address)
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use common_lib::memory::PhysicalPageNumber;
+/// Walks the page table hierarchy to the level 0 entry that maps a virtual page
+/// without allocating any page tables.
+///
+/// # Arguments
+///
+/// * `page_table_root` - A mutable reference to the root page table.
+/// * `root_level` - The level of the root page table.
+/// * `vpn` - The virtual page number to look up.
+///
+/// # Returns
+///
+/// * `Ok(&mut PageTableEntry)` - The valid level 0 leaf entry for the page.
+/// * `Err(MapError::NotMapped)` - If the page is not mapped.
+/// * `Err(MapError::WouldSplitSuperpage)` - If the page is covered by a
+///   superpage leaf entry.
+fn find_level_0_entry(
+    page_table_root: &mut PageTable,
+    root_level: u8,
+    vpn: VirtualPageNumber,
+) -> Result<&mut PageTableEntry, MapError> {
+    let mut page_table = page_table_root;
 
-    /// Set up a basic three-level page table structure for testing translation.
-    fn setup_page_tables() -> (PageTable, *const PageTable, *const PageTable) {
-        let mut root = PageTable::new();
-        let mut level1 = Box::new(PageTable::new());
-        let mut level0 = Box::new(PageTable::new());
+    for level in (1..=root_level).rev() {
+        let entry = *page_table.get_entry(vpn.get_level_index(level as usize));
+        if !entry.is_valid() {
+            return Err(MapError::NotMapped);
+        }
 
-        // Create a mapping for virtual page 0x0012_3456 -> physical page
-        // 0x00AB_CDEF. vpn2 = 0x0123 (291), vpn1 = 0x0056 (86), vpn0 = 0x0056
-        // (86)
+        if entry.is_leaf() {
+            return Err(MapError::WouldSplitSuperpage);
+        }
 
-        // Set up level 0 page table (contains the leaf entry).
-        let mut leaf_entry = PageTableEntry::new();
-        leaf_entry.set_valid(true);
-        leaf_entry.set_readable(true);
-        leaf_entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(
-            0x00AB_CDEF,
-        ));
-        level0.set_entry(0x0056, leaf_entry);
+        page_table = unsafe { &mut *page_table_pointer(entry.get_ppn()) };
+    }
 
-        // Set up level 1 page table (points to level 0).
-        let level0_ptr = Box::into_raw(level0);
-        let level0_ppn = PhysicalPageNumber::from_physical_address(level0_ptr as usize);
+    let page_table_level_0_entry = page_table.get_entry_mut(vpn.get_level_0_index());
+    if !page_table_level_0_entry.is_leaf() {
+        return Err(MapError::NotMapped);
+    }
 
-        let mut l1_entry = PageTableEntry::new();
-        l1_entry.set_valid(true);
-        l1_entry.set_ppn(level0_ppn);
-        level1.set_entry(0x0056, l1_entry);
+    Ok(page_table_level_0_entry)
+}
 
-        // Set up root page table (points to level 1).
-        let level1_ptr = Box::into_raw(level1);
-        let level1_ppn = PhysicalPageNumber::from_physical_address(level1_ptr as usize);
+/// Removes the 4KiB mapping for a virtual page and flushes its stale
+/// translation from the TLB.
+///
+/// The intermediate page tables are left in place even if they become empty.
+/// The physical page that was mapped is not freed.
+///
+/// # Arguments
+///
+/// * `page_table_root` - A mutable reference to the root page table.
+/// * `vpn` - The virtual page number to unmap.
+///
+/// # Returns
+///
+/// * `Ok(PhysicalPageNumber)` - The physical page that was mapped.
+/// * `Err(MapError::NotMapped)` - If the page is not mapped.
+/// * `Err(MapError::WouldSplitSuperpage)` - If the page is covered by a
+///   superpage leaf entry.
+pub fn unmap_vpn(
+    page_table_root: &mut PageTable,
+    vpn: VirtualPageNumber,
+) -> Result<PhysicalPageNumber, MapError> {
+    let unmapped_ppn = unmap_vpn_without_flush(page_table_root, vpn)?;
 
-        let mut root_entry = PageTableEntry::new();
-        root_entry.set_valid(true);
-        root_entry.set_ppn(level1_ppn);
-        root.set_entry(0x0123, root_entry);
+    flush_tlb(vpn, 1);
 
-        (root, level1_ptr, level0_ptr)
-    }
+    Ok(unmapped_ppn)
+}
 
-    /// Clean up allocated page tables to prevent memory leaks.
-    fn cleanup_page_tables(level1_ptr: *const PageTable, level0_ptr: *const PageTable) {
-        unsafe {
-            // Convert back to Box and drop.
-            let _level1 = Box::from_raw(level1_ptr as *mut PageTable);
-            let _level0 = Box::from_raw(level0_ptr as *mut PageTable);
+/// Removes the 4KiB mappings for a range of virtual pages and flushes their
+/// stale translations from the TLB with a single flush.
+///
+/// # Arguments
+///
+/// * `page_table_root` - A mutable reference to the root page table.
+/// * `start_vpn_inclusive` - The first virtual page to unmap.
+/// * `number_of_pages` - The number of pages to unmap.
+///
+/// # Returns
+///
+/// * `Ok(())` - If every page in the range was unmapped.
+/// * `Err(MapError)` - The error from the first page that could not be
+///   unmapped. Pages before the failing page are unmapped and flushed.
+pub fn unmap_range(
+    page_table_root: &mut PageTable,
+    start_vpn_inclusive: VirtualPageNumber,
+    number_of_pages: usize,
+) -> Result<(), MapError> {
+    let mut unmapped_page_count = 0;
+    let mut result = Ok(());
+
+    while unmapped_page_count < number_of_pages {
+        let current_vpn = VirtualPageNumber::from_raw_virtual_page_number(
+            start_vpn_inclusive.raw_vpn() + unmapped_page_count,
+        );
+
+        if let Err(error) = unmap_vpn_without_flush(page_table_root, current_vpn) {
+            result = Err(error);
+            break;
         }
+
+        unmapped_page_count += 1;
     }
 
-    #[test]
-    fn test_translate_valid_address() {
-        let (root, level1_ptr, level0_ptr) = setup_page_tables();
+    flush_tlb(start_vpn_inclusive, unmapped_page_count);
 
-        // Construct a virtual address with: vpn2 = 0x0123, vpn1 = 0x0056, vpn0
-        // = 0x0056, offset = 0x0ABC
-        let virtual_address: usize = (0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0ABC;
+    result
+}
 
-        // Expected physical address: physical page 0x00AB_CDEF with offset
-        // 0x0ABC.
-        let expected_physical_address: usize = (0x00AB_CDEF << 12) | 0x0ABC;
+/// Clears the level 0 leaf entry for a virtual page.
+fn unmap_vpn_without_flush(
+    page_table_root: &mut PageTable,
+    vpn: VirtualPageNumber,
+) -> Result<PhysicalPageNumber, MapError> {
+    let page_table_level_0_entry = find_level_0_entry(page_table_root, root_level(), vpn)?;
+    let unmapped_ppn = page_table_level_0_entry.get_ppn();
 
-        let result = translate_virtual_address(&root, virtual_address);
+    page_table_level_0_entry.clear();
 
-        cleanup_page_tables(level1_ptr, level0_ptr);
-        assert_eq!(result, Some(expected_physical_address));
-    }
+    Ok(unmapped_ppn)
+}
 
-    #[test]
-    fn test_translate_invalid_root_entry() {
-        let root = PageTable::new();
-        // Entry 0x0123 is not set to valid.
+/// Changes the permissions, and optionally the physical page, of an existing
+/// 4KiB mapping and flushes its stale translation from the TLB.
+///
+/// # Arguments
+///
+/// * `page_table_root` - A mutable reference to the root page table.
+/// * `vpn` - The virtual page number to remap.
+/// * `ppn` - The physical page to map instead, or `None` to keep the current
+///   one.
+/// * `flags` - The new permissions for the page.
+///
+/// # Returns
+///
+/// * `Ok(PhysicalPageNumber)` - The physical page that was mapped before the
+///   change.
+/// * `Err(MapError::NotMapped)` - If the page is not mapped.
+/// * `Err(MapError::WouldSplitSuperpage)` - If the page is covered by a
+///   superpage leaf entry.
+pub fn remap_vpn(
+    page_table_root: &mut PageTable,
+    vpn: VirtualPageNumber,
+    ppn: Option<PhysicalPageNumber>,
+    flags: &PageTableEntryFlags,
+) -> Result<PhysicalPageNumber, MapError> {
+    let page_table_level_0_entry = find_level_0_entry(page_table_root, root_level(), vpn)?;
+    let previous_ppn = page_table_level_0_entry.get_ppn();
 
-        let virtual_address = (0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0ABC;
+    // Build the new entry the same way `allocate_vpn` does, so the accessed
+    // and dirty flags start cleared.
+    let mut new_entry = PageTableEntry::new();
+    new_entry.set_valid(true);
+    new_entry.set_flags(flags);
+    new_entry.set_ppn(ppn.unwrap_or(previous_ppn));
 
-        let result = translate_virtual_address(&root, virtual_address);
-        assert_eq!(
-            result, None,
-            "Translation should fail with invalid root entry."
-        );
-    }
+    *page_table_level_0_entry = new_entry;
 
-    #[test]
-    fn test_translate_invalid_level1_entry() {
-        let mut root = PageTable::new();
-        let level1 = Box::new(PageTable::new());
+    flush_tlb(vpn, 1);
 
-        // Set up root to point to level1, but don't set up level1 entry.
-        let level1_ptr = Box::into_raw(level1);
-        let level1_ppn = PhysicalPageNumber::from_physical_address(level1_ptr as usize);
+    Ok(previous_ppn)
+}
+
+/// The result of walking the page tables for a virtual address.
+#[derive(Debug, Clone)]
+pub struct Translation {
+    /// The physical address the virtual address maps to.
+    pub physical_address: PhysicalAddress,
+
+    /// The level of the page table holding the leaf entry, 0 for a 4KiB page,
+    /// 1 for a 2MiB page, 2 for a 1GiB page, 3 for a 512GiB page, and 4 for a
+    /// 256TiB page.
+    pub level: u8,
+
+    /// The permissions, global bit, and memory type of the leaf entry.
+    pub flags: PageTableEntryFlags,
+}
+
+/// A page table entry visited while walking the page tables, as passed to the
+/// callback of `walk_steps`.
+#[derive(Copy, Clone)]
+pub struct WalkStep {
+    /// The level of the page table holding the entry, which starts at the
+    /// root's level: 2 in sv39, 3 in sv48, and 4 in sv57.
+    pub level: u8,
+
+    /// The index of the entry in its page table.
+    pub index: usize,
+
+    /// The page table entry at that index.
+    pub entry: PageTableEntry,
+}
+
+/// Walks the page tables for a virtual address the way the hardware does in
+/// the paging mode set by `set_paging_mode`, stopping at the first leaf
+/// entry, so that superpages translate like 4KiB pages.
+///
+/// # Arguments
+///
+/// * `page_table_root` - A reference to the root page table.
+/// * `virtual_address` - The virtual address to translate.
+///
+/// # Returns
+///
+/// * `Some(Translation)` - The physical address and the leaf entry's level and
+///   flags, if the address is mapped.
+/// * `None` - If an entry on the way is invalid or the leaf entry maps a
+///   misaligned superpage, either of which makes the hardware raise a page
+///   fault.
+///
+/// # Notes
+///
+/// * Only the bits of the address that index the page tables are looked at,
+///   so the caller must reject addresses that are not canonical.
+pub fn walk(page_table_root: &PageTable, virtual_address: VirtualAddress) -> Option<Translation> {
+    walk_steps(page_table_root, virtual_address, &mut |_| {})
+}
+
+/// Walks the page tables for a virtual address like `walk`, calling a
+/// function with every entry visited on the way.
+///
+/// # Arguments
+///
+/// * `page_table_root` - A reference to the root page table.
+/// * `virtual_address` - The virtual address to translate.
+/// * `visit` - Called with each entry visited, starting with the root entry
+///   and ending with the entry the walk stops at.
+///
+/// # Returns
+///
+/// The same as `walk`.
+pub fn walk_steps(
+    page_table_root: &PageTable,
+    virtual_address: VirtualAddress,
+    visit: &mut dyn FnMut(&WalkStep),
+) -> Option<Translation> {
+    walk_steps_from_level(page_table_root, root_level(), virtual_address, visit)
+}
+
+/// Walks the page tables like `walk_steps`, in page tables whose root is at
+/// the given level.
+fn walk_steps_from_level(
+    page_table_root: &PageTable,
+    root_level: u8,
+    virtual_address: VirtualAddress,
+    visit: &mut dyn FnMut(&WalkStep),
+) -> Option<Translation> {
+    let vpn = virtual_address.vpn();
+    let mut page_table = page_table_root;
+
+    for level in (0..=root_level).rev() {
+        let index = vpn.get_level_index(level as usize);
+        let entry = page_table.get_entry(index);
+
+        visit(&WalkStep {
+            level,
+            index,
+            entry: *entry,
+        });
+
+        if !entry.is_valid() {
+            return None;
+        }
+
+        if entry.is_leaf() {
+            // A superpage must start at a physical address aligned to its
+            // size, and the virtual page numbers below its level are part of
+            // the offset into it.
+            let pages_per_entry = 1 << (9 * level as usize);
+            if entry.get_ppn().raw_ppn() & (pages_per_entry - 1) != 0 {
+                return None;
+            }
+
+            let offset = virtual_address.raw_address() & ((pages_per_entry << 12) - 1);
+
+            return Some(Translation {
+                physical_address: PhysicalAddress::from(entry.get_ppn()) + offset,
+                level,
+                flags: entry.get_flags(),
+            });
+        }
+
+        if level == 0 {
+            break;
+        }
+
+        page_table = unsafe { &*page_table_pointer(entry.get_ppn()) };
+    }
+
+    // A level 0 entry that is not a leaf is reserved.
+    None
+}
+
+/// Translates a virtual address to its corresponding physical address using the
+/// provided root page table.
+///
+/// # Arguments
+///
+/// * `page_table_root` - A reference to the root page table.
+/// * `virtual_address` - The virtual address to translate.
+///
+/// # Returns
+///
+/// * `Some(PhysicalAddress)` - The physical address if translation succeeds.
+/// * `None` - If `walk` finds no mapping for the address.
+pub fn translate_virtual_address(
+    page_table_root: &PageTable,
+    virtual_address: VirtualAddress,
+) -> Option<PhysicalAddress> {
+    walk(page_table_root, virtual_address).map(|translation| translation.physical_address)
+}
+
+/// Finds a page that is mapped both writable and executable, which no mapping
+/// should ever be.
+///
+/// # Arguments
+///
+/// * `page_table_root` - A reference to the root page table.
+///
+/// # Returns
+///
+/// * `Some(VirtualPageNumber)` - The first such page, the first page of the
+///   superpage if it is mapped by a superpage leaf entry.
+/// * `None` - If no mapping is both writable and executable.
+pub fn find_writable_executable_page(page_table_root: &PageTable) -> Option<VirtualPageNumber> {
+    find_writable_executable_page_in(page_table_root, root_level(), 0)
+}
+
+/// Searches one page table for `find_writable_executable_page`, descending
+/// into the page tables its entries point to.
+///
+/// # Arguments
+///
+/// * `page_table` - The page table to search.
+/// * `level` - The level of the page table.
+/// * `base_vpn` - The first virtual page number the page table covers.
+fn find_writable_executable_page_in(
+    page_table: &PageTable,
+    level: u8,
+    base_vpn: usize,
+) -> Option<VirtualPageNumber> {
+    let pages_per_entry = 1 << (9 * level as usize);
+
+    for index in 0..512 {
+        let entry = page_table.get_entry(index);
+        if !entry.is_valid() {
+            continue;
+        }
+
+        let entry_vpn = base_vpn + index * pages_per_entry;
+
+        if entry.is_leaf() {
+            if entry.is_writable() && entry.is_executable() {
+                return Some(VirtualPageNumber::from_raw_virtual_page_number(entry_vpn));
+            }
+        } else if level > 0 {
+            let child_page_table = unsafe { &*page_table_pointer(entry.get_ppn()) };
+
+            if let Some(vpn) =
+                find_writable_executable_page_in(child_page_table, level - 1, entry_vpn)
+            {
+                return Some(vpn);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::mock_frame_allocator::MockFrameAllocator;
+    use crate::memory::physical_memory_allocator::PhysicalBumpAllocator;
+    use common_lib::memory::PhysicalPageNumber;
+
+    /// Set up a basic three-level page table structure for testing translation.
+    fn setup_page_tables() -> (PageTable, *const PageTable, *const PageTable) {
+        let mut root = PageTable::new();
+        let mut level1 = Box::new(PageTable::new());
+        let mut level0 = Box::new(PageTable::new());
+
+        // Create a mapping for virtual page 0x0012_3456 -> physical page
+        // 0x00AB_CDEF. vpn2 = 0x0123 (291), vpn1 = 0x0056 (86), vpn0 = 0x0056
+        // (86)
+
+        // Set up level 0 page table (contains the leaf entry).
+        let mut leaf_entry = PageTableEntry::new();
+        leaf_entry.set_valid(true);
+        leaf_entry.set_readable(true);
+        leaf_entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(
+            0x00AB_CDEF,
+        ));
+        level0.set_entry(0x0056, leaf_entry);
+
+        // Set up level 1 page table (points to level 0).
+        let level0_ptr = Box::into_raw(level0);
+        let level0_ppn = PhysicalPageNumber::from_physical_address(level0_ptr as usize);
+
+        let mut l1_entry = PageTableEntry::new();
+        l1_entry.set_valid(true);
+        l1_entry.set_ppn(level0_ppn);
+        level1.set_entry(0x0056, l1_entry);
+
+        // Set up root page table (points to level 1).
+        let level1_ptr = Box::into_raw(level1);
+        let level1_ppn = PhysicalPageNumber::from_physical_address(level1_ptr as usize);
+
+        let mut root_entry = PageTableEntry::new();
+        root_entry.set_valid(true);
+        root_entry.set_ppn(level1_ppn);
+        root.set_entry(0x0123, root_entry);
+
+        (root, level1_ptr, level0_ptr)
+    }
+
+    /// Clean up allocated page tables to prevent memory leaks.
+    fn cleanup_page_tables(level1_ptr: *const PageTable, level0_ptr: *const PageTable) {
+        unsafe {
+            // Convert back to Box and drop.
+            let _level1 = Box::from_raw(level1_ptr as *mut PageTable);
+            let _level0 = Box::from_raw(level0_ptr as *mut PageTable);
+        }
+    }
+
+    /// Returns readable and writable flags.
+    fn read_write_flags() -> PageTableEntryFlags {
+        let mut flags = PageTableEntryFlags::default();
+        flags.set_readable(true);
+        flags.set_writable(true);
+        flags
+    }
+
+    /// Returns the page table a non-leaf entry points to. The table must have
+    /// been allocated from a `MockFrameAllocator` that is still alive.
+    fn child_table(entry: &PageTableEntry) -> &PageTable {
+        unsafe { &*page_table_pointer(entry.get_ppn()) }
+    }
+
+    /// Returns the number of valid entries in a page table.
+    fn valid_entry_count(page_table: &PageTable) -> usize {
+        page_table
+            .get_entries()
+            .iter()
+            .filter(|entry| entry.is_valid())
+            .count()
+    }
+
+    #[test]
+    fn test_set_ppn_keeps_flags() {
+        let mut entry = PageTableEntry::new();
+        entry.set_valid(true);
+        entry.set_readable(true);
+        entry.set_writable(true);
+        entry.set_user(true);
+        entry.set_global(true);
+        entry.set_accessed(true);
+        entry.set_dirty(true);
+        entry.set_software_bits(0b10);
+
+        entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(
+            0x0FFF_FFFF_FFFF,
+        ));
+        entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(
+            0x0012_3456,
+        ));
+
+        assert_eq!(entry.get_ppn().raw_ppn(), 0x0012_3456);
+        assert!(entry.is_valid() && entry.is_readable() && entry.is_writable());
+        assert!(entry.is_user() && entry.is_global());
+        assert!(entry.is_accessed() && entry.is_dirty());
+        assert_eq!(entry.get_software_bits(), 0b10);
+    }
+
+    #[test]
+    fn test_software_bits_keep_other_fields() {
+        let mut entry = PageTableEntry::new();
+        entry.set_valid(true);
+        entry.set_dirty(true);
+        entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(0x0ABC));
+
+        entry.set_software_bits(0b111);
+        assert_eq!(entry.get_software_bits(), 0b11);
+
+        entry.set_software_bits(0);
+        assert_eq!(entry.get_software_bits(), 0);
+        assert!(entry.is_valid() && entry.is_dirty());
+        assert_eq!(entry.get_ppn().raw_ppn(), 0x0ABC);
+    }
+
+    #[test]
+    fn test_memory_type_keeps_other_fields() {
+        let mut entry = PageTableEntry::new();
+        entry.set_valid(true);
+        entry.set_readable(true);
+        entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(
+            0x0FFF_FFFF_FFFF,
+        ));
+
+        entry.set_memory_type(MemoryType::Io);
+        assert_eq!(entry.get_memory_type(), MemoryType::Io);
+        assert_eq!(entry.get_ppn().raw_ppn(), 0x0FFF_FFFF_FFFF);
+
+        entry.set_memory_type(MemoryType::NonCacheable);
+        assert_eq!(entry.get_memory_type(), MemoryType::NonCacheable);
+        assert!(entry.is_valid() && entry.is_readable());
+
+        entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(0x0ABC));
+        assert_eq!(entry.get_memory_type(), MemoryType::NonCacheable);
+    }
+
+    #[test]
+    fn test_set_flags_sets_memory_type_only_with_svpbmt() {
+        let flags = PageTableEntryFlags {
+            readable: true,
+            memory_type: MemoryType::Io,
+            ..Default::default()
+        };
+
+        let mut entry = PageTableEntry::new();
+        entry.set_flags(&flags);
+        assert_eq!(entry.get_memory_type(), MemoryType::Pma);
+
+        set_svpbmt_enabled(true);
+        entry.set_flags(&flags);
+        set_svpbmt_enabled(false);
+
+        assert_eq!(entry.get_memory_type(), MemoryType::Io);
+        assert!(entry.is_readable());
+    }
+
+    #[test]
+    fn test_translate_valid_address() {
+        let (root, level1_ptr, level0_ptr) = setup_page_tables();
+
+        // Construct a virtual address with: vpn2 = 0x0123, vpn1 = 0x0056, vpn0
+        // = 0x0056, offset = 0x0ABC
+        let virtual_address =
+            VirtualAddress::new((0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0ABC);
+
+        // Expected physical address: physical page 0x00AB_CDEF with offset
+        // 0x0ABC.
+        let expected_physical_address = PhysicalAddress::new((0x00AB_CDEF << 12) | 0x0ABC);
+
+        let result = translate_virtual_address(&root, virtual_address);
+
+        cleanup_page_tables(level1_ptr, level0_ptr);
+        assert_eq!(result, Some(expected_physical_address));
+    }
+
+    #[test]
+    fn test_translate_invalid_root_entry() {
+        let root = PageTable::new();
+        // Entry 0x0123 is not set to valid.
+
+        let virtual_address =
+            VirtualAddress::new((0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0ABC);
+
+        let result = translate_virtual_address(&root, virtual_address);
+        assert_eq!(
+            result, None,
+            "Translation should fail with invalid root entry."
+        );
+    }
+
+    #[test]
+    fn test_translate_invalid_level1_entry() {
+        let mut root = PageTable::new();
+        let level1 = Box::new(PageTable::new());
+
+        // Set up root to point to level1, but don't set up level1 entry.
+        let level1_ptr = Box::into_raw(level1);
+        let level1_ppn = PhysicalPageNumber::from_physical_address(level1_ptr as usize);
 
         let mut root_entry = PageTableEntry::new();
         root_entry.set_valid(true);
         root_entry.set_ppn(level1_ppn);
         root.set_entry(0x0123, root_entry);
 
-        let virtual_address = (0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0ABC;
+        let virtual_address =
+            VirtualAddress::new((0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0ABC);
 
         let result = translate_virtual_address(&root, virtual_address);
 
@@ -740,7 +1515,8 @@ mod tests {
         root_entry.set_ppn(level1_ppn);
         root.set_entry(0x0123, root_entry);
 
-        let virtual_address = (0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0ABC;
+        let virtual_address =
+            VirtualAddress::new((0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0ABC);
 
         let result = translate_virtual_address(&root, virtual_address);
 
@@ -760,13 +1536,15 @@ mod tests {
         let (root, level1_ptr, level0_ptr) = setup_page_tables();
 
         // Test with offset 0x0000.
-        let virtual_address_1: usize = (0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0000;
-        let expected_physical_address_1: usize = (0x00AB_CDEF << 12) | 0x0000;
+        let virtual_address_1 =
+            VirtualAddress::new((0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0000);
+        let expected_physical_address_1 = PhysicalAddress::new((0x00AB_CDEF << 12) | 0x0000);
         let result_1 = translate_virtual_address(&root, virtual_address_1);
 
         // Test with offset 0x0FFF (maximum offset).
-        let virtual_address_2 = (0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0FFF;
-        let expected_physical_address_2 = (0x00AB_CDEF << 12) | 0x0FFF;
+        let virtual_address_2 =
+            VirtualAddress::new((0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0FFF);
+        let expected_physical_address_2 = PhysicalAddress::new((0x00AB_CDEF << 12) | 0x0FFF);
         let result_2 = translate_virtual_address(&root, virtual_address_2);
 
         cleanup_page_tables(level1_ptr, level0_ptr);
@@ -782,4 +1560,689 @@ mod tests {
             "Translation with maximum offset failed."
         );
     }
+
+    #[test]
+    fn test_unmap_vpn_clears_leaf() {
+        let (mut root, level1_ptr, level0_ptr) = setup_page_tables();
+
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(
+            (0x0123 << 18) | (0x0056 << 9) | 0x0056,
+        );
+
+        let result = unmap_vpn(&mut root, vpn);
+        let translation = translate_virtual_address(&root, VirtualAddress::from(vpn));
+        let second_result = unmap_vpn(&mut root, vpn);
+
+        cleanup_page_tables(level1_ptr, level0_ptr);
+
+        assert_eq!(
+            result,
+            Ok(PhysicalPageNumber::from_raw_physical_page_number(
+                0x00AB_CDEF
+            ))
+        );
+        assert_eq!(translation, None);
+        assert_eq!(second_result, Err(MapError::NotMapped));
+    }
+
+    #[test]
+    fn test_unmap_vpn_not_mapped() {
+        let mut root = PageTable::new();
+
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(0x1234);
+
+        assert_eq!(unmap_vpn(&mut root, vpn), Err(MapError::NotMapped));
+    }
+
+    #[test]
+    fn test_unmap_vpn_would_split_superpage() {
+        let mut root = PageTable::new();
+
+        let mut flags = PageTableEntryFlags::default();
+        flags.set_readable(true);
+
+        let gigapage_vpn = VirtualPageNumber::from_raw_virtual_page_number(384 << 18);
+        let ppn = PhysicalPageNumber::from_raw_physical_page_number(2 << 18);
+        allocate_root_vpn(&mut root, gigapage_vpn, ppn, &flags).unwrap();
+
+        // Any 4KiB page inside the gigapage is covered by the root leaf.
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number((384 << 18) + 5);
+
+        assert_eq!(
+            unmap_vpn(&mut root, vpn),
+            Err(MapError::WouldSplitSuperpage)
+        );
+        assert!(root.get_entry(384).is_leaf());
+    }
+
+    #[test]
+    fn test_remap_vpn_changes_flags_and_ppn() {
+        let (mut root, level1_ptr, level0_ptr) = setup_page_tables();
+
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(
+            (0x0123 << 18) | (0x0056 << 9) | 0x0056,
+        );
+        let new_ppn = PhysicalPageNumber::from_raw_physical_page_number(0x0012_3456);
+
+        let mut flags = PageTableEntryFlags::default();
+        flags.set_readable(true);
+        flags.set_writable(true);
+
+        let result = remap_vpn(&mut root, vpn, Some(new_ppn), &flags);
+        let translation = translate_virtual_address(&root, VirtualAddress::from(vpn));
+        let entry = *unsafe { &*level0_ptr }.get_entry(0x0056);
+
+        cleanup_page_tables(level1_ptr, level0_ptr);
+
+        assert_eq!(
+            result,
+            Ok(PhysicalPageNumber::from_raw_physical_page_number(
+                0x00AB_CDEF
+            ))
+        );
+        assert_eq!(translation, Some(PhysicalAddress::from(new_ppn)));
+        assert!(entry.is_readable());
+        assert!(entry.is_writable());
+        assert!(!entry.is_executable());
+    }
+
+    #[test]
+    fn test_remap_vpn_not_mapped() {
+        let mut root = PageTable::new();
+
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(0x1234);
+        let flags = PageTableEntryFlags::default();
+
+        assert_eq!(
+            remap_vpn(&mut root, vpn, None, &flags),
+            Err(MapError::NotMapped)
+        );
+    }
+
+    #[test]
+    fn test_allocate_root_vpn_creates_gigapage() {
+        let mut root = PageTable::new();
+
+        let mut flags = PageTableEntryFlags::default();
+        flags.set_readable(true);
+        flags.set_writable(true);
+
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(384 << 18);
+        let ppn = PhysicalPageNumber::from_raw_physical_page_number(2 << 18);
+
+        let result = allocate_root_vpn(&mut root, vpn, ppn, &flags);
+        assert_eq!(result, Ok(()));
+
+        let entry = root.get_entry(384);
+        assert!(entry.is_valid());
+        assert!(entry.is_leaf());
+        assert!(entry.is_readable());
+        assert!(entry.is_writable());
+        assert!(!entry.is_executable());
+        assert_eq!(entry.get_ppn(), ppn);
+    }
+
+    #[test]
+    fn test_allocate_root_vpn_misaligned() {
+        let mut root = PageTable::new();
+
+        let mut flags = PageTableEntryFlags::default();
+        flags.set_readable(true);
+
+        // A PPN one 4KiB page past a 1GiB boundary cannot back a gigapage.
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(384 << 18);
+        let ppn = PhysicalPageNumber::from_raw_physical_page_number((2 << 18) + 1);
+
+        let result = allocate_root_vpn(&mut root, vpn, ppn, &flags);
+        assert_eq!(result, Err(MapError::Misaligned));
+        assert!(!root.get_entry(384).is_valid());
+    }
+
+    #[test]
+    fn test_allocate_root_vpn_already_mapped() {
+        let mut root = PageTable::new();
+
+        let mut flags = PageTableEntryFlags::default();
+        flags.set_readable(true);
+
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(384 << 18);
+        let first_ppn = PhysicalPageNumber::from_raw_physical_page_number(1 << 18);
+        let second_ppn = PhysicalPageNumber::from_raw_physical_page_number(2 << 18);
+
+        assert_eq!(allocate_root_vpn(&mut root, vpn, first_ppn, &flags), Ok(()));
+
+        // The second mapping must fail and leave the first one untouched.
+        assert_eq!(
+            allocate_root_vpn(&mut root, vpn, second_ppn, &flags),
+            Err(MapError::AlreadyMapped)
+        );
+        assert_eq!(root.get_entry(384).get_ppn(), first_ppn);
+    }
+
+    #[test]
+    fn test_allocate_root_vpn_would_clobber_table() {
+        let (mut root, level1_ptr, level0_ptr) = setup_page_tables();
+
+        let mut flags = PageTableEntryFlags::default();
+        flags.set_readable(true);
+
+        // Entry 0x0123 points to a level 1 page table.
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(0x0123 << 18);
+        let ppn = PhysicalPageNumber::from_raw_physical_page_number(1 << 18);
+
+        let result = allocate_root_vpn(&mut root, vpn, ppn, &flags);
+        let entry_is_leaf = root.get_entry(0x0123).is_leaf();
+
+        cleanup_page_tables(level1_ptr, level0_ptr);
+
+        assert_eq!(result, Err(MapError::WouldClobberTable));
+        assert!(!entry_is_leaf);
+    }
+
+    #[test]
+    fn test_map_mmio_maps_device_pages() {
+        let (mut root, level1_ptr, level0_ptr) = setup_page_tables();
+
+        // The tables for vpn2 = 0x0123, vpn1 = 0x0056 exist, so an allocator
+        // without memory is enough.
+        let virtual_address = VirtualAddress::new((0x0123 << 30) | (0x0056 << 21) | (0x0100 << 12));
+        let result = map_mmio(
+            &mut root,
+            PhysicalAddress::new(0x1000_0000),
+            virtual_address,
+            4096 + 1,
+            &mut PhysicalBumpAllocator::new(),
+        );
+
+        let first_entry = *find_level_0_entry(&mut root, 2, virtual_address.vpn()).unwrap();
+        let second_translation = translate_virtual_address(&root, virtual_address + 4096 + 0x10);
+        let third_translation = translate_virtual_address(&root, virtual_address + 2 * 4096);
+        let misaligned_result = map_mmio(
+            &mut root,
+            PhysicalAddress::new(0x1000_0010),
+            virtual_address,
+            4,
+            &mut PhysicalBumpAllocator::new(),
+        );
+
+        cleanup_page_tables(level1_ptr, level0_ptr);
+
+        assert_eq!(result, Ok(()));
+        assert!(first_entry.is_readable() && first_entry.is_writable());
+        assert!(first_entry.is_global() && !first_entry.is_executable() && !first_entry.is_user());
+        assert_eq!(first_entry.get_ppn().to_physical_address(), 0x1000_0000);
+        assert_eq!(second_translation, Some(PhysicalAddress::new(0x1000_1010)));
+        assert_eq!(third_translation, None);
+        assert_eq!(misaligned_result, Err(MapError::Misaligned));
+    }
+
+    #[test]
+    fn test_find_writable_executable_page() {
+        let (root, level1_ptr, level0_ptr) = setup_page_tables();
+
+        let before = find_writable_executable_page(&root);
+
+        let level0 = unsafe { &mut *(level0_ptr as *mut PageTable) };
+        let mut executable_entry = *level0.get_entry(0x0056);
+        executable_entry.set_executable(true);
+        level0.set_entry(0x0057, executable_entry);
+
+        let executable_only = find_writable_executable_page(&root);
+
+        let mut writable_executable_entry = executable_entry;
+        writable_executable_entry.set_writable(true);
+        level0.set_entry(0x0058, writable_executable_entry);
+
+        let found = find_writable_executable_page(&root);
+
+        let mut gigapage_flags = PageTableEntryFlags::default();
+        gigapage_flags.set_readable(true);
+        gigapage_flags.set_writable(true);
+        gigapage_flags.set_executable(true);
+
+        let mut gigapage_root = PageTable::new();
+        allocate_root_vpn(
+            &mut gigapage_root,
+            VirtualPageNumber::from_raw_virtual_page_number(3 << 18),
+            PhysicalPageNumber::from_raw_physical_page_number(1 << 18),
+            &gigapage_flags,
+        )
+        .unwrap();
+
+        cleanup_page_tables(level1_ptr, level0_ptr);
+
+        assert_eq!(before, None);
+        assert_eq!(executable_only, None);
+        assert_eq!(
+            found,
+            Some(VirtualPageNumber::from_raw_virtual_page_number(
+                (0x0123 << 18) | (0x0056 << 9) | 0x0058
+            ))
+        );
+        assert_eq!(
+            find_writable_executable_page(&gigapage_root),
+            Some(VirtualPageNumber::from_raw_virtual_page_number(3 << 18))
+        );
+    }
+
+    #[test]
+    fn test_allocate_vpn_creates_tables_and_backing_page() {
+        let mut root = PageTable::new();
+        let mut allocator = MockFrameAllocator::new(8);
+
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number((5 << 18) | (7 << 9) | 9);
+
+        let ppn = allocate_vpn(&mut root, vpn, None, &read_write_flags(), &mut allocator).unwrap();
+
+        // The level 1 table, the level 0 table, and the backing page.
+        assert_eq!(allocator.allocation_count(), 3);
+        assert!(allocator.is_allocated(PhysicalAddress::from(ppn)));
+        assert_eq!(
+            translate_virtual_address(&root, VirtualAddress::from(vpn) + 0x123),
+            Some(PhysicalAddress::from(ppn) + 0x123)
+        );
+
+        // The new tables were cleared over the poison the allocator filled
+        // them with, so only the entries on the path to the page are valid.
+        let level1 = child_table(root.get_entry(5));
+        let level0 = child_table(level1.get_entry(7));
+
+        assert_eq!(valid_entry_count(&root), 1);
+        assert_eq!(valid_entry_count(level1), 1);
+        assert_eq!(valid_entry_count(level0), 1);
+
+        let entry = level0.get_entry(9);
+        assert!(entry.is_readable() && entry.is_writable() && !entry.is_executable());
+    }
+
+    #[test]
+    fn test_allocate_vpn_reuses_existing_mapping() {
+        let mut root = PageTable::new();
+        let mut allocator = MockFrameAllocator::new(8);
+        let flags = read_write_flags();
+
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(0x1234);
+
+        let ppn = allocate_vpn(&mut root, vpn, None, &flags, &mut allocator).unwrap();
+        let second_ppn = allocate_vpn(&mut root, vpn, None, &flags, &mut allocator);
+        let same_ppn = allocate_vpn(&mut root, vpn, Some(ppn), &flags, &mut allocator);
+
+        let other_ppn = PhysicalPageNumber::from_raw_physical_page_number(ppn.raw_ppn() + 1);
+        let other_result = allocate_vpn(&mut root, vpn, Some(other_ppn), &flags, &mut allocator);
+
+        assert_eq!(second_ppn, Ok(ppn));
+        assert_eq!(same_ppn, Ok(ppn));
+        assert_eq!(other_result, Err(MapError::AlreadyMapped));
+        assert_eq!(allocator.allocation_count(), 3);
+        assert_eq!(
+            translate_virtual_address(&root, VirtualAddress::from(vpn)),
+            Some(PhysicalAddress::from(ppn))
+        );
+    }
+
+    #[test]
+    fn test_allocate_vpn_out_of_memory_at_every_level() {
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number((5 << 18) | (7 << 9) | 9);
+        let flags = read_write_flags();
+
+        for successful_allocations in 0..3 {
+            let mut root = PageTable::new();
+            let mut allocator = MockFrameAllocator::new(8);
+
+            allocator.fail_after(successful_allocations);
+
+            assert_eq!(
+                allocate_vpn(&mut root, vpn, None, &flags, &mut allocator),
+                Err(MapError::OutOfMemory),
+                "Allocation {successful_allocations} did not fail."
+            );
+            assert_eq!(
+                translate_virtual_address(&root, VirtualAddress::from(vpn)),
+                None
+            );
+
+            // A retry keeps the tables the failed call created and only
+            // allocates what is still missing.
+            allocator.stop_failing();
+
+            let ppn = allocate_vpn(&mut root, vpn, None, &flags, &mut allocator).unwrap();
+
+            assert_eq!(allocator.allocation_count(), 3);
+            assert_eq!(allocator.allocated_frame_count(), 3);
+            assert_eq!(
+                translate_virtual_address(&root, VirtualAddress::from(vpn)),
+                Some(PhysicalAddress::from(ppn))
+            );
+        }
+    }
+
+    #[test]
+    fn test_map_range_crosses_level_0_table_boundary() {
+        let mut root = PageTable::new();
+        let mut allocator = MockFrameAllocator::new(8);
+
+        // Pages 510 through 513 of the level 1 entry 7 span two level 0
+        // tables. `map_range` maps one page more than the count it is given.
+        let start_vpn = VirtualPageNumber::from_raw_virtual_page_number((3 << 18) | (7 << 9) | 510);
+        let start_ppn = PhysicalPageNumber::from_raw_physical_page_number(0x8_0000);
+
+        let result = map_range(
+            &mut root,
+            start_ppn,
+            start_vpn,
+            3,
+            &read_write_flags(),
+            &mut allocator,
+        );
+
+        assert_eq!(result, Ok(()));
+
+        // One level 1 table and two level 0 tables, with no backing pages
+        // since every physical page was given.
+        assert_eq!(allocator.allocation_count(), 3);
+
+        for page in 0..4 {
+            assert_eq!(
+                translate_virtual_address(&root, VirtualAddress::from(start_vpn) + page * 4096),
+                Some(PhysicalAddress::from(start_ppn) + page * 4096)
+            );
+        }
+
+        assert_eq!(
+            translate_virtual_address(&root, VirtualAddress::from(start_vpn) + 4 * 4096),
+            None
+        );
+
+        let level1 = child_table(root.get_entry(3));
+        assert_eq!(valid_entry_count(level1), 2);
+        assert_eq!(valid_entry_count(child_table(level1.get_entry(7))), 2);
+        assert_eq!(valid_entry_count(child_table(level1.get_entry(8))), 2);
+    }
+
+    #[test]
+    fn test_map_range_out_of_memory_keeps_earlier_pages() {
+        let mut root = PageTable::new();
+        let mut allocator = MockFrameAllocator::new(8);
+
+        let start_vpn = VirtualPageNumber::from_raw_virtual_page_number((3 << 18) | (7 << 9) | 510);
+        let start_ppn = PhysicalPageNumber::from_raw_physical_page_number(0x8_0000);
+
+        // Enough for the first level 0 table but not the second.
+        allocator.fail_after(2);
+
+        let result = map_range(
+            &mut root,
+            start_ppn,
+            start_vpn,
+            3,
+            &read_write_flags(),
+            &mut allocator,
+        );
+
+        assert_eq!(result, Err(MapError::OutOfMemory));
+
+        for page in 0..2 {
+            assert_eq!(
+                translate_virtual_address(&root, VirtualAddress::from(start_vpn) + page * 4096),
+                Some(PhysicalAddress::from(start_ppn) + page * 4096)
+            );
+        }
+
+        assert_eq!(
+            translate_virtual_address(&root, VirtualAddress::from(start_vpn) + 2 * 4096),
+            None
+        );
+    }
+
+    #[test]
+    fn test_identity_map_range_then_unmap_range() {
+        let mut root = PageTable::new();
+        let mut allocator = MockFrameAllocator::new(8);
+
+        let start_ppn = PhysicalPageNumber::from_raw_physical_page_number(0x8_0000);
+        let end_ppn = PhysicalPageNumber::from_raw_physical_page_number(0x8_000F);
+
+        identity_map_range(
+            &mut root,
+            start_ppn,
+            end_ppn,
+            &read_write_flags(),
+            &mut allocator,
+        )
+        .unwrap();
+
+        let allocation_count = allocator.allocation_count();
+        let start_vpn = VirtualPageNumber::from_raw_virtual_page_number(start_ppn.raw_ppn());
+
+        assert_eq!(unmap_range(&mut root, start_vpn, 8), Ok(()));
+
+        // The tables stay in place, so nothing was freed or allocated.
+        assert_eq!(allocator.allocation_count(), allocation_count);
+        assert_eq!(allocator.allocated_frame_count(), allocation_count);
+
+        for page in 0..16 {
+            let address = PhysicalAddress::from(start_ppn) + page * 4096;
+            let translation =
+                translate_virtual_address(&root, VirtualAddress::new(address.raw_address()));
+
+            assert_eq!(translation, (page >= 8).then_some(address));
+        }
+
+        assert_eq!(
+            unmap_range(&mut root, start_vpn, 1),
+            Err(MapError::NotMapped)
+        );
+    }
+
+    #[test]
+    fn test_walk_gigapage_keeps_lower_indices_in_offset() {
+        let mut root = PageTable::new();
+
+        let mut flags = read_write_flags();
+        flags.set_global(true);
+
+        allocate_root_vpn(
+            &mut root,
+            VirtualPageNumber::from_raw_virtual_page_number(3 << 18),
+            PhysicalPageNumber::from_raw_physical_page_number(2 << 18),
+            &flags,
+        )
+        .unwrap();
+
+        let offset = (0x0056 << 21) | (0x0034 << 12) | 0x0ABC;
+        let translation = walk(&root, VirtualAddress::new((3 << 30) + offset)).unwrap();
+
+        assert_eq!(
+            translation.physical_address,
+            PhysicalAddress::new((2 << 30) + offset)
+        );
+        assert_eq!(translation.level, 2);
+        assert!(translation.flags.readable && translation.flags.writable);
+        assert!(translation.flags.global && !translation.flags.executable);
+        assert!(!translation.flags.user);
+    }
+
+    #[test]
+    fn test_walk_megapage_and_misaligned_superpage() {
+        let mut root = PageTable::new();
+        let level1 = Box::into_raw(Box::new(PageTable::new()));
+
+        let mut root_entry = PageTableEntry::new();
+        root_entry.set_valid(true);
+        root_entry.set_ppn(PhysicalPageNumber::from_physical_address(level1 as usize));
+        root.set_entry(1, root_entry);
+
+        // Entry 5 maps a 2MiB page, and entry 6 one whose physical page is
+        // not 2MiB aligned.
+        let mut leaf_entry = PageTableEntry::new();
+        leaf_entry.set_valid(true);
+        leaf_entry.set_flags(&read_write_flags());
+        leaf_entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(0x8_0000));
+        unsafe { (*level1).set_entry(5, leaf_entry) };
+
+        leaf_entry.set_ppn(PhysicalPageNumber::from_raw_physical_page_number(0x8_0001));
+        unsafe { (*level1).set_entry(6, leaf_entry) };
+
+        let base_address = (1 << 30) | (5 << 21);
+        let translation = walk(&root, VirtualAddress::new(base_address + 0x12_3456));
+        let misaligned_translation = walk(&root, VirtualAddress::new(base_address + (1 << 21)));
+        let translated_address =
+            translate_virtual_address(&root, VirtualAddress::new(base_address + 0x1000));
+
+        unsafe { drop(Box::from_raw(level1)) };
+
+        let translation = translation.unwrap();
+        assert_eq!(
+            translation.physical_address,
+            PhysicalAddress::new(0x8000_0000 + 0x12_3456)
+        );
+        assert_eq!(translation.level, 1);
+        assert!(translation.flags.readable && translation.flags.writable);
+        assert!(misaligned_translation.is_none());
+        assert_eq!(translated_address, Some(PhysicalAddress::new(0x8000_1000)));
+    }
+
+    #[test]
+    fn test_walk_level_0_page_reports_level_and_flags() {
+        let (root, level1_ptr, level0_ptr) = setup_page_tables();
+
+        let translation = walk(
+            &root,
+            VirtualAddress::new((0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12) | 0x0ABC),
+        );
+
+        cleanup_page_tables(level1_ptr, level0_ptr);
+
+        let translation = translation.unwrap();
+        assert_eq!(
+            translation.physical_address,
+            PhysicalAddress::new((0x00AB_CDEF << 12) | 0x0ABC)
+        );
+        assert_eq!(translation.level, 0);
+        assert!(translation.flags.readable && !translation.flags.writable);
+    }
+
+    #[test]
+    fn test_walk_steps_visits_every_level() {
+        let (root, level1_ptr, level0_ptr) = setup_page_tables();
+
+        let mut steps = Vec::new();
+        let translation = walk_steps(
+            &root,
+            VirtualAddress::new((0x0123 << 30) | (0x0056 << 21) | (0x0056 << 12)),
+            &mut |step| steps.push((step.level, step.index, step.entry.is_leaf())),
+        );
+        let invalid_translation =
+            walk_steps(&root, VirtualAddress::new(0x0124 << 30), &mut |step| {
+                steps.push((step.level, step.index, step.entry.is_valid()))
+            });
+
+        cleanup_page_tables(level1_ptr, level0_ptr);
+
+        assert!(translation.is_some());
+        assert!(invalid_translation.is_none());
+        assert_eq!(
+            steps,
+            [
+                (2, 0x0123, false),
+                (1, 0x0056, false),
+                (0, 0x0056, true),
+                (2, 0x0124, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allocate_vpn_from_level_builds_every_level_of_sv48_and_sv57() {
+        for root_level in [3u8, 4] {
+            let mut root = PageTable::new();
+            let mut allocator = MockFrameAllocator::new(8);
+
+            let vpn = VirtualPageNumber::from_raw_virtual_page_number(
+                (2 << (9 * root_level as usize)) | (5 << 18) | (7 << 9) | 9,
+            );
+
+            let ppn = allocate_vpn_from_level(
+                &mut root,
+                root_level,
+                vpn,
+                None,
+                &read_write_flags(),
+                &mut allocator,
+            )
+            .unwrap();
+
+            // A table below every level but the lowest, and the backing page.
+            assert_eq!(allocator.allocation_count(), root_level as usize + 1);
+            assert!(root.get_entry(2).is_valid());
+
+            let mut levels = Vec::new();
+            let translation = walk_steps_from_level(
+                &root,
+                root_level,
+                VirtualAddress::from(vpn) + 0x123,
+                &mut |step| levels.push(step.level),
+            );
+
+            assert_eq!(
+                translation.unwrap().physical_address,
+                PhysicalAddress::from(ppn) + 0x123
+            );
+            assert_eq!(levels, (0..=root_level).rev().collect::<Vec<_>>());
+            assert_eq!(
+                find_level_0_entry(&mut root, root_level, vpn)
+                    .unwrap()
+                    .get_ppn(),
+                ppn
+            );
+        }
+    }
+
+    #[test]
+    fn test_allocate_root_vpn_at_level_maps_sv48_root_leaf() {
+        let mut root = PageTable::new();
+        let flags = read_write_flags();
+        let vpn = VirtualPageNumber::from_raw_virtual_page_number(4 << 27);
+
+        // A PPN aligned to 1GiB but not to 512GiB cannot back a root leaf.
+        assert_eq!(
+            allocate_root_vpn_at_level(
+                &mut root,
+                3,
+                vpn,
+                PhysicalPageNumber::from_raw_physical_page_number(1 << 18),
+                &flags,
+            ),
+            Err(MapError::Misaligned)
+        );
+
+        allocate_root_vpn_at_level(
+            &mut root,
+            3,
+            vpn,
+            PhysicalPageNumber::from_raw_physical_page_number(1 << 27),
+            &flags,
+        )
+        .unwrap();
+
+        let offset = (0x0012 << 30) | (0x0056 << 21) | 0x0ABC;
+        let translation = walk_steps_from_level(
+            &root,
+            3,
+            VirtualAddress::new((4 << 39) + offset),
+            &mut |_| {},
+        )
+        .unwrap();
+
+        assert!(root.get_entry(4).is_leaf());
+        assert_eq!(translation.level, 3);
+        assert_eq!(
+            translation.physical_address,
+            PhysicalAddress::new((1 << 39) + offset)
+        );
+        assert_eq!(
+            find_level_0_entry(&mut root, 3, vpn).err(),
+            Some(MapError::WouldSplitSuperpage)
+        );
+    }
 }
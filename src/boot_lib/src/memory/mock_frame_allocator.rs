@@ -0,0 +1,184 @@
+//! A physical memory allocator for host tests, backed by host memory.
+//!
+//! The physical memory offset is zero in host tests, so the host address of a
+//! frame doubles as its physical address, and page tables allocated from the
+//! mock can be walked by the real mapping code.
+
+use super::physical_memory_allocator::PhysicalMemoryAllocator;
+use common_lib::memory::{MemoryRegion, PhysicalAddress};
+
+/// The size of a frame in bytes.
+const PAGE_SIZE: usize = 4096;
+
+/// The byte every frame is filled with when the allocator is created, so that
+/// code that forgets to clear a new page table finds garbage entries in it,
+/// like it would in memory fresh from the firmware.
+pub(crate) const POISON_BYTE: u8 = 0xA5;
+
+/// A single page aligned frame of host memory.
+#[repr(C, align(4096))]
+struct Frame([u8; PAGE_SIZE]);
+
+/// Hands out the frames of a host allocated arena, and can be told to fail
+/// after a number of allocations to test the out of memory paths.
+pub(crate) struct MockFrameAllocator {
+    frames: Box<[Frame]>,
+
+    /// True for every frame that is allocated, indexed like `frames`.
+    is_allocated: Vec<bool>,
+
+    /// The number of allocations that succeed before every later one fails,
+    /// or `None` to fail only when the arena is exhausted.
+    remaining_allocations: Option<usize>,
+
+    /// The number of successful allocations, of single or contiguous frames.
+    allocation_count: usize,
+}
+
+impl MockFrameAllocator {
+    /// Creates an allocator over a new arena.
+    ///
+    /// # Parameters
+    ///
+    /// * `frame_count` - The number of frames in the arena.
+    pub(crate) fn new(frame_count: usize) -> Self {
+        Self {
+            frames: (0..frame_count)
+                .map(|_| Frame([POISON_BYTE; PAGE_SIZE]))
+                .collect(),
+            is_allocated: vec![false; frame_count],
+            remaining_allocations: None,
+            allocation_count: 0,
+        }
+    }
+
+    /// Makes every allocation after the next `allocation_count` ones fail, as
+    /// if the arena were exhausted.
+    ///
+    /// # Parameters
+    ///
+    /// * `allocation_count` - The number of allocations that still succeed.
+    pub(crate) fn fail_after(&mut self, allocation_count: usize) {
+        self.remaining_allocations = Some(allocation_count);
+    }
+
+    /// Lets allocations succeed again for as long as the arena has room.
+    pub(crate) fn stop_failing(&mut self) {
+        self.remaining_allocations = None;
+    }
+
+    /// Returns the number of successful allocations so far.
+    pub(crate) fn allocation_count(&self) -> usize {
+        self.allocation_count
+    }
+
+    /// Returns the number of frames that are allocated.
+    pub(crate) fn allocated_frame_count(&self) -> usize {
+        self.is_allocated
+            .iter()
+            .filter(|&&is_allocated| is_allocated)
+            .count()
+    }
+
+    /// Returns true if an address lies in an allocated frame of the arena.
+    ///
+    /// # Parameters
+    ///
+    /// * `address` - The physical address.
+    pub(crate) fn is_allocated(&self, address: PhysicalAddress) -> bool {
+        self.frame_index(address)
+            .is_some_and(|index| self.is_allocated[index])
+    }
+
+    /// Returns the physical address of a frame.
+    fn frame_address(&self, index: usize) -> usize {
+        self.frames[index].0.as_ptr() as usize
+    }
+
+    /// Returns the index of the frame holding an address, or `None` if the
+    /// address is outside the arena.
+    fn frame_index(&self, address: PhysicalAddress) -> Option<usize> {
+        let offset = address
+            .raw_address()
+            .checked_sub(self.frames.first()?.0.as_ptr() as usize)?;
+
+        Some(offset / PAGE_SIZE).filter(|&index| index < self.frames.len())
+    }
+
+    /// Consumes one of the allocations `fail_after` allows.
+    ///
+    /// # Returns
+    ///
+    /// False if the allocation has to fail.
+    fn take_allocation(&mut self) -> bool {
+        match &mut self.remaining_allocations {
+            Some(0) => false,
+            Some(remaining_allocations) => {
+                *remaining_allocations -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+impl PhysicalMemoryAllocator for MockFrameAllocator {
+    fn allocate_page(&mut self) -> Option<PhysicalAddress> {
+        self.allocate_contiguous(1, PAGE_SIZE)
+    }
+
+    fn allocate_contiguous_in_range(
+        &mut self,
+        page_count: usize,
+        alignment: usize,
+        start_address: PhysicalAddress,
+        end_address: PhysicalAddress,
+    ) -> Option<PhysicalAddress> {
+        if page_count == 0 || !alignment.is_power_of_two() {
+            return None;
+        }
+
+        let alignment = alignment.max(PAGE_SIZE);
+
+        let first_index = (0..self.frames.len()).find(|&index| {
+            let address = self.frame_address(index);
+            let indices = index..index + page_count;
+
+            address.is_multiple_of(alignment)
+                && address >= start_address.raw_address()
+                && address + page_count * PAGE_SIZE <= end_address.raw_address()
+                && indices.end <= self.frames.len()
+                && indices.into_iter().all(|index| !self.is_allocated[index])
+        })?;
+
+        if !self.take_allocation() {
+            return None;
+        }
+
+        self.is_allocated[first_index..first_index + page_count].fill(true);
+        self.allocation_count += 1;
+
+        Some(PhysicalAddress::new(self.frame_address(first_index)))
+    }
+
+    fn total_memory_size(&self) -> usize {
+        self.frames.len() * PAGE_SIZE
+    }
+
+    fn allocated_memory_size(&self) -> usize {
+        self.allocated_frame_count() * PAGE_SIZE
+    }
+
+    fn memory_regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        self.frames
+            .first()
+            .map(|frame| MemoryRegion::new(frame.0.as_ptr() as usize, self.total_memory_size()))
+            .into_iter()
+    }
+
+    fn allocated_regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        (0..self.frames.len())
+            .filter(|&index| self.is_allocated[index])
+            .map(|index| MemoryRegion::new(self.frame_address(index), PAGE_SIZE))
+    }
+}
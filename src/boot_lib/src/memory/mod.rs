@@ -1,3 +1,7 @@
 pub mod memory_map;
 pub mod mmu;
+#[cfg(test)]
+pub(crate) mod mock_frame_allocator;
+pub mod page_table_dump;
 pub mod physical_memory_allocator;
+pub mod zone;
@@ -0,0 +1,418 @@
+//! Readable dumps of page tables in the paging mode set by
+//! `mmu::set_paging_mode`.
+//!
+//! A dump lists the leaf entries of a page table in increasing virtual address
+//! order, merging each run of virtually contiguous entries of the same size
+//! and with the same flags into one line:
+//!
+//! ```text
+//! 0xffffffc000000000-0xffffffdfffffffff RW--G 1GiB x128
+//! ```
+//!
+//! The physical addresses the entries map are left out, so that a run covers
+//! every entry of a mapping like the direct physical memory mapping, however
+//! its physical memory is laid out. Both the boot stage and the kernel's
+//! monitor print page tables this way.
+
+use super::mmu::{PageTable, PageTableEntry, page_table_pointer, paging_mode};
+use common_lib::layout::PagingMode;
+use core::fmt;
+
+/// The number of entries in a page table.
+const PAGE_TABLE_ENTRY_COUNT: usize = 512;
+
+/// The size of the smallest page.
+const PAGE_SIZE: usize = 4096;
+
+/// A leaf entry of a page table, as passed to the callback of
+/// `for_each_leaf`.
+#[derive(Copy, Clone)]
+pub struct Leaf {
+    /// The first virtual address the entry maps, sign extended.
+    pub virtual_address: usize,
+
+    /// The level of the page table holding the entry, 0 for a 4KiB page, 1
+    /// for a 2MiB page, 2 for a 1GiB page, 3 for a 512GiB page, and 4 for a
+    /// 256TiB page.
+    pub level: u8,
+
+    /// The leaf entry itself.
+    pub entry: PageTableEntry,
+}
+
+impl Leaf {
+    /// Returns the number of bytes the entry maps.
+    pub const fn size(&self) -> usize {
+        page_size(self.level)
+    }
+
+    /// Returns the flags of the entry as "RWXUG", with '-' for clear bits.
+    pub fn flag_characters(&self) -> [u8; 5] {
+        let flag = |is_set: bool, character: u8| if is_set { character } else { b'-' };
+
+        [
+            flag(self.entry.is_readable(), b'R'),
+            flag(self.entry.is_writable(), b'W'),
+            flag(self.entry.is_executable(), b'X'),
+            flag(self.entry.is_user(), b'U'),
+            flag(self.entry.is_global(), b'G'),
+        ]
+    }
+}
+
+/// A run of virtually contiguous leaf entries of the same size with the same
+/// flags, which a dump prints as one line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LeafRange {
+    /// The first virtual address of the run, sign extended.
+    pub virtual_address: usize,
+
+    /// The level of the page tables holding the entries.
+    pub level: u8,
+
+    /// The number of entries in the run.
+    pub entry_count: usize,
+
+    /// The flags of the entries as "RWXUG", with '-' for clear bits.
+    pub flags: [u8; 5],
+}
+
+impl LeafRange {
+    /// Returns the number of bytes the run maps.
+    pub const fn size(&self) -> usize {
+        page_size(self.level) * self.entry_count
+    }
+
+    /// Returns the last virtual address the run maps.
+    pub const fn last_virtual_address(&self) -> usize {
+        self.virtual_address.wrapping_add(self.size() - 1)
+    }
+
+    /// Adds a leaf entry to the end of the run.
+    ///
+    /// # Returns
+    ///
+    /// True if the entry was added, or false if it does not directly follow
+    /// the run or differs from it in size or flags.
+    fn try_extend(&mut self, leaf: &Leaf) -> bool {
+        let is_extension = self.level == leaf.level
+            && self.flags == leaf.flag_characters()
+            && self.virtual_address.wrapping_add(self.size()) == leaf.virtual_address;
+
+        if is_extension {
+            self.entry_count += 1;
+        }
+
+        is_extension
+    }
+}
+
+impl From<&Leaf> for LeafRange {
+    fn from(leaf: &Leaf) -> Self {
+        Self {
+            virtual_address: leaf.virtual_address,
+            level: leaf.level,
+            entry_count: 1,
+            flags: leaf.flag_characters(),
+        }
+    }
+}
+
+impl fmt::Display for LeafRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let page_size = match self.level {
+            0 => "4KiB",
+            1 => "2MiB",
+            2 => "1GiB",
+            3 => "512GiB",
+            _ => "256TiB",
+        };
+
+        write!(
+            f,
+            "{:#018x}-{:#018x} {} {} x{}",
+            self.virtual_address,
+            self.last_virtual_address(),
+            core::str::from_utf8(&self.flags).unwrap_or("?????"),
+            page_size,
+            self.entry_count
+        )
+    }
+}
+
+/// Calls a function with every leaf entry of a page table and the tables
+/// below it, in increasing virtual address order.
+///
+/// # Arguments
+///
+/// * `page_table_root` - The root page table to walk. The tables
+///   below it must be accessible through `page_table_pointer`.
+/// * `callback` - Called with each leaf entry.
+pub fn for_each_leaf(page_table_root: &PageTable, callback: &mut dyn FnMut(&Leaf)) {
+    let paging_mode = paging_mode();
+
+    for_each_leaf_in(
+        paging_mode,
+        page_table_root,
+        paging_mode.root_level() as u8,
+        0,
+        callback,
+    );
+}
+
+/// Calls a function with every run of leaf entries of a page table, in
+/// increasing virtual address order.
+///
+/// # Arguments
+///
+/// * `page_table_root` - The root page table to walk. The tables
+///   below it must be accessible through `page_table_pointer`.
+/// * `callback` - Called with each run.
+pub fn for_each_leaf_range(page_table_root: &PageTable, callback: &mut dyn FnMut(&LeafRange)) {
+    let mut pending_range: Option<LeafRange> = None;
+
+    for_each_leaf(page_table_root, &mut |leaf| {
+        if pending_range
+            .as_mut()
+            .is_some_and(|range| range.try_extend(leaf))
+        {
+            return;
+        }
+
+        if let Some(range) = pending_range.replace(LeafRange::from(leaf)) {
+            callback(&range);
+        }
+    });
+
+    if let Some(range) = pending_range {
+        callback(&range);
+    }
+}
+
+/// Writes every run of leaf entries of a page table, one per line.
+///
+/// # Arguments
+///
+/// * `writer` - Where the runs are written.
+/// * `page_table_root` - The root page table to dump. The tables
+///   below it must be accessible through `page_table_pointer`.
+/// * `indent` - The number of spaces each line starts with.
+pub fn write_leaf_ranges(
+    writer: &mut dyn fmt::Write,
+    page_table_root: &PageTable,
+    indent: usize,
+) -> fmt::Result {
+    let mut result = Ok(());
+
+    for_each_leaf_range(page_table_root, &mut |range| {
+        if result.is_ok() {
+            result = writeln!(writer, "{:indent$}{}", "", range);
+        }
+    });
+
+    result
+}
+
+/// Walks one page table for `for_each_leaf`, descending into the page tables
+/// its entries point to.
+///
+/// # Arguments
+///
+/// * `paging_mode` - The paging mode the page tables are built in.
+/// * `page_table` - The page table to walk.
+/// * `level` - The level of the page table.
+/// * `base_virtual_address` - The first virtual address the table maps.
+/// * `callback` - Called with each leaf entry.
+fn for_each_leaf_in(
+    paging_mode: PagingMode,
+    page_table: &PageTable,
+    level: u8,
+    base_virtual_address: usize,
+    callback: &mut dyn FnMut(&Leaf),
+) {
+    for index in 0..PAGE_TABLE_ENTRY_COUNT {
+        let entry = page_table.get_entry(index);
+        if !entry.is_valid() {
+            continue;
+        }
+
+        let virtual_address =
+            paging_mode.sign_extend(base_virtual_address + index * page_size(level));
+
+        if entry.is_leaf() {
+            callback(&Leaf {
+                virtual_address,
+                level,
+                entry: *entry,
+            });
+        } else if level > 0 {
+            let child_page_table = unsafe { &*page_table_pointer(entry.get_ppn()) };
+
+            for_each_leaf_in(
+                paging_mode,
+                child_page_table,
+                level - 1,
+                virtual_address,
+                callback,
+            );
+        }
+    }
+}
+
+/// Returns the size of the memory mapped by a leaf entry at a page table
+/// level.
+const fn page_size(level: u8) -> usize {
+    PAGE_SIZE << (9 * level as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{
+        mmu::{PageTableEntryFlags, allocate_root_vpn, map_range},
+        mock_frame_allocator::MockFrameAllocator,
+    };
+    use common_lib::memory::{PhysicalPageNumber, VirtualPageNumber};
+
+    fn flags(is_writable: bool, is_global: bool) -> PageTableEntryFlags {
+        let mut flags = PageTableEntryFlags::default();
+        flags.set_readable(true);
+        flags.set_writable(is_writable);
+        flags.set_global(is_global);
+        flags
+    }
+
+    fn ranges(root: &PageTable) -> Vec<LeafRange> {
+        let mut ranges = Vec::new();
+        for_each_leaf_range(root, &mut |range| ranges.push(*range));
+
+        ranges
+    }
+
+    #[test]
+    fn test_contiguous_gigapages_with_same_flags_form_one_range() {
+        let mut root = PageTable::new();
+
+        // Root entries 256 through 383 are the first half of the upper
+        // address space. The physical pages need not be contiguous.
+        for index in 0..128 {
+            allocate_root_vpn(
+                &mut root,
+                VirtualPageNumber::from_raw_virtual_page_number((256 + index) << 18),
+                PhysicalPageNumber::from_raw_physical_page_number(((index * 7) % 128) << 18),
+                &flags(true, true),
+            )
+            .unwrap();
+        }
+
+        let ranges = ranges(&root);
+
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(
+            ranges[0].to_string(),
+            "0xffffffc000000000-0xffffffdfffffffff RW--G 1GiB x128"
+        );
+    }
+
+    #[test]
+    fn test_ranges_split_on_flags_size_and_gaps() {
+        let mut root = PageTable::new();
+        let mut allocator = MockFrameAllocator::new(8);
+
+        let vpn = |page: usize| VirtualPageNumber::from_raw_virtual_page_number((1 << 18) | page);
+        let ppn = PhysicalPageNumber::from_raw_physical_page_number(0x8_0000);
+
+        // `map_range` maps one page more than the count it is given. Pages 0
+        // through 3 are writable, 4 and 5 are not, and 7 follows a gap.
+        map_range(
+            &mut root,
+            ppn,
+            vpn(0),
+            3,
+            &flags(true, false),
+            &mut allocator,
+        )
+        .unwrap();
+        map_range(
+            &mut root,
+            ppn,
+            vpn(4),
+            1,
+            &flags(false, false),
+            &mut allocator,
+        )
+        .unwrap();
+        map_range(
+            &mut root,
+            ppn,
+            vpn(7),
+            0,
+            &flags(false, false),
+            &mut allocator,
+        )
+        .unwrap();
+
+        allocate_root_vpn(
+            &mut root,
+            VirtualPageNumber::from_raw_virtual_page_number(2 << 18),
+            PhysicalPageNumber::from_raw_physical_page_number(0),
+            &flags(false, false),
+        )
+        .unwrap();
+
+        let lines: Vec<String> = ranges(&root).iter().map(LeafRange::to_string).collect();
+
+        assert_eq!(
+            lines,
+            [
+                "0x0000000040000000-0x0000000040003fff RW--- 4KiB x4",
+                "0x0000000040004000-0x0000000040005fff R---- 4KiB x2",
+                "0x0000000040007000-0x0000000040007fff R---- 4KiB x1",
+                "0x0000000080000000-0x00000000bfffffff R---- 1GiB x1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_leaf_ranges_indents_every_line() {
+        let mut root = PageTable::new();
+
+        for index in [0, 1] {
+            allocate_root_vpn(
+                &mut root,
+                VirtualPageNumber::from_raw_virtual_page_number(index << 18),
+                PhysicalPageNumber::from_raw_physical_page_number(index << 18),
+                &flags(true, false),
+            )
+            .unwrap();
+        }
+
+        let mut output = String::new();
+        write_leaf_ranges(&mut output, &root, 2).unwrap();
+
+        assert_eq!(
+            output,
+            "  0x0000000000000000-0x000000007fffffff RW--- 1GiB x2\n"
+        );
+    }
+
+    #[test]
+    fn test_sv48_root_leaf_is_sign_extended_from_bit_47() {
+        let mut root = PageTable::new();
+
+        let mut entry = PageTableEntry::new();
+        entry.set_valid(true);
+        entry.set_flags(&flags(true, true));
+        root.set_entry(384, entry);
+
+        let mut leaf_ranges = Vec::new();
+        for_each_leaf_in(PagingMode::Sv48, &root, 3, 0, &mut |leaf| {
+            leaf_ranges.push(LeafRange::from(leaf).to_string())
+        });
+
+        assert_eq!(
+            leaf_ranges,
+            ["0xffffc00000000000-0xffffc07fffffffff RW--G 512GiB x1"]
+        );
+    }
+}
@@ -3,7 +3,8 @@
 //! This module provides a simple bump allocator for physical memory pages. It
 //! does not support deallocation of memory pages.
 
-use common_lib::memory::MemoryRegion;
+use super::zone::MemoryZone;
+use common_lib::memory::{MemoryRegion, PhysicalAddress};
 use core::iter::Iterator;
 
 /// Trait defining the interface for physical memory allocators.
@@ -16,10 +17,121 @@ pub trait PhysicalMemoryAllocator {
     ///
     /// # Returns
     ///
-    /// * `Some(*mut u8)` - If a page was successfully allocated, returns a
-    ///   pointer to the page.
+    /// * `Some(PhysicalAddress)` - If a page was successfully allocated,
+    ///   returns the physical address of the page.
     /// * `None` - If there is no more memory available to allocate.
-    fn allocate_page(&mut self) -> Option<*mut u8>;
+    fn allocate_page(&mut self) -> Option<PhysicalAddress>;
+
+    /// Allocates physically contiguous pages whose first page is aligned and
+    /// that all lie within a range of physical addresses.
+    ///
+    /// # Parameters
+    ///
+    /// * `page_count` - The number of 4KiB pages to allocate.
+    /// * `alignment` - The alignment of the first page in bytes. Must be a
+    ///   power of two. Alignments below 4KiB are rounded up to 4KiB.
+    /// * `start_address` - The lowest physical address the pages may use.
+    /// * `end_address` - The physical address the pages must end at or
+    ///   before.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(PhysicalAddress)` - If the pages were successfully allocated,
+    ///   returns the physical address of the first page.
+    /// * `None` - If `page_count` is zero, `alignment` is not a power of two,
+    ///   or there is no run of free pages that large in the range.
+    fn allocate_contiguous_in_range(
+        &mut self,
+        page_count: usize,
+        alignment: usize,
+        start_address: PhysicalAddress,
+        end_address: PhysicalAddress,
+    ) -> Option<PhysicalAddress>;
+
+    /// Allocates physically contiguous pages whose first page is aligned,
+    /// such as a 2MiB aligned block for a megapage or a buffer a device
+    /// reaches by its physical address.
+    ///
+    /// # Parameters
+    ///
+    /// * `page_count` - The number of 4KiB pages to allocate.
+    /// * `alignment` - The alignment of the first page in bytes. Must be a
+    ///   power of two. Alignments below 4KiB are rounded up to 4KiB.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(PhysicalAddress)` - If the pages were successfully allocated,
+    ///   returns the physical address of the first page.
+    /// * `None` - If `page_count` is zero, `alignment` is not a power of two,
+    ///   or there is no run of free pages that large.
+    fn allocate_contiguous(
+        &mut self,
+        page_count: usize,
+        alignment: usize,
+    ) -> Option<PhysicalAddress> {
+        self.allocate_contiguous_in_range(
+            page_count,
+            alignment,
+            PhysicalAddress::new(0),
+            PhysicalAddress::new(usize::MAX),
+        )
+    }
+
+    /// Allocates physically contiguous pages from a memory zone, such as
+    /// memory below 4GiB for a device with 32-bit DMA addresses.
+    ///
+    /// # Parameters
+    ///
+    /// * `page_count` - The number of 4KiB pages to allocate.
+    /// * `alignment` - The alignment of the first page in bytes. Must be a
+    ///   power of two. Alignments below 4KiB are rounded up to 4KiB.
+    /// * `zone` - The zone to allocate from.
+    /// * `allow_higher_zones` - True to fall back to the zones above `zone`,
+    ///   lowest first, when `zone` has no room.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(PhysicalAddress)` - If the pages were successfully allocated,
+    ///   returns the physical address of the first page.
+    /// * `None` - If no allowed zone has a run of free pages that large.
+    fn allocate_contiguous_in_zone(
+        &mut self,
+        page_count: usize,
+        alignment: usize,
+        zone: MemoryZone,
+        allow_higher_zones: bool,
+    ) -> Option<PhysicalAddress> {
+        MemoryZone::ALL
+            .into_iter()
+            .filter(|&candidate_zone| {
+                candidate_zone == zone || (allow_higher_zones && candidate_zone > zone)
+            })
+            .find_map(|candidate_zone| {
+                self.allocate_contiguous_in_range(
+                    page_count,
+                    alignment,
+                    candidate_zone.start_address(),
+                    candidate_zone.end_address(),
+                )
+            })
+    }
+
+    /// Returns the amount of memory of a zone available to the allocator, in
+    /// bytes, whether allocated or not.
+    ///
+    /// # Parameters
+    ///
+    /// * `zone` - The zone.
+    ///
+    /// # Returns
+    ///
+    /// The total size of the parts of all memory regions within the zone.
+    fn zone_memory_size(&self, zone: MemoryZone) -> usize {
+        self.memory_regions()
+            .filter_map(|region| zone.clip(&region))
+            .map(|region| region.size)
+            .sum()
+    }
 
     /// Returns the total amount of memory available for allocation, in bytes.
     ///
@@ -95,6 +207,13 @@ impl PhysicalBumpAllocator {
         }
     }
 
+    /// Returns the next physical address the allocator would hand out. Every
+    /// region before the current one, and the part of the current region below
+    /// this address, has been allocated.
+    pub const fn next_allocation_address(&self) -> PhysicalAddress {
+        PhysicalAddress::new(self.next_allocation_address)
+    }
+
     /// Resets the physical bump allocator with the provided memory regions. All
     /// current state is lost.
     ///
@@ -131,10 +250,10 @@ impl PhysicalMemoryAllocator for PhysicalBumpAllocator {
     ///
     /// # Returns
     ///
-    /// * `Some(*mut u8)` - If a page was successfully allocated, returns a
-    ///   pointer to the page.
+    /// * `Some(PhysicalAddress)` - If a page was successfully allocated,
+    ///   returns the physical address of the page.
     /// * `None` - If there is no more memory available to allocate.
-    fn allocate_page(&mut self) -> Option<*mut u8> {
+    fn allocate_page(&mut self) -> Option<PhysicalAddress> {
         // Check if we have any regions to allocate from.
         if self.region_count == 0 {
             return None;
@@ -181,13 +300,92 @@ impl PhysicalMemoryAllocator for PhysicalBumpAllocator {
             }
 
             // Return the raw pointer to the allocated memory.
-            return Some(allocation_address as *mut u8);
+            return Some(PhysicalAddress::new(allocation_address));
         }
 
         // No more memory available.
         None
     }
 
+    /// Allocates physically contiguous pages whose first page is aligned and
+    /// that all lie within a range of physical addresses.
+    ///
+    /// The pages come from the first region, starting at the current one,
+    /// that has room for them within the range after aligning. Memory skipped
+    /// to align the pages or to reach that region is never allocated, since
+    /// the allocator only moves forward. A request that fits nowhere leaves
+    /// the allocator unchanged.
+    ///
+    /// # Parameters
+    ///
+    /// * `page_count` - The number of 4KiB pages to allocate.
+    /// * `alignment` - The alignment of the first page in bytes. Must be a
+    ///   power of two. Alignments below 4KiB are rounded up to 4KiB.
+    /// * `start_address` - The lowest physical address the pages may use.
+    /// * `end_address` - The physical address the pages must end at or
+    ///   before.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(PhysicalAddress)` - If the pages were successfully allocated,
+    ///   returns the physical address of the first page.
+    /// * `None` - If `page_count` is zero, `alignment` is not a power of two,
+    ///   or no region has room for the pages within the range.
+    fn allocate_contiguous_in_range(
+        &mut self,
+        page_count: usize,
+        alignment: usize,
+        start_address: PhysicalAddress,
+        end_address: PhysicalAddress,
+    ) -> Option<PhysicalAddress> {
+        if page_count == 0 || !alignment.is_power_of_two() {
+            return None;
+        }
+
+        let alignment = alignment.max(4096);
+        let size = page_count.checked_mul(4096)?;
+
+        // Find the first region with room before changing any state, so a
+        // request that fits nowhere does not use up the remaining regions.
+        let (region_index, allocation_address) = (self.current_region_index..self.region_count)
+            .find_map(|region_index| {
+                let region = self.memory_regions[region_index];
+                let region_end_address = region.start + region.size;
+
+                let region_start_address = if region_index == self.current_region_index {
+                    self.next_allocation_address
+                } else {
+                    region.start
+                };
+
+                let allocation_address = region_start_address
+                    .max(start_address.raw_address())
+                    .checked_next_multiple_of(alignment)?;
+                let allocation_end_address = allocation_address.checked_add(size)?;
+
+                (allocation_end_address <= region_end_address.min(end_address.raw_address()))
+                    .then_some((region_index, allocation_address))
+            })?;
+
+        let region = self.memory_regions[region_index];
+        let region_end_address = region.start + region.size;
+
+        self.current_region_index = region_index;
+        self.next_allocation_address = allocation_address + size;
+
+        // Like a single page allocation, move to the next region once this
+        // one is used up.
+        if self.next_allocation_address + 4096 > region_end_address {
+            self.current_region_index += 1;
+
+            if self.current_region_index < self.region_count {
+                self.next_allocation_address = self.memory_regions[self.current_region_index].start;
+            }
+        }
+
+        Some(PhysicalAddress::new(allocation_address))
+    }
+
     /// Returns the total amount of memory available for allocation, in bytes.
     ///
     /// # Returns
@@ -292,8 +490,8 @@ mod tests {
         let mut allocator = PhysicalBumpAllocator::new();
         allocator.reset(&regions, regions.len());
 
-        let ptr = allocator.allocate_page().unwrap();
-        assert_eq!(ptr as usize, 0x1000);
+        let address = allocator.allocate_page().unwrap();
+        assert_eq!(address.raw_address(), 0x1000);
         assert_eq!(allocator.next_allocation_address, 0x2000);
         assert_eq!(allocator.allocated_memory_size(), 0x1000);
     }
@@ -305,13 +503,13 @@ mod tests {
         let mut allocator = PhysicalBumpAllocator::new();
         allocator.reset(&regions, regions.len());
 
-        let ptr1 = allocator.allocate_page().unwrap();
-        let ptr2 = allocator.allocate_page().unwrap();
-        let ptr3 = allocator.allocate_page().unwrap();
+        let address1 = allocator.allocate_page().unwrap();
+        let address2 = allocator.allocate_page().unwrap();
+        let address3 = allocator.allocate_page().unwrap();
 
-        assert_eq!(ptr1 as usize, 0x1000);
-        assert_eq!(ptr2 as usize, 0x2000);
-        assert_eq!(ptr3 as usize, 0x3000);
+        assert_eq!(address1.raw_address(), 0x1000);
+        assert_eq!(address2.raw_address(), 0x2000);
+        assert_eq!(address3.raw_address(), 0x3000);
 
         // The region should now be exhausted.
         assert_eq!(allocator.current_region_index, 1);
@@ -328,16 +526,16 @@ mod tests {
         allocator.reset(&regions, regions.len());
 
         // Allocate from the first region.
-        let ptr1 = allocator.allocate_page().unwrap();
-        assert_eq!(ptr1 as usize, 0x1000);
+        let address1 = allocator.allocate_page().unwrap();
+        assert_eq!(address1.raw_address(), 0x1000);
 
         // The first region is now exhausted, next allocation should come from
         // the second region.
-        let ptr2 = allocator.allocate_page().unwrap();
-        assert_eq!(ptr2 as usize, 0x10000);
+        let address2 = allocator.allocate_page().unwrap();
+        assert_eq!(address2.raw_address(), 0x10000);
 
-        let ptr3 = allocator.allocate_page().unwrap();
-        assert_eq!(ptr3 as usize, 0x11000);
+        let address3 = allocator.allocate_page().unwrap();
+        assert_eq!(address3.raw_address(), 0x11000);
 
         // The second region should now be exhausted.
         assert_eq!(allocator.current_region_index, 2);
@@ -353,8 +551,8 @@ mod tests {
         allocator.reset(&regions, regions.len());
 
         // Allocate the only page.
-        let ptr = allocator.allocate_page().unwrap();
-        assert_eq!(ptr as usize, 0x1000);
+        let address = allocator.allocate_page().unwrap();
+        assert_eq!(address.raw_address(), 0x1000);
 
         // Try to allocate again, should be None.
         assert!(allocator.allocate_page().is_none());
@@ -389,13 +587,13 @@ mod tests {
         assert_eq!(allocator.available_memory_size(), 0x4000);
 
         // Allocate one page (0x1000).
-        let _ptr = allocator.allocate_page().unwrap();
+        let _address = allocator.allocate_page().unwrap();
         assert_eq!(allocator.allocated_memory_size(), 0x1000);
         assert_eq!(allocator.available_memory_size(), 0x3000);
 
         // Allocate two more pages (0x2000).
-        let _ptr2 = allocator.allocate_page().unwrap();
-        let _ptr3 = allocator.allocate_page().unwrap();
+        let _address2 = allocator.allocate_page().unwrap();
+        let _address3 = allocator.allocate_page().unwrap();
         assert_eq!(allocator.allocated_memory_size(), 0x3000);
         assert_eq!(allocator.available_memory_size(), 0x1000);
     }
@@ -415,17 +613,17 @@ mod tests {
         assert_eq!(allocator.available_memory_size(), 0x3000);
 
         // Allocate from the first region.
-        let _ptr1 = allocator.allocate_page().unwrap();
+        let _address1 = allocator.allocate_page().unwrap();
         assert_eq!(allocator.allocated_memory_size(), 0x1000);
         assert_eq!(allocator.available_memory_size(), 0x2000);
 
         // Allocate from the second region.
-        let _ptr2 = allocator.allocate_page().unwrap();
+        let _address2 = allocator.allocate_page().unwrap();
         assert_eq!(allocator.allocated_memory_size(), 0x2000);
         assert_eq!(allocator.available_memory_size(), 0x1000);
 
         // Allocate the final page.
-        let _ptr3 = allocator.allocate_page().unwrap();
+        let _address3 = allocator.allocate_page().unwrap();
         assert_eq!(allocator.allocated_memory_size(), 0x3000);
         assert_eq!(allocator.available_memory_size(), 0);
     }
@@ -443,11 +641,142 @@ mod tests {
         assert_eq!(allocator.available_memory_size(), 0x1000);
 
         // Allocate the only page.
-        let _ptr = allocator.allocate_page().unwrap();
+        let _address = allocator.allocate_page().unwrap();
 
         // No more memory available.
         assert_eq!(allocator.allocated_memory_size(), 0x1000);
         assert_eq!(allocator.available_memory_size(), 0);
         assert!(allocator.allocate_page().is_none());
     }
+
+    #[test]
+    fn test_allocate_contiguous_pages() {
+        let regions = [MemoryRegion::new(0x1000, 0x8000)];
+
+        let mut allocator = PhysicalBumpAllocator::new();
+        allocator.reset(&regions, regions.len());
+
+        let address1 = allocator.allocate_contiguous(3, 4096).unwrap();
+        assert_eq!(address1.raw_address(), 0x1000);
+        assert_eq!(allocator.allocated_memory_size(), 0x3000);
+
+        // The next page follows the block.
+        let address2 = allocator.allocate_page().unwrap();
+        assert_eq!(address2.raw_address(), 0x4000);
+    }
+
+    #[test]
+    fn test_allocate_contiguous_aligned() {
+        let regions = [MemoryRegion::new(0x1000, 0x20_0000 + 0x3000)];
+
+        let mut allocator = PhysicalBumpAllocator::new();
+        allocator.reset(&regions, regions.len());
+
+        // The pages before the 2MiB boundary are skipped.
+        let address = allocator.allocate_contiguous(2, 0x20_0000).unwrap();
+        assert_eq!(address.raw_address(), 0x20_0000);
+        assert_eq!(allocator.next_allocation_address, 0x20_2000);
+
+        // Alignments below a page are rounded up to a page.
+        let address = allocator.allocate_contiguous(1, 16).unwrap();
+        assert_eq!(address.raw_address(), 0x20_2000);
+    }
+
+    #[test]
+    fn test_allocate_contiguous_skips_small_regions() {
+        let regions = [
+            MemoryRegion::new(0x1000, 0x2000),  // Two pages.
+            MemoryRegion::new(0x10000, 0x4000), // Four pages.
+        ];
+
+        let mut allocator = PhysicalBumpAllocator::new();
+        allocator.reset(&regions, regions.len());
+
+        let address = allocator.allocate_contiguous(3, 4096).unwrap();
+        assert_eq!(address.raw_address(), 0x10000);
+        assert_eq!(allocator.current_region_index, 1);
+        assert_eq!(allocator.next_allocation_address, 0x13000);
+    }
+
+    #[test]
+    fn test_allocate_contiguous_failure_keeps_state() {
+        let regions = [
+            MemoryRegion::new(0x1000, 0x2000),
+            MemoryRegion::new(0x10000, 0x2000),
+        ];
+
+        let mut allocator = PhysicalBumpAllocator::new();
+        allocator.reset(&regions, regions.len());
+
+        assert!(allocator.allocate_contiguous(3, 4096).is_none());
+        assert!(allocator.allocate_contiguous(0, 4096).is_none());
+        assert!(allocator.allocate_contiguous(1, 3000).is_none());
+
+        // Nothing was used up by the failed requests.
+        assert_eq!(allocator.allocated_memory_size(), 0);
+        assert_eq!(allocator.allocate_page().unwrap().raw_address(), 0x1000);
+    }
+
+    #[test]
+    fn test_allocate_contiguous_in_zone() {
+        let regions = [
+            MemoryRegion::new(0x8000_0000, 0x2000),
+            MemoryRegion::new(0x1_0000_0000, 0x4000),
+        ];
+
+        let mut allocator = PhysicalBumpAllocator::new();
+        allocator.reset(&regions, regions.len());
+
+        assert_eq!(allocator.zone_memory_size(MemoryZone::Dma32), 0x2000);
+        assert_eq!(allocator.zone_memory_size(MemoryZone::Normal), 0x4000);
+
+        // Normal memory comes from above 4GiB even though low memory is free.
+        let address = allocator
+            .allocate_contiguous_in_zone(1, 4096, MemoryZone::Normal, false)
+            .unwrap();
+        assert_eq!(address.raw_address(), 0x1_0000_0000);
+    }
+
+    #[test]
+    fn test_allocate_contiguous_in_zone_fallback() {
+        let regions = [
+            MemoryRegion::new(0x8000_0000, 0x1000),
+            MemoryRegion::new(0x1_0000_0000, 0x4000),
+        ];
+
+        let mut allocator = PhysicalBumpAllocator::new();
+        allocator.reset(&regions, regions.len());
+
+        // Low memory only has one page, so two pages need the fallback.
+        assert!(
+            allocator
+                .allocate_contiguous_in_zone(2, 4096, MemoryZone::Dma32, false)
+                .is_none()
+        );
+
+        let address = allocator
+            .allocate_contiguous_in_zone(2, 4096, MemoryZone::Dma32, true)
+            .unwrap();
+        assert_eq!(address.raw_address(), 0x1_0000_0000);
+    }
+
+    #[test]
+    fn test_allocate_contiguous_in_range_region_across_zones() {
+        let regions = [MemoryRegion::new(0xFFFF_E000, 0x4000)];
+
+        let mut allocator = PhysicalBumpAllocator::new();
+        allocator.reset(&regions, regions.len());
+
+        // Three pages would cross 4GiB, so they do not fit in low memory.
+        assert!(
+            allocator
+                .allocate_contiguous_in_zone(3, 4096, MemoryZone::Dma32, false)
+                .is_none()
+        );
+
+        let address = allocator
+            .allocate_contiguous_in_zone(2, 4096, MemoryZone::Dma32, false)
+            .unwrap();
+        assert_eq!(address.raw_address(), 0xFFFF_E000);
+    }
 }
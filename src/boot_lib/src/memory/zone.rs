@@ -0,0 +1,128 @@
+//! Physical memory zones.
+//!
+//! Some devices can only address the low part of physical memory, so memory
+//! is classified by address into zones, from lowest to highest. An allocation
+//! may ask for memory of a zone and, when it allows it, fall back to the
+//! zones above it.
+
+use common_lib::memory::{MemoryRegion, PhysicalAddress};
+use core::fmt;
+
+/// A range of physical memory that devices with the same addressing limit can
+/// reach.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MemoryZone {
+    /// Memory below 4GiB, which devices with 32-bit DMA addresses can reach.
+    Dma32,
+
+    /// Memory at or above 4GiB.
+    Normal,
+}
+
+impl MemoryZone {
+    /// Every zone, from the lowest addresses to the highest.
+    pub const ALL: [Self; 2] = [Self::Dma32, Self::Normal];
+
+    /// Returns the first physical address of the zone.
+    pub const fn start_address(&self) -> PhysicalAddress {
+        match self {
+            Self::Dma32 => PhysicalAddress::new(0),
+            Self::Normal => PhysicalAddress::new(1 << 32),
+        }
+    }
+
+    /// Returns the physical address after the last one of the zone. The
+    /// highest zone ends at `usize::MAX`, leaving out the very last byte of
+    /// the address space.
+    pub const fn end_address(&self) -> PhysicalAddress {
+        match self {
+            Self::Dma32 => PhysicalAddress::new(1 << 32),
+            Self::Normal => PhysicalAddress::new(usize::MAX),
+        }
+    }
+
+    /// Finds the zone of a physical address.
+    ///
+    /// # Parameters
+    ///
+    /// * `address` - The physical address.
+    ///
+    /// # Returns
+    ///
+    /// The zone holding the address.
+    pub const fn for_address(address: PhysicalAddress) -> Self {
+        if address.raw_address() < Self::Dma32.end_address().raw_address() {
+            Self::Dma32
+        } else {
+            Self::Normal
+        }
+    }
+
+    /// Returns the part of a memory region within the zone.
+    ///
+    /// # Parameters
+    ///
+    /// * `region` - The memory region.
+    ///
+    /// # Returns
+    ///
+    /// The part of the region in the zone, or `None` if none of it is.
+    pub fn clip(&self, region: &MemoryRegion) -> Option<MemoryRegion> {
+        let start = region.start.max(self.start_address().raw_address());
+        let end = region
+            .start
+            .saturating_add(region.size)
+            .min(self.end_address().raw_address());
+
+        (start < end).then(|| MemoryRegion::new(start, end - start))
+    }
+}
+
+impl fmt::Display for MemoryZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Dma32 => "DMA32",
+            Self::Normal => "Normal",
+        };
+
+        f.pad(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_address() {
+        assert_eq!(
+            MemoryZone::for_address(PhysicalAddress::new(0x8000_0000)),
+            MemoryZone::Dma32
+        );
+        assert_eq!(
+            MemoryZone::for_address(PhysicalAddress::new(0xFFFF_FFFF)),
+            MemoryZone::Dma32
+        );
+        assert_eq!(
+            MemoryZone::for_address(PhysicalAddress::new(0x1_0000_0000)),
+            MemoryZone::Normal
+        );
+    }
+
+    #[test]
+    fn test_clip_region_across_zones() {
+        let region = MemoryRegion::new(0xF000_0000, 0x2000_0000);
+
+        assert_eq!(
+            MemoryZone::Dma32.clip(&region),
+            Some(MemoryRegion::new(0xF000_0000, 0x1000_0000))
+        );
+        assert_eq!(
+            MemoryZone::Normal.clip(&region),
+            Some(MemoryRegion::new(0x1_0000_0000, 0x1000_0000))
+        );
+
+        let low_region = MemoryRegion::new(0x8000_0000, 0x1000);
+        assert_eq!(MemoryZone::Normal.clip(&low_region), None);
+    }
+}
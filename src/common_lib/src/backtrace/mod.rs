@@ -0,0 +1,377 @@
+//! Stack backtraces built by following frame pointers.
+//!
+//! With frame pointers enabled, every RISC-V function that sets up a frame
+//! points s0 (fp) at the stack pointer value it was entered with and saves
+//! its return address and the caller's frame pointer directly below it:
+//!
+//! ```text
+//! fp - 8:  return address (ra)
+//! fp - 16: caller's frame pointer (s0)
+//! ```
+//!
+//! Following the saved frame pointers therefore visits the return address of
+//! every active call. The walk stops at the first frame that does not look
+//! valid, since code built without frame pointers, such as the precompiled
+//! core library, may use s0 as a general purpose register.
+
+use core::{fmt, ops::Range};
+
+/// The most frames a backtrace visits, in case the saved frame pointers form
+/// a very long but valid looking chain.
+pub const MAX_BACKTRACE_DEPTH: usize = 64;
+
+/// A single frame of a backtrace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackFrame {
+    /// The frame pointer of the frame, which is the stack pointer value the
+    /// function was entered with.
+    pub frame_pointer: usize,
+
+    /// The address the function returns to.
+    pub return_address: usize,
+}
+
+/// Iterator over the frames of a stack, from the innermost call outwards.
+///
+/// Created by `Backtrace::new`.
+pub struct Backtrace {
+    frame_pointer: usize,
+    stack_range: Range<usize>,
+    depth: usize,
+}
+
+impl Backtrace {
+    /// Creates an iterator that walks the stack starting at a frame pointer.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_pointer` - The value of s0 in the function whose callers are
+    ///   wanted.
+    /// * `stack_range` - The addresses the stack may occupy. Frames outside of
+    ///   it end the walk.
+    ///
+    /// # Safety
+    ///
+    /// Every address in `stack_range` must be readable.
+    pub unsafe fn new(frame_pointer: usize, stack_range: Range<usize>) -> Self {
+        Self {
+            frame_pointer,
+            stack_range,
+            depth: 0,
+        }
+    }
+
+    /// Reads the word stored `offset` bytes below the current frame pointer.
+    fn read_below_frame_pointer(&self, offset: usize) -> usize {
+        let address = self.frame_pointer - offset;
+
+        // `new` requires the stack range to be readable, and the caller checked
+        // that the address lies within it.
+        unsafe { core::ptr::read_volatile(address as *const usize) }
+    }
+}
+
+impl Iterator for Backtrace {
+    type Item = StackFrame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        const WORD_SIZE: usize = core::mem::size_of::<usize>();
+
+        let frame_pointer = self.frame_pointer;
+
+        let is_valid_frame = frame_pointer.is_multiple_of(WORD_SIZE)
+            && frame_pointer >= self.stack_range.start + 2 * WORD_SIZE
+            && frame_pointer <= self.stack_range.end
+            && self.depth < MAX_BACKTRACE_DEPTH;
+
+        if !is_valid_frame {
+            return None;
+        }
+
+        let return_address = self.read_below_frame_pointer(WORD_SIZE);
+        let caller_frame_pointer = self.read_below_frame_pointer(2 * WORD_SIZE);
+
+        if return_address == 0 {
+            return None;
+        }
+
+        // The stack grows down, so callers' frames are at higher addresses.
+        // Anything else means the chain is corrupt, and ending here also
+        // prevents loops.
+        self.frame_pointer = if caller_frame_pointer > frame_pointer {
+            caller_frame_pointer
+        } else {
+            0
+        };
+        self.depth += 1;
+
+        Some(StackFrame {
+            frame_pointer,
+            return_address,
+        })
+    }
+}
+
+/// The registers a panic report prints, captured where the panic was raised.
+///
+/// Created with the `capture_registers!` macro, which expands in the calling
+/// function so that the stack pointer, return address, and frame pointer are
+/// its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegisterSnapshot {
+    pub stack_pointer: usize,
+    pub return_address: usize,
+    pub frame_pointer: usize,
+
+    /// The address of the instruction that took the most recent trap.
+    pub sepc: usize,
+
+    /// The cause of the most recent trap.
+    pub scause: usize,
+
+    /// The trap value of the most recent trap, such as a faulting address.
+    pub stval: usize,
+}
+
+impl fmt::Display for RegisterSnapshot {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            formatter,
+            "sp:   {:#018x}  ra:     {:#018x}  fp:    {:#018x}",
+            self.stack_pointer, self.return_address, self.frame_pointer
+        )?;
+        write!(
+            formatter,
+            "sepc: {:#018x}  scause: {:#018x}  stval: {:#018x}",
+            self.sepc, self.scause, self.stval
+        )
+    }
+}
+
+/// Captures the current sp, ra, s0, sepc, scause, and stval into a
+/// `RegisterSnapshot`.
+///
+/// Only usable on riscv64 supervisor mode code.
+#[macro_export]
+macro_rules! capture_registers {
+    () => {{
+        let (stack_pointer, return_address, frame_pointer): (usize, usize, usize);
+        let (sepc, scause, stval): (usize, usize, usize);
+
+        unsafe {
+            core::arch::asm!(
+                "mv {}, sp",
+                "mv {}, ra",
+                "mv {}, s0",
+                "csrr {}, sepc",
+                "csrr {}, scause",
+                "csrr {}, stval",
+                out(reg) stack_pointer,
+                out(reg) return_address,
+                out(reg) frame_pointer,
+                out(reg) sepc,
+                out(reg) scause,
+                out(reg) stval,
+                options(nomem, nostack),
+            );
+        }
+
+        $crate::backtrace::RegisterSnapshot {
+            stack_pointer,
+            return_address,
+            frame_pointer,
+            sepc,
+            scause,
+            stval,
+        }
+    }};
+}
+
+/// Looks up the function containing an address, returning its name and the
+/// offset of the address into it.
+pub type Symbolizer<'a> = &'a dyn Fn(usize) -> Option<(&'static str, usize)>;
+
+/// Writes the frames of a stack as a numbered list of return addresses.
+///
+/// # Arguments
+///
+/// * `writer` - Where the backtrace is written.
+/// * `frame_pointer` - The value of s0 in the function whose callers are
+///   wanted.
+/// * `stack_range` - The addresses the stack may occupy.
+/// * `symbolize` - Names the function of each return address, printed as
+///   `name+0x42`. Addresses it returns `None` for are printed alone.
+///
+/// # Safety
+///
+/// Every address in `stack_range` must be readable.
+pub unsafe fn write_backtrace(
+    writer: &mut dyn fmt::Write,
+    frame_pointer: usize,
+    stack_range: Range<usize>,
+    symbolize: Symbolizer,
+) -> fmt::Result {
+    let mut frame_count = 0;
+
+    for (index, frame) in unsafe { Backtrace::new(frame_pointer, stack_range) }.enumerate() {
+        write!(writer, "  #{:<2} {:#018x}", index, frame.return_address)?;
+
+        match symbolize(frame.return_address) {
+            Some((name, offset)) => writeln!(writer, " {}+{:#x}", name, offset)?,
+            None => writeln!(writer)?,
+        }
+
+        frame_count += 1;
+    }
+
+    if frame_count == 0 {
+        writeln!(writer, "  <no frames>")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{string::String, vec, vec::Vec};
+
+    /// A fake stack of words with helpers to lay out frames in it.
+    struct FakeStack {
+        words: Vec<usize>,
+    }
+
+    impl FakeStack {
+        fn new(word_count: usize) -> Self {
+            Self {
+                words: vec![0; word_count],
+            }
+        }
+
+        /// Returns the address of a word of the stack.
+        fn address(&self, index: usize) -> usize {
+            &self.words[index] as *const usize as usize
+        }
+
+        /// Creates a frame whose frame pointer is the address just past word
+        /// `index`, storing the return address and caller frame pointer below
+        /// it. Returns the frame pointer.
+        fn push_frame(
+            &mut self,
+            index: usize,
+            return_address: usize,
+            caller_frame_pointer: usize,
+        ) -> usize {
+            self.words[index - 1] = return_address;
+            self.words[index - 2] = caller_frame_pointer;
+
+            self.address(index - 1) + core::mem::size_of::<usize>()
+        }
+
+        fn range(&self) -> Range<usize> {
+            self.address(0)..self.address(self.words.len() - 1) + core::mem::size_of::<usize>()
+        }
+    }
+
+    #[test]
+    fn test_walks_frame_chain() {
+        let mut stack = FakeStack::new(32);
+
+        let outer = stack.push_frame(30, 0x8000_0300, 0);
+        let middle = stack.push_frame(20, 0x8000_0200, outer);
+        let inner = stack.push_frame(10, 0x8000_0100, middle);
+
+        let frames: Vec<_> = unsafe { Backtrace::new(inner, stack.range()) }.collect();
+
+        assert_eq!(
+            frames,
+            vec![
+                StackFrame {
+                    frame_pointer: inner,
+                    return_address: 0x8000_0100
+                },
+                StackFrame {
+                    frame_pointer: middle,
+                    return_address: 0x8000_0200
+                },
+                StackFrame {
+                    frame_pointer: outer,
+                    return_address: 0x8000_0300
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stops_at_invalid_frames() {
+        let mut stack = FakeStack::new(32);
+        let range = stack.range();
+
+        // A caller frame pointer below the current frame would loop.
+        let looping = stack.push_frame(20, 0x8000_0100, stack.address(4));
+        assert_eq!(unsafe { Backtrace::new(looping, range.clone()) }.count(), 1);
+
+        // A caller frame pointer outside the stack is reported but not
+        // followed.
+        let escaping = stack.push_frame(10, 0x8000_0100, range.end + 64);
+        assert_eq!(
+            unsafe { Backtrace::new(escaping, range.clone()) }.count(),
+            1
+        );
+
+        // A zero return address ends the walk.
+        let terminal = stack.push_frame(30, 0, 0);
+        assert_eq!(
+            unsafe { Backtrace::new(terminal, range.clone()) }.count(),
+            0
+        );
+
+        // Misaligned, null, and out of range frame pointers yield nothing.
+        assert_eq!(
+            unsafe { Backtrace::new(escaping + 1, range.clone()) }.count(),
+            0
+        );
+        assert_eq!(unsafe { Backtrace::new(0, range.clone()) }.count(), 0);
+        assert_eq!(
+            unsafe { Backtrace::new(range.start, range.clone()) }.count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let mut stack = FakeStack::new(2 * MAX_BACKTRACE_DEPTH + 8);
+
+        let mut frame_pointer = 0;
+        for frame in (1..=MAX_BACKTRACE_DEPTH + 2).rev() {
+            frame_pointer = stack.push_frame(2 * frame + 2, 0x1000 + frame, frame_pointer);
+        }
+
+        assert_eq!(
+            unsafe { Backtrace::new(frame_pointer, stack.range()) }.count(),
+            MAX_BACKTRACE_DEPTH
+        );
+    }
+
+    #[test]
+    fn test_write_backtrace() {
+        let mut stack = FakeStack::new(16);
+
+        let outer = stack.push_frame(14, 0x8000_0200, 0);
+        let inner = stack.push_frame(8, 0x8000_0100, outer);
+
+        let symbolize =
+            |address: usize| (address == 0x8000_0100).then_some(("kernel::kernel_main", 0x42));
+
+        let mut output = String::new();
+        unsafe { write_backtrace(&mut output, inner, stack.range(), &symbolize) }.unwrap();
+        assert_eq!(
+            output,
+            "  #0  0x0000000080000100 kernel::kernel_main+0x42\n  #1  0x0000000080000200\n"
+        );
+
+        let mut output = String::new();
+        unsafe { write_backtrace(&mut output, 0, stack.range(), &symbolize) }.unwrap();
+        assert_eq!(output, "  <no frames>\n");
+    }
+}
@@ -0,0 +1,54 @@
+use core::fmt;
+
+/// The reasons `BootInfo::validate` can reject the information the boot
+/// stage handed over.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BootInfoError {
+    /// The address is null or not aligned for a `BootInfo`.
+    InvalidAddress(usize),
+
+    /// The structure does not start with `BOOT_INFO_MAGIC`. Holds the value
+    /// found.
+    BadMagic(u64),
+
+    /// The boot stage wrote a different version of the structure. Holds the
+    /// version found.
+    UnsupportedVersion(u32),
+
+    /// The size the boot stage recorded does not match the size of the
+    /// structure. Holds the size found.
+    SizeMismatch(u32),
+
+    /// More memory regions are recorded than the structure has room for.
+    /// Holds the count found.
+    TooManyMemoryRegions(usize),
+
+    /// More allocated regions are recorded than the structure has room for.
+    /// Holds the count found.
+    TooManyAllocatedRegions(usize),
+
+    /// The console is not one of the `BootConsole` values. Holds the value
+    /// found.
+    UnknownConsole(u32),
+
+    /// A paging mode is not one of the `PagingMode` values. Holds the value
+    /// found.
+    UnknownPagingMode(u32),
+}
+
+impl fmt::Display for BootInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress(address) => write!(f, "invalid address {:#x}", address),
+            Self::BadMagic(magic) => write!(f, "bad magic {:#x}", magic),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported version {}", version),
+            Self::SizeMismatch(size) => write!(f, "unexpected size {} bytes", size),
+            Self::TooManyMemoryRegions(count) => write!(f, "too many memory regions ({})", count),
+            Self::TooManyAllocatedRegions(count) => {
+                write!(f, "too many allocated regions ({})", count)
+            }
+            Self::UnknownConsole(console) => write!(f, "unknown console {}", console),
+            Self::UnknownPagingMode(mode) => write!(f, "unknown paging mode {}", mode),
+        }
+    }
+}
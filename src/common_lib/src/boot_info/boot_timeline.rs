@@ -0,0 +1,111 @@
+use core::fmt;
+
+/// A point of the boot the profiler records the time of, in the order they
+/// are reached.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BootMilestone {
+    /// The boot stage started running Rust code.
+    BootEntered,
+
+    /// The boot stage found and parsed the firmware's DTB.
+    DtbParsed,
+
+    /// The boot stage built the map of usable memory.
+    MemoryMapBuilt,
+
+    /// The boot stage enabled paging with the kernel mapped.
+    MmuReady,
+
+    /// The kernel started running after the boot stage jumped to it.
+    KernelEntered,
+
+    /// The boot hart finished initializing the kernel and became idle.
+    KernelIdle,
+}
+
+impl BootMilestone {
+    /// The number of milestones.
+    pub const COUNT: usize = 6;
+
+    /// Every milestone, in the order they are reached.
+    pub const ALL: [Self; Self::COUNT] = [
+        Self::BootEntered,
+        Self::DtbParsed,
+        Self::MemoryMapBuilt,
+        Self::MmuReady,
+        Self::KernelEntered,
+        Self::KernelIdle,
+    ];
+}
+
+impl fmt::Display for BootMilestone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BootEntered => write!(f, "boot entered"),
+            Self::DtbParsed => write!(f, "DTB parsed"),
+            Self::MemoryMapBuilt => write!(f, "memory map built"),
+            Self::MmuReady => write!(f, "MMU ready"),
+            Self::KernelEntered => write!(f, "kernel entered"),
+            Self::KernelIdle => write!(f, "kernel idle"),
+        }
+    }
+}
+
+/// The value of the `time` CSR at each `BootMilestone` reached so far.
+///
+/// The layout is `repr(C)` since the boot stage hands its part over in a
+/// `BootInfo`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootTimeline {
+    /// The time of each milestone, indexed by its position in
+    /// `BootMilestone::ALL`, or zero if it was not reached.
+    ticks: [u64; BootMilestone::COUNT],
+}
+
+impl BootTimeline {
+    /// Creates a timeline with no milestone reached.
+    pub const fn new() -> Self {
+        Self {
+            ticks: [0; BootMilestone::COUNT],
+        }
+    }
+
+    /// Records the time a milestone was reached.
+    ///
+    /// # Parameters
+    ///
+    /// * `milestone` - The milestone.
+    /// * `ticks` - The value of the `time` CSR.
+    pub fn record(&mut self, milestone: BootMilestone, ticks: u64) {
+        self.ticks[milestone as usize] = ticks;
+    }
+
+    /// Returns the time a milestone was reached.
+    ///
+    /// # Parameters
+    ///
+    /// * `milestone` - The milestone.
+    ///
+    /// # Returns
+    ///
+    /// The value of the `time` CSR, or `None` if the milestone was not
+    /// reached or not recorded.
+    pub fn ticks(&self, milestone: BootMilestone) -> Option<u64> {
+        Some(self.ticks[milestone as usize]).filter(|&ticks| ticks != 0)
+    }
+
+    /// Returns an iterator over the milestones reached and their times, in
+    /// the order they are reached.
+    pub fn milestones(&self) -> impl Iterator<Item = (BootMilestone, u64)> + '_ {
+        BootMilestone::ALL
+            .into_iter()
+            .filter_map(|milestone| Some((milestone, self.ticks(milestone)?)))
+    }
+}
+
+impl Default for BootTimeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
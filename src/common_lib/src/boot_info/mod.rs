@@ -0,0 +1,590 @@
+//! The information the boot stage hands over to the kernel.
+//!
+//! The boot stage fills a `BootInfo` in a page of its own and passes the
+//! physical address of that page to the kernel in a1. The structure starts
+//! with a magic number, a version, and its own size, which the kernel checks
+//! with `BootInfo::validate` before it trusts any other field. A kernel
+//! started by a mismatched boot stage then fails with a clear error instead
+//! of reading garbage.
+//!
+//! Besides the usable RAM, the boot stage records the ranges its allocator
+//! handed out, such as the page tables and the copy of the DTB, so the
+//! kernel's allocator can start from the usable RAM with those ranges carved
+//! out instead of handing the pages out again.
+
+mod boot_info_error;
+mod boot_timeline;
+
+pub use boot_info_error::BootInfoError;
+pub use boot_timeline::{BootMilestone, BootTimeline};
+
+use crate::{
+    layout::PagingMode,
+    memory::{MemoryRegion, PhysicalAddress, VirtualAddress},
+};
+use core::mem::{align_of, size_of};
+
+/// The value at the start of every `BootInfo`, "RISCBOOT" in little-endian
+/// byte order.
+pub const BOOT_INFO_MAGIC: u64 = u64::from_le_bytes(*b"RISCBOOT");
+
+/// The version of the `BootInfo` layout. Bumped whenever a field is added,
+/// removed, or changes meaning.
+pub const BOOT_INFO_VERSION: u32 = 6;
+
+/// The most memory regions a `BootInfo` records, both of usable RAM and of
+/// allocated ranges.
+pub const MAX_BOOT_MEMORY_REGIONS: usize = 64;
+
+/// The console the boot stage printed to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BootConsole {
+    /// The boot stage printed nothing.
+    None,
+
+    /// The SBI debug console extension.
+    SbiDebugConsole,
+}
+
+impl BootConsole {
+    /// Returns the value stored in a `BootInfo` for the console.
+    const fn to_raw(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::SbiDebugConsole => 1,
+        }
+    }
+
+    /// Decodes the value stored in a `BootInfo`.
+    ///
+    /// # Returns
+    ///
+    /// The console, or `None` if the value is not a known console.
+    const fn from_raw(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::None),
+            1 => Some(Self::SbiDebugConsole),
+            _ => None,
+        }
+    }
+}
+
+/// The state of the machine when the boot stage jumps to the kernel.
+///
+/// The layout is `repr(C)` since the boot stage and the kernel are separate
+/// binaries.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct BootInfo {
+    /// Always `BOOT_INFO_MAGIC`.
+    magic: u64,
+
+    /// The `BOOT_INFO_VERSION` of the boot stage that wrote the structure.
+    version: u32,
+
+    /// The size of the structure in bytes, as the boot stage saw it.
+    size: u32,
+
+    /// The hart the boot stage ran on.
+    pub hart_id: usize,
+
+    /// The physical address of the copy of the DTB the boot stage made for
+    /// the kernel.
+    pub dtb_physical_address: PhysicalAddress,
+
+    /// The number of bytes of the DTB.
+    pub dtb_size: usize,
+
+    /// The physical address of the root page table that is active when the
+    /// kernel starts.
+    pub root_page_table_physical_address: PhysicalAddress,
+
+    /// The `PagingMode` the root page table is active in, as stored by
+    /// `PagingMode::satp_mode`.
+    paging_mode: u32,
+
+    /// The largest `PagingMode` the boot hart accepted in satp, as stored by
+    /// `PagingMode::satp_mode`.
+    highest_paging_mode: u32,
+
+    /// The physical address the boot stage's own image is loaded at. The
+    /// image is free once the kernel no longer runs on the boot stack.
+    pub boot_physical_base: PhysicalAddress,
+
+    /// The number of bytes of the boot stage's image, including its stack.
+    pub boot_size: usize,
+
+    /// The physical address the kernel image is loaded at.
+    pub kernel_physical_base: PhysicalAddress,
+
+    /// The virtual address the kernel image is mapped at, which includes
+    /// `kernel_slide`.
+    pub kernel_virtual_base: VirtualAddress,
+
+    /// The number of bytes of the kernel image.
+    pub kernel_size: usize,
+
+    /// The number of bytes the kernel image is mapped above the addresses it
+    /// was linked at, wrapping around if it is mapped below them. Zero unless
+    /// the boot stage randomized the kernel's virtual base or enabled a
+    /// paging mode larger than sv39, which moves the kernel half.
+    pub kernel_slide: usize,
+
+    /// The next physical address the boot stage's allocator would have
+    /// handed out. Memory regions before it, and the part of its region
+    /// below it, are in use.
+    pub allocator_watermark: PhysicalAddress,
+
+    /// The `BootConsole` the boot stage printed to, as stored by
+    /// `BootConsole::to_raw`.
+    console: u32,
+
+    /// The times the boot stage reached its milestones.
+    pub timeline: BootTimeline,
+
+    /// The number of valid entries of `memory_regions`.
+    memory_region_count: usize,
+
+    /// The usable RAM the boot stage found, sorted by start address, with the
+    /// boot stage, the kernel image, the firmware's DTB, and reserved memory
+    /// already carved out.
+    memory_regions: [MemoryRegion; MAX_BOOT_MEMORY_REGIONS],
+
+    /// The number of valid entries of `allocated_regions`.
+    allocated_region_count: usize,
+
+    /// The parts of `memory_regions` the boot stage's allocator handed out,
+    /// sorted by start address.
+    allocated_regions: [MemoryRegion; MAX_BOOT_MEMORY_REGIONS],
+}
+
+impl BootInfo {
+    /// Creates a `BootInfo` with a valid header, no memory regions, and every
+    /// other field zero.
+    pub const fn new() -> Self {
+        Self {
+            magic: BOOT_INFO_MAGIC,
+            version: BOOT_INFO_VERSION,
+            size: size_of::<Self>() as u32,
+            hart_id: 0,
+            dtb_physical_address: PhysicalAddress::new(0),
+            dtb_size: 0,
+            root_page_table_physical_address: PhysicalAddress::new(0),
+            paging_mode: PagingMode::Sv39.satp_mode() as u32,
+            highest_paging_mode: PagingMode::Sv39.satp_mode() as u32,
+            boot_physical_base: PhysicalAddress::new(0),
+            boot_size: 0,
+            kernel_physical_base: PhysicalAddress::new(0),
+            kernel_virtual_base: VirtualAddress::new(0),
+            kernel_size: 0,
+            kernel_slide: 0,
+            allocator_watermark: PhysicalAddress::new(0),
+            console: BootConsole::None.to_raw(),
+            timeline: BootTimeline::new(),
+            memory_region_count: 0,
+            memory_regions: [MemoryRegion::new(0, 0); MAX_BOOT_MEMORY_REGIONS],
+            allocated_region_count: 0,
+            allocated_regions: [MemoryRegion::new(0, 0); MAX_BOOT_MEMORY_REGIONS],
+        }
+    }
+
+    /// Finds and validates the `BootInfo` the boot stage handed over.
+    ///
+    /// # Parameters
+    ///
+    /// * `address` - The address of the structure.
+    ///
+    /// # Returns
+    ///
+    /// The validated structure, or the first problem found.
+    ///
+    /// # Safety
+    ///
+    /// Unless the address is null or misaligned, which are rejected before
+    /// anything is read, `size_of::<BootInfo>()` bytes at `address` must be
+    /// readable and must stay unchanged for the rest of the program.
+    pub unsafe fn from_address(address: usize) -> Result<&'static Self, BootInfoError> {
+        if address == 0 || !address.is_multiple_of(align_of::<Self>()) {
+            return Err(BootInfoError::InvalidAddress(address));
+        }
+
+        let boot_info = unsafe { &*(address as *const Self) };
+        boot_info.validate()?;
+
+        Ok(boot_info)
+    }
+
+    /// Checks the header and the fields whose values are restricted.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the structure can be used, or the first problem found.
+    pub fn validate(&self) -> Result<(), BootInfoError> {
+        if self.magic != BOOT_INFO_MAGIC {
+            return Err(BootInfoError::BadMagic(self.magic));
+        }
+
+        if self.version != BOOT_INFO_VERSION {
+            return Err(BootInfoError::UnsupportedVersion(self.version));
+        }
+
+        if self.size as usize != size_of::<Self>() {
+            return Err(BootInfoError::SizeMismatch(self.size));
+        }
+
+        if self.memory_region_count > MAX_BOOT_MEMORY_REGIONS {
+            return Err(BootInfoError::TooManyMemoryRegions(
+                self.memory_region_count,
+            ));
+        }
+
+        if self.allocated_region_count > MAX_BOOT_MEMORY_REGIONS {
+            return Err(BootInfoError::TooManyAllocatedRegions(
+                self.allocated_region_count,
+            ));
+        }
+
+        if BootConsole::from_raw(self.console).is_none() {
+            return Err(BootInfoError::UnknownConsole(self.console));
+        }
+
+        for mode in [self.paging_mode, self.highest_paging_mode] {
+            if PagingMode::from_satp_mode(mode as usize).is_none() {
+                return Err(BootInfoError::UnknownPagingMode(mode));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the version of the layout the boot stage wrote.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the console the boot stage printed to, or `BootConsole::None`
+    /// if the structure names an unknown console.
+    pub fn console(&self) -> BootConsole {
+        BootConsole::from_raw(self.console).unwrap_or(BootConsole::None)
+    }
+
+    /// Records the console the boot stage printed to.
+    ///
+    /// # Parameters
+    ///
+    /// * `console` - The console.
+    pub fn set_console(&mut self, console: BootConsole) {
+        self.console = console.to_raw();
+    }
+
+    /// Returns the paging mode the root page table is active in, or
+    /// `PagingMode::Sv39` if the structure names an unknown mode.
+    pub fn paging_mode(&self) -> PagingMode {
+        PagingMode::from_satp_mode(self.paging_mode as usize).unwrap_or(PagingMode::Sv39)
+    }
+
+    /// Returns the largest paging mode the boot hart supports, or
+    /// `PagingMode::Sv39` if the structure names an unknown mode.
+    pub fn highest_paging_mode(&self) -> PagingMode {
+        PagingMode::from_satp_mode(self.highest_paging_mode as usize).unwrap_or(PagingMode::Sv39)
+    }
+
+    /// Records the paging mode the boot stage enabled and the largest one the
+    /// boot hart supports.
+    ///
+    /// # Parameters
+    ///
+    /// * `paging_mode` - The mode the root page table is active in.
+    /// * `highest_paging_mode` - The largest mode the boot hart accepted.
+    pub fn set_paging_modes(&mut self, paging_mode: PagingMode, highest_paging_mode: PagingMode) {
+        self.paging_mode = paging_mode.satp_mode() as u32;
+        self.highest_paging_mode = highest_paging_mode.satp_mode() as u32;
+    }
+
+    /// Returns an iterator over the usable memory regions, sorted by start
+    /// address.
+    pub fn memory_regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        self.memory_regions[..self.memory_region_count.min(MAX_BOOT_MEMORY_REGIONS)]
+            .iter()
+            .copied()
+    }
+
+    /// Records the usable memory regions, replacing any recorded before.
+    ///
+    /// # Parameters
+    ///
+    /// * `regions` - The regions, sorted by start address.
+    ///
+    /// # Returns
+    ///
+    /// `BootInfoError::TooManyMemoryRegions` with the number of regions
+    /// given if there are more than `MAX_BOOT_MEMORY_REGIONS`. The first
+    /// `MAX_BOOT_MEMORY_REGIONS` are recorded in that case.
+    pub fn set_memory_regions(
+        &mut self,
+        regions: impl IntoIterator<Item = MemoryRegion>,
+    ) -> Result<(), BootInfoError> {
+        let region_count = copy_regions(&mut self.memory_regions, regions);
+        self.memory_region_count = region_count.min(MAX_BOOT_MEMORY_REGIONS);
+
+        if region_count > MAX_BOOT_MEMORY_REGIONS {
+            return Err(BootInfoError::TooManyMemoryRegions(region_count));
+        }
+
+        Ok(())
+    }
+
+    /// Returns an iterator over the ranges the boot stage's allocator handed
+    /// out, sorted by start address.
+    pub fn allocated_regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        self.allocated_regions[..self.allocated_region_count.min(MAX_BOOT_MEMORY_REGIONS)]
+            .iter()
+            .copied()
+    }
+
+    /// Records the ranges the boot stage's allocator handed out, replacing
+    /// any recorded before.
+    ///
+    /// # Parameters
+    ///
+    /// * `regions` - The regions, sorted by start address.
+    ///
+    /// # Returns
+    ///
+    /// `BootInfoError::TooManyAllocatedRegions` with the number of regions
+    /// given if there are more than `MAX_BOOT_MEMORY_REGIONS`. The first
+    /// `MAX_BOOT_MEMORY_REGIONS` are recorded in that case, and the kernel
+    /// may hand out the memory of the rest again.
+    pub fn set_allocated_regions(
+        &mut self,
+        regions: impl IntoIterator<Item = MemoryRegion>,
+    ) -> Result<(), BootInfoError> {
+        let region_count = copy_regions(&mut self.allocated_regions, regions);
+        self.allocated_region_count = region_count.min(MAX_BOOT_MEMORY_REGIONS);
+
+        if region_count > MAX_BOOT_MEMORY_REGIONS {
+            return Err(BootInfoError::TooManyAllocatedRegions(region_count));
+        }
+
+        Ok(())
+    }
+}
+
+/// Copies regions into an array until it is full.
+///
+/// # Parameters
+///
+/// * `slots` - The array to copy to.
+/// * `regions` - The regions to copy.
+///
+/// # Returns
+///
+/// The number of regions given, which is more than the length of `slots` if
+/// some were not copied.
+fn copy_regions(
+    slots: &mut [MemoryRegion],
+    regions: impl IntoIterator<Item = MemoryRegion>,
+) -> usize {
+    let mut region_count = 0;
+
+    for region in regions {
+        if let Some(slot) = slots.get_mut(region_count) {
+            *slot = region;
+        }
+
+        region_count += 1;
+    }
+
+    region_count
+}
+
+impl Default for BootInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_is_valid() {
+        let boot_info = BootInfo::new();
+
+        assert_eq!(boot_info.validate(), Ok(()));
+        assert_eq!(boot_info.version(), BOOT_INFO_VERSION);
+        assert_eq!(boot_info.console(), BootConsole::None);
+        assert_eq!(boot_info.memory_regions().count(), 0);
+        assert_eq!(boot_info.allocated_regions().count(), 0);
+        assert_eq!(boot_info.paging_mode(), PagingMode::Sv39);
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_header() {
+        let mut boot_info = BootInfo::new();
+        boot_info.magic = 0x1234;
+        assert_eq!(boot_info.validate(), Err(BootInfoError::BadMagic(0x1234)));
+
+        let mut boot_info = BootInfo::new();
+        boot_info.version = BOOT_INFO_VERSION + 1;
+        assert_eq!(
+            boot_info.validate(),
+            Err(BootInfoError::UnsupportedVersion(BOOT_INFO_VERSION + 1))
+        );
+
+        let mut boot_info = BootInfo::new();
+        boot_info.size = 16;
+        assert_eq!(boot_info.validate(), Err(BootInfoError::SizeMismatch(16)));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_fields() {
+        let mut boot_info = BootInfo::new();
+        boot_info.memory_region_count = MAX_BOOT_MEMORY_REGIONS + 1;
+        assert_eq!(
+            boot_info.validate(),
+            Err(BootInfoError::TooManyMemoryRegions(
+                MAX_BOOT_MEMORY_REGIONS + 1
+            ))
+        );
+
+        let mut boot_info = BootInfo::new();
+        boot_info.allocated_region_count = MAX_BOOT_MEMORY_REGIONS + 1;
+        assert_eq!(
+            boot_info.validate(),
+            Err(BootInfoError::TooManyAllocatedRegions(
+                MAX_BOOT_MEMORY_REGIONS + 1
+            ))
+        );
+
+        let mut boot_info = BootInfo::new();
+        boot_info.console = 7;
+        assert_eq!(boot_info.validate(), Err(BootInfoError::UnknownConsole(7)));
+        assert_eq!(boot_info.console(), BootConsole::None);
+
+        let mut boot_info = BootInfo::new();
+        boot_info.highest_paging_mode = 0;
+        assert_eq!(
+            boot_info.validate(),
+            Err(BootInfoError::UnknownPagingMode(0))
+        );
+        assert_eq!(boot_info.highest_paging_mode(), PagingMode::Sv39);
+    }
+
+    #[test]
+    fn test_paging_modes() {
+        let mut boot_info = BootInfo::new();
+        boot_info.set_paging_modes(PagingMode::Sv39, PagingMode::Sv57);
+
+        assert_eq!(boot_info.paging_mode(), PagingMode::Sv39);
+        assert_eq!(boot_info.highest_paging_mode(), PagingMode::Sv57);
+        assert_eq!(boot_info.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_memory_regions() {
+        let mut boot_info = BootInfo::new();
+        boot_info.set_console(BootConsole::SbiDebugConsole);
+
+        let regions = [
+            MemoryRegion::new(0x8000_0000, 0x1000),
+            MemoryRegion::new(0x9000_0000, 0x2000),
+        ];
+        assert_eq!(boot_info.set_memory_regions(regions), Ok(()));
+
+        assert!(boot_info.memory_regions().eq(regions));
+        assert_eq!(boot_info.console(), BootConsole::SbiDebugConsole);
+        assert_eq!(boot_info.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_too_many_memory_regions() {
+        let mut boot_info = BootInfo::new();
+        let regions =
+            (0..MAX_BOOT_MEMORY_REGIONS + 2).map(|index| MemoryRegion::new(index * 0x2000, 0x1000));
+
+        assert_eq!(
+            boot_info.set_memory_regions(regions),
+            Err(BootInfoError::TooManyMemoryRegions(
+                MAX_BOOT_MEMORY_REGIONS + 2
+            ))
+        );
+        assert_eq!(boot_info.memory_regions().count(), MAX_BOOT_MEMORY_REGIONS);
+        assert_eq!(boot_info.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_allocated_regions() {
+        let mut boot_info = BootInfo::new();
+
+        let memory_regions = [MemoryRegion::new(0x8000_0000, 0x10_0000)];
+        let allocated_regions = [MemoryRegion::new(0x8000_0000, 0x3000)];
+        assert_eq!(boot_info.set_memory_regions(memory_regions), Ok(()));
+        assert_eq!(boot_info.set_allocated_regions(allocated_regions), Ok(()));
+
+        assert!(boot_info.memory_regions().eq(memory_regions));
+        assert!(boot_info.allocated_regions().eq(allocated_regions));
+        assert_eq!(boot_info.validate(), Ok(()));
+
+        let regions =
+            (0..MAX_BOOT_MEMORY_REGIONS + 1).map(|index| MemoryRegion::new(index * 0x2000, 0x1000));
+
+        assert_eq!(
+            boot_info.set_allocated_regions(regions),
+            Err(BootInfoError::TooManyAllocatedRegions(
+                MAX_BOOT_MEMORY_REGIONS + 1
+            ))
+        );
+        assert_eq!(
+            boot_info.allocated_regions().count(),
+            MAX_BOOT_MEMORY_REGIONS
+        );
+        assert!(boot_info.memory_regions().eq(memory_regions));
+    }
+
+    #[test]
+    fn test_timeline() {
+        let mut boot_info = BootInfo::new();
+        assert_eq!(boot_info.timeline.milestones().count(), 0);
+
+        boot_info.timeline.record(BootMilestone::BootEntered, 100);
+        boot_info.timeline.record(BootMilestone::MmuReady, 300);
+
+        assert_eq!(
+            boot_info.timeline.ticks(BootMilestone::BootEntered),
+            Some(100)
+        );
+        assert_eq!(boot_info.timeline.ticks(BootMilestone::DtbParsed), None);
+        assert!(boot_info.timeline.milestones().eq([
+            (BootMilestone::BootEntered, 100),
+            (BootMilestone::MmuReady, 300),
+        ]));
+
+        assert!(
+            BootMilestone::ALL
+                .iter()
+                .enumerate()
+                .all(|(index, &milestone)| milestone as usize == index)
+        );
+    }
+
+    #[test]
+    fn test_from_address() {
+        let mut boot_info = Box::new(BootInfo::new());
+        boot_info.hart_id = 3;
+        let address = &*boot_info as *const BootInfo as usize;
+
+        let found = unsafe { BootInfo::from_address(address) }.unwrap();
+        assert_eq!(found.hart_id, 3);
+
+        assert_eq!(
+            unsafe { BootInfo::from_address(0) }.unwrap_err(),
+            BootInfoError::InvalidAddress(0)
+        );
+        assert_eq!(
+            unsafe { BootInfo::from_address(address + 1) }.unwrap_err(),
+            BootInfoError::InvalidAddress(address + 1)
+        );
+    }
+}
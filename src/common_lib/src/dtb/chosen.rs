@@ -0,0 +1,89 @@
+use super::{Dtb, DtbNode};
+
+/// The boot parameters passed in the /chosen node of a Device Tree Blob.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Chosen<'a> {
+    /// The kernel command line from the "bootargs" property.
+    pub bootargs: Option<&'a str>,
+
+    /// The path of the node for the boot console from the "stdout-path"
+    /// property, such as "/soc/serial@10000000". Any options after a ':' are
+    /// included.
+    pub stdout_path: Option<&'a str>,
+
+    /// The physical address of the first byte of the initial ramdisk from the
+    /// "linux,initrd-start" property.
+    pub initrd_start: Option<u64>,
+
+    /// The physical address one past the last byte of the initial ramdisk
+    /// from the "linux,initrd-end" property.
+    pub initrd_end: Option<u64>,
+
+    /// Random bytes from the "rng-seed" property, which QEMU and some
+    /// bootloaders pass to seed the kernel's random number generator.
+    pub rng_seed: Option<&'a [u8]>,
+}
+
+impl<'a> Chosen<'a> {
+    /// Returns the physical address range of the initial ramdisk.
+    ///
+    /// # Returns
+    ///
+    /// The start and end addresses, or `None` if either is missing or the
+    /// range is empty.
+    pub fn initrd_range(&self) -> Option<(u64, u64)> {
+        let start = self.initrd_start?;
+        let end = self.initrd_end?;
+
+        if start < end {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+
+    /// Finds the node of the boot console named by "stdout-path".
+    ///
+    /// # Parameters
+    ///
+    /// * `dtb` - The Device Tree Blob this /chosen node was read from.
+    ///
+    /// # Returns
+    ///
+    /// The node, or `None` if there is no "stdout-path" or it does not
+    /// resolve. Options after a ':', such as a baud rate, are ignored.
+    pub fn stdout_node(&self, dtb: &Dtb<'a>) -> Option<DtbNode<'a>> {
+        let path = self.stdout_path?.split(':').next()?;
+
+        dtb.find_node(path)
+    }
+}
+
+/// Extracts the boot parameters from the /chosen node of a Device Tree Blob.
+///
+/// # Parameters
+///
+/// * `dtb` - The Device Tree Blob.
+///
+/// # Returns
+///
+/// The parameters found in the /chosen node, or `None` if the DTB has no
+/// /chosen node. Properties that are missing or malformed are `None`.
+pub fn chosen<'a>(dtb: &Dtb<'a>) -> Option<Chosen<'a>> {
+    let chosen_node = dtb.root_node()?.child("chosen")?;
+
+    let mut chosen = Chosen::default();
+
+    for property in chosen_node.properties() {
+        match property.name {
+            "bootargs" => chosen.bootargs = property.get_property_data_as_str(),
+            "stdout-path" => chosen.stdout_path = property.get_property_data_as_str(),
+            "linux,initrd-start" => chosen.initrd_start = property.get_property_data_as_u64(),
+            "linux,initrd-end" => chosen.initrd_end = property.get_property_data_as_u64(),
+            "rng-seed" => chosen.rng_seed = Some(property.data).filter(|data| !data.is_empty()),
+            _ => {}
+        }
+    }
+
+    Some(chosen)
+}
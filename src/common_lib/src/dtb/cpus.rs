@@ -0,0 +1,124 @@
+use super::{Dtb, DtbNode, IsaFeatures};
+
+/// The description of a single hart from a node under /cpus.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuInfo<'a> {
+    /// The hart ID from the "reg" property.
+    pub hart_id: u64,
+
+    /// The ISA string from the "riscv,isa" property, such as
+    /// "rv64imafdc_zicsr_zifencei".
+    pub isa: Option<&'a str>,
+
+    /// The supported paging mode from the "mmu-type" property, such as
+    /// "riscv,sv39".
+    pub mmu_type: Option<&'a str>,
+
+    /// The frequency of the `time` CSR in hertz. Taken from the cpu node if
+    /// it has a "timebase-frequency" property, otherwise from /cpus.
+    pub timebase_frequency: Option<u64>,
+
+    /// The value of the "status" property, if any.
+    pub status: Option<&'a str>,
+
+    /// The cpu node itself, for reading properties not covered here.
+    pub node: DtbNode<'a>,
+}
+
+impl<'a> CpuInfo<'a> {
+    /// Returns true if the hart is usable. A cpu node without a "status"
+    /// property, or with a status of "okay", is usable.
+    pub fn is_enabled(&self) -> bool {
+        matches!(self.status, None | Some("okay") | Some("ok"))
+    }
+
+    /// Returns the ISA extensions supported by the hart.
+    ///
+    /// The "riscv,isa-extensions" string list is preferred when present since
+    /// newer bindings deprecate parsing the "riscv,isa" string. A cpu node with
+    /// neither property yields an empty set.
+    pub fn isa_features(&self) -> IsaFeatures {
+        if let Some(property) = self.node.property("riscv,isa-extensions") {
+            return IsaFeatures::from_isa_extensions(property.get_property_data_as_str_list());
+        }
+
+        self.isa
+            .map(IsaFeatures::from_isa_string)
+            .unwrap_or_default()
+    }
+}
+
+/// Returns an iterator over every hart described under the /cpus node.
+///
+/// Children of /cpus whose "device_type" is not "cpu", such as "cpu-map", are
+/// skipped. The iterator is empty if the DTB has no /cpus node.
+///
+/// # Parameters
+///
+/// * `dtb` - The Device Tree Blob.
+///
+/// # Examples
+///
+/// ```ignore
+/// let enabled_hart_count = dtb::cpus(&dtb)
+///     .filter(|cpu| cpu.is_enabled())
+///     .count();
+/// ```
+pub fn cpus<'a>(dtb: &Dtb<'a>) -> impl Iterator<Item = CpuInfo<'a>> + use<'a> {
+    let cpus_node = dtb
+        .root_node()
+        .and_then(|root_node| root_node.child("cpus"));
+
+    // The /cpus node provides the default timebase frequency for all harts.
+    let default_timebase_frequency = cpus_node
+        .and_then(|node| node.property("timebase-frequency"))
+        .and_then(|property| property.get_property_data_as_u64());
+
+    cpus_node
+        .into_iter()
+        .flat_map(|node| node.children())
+        .filter_map(move |node| parse_cpu_node(node, default_timebase_frequency))
+}
+
+/// Builds a `CpuInfo` from a child of /cpus.
+///
+/// # Returns
+///
+/// The parsed information, or `None` if the node is not a cpu node or has no
+/// "reg" property.
+fn parse_cpu_node(
+    node: DtbNode<'_>,
+    default_timebase_frequency: Option<u64>,
+) -> Option<CpuInfo<'_>> {
+    let device_type = node.property("device_type")?.get_property_data_as_str()?;
+    if device_type != "cpu" {
+        return None;
+    }
+
+    // The hart ID is the first address in "reg", described by the
+    // #address-cells of /cpus.
+    let (hart_id, _) = node.first_reg()?;
+
+    let mut cpu_info = CpuInfo {
+        hart_id,
+        isa: None,
+        mmu_type: None,
+        timebase_frequency: default_timebase_frequency,
+        status: None,
+        node,
+    };
+
+    for property in node.properties() {
+        match property.name {
+            "riscv,isa" => cpu_info.isa = property.get_property_data_as_str(),
+            "mmu-type" => cpu_info.mmu_type = property.get_property_data_as_str(),
+            "status" => cpu_info.status = property.get_property_data_as_str(),
+            "timebase-frequency" => {
+                cpu_info.timebase_frequency = property.get_property_data_as_u64()
+            }
+            _ => {}
+        }
+    }
+
+    Some(cpu_info)
+}
@@ -0,0 +1,71 @@
+use core::fmt;
+
+/// The blocks that make up a Device Tree Blob after its header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DtbBlock {
+    MemoryReservation,
+    Structure,
+    Strings,
+}
+
+/// The reasons `Dtb::parse` can reject a Device Tree Blob.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DtbError {
+    /// The address is null or not aligned to 8 bytes as the specification
+    /// requires.
+    InvalidAddress(usize),
+
+    /// The header does not start with 0xd00dfeed. Holds the value found.
+    BadMagic(u32),
+
+    /// The blob is not compatible with version 17 of the format.
+    UnsupportedVersion {
+        version: u32,
+        last_compatible_version: u32,
+    },
+
+    /// The total size is too small to hold the header. Holds the total size.
+    InvalidTotalSize(u32),
+
+    /// A block starts inside the header or extends past the end of the blob.
+    BlockOutOfBounds(DtbBlock),
+
+    /// A block is not aligned as the specification requires.
+    BlockMisaligned(DtbBlock),
+
+    /// The structure block does not end with an FDT_END token.
+    MissingEndToken,
+}
+
+impl fmt::Display for DtbBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MemoryReservation => write!(f, "memory reservation block"),
+            Self::Structure => write!(f, "structure block"),
+            Self::Strings => write!(f, "strings block"),
+        }
+    }
+}
+
+impl fmt::Display for DtbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress(address) => write!(f, "invalid address {:#x}", address),
+            Self::BadMagic(magic) => write!(f, "bad magic {:#x}", magic),
+            Self::UnsupportedVersion {
+                version,
+                last_compatible_version,
+            } => write!(
+                f,
+                "unsupported version {} (compatible with {})",
+                version, last_compatible_version
+            ),
+            Self::InvalidTotalSize(total_size) => {
+                write!(f, "invalid total size {:#x}", total_size)
+            }
+            Self::BlockOutOfBounds(block) => write!(f, "{} out of bounds", block),
+            Self::BlockMisaligned(block) => write!(f, "{} misaligned", block),
+            Self::MissingEndToken => write!(f, "structure block missing FDT_END"),
+        }
+    }
+}
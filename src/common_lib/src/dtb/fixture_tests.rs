@@ -0,0 +1,385 @@
+//! Tests of the parser against complete Device Tree Blobs in `fixtures/`.
+//!
+//! The blobs built with `DtbBuilder` cover one feature each, while the
+//! fixtures are whole trees laid out the way libfdt lays them out:
+//!
+//! * `qemu-virt.dtb` - The tree of QEMU's virt machine with two harts and
+//!   128MiB of memory, as OpenSBI hands it to the boot stage: with the
+//!   reserved-memory node for the firmware and a /chosen node with
+//!   "bootargs" and "rng-seed". A fresh tree can be dumped with
+//!   `qemu-system-riscv64 -machine virt,dumpdtb=qemu-virt.dtb -smp 2 -m 128M`,
+//!   which lacks the OpenSBI additions.
+//! * `nested-ranges.dtb` - A bus that moves its children, with a nested bus,
+//!   a bus with an empty "ranges", and a bus without one, plus memory
+//!   reservation entries.
+//! * `truncated.dtb` - The first half of `qemu-virt.dtb`.
+//! * `bad-magic.dtb` - `qemu-virt.dtb` with the last bit of its magic set.
+//! * `missing-end-token.dtb` - `qemu-virt.dtb` with its FDT_END token
+//!   replaced by FDT_NOP.
+//! * `strings-out-of-bounds.dtb` - `qemu-virt.dtb` with a strings block size
+//!   that reaches past the end of the blob.
+
+use super::*;
+use std::{cell::RefCell, vec, vec::Vec};
+
+const QEMU_VIRT: &[u8] = include_bytes!("fixtures/qemu-virt.dtb");
+const NESTED_RANGES: &[u8] = include_bytes!("fixtures/nested-ranges.dtb");
+const TRUNCATED: &[u8] = include_bytes!("fixtures/truncated.dtb");
+const BAD_MAGIC: &[u8] = include_bytes!("fixtures/bad-magic.dtb");
+const MISSING_END_TOKEN: &[u8] = include_bytes!("fixtures/missing-end-token.dtb");
+const STRINGS_OUT_OF_BOUNDS: &[u8] = include_bytes!("fixtures/strings-out-of-bounds.dtb");
+
+/// Collects the address ranges of a node's "reg" property, translated to
+/// physical addresses where the buses allow it.
+fn translated_regs(node: &DtbNode) -> Vec<(Option<u64>, u64)> {
+    let regs = RefCell::new(Vec::new());
+
+    node.property("reg").unwrap().get_property_data_as_reg(
+        &node.parent_cell_info(),
+        |address, size| {
+            regs.borrow_mut()
+                .push((node.translate_address(address), size));
+        },
+    );
+
+    regs.into_inner()
+}
+
+/// Walks everything reachable from a blob that parses, which must not panic
+/// however the blob is damaged.
+fn walk_everything(dtb: &Dtb) {
+    let phandle_index = PhandleIndex::new(dtb);
+
+    for node in dtb.nodes() {
+        for property in node.properties() {
+            property.get_property_data_as_reg(&node.parent_cell_info(), |address, _| {
+                let _ = node.translate_address(address);
+            });
+            let _ = property.get_property_data_as_str_list().count();
+        }
+
+        let _ = node.children().count();
+        let _ = node.interrupts(&phandle_index).count();
+    }
+
+    let _ = chosen(dtb).map(|chosen| chosen.stdout_node(dtb));
+    for cpu in cpus(dtb) {
+        let _ = cpu.isa_features();
+    }
+
+    walk_memory_reservation_entries(dtb, |_| {});
+    walk_structure_block(dtb, |_, _| {}, |_, _, _, _| {});
+}
+
+#[test]
+fn test_qemu_virt_header() {
+    let dtb = Dtb::parse(QEMU_VIRT).unwrap();
+    let header = dtb.header();
+
+    assert_eq!(dtb.total_size(), QEMU_VIRT.len());
+    assert_eq!(header.version, FDT_VERSION);
+    assert_eq!(header.last_compatible_version, 16);
+    assert_eq!(header.boot_physical_cpuid, 0);
+}
+
+#[test]
+fn test_qemu_virt_node_iteration() {
+    let dtb = Dtb::parse(QEMU_VIRT).unwrap();
+
+    let top_level_nodes: Vec<_> = dtb
+        .root_node()
+        .unwrap()
+        .children()
+        .map(|node| node.name)
+        .collect();
+
+    assert_eq!(
+        top_level_nodes,
+        vec![
+            "poweroff",
+            "reboot",
+            "platform-bus@4000000",
+            "reserved-memory",
+            "memory@80000000",
+            "cpus",
+            "fw-cfg@10100000",
+            "flash@20000000",
+            "chosen",
+            "soc",
+        ]
+    );
+
+    assert_eq!(dtb.nodes().count(), 35);
+    assert_eq!(dtb.compatible_nodes("virtio,mmio").count(), 8);
+    assert!(dtb.nodes().all(|node| node.depth() <= 4));
+
+    let core1 = dtb.find_node("/cpus/cpu-map/cluster0/core1").unwrap();
+    assert_eq!(core1.depth(), 4);
+    assert_eq!(core1.parent().unwrap().name, "cluster0");
+}
+
+#[test]
+fn test_qemu_virt_reg_parsing() {
+    let dtb = Dtb::parse(QEMU_VIRT).unwrap();
+
+    let memory = dtb.find_node("/memory@80000000").unwrap();
+    assert_eq!(memory.first_reg(), Some((0x8000_0000, 0x800_0000)));
+
+    let flash = dtb.find_node("/flash@20000000").unwrap();
+    assert_eq!(
+        translated_regs(&flash),
+        vec![
+            (Some(0x2000_0000), 0x200_0000),
+            (Some(0x2200_0000), 0x200_0000)
+        ]
+    );
+
+    // The soc bus has an empty "ranges", so its devices sit at their bus
+    // addresses.
+    let uart = dtb.compatible_nodes("ns16550a").next().unwrap();
+    assert_eq!(translated_regs(&uart), vec![(Some(0x1000_0000), 0x100)]);
+
+    let test_device = dtb.compatible_nodes("sifive,test0").next().unwrap();
+    assert_eq!(test_device.name, "test@100000");
+    assert_eq!(
+        translated_regs(&test_device),
+        vec![(Some(0x10_0000), 0x1000)]
+    );
+
+    let plic = dtb.compatible_nodes("riscv,plic0").next().unwrap();
+    assert_eq!(plic.first_reg(), Some((0xc00_0000, 0x60_0000)));
+    assert_eq!(plic.cell_info().address_cells, 0);
+
+    // The PCI host bridge uses three address cells for its children.
+    let pci = dtb.find_node("/soc/pci@30000000").unwrap();
+    assert_eq!(pci.cell_info().address_cells, 3);
+    assert_eq!(pci.property("ranges").unwrap().data.len(), 3 * 7 * 4);
+}
+
+#[test]
+fn test_qemu_virt_reserved_memory() {
+    let dtb = Dtb::parse(QEMU_VIRT).unwrap();
+
+    // OpenSBI describes its own memory with reserved-memory nodes rather than
+    // memory reservation entries.
+    let entries = RefCell::new(Vec::new());
+    walk_memory_reservation_entries(&dtb, |entry| {
+        entries.borrow_mut().push((entry.address, entry.size));
+    });
+    assert!(entries.into_inner().is_empty());
+
+    let reserved_memory = dtb.root_node().unwrap().child("reserved-memory").unwrap();
+    assert_eq!(reserved_memory.cell_info().address_cells, 2);
+    assert_eq!(reserved_memory.cell_info().size_cells, 2);
+
+    let regions: Vec<_> = reserved_memory
+        .children()
+        .map(|node| {
+            (
+                node.name,
+                node.first_reg().unwrap(),
+                node.property("no-map").is_some(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        regions,
+        vec![
+            ("mmode_resv1@80000000", (0x8000_0000, 0x4_0000), true),
+            ("mmode_resv0@80040000", (0x8004_0000, 0x2_0000), true),
+        ]
+    );
+}
+
+#[test]
+fn test_qemu_virt_chosen() {
+    let dtb = Dtb::parse(QEMU_VIRT).unwrap();
+    let chosen = chosen(&dtb).unwrap();
+
+    assert_eq!(chosen.bootargs, Some("console=ttyS0 nokaslr"));
+    assert_eq!(chosen.stdout_path, Some("/soc/serial@10000000"));
+    assert_eq!(chosen.stdout_node(&dtb).unwrap().name, "serial@10000000");
+    assert_eq!(chosen.rng_seed.map(|seed| seed.len()), Some(32));
+    assert_eq!(chosen.initrd_range(), None);
+}
+
+#[test]
+fn test_qemu_virt_cpus() {
+    let dtb = Dtb::parse(QEMU_VIRT).unwrap();
+    let cpus: Vec<_> = cpus(&dtb).collect();
+
+    // The cpu-map node is not a hart.
+    assert_eq!(cpus.len(), 2);
+
+    for (hart_id, cpu) in cpus.iter().enumerate() {
+        assert_eq!(cpu.hart_id, hart_id as u64);
+        assert!(cpu.is_enabled());
+        assert_eq!(cpu.mmu_type, Some("riscv,sv57"));
+        assert_eq!(cpu.timebase_frequency, Some(10_000_000));
+
+        let features = cpu.isa_features();
+        assert!(features.contains(IsaFeatures::I | IsaFeatures::M | IsaFeatures::H));
+        assert!(features.contains(IsaFeatures::ZICBOM | IsaFeatures::SSTC));
+        assert!(!features.contains(IsaFeatures::V));
+        assert!(!features.contains(IsaFeatures::SVPBMT));
+    }
+}
+
+#[test]
+fn test_qemu_virt_interrupts() {
+    let dtb = Dtb::parse(QEMU_VIRT).unwrap();
+    let phandle_index = PhandleIndex::new(&dtb);
+
+    let uart = dtb.find_node("/soc/serial@10000000").unwrap();
+    let interrupts: Vec<_> = uart.interrupts(&phandle_index).collect();
+
+    assert_eq!(interrupts.len(), 1);
+    assert_eq!(interrupts[0].controller.name, "plic@c000000");
+    assert_eq!(interrupts[0].specifier.irq(), Some(10));
+
+    // Each PLIC context is wired to a hart's local interrupt controller:
+    // machine external (11) and supervisor external (9) for both harts.
+    let plic = dtb.find_node("/soc/plic@c000000").unwrap();
+    let contexts: Vec<_> = plic
+        .interrupts(&phandle_index)
+        .map(|interrupt| {
+            (
+                interrupt.controller.parent().unwrap().name,
+                interrupt.specifier.irq(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        contexts,
+        vec![
+            ("cpu@0", Some(11)),
+            ("cpu@0", Some(9)),
+            ("cpu@1", Some(11)),
+            ("cpu@1", Some(9)),
+        ]
+    );
+
+    // Every virtio-mmio window has an interrupt of its own.
+    let mut virtio_irqs: Vec<_> = dtb
+        .compatible_nodes("virtio,mmio")
+        .flat_map(|node| node.interrupts(&phandle_index).collect::<Vec<_>>())
+        .filter_map(|interrupt| interrupt.specifier.irq())
+        .collect();
+    virtio_irqs.sort_unstable();
+
+    assert_eq!(virtio_irqs, (1..=8).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_nested_ranges_translation() {
+    let dtb = Dtb::parse(NESTED_RANGES).unwrap();
+
+    // The second "reg" entry falls in the bus's second range.
+    let device = dtb.compatible_nodes("test,device").next().unwrap();
+    assert_eq!(
+        translated_regs(&device),
+        vec![(Some(0xf000_1000), 0x100), (Some(0x1_0000_0040), 0x40)]
+    );
+
+    let nested_device = dtb.compatible_nodes("test,nested-device").next().unwrap();
+    assert_eq!(
+        translated_regs(&nested_device),
+        vec![(Some(0xf008_0020), 0x10)]
+    );
+
+    let identity_device = dtb.compatible_nodes("test,identity-device").next().unwrap();
+    assert_eq!(
+        translated_regs(&identity_device),
+        vec![(Some(0xf00c_0010), 0x10)]
+    );
+
+    // A bus without "ranges" does not map its children into its parent.
+    let isolated_device = dtb.compatible_nodes("test,isolated-device").next().unwrap();
+    assert_eq!(translated_regs(&isolated_device), vec![(None, 0x8)]);
+}
+
+#[test]
+fn test_nested_ranges_memory() {
+    let dtb = Dtb::parse(NESTED_RANGES).unwrap();
+
+    // The root has two address cells and one size cell.
+    let memory = dtb.find_node("/memory@40000000").unwrap();
+    assert_eq!(
+        translated_regs(&memory),
+        vec![
+            (Some(0x4000_0000), 0x1000_0000),
+            (Some(0x1_0000_0000), 0x1000_0000)
+        ]
+    );
+
+    let entries = RefCell::new(Vec::new());
+    walk_memory_reservation_entries(&dtb, |entry| {
+        entries.borrow_mut().push((entry.address, entry.size));
+    });
+
+    assert_eq!(
+        entries.into_inner(),
+        vec![(0x4000_0000, 0x1_0000), (0x4ff0_0000, 0x10_0000)]
+    );
+}
+
+#[test]
+fn test_malformed_fixtures() {
+    let parse = |blob| Dtb::parse(blob).map(|_| ());
+
+    assert_eq!(
+        parse(TRUNCATED),
+        Err(DtbError::InvalidTotalSize(QEMU_VIRT.len() as u32))
+    );
+    assert_eq!(parse(BAD_MAGIC), Err(DtbError::BadMagic(0xd00d_feee)));
+    assert_eq!(parse(MISSING_END_TOKEN), Err(DtbError::MissingEndToken));
+    assert_eq!(
+        parse(STRINGS_OUT_OF_BOUNDS),
+        Err(DtbError::BlockOutOfBounds(DtbBlock::Strings))
+    );
+}
+
+#[test]
+fn test_every_truncation_fails_gracefully() {
+    // Every prefix is rejected, since the header claims the whole blob.
+    for length in 0..QEMU_VIRT.len() {
+        assert!(Dtb::parse(&QEMU_VIRT[..length]).is_err());
+    }
+
+    // A prefix whose header is patched so that every block ends within it
+    // may parse, such as one that cuts the strings block short, and must
+    // then be walked without panicking.
+    let mut parsed_count = 0;
+
+    for length in DtbHeader::SIZE..QEMU_VIRT.len() {
+        let mut truncated = QEMU_VIRT[..length].to_vec();
+        let header = DtbHeader::read(&truncated).unwrap();
+
+        let block_size_within =
+            |offset: u32, size: u32| size.min((length as u32).saturating_sub(offset));
+        let strings_block_size =
+            block_size_within(header.strings_block_offset, header.strings_block_size);
+        let structure_block_size =
+            block_size_within(header.structure_block_offset, header.structure_block_size);
+
+        truncated[4..8].copy_from_slice(&(length as u32).to_be_bytes());
+        truncated[32..36].copy_from_slice(&strings_block_size.to_be_bytes());
+        truncated[36..40].copy_from_slice(&structure_block_size.to_be_bytes());
+
+        if let Ok(dtb) = Dtb::parse(&truncated) {
+            walk_everything(&dtb);
+            parsed_count += 1;
+        }
+    }
+
+    assert!(parsed_count > 0);
+}
+
+#[test]
+fn test_fixtures_walk_without_panicking() {
+    for blob in [QEMU_VIRT, NESTED_RANGES] {
+        walk_everything(&Dtb::parse(blob).unwrap());
+    }
+}
@@ -0,0 +1,334 @@
+use super::{DtbNode, PhandleIndex};
+
+/// The largest "#interrupt-cells" value an `InterruptSpecifier` can hold.
+/// Interrupt controllers on RISC-V use one or two cells.
+pub const MAX_INTERRUPT_CELLS: usize = 4;
+
+/// The cells that identify an interrupt to its interrupt controller.
+///
+/// The meaning of the cells is defined by the controller. For the PLIC and the
+/// per-hart interrupt controllers the single cell is the interrupt number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptSpecifier {
+    cells: [u32; MAX_INTERRUPT_CELLS],
+    cell_count: usize,
+}
+
+impl InterruptSpecifier {
+    /// Returns the cells of the specifier.
+    pub fn cells(&self) -> &[u32] {
+        &self.cells[..self.cell_count]
+    }
+
+    /// Returns the interrupt number, which is the first cell for every
+    /// controller the kernel supports.
+    ///
+    /// # Returns
+    ///
+    /// The first cell, or `None` if the controller uses zero cells.
+    pub fn irq(&self) -> Option<u32> {
+        self.cells().first().copied()
+    }
+}
+
+/// An interrupt a device generates, as described by its "interrupts" or
+/// "interrupts-extended" property.
+#[derive(Debug, Clone, Copy)]
+pub struct DtbInterrupt<'a> {
+    /// The interrupt controller the interrupt is wired to.
+    pub controller: DtbNode<'a>,
+
+    /// The specifier, sized by the controller's "#interrupt-cells".
+    pub specifier: InterruptSpecifier,
+}
+
+impl<'a> DtbNode<'a> {
+    /// Returns the value of this node's "#interrupt-cells" property, which
+    /// gives the size of the specifiers of interrupts wired to it.
+    pub fn interrupt_cells(&self) -> Option<u32> {
+        self.property("#interrupt-cells")
+            .filter(|property| property.data.len() == 4)
+            .map(|property| property.get_property_data_as_u32())
+    }
+
+    /// Finds the interrupt controller that this node's "interrupts" property
+    /// refers to.
+    ///
+    /// The "interrupt-parent" property is inherited, so the closest of this
+    /// node and its ancestors that has one decides the controller.
+    ///
+    /// # Parameters
+    ///
+    /// * `phandle_index` - The phandle index of the DTB this node belongs to.
+    ///
+    /// # Returns
+    ///
+    /// The interrupt controller, or `None` if no ancestor names one or the
+    /// phandle does not resolve.
+    pub fn interrupt_parent(&self, phandle_index: &PhandleIndex<'a>) -> Option<DtbNode<'a>> {
+        let mut current_node = *self;
+
+        loop {
+            if let Some(property) = current_node.property("interrupt-parent") {
+                return phandle_index.resolve_phandle(property.get_property_data_as_u32());
+            }
+
+            current_node = current_node.parent()?;
+        }
+    }
+
+    /// Returns an iterator over the interrupts this node generates.
+    ///
+    /// The "interrupts-extended" property is used if present, since it names
+    /// the controller of every interrupt. Otherwise the "interrupts" property
+    /// is decoded against the node's interrupt parent.
+    ///
+    /// # Parameters
+    ///
+    /// * `phandle_index` - The phandle index of the DTB this node belongs to.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the interrupts in property order. It is empty if the
+    /// node has neither property, and ends early at a controller without a
+    /// usable "#interrupt-cells" or at a truncated specifier.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let phandle_index = PhandleIndex::new(&dtb);
+    ///
+    /// for interrupt in uart_node.interrupts(&phandle_index) {
+    ///     plic.enable(interrupt.specifier.irq().unwrap());
+    /// }
+    /// ```
+    pub fn interrupts<'b>(&self, phandle_index: &'b PhandleIndex<'a>) -> DtbInterruptIter<'a, 'b> {
+        if let Some(property) = self.property("interrupts-extended") {
+            return DtbInterruptIter {
+                data: property.data,
+                phandle_index,
+                controller: None,
+            };
+        }
+
+        let data = self
+            .property("interrupts")
+            .map_or(&[][..], |property| property.data);
+
+        // Without an interrupt parent the specifiers cannot be sized, so the
+        // iterator is left empty.
+        let controller = self.interrupt_parent(phandle_index);
+
+        DtbInterruptIter {
+            data: if controller.is_some() { data } else { &[] },
+            phandle_index,
+            controller,
+        }
+    }
+}
+
+/// Iterator over the interrupts of a node.
+///
+/// Created by `DtbNode::interrupts`.
+pub struct DtbInterruptIter<'a, 'b> {
+    /// The cells that have not been decoded yet.
+    data: &'a [u8],
+
+    phandle_index: &'b PhandleIndex<'a>,
+
+    /// The controller of every interrupt when decoding "interrupts", or `None`
+    /// when decoding "interrupts-extended", where each specifier is preceded
+    /// by the phandle of its controller.
+    controller: Option<DtbNode<'a>>,
+}
+
+impl<'a> Iterator for DtbInterruptIter<'a, '_> {
+    type Item = DtbInterrupt<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let controller = match self.controller {
+            Some(controller) => controller,
+            None => {
+                let phandle = self.take_cell()?;
+                let Some(controller) = self.phandle_index.resolve_phandle(phandle) else {
+                    self.data = &[];
+                    return None;
+                };
+
+                controller
+            }
+        };
+
+        let cell_count = controller.interrupt_cells().unwrap_or(u32::MAX) as usize;
+        if cell_count > MAX_INTERRUPT_CELLS || self.data.len() < cell_count * 4 {
+            self.data = &[];
+            return None;
+        }
+
+        let mut specifier = InterruptSpecifier {
+            cells: [0; MAX_INTERRUPT_CELLS],
+            cell_count,
+        };
+
+        for cell in specifier.cells.iter_mut().take(cell_count) {
+            *cell = self.take_cell()?;
+        }
+
+        Some(DtbInterrupt {
+            controller,
+            specifier,
+        })
+    }
+}
+
+impl DtbInterruptIter<'_, '_> {
+    /// Removes and returns the next big-endian cell of the property data.
+    fn take_cell(&mut self) -> Option<u32> {
+        let (cell, remaining) = self.data.split_first_chunk::<4>()?;
+        self.data = remaining;
+
+        Some(u32::from_be_bytes(*cell))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Dtb;
+    use super::super::test_builder::DtbBuilder;
+    use super::*;
+    use std::{vec, vec::Vec};
+
+    /// Builds a tree with the interrupt topology of the QEMU virt machine: two
+    /// harts with local interrupt controllers, a PLIC wired to both, and a
+    /// UART that inherits the PLIC as its interrupt parent from "soc".
+    fn sample_blob() -> Vec<u8> {
+        let mut builder = DtbBuilder::new();
+        builder.begin_node("").begin_node("cpus");
+
+        for (cpu_name, phandle) in [("cpu@0", 1), ("cpu@1", 2)] {
+            builder
+                .begin_node(cpu_name)
+                .begin_node("interrupt-controller")
+                .property_u32("#interrupt-cells", 1)
+                .property("interrupt-controller", &[])
+                .property_u32("phandle", phandle)
+                .end_node()
+                .end_node();
+        }
+
+        builder
+            .end_node()
+            .begin_node("soc")
+            .property_u32("interrupt-parent", 3)
+            .begin_node("plic@c000000")
+            .property_u32("#interrupt-cells", 1)
+            .property("interrupt-controller", &[])
+            .property_cells("interrupts-extended", &[1, 11, 1, 9, 2, 11, 2, 9])
+            .property_u32("phandle", 3)
+            .end_node()
+            .begin_node("serial@10000000")
+            .property_u32("interrupts", 10)
+            .end_node()
+            .begin_node("rtc@101000")
+            .property_cells("interrupts", &[11, 12])
+            .end_node()
+            .begin_node("gpio@0")
+            .property_u32("interrupt-parent", 9)
+            .property_u32("interrupts", 4)
+            .end_node()
+            .end_node()
+            .end_node()
+            .build()
+    }
+
+    fn decode(blob: &[u8], path: &[&str]) -> Vec<(&'static str, Vec<u32>)> {
+        let dtb = Dtb::parse(blob).unwrap();
+        let phandle_index = PhandleIndex::new(&dtb);
+
+        let node = path
+            .iter()
+            .try_fold(dtb.root_node().unwrap(), |node, name| node.child(name))
+            .unwrap();
+
+        node.interrupts(&phandle_index)
+            .map(|interrupt| {
+                let controller_name = match interrupt.controller.phandle() {
+                    Some(1) => "hart0",
+                    Some(2) => "hart1",
+                    Some(3) => "plic",
+                    _ => "unknown",
+                };
+
+                (controller_name, interrupt.specifier.cells().to_vec())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_interrupts_with_inherited_parent() {
+        let blob = sample_blob();
+
+        assert_eq!(decode(&blob, &["soc", "serial"]), vec![("plic", vec![10])]);
+        assert_eq!(
+            decode(&blob, &["soc", "rtc"]),
+            vec![("plic", vec![11]), ("plic", vec![12])]
+        );
+    }
+
+    #[test]
+    fn test_interrupts_extended() {
+        let blob = sample_blob();
+
+        assert_eq!(
+            decode(&blob, &["soc", "plic"]),
+            vec![
+                ("hart0", vec![11]),
+                ("hart0", vec![9]),
+                ("hart1", vec![11]),
+                ("hart1", vec![9])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interrupts_unresolved() {
+        let blob = sample_blob();
+
+        // The phandle of the interrupt parent does not exist.
+        assert_eq!(decode(&blob, &["soc", "gpio"]), vec![]);
+
+        // Nodes without an interrupt property yield nothing.
+        assert_eq!(decode(&blob, &["cpus"]), vec![]);
+    }
+
+    #[test]
+    fn test_interrupt_parent_and_cells() {
+        let blob = sample_blob();
+        let dtb = Dtb::parse(&blob).unwrap();
+        let phandle_index = PhandleIndex::new(&dtb);
+        let root_node = dtb.root_node().unwrap();
+        let soc_node = root_node.child("soc").unwrap();
+        let serial_node = soc_node.child("serial").unwrap();
+
+        assert_eq!(serial_node.parent().unwrap().name, "soc");
+        assert_eq!(soc_node.parent().unwrap().name, "");
+        assert!(root_node.parent().is_none());
+
+        let plic_node = serial_node.interrupt_parent(&phandle_index).unwrap();
+        assert_eq!(plic_node.name, "plic@c000000");
+        assert_eq!(plic_node.interrupt_cells(), Some(1));
+        assert_eq!(
+            root_node
+                .interrupt_parent(&phandle_index)
+                .map(|node| node.name),
+            None
+        );
+
+        let interrupt = serial_node.interrupts(&phandle_index).next().unwrap();
+        assert_eq!(interrupt.specifier.irq(), Some(10));
+    }
+}
@@ -0,0 +1,379 @@
+use core::{
+    fmt,
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign},
+};
+
+/// A set of RISC-V ISA extensions.
+///
+/// Built from the "riscv,isa" or "riscv,isa-extensions" property of a cpu node
+/// so that the kernel can check for optional extensions at runtime. Extensions
+/// that the kernel has no use for are ignored while parsing.
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+#[repr(transparent)]
+pub struct IsaFeatures(u64);
+
+impl IsaFeatures {
+    pub const I: Self = Self(1 << 0);
+    pub const M: Self = Self(1 << 1);
+    pub const A: Self = Self(1 << 2);
+    pub const F: Self = Self(1 << 3);
+    pub const D: Self = Self(1 << 4);
+    pub const C: Self = Self(1 << 5);
+    pub const V: Self = Self(1 << 6);
+    pub const H: Self = Self(1 << 7);
+    pub const ZICSR: Self = Self(1 << 8);
+    pub const ZIFENCEI: Self = Self(1 << 9);
+    pub const ZICNTR: Self = Self(1 << 10);
+    pub const ZIHPM: Self = Self(1 << 11);
+    pub const ZIHINTPAUSE: Self = Self(1 << 12);
+    pub const ZICBOM: Self = Self(1 << 13);
+    pub const ZICBOP: Self = Self(1 << 14);
+    pub const ZICBOZ: Self = Self(1 << 15);
+    pub const ZBA: Self = Self(1 << 16);
+    pub const ZBB: Self = Self(1 << 17);
+    pub const ZBS: Self = Self(1 << 18);
+    pub const ZKR: Self = Self(1 << 19);
+    pub const ZAWRS: Self = Self(1 << 20);
+    pub const SSTC: Self = Self(1 << 21);
+    pub const SSCOFPMF: Self = Self(1 << 22);
+    pub const SVPBMT: Self = Self(1 << 23);
+    pub const SVNAPOT: Self = Self(1 << 24);
+    pub const SVINVAL: Self = Self(1 << 25);
+
+    /// The names of every known extension, in the order they are printed.
+    const NAMES: [(Self, &'static str); 26] = [
+        (Self::I, "i"),
+        (Self::M, "m"),
+        (Self::A, "a"),
+        (Self::F, "f"),
+        (Self::D, "d"),
+        (Self::C, "c"),
+        (Self::V, "v"),
+        (Self::H, "h"),
+        (Self::ZICSR, "zicsr"),
+        (Self::ZIFENCEI, "zifencei"),
+        (Self::ZICNTR, "zicntr"),
+        (Self::ZIHPM, "zihpm"),
+        (Self::ZIHINTPAUSE, "zihintpause"),
+        (Self::ZICBOM, "zicbom"),
+        (Self::ZICBOP, "zicbop"),
+        (Self::ZICBOZ, "zicboz"),
+        (Self::ZBA, "zba"),
+        (Self::ZBB, "zbb"),
+        (Self::ZBS, "zbs"),
+        (Self::ZKR, "zkr"),
+        (Self::ZAWRS, "zawrs"),
+        (Self::SSTC, "sstc"),
+        (Self::SSCOFPMF, "sscofpmf"),
+        (Self::SVPBMT, "svpbmt"),
+        (Self::SVNAPOT, "svnapot"),
+        (Self::SVINVAL, "svinval"),
+    ];
+
+    /// Returns a set with no extensions.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Returns the raw bit representation of the set.
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Creates a set from its raw bit representation. Unknown bits are kept.
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Returns true if the set has no extensions.
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if every extension in `other` is also in this set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Adds every extension in `other` to this set.
+    pub const fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Returns the extension with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A single letter or multi-letter extension name without a
+    ///   version, such as "m" or "zicboz". Case is ignored.
+    ///
+    /// # Returns
+    ///
+    /// The matching extension, or `None` if the kernel does not track it.
+    pub fn from_extension_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(_, known_name)| name.eq_ignore_ascii_case(known_name))
+            .map(|(feature, _)| *feature)
+    }
+
+    /// Parses a "riscv,isa" string such as "rv64imafdc_zicsr_zifencei_sstc".
+    ///
+    /// The string starts with "rv32" or "rv64" followed by single letter
+    /// extensions, where "g" stands for "imafd_zicsr_zifencei". Multi-letter
+    /// extensions start with 's', 'x', or 'z' and are separated by
+    /// underscores. Version numbers such as "2p1" are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `isa` - The ISA string.
+    ///
+    /// # Returns
+    ///
+    /// The known extensions in the string. A string without an "rv32" or
+    /// "rv64" prefix yields an empty set.
+    pub fn from_isa_string(isa: &str) -> Self {
+        let mut features = Self::empty();
+
+        let Some(extensions) =
+            strip_prefix_ignore_case(isa, "rv64").or_else(|| strip_prefix_ignore_case(isa, "rv32"))
+        else {
+            return features;
+        };
+
+        // Single letter extensions run until the first underscore or the first
+        // multi-letter extension.
+        let mut single_letter_end = extensions.len();
+
+        for (index, character) in extensions.char_indices() {
+            match character.to_ascii_lowercase() {
+                '_' | 's' | 'x' | 'z' => {
+                    single_letter_end = index;
+                    break;
+                }
+                'g' => features.insert(
+                    Self::I | Self::M | Self::A | Self::F | Self::D | Self::ZICSR | Self::ZIFENCEI,
+                ),
+                // Version numbers such as "2p1" follow the extension letter.
+                '0'..='9' | 'p' => {}
+                letter => {
+                    let mut name_buffer = [0; 4];
+                    let name = letter.encode_utf8(&mut name_buffer);
+
+                    if let Some(feature) = Self::from_extension_name(name) {
+                        features.insert(feature);
+                    }
+                }
+            }
+        }
+
+        let multi_letter_extensions = extensions[single_letter_end..]
+            .split('_')
+            .filter(|extension| !extension.is_empty())
+            .map(strip_version);
+
+        features | Self::from_isa_extensions(multi_letter_extensions)
+    }
+
+    /// Builds a set from a list of extension names, such as the strings in a
+    /// "riscv,isa-extensions" property.
+    ///
+    /// # Arguments
+    ///
+    /// * `extensions` - Extension names without versions. Unknown names are
+    ///   ignored.
+    pub fn from_isa_extensions<'a>(extensions: impl IntoIterator<Item = &'a str>) -> Self {
+        extensions
+            .into_iter()
+            .filter_map(Self::from_extension_name)
+            .fold(Self::empty(), |features, feature| features | feature)
+    }
+}
+
+impl BitOr for IsaFeatures {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for IsaFeatures {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitAnd for IsaFeatures {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+impl BitAndAssign for IsaFeatures {
+    fn bitand_assign(&mut self, other: Self) {
+        self.0 &= other.0;
+    }
+}
+
+impl fmt::Debug for IsaFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "IsaFeatures({})", self)
+    }
+}
+
+impl fmt::Display for IsaFeatures {
+    /// Writes the extension names separated by underscores, such as
+    /// "i_m_a_zicsr".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+
+        for (feature, name) in Self::NAMES.iter() {
+            if !self.contains(*feature) {
+                continue;
+            }
+
+            if !first {
+                write!(f, "_")?;
+            }
+
+            write!(f, "{}", name)?;
+            first = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Strips a prefix from a string, ignoring ASCII case.
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let candidate = text.get(..prefix.len())?;
+
+    if candidate.eq_ignore_ascii_case(prefix) {
+        Some(&text[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+/// Removes a trailing version such as "2" or "2p0" from an extension name.
+fn strip_version(extension: &str) -> &str {
+    let is_digit = |character: char| character.is_ascii_digit();
+
+    let trimmed = extension.trim_end_matches(is_digit);
+    if trimmed.len() == extension.len() {
+        return extension;
+    }
+
+    // A "p" between two numbers separates the major and minor versions.
+    if let Some(before_minor) = trimmed.strip_suffix(['p', 'P']) {
+        let before_major = before_minor.trim_end_matches(is_digit);
+
+        if before_major.len() < before_minor.len() {
+            return before_major;
+        }
+    }
+
+    trimmed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::format;
+
+    #[test]
+    fn test_from_isa_string_single_letters() {
+        let features = IsaFeatures::from_isa_string("rv64imafdc");
+
+        assert!(features.contains(
+            IsaFeatures::I | IsaFeatures::M | IsaFeatures::A | IsaFeatures::F | IsaFeatures::D
+        ));
+        assert!(features.contains(IsaFeatures::C));
+        assert!(!features.contains(IsaFeatures::V));
+        assert!(!features.contains(IsaFeatures::ZICSR));
+    }
+
+    #[test]
+    fn test_from_isa_string_g_expands() {
+        let features = IsaFeatures::from_isa_string("rv64gc");
+
+        assert!(features.contains(IsaFeatures::ZICSR | IsaFeatures::ZIFENCEI));
+        assert!(features.contains(IsaFeatures::D | IsaFeatures::C));
+    }
+
+    #[test]
+    fn test_from_isa_string_multi_letter() {
+        let features =
+            IsaFeatures::from_isa_string("rv64imafdch_zicsr_zifencei_zicboz_sstc_svpbmt_xfoo");
+
+        assert!(features.contains(IsaFeatures::H));
+        assert!(features.contains(IsaFeatures::ZICBOZ));
+        assert!(features.contains(IsaFeatures::SSTC));
+        assert!(features.contains(IsaFeatures::SVPBMT));
+        assert!(!features.contains(IsaFeatures::SVNAPOT));
+    }
+
+    #[test]
+    fn test_from_isa_string_multi_letter_without_underscore() {
+        let features = IsaFeatures::from_isa_string("rv64imaczicsr_zifencei");
+
+        assert!(features.contains(IsaFeatures::C | IsaFeatures::ZICSR | IsaFeatures::ZIFENCEI));
+    }
+
+    #[test]
+    fn test_from_isa_string_versions() {
+        let features = IsaFeatures::from_isa_string("RV64I2P1M2A2P1_ZICSR2P0_ZBA1_SSTC");
+
+        assert!(features.contains(IsaFeatures::I | IsaFeatures::M | IsaFeatures::A));
+        assert!(features.contains(IsaFeatures::ZICSR | IsaFeatures::ZBA | IsaFeatures::SSTC));
+        assert!(!features.contains(IsaFeatures::F));
+    }
+
+    #[test]
+    fn test_from_isa_string_invalid_prefix() {
+        assert!(IsaFeatures::from_isa_string("imafdc").is_empty());
+        assert!(IsaFeatures::from_isa_string("").is_empty());
+        assert!(IsaFeatures::from_isa_string("rv").is_empty());
+    }
+
+    #[test]
+    fn test_from_isa_extensions() {
+        let features = IsaFeatures::from_isa_extensions(["i", "m", "zicboz", "svnapot", "unknown"]);
+
+        assert_eq!(
+            features,
+            IsaFeatures::I | IsaFeatures::M | IsaFeatures::ZICBOZ | IsaFeatures::SVNAPOT
+        );
+    }
+
+    #[test]
+    fn test_intersection() {
+        let first = IsaFeatures::from_isa_string("rv64imac_sstc");
+        let second = IsaFeatures::from_isa_string("rv64imafdc");
+
+        assert_eq!(
+            first & second,
+            IsaFeatures::I | IsaFeatures::M | IsaFeatures::A | IsaFeatures::C
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let features = IsaFeatures::I | IsaFeatures::M | IsaFeatures::ZICSR | IsaFeatures::SSTC;
+
+        assert_eq!(format!("{}", features), "i_m_zicsr_sstc");
+        assert_eq!(format!("{}", IsaFeatures::empty()), "");
+    }
+
+    #[test]
+    fn test_strip_version() {
+        assert_eq!(strip_version("zicsr"), "zicsr");
+        assert_eq!(strip_version("zicsr2"), "zicsr");
+        assert_eq!(strip_version("zicsr2p0"), "zicsr");
+        assert_eq!(strip_version("zve32x"), "zve32x");
+        assert_eq!(strip_version("zvl128b"), "zvl128b");
+    }
+}
@@ -0,0 +1,1939 @@
+//! Device Tree Blob (DTB) parser module.
+//!
+//! This module provides functionality to parse and traverse a Devicetree Blob
+//! (DTB) in accordance with the Devicetree Specification without allocating
+//! onto the heap. It includes capabilities to:
+//! - Walk through memory reservation entries.
+//! - Traverse the structure block containing nodes and properties.
+//! - Parse individual nodes and properties.
+//! - Extract and interpret cell values (address/size).
+//!
+//! The parser works on a byte slice that holds the whole blob, so every read
+//! is bounds checked and the parser can be exercised on the host with fixture
+//! blobs. A `Dtb` is created from a slice with `Dtb::parse`, or from the
+//! address the firmware passed with the unsafe `Dtb::from_address`. Data that
+//! does not follow the format ends a traversal early rather than panicking.
+//!
+//! The structure block can be traversed either with the callback based
+//! `walk_structure_block` or with iterators. `Dtb::nodes` yields every node
+//! depth first, and `DtbNode::properties` and `DtbNode::children` yield the
+//! contents of a single node, so lookups can use `find`, `filter`, and `for`
+//! loops.
+
+mod chosen;
+mod cpus;
+mod dtb_error;
+#[cfg(test)]
+mod fixture_tests;
+mod interrupts;
+mod isa;
+mod phandle;
+#[cfg(test)]
+mod test_builder;
+
+pub use chosen::{Chosen, chosen};
+pub use cpus::{CpuInfo, cpus};
+pub use dtb_error::{DtbBlock, DtbError};
+pub use interrupts::{DtbInterrupt, DtbInterruptIter, InterruptSpecifier, MAX_INTERRUPT_CELLS};
+pub use isa::IsaFeatures;
+pub use phandle::{MAX_INDEXED_PHANDLES, PhandleIndex};
+
+//=============================================================================
+// Constants
+//=============================================================================
+
+/// FDT token indicating the beginning of a node.
+const FDT_BEGIN_NODE: u32 = 1;
+/// FDT token indicating the end of a node.
+const FDT_END_NODE: u32 = 2;
+/// FDT token indicating a property definition.
+const FDT_PROP: u32 = 3;
+/// FDT token used for padding.
+const FDT_NOP: u32 = 4;
+/// FDT token indicating the end of the structure block.
+const FDT_END: u32 = 9;
+
+/// The value of the magic field of every DTB header.
+const FDT_MAGIC: u32 = 0xd00dfeed;
+
+/// The version of the DTB format this parser implements.
+const FDT_VERSION: u32 = 17;
+
+/// The size in bytes of an entry in the memory reservation block.
+const MEMORY_RESERVATION_ENTRY_SIZE: usize = 2 * core::mem::size_of::<u64>();
+
+/// The deepest node nesting `DtbNodeIter` and `walk_structure_block` follow.
+/// Nodes nested deeper than this are skipped.
+const MAX_NODE_DEPTH: usize = 16;
+
+//=============================================================================
+// Data Structures
+//=============================================================================
+
+/// Header of a Device Tree Blob.
+///
+/// The header is stored big-endian at the start of the blob. The fields here
+/// have been converted to native endianness by `DtbHeader::read`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DtbHeader {
+    /// This field shall contain the value 0xd00dfeed.
+    pub magic: u32,
+
+    /// This field shall contain the total size in bytes of the devicetree data
+    /// structure, encompassing all sections: the header, memory reservation
+    /// block, structure block, strings block, and any free space gaps between
+    /// or after blocks.
+    pub total_size: u32,
+
+    /// This field shall contain the offset in bytes of the structure block from
+    /// the beginning of the header.
+    pub structure_block_offset: u32,
+
+    /// This field shall contain the offset in bytes of the strings block from
+    /// the beginning of the header.
+    pub strings_block_offset: u32,
+
+    /// This field shall contain the offset in bytes of the memory reservation
+    /// block from the beginning of the header.
+    pub memory_reservation_block_offset: u32,
+
+    /// This field shall contain the version of the devicetree data structure.
+    /// The version is 17 if using the structure as defined in this document.
+    pub version: u32,
+
+    /// This field shall contain the lowest version with which the current
+    /// version is backwards compatible. For version 17, this field shall
+    /// contain 16.
+    pub last_compatible_version: u32,
+
+    /// This field shall contain the physical ID of the system's boot CPU,
+    /// identical to the physical ID given in the reg property of that CPU node
+    /// within the devicetree.
+    pub boot_physical_cpuid: u32,
+
+    /// This field shall contain the length in bytes of the strings block
+    /// section of the devicetree blob.
+    pub strings_block_size: u32,
+
+    /// This field shall contain the length in bytes of the structure block
+    /// section of the devicetree blob.
+    pub structure_block_size: u32,
+}
+
+impl DtbHeader {
+    /// The size in bytes of the header at the start of every blob.
+    pub const SIZE: usize = 10 * core::mem::size_of::<u32>();
+
+    /// Reads the header from the start of a blob.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - The blob, or at least its first `DtbHeader::SIZE` bytes.
+    ///
+    /// # Returns
+    ///
+    /// The header, or `None` if `data` is too short to hold one. The fields
+    /// are not validated.
+    pub fn read(data: &[u8]) -> Option<Self> {
+        let field = |index: usize| read_u32(data, index * core::mem::size_of::<u32>());
+
+        Some(Self {
+            magic: field(0)?,
+            total_size: field(1)?,
+            structure_block_offset: field(2)?,
+            strings_block_offset: field(3)?,
+            memory_reservation_block_offset: field(4)?,
+            version: field(5)?,
+            last_compatible_version: field(6)?,
+            boot_physical_cpuid: field(7)?,
+            strings_block_size: field(8)?,
+            structure_block_size: field(9)?,
+        })
+    }
+}
+
+/// A Device Tree Blob whose header has been validated.
+///
+/// Created by `Dtb::parse` or `Dtb::from_address`. Every block offset and size
+/// in the header lies within the blob, and all reads are bounds checked
+/// against the blob. The handle is a borrowed slice, so it is cheap to copy
+/// and nodes and properties found through it borrow the blob rather than the
+/// handle.
+#[derive(Debug, Clone, Copy)]
+pub struct Dtb<'a> {
+    data: &'a [u8],
+}
+
+impl Dtb<'static> {
+    /// Validates the Device Tree Blob at an address.
+    ///
+    /// Only the magic and total size are read through the raw pointer. The
+    /// rest of the validation is done by `Dtb::parse` on the slice they
+    /// describe.
+    ///
+    /// # Parameters
+    ///
+    /// * `address` - The address of the DTB header, usually the value the
+    ///   firmware passed in a1.
+    ///
+    /// # Returns
+    ///
+    /// The validated DTB, or the first problem found.
+    ///
+    /// # Safety
+    ///
+    /// The first 8 bytes at `address` must be readable unless the address is
+    /// null or misaligned, which are rejected before anything is read. If the
+    /// magic matches, the whole `total_size` bytes the header claims must be
+    /// readable and must stay unchanged for the rest of the program.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let dtb = unsafe { Dtb::from_address(dtb_physical_address) }
+    ///     .unwrap_or_else(|error| panic!("Invalid DTB: {}.", error));
+    /// ```
+    pub unsafe fn from_address(address: usize) -> Result<Self, DtbError> {
+        if address == 0 || !address.is_multiple_of(8) {
+            return Err(DtbError::InvalidAddress(address));
+        }
+
+        // Read just enough of the header to learn how large the blob is.
+        let header_start = unsafe {
+            core::slice::from_raw_parts(address as *const u8, 2 * core::mem::size_of::<u32>())
+        };
+
+        let magic = read_u32(header_start, 0).unwrap_or(0);
+        if magic != FDT_MAGIC {
+            return Err(DtbError::BadMagic(magic));
+        }
+
+        let total_size = read_u32(header_start, core::mem::size_of::<u32>()).unwrap_or(0);
+        if (total_size as usize) < DtbHeader::SIZE {
+            return Err(DtbError::InvalidTotalSize(total_size));
+        }
+
+        let data =
+            unsafe { core::slice::from_raw_parts(address as *const u8, total_size as usize) };
+
+        Self::parse(data)
+    }
+}
+
+impl<'a> Dtb<'a> {
+    /// Validates a Device Tree Blob held in a byte slice.
+    ///
+    /// The magic, version, and total size are checked, as are the bounds and
+    /// alignment of each block, that the memory reservation block is
+    /// terminated, and that the structure block ends with an FDT_END token.
+    ///
+    /// # Parameters
+    ///
+    /// * `data` - The blob. It may be followed by unrelated bytes; only the
+    ///   `total_size` bytes the header claims are used.
+    ///
+    /// # Returns
+    ///
+    /// The validated DTB, or the first problem found.
+    pub fn parse(data: &'a [u8]) -> Result<Self, DtbError> {
+        let Some(header) = DtbHeader::read(data) else {
+            return Err(DtbError::InvalidTotalSize(data.len() as u32));
+        };
+
+        if header.magic != FDT_MAGIC {
+            return Err(DtbError::BadMagic(header.magic));
+        }
+
+        if header.version < FDT_VERSION || header.last_compatible_version > FDT_VERSION {
+            return Err(DtbError::UnsupportedVersion {
+                version: header.version,
+                last_compatible_version: header.last_compatible_version,
+            });
+        }
+
+        let total_size = header.total_size as usize;
+        if total_size < DtbHeader::SIZE || total_size > data.len() {
+            return Err(DtbError::InvalidTotalSize(header.total_size));
+        }
+
+        let dtb = Self {
+            data: &data[..total_size],
+        };
+
+        dtb.validate_block(
+            DtbBlock::MemoryReservation,
+            header.memory_reservation_block_offset,
+            // The block has no size field; it must at least hold its
+            // terminating entry.
+            MEMORY_RESERVATION_ENTRY_SIZE as u32,
+            8,
+        )?;
+        dtb.validate_block(
+            DtbBlock::Structure,
+            header.structure_block_offset,
+            header.structure_block_size,
+            4,
+        )?;
+        dtb.validate_block(
+            DtbBlock::Strings,
+            header.strings_block_offset,
+            header.strings_block_size,
+            1,
+        )?;
+
+        dtb.validate_memory_reservation_terminator()?;
+        dtb.validate_end_token()?;
+
+        Ok(dtb)
+    }
+
+    /// Returns the header of the blob.
+    pub fn header(&self) -> DtbHeader {
+        // Parsing checked that the blob holds a header.
+        DtbHeader::read(self.data).unwrap_or_default()
+    }
+
+    /// Returns the bytes of the whole blob.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the address of the first byte of the blob.
+    pub fn address(&self) -> usize {
+        self.data.as_ptr() as usize
+    }
+
+    /// Returns the size in bytes of the whole blob.
+    pub fn total_size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns the root node of the structure block.
+    ///
+    /// # Returns
+    ///
+    /// The root node, or `None` if the structure block does not start with a
+    /// node.
+    pub fn root_node(&self) -> Option<DtbNode<'a>> {
+        self.nodes().next()
+    }
+
+    /// Returns an iterator over every node in the structure block in depth
+    /// first order, starting with the root node.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let memory_nodes = dtb
+    ///     .nodes()
+    ///     .filter(|node| node.name == "memory" || node.name.starts_with("memory@"));
+    /// ```
+    pub fn nodes(&self) -> DtbNodeIter<'a> {
+        DtbNodeIter {
+            dtb: *self,
+            current_offset: self.header().structure_block_offset as usize,
+            depth: 0,
+            cell_info_stack: [CellInfo::default(); MAX_NODE_DEPTH + 1],
+            finished: false,
+        }
+    }
+
+    /// Finds a node by its full path or by an alias.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - Either a path from the root such as "/soc/serial@10000000",
+    ///   where components without a unit address match the first node of that
+    ///   name, or the name of a property of /aliases optionally followed by a
+    ///   path relative to the aliased node, such as "serial0".
+    ///
+    /// # Returns
+    ///
+    /// The node, or `None` if the path or alias does not resolve.
+    pub fn find_node(&self, path: &str) -> Option<DtbNode<'a>> {
+        let root_node = self.root_node()?;
+
+        let (start_node, relative_path) = match path.strip_prefix('/') {
+            Some(relative_path) => (root_node, relative_path),
+            None => {
+                let (alias, relative_path) = path.split_once('/').unwrap_or((path, ""));
+                let aliased_path = root_node
+                    .child("aliases")?
+                    .property(alias)?
+                    .get_property_data_as_str()?;
+
+                // An alias must name a full path, which also stops aliases
+                // from referring to each other.
+                if !aliased_path.starts_with('/') {
+                    return None;
+                }
+
+                (self.find_node(aliased_path)?, relative_path)
+            }
+        };
+
+        relative_path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .try_fold(start_node, |node, component| node.child(component))
+    }
+
+    /// Returns an iterator over every node whose "compatible" property lists
+    /// the given value, in depth first order.
+    ///
+    /// # Parameters
+    ///
+    /// * `compatible` - The compatible string a driver binds to, such as
+    ///   "ns16550a".
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let uart_node = dtb
+    ///     .compatible_nodes("ns16550a")
+    ///     .find(|node| node.is_enabled());
+    /// ```
+    pub fn compatible_nodes<'b>(
+        &self,
+        compatible: &'b str,
+    ) -> impl Iterator<Item = DtbNode<'a>> + use<'a, 'b> {
+        self.nodes()
+            .filter(move |node| node.is_compatible(compatible))
+    }
+
+    /// Reads the big-endian u32 at an offset from the start of the blob.
+    fn read_u32(&self, offset: usize) -> Option<u32> {
+        read_u32(self.data, offset)
+    }
+
+    /// Reads a null-terminated string at an offset from the start of the blob,
+    /// such as a node name.
+    fn read_string(&self, offset: usize) -> Option<&'a str> {
+        read_null_terminated_string(self.data, offset)
+    }
+
+    /// Reads a property name from the strings block.
+    ///
+    /// # Parameters
+    ///
+    /// * `name_offset` - The offset of the name from the start of the strings
+    ///   block.
+    fn read_property_name(&self, name_offset: u32) -> Option<&'a str> {
+        let header = self.header();
+
+        let strings_block_start = header.strings_block_offset as usize;
+        let strings_block_end = strings_block_start + header.strings_block_size as usize;
+        let strings_block = self.data.get(strings_block_start..strings_block_end)?;
+
+        read_null_terminated_string(strings_block, name_offset as usize)
+    }
+
+    /// Checks that a block lies after the header, within the blob, and at the
+    /// required alignment.
+    fn validate_block(
+        &self,
+        block: DtbBlock,
+        offset: u32,
+        size: u32,
+        alignment: usize,
+    ) -> Result<(), DtbError> {
+        let offset = offset as usize;
+        let size = size as usize;
+
+        if offset < DtbHeader::SIZE || offset + size > self.total_size() {
+            return Err(DtbError::BlockOutOfBounds(block));
+        }
+
+        if !offset.is_multiple_of(alignment) {
+            return Err(DtbError::BlockMisaligned(block));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the memory reservation block reaches its all zero
+    /// terminating entry before the end of the blob.
+    fn validate_memory_reservation_terminator(&self) -> Result<(), DtbError> {
+        let mut entry_offset = self.header().memory_reservation_block_offset as usize;
+
+        while let Some(entry) = read_memory_reservation_entry(self.data, entry_offset) {
+            if entry.address == 0 && entry.size == 0 {
+                return Ok(());
+            }
+
+            entry_offset += MEMORY_RESERVATION_ENTRY_SIZE;
+        }
+
+        Err(DtbError::BlockOutOfBounds(DtbBlock::MemoryReservation))
+    }
+
+    /// Checks that the last token of the structure block is FDT_END.
+    fn validate_end_token(&self) -> Result<(), DtbError> {
+        let header = self.header();
+        let structure_block_size = header.structure_block_size as usize;
+
+        if structure_block_size < core::mem::size_of::<u32>()
+            || !structure_block_size.is_multiple_of(core::mem::size_of::<u32>())
+        {
+            return Err(DtbError::MissingEndToken);
+        }
+
+        let end_token_offset = header.structure_block_offset as usize + structure_block_size
+            - core::mem::size_of::<u32>();
+
+        if self.read_u32(end_token_offset) != Some(FDT_END) {
+            return Err(DtbError::MissingEndToken);
+        }
+
+        Ok(())
+    }
+}
+
+/// Represents an entry in the memory reservation block of a Device Tree Blob.
+#[derive(Debug, Clone, Copy)]
+pub struct DtbMemoryReservationEntry {
+    /// This field shall contain the address of the memory region.
+    pub address: u64,
+
+    /// This field shall contain the size of the memory region.
+    pub size: u64,
+}
+
+/// Represents a node in a Device Tree Blob.
+#[derive(Debug, Clone, Copy)]
+pub struct DtbNode<'a> {
+    /// Name of the node.
+    pub name: &'a str,
+
+    /// The DTB this node belongs to.
+    dtb: Dtb<'a>,
+
+    /// Offset from the start of the blob of the first token after the node
+    /// name.
+    properties_offset: usize,
+
+    /// Depth of the node in the tree. The root node has a depth of 0.
+    depth: usize,
+
+    /// Cell info of the parent node, which describes this node's "reg"
+    /// property.
+    parent_cell_info: CellInfo,
+}
+
+/// Represents property information from a Device Tree Blob.
+#[derive(Debug, Clone, Copy)]
+pub struct DtbProperty<'a> {
+    /// Name of the property.
+    pub name: &'a str,
+    /// The raw big-endian property data. Empty for boolean properties.
+    pub data: &'a [u8],
+}
+
+impl<'a> DtbProperty<'a> {
+    /// Parses the property data as a u32 value.
+    ///
+    /// This function reads the property data as a big-endian u32 value and
+    /// returns it as a native-endian u32 value. Data shorter than 4 bytes
+    /// reads as 0.
+    pub fn get_property_data_as_u32(&self) -> u32 {
+        read_u32(self.data, 0).unwrap_or(0)
+    }
+
+    /// Parses the property data as an integer stored in either one or two
+    /// cells.
+    ///
+    /// Properties such as "linux,initrd-start" may be written as a u32 or a
+    /// u64 depending on the bootloader, so the data length decides the width.
+    ///
+    /// # Returns
+    ///
+    /// The native-endian value, or `None` if the data is not 4 or 8 bytes
+    /// long.
+    pub fn get_property_data_as_u64(&self) -> Option<u64> {
+        match self.data.len() {
+            4 => read_u32(self.data, 0).map(u64::from),
+            8 => read_u64(self.data, 0),
+            _ => None,
+        }
+    }
+
+    /// Parses the property data as a null-terminated string.
+    ///
+    /// # Returns
+    ///
+    /// The string without its null terminator, or `None` if the data is empty,
+    /// is not null-terminated, or is not valid UTF-8. For string list
+    /// properties only the first string is returned.
+    pub fn get_property_data_as_str(&self) -> Option<&'a str> {
+        read_null_terminated_string(self.data, 0)
+    }
+
+    /// Parses the property data as a list of null-terminated strings, such as
+    /// the "compatible" property.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the strings in order. Strings that are not valid UTF-8
+    /// are skipped, as is anything after the last null terminator.
+    pub fn get_property_data_as_str_list(&self) -> impl Iterator<Item = &'a str> + use<'a> {
+        // Each string keeps its null terminator so that unterminated trailing
+        // data can be told apart and dropped.
+        self.data
+            .split_inclusive(|&byte| byte == 0)
+            .filter_map(|string_bytes| string_bytes.strip_suffix(&[0]))
+            .filter_map(|string_bytes| core::str::from_utf8(string_bytes).ok())
+    }
+
+    pub fn get_property_data_as_reg(
+        &self,
+        cells_info: &CellInfo,
+        mut address_range_callback: impl FnMut(u64, u64),
+    ) {
+        // Parse the property data as a series of address/size pairs according
+        // to the DTB spec for "reg" properties.
+        //
+        // Each entry consists of an address and size value, where the address
+        // is represented using `address_cells` 32-bit cells and the size using
+        // `size_cells` 32-bit cells. This method invokes the callback for each
+        // address/size pair found in the property data.
+        let address_bytes = cells_info.address_cells as usize * 4;
+        let size_bytes = cells_info.size_cells as usize * 4;
+        let entry_bytes = address_bytes + size_bytes;
+
+        // A node that uses no cells at all has no entries to report.
+        if entry_bytes == 0 {
+            return;
+        }
+
+        // Process each complete entry. Trailing bytes that do not form a whole
+        // entry are ignored.
+        for entry in self.data.chunks_exact(entry_bytes) {
+            let (address_cells, size_cells) = entry.split_at(address_bytes);
+
+            // Invoke the callback with this address/size pair.
+            address_range_callback(read_cells(address_cells), read_cells(size_cells));
+        }
+    }
+}
+
+/// Represents the address and size cells information for a node.
+#[derive(Debug, Clone, Copy)]
+pub struct CellInfo {
+    /// Number of 32-bit cells used to represent addresses in child nodes.
+    pub address_cells: u32,
+    /// Number of 32-bit cells used to represent sizes in child nodes.
+    pub size_cells: u32,
+}
+
+impl Default for CellInfo {
+    fn default() -> Self {
+        // Default values according to the DTB specification.
+        Self {
+            address_cells: 2,
+            size_cells: 1,
+        }
+    }
+}
+
+//=============================================================================
+// Core Traversal Functions
+//=============================================================================
+
+/// Traverses memory reservation entries in a Device Tree Blob.
+///
+/// Walks through all memory reservation entries in the DTB, calling the
+/// provided callback function for each entry until the terminating entry (with
+/// both address and size set to 0) is encountered.
+///
+/// # Parameters
+///
+/// * `dtb` - The Device Tree Blob.
+/// * `callback` - Function to call for each memory reservation entry.
+pub fn walk_memory_reservation_entries(dtb: &Dtb, callback: impl Fn(&DtbMemoryReservationEntry)) {
+    let mut entry_offset = dtb.header().memory_reservation_block_offset as usize;
+
+    while let Some(memory_reservation_entry) = read_memory_reservation_entry(dtb.data, entry_offset)
+    {
+        // The last entry in the list will have an address and size of 0.
+        if memory_reservation_entry.address == 0 && memory_reservation_entry.size == 0 {
+            break;
+        }
+
+        callback(&memory_reservation_entry);
+
+        entry_offset += MEMORY_RESERVATION_ENTRY_SIZE;
+    }
+}
+
+/// Traverses the structure block of a Device Tree Blob (DTB).
+///
+/// This function walks through the structure block in a DTB, which contains
+/// nodes and their properties arranged in a hierarchical tree structure. It
+/// processes FDT_BEGIN_NODE tokens to parse nodes and their children
+/// recursively, FDT_NOP tokens which are ignored, and stops when encountering
+/// an FDT_END token.
+///
+/// The function invokes the provided callbacks for each node and property
+/// encountered during traversal, allowing the caller to process the device tree
+/// information as needed in an allocation free way.
+///
+/// # Parameters
+///
+/// * `dtb` - The Device Tree Blob.
+/// * `node_callback` - Function to call with each node and its depth:
+///   - Node object containing the node's name.
+///   - Current node depth in the tree.
+/// * `property_callback` - Function to call with the parsed property details:
+///   - Node object containing the node's name.
+///   - Property object containing name and data.
+///   - Cell info for the current node (address_cells and size_cells).
+///   - Current node depth in the tree.
+///
+/// # Examples
+///
+/// ```ignore
+/// walk_structure_block(
+///     &dtb,
+///     |node, depth| println!("Node: {} at depth {}", node.name, depth),
+///     |node, property, cell_info, depth| println!("Property: {} at depth {}", property.name, depth)
+/// );
+/// ```
+pub fn walk_structure_block<'a>(
+    dtb: &Dtb<'a>,
+    mut node_callback: impl FnMut(&DtbNode<'a>, i32),
+    mut property_callback: impl FnMut(&DtbNode<'a>, &DtbProperty<'a>, &CellInfo, i32),
+) {
+    // Walk the structure block with default cell info for the root.
+    let mut current_offset = dtb.header().structure_block_offset as usize;
+    let default_cells_info = CellInfo::default();
+
+    // Reading past the end of the blob ends the walk like an FDT_END token.
+    while let Some(token) = dtb.read_u32(current_offset) {
+        current_offset += core::mem::size_of::<u32>();
+
+        match token {
+            FDT_BEGIN_NODE => {
+                // Parse this node and all its children.
+                current_offset = parse_node(
+                    dtb,
+                    current_offset,
+                    0,
+                    default_cells_info,
+                    &mut node_callback,
+                    &mut property_callback,
+                );
+            }
+            FDT_NOP => {
+                // Nothing to do for NOP tokens.
+            }
+            FDT_END => {
+                // End of the structure block.
+                break;
+            }
+            _ => {
+                // Unexpected token at the structure block root. Stop rather
+                // than interpret arbitrary data as tokens.
+                break;
+            }
+        }
+    }
+}
+
+//=============================================================================
+// Iterator API
+//=============================================================================
+
+impl<'a> DtbNode<'a> {
+    /// Creates a node from the offset of its name, which immediately follows
+    /// its FDT_BEGIN_NODE token.
+    ///
+    /// # Returns
+    ///
+    /// The node, or `None` if the name is not a null-terminated UTF-8 string
+    /// within the blob.
+    fn from_offset(
+        dtb: Dtb<'a>,
+        name_offset: usize,
+        depth: usize,
+        parent_cell_info: CellInfo,
+    ) -> Option<Self> {
+        let name = dtb.read_string(name_offset)?;
+
+        // Align to 4-byte boundary after the name. +1 for null terminator.
+        let properties_offset = (name_offset + name.len() + 1 + 3) & !3;
+
+        Some(Self {
+            name,
+            dtb,
+            properties_offset,
+            depth,
+            parent_cell_info,
+        })
+    }
+
+    /// Returns the depth of the node in the tree. The root node has a depth of
+    /// 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the cell info that describes this node's "reg" property, which
+    /// is defined by its parent.
+    pub fn parent_cell_info(&self) -> CellInfo {
+        self.parent_cell_info
+    }
+
+    /// Returns the cell info this node defines for its children.
+    ///
+    /// # Returns
+    ///
+    /// The values of this node's "#address-cells" and "#size-cells"
+    /// properties. A property that is missing keeps the value inherited from
+    /// the parent.
+    pub fn cell_info(&self) -> CellInfo {
+        let mut cell_info = self.parent_cell_info;
+
+        for property in self.properties() {
+            if property.name == "#address-cells" {
+                cell_info.address_cells = property.get_property_data_as_u32();
+            } else if property.name == "#size-cells" {
+                cell_info.size_cells = property.get_property_data_as_u32();
+            }
+        }
+
+        cell_info
+    }
+
+    /// Returns the phandle other nodes use to refer to this node.
+    ///
+    /// # Returns
+    ///
+    /// The value of the "phandle" property, or of the older "linux,phandle"
+    /// property if there is none, or `None` if the node has neither.
+    pub fn phandle(&self) -> Option<u32> {
+        self.property("phandle")
+            .or_else(|| self.property("linux,phandle"))
+            .filter(|property| property.data.len() == 4)
+            .map(|property| property.get_property_data_as_u32())
+    }
+
+    /// Returns true if the node's "compatible" property lists the given value.
+    ///
+    /// # Parameters
+    ///
+    /// * `compatible` - The compatible string to look for, such as
+    ///   "riscv,plic0".
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.property("compatible").is_some_and(|property| {
+            property
+                .get_property_data_as_str_list()
+                .any(|value| value == compatible)
+        })
+    }
+
+    /// Returns true if the device the node describes is usable. A node without
+    /// a "status" property, or with a status of "okay", is usable.
+    pub fn is_enabled(&self) -> bool {
+        match self.property("status") {
+            Some(property) => matches!(
+                property.get_property_data_as_str(),
+                Some("okay") | Some("ok")
+            ),
+            None => true,
+        }
+    }
+
+    /// Returns the first address range of the node's "reg" property.
+    ///
+    /// # Returns
+    ///
+    /// The address and size of the first entry, decoded with the parent's
+    /// cell info, or `None` if the node has no complete "reg" entry.
+    pub fn first_reg(&self) -> Option<(u64, u64)> {
+        let mut first_entry = None;
+
+        self.property("reg")?.get_property_data_as_reg(
+            &self.parent_cell_info(),
+            |address, size| {
+                first_entry.get_or_insert((address, size));
+            },
+        );
+
+        first_entry
+    }
+
+    /// Translates an address in this node's "reg" property to an address on
+    /// the root bus, which is a physical address, through the "ranges"
+    /// properties of the node's ancestors.
+    ///
+    /// An empty "ranges" property maps a bus one to one onto its parent's.
+    ///
+    /// # Parameters
+    ///
+    /// * `address` - The address, decoded with the parent's cell info.
+    ///
+    /// # Returns
+    ///
+    /// The translated address, or `None` if an ancestor other than the root
+    /// has no "ranges" property or none of its ranges covers the address.
+    pub fn translate_address(&self, mut address: u64) -> Option<u64> {
+        let mut bus = self.parent()?;
+
+        while bus.depth > 0 {
+            let ranges = bus.property("ranges")?;
+
+            if !ranges.data.is_empty() {
+                let child_address_bytes = bus.cell_info().address_cells as usize * 4;
+                let parent_address_bytes = bus.parent_cell_info().address_cells as usize * 4;
+                let size_bytes = bus.cell_info().size_cells as usize * 4;
+                let entry_bytes = child_address_bytes + parent_address_bytes + size_bytes;
+
+                if entry_bytes == 0 {
+                    return None;
+                }
+
+                address = ranges.data.chunks_exact(entry_bytes).find_map(|entry| {
+                    let (child_address, rest) = entry.split_at(child_address_bytes);
+                    let (parent_address, size) = rest.split_at(parent_address_bytes);
+
+                    let offset = address.checked_sub(read_cells(child_address))?;
+
+                    (offset < read_cells(size))
+                        .then(|| read_cells(parent_address).checked_add(offset))
+                        .flatten()
+                })?;
+            }
+
+            bus = bus.parent()?;
+        }
+
+        Some(address)
+    }
+
+    /// Returns an iterator over the properties of this node.
+    pub fn properties(&self) -> DtbPropertyIter<'a> {
+        DtbPropertyIter {
+            dtb: self.dtb,
+            current_offset: self.properties_offset,
+            finished: false,
+        }
+    }
+
+    /// Finds a property of this node by name.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - The name of the property to find.
+    pub fn property(&self, name: &str) -> Option<DtbProperty<'a>> {
+        self.properties().find(|property| property.name == name)
+    }
+
+    /// Returns an iterator over the direct children of this node.
+    pub fn children(&self) -> DtbChildIter<'a> {
+        DtbChildIter {
+            dtb: self.dtb,
+            current_offset: self.properties_offset,
+            depth: self.depth + 1,
+            cell_info: self.cell_info(),
+            finished: false,
+        }
+    }
+
+    /// Finds a direct child of this node by name.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - Either the full node name, such as "uart@10000000", or the
+    ///   name without a unit address, such as "uart". Without a unit address
+    ///   the first matching child is returned.
+    pub fn child(&self, name: &str) -> Option<DtbNode<'a>> {
+        self.children()
+            .find(|child| child.name == name || child.name.split('@').next() == Some(name))
+    }
+
+    /// Finds the parent of this node.
+    ///
+    /// Nodes do not record their parent, so the tree is walked from the root
+    /// and the last node seen one level up is returned. The cost is linear in
+    /// the number of nodes that precede this one.
+    ///
+    /// # Returns
+    ///
+    /// The parent node, or `None` for the root node.
+    pub fn parent(&self) -> Option<DtbNode<'a>> {
+        if self.depth == 0 {
+            return None;
+        }
+
+        let mut parent = None;
+
+        for node in self.dtb.nodes() {
+            if node.properties_offset == self.properties_offset {
+                return parent;
+            }
+
+            if node.depth == self.depth - 1 {
+                parent = Some(node);
+            }
+        }
+
+        None
+    }
+}
+
+/// Two nodes are equal when they are the same node of the same blob.
+impl PartialEq for DtbNode<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.dtb.address() == other.dtb.address()
+            && self.properties_offset == other.properties_offset
+    }
+}
+
+impl Eq for DtbNode<'_> {}
+
+/// Iterator over every node of a Device Tree Blob in depth first order.
+///
+/// Created by `Dtb::nodes`.
+pub struct DtbNodeIter<'a> {
+    dtb: Dtb<'a>,
+    current_offset: usize,
+
+    /// The depth of the next node to be found.
+    depth: usize,
+
+    /// The cell info defined by the current ancestor at each depth. Entry 0
+    /// holds the defaults that apply to the root node.
+    cell_info_stack: [CellInfo; MAX_NODE_DEPTH + 1],
+
+    finished: bool,
+}
+
+impl<'a> Iterator for DtbNodeIter<'a> {
+    type Item = DtbNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let Some(token) = self.dtb.read_u32(self.current_offset) else {
+                self.finished = true;
+                break;
+            };
+
+            match token {
+                FDT_BEGIN_NODE => {
+                    if self.depth >= MAX_NODE_DEPTH {
+                        self.finished = true;
+                        break;
+                    }
+
+                    let Some(node) = DtbNode::from_offset(
+                        self.dtb,
+                        self.current_offset + core::mem::size_of::<u32>(),
+                        self.depth,
+                        self.cell_info_stack[self.depth],
+                    ) else {
+                        self.finished = true;
+                        break;
+                    };
+
+                    // The children of this node are described by its own cell
+                    // info.
+                    self.cell_info_stack[self.depth + 1] = node.cell_info();
+                    self.depth += 1;
+                    self.current_offset = node.properties_offset;
+
+                    return Some(node);
+                }
+                FDT_END_NODE => {
+                    self.depth = self.depth.saturating_sub(1);
+                    self.current_offset += core::mem::size_of::<u32>();
+                }
+                FDT_PROP => match skip_property(&self.dtb, self.current_offset) {
+                    Some(next_offset) => self.current_offset = next_offset,
+                    None => self.finished = true,
+                },
+                FDT_NOP => {
+                    self.current_offset += core::mem::size_of::<u32>();
+                }
+                _ => {
+                    // FDT_END or an unexpected token.
+                    self.finished = true;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Iterator over the properties of a single node.
+///
+/// Created by `DtbNode::properties`.
+pub struct DtbPropertyIter<'a> {
+    dtb: Dtb<'a>,
+    current_offset: usize,
+    finished: bool,
+}
+
+impl<'a> Iterator for DtbPropertyIter<'a> {
+    type Item = DtbProperty<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let Some(token) = self.dtb.read_u32(self.current_offset) else {
+                self.finished = true;
+                break;
+            };
+
+            match token {
+                FDT_PROP => {
+                    let Some((property, next_offset)) = parse_property(
+                        &self.dtb,
+                        self.current_offset + core::mem::size_of::<u32>(),
+                    ) else {
+                        self.finished = true;
+                        break;
+                    };
+
+                    self.current_offset = next_offset;
+
+                    return Some(property);
+                }
+                FDT_NOP => {
+                    self.current_offset += core::mem::size_of::<u32>();
+                }
+                _ => {
+                    // Properties always precede child nodes, so any other
+                    // token ends the property list.
+                    self.finished = true;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Iterator over the direct children of a single node.
+///
+/// Created by `DtbNode::children`.
+pub struct DtbChildIter<'a> {
+    dtb: Dtb<'a>,
+    current_offset: usize,
+
+    /// The depth of the children.
+    depth: usize,
+
+    /// The cell info of the parent node.
+    cell_info: CellInfo,
+
+    finished: bool,
+}
+
+impl<'a> Iterator for DtbChildIter<'a> {
+    type Item = DtbNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let Some(token) = self.dtb.read_u32(self.current_offset) else {
+                self.finished = true;
+                break;
+            };
+
+            match token {
+                FDT_BEGIN_NODE => {
+                    let Some(child) = DtbNode::from_offset(
+                        self.dtb,
+                        self.current_offset + core::mem::size_of::<u32>(),
+                        self.depth,
+                        self.cell_info,
+                    ) else {
+                        self.finished = true;
+                        break;
+                    };
+
+                    // Continue after the child's entire subtree.
+                    self.current_offset = skip_node(&self.dtb, child.properties_offset);
+
+                    return Some(child);
+                }
+                FDT_PROP => match skip_property(&self.dtb, self.current_offset) {
+                    Some(next_offset) => self.current_offset = next_offset,
+                    None => self.finished = true,
+                },
+                FDT_NOP => {
+                    self.current_offset += core::mem::size_of::<u32>();
+                }
+                _ => {
+                    // FDT_END_NODE of the parent, FDT_END, or an unexpected
+                    // token.
+                    self.finished = true;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns the offset immediately after the property whose FDT_PROP token is
+/// at the given offset.
+///
+/// # Returns
+///
+/// The offset of the next token, or `None` if the property's data length is
+/// not within the blob.
+fn skip_property(dtb: &Dtb, token_offset: usize) -> Option<usize> {
+    let data_length = dtb.read_u32(token_offset + core::mem::size_of::<u32>())? as usize;
+
+    // Skip the token, the data length, the name offset, and the data, then
+    // align to a 4-byte boundary.
+    let data_end_offset = token_offset + 3 * core::mem::size_of::<u32>() + data_length;
+
+    Some((data_end_offset + 3) & !3)
+}
+
+/// Returns the offset immediately after the FDT_END_NODE token that closes a
+/// node.
+///
+/// # Parameters
+///
+/// * `properties_offset` - The offset of the first token after the node name.
+///
+/// # Returns
+///
+/// The offset after the node, or the end of the blob if the node is malformed
+/// so that callers end their iteration.
+fn skip_node(dtb: &Dtb, properties_offset: usize) -> usize {
+    let mut current_offset = properties_offset;
+    let mut open_node_count = 1;
+
+    loop {
+        let Some(token) = dtb.read_u32(current_offset) else {
+            return dtb.total_size();
+        };
+
+        match token {
+            FDT_BEGIN_NODE => {
+                // Skip the token and the nested node's name.
+                let name_offset = current_offset + core::mem::size_of::<u32>();
+                let Some(name) = dtb.read_string(name_offset) else {
+                    return dtb.total_size();
+                };
+
+                current_offset = (name_offset + name.len() + 1 + 3) & !3;
+                open_node_count += 1;
+            }
+            FDT_END_NODE => {
+                current_offset += core::mem::size_of::<u32>();
+                open_node_count -= 1;
+
+                if open_node_count == 0 {
+                    return current_offset;
+                }
+            }
+            FDT_PROP => {
+                let Some(next_offset) = skip_property(dtb, current_offset) else {
+                    return dtb.total_size();
+                };
+
+                current_offset = next_offset;
+            }
+            FDT_NOP => {
+                current_offset += core::mem::size_of::<u32>();
+            }
+            _ => {
+                // FDT_END or an unexpected token. Stop here so callers see the
+                // same token and end their iteration.
+                return current_offset;
+            }
+        }
+    }
+}
+
+//=============================================================================
+// Node and Property Parsing
+//=============================================================================
+
+/// Parses a node in the Device Tree Blob (DTB).
+///
+/// This function recursively processes a node in the device tree, including its
+/// name, properties, and child nodes. It calls the provided callbacks for each
+/// node and property encountered during traversal. Nodes nested deeper than
+/// `MAX_NODE_DEPTH` are skipped along with their children.
+///
+/// # Parameters
+///
+/// * `dtb` - The Device Tree Blob.
+/// * `current_offset` - Offset where the node data begins (points to node
+///   name).
+/// * `node_depth` - Current depth in the device tree hierarchy.
+/// * `parent_cells_info` - Address and size cells information from the parent node.
+/// * `node_callback` - Function to call with each node and its depth.
+///   - Node object containing the node's name.
+///   - Current node depth in the tree.
+/// * `property_callback` - Function to call with the parsed property details:
+///   - Node object containing the node's name.
+///   - Property object containing name and data.
+///   - Cell info for the current node (address_cells and size_cells).
+///   - Current node depth in the tree.
+///
+/// # Returns
+///
+/// The offset immediately after this node and all its children, aligned to a
+/// 4-byte boundary, or the end of the blob if the node is malformed.
+fn parse_node<'a>(
+    dtb: &Dtb<'a>,
+    mut current_offset: usize,
+    node_depth: i32,
+    parent_cells_info: CellInfo,
+    node_callback: &mut impl FnMut(&DtbNode<'a>, i32),
+    property_callback: &mut impl FnMut(&DtbNode<'a>, &DtbProperty<'a>, &CellInfo, i32),
+) -> usize {
+    // Create a DtbNode instance.
+    let Some(node) =
+        DtbNode::from_offset(*dtb, current_offset, node_depth as usize, parent_cells_info)
+    else {
+        return dtb.total_size();
+    };
+
+    // Bound the recursion so that a corrupt blob cannot exhaust the stack.
+    if node.depth >= MAX_NODE_DEPTH {
+        return skip_node(dtb, node.properties_offset);
+    }
+
+    // Initialize with parent's cell info, will be updated if this node has its
+    // own values.
+    let mut current_cells_info = parent_cells_info;
+
+    // Call the node callback.
+    node_callback(&node, node_depth);
+
+    // Continue after the node name.
+    current_offset = node.properties_offset;
+
+    loop {
+        let Some(token) = dtb.read_u32(current_offset) else {
+            return current_offset;
+        };
+
+        current_offset += core::mem::size_of::<u32>();
+
+        match token {
+            FDT_PROP => {
+                // We found a property - back up to the token and process all
+                // properties.
+                current_offset -= core::mem::size_of::<u32>();
+
+                // Perform a pre-pass to process special properties that affect
+                // cell info.
+                process_properties(
+                    dtb,
+                    current_offset,
+                    &node,
+                    current_cells_info,
+                    node_depth,
+                    |_, property, _, _| {
+                        if property.name == "#address-cells" {
+                            current_cells_info.address_cells = property.get_property_data_as_u32();
+                        } else if property.name == "#size-cells" {
+                            current_cells_info.size_cells = property.get_property_data_as_u32();
+                        }
+                    },
+                );
+
+                // Process all properties with updated cell info.
+                let next_offset = process_properties(
+                    dtb,
+                    current_offset,
+                    &node,
+                    current_cells_info,
+                    node_depth,
+                    |node, prop, cells, depth| property_callback(node, prop, cells, depth),
+                );
+
+                // Update offset.
+                current_offset = next_offset;
+            }
+            FDT_BEGIN_NODE => {
+                // Recursively parse a child node with current node's cells
+                // info.
+                current_offset = parse_node(
+                    dtb,
+                    current_offset,
+                    node_depth + 1,
+                    current_cells_info,
+                    node_callback,
+                    property_callback,
+                );
+            }
+            FDT_END_NODE => {
+                // End of current node.
+                return current_offset;
+            }
+            FDT_NOP => {
+                // Nothing to do for NOP tokens.
+            }
+            FDT_END => {
+                // End of entire tree - should not happen while node parsing.
+                return current_offset;
+            }
+            _ => {
+                // Unexpected token. Try to recover by returning current
+                // offset.
+                return current_offset;
+            }
+        }
+    }
+}
+
+/// Processes property tokens in a Device Tree Blob node.
+///
+/// This function sequentially processes FDT_PROP tokens found in a node,
+/// invoking the property callback for each property. It stops processing when
+/// it encounters any token that is not an FDT_PROP or FDT_NOP.
+///
+/// # Parameters
+///
+/// * `dtb` - The Device Tree Blob.
+/// * `current_offset` - Offset where property processing should begin.
+/// * `node` - Reference to the node containing these properties.
+/// * `current_cells_info` - Cell info for the current node.
+/// * `node_depth` - Current depth in the device tree hierarchy.
+/// * `property_callback` - Function to call for each property processed.
+///
+/// # Returns
+///
+/// The offset immediately after the last property token, pointing to the next
+/// non-property token in the device tree, or the end of the blob if a property
+/// is malformed.
+fn process_properties<'a>(
+    dtb: &Dtb<'a>,
+    mut current_offset: usize,
+    node: &DtbNode<'a>,
+    current_cells_info: CellInfo,
+    node_depth: i32,
+    mut property_callback: impl FnMut(&DtbNode<'a>, &DtbProperty<'a>, &CellInfo, i32),
+) -> usize {
+    // Process only property tokens and exit on any other token. The offset of
+    // that token is returned since parse_node expects to read the token.
+    while dtb.read_u32(current_offset) == Some(FDT_PROP) {
+        // Move past the token.
+        current_offset += core::mem::size_of::<u32>();
+
+        // Parse this property.
+        let Some((property, next_offset)) = parse_property(dtb, current_offset) else {
+            return dtb.total_size();
+        };
+
+        // Call the property callback.
+        property_callback(node, &property, &current_cells_info, node_depth);
+
+        // Update the current offset.
+        current_offset = next_offset;
+    }
+
+    current_offset
+}
+
+/// Parses a property node in the Device Tree Blob (DTB).
+///
+/// The FDT_PROP node structure in the DTB contains:
+/// - A 4-byte length value (big-endian) indicating property data size.
+/// - A 4-byte offset (big-endian) into the strings block for the property name.
+/// - The actual property data (of the specified length).
+/// - Padding to align to a 4-byte boundary.
+///
+/// This function extracts all information for the property and returns a
+/// DtbProperty structure containing the details.
+///
+/// # Parameters
+///
+/// * `dtb` - The Device Tree Blob.
+/// * `property_offset` - Offset where the property node data begins, just after
+///   its FDT_PROP token.
+///
+/// # Returns
+///
+/// A tuple containing:
+/// - The DtbProperty struct with property information.
+/// - The offset immediately after this property entry, aligned to a 4-byte
+///   boundary.
+///
+/// `None` is returned if the name or data lie outside of the blob.
+fn parse_property<'a>(dtb: &Dtb<'a>, property_offset: usize) -> Option<(DtbProperty<'a>, usize)> {
+    let mut current_offset = property_offset;
+
+    // Read data length and name offset. Note that data length can be zero which
+    // indicates a boolean property with implicit value of true.
+    let data_length = dtb.read_u32(current_offset)? as usize;
+    current_offset += core::mem::size_of::<u32>();
+
+    let nameoff = dtb.read_u32(current_offset)?;
+    current_offset += core::mem::size_of::<u32>();
+
+    // Get the property name.
+    let property_name = dtb.read_property_name(nameoff)?;
+
+    let property = DtbProperty {
+        name: property_name,
+        data: dtb.data.get(current_offset..current_offset + data_length)?,
+    };
+
+    // Skip property data and align to 4-byte boundary.
+    current_offset += data_length;
+    current_offset = (current_offset + 3) & !3;
+
+    Some((property, current_offset))
+}
+
+//=============================================================================
+// Byte Readers
+//=============================================================================
+
+/// Reads a big-endian u32 from a byte slice.
+///
+/// # Returns
+///
+/// The native-endian value, or `None` if the 4 bytes at `offset` are not all
+/// within `data`.
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(core::mem::size_of::<u32>())?)?;
+
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads a big-endian u64 from a byte slice.
+///
+/// # Returns
+///
+/// The native-endian value, or `None` if the 8 bytes at `offset` are not all
+/// within `data`.
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(core::mem::size_of::<u64>())?)?;
+
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Combines a run of big-endian 32-bit cells into a single value. Only the
+/// last two cells fit, so earlier cells are shifted out.
+fn read_cells(cells: &[u8]) -> u64 {
+    cells
+        .chunks_exact(core::mem::size_of::<u32>())
+        .fold(0, |value, cell| {
+            (value << 32) | u32::from_be_bytes([cell[0], cell[1], cell[2], cell[3]]) as u64
+        })
+}
+
+/// Reads an entry of the memory reservation block.
+///
+/// # Returns
+///
+/// The entry, or `None` if it does not lie entirely within `data`.
+fn read_memory_reservation_entry(data: &[u8], offset: usize) -> Option<DtbMemoryReservationEntry> {
+    Some(DtbMemoryReservationEntry {
+        address: read_u64(data, offset)?,
+        size: read_u64(data, offset + core::mem::size_of::<u64>())?,
+    })
+}
+
+/// Reads a null-terminated string from a byte slice.
+///
+/// # Parameters
+///
+/// * `data` - The bytes that contain the string.
+/// * `offset` - Offset where the string begins.
+///
+/// # Returns
+///
+/// A string slice without the null terminator, or `None` if the string has no
+/// null terminator within `data` or is not valid UTF-8.
+///
+/// # Examples
+///
+/// ```ignore
+/// let string = read_null_terminated_string(dtb.as_bytes(), offset);
+/// ```
+fn read_null_terminated_string(data: &[u8], offset: usize) -> Option<&str> {
+    let bytes = data.get(offset..)?;
+
+    // Find the string length by locating the null terminator.
+    let length = bytes.iter().position(|&byte| byte == 0)?;
+
+    core::str::from_utf8(&bytes[..length]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_builder::DtbBuilder;
+    use super::*;
+    use std::{vec, vec::Vec};
+
+    /// Builds a small tree resembling the QEMU virt machine.
+    fn sample_blob() -> Vec<u8> {
+        DtbBuilder::new()
+            .reserve(0x8000_0000, 0x2_0000)
+            .begin_node("")
+            .property_u32("#address-cells", 2)
+            .property_u32("#size-cells", 2)
+            .property_str("model", "riscv-virtio,qemu")
+            .begin_node("chosen")
+            .property_str("bootargs", "console=ttyS0")
+            .end_node()
+            .begin_node("memory@80000000")
+            .property_str("device_type", "memory")
+            .property(
+                "reg",
+                &[0x8000_0000u64.to_be_bytes(), 0x800_0000u64.to_be_bytes()].concat(),
+            )
+            .end_node()
+            .begin_node("soc")
+            .property_u32("#address-cells", 1)
+            .property_u32("#size-cells", 1)
+            .begin_node("serial@10000000")
+            .property("compatible", b"ns16550a\0snps,dw-apb-uart\0")
+            .property(
+                "reg",
+                &[0x1000_0000u32.to_be_bytes(), 0x100u32.to_be_bytes()].concat(),
+            )
+            .property_u32("phandle", 7)
+            .property("interrupt-controller", &[])
+            .end_node()
+            .end_node()
+            .end_node()
+            .build()
+    }
+
+    #[test]
+    fn test_parse_valid_blob() {
+        let blob = sample_blob();
+        let dtb = Dtb::parse(&blob).unwrap();
+
+        assert_eq!(dtb.total_size(), blob.len());
+        assert_eq!(dtb.header().magic, FDT_MAGIC);
+        assert_eq!(dtb.header().version, FDT_VERSION);
+    }
+
+    #[test]
+    fn test_parse_ignores_trailing_bytes() {
+        let mut blob = sample_blob();
+        let total_size = blob.len();
+        blob.extend_from_slice(&[0xff; 64]);
+
+        let dtb = Dtb::parse(&blob).unwrap();
+
+        assert_eq!(dtb.total_size(), total_size);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let blob = sample_blob();
+
+        let parse_with = |offset: usize, value: u32| {
+            let mut corrupted = blob.clone();
+            corrupted[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+            Dtb::parse(&corrupted).map(|_| ())
+        };
+
+        assert_eq!(
+            parse_with(0, 0xdeadbeef),
+            Err(DtbError::BadMagic(0xdeadbeef))
+        );
+        assert_eq!(
+            parse_with(20, 16),
+            Err(DtbError::UnsupportedVersion {
+                version: 16,
+                last_compatible_version: 16
+            })
+        );
+        assert_eq!(parse_with(4, 8), Err(DtbError::InvalidTotalSize(8)));
+        assert_eq!(
+            parse_with(4, blob.len() as u32 + 4),
+            Err(DtbError::InvalidTotalSize(blob.len() as u32 + 4))
+        );
+        assert_eq!(
+            parse_with(36, 0x10000),
+            Err(DtbError::BlockOutOfBounds(DtbBlock::Structure))
+        );
+        assert_eq!(
+            parse_with(12, 0x10000),
+            Err(DtbError::BlockOutOfBounds(DtbBlock::Strings))
+        );
+        assert_eq!(
+            parse_with(16, 4),
+            Err(DtbError::BlockOutOfBounds(DtbBlock::MemoryReservation))
+        );
+        assert_eq!(
+            parse_with(16, 44),
+            Err(DtbError::BlockMisaligned(DtbBlock::MemoryReservation))
+        );
+
+        let structure_block_size = u32::from_be_bytes(blob[36..40].try_into().unwrap());
+        assert_eq!(
+            parse_with(36, structure_block_size - 4),
+            Err(DtbError::MissingEndToken)
+        );
+
+        assert_eq!(
+            Dtb::parse(&blob[..DtbHeader::SIZE - 1]).map(|_| ()),
+            Err(DtbError::InvalidTotalSize(DtbHeader::SIZE as u32 - 1))
+        );
+    }
+
+    #[test]
+    fn test_nodes_depth_first() {
+        let blob = sample_blob();
+        let dtb = Dtb::parse(&blob).unwrap();
+
+        let nodes: Vec<_> = dtb.nodes().map(|node| (node.name, node.depth())).collect();
+
+        assert_eq!(
+            nodes,
+            vec![
+                ("", 0),
+                ("chosen", 1),
+                ("memory@80000000", 1),
+                ("soc", 1),
+                ("serial@10000000", 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_children_and_cell_info() {
+        let blob = sample_blob();
+        let dtb = Dtb::parse(&blob).unwrap();
+        let root_node = dtb.root_node().unwrap();
+
+        let children: Vec<_> = root_node.children().map(|node| node.name).collect();
+        assert_eq!(children, vec!["chosen", "memory@80000000", "soc"]);
+
+        let memory_node = root_node.child("memory").unwrap();
+        let mut regions = Vec::new();
+        memory_node
+            .property("reg")
+            .unwrap()
+            .get_property_data_as_reg(&memory_node.parent_cell_info(), |address, size| {
+                regions.push((address, size));
+            });
+        assert_eq!(regions, vec![(0x8000_0000, 0x800_0000)]);
+
+        let serial_node = root_node.child("soc").unwrap().child("serial").unwrap();
+        let mut regions = Vec::new();
+        serial_node
+            .property("reg")
+            .unwrap()
+            .get_property_data_as_reg(&serial_node.parent_cell_info(), |address, size| {
+                regions.push((address, size));
+            });
+        assert_eq!(regions, vec![(0x1000_0000, 0x100)]);
+    }
+
+    #[test]
+    fn test_property_accessors() {
+        let blob = sample_blob();
+        let dtb = Dtb::parse(&blob).unwrap();
+        let root_node = dtb.root_node().unwrap();
+
+        assert_eq!(
+            root_node
+                .property("model")
+                .unwrap()
+                .get_property_data_as_str(),
+            Some("riscv-virtio,qemu")
+        );
+        assert_eq!(
+            root_node
+                .property("#size-cells")
+                .unwrap()
+                .get_property_data_as_u64(),
+            Some(2)
+        );
+
+        let serial_node = root_node
+            .child("soc")
+            .unwrap()
+            .child("serial@10000000")
+            .unwrap();
+        let compatible: Vec<_> = serial_node
+            .property("compatible")
+            .unwrap()
+            .get_property_data_as_str_list()
+            .collect();
+        assert_eq!(compatible, vec!["ns16550a", "snps,dw-apb-uart"]);
+
+        let interrupt_controller = serial_node.property("interrupt-controller").unwrap();
+        assert!(interrupt_controller.data.is_empty());
+        assert_eq!(interrupt_controller.get_property_data_as_u32(), 0);
+        assert_eq!(interrupt_controller.get_property_data_as_str(), None);
+
+        assert_eq!(serial_node.phandle(), Some(7));
+        assert_eq!(root_node.phandle(), None);
+    }
+
+    #[test]
+    fn test_find_node() {
+        let blob = DtbBuilder::new()
+            .begin_node("")
+            .begin_node("aliases")
+            .property_str("serial0", "/soc/serial@10000000")
+            .property_str("broken", "serial0")
+            .end_node()
+            .begin_node("soc")
+            .begin_node("serial@10000000")
+            .begin_node("port")
+            .end_node()
+            .end_node()
+            .end_node()
+            .end_node()
+            .build();
+        let dtb = Dtb::parse(&blob).unwrap();
+        let serial_node = dtb.find_node("/soc/serial@10000000").unwrap();
+
+        assert_eq!(serial_node.name, "serial@10000000");
+        assert_eq!(dtb.find_node("/soc/serial"), Some(serial_node));
+        assert_eq!(dtb.find_node("serial0"), Some(serial_node));
+        assert_eq!(dtb.find_node("serial0/port").unwrap().name, "port");
+        assert_eq!(dtb.find_node("/"), dtb.root_node());
+        assert_ne!(dtb.find_node("/soc"), Some(serial_node));
+
+        assert_eq!(dtb.find_node("/soc/uart"), None);
+        assert_eq!(dtb.find_node("serial1"), None);
+        assert_eq!(dtb.find_node("broken"), None);
+    }
+
+    #[test]
+    fn test_compatible_and_reg_lookup() {
+        let blob = sample_blob();
+        let dtb = Dtb::parse(&blob).unwrap();
+
+        let serial_node = dtb.compatible_nodes("snps,dw-apb-uart").next().unwrap();
+        assert_eq!(serial_node.name, "serial@10000000");
+        assert!(serial_node.is_compatible("ns16550a"));
+        assert!(!serial_node.is_compatible("ns16550"));
+        assert!(serial_node.is_enabled());
+        assert_eq!(serial_node.first_reg(), Some((0x1000_0000, 0x100)));
+
+        assert_eq!(dtb.compatible_nodes("riscv,plic0").count(), 0);
+        assert_eq!(dtb.root_node().unwrap().first_reg(), None);
+    }
+
+    #[test]
+    fn test_translate_address() {
+        let mut ranges = Vec::new();
+        ranges.extend_from_slice(&0x1000u32.to_be_bytes());
+        ranges.extend_from_slice(&0x2_0000_0000u64.to_be_bytes());
+        ranges.extend_from_slice(&0x1000u32.to_be_bytes());
+
+        let blob = DtbBuilder::new()
+            .begin_node("")
+            .property_u32("#address-cells", 2)
+            .property_u32("#size-cells", 2)
+            .begin_node("bus")
+            .property_u32("#address-cells", 1)
+            .property_u32("#size-cells", 1)
+            .property("ranges", &ranges)
+            .begin_node("device@1100")
+            .end_node()
+            .begin_node("flat")
+            .property("ranges", &[])
+            .begin_node("device@1200")
+            .end_node()
+            .end_node()
+            .begin_node("closed")
+            .begin_node("device@1300")
+            .end_node()
+            .end_node()
+            .end_node()
+            .begin_node("device@80000000")
+            .end_node()
+            .end_node()
+            .build();
+
+        let dtb = Dtb::parse(&blob).unwrap();
+        let node = |path| dtb.find_node(path).unwrap();
+
+        let device = node("/bus/device@1100");
+        assert_eq!(device.translate_address(0x1100), Some(0x2_0000_0100));
+        assert_eq!(device.translate_address(0x2000), None);
+        assert_eq!(device.translate_address(0xFFF), None);
+
+        assert_eq!(
+            node("/bus/flat/device@1200").translate_address(0x1200),
+            Some(0x2_0000_0200)
+        );
+        assert_eq!(
+            node("/bus/closed/device@1300").translate_address(0x1300),
+            None
+        );
+        assert_eq!(
+            node("/device@80000000").translate_address(0x8000_0000),
+            Some(0x8000_0000)
+        );
+    }
+
+    #[test]
+    fn test_walk_structure_block_matches_iterators() {
+        let blob = sample_blob();
+        let dtb = Dtb::parse(&blob).unwrap();
+
+        let mut walked_nodes = Vec::new();
+        let mut walked_property_count = 0;
+        walk_structure_block(
+            &dtb,
+            |node, depth| walked_nodes.push((node.name, depth as usize)),
+            |_, _, _, _| walked_property_count += 1,
+        );
+
+        let iterated_nodes: Vec<_> = dtb.nodes().map(|node| (node.name, node.depth())).collect();
+        let iterated_property_count: usize =
+            dtb.nodes().map(|node| node.properties().count()).sum();
+
+        assert_eq!(walked_nodes, iterated_nodes);
+        assert_eq!(walked_property_count, iterated_property_count);
+    }
+
+    #[test]
+    fn test_memory_reservation_entries() {
+        let blob = sample_blob();
+        let dtb = Dtb::parse(&blob).unwrap();
+
+        let entries = core::cell::RefCell::new(Vec::new());
+        walk_memory_reservation_entries(&dtb, |entry| {
+            entries.borrow_mut().push((entry.address, entry.size));
+        });
+
+        assert_eq!(entries.into_inner(), vec![(0x8000_0000, 0x2_0000)]);
+    }
+
+    #[test]
+    fn test_corrupted_structure_block_does_not_panic() {
+        let blob = sample_blob();
+        let header = Dtb::parse(&blob).unwrap().header();
+        let structure_block_start = header.structure_block_offset as usize;
+        let strings_block_end = (header.strings_block_offset + header.strings_block_size) as usize;
+
+        // Overwrite each byte of the structure and strings blocks in turn and
+        // walk everything that still parses.
+        for offset in structure_block_start..strings_block_end {
+            for value in [0x00, 0x03, 0x7f, 0xff] {
+                let mut corrupted = blob.clone();
+                corrupted[offset] = value;
+
+                let Ok(dtb) = Dtb::parse(&corrupted) else {
+                    continue;
+                };
+
+                for node in dtb.nodes() {
+                    for property in node.properties() {
+                        property.get_property_data_as_reg(&node.parent_cell_info(), |_, _| {});
+                        let _ = property.get_property_data_as_str_list().count();
+                    }
+
+                    let _ = node.children().count();
+                }
+
+                walk_structure_block(&dtb, |_, _| {}, |_, _, _, _| {});
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use super::{Dtb, DtbNode};
+use core::cell::OnceCell;
+
+/// The maximum number of phandles a `PhandleIndex` holds. Phandles beyond this
+/// are still resolved, but by walking the structure block.
+pub const MAX_INDEXED_PHANDLES: usize = 64;
+
+/// A lookup table from phandle to node, so that references such as
+/// "interrupt-parent" and "clocks" can be followed without walking the whole
+/// tree each time.
+///
+/// The table is built the first time `resolve_phandle` is called and holds
+/// nodes sorted by phandle for binary search. It lives next to the DTB rather
+/// than inside it, so it does not allocate.
+pub struct PhandleIndex<'a> {
+    dtb: Dtb<'a>,
+    entries: OnceCell<PhandleEntries<'a>>,
+}
+
+/// The contents of a built `PhandleIndex`.
+struct PhandleEntries<'a> {
+    /// The indexed phandles in ascending order. Only the first `count` entries
+    /// are valid.
+    phandles: [u32; MAX_INDEXED_PHANDLES],
+
+    /// The node of each entry in `phandles`.
+    nodes: [Option<DtbNode<'a>>; MAX_INDEXED_PHANDLES],
+
+    count: usize,
+
+    /// True if the DTB has more phandles than the index holds.
+    overflowed: bool,
+}
+
+impl<'a> PhandleIndex<'a> {
+    /// Creates an empty index for a DTB. No nodes are read until the first
+    /// lookup.
+    ///
+    /// # Parameters
+    ///
+    /// * `dtb` - The Device Tree Blob.
+    pub const fn new(dtb: &Dtb<'a>) -> Self {
+        Self {
+            dtb: *dtb,
+            entries: OnceCell::new(),
+        }
+    }
+
+    /// Finds the node with the given phandle.
+    ///
+    /// # Parameters
+    ///
+    /// * `phandle` - The phandle, usually read from a property such as
+    ///   "interrupt-parent".
+    ///
+    /// # Returns
+    ///
+    /// The node whose "phandle" or "linux,phandle" property matches, or `None`
+    /// if there is no such node.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let phandle_index = PhandleIndex::new(&dtb);
+    ///
+    /// let interrupt_controller = node
+    ///     .property("interrupt-parent")
+    ///     .and_then(|property| phandle_index.resolve_phandle(property.get_property_data_as_u32()));
+    /// ```
+    pub fn resolve_phandle(&self, phandle: u32) -> Option<DtbNode<'a>> {
+        let entries = self
+            .entries
+            .get_or_init(|| PhandleEntries::build(&self.dtb));
+
+        let phandles = &entries.phandles[..entries.count];
+
+        if let Ok(index) = phandles.binary_search(&phandle) {
+            return entries.nodes[index];
+        }
+
+        // A phandle missing from a full index may belong to a node that did
+        // not fit.
+        if entries.overflowed {
+            return self
+                .dtb
+                .nodes()
+                .find(|node| node.phandle() == Some(phandle));
+        }
+
+        None
+    }
+}
+
+impl<'a> PhandleEntries<'a> {
+    /// Walks every node of the DTB once and records the nodes that have a
+    /// phandle, keeping the entries sorted.
+    fn build(dtb: &Dtb<'a>) -> Self {
+        let mut entries = Self {
+            phandles: [0; MAX_INDEXED_PHANDLES],
+            nodes: [None; MAX_INDEXED_PHANDLES],
+            count: 0,
+            overflowed: false,
+        };
+
+        for node in dtb.nodes() {
+            let Some(phandle) = node.phandle() else {
+                continue;
+            };
+
+            if entries.count == MAX_INDEXED_PHANDLES {
+                entries.overflowed = true;
+                break;
+            }
+
+            // Phandles are usually assigned in tree order, so this insertion
+            // rarely moves anything.
+            let insert_index =
+                entries.phandles[..entries.count].partition_point(|&existing| existing < phandle);
+
+            entries
+                .phandles
+                .copy_within(insert_index..entries.count, insert_index + 1);
+            entries
+                .nodes
+                .copy_within(insert_index..entries.count, insert_index + 1);
+
+            entries.phandles[insert_index] = phandle;
+            entries.nodes[insert_index] = Some(node);
+            entries.count += 1;
+        }
+
+        entries
+    }
+}
@@ -0,0 +1,119 @@
+//! A builder that assembles Device Tree Blobs in memory for the unit tests of
+//! the parser and its helpers.
+
+use super::{
+    DtbHeader, FDT_BEGIN_NODE, FDT_END, FDT_END_NODE, FDT_MAGIC, FDT_PROP, FDT_VERSION,
+    MEMORY_RESERVATION_ENTRY_SIZE,
+};
+use std::vec::Vec;
+
+/// Assembles a Device Tree Blob in memory so tests do not need fixture
+/// files.
+pub(super) struct DtbBuilder {
+    memory_reservations: Vec<(u64, u64)>,
+    structure_block: Vec<u8>,
+    strings_block: Vec<u8>,
+}
+
+impl DtbBuilder {
+    pub(super) fn new() -> Self {
+        Self {
+            memory_reservations: Vec::new(),
+            structure_block: Vec::new(),
+            strings_block: Vec::new(),
+        }
+    }
+
+    pub(super) fn reserve(&mut self, address: u64, size: u64) -> &mut Self {
+        self.memory_reservations.push((address, size));
+        self
+    }
+
+    pub(super) fn begin_node(&mut self, name: &str) -> &mut Self {
+        self.token(FDT_BEGIN_NODE);
+        self.structure_block.extend_from_slice(name.as_bytes());
+        self.structure_block.push(0);
+        self.pad();
+        self
+    }
+
+    pub(super) fn end_node(&mut self) -> &mut Self {
+        self.token(FDT_END_NODE)
+    }
+
+    pub(super) fn property(&mut self, name: &str, data: &[u8]) -> &mut Self {
+        let name_offset = self.strings_block.len() as u32;
+        self.strings_block.extend_from_slice(name.as_bytes());
+        self.strings_block.push(0);
+
+        self.token(FDT_PROP);
+        self.token(data.len() as u32);
+        self.token(name_offset);
+        self.structure_block.extend_from_slice(data);
+        self.pad();
+        self
+    }
+
+    pub(super) fn property_u32(&mut self, name: &str, value: u32) -> &mut Self {
+        self.property(name, &value.to_be_bytes())
+    }
+
+    pub(super) fn property_cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+        let data: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+        self.property(name, &data)
+    }
+
+    pub(super) fn property_str(&mut self, name: &str, value: &str) -> &mut Self {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        self.property(name, &data)
+    }
+
+    fn token(&mut self, value: u32) -> &mut Self {
+        self.structure_block.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn pad(&mut self) {
+        while !self.structure_block.len().is_multiple_of(4) {
+            self.structure_block.push(0);
+        }
+    }
+
+    pub(super) fn build(&mut self) -> Vec<u8> {
+        self.token(FDT_END);
+
+        let memory_reservation_block_offset = DtbHeader::SIZE;
+        let structure_block_offset = memory_reservation_block_offset
+            + (self.memory_reservations.len() + 1) * MEMORY_RESERVATION_ENTRY_SIZE;
+        let strings_block_offset = structure_block_offset + self.structure_block.len();
+        let total_size = strings_block_offset + self.strings_block.len();
+
+        let header = [
+            FDT_MAGIC,
+            total_size as u32,
+            structure_block_offset as u32,
+            strings_block_offset as u32,
+            memory_reservation_block_offset as u32,
+            FDT_VERSION,
+            16,
+            0,
+            self.strings_block.len() as u32,
+            self.structure_block.len() as u32,
+        ];
+
+        let mut blob = Vec::new();
+        for field in header {
+            blob.extend_from_slice(&field.to_be_bytes());
+        }
+
+        for (address, size) in self.memory_reservations.iter().chain([&(0, 0)]) {
+            blob.extend_from_slice(&address.to_be_bytes());
+            blob.extend_from_slice(&size.to_be_bytes());
+        }
+
+        blob.extend_from_slice(&self.structure_block);
+        blob.extend_from_slice(&self.strings_block);
+        blob
+    }
+}
@@ -0,0 +1,60 @@
+use core::fmt;
+
+/// The reasons `ElfFile::parse` can reject an ELF file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    /// The file is too small to hold the ELF header.
+    TooShort,
+
+    /// The file does not start with the ELF magic number.
+    BadMagic,
+
+    /// The file is not a 64-bit ELF file.
+    NotElf64,
+
+    /// The file is not little-endian.
+    NotLittleEndian,
+
+    /// The file uses an ELF version other than the current one. Holds the
+    /// version found.
+    UnsupportedVersion(u32),
+
+    /// The file is not an executable. Holds the type found.
+    NotExecutable(u16),
+
+    /// The file is not for RISC-V. Holds the machine found.
+    WrongMachine(u16),
+
+    /// The program header table has an unexpected entry size or extends past
+    /// the end of the file.
+    BadProgramHeaderTable,
+
+    /// A loadable segment is malformed. Holds the index of its program
+    /// header.
+    BadSegment(usize),
+
+    /// The dynamic section extends past the end of the file.
+    BadDynamicSection,
+
+    /// The RELA relocation table is not stored in a loadable segment or has
+    /// an unexpected entry size.
+    BadRelocationTable,
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort => write!(f, "file too short for an ELF header"),
+            Self::BadMagic => write!(f, "bad magic"),
+            Self::NotElf64 => write!(f, "not a 64-bit ELF file"),
+            Self::NotLittleEndian => write!(f, "not little-endian"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported version {}", version),
+            Self::NotExecutable(file_type) => write!(f, "not an executable (type {})", file_type),
+            Self::WrongMachine(machine) => write!(f, "wrong machine {:#x}", machine),
+            Self::BadProgramHeaderTable => write!(f, "bad program header table"),
+            Self::BadSegment(index) => write!(f, "bad segment in program header {}", index),
+            Self::BadDynamicSection => write!(f, "bad dynamic section"),
+            Self::BadRelocationTable => write!(f, "bad relocation table"),
+        }
+    }
+}
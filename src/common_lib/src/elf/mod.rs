@@ -0,0 +1,751 @@
+//! ELF64 executable parser.
+//!
+//! This module reads the parts of a RISC-V ELF64 executable that are needed
+//! to load it: the entry point, the loadable segments, and, for a position
+//! independent executable, the relocations its dynamic section points to. It
+//! works on a
+//! byte slice that holds the whole file without allocating, and every read is
+//! bounds checked. `ElfFile::parse` validates the header and every loadable
+//! segment up front, so the segments an `ElfFile` yields can be used without
+//! further checks against the file.
+//!
+//! Only little-endian, 64-bit executables of type `ET_EXEC` or `ET_DYN` for
+//! RISC-V are accepted. Section headers are ignored.
+
+mod elf_error;
+
+pub use elf_error::ElfError;
+
+//=============================================================================
+// Constants
+//=============================================================================
+
+/// The magic number at the start of every ELF file.
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+
+/// The `e_ident[EI_CLASS]` value of 64-bit files.
+const ELF_CLASS_64: u8 = 2;
+
+/// The `e_ident[EI_DATA]` value of little-endian files.
+const ELF_DATA_LITTLE_ENDIAN: u8 = 1;
+
+/// The current and only ELF version.
+const ELF_VERSION_CURRENT: u32 = 1;
+
+/// The `e_type` value of executables.
+const ELF_TYPE_EXECUTABLE: u16 = 2;
+
+/// The `e_type` value of shared objects, which position independent
+/// executables are.
+const ELF_TYPE_SHARED: u16 = 3;
+
+/// The `e_machine` value of RISC-V.
+const ELF_MACHINE_RISCV: u16 = 243;
+
+/// The size in bytes of the ELF64 header.
+const ELF_HEADER_SIZE: usize = 64;
+
+/// The size in bytes of an ELF64 program header.
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+/// The `p_type` value of loadable segments.
+pub const PT_LOAD: u32 = 1;
+
+/// The `p_type` value of the dynamic section.
+pub const PT_DYNAMIC: u32 = 2;
+
+/// The size in bytes of an entry of the dynamic section.
+const DYNAMIC_ENTRY_SIZE: usize = 16;
+
+/// The `d_tag` value that ends the dynamic section.
+const DT_NULL: u64 = 0;
+
+/// The `d_tag` value of the virtual address of the RELA relocation table.
+const DT_RELA: u64 = 7;
+
+/// The `d_tag` value of the size in bytes of the RELA relocation table.
+const DT_RELASZ: u64 = 8;
+
+/// The `d_tag` value of the size in bytes of a RELA relocation.
+const DT_RELAENT: u64 = 9;
+
+/// The size in bytes of an ELF64 RELA relocation.
+const RELA_ENTRY_SIZE: usize = 24;
+
+/// The relocation type that does nothing.
+pub const R_RISCV_NONE: u32 = 0;
+
+/// The relocation type that stores the load bias plus the addend in a 64-bit
+/// word.
+pub const R_RISCV_RELATIVE: u32 = 3;
+
+/// The `p_flags` bit of executable segments.
+const PF_X: u32 = 1 << 0;
+
+/// The `p_flags` bit of writable segments.
+const PF_W: u32 = 1 << 1;
+
+/// The `p_flags` bit of readable segments.
+const PF_R: u32 = 1 << 2;
+
+//=============================================================================
+// Data Structures
+//=============================================================================
+
+/// A validated ELF64 executable.
+#[derive(Debug, Copy, Clone)]
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+
+    /// The `e_type` of the file.
+    file_type: u16,
+
+    /// The virtual address execution starts at.
+    entry_point: u64,
+
+    /// The file offset of the program header table.
+    program_header_offset: usize,
+
+    /// The number of entries in the program header table.
+    program_header_count: usize,
+}
+
+/// The access a segment asks for once it is loaded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct SegmentFlags {
+    pub readable: bool,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+/// An entry of the program header table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProgramHeader {
+    /// The kind of segment, such as `PT_LOAD`.
+    pub segment_type: u32,
+
+    /// The access the segment asks for.
+    pub flags: SegmentFlags,
+
+    /// The file offset of the segment's contents.
+    pub offset: u64,
+
+    /// The virtual address the segment is loaded at.
+    pub virtual_address: u64,
+
+    /// The number of bytes of the segment stored in the file.
+    pub file_size: u64,
+
+    /// The number of bytes the segment occupies in memory. Bytes past
+    /// `file_size` are zero, which is how BSS is stored.
+    pub memory_size: u64,
+
+    /// The alignment of the segment in memory and in the file.
+    pub alignment: u64,
+}
+
+/// An entry of the RELA relocation table of a position independent
+/// executable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// The virtual address the relocation patches, as linked.
+    pub offset: u64,
+
+    /// The kind of relocation, such as `R_RISCV_RELATIVE`.
+    pub relocation_type: u32,
+
+    /// The index of the symbol in the dynamic symbol table, or zero.
+    pub symbol_index: u32,
+
+    /// The constant the relocation adds.
+    pub addend: i64,
+}
+
+impl SegmentFlags {
+    /// Decodes the `p_flags` field of a program header.
+    pub const fn from_raw(flags: u32) -> Self {
+        Self {
+            readable: flags & PF_R != 0,
+            writable: flags & PF_W != 0,
+            executable: flags & PF_X != 0,
+        }
+    }
+}
+
+impl ProgramHeader {
+    /// Returns whether the segment is loaded into memory.
+    pub const fn is_loadable(&self) -> bool {
+        self.segment_type == PT_LOAD
+    }
+
+    /// Returns the first virtual address past the segment in memory.
+    pub const fn end_address(&self) -> u64 {
+        self.virtual_address + self.memory_size
+    }
+}
+
+impl<'a> ElfFile<'a> {
+    /// Parses and validates an ELF64 executable.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The whole file.
+    ///
+    /// # Returns
+    ///
+    /// The file, or the first problem found with the header, the program
+    /// header table, or a loadable segment.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if data.len() < ELF_HEADER_SIZE {
+            return Err(ElfError::TooShort);
+        }
+
+        if data[0..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+
+        if data[4] != ELF_CLASS_64 {
+            return Err(ElfError::NotElf64);
+        }
+
+        if data[5] != ELF_DATA_LITTLE_ENDIAN {
+            return Err(ElfError::NotLittleEndian);
+        }
+
+        // The header is long enough for every fixed offset read below.
+        let file_type = read_u16(data, 16).ok_or(ElfError::TooShort)?;
+        let machine = read_u16(data, 18).ok_or(ElfError::TooShort)?;
+        let version = read_u32(data, 20).ok_or(ElfError::TooShort)?;
+        let entry_point = read_u64(data, 24).ok_or(ElfError::TooShort)?;
+        let program_header_offset = read_u64(data, 32).ok_or(ElfError::TooShort)?;
+        let program_header_size = read_u16(data, 54).ok_or(ElfError::TooShort)?;
+        let program_header_count = read_u16(data, 56).ok_or(ElfError::TooShort)?;
+
+        if data[6] as u32 != ELF_VERSION_CURRENT || version != ELF_VERSION_CURRENT {
+            return Err(ElfError::UnsupportedVersion(version));
+        }
+
+        if file_type != ELF_TYPE_EXECUTABLE && file_type != ELF_TYPE_SHARED {
+            return Err(ElfError::NotExecutable(file_type));
+        }
+
+        if machine != ELF_MACHINE_RISCV {
+            return Err(ElfError::WrongMachine(machine));
+        }
+
+        let program_header_offset =
+            usize::try_from(program_header_offset).map_err(|_| ElfError::BadProgramHeaderTable)?;
+        let program_header_count = program_header_count as usize;
+
+        let table_end = (program_header_count * PROGRAM_HEADER_SIZE)
+            .checked_add(program_header_offset)
+            .ok_or(ElfError::BadProgramHeaderTable)?;
+
+        if (program_header_count > 0 && program_header_size as usize != PROGRAM_HEADER_SIZE)
+            || table_end > data.len()
+        {
+            return Err(ElfError::BadProgramHeaderTable);
+        }
+
+        let file = Self {
+            data,
+            file_type,
+            entry_point,
+            program_header_offset,
+            program_header_count,
+        };
+
+        for (index, program_header) in file.program_headers().enumerate() {
+            if program_header.is_loadable() && !file.is_segment_valid(&program_header) {
+                return Err(ElfError::BadSegment(index));
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Returns the virtual address execution starts at.
+    pub fn entry_point(&self) -> u64 {
+        self.entry_point
+    }
+
+    /// Returns whether the file is a position independent executable, which
+    /// runs at any offset from the addresses it was linked at once its
+    /// relocations are applied.
+    pub fn is_position_independent(&self) -> bool {
+        self.file_type == ELF_TYPE_SHARED
+    }
+
+    /// Returns an iterator over every entry of the program header table.
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        let data = self.data;
+        let program_header_offset = self.program_header_offset;
+
+        (0..self.program_header_count).map(move |index| {
+            // `parse` checked that the whole table is within the file.
+            read_program_header(data, program_header_offset + index * PROGRAM_HEADER_SIZE)
+                .expect("The program header table is within the file.")
+        })
+    }
+
+    /// Returns an iterator over the loadable segments.
+    pub fn load_segments(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        self.program_headers()
+            .filter(|program_header| program_header.is_loadable())
+    }
+
+    /// Returns the bytes of a segment stored in the file, which are the first
+    /// `file_size` bytes of the segment in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `program_header` - A loadable segment of this file.
+    ///
+    /// # Panics
+    ///
+    /// If the segment's contents are not within the file, which `parse`
+    /// rules out for the file's own loadable segments.
+    pub fn segment_data(&self, program_header: &ProgramHeader) -> &'a [u8] {
+        let start = program_header.offset as usize;
+        let end = start + program_header.file_size as usize;
+
+        &self.data[start..end]
+    }
+
+    /// Returns the relocations of the RELA table the dynamic section points
+    /// to.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the relocations, which is empty if the file has no
+    /// dynamic section or no RELA table, or `ElfError::BadDynamicSection` or
+    /// `ElfError::BadRelocationTable` if either is malformed.
+    pub fn relocations(&self) -> Result<impl Iterator<Item = Relocation> + 'a, ElfError> {
+        let mut table_address = None;
+        let mut table_size = 0;
+        let mut entry_size = RELA_ENTRY_SIZE as u64;
+
+        if let Some(dynamic) = self
+            .program_headers()
+            .find(|program_header| program_header.segment_type == PT_DYNAMIC)
+        {
+            let start = usize::try_from(dynamic.offset).map_err(|_| ElfError::BadDynamicSection)?;
+            let end = usize::try_from(dynamic.file_size)
+                .ok()
+                .and_then(|size| start.checked_add(size))
+                .filter(|&end| end <= self.data.len())
+                .ok_or(ElfError::BadDynamicSection)?;
+
+            for entry_offset in (start..end).step_by(DYNAMIC_ENTRY_SIZE) {
+                let tag = read_u64(self.data, entry_offset).ok_or(ElfError::BadDynamicSection)?;
+                let value =
+                    read_u64(self.data, entry_offset + 8).ok_or(ElfError::BadDynamicSection)?;
+
+                match tag {
+                    DT_NULL => break,
+                    DT_RELA => table_address = Some(value),
+                    DT_RELASZ => table_size = value,
+                    DT_RELAENT => entry_size = value,
+                    _ => {}
+                }
+            }
+        }
+
+        let (table_offset, entry_count) = match table_address {
+            Some(address) => {
+                if entry_size != RELA_ENTRY_SIZE as u64
+                    || !table_size.is_multiple_of(RELA_ENTRY_SIZE as u64)
+                {
+                    return Err(ElfError::BadRelocationTable);
+                }
+
+                let offset = self
+                    .file_offset_of(address, table_size)
+                    .ok_or(ElfError::BadRelocationTable)?;
+
+                (offset, table_size as usize / RELA_ENTRY_SIZE)
+            }
+            None => (0, 0),
+        };
+
+        let data = self.data;
+
+        Ok((0..entry_count).map(move |index| {
+            let entry_offset = table_offset + index * RELA_ENTRY_SIZE;
+
+            // `file_offset_of` checked that the whole table is within the
+            // file.
+            let info = read_u64(data, entry_offset + 8).expect("The table is within the file.");
+
+            Relocation {
+                offset: read_u64(data, entry_offset).expect("The table is within the file."),
+                relocation_type: info as u32,
+                symbol_index: (info >> 32) as u32,
+                addend: read_u64(data, entry_offset + 16).expect("The table is within the file.")
+                    as i64,
+            }
+        }))
+    }
+
+    /// Finds the file offset of a range of virtual addresses that a loadable
+    /// segment stores in the file.
+    ///
+    /// # Returns
+    ///
+    /// The file offset of `address`, or `None` if no loadable segment stores
+    /// the whole range.
+    fn file_offset_of(&self, address: u64, size: u64) -> Option<usize> {
+        let end_address = address.checked_add(size)?;
+
+        self.load_segments()
+            .find(|segment| {
+                segment.virtual_address <= address
+                    && end_address <= segment.virtual_address + segment.file_size
+            })
+            .map(|segment| (segment.offset + (address - segment.virtual_address)) as usize)
+    }
+
+    /// Returns whether a loadable segment's contents are within the file and
+    /// its memory range is well formed.
+    fn is_segment_valid(&self, program_header: &ProgramHeader) -> bool {
+        let is_in_file = program_header
+            .offset
+            .checked_add(program_header.file_size)
+            .is_some_and(|end| end <= self.data.len() as u64);
+
+        let is_alignment_valid =
+            program_header.alignment == 0 || program_header.alignment.is_power_of_two();
+
+        is_in_file
+            && is_alignment_valid
+            && program_header.file_size <= program_header.memory_size
+            && program_header
+                .virtual_address
+                .checked_add(program_header.memory_size)
+                .is_some()
+    }
+}
+
+//=============================================================================
+// Byte Readers
+//=============================================================================
+
+/// Reads a little-endian u16 from a byte slice.
+///
+/// # Returns
+///
+/// The native-endian value, or `None` if the 2 bytes at `offset` are not all
+/// within `data`.
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(core::mem::size_of::<u16>())?)?;
+
+    Some(u16::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads a little-endian u32 from a byte slice.
+///
+/// # Returns
+///
+/// The native-endian value, or `None` if the 4 bytes at `offset` are not all
+/// within `data`.
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(core::mem::size_of::<u32>())?)?;
+
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads a little-endian u64 from a byte slice.
+///
+/// # Returns
+///
+/// The native-endian value, or `None` if the 8 bytes at `offset` are not all
+/// within `data`.
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(core::mem::size_of::<u64>())?)?;
+
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Reads the program header at a file offset.
+///
+/// # Returns
+///
+/// The program header, or `None` if it is not entirely within `data`.
+fn read_program_header(data: &[u8], offset: usize) -> Option<ProgramHeader> {
+    Some(ProgramHeader {
+        segment_type: read_u32(data, offset)?,
+        flags: SegmentFlags::from_raw(read_u32(data, offset + 4)?),
+        offset: read_u64(data, offset + 8)?,
+        virtual_address: read_u64(data, offset + 16)?,
+        file_size: read_u64(data, offset + 32)?,
+        memory_size: read_u64(data, offset + 40)?,
+        alignment: read_u64(data, offset + 48)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an executable with a program header table right after the
+    /// header and the given segment contents after the table.
+    ///
+    /// Each segment is `(flags, virtual_address, contents, memory_size)`.
+    fn build_elf(entry_point: u64, segments: &[(u32, u64, &[u8], u64)]) -> Vec<u8> {
+        let mut data = vec![0u8; ELF_HEADER_SIZE];
+
+        data[0..4].copy_from_slice(&ELF_MAGIC);
+        data[4] = ELF_CLASS_64;
+        data[5] = ELF_DATA_LITTLE_ENDIAN;
+        data[6] = ELF_VERSION_CURRENT as u8;
+        data[16..18].copy_from_slice(&ELF_TYPE_EXECUTABLE.to_le_bytes());
+        data[18..20].copy_from_slice(&ELF_MACHINE_RISCV.to_le_bytes());
+        data[20..24].copy_from_slice(&ELF_VERSION_CURRENT.to_le_bytes());
+        data[24..32].copy_from_slice(&entry_point.to_le_bytes());
+        data[32..40].copy_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes());
+        data[52..54].copy_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+        data[54..56].copy_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        data[56..58].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+
+        let mut content_offset = ELF_HEADER_SIZE + segments.len() * PROGRAM_HEADER_SIZE;
+
+        for &(flags, virtual_address, contents, memory_size) in segments {
+            data.extend_from_slice(&PT_LOAD.to_le_bytes());
+            data.extend_from_slice(&flags.to_le_bytes());
+            data.extend_from_slice(&(content_offset as u64).to_le_bytes());
+            data.extend_from_slice(&virtual_address.to_le_bytes());
+            data.extend_from_slice(&virtual_address.to_le_bytes());
+            data.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+            data.extend_from_slice(&memory_size.to_le_bytes());
+            data.extend_from_slice(&4096u64.to_le_bytes());
+
+            content_offset += contents.len();
+        }
+
+        for &(_, _, contents, _) in segments {
+            data.extend_from_slice(contents);
+        }
+
+        data
+    }
+
+    #[test]
+    fn test_parse_executable() {
+        let code = [0x13, 0x00, 0x00, 0x00];
+        let initialized_data = [1, 2, 3];
+
+        let data = build_elf(
+            0x1_0000,
+            &[
+                (PF_R | PF_X, 0x1_0000, &code, 4),
+                (PF_R | PF_W, 0x1_1000, &initialized_data, 0x100),
+            ],
+        );
+
+        let file = ElfFile::parse(&data).unwrap();
+        assert_eq!(file.entry_point(), 0x1_0000);
+
+        let segments: Vec<_> = file.load_segments().collect();
+        assert_eq!(segments.len(), 2);
+
+        assert_eq!(
+            segments[0].flags,
+            SegmentFlags {
+                readable: true,
+                writable: false,
+                executable: true,
+            }
+        );
+        assert_eq!(file.segment_data(&segments[0]), code);
+
+        assert_eq!(segments[1].virtual_address, 0x1_1000);
+        assert!(segments[1].flags.writable && !segments[1].flags.executable);
+        assert_eq!(segments[1].memory_size, 0x100);
+        assert_eq!(segments[1].end_address(), 0x1_1100);
+        assert_eq!(file.segment_data(&segments[1]), initialized_data);
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_headers() {
+        let valid = build_elf(0x1_0000, &[(PF_R | PF_X, 0x1_0000, &[0; 4], 4)]);
+
+        assert_eq!(
+            ElfFile::parse(&valid[..32]).unwrap_err(),
+            ElfError::TooShort
+        );
+
+        let mut data = valid.clone();
+        data[0] = 0;
+        assert_eq!(ElfFile::parse(&data).unwrap_err(), ElfError::BadMagic);
+
+        let mut data = valid.clone();
+        data[4] = 1;
+        assert_eq!(ElfFile::parse(&data).unwrap_err(), ElfError::NotElf64);
+
+        let mut data = valid.clone();
+        data[5] = 2;
+        assert_eq!(
+            ElfFile::parse(&data).unwrap_err(),
+            ElfError::NotLittleEndian
+        );
+
+        let mut data = valid.clone();
+        data[16] = 1;
+        assert_eq!(
+            ElfFile::parse(&data).unwrap_err(),
+            ElfError::NotExecutable(1)
+        );
+
+        let mut data = valid.clone();
+        data[18] = 62;
+        assert_eq!(
+            ElfFile::parse(&data).unwrap_err(),
+            ElfError::WrongMachine(62)
+        );
+
+        let mut data = valid.clone();
+        data[54] = 32;
+        assert_eq!(
+            ElfFile::parse(&data).unwrap_err(),
+            ElfError::BadProgramHeaderTable
+        );
+
+        let mut data = valid.clone();
+        data[56] = 200;
+        assert_eq!(
+            ElfFile::parse(&data).unwrap_err(),
+            ElfError::BadProgramHeaderTable
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_segments() {
+        // The file size is larger than the memory size.
+        let data = build_elf(0, &[(PF_R, 0x1_0000, &[0; 8], 4)]);
+        assert_eq!(ElfFile::parse(&data).unwrap_err(), ElfError::BadSegment(0));
+
+        // The contents extend past the end of the file.
+        let data = build_elf(0, &[(PF_R, 0x1_0000, &[0; 8], 8)]);
+        assert_eq!(
+            ElfFile::parse(&data[..data.len() - 1]).unwrap_err(),
+            ElfError::BadSegment(0)
+        );
+
+        // The memory range wraps around the address space.
+        let data = build_elf(0, &[(PF_R, 0x1_0000, &[], 4), (PF_R, u64::MAX, &[], 2)]);
+        assert_eq!(ElfFile::parse(&data).unwrap_err(), ElfError::BadSegment(1));
+    }
+
+    /// Builds a position independent executable whose first segment, at
+    /// 0x1000, holds a RELA table, and whose second program header is the
+    /// dynamic section pointing to it.
+    ///
+    /// Each relocation is `(offset, relocation_type, addend)`.
+    fn build_position_independent_elf(relocations: &[(u64, u32, i64)]) -> Vec<u8> {
+        let mut table = Vec::new();
+
+        for &(offset, relocation_type, addend) in relocations {
+            table.extend_from_slice(&offset.to_le_bytes());
+            table.extend_from_slice(&(relocation_type as u64).to_le_bytes());
+            table.extend_from_slice(&addend.to_le_bytes());
+        }
+
+        let mut dynamic = Vec::new();
+
+        for (tag, value) in [
+            (DT_RELA, 0x1000),
+            (DT_RELASZ, table.len() as u64),
+            (DT_RELAENT, RELA_ENTRY_SIZE as u64),
+            (DT_NULL, 0),
+        ] {
+            dynamic.extend_from_slice(&tag.to_le_bytes());
+            dynamic.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut data = build_elf(
+            0x1000,
+            &[
+                (PF_R, 0x1000, &table, table.len() as u64),
+                (PF_R, 0x2000, &dynamic, dynamic.len() as u64),
+            ],
+        );
+
+        data[16..18].copy_from_slice(&ELF_TYPE_SHARED.to_le_bytes());
+
+        let dynamic_header_offset = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE;
+        data[dynamic_header_offset..dynamic_header_offset + 4]
+            .copy_from_slice(&PT_DYNAMIC.to_le_bytes());
+
+        data
+    }
+
+    #[test]
+    fn test_relocations() {
+        let data = build_position_independent_elf(&[
+            (0x1008, R_RISCV_RELATIVE, 0x1234),
+            (0x1010, R_RISCV_NONE, -1),
+        ]);
+
+        let file = ElfFile::parse(&data).unwrap();
+        assert!(file.is_position_independent());
+        assert_eq!(file.load_segments().count(), 1);
+
+        let relocations: Vec<_> = file.relocations().unwrap().collect();
+        assert_eq!(
+            relocations,
+            [
+                Relocation {
+                    offset: 0x1008,
+                    relocation_type: R_RISCV_RELATIVE,
+                    symbol_index: 0,
+                    addend: 0x1234,
+                },
+                Relocation {
+                    offset: 0x1010,
+                    relocation_type: R_RISCV_NONE,
+                    symbol_index: 0,
+                    addend: -1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relocations_without_dynamic_section() {
+        let data = build_elf(0x1_0000, &[(PF_R | PF_X, 0x1_0000, &[0; 4], 4)]);
+
+        let file = ElfFile::parse(&data).unwrap();
+        assert!(!file.is_position_independent());
+        assert_eq!(file.relocations().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_relocations_reject_bad_tables() {
+        // The table size is not a whole number of relocations.
+        let mut data = build_position_independent_elf(&[(0x1008, R_RISCV_RELATIVE, 0)]);
+        let dynamic_offset = data.len() - 4 * DYNAMIC_ENTRY_SIZE;
+        data[dynamic_offset + 24] = 23;
+        assert_eq!(
+            ElfFile::parse(&data).unwrap().relocations().err(),
+            Some(ElfError::BadRelocationTable)
+        );
+
+        // The table is not stored in a loadable segment.
+        let mut data = build_position_independent_elf(&[(0x1008, R_RISCV_RELATIVE, 0)]);
+        data[dynamic_offset + 8..dynamic_offset + 16].copy_from_slice(&0x3000u64.to_le_bytes());
+        assert_eq!(
+            ElfFile::parse(&data).unwrap().relocations().err(),
+            Some(ElfError::BadRelocationTable)
+        );
+
+        // The dynamic section extends past the end of the file.
+        let data = build_position_independent_elf(&[(0x1008, R_RISCV_RELATIVE, 0)]);
+        assert_eq!(
+            ElfFile::parse(&data[..data.len() - 1])
+                .unwrap()
+                .relocations()
+                .err(),
+            Some(ElfError::BadDynamicSection)
+        );
+    }
+}
@@ -0,0 +1,348 @@
+//! The layout of the kernel's half of the virtual address space.
+//!
+//! The boot stage builds the kernel's page tables and the kernel relies on
+//! them, so both take every window of the kernel half from the `Layout` of
+//! the paging mode the boot stage enabled. The kernel half starts at root
+//! page table entry 256 in every mode, and every window starts on a root
+//! entry of its own, so that address spaces share a window by sharing its
+//! root entries:
+//!
+//! | Root entries | Window                                 |
+//! |--------------|----------------------------------------|
+//! | 256..320     | The kernel image.                      |
+//! | 320..336     | The kernel's copy of the DTB.          |
+//! | 336..352     | The kernel heap.                       |
+//! | 352          | The MMIO window.                       |
+//! | 384..512     | The direct map of physical memory.     |
+//!
+//! A root entry maps 1GiB in sv39, 512GiB in sv48, and 256TiB in sv57, so
+//! the windows grow with the mode. The direct map covers the first 128GiB of
+//! physical memory in sv39 and all of it in the larger modes.
+//!
+//! The kernel is linked at `KERNEL_BASE_VIRTUAL_ADDRESS`, the base of the
+//! kernel image in sv39, and the boot stage slides it to the base of the mode
+//! it enables. The build scripts read `KERNEL_BASE_VIRTUAL_ADDRESS` from this
+//! file, so it must stay a plain hexadecimal literal.
+
+mod paging_mode;
+
+pub use paging_mode::PagingMode;
+
+/// The virtual address the kernel image is linked at, which is where sv39
+/// maps it before any randomization.
+pub const KERNEL_BASE_VIRTUAL_ADDRESS: usize = 0xFFFF_FFC0_0000_0000;
+
+/// The alignment of the slide of the kernel image.
+pub const KASLR_ALIGNMENT: usize = 2 * 1024 * 1024;
+
+/// The first root page table entry of the kernel image window.
+const KERNEL_IMAGE_ROOT_ENTRY: usize = 256;
+
+/// The first root page table entry of the DTB window.
+const DTB_ROOT_ENTRY: usize = 320;
+
+/// The first root page table entry of the kernel heap window.
+const KERNEL_HEAP_ROOT_ENTRY: usize = 336;
+
+/// The root page table entry of the MMIO window.
+const MMIO_WINDOW_ROOT_ENTRY: usize = 352;
+
+/// The first root page table entry of the direct map.
+const DIRECT_MAP_ROOT_ENTRY: usize = 384;
+
+/// The windows of the kernel half in one paging mode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// The paging mode the windows are placed for.
+    pub paging_mode: PagingMode,
+
+    /// The virtual address the boot stage maps the kernel image at before
+    /// randomization, the first address of the kernel half.
+    pub kernel_base_virtual_address: usize,
+
+    /// The most bytes of virtual memory the kernel image may occupy.
+    pub kernel_image_max_size: usize,
+
+    /// The number of bytes above `kernel_base_virtual_address` the boot stage
+    /// may slide the kernel image by. The slide is a multiple of
+    /// `KASLR_ALIGNMENT` below this, and the slid image must still end within
+    /// `kernel_image_max_size`.
+    pub kaslr_window_size: usize,
+
+    /// The virtual address the boot stage maps the kernel's copy of the DTB
+    /// at.
+    ///
+    /// The copy lives in pages the boot stage allocated and is mapped read
+    /// only, so the address stays valid however physical memory is used
+    /// later.
+    pub dtb_virtual_address: usize,
+
+    /// The most bytes of virtual memory the DTB may occupy.
+    pub dtb_max_size: usize,
+
+    /// The first virtual address of the kernel heap. Reserved, since the
+    /// kernel has no heap yet.
+    pub kernel_heap_base_virtual_address: usize,
+
+    /// The number of bytes of the kernel heap window.
+    pub kernel_heap_size: usize,
+
+    /// The first virtual address of the window the kernel maps device
+    /// registers in.
+    pub mmio_window_base_virtual_address: usize,
+
+    /// The number of bytes of the MMIO window.
+    pub mmio_window_size: usize,
+
+    /// The virtual address the boot stage maps physical address zero at.
+    pub direct_map_base_virtual_address: usize,
+
+    /// The number of bytes of physical memory the direct map covers, which
+    /// is the rest of the address space.
+    pub direct_map_size: usize,
+}
+
+impl Layout {
+    /// Places the windows for a paging mode.
+    ///
+    /// # Parameters
+    ///
+    /// * `paging_mode` - The paging mode the boot stage enables.
+    pub const fn new(paging_mode: PagingMode) -> Self {
+        let root_entry_size = paging_mode.root_entry_size();
+
+        Self {
+            paging_mode,
+            kernel_base_virtual_address: root_entry_address(paging_mode, KERNEL_IMAGE_ROOT_ENTRY),
+            kernel_image_max_size: 64 * root_entry_size,
+            kaslr_window_size: 16 * root_entry_size,
+            dtb_virtual_address: root_entry_address(paging_mode, DTB_ROOT_ENTRY),
+            dtb_max_size: 16 * root_entry_size,
+            kernel_heap_base_virtual_address: root_entry_address(
+                paging_mode,
+                KERNEL_HEAP_ROOT_ENTRY,
+            ),
+            kernel_heap_size: 16 * root_entry_size,
+            mmio_window_base_virtual_address: root_entry_address(
+                paging_mode,
+                MMIO_WINDOW_ROOT_ENTRY,
+            ),
+            mmio_window_size: root_entry_size,
+            direct_map_base_virtual_address: root_entry_address(paging_mode, DIRECT_MAP_ROOT_ENTRY),
+            direct_map_size: 128 * root_entry_size,
+        }
+    }
+
+    /// Returns the slide that moves the kernel image from the address it is
+    /// linked at to `kernel_base_virtual_address`, which wraps around in the
+    /// modes whose kernel half starts lower than sv39's.
+    pub const fn kernel_base_slide(&self) -> usize {
+        self.kernel_base_virtual_address
+            .wrapping_sub(KERNEL_BASE_VIRTUAL_ADDRESS)
+    }
+
+    /// Returns the index of the root page table entry that maps a virtual
+    /// address.
+    ///
+    /// # Parameters
+    ///
+    /// * `virtual_address` - The virtual address.
+    pub const fn root_page_table_index(&self, virtual_address: usize) -> usize {
+        (virtual_address / self.paging_mode.root_entry_size()) % 512
+    }
+}
+
+/// Returns the first virtual address a root page table entry maps in a paging
+/// mode, sign extended.
+const fn root_entry_address(paging_mode: PagingMode, root_entry: usize) -> usize {
+    paging_mode.sign_extend(root_entry * paging_mode.root_entry_size())
+}
+
+// The kernel must be linked where sv39 maps it, and every slide must leave
+// room for the image and keep its pages aligned. The windows are the same
+// number of root entries in every mode, so checking sv39 covers them all.
+const _: () = {
+    let layout = Layout::new(PagingMode::Sv39);
+
+    assert!(
+        layout.kernel_base_virtual_address == KERNEL_BASE_VIRTUAL_ADDRESS
+            && layout.kaslr_window_size <= layout.kernel_image_max_size
+            && layout.kaslr_window_size.is_multiple_of(KASLR_ALIGNMENT)
+            && KASLR_ALIGNMENT.is_power_of_two()
+    );
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every window of a layout as its base address and size, in address
+    /// order.
+    fn windows(layout: &Layout) -> [(usize, usize); 5] {
+        [
+            (
+                layout.kernel_base_virtual_address,
+                layout.kernel_image_max_size,
+            ),
+            (layout.dtb_virtual_address, layout.dtb_max_size),
+            (
+                layout.kernel_heap_base_virtual_address,
+                layout.kernel_heap_size,
+            ),
+            (
+                layout.mmio_window_base_virtual_address,
+                layout.mmio_window_size,
+            ),
+            (
+                layout.direct_map_base_virtual_address,
+                layout.direct_map_size,
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_windows_start_on_root_entries() {
+        for mode in PagingMode::PROBE_ORDER {
+            let layout = Layout::new(mode);
+
+            for (base_address, size) in windows(&layout) {
+                assert!(base_address.is_multiple_of(mode.root_entry_size()));
+                assert!(size.is_multiple_of(mode.root_entry_size()));
+                assert!(layout.root_page_table_index(base_address) >= 256);
+            }
+        }
+    }
+
+    #[test]
+    fn test_windows_do_not_overlap() {
+        for mode in PagingMode::PROBE_ORDER {
+            for pair in windows(&Layout::new(mode)).windows(2) {
+                let (base_address, size) = pair[0];
+                let (next_base_address, _) = pair[1];
+
+                assert!(base_address + size <= next_base_address);
+            }
+        }
+    }
+
+    #[test]
+    fn test_windows_are_canonical() {
+        for mode in PagingMode::PROBE_ORDER {
+            for (base_address, size) in windows(&Layout::new(mode)) {
+                assert!(mode.is_canonical(base_address));
+                assert!(mode.is_canonical(base_address + (size - 1)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_direct_map_reaches_the_top() {
+        for mode in PagingMode::PROBE_ORDER {
+            let layout = Layout::new(mode);
+
+            assert_eq!(
+                layout
+                    .direct_map_base_virtual_address
+                    .wrapping_add(layout.direct_map_size),
+                0
+            );
+        }
+    }
+
+    #[test]
+    fn test_root_page_table_index() {
+        for mode in PagingMode::PROBE_ORDER {
+            let layout = Layout::new(mode);
+
+            assert_eq!(
+                layout.root_page_table_index(layout.kernel_base_virtual_address),
+                256
+            );
+            assert_eq!(
+                layout.root_page_table_index(layout.dtb_virtual_address),
+                320
+            );
+            assert_eq!(
+                layout.root_page_table_index(layout.kernel_heap_base_virtual_address),
+                336
+            );
+            assert_eq!(
+                layout.root_page_table_index(layout.mmio_window_base_virtual_address),
+                352
+            );
+            assert_eq!(
+                layout.root_page_table_index(layout.direct_map_base_virtual_address),
+                384
+            );
+            assert_eq!(layout.root_page_table_index(usize::MAX), 511);
+        }
+    }
+
+    #[test]
+    fn test_layout_addresses() {
+        let sv39 = Layout::new(PagingMode::Sv39);
+        assert_eq!(sv39.dtb_virtual_address, 0xFFFF_FFD0_0000_0000);
+        assert_eq!(sv39.direct_map_base_virtual_address, 0xFFFF_FFE0_0000_0000);
+        assert_eq!(sv39.direct_map_size, 128 << 30);
+        assert_eq!(sv39.kernel_base_slide(), 0);
+
+        let sv48 = Layout::new(PagingMode::Sv48);
+        assert_eq!(sv48.kernel_base_virtual_address, 0xFFFF_8000_0000_0000);
+        assert_eq!(sv48.direct_map_base_virtual_address, 0xFFFF_C000_0000_0000);
+        assert_eq!(
+            KERNEL_BASE_VIRTUAL_ADDRESS.wrapping_add(sv48.kernel_base_slide()),
+            sv48.kernel_base_virtual_address
+        );
+
+        let sv57 = Layout::new(PagingMode::Sv57);
+        assert_eq!(sv57.kernel_base_virtual_address, 0xFF00_0000_0000_0000);
+        assert_eq!(sv57.mmio_window_base_virtual_address, 0xFF60_0000_0000_0000);
+        assert_eq!(sv57.direct_map_base_virtual_address, 0xFF80_0000_0000_0000);
+    }
+
+    #[test]
+    fn test_paging_mode_satp() {
+        for mode in PagingMode::PROBE_ORDER {
+            let satp = mode.satp(0x8_0123);
+
+            assert_eq!(PagingMode::from_satp(satp), Some(mode));
+            assert_eq!(satp & ((1 << 44) - 1), 0x8_0123);
+        }
+
+        assert_eq!(PagingMode::Sv39.satp(0x8_0123), 0x8000_0000_0008_0123);
+        assert_eq!(PagingMode::from_satp(0x8_0123), None);
+        assert_eq!(PagingMode::from_satp_mode(11), None);
+    }
+
+    #[test]
+    fn test_paging_mode_sizes() {
+        assert_eq!(PagingMode::Sv39.virtual_address_bits(), 39);
+        assert_eq!(PagingMode::Sv48.virtual_address_bits(), 48);
+        assert_eq!(PagingMode::Sv57.virtual_address_bits(), 57);
+
+        assert_eq!(PagingMode::Sv39.root_entry_size(), 1 << 30);
+        assert_eq!(PagingMode::Sv48.root_entry_size(), 1 << 39);
+        assert_eq!(PagingMode::Sv57.root_entry_size(), 1 << 48);
+
+        assert!(
+            PagingMode::PROBE_ORDER
+                .windows(2)
+                .all(|pair| pair[0] > pair[1])
+        );
+    }
+
+    #[test]
+    fn test_paging_mode_is_canonical() {
+        assert!(PagingMode::Sv39.is_canonical(0x3F_FFFF_FFFF));
+        assert!(!PagingMode::Sv39.is_canonical(0x40_0000_0000));
+        assert!(PagingMode::Sv39.is_canonical(0xFFFF_FFC0_0000_0000));
+        assert!(!PagingMode::Sv39.is_canonical(0xFFFF_FF80_0000_0000));
+
+        assert!(PagingMode::Sv48.is_canonical(0x40_0000_0000));
+        assert!(PagingMode::Sv48.is_canonical(0xFFFF_8000_0000_0000));
+        assert!(!PagingMode::Sv48.is_canonical(0x8000_0000_0000));
+
+        assert!(PagingMode::Sv57.is_canonical(0xFF00_0000_0000_0000));
+        assert!(!PagingMode::Sv57.is_canonical(0x0100_0000_0000_0000));
+    }
+}
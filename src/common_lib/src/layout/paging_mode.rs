@@ -0,0 +1,134 @@
+use core::fmt;
+
+/// A paging mode of the satp register on RV64, ordered from the smallest
+/// address space to the largest.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PagingMode {
+    /// Three levels of page tables and 39 bit virtual addresses.
+    Sv39,
+
+    /// Four levels of page tables and 48 bit virtual addresses.
+    Sv48,
+
+    /// Five levels of page tables and 57 bit virtual addresses.
+    Sv57,
+}
+
+impl PagingMode {
+    /// Every mode, from the largest address space to the smallest, which is
+    /// the order the boot stage probes them in. A hart that supports a mode
+    /// supports every mode after it.
+    pub const PROBE_ORDER: [Self; 3] = [Self::Sv57, Self::Sv48, Self::Sv39];
+
+    /// Returns the value of the MODE field of satp that selects the mode.
+    pub const fn satp_mode(self) -> usize {
+        match self {
+            Self::Sv39 => 8,
+            Self::Sv48 => 9,
+            Self::Sv57 => 10,
+        }
+    }
+
+    /// Decodes the MODE field of satp.
+    ///
+    /// # Parameters
+    ///
+    /// * `satp_mode` - The value of the field.
+    ///
+    /// # Returns
+    ///
+    /// The mode, or `None` if the value is not a paging mode, such as zero
+    /// for bare mode.
+    pub const fn from_satp_mode(satp_mode: usize) -> Option<Self> {
+        match satp_mode {
+            8 => Some(Self::Sv39),
+            9 => Some(Self::Sv48),
+            10 => Some(Self::Sv57),
+            _ => None,
+        }
+    }
+
+    /// Decodes the mode of a satp value.
+    ///
+    /// # Parameters
+    ///
+    /// * `satp` - The raw value of satp.
+    ///
+    /// # Returns
+    ///
+    /// The mode, or `None` if satp selects bare mode or an unknown mode.
+    pub const fn from_satp(satp: usize) -> Option<Self> {
+        Self::from_satp_mode(satp >> 60)
+    }
+
+    /// Returns the satp value that selects a root page table in the mode,
+    /// with an ASID of zero.
+    ///
+    /// # Parameters
+    ///
+    /// * `root_page_table_ppn` - The raw physical page number of the root
+    ///   page table.
+    pub const fn satp(self, root_page_table_ppn: usize) -> usize {
+        // The PPN occupies the lower 44 bits of satp.
+        (self.satp_mode() << 60) | (root_page_table_ppn & ((1 << 44) - 1))
+    }
+
+    /// Returns the number of levels of page tables a translation walks.
+    pub const fn page_table_levels(self) -> usize {
+        match self {
+            Self::Sv39 => 3,
+            Self::Sv48 => 4,
+            Self::Sv57 => 5,
+        }
+    }
+
+    /// Returns the level of the root page table, where level 0 tables map
+    /// 4KiB pages.
+    pub const fn root_level(self) -> usize {
+        self.page_table_levels() - 1
+    }
+
+    /// Returns the number of bits of a virtual address the mode translates.
+    pub const fn virtual_address_bits(self) -> usize {
+        12 + 9 * self.page_table_levels()
+    }
+
+    /// Returns the number of bytes one root page table entry maps.
+    pub const fn root_entry_size(self) -> usize {
+        1 << (self.virtual_address_bits() - 9)
+    }
+
+    /// Returns whether a virtual address is canonical in the mode, which is
+    /// when every bit above the translated bits is a copy of the highest
+    /// translated bit.
+    ///
+    /// # Parameters
+    ///
+    /// * `virtual_address` - The virtual address.
+    pub const fn is_canonical(self, virtual_address: usize) -> bool {
+        self.sign_extend(virtual_address) == virtual_address
+    }
+
+    /// Copies the highest translated bit of a virtual address into the bits
+    /// above it, which turns an address built from page table indices into
+    /// a canonical one.
+    ///
+    /// # Parameters
+    ///
+    /// * `virtual_address` - The virtual address.
+    pub const fn sign_extend(self, virtual_address: usize) -> usize {
+        let shift = usize::BITS as usize - self.virtual_address_bits();
+
+        (((virtual_address << shift) as isize) >> shift) as usize
+    }
+}
+
+impl fmt::Display for PagingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sv39 => write!(f, "sv39"),
+            Self::Sv48 => write!(f, "sv48"),
+            Self::Sv57 => write!(f, "sv57"),
+        }
+    }
+}
@@ -1,3 +1,9 @@
 #![cfg_attr(not(test), no_std)]
 
+pub mod backtrace;
+pub mod boot_info;
+pub mod dtb;
+pub mod elf;
+pub mod layout;
 pub mod memory;
+pub mod syscall;
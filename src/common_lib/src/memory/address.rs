@@ -0,0 +1,273 @@
+//! Physical and virtual address types.
+//!
+//! Both are plain `usize` values underneath, but keeping them apart lets the
+//! compiler catch a physical address passed where a virtual one is expected.
+//! They convert to and from page numbers and support the alignment and offset
+//! arithmetic that page table and allocator code needs.
+
+use super::{PhysicalPageNumber, VirtualPageNumber};
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Sub},
+};
+
+/// The size of a base page in bytes.
+const PAGE_SIZE: usize = 4096;
+
+/// Implements the parts of an address type that do not depend on whether the
+/// address is physical or virtual.
+macro_rules! impl_address {
+    ($address:ident) => {
+        impl $address {
+            /// Creates an address from its raw value.
+            pub const fn new(address: usize) -> Self {
+                Self(address)
+            }
+
+            /// Returns the raw value of the address.
+            pub const fn raw_address(&self) -> usize {
+                self.0
+            }
+
+            /// Returns true if the address is a multiple of an alignment.
+            ///
+            /// # Arguments
+            ///
+            /// * `alignment` - The alignment in bytes. Must be a power of two.
+            pub const fn is_aligned(&self, alignment: usize) -> bool {
+                self.0 & (alignment - 1) == 0
+            }
+
+            /// Rounds the address down to a multiple of an alignment.
+            ///
+            /// # Arguments
+            ///
+            /// * `alignment` - The alignment in bytes. Must be a power of two.
+            pub const fn align_down(&self, alignment: usize) -> Self {
+                Self(self.0 & !(alignment - 1))
+            }
+
+            /// Rounds the address up to a multiple of an alignment.
+            ///
+            /// # Arguments
+            ///
+            /// * `alignment` - The alignment in bytes. Must be a power of two.
+            ///
+            /// # Returns
+            ///
+            /// The aligned address, or `None` if it would overflow.
+            pub const fn align_up(&self, alignment: usize) -> Option<Self> {
+                match self.0.checked_add(alignment - 1) {
+                    Some(address) => Some(Self(address & !(alignment - 1))),
+                    None => None,
+                }
+            }
+
+            /// Returns true if the address is the first byte of a 4KiB page.
+            pub const fn is_page_aligned(&self) -> bool {
+                self.is_aligned(PAGE_SIZE)
+            }
+
+            /// Returns the offset of the address into its 4KiB page.
+            pub const fn page_offset(&self) -> usize {
+                self.0 & (PAGE_SIZE - 1)
+            }
+
+            /// Adds a byte offset to the address.
+            ///
+            /// # Returns
+            ///
+            /// The address, or `None` if it would overflow.
+            pub const fn checked_add(&self, offset: usize) -> Option<Self> {
+                match self.0.checked_add(offset) {
+                    Some(address) => Some(Self(address)),
+                    None => None,
+                }
+            }
+        }
+
+        impl Add<usize> for $address {
+            type Output = Self;
+
+            fn add(self, offset: usize) -> Self {
+                Self(self.0 + offset)
+            }
+        }
+
+        impl AddAssign<usize> for $address {
+            fn add_assign(&mut self, offset: usize) {
+                self.0 += offset;
+            }
+        }
+
+        impl Sub<usize> for $address {
+            type Output = Self;
+
+            fn sub(self, offset: usize) -> Self {
+                Self(self.0 - offset)
+            }
+        }
+
+        /// The number of bytes between two addresses.
+        impl Sub for $address {
+            type Output = usize;
+
+            fn sub(self, other: Self) -> usize {
+                self.0 - other.0
+            }
+        }
+
+        impl fmt::LowerHex for $address {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::LowerHex::fmt(&self.0, f)
+            }
+        }
+
+        impl fmt::UpperHex for $address {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::UpperHex::fmt(&self.0, f)
+            }
+        }
+
+        impl fmt::Display for $address {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{:#x}", self.0)
+            }
+        }
+    };
+}
+
+/// A physical address.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct PhysicalAddress(pub usize);
+
+/// A virtual address.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct VirtualAddress(pub usize);
+
+impl_address!(PhysicalAddress);
+impl_address!(VirtualAddress);
+
+impl PhysicalAddress {
+    /// Returns the number of the page holding the address.
+    pub const fn ppn(&self) -> PhysicalPageNumber {
+        PhysicalPageNumber::from_physical_address(self.0)
+    }
+}
+
+impl VirtualAddress {
+    /// Returns the number of the page holding the address.
+    pub const fn vpn(&self) -> VirtualPageNumber {
+        VirtualPageNumber::from_virtual_address(self.0)
+    }
+
+    /// Returns a pointer to the memory the address maps.
+    pub const fn as_ptr<T>(&self) -> *const T {
+        self.0 as *const T
+    }
+
+    /// Returns a mutable pointer to the memory the address maps.
+    pub const fn as_mut_ptr<T>(&self) -> *mut T {
+        self.0 as *mut T
+    }
+}
+
+impl From<PhysicalPageNumber> for PhysicalAddress {
+    /// Returns the address of the first byte of the page.
+    fn from(ppn: PhysicalPageNumber) -> Self {
+        Self(ppn.to_physical_address())
+    }
+}
+
+impl From<VirtualPageNumber> for VirtualAddress {
+    /// Returns the address of the first byte of the page.
+    fn from(vpn: VirtualPageNumber) -> Self {
+        Self(vpn.to_virtual_address())
+    }
+}
+
+impl<T> From<*const T> for VirtualAddress {
+    fn from(pointer: *const T) -> Self {
+        Self(pointer as usize)
+    }
+}
+
+impl<T> From<*mut T> for VirtualAddress {
+    fn from(pointer: *mut T) -> Self {
+        Self(pointer as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alignment() {
+        let address = PhysicalAddress::new(0x8020_1234);
+
+        assert!(!address.is_page_aligned());
+        assert!(address.is_aligned(4));
+        assert_eq!(address.page_offset(), 0x234);
+        assert_eq!(
+            address.align_down(0x1000),
+            PhysicalAddress::new(0x8020_1000)
+        );
+        assert_eq!(
+            address.align_up(0x1000),
+            Some(PhysicalAddress::new(0x8020_2000))
+        );
+        assert_eq!(
+            address.align_up(0x20_0000),
+            Some(PhysicalAddress::new(0x8040_0000))
+        );
+
+        let aligned_address = PhysicalAddress::new(0x8020_0000);
+        assert_eq!(aligned_address.align_up(0x1000), Some(aligned_address));
+
+        assert_eq!(VirtualAddress::new(usize::MAX).align_up(0x1000), None);
+    }
+
+    #[test]
+    fn test_offset_arithmetic() {
+        let start = VirtualAddress::new(0x1000);
+        let mut end = start + 0x2000;
+
+        assert_eq!(end, VirtualAddress::new(0x3000));
+        assert_eq!(end - start, 0x2000);
+        assert_eq!(end - 0x1000, VirtualAddress::new(0x2000));
+
+        end += 0x10;
+        assert_eq!(end.raw_address(), 0x3010);
+
+        assert_eq!(VirtualAddress::new(usize::MAX).checked_add(1), None);
+    }
+
+    #[test]
+    fn test_page_number_conversions() {
+        let physical_address = PhysicalAddress::new(0x8020_0123);
+        assert_eq!(physical_address.ppn(), PhysicalPageNumber(0x8_0200));
+        assert_eq!(
+            PhysicalAddress::from(physical_address.ppn()),
+            PhysicalAddress::new(0x8020_0000)
+        );
+
+        let virtual_address = VirtualAddress::new(0x4000_5678);
+        assert_eq!(virtual_address.vpn(), VirtualPageNumber(0x4_0005));
+        assert_eq!(
+            VirtualAddress::from(virtual_address.vpn()),
+            VirtualAddress::new(0x4000_5000)
+        );
+    }
+
+    #[test]
+    fn test_formatting() {
+        let address = PhysicalAddress::new(0x8020_0000);
+
+        assert_eq!(format!("{:#x}", address), "0x80200000");
+        assert_eq!(format!("{}", address), "0x80200000");
+        assert_eq!(format!("{:#010X}", VirtualAddress::new(0xAB)), "0x000000AB");
+    }
+}
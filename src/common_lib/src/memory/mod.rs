@@ -1,3 +1,7 @@
+mod address;
+
+pub use address::{PhysicalAddress, VirtualAddress};
+
 /// Represents a physical page number (PPN).
 ///
 /// This is the top 44 bits of a 56-bit physical address. The structure stores
@@ -80,8 +84,9 @@ impl PhysicalPageNumber {
 /// the VPN with bit 0 representing the start of the VPN (the address
 /// right-shifted by 12 bits), as it does not include the 12-bit page offset.
 ///
-/// This virtual page number object only supports sv39 mode where virtual
-/// addresses are a total of 39 bits (12-bit page offset + 27-bit VPN).
+/// The level specific getters follow sv39, where virtual addresses are a
+/// total of 39 bits (12-bit page offset + 27-bit VPN). `get_level_index`
+/// covers the root levels of sv48 and sv57 as well.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(transparent)]
 pub struct VirtualPageNumber(pub usize);
@@ -174,6 +179,20 @@ impl VirtualPageNumber {
     pub const fn get_level_0_index(&self) -> usize {
         (self.0 & 0x1FF) as usize
     }
+
+    /// Get the index for the page table at any level, where level 0 tables
+    /// map 4KiB pages and each level above covers 9 more bits of the VPN.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The level of the page table, up to 4 for the root of sv57.
+    ///
+    /// # Returns
+    ///
+    /// The 9-bit index for the page table at that level.
+    pub const fn get_level_index(&self, level: usize) -> usize {
+        (self.0 >> (9 * level)) & 0x1FF
+    }
 }
 
 /// Represents a contiguous region of memory with a starting address and size.
@@ -195,7 +214,8 @@ impl VirtualPageNumber {
 /// let kernel_region = MemoryRegion::new(0x8000_0000, 0x0200_0000); // 32MB kernel region.
 /// assert_eq!(kernel_region.end(), 0x81FF_FFFF);
 /// ```
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
 pub struct MemoryRegion {
     /// The inclusive starting address of the memory region.
     pub start: usize,
@@ -489,6 +509,20 @@ mod tests {
             assert_eq!(vpn.get_level_0_index(), 0b101010101);
         }
 
+        #[test]
+        fn test_get_level_index_matches_every_level() {
+            // An sv57 VPN with a different index at each of its five levels.
+            let vpn = VirtualPageNumber((5 << 36) | (4 << 27) | (3 << 18) | (2 << 9) | 1);
+
+            assert_eq!(vpn.get_level_index(0), vpn.get_level_0_index());
+            assert_eq!(vpn.get_level_index(1), vpn.get_level_1_index());
+            assert_eq!(vpn.get_level_index(2), vpn.get_level_2_index());
+
+            for level in 0..5 {
+                assert_eq!(vpn.get_level_index(level), level + 1);
+            }
+        }
+
         #[test]
         fn test_conversions_round_trip() {
             // Test a round trip conversion from virtual address to VPN and
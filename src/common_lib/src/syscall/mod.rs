@@ -0,0 +1,389 @@
+//! The system call ABI shared by the kernel and user programs.
+//!
+//! A user program makes a system call with `ecall`. The system call number
+//! goes in a7 and up to six arguments go in a0 through a5. The kernel returns
+//! a single value in a0 and preserves every other register. Read as signed,
+//! a value from -1 down to `-MAX_ERROR_CODE` is a negated `SyscallError`
+//! code and any other value is a successful result. `encode_result` and
+//! `decode_result` convert between that value and a `Result`.
+
+use core::fmt;
+
+/// The most arguments a system call takes.
+pub const MAX_SYSCALL_ARGUMENTS: usize = 6;
+
+/// The file descriptor of the console input.
+pub const STDIN: usize = 0;
+
+/// The file descriptor of the console output.
+pub const STDOUT: usize = 1;
+
+/// The file descriptor of the console error output, which currently goes to
+/// the same place as `STDOUT`.
+pub const STDERR: usize = 2;
+
+/// The `open` flag that creates the file if it does not exist.
+pub const OPEN_CREATE: usize = 1 << 0;
+
+/// The `open` flag that empties the file once it is open.
+pub const OPEN_TRUNCATE: usize = 1 << 1;
+
+/// The `seek` origin that measures the offset from the start of the file.
+pub const SEEK_SET: usize = 0;
+
+/// The `seek` origin that measures the offset from the current offset.
+pub const SEEK_CURRENT: usize = 1;
+
+/// The `seek` origin that measures the offset from the end of the file.
+pub const SEEK_END: usize = 2;
+
+/// The `wait` flag that returns zero at once instead of blocking if no child
+/// has exited yet.
+pub const WAIT_NO_HANG: usize = 1 << 0;
+
+/// The `map_anonymous` and `shm_map` protection bit that lets the program
+/// read the memory.
+pub const PROTECTION_READ: usize = 1 << 0;
+
+/// The `map_anonymous` and `shm_map` protection bit that lets the program
+/// write the memory.
+pub const PROTECTION_WRITE: usize = 1 << 1;
+
+/// The `map_anonymous` and `shm_map` protection bit that lets the program
+/// execute the memory.
+pub const PROTECTION_EXECUTE: usize = 1 << 2;
+
+/// The numbers of the system calls, passed in a7.
+#[repr(usize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyscallNumber {
+    /// `write(fd, buffer, length) -> written`: writes bytes to a file
+    /// descriptor and returns how many were written.
+    Write = 0,
+
+    /// `exit(code) -> !`: ends the calling program. Its parent collects the
+    /// code with `wait`.
+    Exit = 1,
+
+    /// `yield() -> 0`: lets other threads run before returning.
+    Yield = 2,
+
+    /// `fork() -> pid`: starts a copy of the calling program. Returns the
+    /// ID of the new process to the caller and zero to the copy.
+    Fork = 3,
+
+    /// `exec(name, name_length) -> !`: replaces the calling program with the
+    /// named program, which starts with every register but sp zero. Only
+    /// returns on failure.
+    Exec = 4,
+
+    /// `map_anonymous(length, protection) -> address`: reserves zeroed
+    /// memory of at least `length` bytes with the `PROTECTION_*` bits in
+    /// `protection` and returns its page aligned address. Pages are only
+    /// allocated when first touched.
+    MapAnonymous = 5,
+
+    /// `open(path, path_length, flags) -> fd`: opens the file or directory
+    /// at an absolute path with the `OPEN_*` bits in `flags` and returns the
+    /// lowest free file descriptor, which reads and writes from offset zero.
+    Open = 6,
+
+    /// `read(fd, buffer, length) -> read`: reads bytes from a file
+    /// descriptor and returns how many were read, which is zero at the end
+    /// of a file. Reading the console waits until input arrives.
+    Read = 7,
+
+    /// `close(fd) -> 0`: closes a file descriptor.
+    Close = 8,
+
+    /// `seek(fd, offset, origin) -> offset`: moves the offset of a file
+    /// descriptor to the signed `offset` from the `SEEK_*` origin and returns
+    /// the new offset from the start of the file.
+    Seek = 9,
+
+    /// `dup(fd) -> fd`: opens the lowest free file descriptor on the same
+    /// file as another. The two share the offset.
+    Dup = 10,
+
+    /// `pipe(fds) -> 0`: creates a pipe and stores the file descriptors of
+    /// its read end and its write end as two dwords at `fds`.
+    Pipe = 11,
+
+    /// `shm_create(name, name_length, length) -> fd`: creates a zeroed
+    /// shared memory object of at least `length` bytes and returns the lowest
+    /// free file descriptor on it. A `name_length` of zero creates an
+    /// anonymous object, which other programs can only reach through
+    /// descriptors `fork` copies.
+    ShmCreate = 12,
+
+    /// `shm_open(name, name_length) -> fd`: opens the lowest free file
+    /// descriptor on the shared memory object with a name.
+    ShmOpen = 13,
+
+    /// `shm_map(fd, protection) -> address`: maps the whole shared memory
+    /// object of a file descriptor with the `PROTECTION_*` bits in
+    /// `protection` and returns its page aligned address. Each mapping has
+    /// its own protection.
+    ShmMap = 14,
+
+    /// `futex_wait(address, expected) -> 0`: blocks until `futex_wake` is
+    /// called on the aligned 32-bit word at `address` if the word holds the
+    /// low 32 bits of `expected`, and fails with `SyscallError::TryAgain` at
+    /// once if not. The word must be writable.
+    FutexWait = 15,
+
+    /// `futex_wake(address, count) -> woken`: wakes at most `count` of the
+    /// programs blocked in `futex_wait` on the word at `address` and returns
+    /// how many were woken.
+    FutexWake = 16,
+
+    /// `wait(pid, status, flags) -> pid`: waits for the child with ID `pid`,
+    /// or any child if `pid` is zero, to exit, stores its exit code as a
+    /// dword at `status` unless `status` is zero, and returns its ID. With
+    /// `WAIT_NO_HANG` in `flags`, returns zero instead of blocking.
+    Wait = 17,
+}
+
+impl SyscallNumber {
+    /// The number of system calls. Numbers run from zero to one less than
+    /// this.
+    pub const COUNT: usize = 18;
+
+    /// Decodes a system call number.
+    ///
+    /// # Returns
+    ///
+    /// The system call, or `None` if no system call has the number.
+    pub const fn from_raw(number: usize) -> Option<Self> {
+        match number {
+            0 => Some(Self::Write),
+            1 => Some(Self::Exit),
+            2 => Some(Self::Yield),
+            3 => Some(Self::Fork),
+            4 => Some(Self::Exec),
+            5 => Some(Self::MapAnonymous),
+            6 => Some(Self::Open),
+            7 => Some(Self::Read),
+            8 => Some(Self::Close),
+            9 => Some(Self::Seek),
+            10 => Some(Self::Dup),
+            11 => Some(Self::Pipe),
+            12 => Some(Self::ShmCreate),
+            13 => Some(Self::ShmOpen),
+            14 => Some(Self::ShmMap),
+            15 => Some(Self::FutexWait),
+            16 => Some(Self::FutexWake),
+            17 => Some(Self::Wait),
+            _ => None,
+        }
+    }
+}
+
+/// The reasons a system call fails. The kernel returns the negated code.
+#[repr(usize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyscallError {
+    /// No system call has the number in a7.
+    NoSuchSyscall = 1,
+
+    /// A pointer argument points outside the memory the program may access.
+    BadAddress = 2,
+
+    /// A file descriptor argument is not open.
+    BadFileDescriptor = 3,
+
+    /// An argument is out of range.
+    InvalidArgument = 4,
+
+    /// The named object does not exist.
+    NotFound = 5,
+
+    /// The kernel ran out of memory or process slots.
+    OutOfMemory = 6,
+
+    /// The file to read or write is a directory.
+    IsADirectory = 7,
+
+    /// The file system cannot be written.
+    ReadOnly = 8,
+
+    /// The process or the kernel has no free file descriptor or open file.
+    TooManyOpenFiles = 9,
+
+    /// The file descriptor does not support `seek`, like the console.
+    NotSeekable = 10,
+
+    /// The device holding the file failed.
+    IoError = 11,
+
+    /// Every read end of the pipe is closed.
+    BrokenPipe = 12,
+
+    /// An object with the name already exists.
+    AlreadyExists = 13,
+
+    /// The futex word no longer holds the expected value, so the program
+    /// should check it again.
+    TryAgain = 14,
+
+    /// The program has no child to wait for.
+    NoChild = 15,
+}
+
+/// The largest `SyscallError` code.
+pub const MAX_ERROR_CODE: usize = 15;
+
+impl SyscallError {
+    /// Decodes an error code.
+    ///
+    /// # Returns
+    ///
+    /// The error, or `None` if no error has the code.
+    pub const fn from_code(code: usize) -> Option<Self> {
+        match code {
+            1 => Some(Self::NoSuchSyscall),
+            2 => Some(Self::BadAddress),
+            3 => Some(Self::BadFileDescriptor),
+            4 => Some(Self::InvalidArgument),
+            5 => Some(Self::NotFound),
+            6 => Some(Self::OutOfMemory),
+            7 => Some(Self::IsADirectory),
+            8 => Some(Self::ReadOnly),
+            9 => Some(Self::TooManyOpenFiles),
+            10 => Some(Self::NotSeekable),
+            11 => Some(Self::IoError),
+            12 => Some(Self::BrokenPipe),
+            13 => Some(Self::AlreadyExists),
+            14 => Some(Self::TryAgain),
+            15 => Some(Self::NoChild),
+            _ => None,
+        }
+    }
+
+    /// Returns the code of the error.
+    pub const fn code(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuchSyscall => write!(f, "no such system call"),
+            Self::BadAddress => write!(f, "bad address"),
+            Self::BadFileDescriptor => write!(f, "bad file descriptor"),
+            Self::InvalidArgument => write!(f, "invalid argument"),
+            Self::NotFound => write!(f, "not found"),
+            Self::OutOfMemory => write!(f, "out of memory"),
+            Self::IsADirectory => write!(f, "is a directory"),
+            Self::ReadOnly => write!(f, "read-only file system"),
+            Self::TooManyOpenFiles => write!(f, "too many open files"),
+            Self::NotSeekable => write!(f, "not seekable"),
+            Self::IoError => write!(f, "input/output error"),
+            Self::BrokenPipe => write!(f, "broken pipe"),
+            Self::AlreadyExists => write!(f, "already exists"),
+            Self::TryAgain => write!(f, "try again"),
+            Self::NoChild => write!(f, "no child process"),
+        }
+    }
+}
+
+/// Encodes the result of a system call as the value returned in a0.
+///
+/// # Arguments
+///
+/// * `result` - The result. Successful values must not fall in the range
+///   reserved for error codes.
+pub const fn encode_result(result: Result<usize, SyscallError>) -> usize {
+    match result {
+        Ok(value) => value,
+        Err(error) => error.code().wrapping_neg(),
+    }
+}
+
+/// Decodes the value a system call returned in a0.
+///
+/// # Arguments
+///
+/// * `value` - The value of a0 after the `ecall`.
+pub const fn decode_result(value: usize) -> Result<usize, SyscallError> {
+    match SyscallError::from_code(value.wrapping_neg()) {
+        Some(error) => Err(error),
+        None => Ok(value),
+    }
+}
+
+/// Makes a system call. Used by user programs.
+///
+/// # Arguments
+///
+/// * `number` - The system call to make.
+/// * `arguments` - The arguments, with unused ones set to zero.
+///
+/// # Returns
+///
+/// The decoded result in a0.
+///
+/// # Safety
+///
+/// The arguments must be valid for the system call, such as pointers to
+/// memory the program owns.
+#[cfg(target_arch = "riscv64")]
+pub unsafe fn syscall(
+    number: SyscallNumber,
+    arguments: [usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let mut value = arguments[0];
+
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") value,
+            in("a1") arguments[1],
+            in("a2") arguments[2],
+            in("a3") arguments[3],
+            in("a4") arguments[4],
+            in("a5") arguments[5],
+            in("a7") number as usize,
+            options(nostack)
+        );
+    }
+
+    decode_result(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syscall_numbers_round_trip() {
+        for number in 0..SyscallNumber::COUNT {
+            let syscall = SyscallNumber::from_raw(number).unwrap();
+
+            assert_eq!(syscall as usize, number);
+        }
+
+        assert_eq!(SyscallNumber::from_raw(SyscallNumber::COUNT), None);
+    }
+
+    #[test]
+    fn test_result_encoding() {
+        for value in [0, 1, 4096, usize::MAX - MAX_ERROR_CODE] {
+            assert_eq!(decode_result(encode_result(Ok(value))), Ok(value));
+        }
+
+        for code in 1..=MAX_ERROR_CODE {
+            let error = SyscallError::from_code(code).unwrap();
+
+            assert_eq!(
+                encode_result(Err(error)),
+                (code as isize).wrapping_neg() as usize
+            );
+            assert_eq!(decode_result(encode_result(Err(error))), Err(error));
+        }
+
+        assert_eq!(SyscallError::from_code(0), None);
+        assert_eq!(SyscallError::from_code(MAX_ERROR_CODE + 1), None);
+    }
+}
@@ -0,0 +1,45 @@
+//! Symbolized backtraces of kernel stacks.
+
+use crate::{console::ConsoleWriter, memory, symbols};
+use common_lib::backtrace::write_backtrace;
+use core::ops::Range;
+
+/// Prints the frames of a kernel stack to the console.
+///
+/// # Arguments
+///
+/// * `frame_pointer` - The value of s0 in the innermost function.
+/// * `stack_pointer` - The value of sp in the innermost function.
+pub fn print(frame_pointer: usize, stack_pointer: usize) {
+    let stack_range = readable_stack_range(stack_pointer);
+
+    // Only mapped readable pages are part of the range.
+    let _ = unsafe {
+        write_backtrace(
+            &mut ConsoleWriter,
+            frame_pointer,
+            stack_range,
+            &symbols::lookup,
+        )
+    };
+}
+
+/// Returns the range of mapped memory above a stack pointer that a backtrace
+/// may read.
+///
+/// The boot hart still runs on the boot stage's stack, whose bounds the
+/// kernel does not know, so the range extends page by page from the stack
+/// pointer for as long as the pages are mapped readable.
+fn readable_stack_range(stack_pointer: usize) -> Range<usize> {
+    const PAGE_SIZE: usize = 4096;
+    const MAX_STACK_SIZE: usize = 64 * 1024;
+
+    let first_page = stack_pointer & !(PAGE_SIZE - 1);
+    let mut end = first_page;
+
+    while end - first_page < MAX_STACK_SIZE && memory::is_readable(end) {
+        end += PAGE_SIZE;
+    }
+
+    stack_pointer..end.max(stack_pointer)
+}
@@ -0,0 +1,483 @@
+//! Block devices.
+//!
+//! Drivers implement `BlockDevice` and register their devices by name with
+//! `register`. Everything else, such as file systems, finds a device with
+//! `find` and transfers whole blocks through the returned `BlockDeviceHandle`,
+//! without knowing which driver is behind it.
+//!
+//! Transfers are split into requests no larger than the device accepts. Every
+//! request is queued on its device and passed to the driver with
+//! `BlockDevice::submit` as soon as the driver has room for it. The driver
+//! reports the outcome with `complete`, usually from its interrupt handler,
+//! which wakes the thread waiting for the request and submits the next queued
+//! one. Transfers block, so they must be made from kernel threads.
+
+use crate::task::WaitQueue;
+use core::fmt;
+use kernel_lib::{
+    ring_buffer::RingBuffer,
+    sync::{SpinLock, SpinLockIrqSave},
+};
+
+/// The most block devices that can be registered.
+pub const MAX_BLOCK_DEVICES: usize = 4;
+
+/// The most requests that can be queued or in flight at once, across every
+/// device.
+pub const MAX_REQUESTS: usize = 16;
+
+/// The reasons a block device could not be registered or a transfer failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    /// All `MAX_BLOCK_DEVICES` devices are registered already.
+    TooManyDevices,
+
+    /// A device with the name is registered already.
+    NameInUse,
+
+    /// The buffer length is not a whole number of blocks.
+    UnalignedLength,
+
+    /// The transfer reaches past the end of the device.
+    OutOfRange,
+
+    /// The device does not allow writes.
+    ReadOnly,
+
+    /// The buffer is not mapped in the kernel's address space.
+    BadBuffer,
+
+    /// The device cannot take another request until one completes. Only
+    /// returned by `BlockDevice::submit`, after which the request stays
+    /// queued.
+    Busy,
+
+    /// The device does not support the request.
+    Unsupported,
+
+    /// The device reported an I/O error.
+    Io,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyDevices => {
+                write!(f, "all {} block devices are in use", MAX_BLOCK_DEVICES)
+            }
+            Self::NameInUse => write!(f, "the name is in use"),
+            Self::UnalignedLength => write!(f, "the length is not a whole number of blocks"),
+            Self::OutOfRange => write!(f, "the transfer is past the end of the device"),
+            Self::ReadOnly => write!(f, "the device is read only"),
+            Self::BadBuffer => write!(f, "the buffer is not mapped"),
+            Self::Busy => write!(f, "the device is busy"),
+            Self::Unsupported => write!(f, "unsupported request"),
+            Self::Io => write!(f, "I/O error"),
+        }
+    }
+}
+
+/// The direction of a transfer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockOperation {
+    /// Copies blocks from the device into the buffer.
+    Read,
+
+    /// Copies blocks from the buffer to the device.
+    Write,
+}
+
+/// A transfer of consecutive blocks that a device performs in one go.
+#[derive(Debug, Copy, Clone)]
+pub struct BlockRequest {
+    pub operation: BlockOperation,
+
+    /// The first block of the transfer.
+    pub first_block: usize,
+
+    /// The number of blocks, no more than `max_blocks_per_request`.
+    pub block_count: usize,
+
+    /// The kernel virtual address of the buffer, which holds `block_count`
+    /// blocks and stays valid until the request completes.
+    pub buffer_address: usize,
+}
+
+/// Identifies a request between `BlockDevice::submit` and `complete`.
+pub type RequestId = usize;
+
+/// A device that stores fixed size blocks.
+pub trait BlockDevice: Sync {
+    /// Returns the size of a block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// Returns the number of blocks of the device.
+    fn block_count(&self) -> usize;
+
+    /// Returns true if the device does not allow writes.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// Returns the most blocks a single request may transfer.
+    fn max_blocks_per_request(&self) -> usize;
+
+    /// Starts a request. The request is already checked against the size of
+    /// the device.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID to pass to `complete` once the request is done. It must
+    ///   not be called from within `submit`.
+    /// * `request` - The request.
+    ///
+    /// # Returns
+    ///
+    /// `BlockError::Busy` if the device cannot take the request until another
+    /// one completes, or any other error if the request cannot be started at
+    /// all.
+    fn submit(&self, id: RequestId, request: &BlockRequest) -> Result<(), BlockError>;
+}
+
+/// A registered device.
+#[derive(Copy, Clone)]
+struct Registration {
+    name: &'static str,
+    device: &'static dyn BlockDevice,
+}
+
+/// Where a request is in its life.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RequestState {
+    /// Waiting in the queue of its device.
+    Queued,
+
+    /// Passed to the driver.
+    Submitted,
+
+    /// Done, with the outcome the driver reported.
+    Completed(Result<(), BlockError>),
+}
+
+/// A request and the device it is for.
+#[derive(Debug, Copy, Clone)]
+struct RequestEntry {
+    device_index: usize,
+    request: BlockRequest,
+    state: RequestState,
+}
+
+/// The registered devices.
+static DEVICES: SpinLock<[Option<Registration>; MAX_BLOCK_DEVICES]> =
+    SpinLock::new([None; MAX_BLOCK_DEVICES]);
+
+/// Every request that is queued, in flight, or not yet collected by its
+/// thread, indexed by request ID.
+static REQUESTS: SpinLockIrqSave<[Option<RequestEntry>; MAX_REQUESTS]> =
+    SpinLockIrqSave::new([None; MAX_REQUESTS]);
+
+/// The IDs of the requests waiting to be submitted to each device, indexed
+/// like `DEVICES`.
+///
+/// The lock is also held while requests are submitted, so that a request the
+/// device turned away is back in the queue before a completion looks for the
+/// next one.
+static PENDING_REQUESTS: [SpinLockIrqSave<RingBuffer<RequestId, MAX_REQUESTS>>; MAX_BLOCK_DEVICES] =
+    [const { SpinLockIrqSave::new(RingBuffer::new()) }; MAX_BLOCK_DEVICES];
+
+/// The threads waiting for a request to complete or for a free request.
+static REQUEST_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// A registered block device.
+#[derive(Copy, Clone)]
+pub struct BlockDeviceHandle {
+    index: usize,
+    registration: Registration,
+}
+
+impl BlockDeviceHandle {
+    /// Returns the name the device was registered with.
+    pub fn name(&self) -> &'static str {
+        self.registration.name
+    }
+
+    /// Returns the size of a block in bytes.
+    pub fn block_size(&self) -> usize {
+        self.registration.device.block_size()
+    }
+
+    /// Returns the number of blocks of the device.
+    pub fn block_count(&self) -> usize {
+        self.registration.device.block_count()
+    }
+
+    /// Returns true if the device does not allow writes.
+    pub fn is_read_only(&self) -> bool {
+        self.registration.device.is_read_only()
+    }
+
+    /// Reads blocks, blocking until they are read.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_block` - The first block to read.
+    /// * `buffer` - Receives the blocks. Its length must be a whole number of
+    ///   blocks.
+    pub fn read_blocks(&self, first_block: usize, buffer: &mut [u8]) -> Result<(), BlockError> {
+        self.transfer(
+            BlockOperation::Read,
+            first_block,
+            buffer.as_mut_ptr() as usize,
+            buffer.len(),
+        )
+    }
+
+    /// Writes blocks, blocking until they are written.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_block` - The first block to write.
+    /// * `buffer` - The blocks to write. Its length must be a whole number of
+    ///   blocks.
+    #[allow(dead_code)]
+    pub fn write_blocks(&self, first_block: usize, buffer: &[u8]) -> Result<(), BlockError> {
+        if self.is_read_only() {
+            return Err(BlockError::ReadOnly);
+        }
+
+        self.transfer(
+            BlockOperation::Write,
+            first_block,
+            buffer.as_ptr() as usize,
+            buffer.len(),
+        )
+    }
+
+    /// Transfers blocks between the device and a buffer, split into requests
+    /// the device accepts.
+    fn transfer(
+        &self,
+        operation: BlockOperation,
+        first_block: usize,
+        buffer_address: usize,
+        length: usize,
+    ) -> Result<(), BlockError> {
+        let block_size = self.block_size();
+
+        if !length.is_multiple_of(block_size) {
+            return Err(BlockError::UnalignedLength);
+        }
+
+        let block_count = length / block_size;
+        if first_block
+            .checked_add(block_count)
+            .is_none_or(|end| end > self.block_count())
+        {
+            return Err(BlockError::OutOfRange);
+        }
+
+        let max_blocks_per_request = self.registration.device.max_blocks_per_request().max(1);
+        let mut done = 0;
+
+        while done < block_count {
+            let request_block_count = (block_count - done).min(max_blocks_per_request);
+
+            self.submit_and_wait(BlockRequest {
+                operation,
+                first_block: first_block + done,
+                block_count: request_block_count,
+                buffer_address: buffer_address + done * block_size,
+            })?;
+
+            done += request_block_count;
+        }
+
+        Ok(())
+    }
+
+    /// Queues a request and blocks until it completes.
+    fn submit_and_wait(&self, request: BlockRequest) -> Result<(), BlockError> {
+        let id = allocate_request(RequestEntry {
+            device_index: self.index,
+            request,
+            state: RequestState::Queued,
+        });
+
+        PENDING_REQUESTS[self.index]
+            .lock()
+            .push_back(id)
+            .expect("Every request fits in the queue of its device.");
+
+        dispatch(self.index);
+
+        let mut result = Ok(());
+        REQUEST_WAIT_QUEUE.wait_until(|| match REQUESTS.lock()[id] {
+            Some(RequestEntry {
+                state: RequestState::Completed(outcome),
+                ..
+            }) => {
+                result = outcome;
+                true
+            }
+            _ => false,
+        });
+
+        REQUESTS.lock()[id] = None;
+
+        // Threads waiting for a free request wait on the same queue.
+        REQUEST_WAIT_QUEUE.wake_all();
+
+        result
+    }
+}
+
+/// Registers a block device.
+///
+/// # Arguments
+///
+/// * `name` - The unique name to find the device by, such as "vda".
+/// * `device` - The device.
+pub fn register(name: &'static str, device: &'static dyn BlockDevice) -> Result<(), BlockError> {
+    let mut devices = DEVICES.lock();
+
+    if devices
+        .iter()
+        .flatten()
+        .any(|registration| registration.name == name)
+    {
+        return Err(BlockError::NameInUse);
+    }
+
+    let slot = devices
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(BlockError::TooManyDevices)?;
+
+    *slot = Some(Registration { name, device });
+
+    Ok(())
+}
+
+/// Finds a registered block device by name.
+///
+/// # Returns
+///
+/// The device, or `None` if no device has the name.
+pub fn find(name: &str) -> Option<BlockDeviceHandle> {
+    DEVICES
+        .lock()
+        .iter()
+        .enumerate()
+        .find_map(|(index, registration)| {
+            registration
+                .filter(|registration| registration.name == name)
+                .map(|registration| BlockDeviceHandle {
+                    index,
+                    registration,
+                })
+        })
+}
+
+/// Calls a function for every registered block device.
+///
+/// # Arguments
+///
+/// * `callback` - Called with each device.
+pub fn for_each_device(mut callback: impl FnMut(BlockDeviceHandle)) {
+    let devices = *DEVICES.lock();
+
+    for (index, registration) in devices.iter().enumerate() {
+        if let Some(registration) = *registration {
+            callback(BlockDeviceHandle {
+                index,
+                registration,
+            });
+        }
+    }
+}
+
+/// Reports the outcome of a request passed to `BlockDevice::submit`. May be
+/// called from interrupt handlers.
+///
+/// # Arguments
+///
+/// * `id` - The ID the request was submitted with.
+/// * `result` - The outcome of the request.
+pub fn complete(id: RequestId, result: Result<(), BlockError>) {
+    let device_index = {
+        let mut requests = REQUESTS.lock();
+
+        let Some(entry) = requests
+            .get_mut(id)
+            .and_then(|entry| entry.as_mut())
+            .filter(|entry| entry.state == RequestState::Submitted)
+        else {
+            return;
+        };
+
+        entry.state = RequestState::Completed(result);
+        entry.device_index
+    };
+
+    REQUEST_WAIT_QUEUE.wake_all();
+
+    // The device has room for another request now.
+    dispatch(device_index);
+}
+
+/// Takes a free request ID for a request, blocking until one is free.
+fn allocate_request(entry: RequestEntry) -> RequestId {
+    loop {
+        {
+            let mut requests = REQUESTS.lock();
+
+            if let Some(id) = requests.iter().position(|slot| slot.is_none()) {
+                requests[id] = Some(entry);
+                return id;
+            }
+        }
+
+        REQUEST_WAIT_QUEUE.wait_until(|| REQUESTS.lock().iter().any(|slot| slot.is_none()));
+    }
+}
+
+/// Submits the queued requests of a device until the device is busy or no
+/// request is left.
+fn dispatch(device_index: usize) {
+    let Some(registration) = DEVICES.lock()[device_index] else {
+        return;
+    };
+
+    let mut pending = PENDING_REQUESTS[device_index].lock();
+
+    while let Some(id) = pending.pop_front() {
+        let request = {
+            let mut requests = REQUESTS.lock();
+            let entry = requests[id]
+                .as_mut()
+                .expect("A queued request has an entry.");
+
+            entry.state = RequestState::Submitted;
+            entry.request
+        };
+
+        match registration.device.submit(id, &request) {
+            Ok(()) => {}
+            Err(BlockError::Busy) => {
+                if let Some(entry) = REQUESTS.lock()[id].as_mut() {
+                    entry.state = RequestState::Queued;
+                }
+
+                let _ = pending.push_front(id);
+                break;
+            }
+            Err(error) => {
+                if let Some(entry) = REQUESTS.lock()[id].as_mut() {
+                    entry.state = RequestState::Completed(Err(error));
+                }
+
+                REQUEST_WAIT_QUEUE.wake_all();
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! The kernel command line.
+//!
+//! The bootloader passes the command line in the "bootargs" property of the
+//! device tree's /chosen node. It is copied into kernel memory during early
+//! boot so that later code can read options from it without depending on the
+//! device tree staying mapped. Subsystems read their options through the typed
+//! accessors of the returned `CommandLine`.
+
+use crate::debug_println;
+use kernel_lib::{cmdline::CommandLine, sync::Once};
+
+/// The longest command line the kernel keeps. Longer command lines are
+/// truncated.
+const MAX_COMMAND_LINE_LENGTH: usize = 1024;
+
+/// A copy of the command line in kernel memory.
+struct CommandLineBuffer {
+    bytes: [u8; MAX_COMMAND_LINE_LENGTH],
+
+    /// The number of valid bytes in `bytes`.
+    length: usize,
+}
+
+impl CommandLineBuffer {
+    fn as_str(&self) -> &str {
+        // The buffer only ever holds a prefix of a &str cut on a character
+        // boundary.
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.length]) }
+    }
+}
+
+static COMMAND_LINE: Once<CommandLineBuffer> = Once::new();
+
+/// Copies the command line into kernel memory. Called once by the boot hart
+/// before any other hart reads the command line. Later calls are ignored.
+///
+/// # Arguments
+///
+/// * `command_line` - The command line from the device tree.
+pub fn initialize(command_line: &str) {
+    let mut length = command_line.len().min(MAX_COMMAND_LINE_LENGTH);
+
+    // Truncate on a character boundary so the stored text stays valid UTF-8.
+    while !command_line.is_char_boundary(length) {
+        length -= 1;
+    }
+
+    if length < command_line.len() {
+        debug_println!(
+            "Kernel command line truncated to {} bytes.",
+            MAX_COMMAND_LINE_LENGTH
+        );
+    }
+
+    COMMAND_LINE.call_once(|| {
+        let mut bytes = [0; MAX_COMMAND_LINE_LENGTH];
+        bytes[..length].copy_from_slice(&command_line.as_bytes()[..length]);
+
+        CommandLineBuffer { bytes, length }
+    });
+}
+
+/// Returns the kernel command line, which is empty if there is none.
+///
+/// # Examples
+///
+/// ```ignore
+/// let log_level = cmdline::command_line().get_u64("loglevel").unwrap_or(4);
+/// ```
+pub fn command_line() -> CommandLine<'static> {
+    CommandLine::new(COMMAND_LINE.get().map_or("", CommandLineBuffer::as_str))
+}
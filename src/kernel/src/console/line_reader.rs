@@ -0,0 +1,55 @@
+use super::{poll_byte, read_byte, write_bytes};
+use kernel_lib::line_editor::LineEditor;
+
+/// Reads lines of console input, echoing what is typed.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut line_reader = LineReader::<128>::new();
+///
+/// loop {
+///     if let Some(line) = line_reader.poll_line() {
+///         run_command(line);
+///     }
+///
+///     wait_for_interrupt();
+/// }
+/// ```
+pub struct LineReader<const CAPACITY: usize> {
+    editor: LineEditor<CAPACITY>,
+}
+
+impl<const CAPACITY: usize> LineReader<CAPACITY> {
+    /// Creates a reader with an empty line.
+    pub const fn new() -> Self {
+        Self {
+            editor: LineEditor::new(),
+        }
+    }
+
+    /// Processes the input waiting on the console without blocking.
+    ///
+    /// # Returns
+    ///
+    /// The line once it has been ended with enter, or `None` if the input so
+    /// far does not complete a line. Input after the end of a line stays on
+    /// the console for the next call.
+    pub fn poll_line(&mut self) -> Option<&str> {
+        while let Some(byte) = poll_byte() {
+            if self.editor.push_byte(byte, write_bytes) {
+                return Some(self.editor.line());
+            }
+        }
+
+        None
+    }
+
+    /// Reads a line, spinning until it has been ended with enter.
+    #[allow(dead_code)]
+    pub fn read_line(&mut self) -> &str {
+        while !self.editor.push_byte(read_byte(), write_bytes) {}
+
+        self.editor.line()
+    }
+}
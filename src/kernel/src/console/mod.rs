@@ -0,0 +1,336 @@
+//! The kernel console.
+//!
+//! Console backends describe themselves with a static `ConsoleDevice` and
+//! register it with `register`. `debug_print!` and `debug_println!` write to
+//! every active device in registration order.
+//!
+//! `initialize` registers the SBI debug console as a boot console so output
+//! works before any driver has started. Device drivers register their device
+//! as the stdout console when it is the one the /chosen "stdout-path" names.
+//! Boot consoles are deactivated once a stdout console registers, since they
+//! usually drive the same serial port through the firmware. Secondary
+//! consoles, such as a virtio console next to the UART, receive output
+//! alongside the stdout console.
+//!
+//! Input is polled from the first active device that can read. `LineReader`
+//! collects it into lines with basic editing for interactive use. Drivers
+//! whose device interrupts when input arrives call `notify_input`, which lets
+//! kernel threads block in `wait_for_input` instead of polling.
+
+mod line_reader;
+
+pub use line_reader::LineReader;
+
+use crate::{
+    log,
+    sbi::debug_console::SBI_CONSOLE_DEVICE,
+    task::{self, WaitQueue},
+};
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+    time::Duration,
+};
+
+/// The maximum number of console devices that can be registered.
+pub const MAX_CONSOLE_DEVICES: usize = 4;
+
+/// A backend that console output can be written to.
+pub struct ConsoleDevice {
+    /// A short name for the device used in log messages, such as "uart".
+    pub name: &'static str,
+
+    /// Writes bytes to the device, blocking until they are written. Line feeds
+    /// are written as is, so devices that need a carriage return add it. It
+    /// may be called from trap context and from several harts at once.
+    pub write: fn(bytes: &[u8]),
+
+    /// Reads the bytes waiting on the device into a buffer without blocking
+    /// and returns how many were read, or `None` if the device has no input.
+    pub read: Option<fn(buffer: &mut [u8]) -> usize>,
+}
+
+/// The role a console device is registered in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConsoleKind {
+    /// A device used until a stdout console registers.
+    Boot,
+
+    /// The device named by the /chosen "stdout-path".
+    Stdout,
+
+    /// A device that receives output alongside the stdout console.
+    Secondary,
+}
+
+/// The reasons a console device could not be registered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConsoleError {
+    /// `MAX_CONSOLE_DEVICES` devices are already registered.
+    TableFull,
+}
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TableFull => write!(f, "console device table full"),
+        }
+    }
+}
+
+/// A registered console device.
+struct ConsoleSlot {
+    /// The device, or null if the slot is free.
+    device: AtomicPtr<ConsoleDevice>,
+
+    /// True if the device is registered as a boot console.
+    is_boot_console: AtomicBool,
+
+    /// True while output is written to the device. Set last during
+    /// registration, so a set flag implies the other fields are valid.
+    is_active: AtomicBool,
+}
+
+/// The registered console devices in registration order.
+static CONSOLE_SLOTS: [ConsoleSlot; MAX_CONSOLE_DEVICES] = [const {
+    ConsoleSlot {
+        device: AtomicPtr::new(core::ptr::null_mut()),
+        is_boot_console: AtomicBool::new(false),
+        is_active: AtomicBool::new(false),
+    }
+}; MAX_CONSOLE_DEVICES];
+
+/// True once a stdout console has registered.
+static HAS_STDOUT_CONSOLE: AtomicBool = AtomicBool::new(false);
+
+/// True once a device notifies the console when input arrives.
+static ARE_INPUT_NOTIFICATIONS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Set by `notify_input` and cleared by the thread it wakes.
+static IS_INPUT_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// The threads blocked in `wait_for_input`.
+static INPUT_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// Registers the SBI debug console as the boot console. Must be called before
+/// anything is printed, since output is dropped while no device is active.
+pub fn initialize() {
+    let _ = register(&SBI_CONSOLE_DEVICE, ConsoleKind::Boot);
+}
+
+/// Registers a console device and starts writing output to it.
+///
+/// Registering a stdout console deactivates every boot console.
+///
+/// # Arguments
+///
+/// * `device` - The device to register.
+/// * `kind` - The role of the device.
+pub fn register(device: &'static ConsoleDevice, kind: ConsoleKind) -> Result<(), ConsoleError> {
+    let device_pointer = device as *const ConsoleDevice as *mut ConsoleDevice;
+
+    let slot = CONSOLE_SLOTS
+        .iter()
+        .find(|slot| {
+            slot.device
+                .compare_exchange(
+                    core::ptr::null_mut(),
+                    device_pointer,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        })
+        .ok_or(ConsoleError::TableFull)?;
+
+    slot.is_boot_console
+        .store(kind == ConsoleKind::Boot, Ordering::Relaxed);
+
+    if kind == ConsoleKind::Stdout {
+        for other_slot in &CONSOLE_SLOTS {
+            if other_slot.is_boot_console.load(Ordering::Relaxed) {
+                other_slot.is_active.store(false, Ordering::Release);
+            }
+        }
+    }
+
+    slot.is_active.store(true, Ordering::Release);
+
+    if kind == ConsoleKind::Stdout {
+        HAS_STDOUT_CONSOLE.store(true, Ordering::Release);
+    }
+
+    Ok(())
+}
+
+/// Returns true if a stdout console is registered.
+pub fn has_stdout_console() -> bool {
+    HAS_STDOUT_CONSOLE.load(Ordering::Acquire)
+}
+
+/// Calls a function with every registered console device and whether output
+/// is currently written to it.
+///
+/// # Arguments
+///
+/// * `callback` - The function to call for each device.
+pub fn for_each_device(mut callback: impl FnMut(&'static ConsoleDevice, bool)) {
+    for slot in &CONSOLE_SLOTS {
+        let device = slot.device.load(Ordering::Acquire);
+
+        if !device.is_null() {
+            // Only pointers to static devices are stored by `register`.
+            callback(unsafe { &*device }, slot.is_active.load(Ordering::Acquire));
+        }
+    }
+}
+
+/// Writes bytes to every active console device.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to write.
+pub fn write_bytes(bytes: &[u8]) {
+    for device in active_devices() {
+        (device.write)(bytes);
+    }
+}
+
+/// Reads the bytes waiting on the console without blocking.
+///
+/// Input is read from the first active device that supports reading, which
+/// is the stdout console once one has registered.
+///
+/// # Arguments
+///
+/// * `buffer` - Receives the bytes.
+///
+/// # Returns
+///
+/// The number of bytes read, which is zero if no input is waiting.
+pub fn poll_input(buffer: &mut [u8]) -> usize {
+    active_devices()
+        .find_map(|device| device.read)
+        .map_or(0, |read| read(buffer))
+}
+
+/// Reads a byte from the console without blocking.
+///
+/// # Returns
+///
+/// The byte, or `None` if no input is waiting.
+pub fn poll_byte() -> Option<u8> {
+    let mut byte = 0;
+
+    match poll_input(core::slice::from_mut(&mut byte)) {
+        0 => None,
+        _ => Some(byte),
+    }
+}
+
+/// Reads a byte from the console, spinning until one arrives.
+pub fn read_byte() -> u8 {
+    loop {
+        if let Some(byte) = poll_byte() {
+            return byte;
+        }
+
+        core::hint::spin_loop();
+    }
+}
+
+/// Records that the input device will call `notify_input` from now on, so
+/// `wait_for_input` can block until it does.
+pub fn enable_input_notifications() {
+    ARE_INPUT_NOTIFICATIONS_ENABLED.store(true, Ordering::Release);
+}
+
+/// Wakes the threads waiting for input. Called by drivers, usually from an
+/// interrupt handler, after new input becomes readable.
+pub fn notify_input() {
+    IS_INPUT_PENDING.store(true, Ordering::Release);
+    INPUT_WAIT_QUEUE.wake_all();
+}
+
+/// Blocks the calling kernel thread until new input may be readable.
+///
+/// The thread sleeps until `notify_input` is called. Without input
+/// notifications it sleeps for the poll interval instead. Callers poll the
+/// console until it is empty before waiting, since input that was already
+/// waiting raises no notification.
+///
+/// # Arguments
+///
+/// * `poll_interval` - How long to sleep when no device notifies input.
+pub fn wait_for_input(poll_interval: Duration) {
+    if ARE_INPUT_NOTIFICATIONS_ENABLED.load(Ordering::Acquire) {
+        INPUT_WAIT_QUEUE.wait_until(|| IS_INPUT_PENDING.swap(false, Ordering::AcqRel));
+    } else {
+        task::sleep(poll_interval);
+    }
+}
+
+/// Returns an iterator over the active console devices in registration
+/// order.
+fn active_devices() -> impl Iterator<Item = &'static ConsoleDevice> {
+    CONSOLE_SLOTS
+        .iter()
+        .filter(|slot| slot.is_active.load(Ordering::Acquire))
+        // Active slots always hold a pointer to a static device.
+        .map(|slot| unsafe { &*slot.device.load(Ordering::Relaxed) })
+}
+
+/// A formatter that writes to every active console device and records the
+/// text in the kernel log.
+pub struct ConsoleWriter;
+
+impl Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        log::record(s.as_bytes());
+        write_bytes(s.as_bytes());
+
+        Ok(())
+    }
+}
+
+/// Prints formatted text to the console without heap allocations.
+///
+/// This macro works similar to `format!` but writes directly to every active
+/// console device.
+///
+/// # Examples
+///
+/// ```
+/// debug_print!("Hello, {}!", "world");
+/// debug_println!("Value = {}", 42);
+/// ```
+#[macro_export]
+macro_rules! debug_print {
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        use $crate::console::ConsoleWriter;
+        let _ = write!(ConsoleWriter, $($arg)*);
+    }};
+}
+
+/// Prints formatted text to the console, followed by a newline.
+///
+/// This macro works similar to `format!` but writes directly to every active
+/// console device.
+///
+/// # Examples
+///
+/// ```
+/// debug_println!("Hello, {}!", "world");
+/// debug_println!("Value = {}", 42);
+/// ```
+#[macro_export]
+macro_rules! debug_println {
+    () => {
+        $crate::debug_print!("\n")
+    };
+    ($($arg:tt)*) => {{
+        $crate::debug_print!($($arg)*);
+        $crate::debug_print!("\n");
+    }};
+}
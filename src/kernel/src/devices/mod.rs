@@ -0,0 +1,368 @@
+//! The device manager, which binds drivers to the devices in the DTB.
+//!
+//! Every driver declares the compatible strings it handles and the stage of
+//! boot at which it is probed. `scan` walks the DTB once, skips disabled
+//! nodes, and records each node a driver matches as a `Device`, with its
+//! register ranges translated to physical addresses and its interrupts
+//! resolved. `probe` then calls the matching drivers of a stage, which set up
+//! their devices from the `Device` rather than searching the DTB themselves.
+//!
+//! A node is matched by the first of its compatible strings, which are listed
+//! from most to least specific, that a driver declares.
+
+use crate::{
+    debug_println,
+    drivers::{
+        plic::{self, PlicError},
+        uart::{self, UartError},
+        virtio::{self, VirtioError},
+    },
+};
+use common_lib::dtb::{Dtb, DtbNode, PhandleIndex};
+use core::fmt;
+use kernel_lib::sync::SpinLock;
+
+/// The most devices the manager records.
+pub const MAX_DEVICES: usize = 16;
+
+/// The most register ranges recorded for a device.
+pub const MAX_REGS: usize = 4;
+
+/// The most interrupts recorded for a device.
+pub const MAX_IRQS: usize = 4;
+
+/// The drivers the kernel has, in the order they are tried against a
+/// compatible string.
+static DRIVERS: [&Driver; 3] = [&uart::DRIVER, &plic::DRIVER, &virtio::DRIVER];
+
+/// The points during boot at which drivers are probed. Each stage may rely
+/// on the devices of the stages before it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProbeStage {
+    /// The console, probed as soon as the DTB is found so that boot messages
+    /// leave the SBI debug console early.
+    Console,
+
+    /// The interrupt controllers, probed before the secondary harts start.
+    InterruptController,
+
+    /// Every other device, which may route its interrupts through the
+    /// interrupt controllers.
+    Device,
+}
+
+/// A driver for the devices with certain compatible strings.
+pub struct Driver {
+    pub name: &'static str,
+
+    /// The compatible strings of the devices the driver handles.
+    pub compatibles: &'static [&'static str],
+
+    pub stage: ProbeStage,
+
+    /// Sets up a device. Called once for every device the driver matches.
+    pub probe: fn(device: &Device) -> Result<(), ProbeError>,
+}
+
+/// The reasons a driver did not take a device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProbeError {
+    /// The driver does not use the device, such as a UART other than the
+    /// console or a virtio-mmio window with nothing behind it.
+    Skipped,
+
+    /// The device has no register range with a physical address.
+    NoRegisters,
+
+    Uart(UartError),
+    Plic(PlicError),
+    Virtio(VirtioError),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Skipped => write!(f, "skipped"),
+            Self::NoRegisters => write!(f, "no register range"),
+            Self::Uart(error) => write!(f, "{}", error),
+            Self::Plic(error) => write!(f, "{}", error),
+            Self::Virtio(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<UartError> for ProbeError {
+    fn from(error: UartError) -> Self {
+        Self::Uart(error)
+    }
+}
+
+impl From<PlicError> for ProbeError {
+    fn from(error: PlicError) -> Self {
+        Self::Plic(error)
+    }
+}
+
+impl From<VirtioError> for ProbeError {
+    fn from(error: VirtioError) -> Self {
+        Self::Virtio(error)
+    }
+}
+
+/// A physical address range of a device's registers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RegisterRange {
+    pub physical_address: u64,
+    pub size: u64,
+}
+
+/// A device node matched by a driver, with the resources the driver needs.
+#[derive(Debug, Copy, Clone)]
+pub struct Device {
+    dtb: Dtb<'static>,
+    node: DtbNode<'static>,
+
+    /// The "reg" ranges that translate to physical addresses. Only the first
+    /// `reg_count` entries are valid.
+    regs: [RegisterRange; MAX_REGS],
+    reg_count: usize,
+
+    /// The PLIC interrupt sources of the device. Only the first `irq_count`
+    /// entries are valid.
+    irqs: [u32; MAX_IRQS],
+    irq_count: usize,
+}
+
+impl Device {
+    /// Gathers the resources of a device node.
+    ///
+    /// # Arguments
+    ///
+    /// * `dtb` - The Device Tree Blob holding the node.
+    /// * `node` - The node.
+    /// * `phandle_index` - The phandle index of the DTB, to find the
+    ///   interrupts.
+    fn new(
+        dtb: Dtb<'static>,
+        node: DtbNode<'static>,
+        phandle_index: &PhandleIndex<'static>,
+    ) -> Self {
+        let mut device = Self {
+            dtb,
+            node,
+            regs: [RegisterRange {
+                physical_address: 0,
+                size: 0,
+            }; MAX_REGS],
+            reg_count: 0,
+            irqs: [0; MAX_IRQS],
+            irq_count: 0,
+        };
+
+        if let Some(reg) = node.property("reg") {
+            reg.get_property_data_as_reg(&node.parent_cell_info(), |address, size| {
+                let Some(physical_address) = node.translate_address(address) else {
+                    return;
+                };
+
+                if device.reg_count < MAX_REGS {
+                    device.regs[device.reg_count] = RegisterRange {
+                        physical_address,
+                        size,
+                    };
+                    device.reg_count += 1;
+                }
+            });
+        }
+
+        for irq in node
+            .interrupts(phandle_index)
+            .filter_map(|interrupt| interrupt.specifier.irq())
+            .take(MAX_IRQS)
+        {
+            device.irqs[device.irq_count] = irq;
+            device.irq_count += 1;
+        }
+
+        device
+    }
+
+    /// Returns the DTB the device was found in, for drivers that need other
+    /// nodes, such as the PLIC reading the harts.
+    pub fn dtb(&self) -> &Dtb<'static> {
+        &self.dtb
+    }
+
+    /// Returns the node of the device.
+    pub fn node(&self) -> &DtbNode<'static> {
+        &self.node
+    }
+
+    /// Returns the name of the node, such as "serial@10000000".
+    pub fn name(&self) -> &'static str {
+        self.node.name
+    }
+
+    /// Returns the register ranges of the device at their physical addresses.
+    /// Ranges that do not translate to a physical address are left out.
+    pub fn regs(&self) -> &[RegisterRange] {
+        &self.regs[..self.reg_count]
+    }
+
+    /// Returns the first register range of the device.
+    pub fn first_reg(&self) -> Result<RegisterRange, ProbeError> {
+        self.regs().first().copied().ok_or(ProbeError::NoRegisters)
+    }
+
+    /// Returns the interrupt sources of the device.
+    pub fn irqs(&self) -> &[u32] {
+        &self.irqs[..self.irq_count]
+    }
+
+    /// Returns the first interrupt source of the device, if it has one.
+    pub fn first_irq(&self) -> Option<u32> {
+        self.irqs().first().copied()
+    }
+}
+
+/// How far a recorded device has come.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeviceState {
+    /// The device's stage has not been probed yet.
+    Pending,
+
+    /// The driver set up the device.
+    Bound,
+
+    /// The driver does not use the device.
+    Skipped,
+
+    /// The driver failed to set up the device.
+    Failed(ProbeError),
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pending => write!(f, "pending"),
+            Self::Bound => write!(f, "bound"),
+            Self::Skipped => write!(f, "skipped"),
+            Self::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// A device recorded by `scan` and the driver it matched.
+#[derive(Copy, Clone)]
+struct Entry {
+    device: Device,
+    driver: &'static Driver,
+    state: DeviceState,
+}
+
+/// The devices recorded by `scan`, in DTB order.
+static DEVICES: SpinLock<[Option<Entry>; MAX_DEVICES]> = SpinLock::new([None; MAX_DEVICES]);
+
+/// Walks the DTB once and records every enabled node a driver matches. Must
+/// be called once, before `probe`.
+///
+/// # Arguments
+///
+/// * `dtb` - The Device Tree Blob describing the devices.
+///
+/// # Returns
+///
+/// The number of devices recorded.
+pub fn scan(dtb: &Dtb<'static>) -> usize {
+    let phandle_index = PhandleIndex::new(dtb);
+    let mut devices = DEVICES.lock();
+    let mut count = 0;
+
+    for node in dtb.nodes().filter(|node| node.is_enabled()) {
+        let Some(driver) = find_driver(&node) else {
+            continue;
+        };
+
+        if count == MAX_DEVICES {
+            debug_println!(
+                "Ignoring {}: the device table holds {} devices.",
+                node.name,
+                MAX_DEVICES
+            );
+            continue;
+        }
+
+        devices[count] = Some(Entry {
+            device: Device::new(*dtb, node, &phandle_index),
+            driver,
+            state: DeviceState::Pending,
+        });
+        count += 1;
+    }
+
+    count
+}
+
+/// Probes the devices of a stage with their drivers, in DTB order, and logs
+/// any that fail.
+///
+/// # Arguments
+///
+/// * `stage` - The stage to probe.
+pub fn probe(stage: ProbeStage) {
+    for index in 0..MAX_DEVICES {
+        // The table is not locked while a driver runs, since probing may
+        // take a while and drivers may log.
+        let entry = match DEVICES.lock()[index] {
+            Some(entry) if entry.driver.stage == stage && entry.state == DeviceState::Pending => {
+                entry
+            }
+            _ => continue,
+        };
+
+        let state = match (entry.driver.probe)(&entry.device) {
+            Ok(()) => DeviceState::Bound,
+            Err(ProbeError::Skipped) => DeviceState::Skipped,
+            Err(error) => {
+                debug_println!(
+                    "The {} driver failed to probe {}: {}.",
+                    entry.driver.name,
+                    entry.device.name(),
+                    error
+                );
+
+                DeviceState::Failed(error)
+            }
+        };
+
+        if let Some(entry) = DEVICES.lock()[index].as_mut() {
+            entry.state = state;
+        }
+    }
+}
+
+/// Calls a function for every recorded device.
+///
+/// # Arguments
+///
+/// * `callback` - Called with each device, the name of its driver, and its
+///   state.
+pub fn for_each_device(mut callback: impl FnMut(&Device, &'static str, DeviceState)) {
+    let devices = *DEVICES.lock();
+
+    for entry in devices.iter().flatten() {
+        callback(&entry.device, entry.driver.name, entry.state);
+    }
+}
+
+/// Finds the driver for a node by its compatible strings.
+fn find_driver(node: &DtbNode) -> Option<&'static Driver> {
+    node.property("compatible")?
+        .get_property_data_as_str_list()
+        .find_map(|compatible| {
+            DRIVERS
+                .iter()
+                .copied()
+                .find(|driver| driver.compatibles.contains(&compatible))
+        })
+}
@@ -0,0 +1,5 @@
+//! Drivers for devices discovered through the DTB.
+
+pub mod plic;
+pub mod uart;
+pub mod virtio;
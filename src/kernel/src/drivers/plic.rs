@@ -0,0 +1,365 @@
+//! Driver for the RISC-V Platform-Level Interrupt Controller (PLIC).
+//!
+//! The PLIC routes the interrupts of devices to hart contexts. A hart has one
+//! context for every privilege mode that takes external interrupts, and the
+//! kernel only uses the supervisor context of each hart. An interrupt source
+//! is delivered to a context when it is enabled for the context and its
+//! priority is above the context's threshold. Delivered interrupts are claimed
+//! and completed by the `irq` module, which drivers register their handlers
+//! with.
+//!
+//! The registers are reached through the direct physical memory mapping.
+
+use crate::{
+    debug_println,
+    devices::{Device, Driver, ProbeError, ProbeStage},
+    hart::{MAX_HART_COUNT, current_hart_id},
+    memory::physical_to_virtual,
+};
+use common_lib::dtb::{self, PhandleIndex};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+use kernel_lib::sync::interrupts::SSTATUS_SIE;
+
+/// The driver of the PLIC. QEMU's virt machine lists both compatible strings.
+pub static DRIVER: Driver = Driver {
+    name: "plic",
+    compatibles: &["riscv,plic0", "sifive,plic-1.0.0"],
+    stage: ProbeStage::InterruptController,
+    probe,
+};
+
+/// The number of interrupt sources the PLIC architecture allows, including
+/// the reserved source 0.
+pub const MAX_INTERRUPT_SOURCES: usize = 1024;
+
+/// The offset of the source priority registers, one u32 per source.
+const PRIORITY_OFFSET: usize = 0x0;
+
+/// The offset of the enable bits of context 0, one bit per source.
+const ENABLE_OFFSET: usize = 0x2000;
+
+/// The distance between the enable bits of consecutive contexts.
+const ENABLE_CONTEXT_STRIDE: usize = 0x80;
+
+/// The offset of the threshold register of context 0.
+const THRESHOLD_OFFSET: usize = 0x20_0000;
+
+/// The offset of the claim/complete register of context 0.
+const CLAIM_COMPLETE_OFFSET: usize = 0x20_0004;
+
+/// The distance between the threshold and claim/complete registers of
+/// consecutive contexts.
+const CONTEXT_STRIDE: usize = 0x1000;
+
+/// The interrupt number, as seen by a hart's local interrupt controller, of
+/// the supervisor external interrupt. A PLIC context wired to it is the
+/// hart's supervisor context.
+const SUPERVISOR_EXTERNAL_INTERRUPT: u32 = 9;
+
+/// The supervisor external interrupt enable bit in the sie CSR.
+const SIE_SEIE: usize = 1 << 9;
+
+/// Marks a hart without a supervisor context in `SUPERVISOR_CONTEXTS`.
+const NO_CONTEXT: usize = usize::MAX;
+
+/// The virtual address of the PLIC registers, or 0 before `probe`.
+static PLIC_BASE_ADDRESS: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of interrupt sources, including the reserved source 0.
+static INTERRUPT_SOURCE_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// The supervisor context of each hart, indexed by hart ID.
+static SUPERVISOR_CONTEXTS: [AtomicUsize; MAX_HART_COUNT] =
+    [const { AtomicUsize::new(NO_CONTEXT) }; MAX_HART_COUNT];
+
+/// The reasons a PLIC operation failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlicError {
+    /// The PLIC's registers are outside the direct physical memory mapping.
+    InvalidRegisters,
+
+    /// No PLIC has been probed successfully.
+    NotInitialized,
+
+    /// The interrupt source is 0 or not implemented by the PLIC.
+    InvalidInterrupt,
+
+    /// The hart does not have a supervisor context.
+    InvalidHart,
+}
+
+impl fmt::Display for PlicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRegisters => write!(f, "invalid register region"),
+            Self::NotInitialized => write!(f, "PLIC not initialized"),
+            Self::InvalidInterrupt => write!(f, "invalid interrupt source"),
+            Self::InvalidHart => write!(f, "hart has no supervisor context"),
+        }
+    }
+}
+
+/// Sets up the PLIC and masks every interrupt source. Only the first PLIC is
+/// used, and any other is skipped.
+///
+/// Probed on the boot hart before `initialize_hart` is called on any hart.
+///
+/// # Arguments
+///
+/// * `device` - The PLIC.
+fn probe(device: &Device) -> Result<(), ProbeError> {
+    if INTERRUPT_SOURCE_COUNT.load(Ordering::Acquire) != 0 {
+        return Err(ProbeError::Skipped);
+    }
+
+    let dtb = device.dtb();
+    let plic_node = device.node();
+
+    let physical_address = device.first_reg()?.physical_address;
+    let base_address =
+        physical_to_virtual(physical_address as usize).ok_or(PlicError::InvalidRegisters)?;
+
+    // "riscv,ndev" counts the implemented sources, which are numbered from 1.
+    let source_count = plic_node
+        .property("riscv,ndev")
+        .map_or(MAX_INTERRUPT_SOURCES as u32, |property| {
+            property.get_property_data_as_u32().saturating_add(1)
+        })
+        .min(MAX_INTERRUPT_SOURCES as u32);
+
+    // Every hart's local interrupt controller is a child of its cpu node. The
+    // PLIC's "interrupts-extended" lists one entry per context, naming the
+    // local interrupt controller and the interrupt the context raises in it.
+    let mut local_controller_phandles = [None; MAX_HART_COUNT];
+    for cpu in dtb::cpus(dtb).filter(|cpu| (cpu.hart_id as usize) < MAX_HART_COUNT) {
+        local_controller_phandles[cpu.hart_id as usize] = cpu
+            .node
+            .child("interrupt-controller")
+            .and_then(|node| node.phandle());
+    }
+
+    let phandle_index = PhandleIndex::new(dtb);
+    let mut context_count = 0;
+
+    for (context, interrupt) in plic_node.interrupts(&phandle_index).enumerate() {
+        context_count = context + 1;
+
+        if interrupt.specifier.irq() != Some(SUPERVISOR_EXTERNAL_INTERRUPT) {
+            continue;
+        }
+
+        let controller_phandle = interrupt.controller.phandle();
+        if let Some(hart_id) = local_controller_phandles
+            .iter()
+            .position(|phandle| phandle.is_some() && *phandle == controller_phandle)
+        {
+            SUPERVISOR_CONTEXTS[hart_id].store(context, Ordering::Relaxed);
+        }
+    }
+
+    PLIC_BASE_ADDRESS.store(base_address, Ordering::Relaxed);
+    INTERRUPT_SOURCE_COUNT.store(source_count, Ordering::Release);
+
+    // Firmware may leave sources enabled, so start with every source at
+    // priority 0, which never interrupts, and disabled in every context.
+    for irq in 1..source_count {
+        write_register(PRIORITY_OFFSET + irq as usize * 4, 0);
+    }
+
+    for context in 0..context_count {
+        for word in 0..(source_count as usize).div_ceil(32) {
+            write_register(enable_offset(context, word), 0);
+        }
+    }
+
+    debug_println!(
+        "PLIC at {:#x} with {} interrupt sources and {} contexts.",
+        physical_address,
+        source_count - 1,
+        context_count
+    );
+
+    Ok(())
+}
+
+/// Accepts every enabled interrupt source with a priority above 0 on the
+/// calling hart and enables supervisor external interrupts.
+///
+/// The trap vector must already be installed on this hart.
+pub fn initialize_hart() -> Result<(), PlicError> {
+    let hart_id = current_hart_id();
+
+    set_threshold(hart_id, 0)?;
+
+    unsafe {
+        core::arch::asm!(
+            "csrs sie, {}",
+            "csrs sstatus, {}",
+            in(reg) SIE_SEIE,
+            in(reg) SSTATUS_SIE,
+            options(nomem, nostack)
+        );
+    }
+
+    Ok(())
+}
+
+/// Sets the priority of an interrupt source.
+///
+/// # Arguments
+///
+/// * `irq` - The interrupt source.
+/// * `priority` - The priority. 0 masks the source and higher values take
+///   precedence. The PLIC ignores bits above its highest priority, which is
+///   7 on QEMU's virt machine.
+pub fn set_priority(irq: u32, priority: u32) -> Result<(), PlicError> {
+    validate_interrupt(irq)?;
+
+    write_register(PRIORITY_OFFSET + irq as usize * 4, priority);
+
+    Ok(())
+}
+
+/// Sets the priority threshold of a hart's supervisor context. Only sources
+/// with a priority above the threshold interrupt the hart.
+///
+/// # Arguments
+///
+/// * `hart_id` - The hart whose threshold is set.
+/// * `threshold` - The threshold.
+pub fn set_threshold(hart_id: usize, threshold: u32) -> Result<(), PlicError> {
+    let context = supervisor_context(hart_id)?;
+
+    write_register(THRESHOLD_OFFSET + context * CONTEXT_STRIDE, threshold);
+
+    Ok(())
+}
+
+/// Routes an interrupt source to a hart.
+///
+/// The source also needs a priority above the hart's threshold before it
+/// interrupts the hart.
+///
+/// # Arguments
+///
+/// * `irq` - The interrupt source.
+/// * `hart_id` - The hart that should take the interrupt.
+pub fn enable(irq: u32, hart_id: usize) -> Result<(), PlicError> {
+    set_enabled(irq, hart_id, true)
+}
+
+/// Stops routing an interrupt source to a hart.
+///
+/// # Arguments
+///
+/// * `irq` - The interrupt source.
+/// * `hart_id` - The hart that should no longer take the interrupt.
+pub fn disable(irq: u32, hart_id: usize) -> Result<(), PlicError> {
+    set_enabled(irq, hart_id, false)
+}
+
+/// Claims the highest priority pending interrupt of the calling hart.
+///
+/// # Returns
+///
+/// The interrupt source, or `None` if no interrupt is pending. A claimed
+/// interrupt must be passed to `complete` once it has been handled.
+pub fn claim() -> Option<u32> {
+    let context = supervisor_context(current_hart_id()).ok()?;
+
+    match read_register(CLAIM_COMPLETE_OFFSET + context * CONTEXT_STRIDE) {
+        0 => None,
+        irq => Some(irq),
+    }
+}
+
+/// Signals that a claimed interrupt has been handled so the source can raise
+/// it again.
+///
+/// # Arguments
+///
+/// * `irq` - The interrupt source returned by `claim` on the calling hart.
+pub fn complete(irq: u32) {
+    let Ok(context) = supervisor_context(current_hart_id()) else {
+        return;
+    };
+
+    write_register(CLAIM_COMPLETE_OFFSET + context * CONTEXT_STRIDE, irq);
+}
+
+/// Sets or clears the enable bit of an interrupt source in a hart's
+/// supervisor context.
+///
+/// The enable bits of a context share words, so callers must not change the
+/// bits of the same hart from two harts at once.
+fn set_enabled(irq: u32, hart_id: usize, enabled: bool) -> Result<(), PlicError> {
+    validate_interrupt(irq)?;
+    let context = supervisor_context(hart_id)?;
+
+    let offset = enable_offset(context, irq as usize / 32);
+    let bit = 1 << (irq % 32);
+    let value = read_register(offset);
+
+    write_register(offset, if enabled { value | bit } else { value & !bit });
+
+    Ok(())
+}
+
+/// Returns the offset of a word of enable bits of a context.
+fn enable_offset(context: usize, word: usize) -> usize {
+    ENABLE_OFFSET + context * ENABLE_CONTEXT_STRIDE + word * 4
+}
+
+/// Checks that the PLIC is initialized and implements an interrupt source.
+///
+/// # Arguments
+///
+/// * `irq` - The interrupt source.
+pub fn validate_interrupt(irq: u32) -> Result<(), PlicError> {
+    let source_count = INTERRUPT_SOURCE_COUNT.load(Ordering::Acquire);
+
+    if source_count == 0 {
+        return Err(PlicError::NotInitialized);
+    }
+
+    if irq == 0 || irq >= source_count {
+        return Err(PlicError::InvalidInterrupt);
+    }
+
+    Ok(())
+}
+
+/// Returns the supervisor context of a hart.
+fn supervisor_context(hart_id: usize) -> Result<usize, PlicError> {
+    if INTERRUPT_SOURCE_COUNT.load(Ordering::Acquire) == 0 {
+        return Err(PlicError::NotInitialized);
+    }
+
+    let context = SUPERVISOR_CONTEXTS
+        .get(hart_id)
+        .ok_or(PlicError::InvalidHart)?
+        .load(Ordering::Relaxed);
+
+    if context == NO_CONTEXT {
+        return Err(PlicError::InvalidHart);
+    }
+
+    Ok(context)
+}
+
+/// Reads a PLIC register.
+fn read_register(offset: usize) -> u32 {
+    let address = PLIC_BASE_ADDRESS.load(Ordering::Relaxed) + offset;
+
+    unsafe { core::ptr::read_volatile(address as *const u32) }
+}
+
+/// Writes a PLIC register.
+fn write_register(offset: usize, value: u32) {
+    let address = PLIC_BASE_ADDRESS.load(Ordering::Relaxed) + offset;
+
+    unsafe { core::ptr::write_volatile(address as *mut u32, value) }
+}
@@ -0,0 +1,351 @@
+//! Driver for NS16550A compatible UARTs.
+//!
+//! The UART is programmed for polled transmission and reception of 8 data
+//! bits, no parity, and 1 stop bit with its FIFOs enabled. The baud rate
+//! divisor is left as the firmware configured it, since the firmware already
+//! uses the UART for its own console. The registers are mapped with
+//! `memory::map_mmio`.
+//!
+//! The device manager probes every enabled UART, and the one named by the
+//! /chosen "stdout-path", or the first one without a "stdout-path", becomes
+//! the console.
+//!
+//! Once the PLIC is up, `enable_receive_interrupt` lets the UART interrupt
+//! when bytes arrive. The handler moves them into a receive buffer and
+//! notifies the console, so readers can block instead of polling.
+
+use crate::{
+    console::{self, ConsoleDevice, ConsoleKind},
+    debug_println,
+    devices::{Device, Driver, ProbeError, ProbeStage},
+    irq::{self, IrqError},
+    memory,
+};
+use common_lib::{dtb, memory::PhysicalAddress};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+use kernel_lib::sync::{BoundedQueue, SpinLockIrqSave};
+
+/// The driver of NS16550A compatible UARTs.
+pub static DRIVER: Driver = Driver {
+    name: "uart",
+    compatibles: &["ns16550a", "ns16550"],
+    stage: ProbeStage::Console,
+    probe,
+};
+
+/// The transmit holding register (write).
+const THR: usize = 0;
+
+/// The receive buffer register (read).
+const RBR: usize = 0;
+
+/// The interrupt enable register.
+const IER: usize = 1;
+
+/// The FIFO control register (write).
+const FCR: usize = 2;
+
+/// The line control register.
+const LCR: usize = 3;
+
+/// The modem control register.
+const MCR: usize = 4;
+
+/// The line status register.
+const LSR: usize = 5;
+
+/// FCR value that enables the FIFOs and clears both of them.
+const FCR_ENABLE_AND_CLEAR_FIFOS: u8 = 0b111;
+
+/// LCR value for 8 data bits, no parity, 1 stop bit, and the divisor latch
+/// closed.
+const LCR_8N1: u8 = 0b11;
+
+/// IER value that enables the received data available interrupt.
+const IER_RECEIVED_DATA_AVAILABLE: u8 = 1 << 0;
+
+/// MCR value that asserts DTR and RTS.
+const MCR_DTR_RTS: u8 = 0b11;
+
+/// The LSR bit that is set when the receive buffer holds a byte.
+const LSR_DATA_READY: u8 = 1 << 0;
+
+/// The LSR bit that is set when the transmit holding register is empty.
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+/// The virtual address of the UART registers, or 0 before the console UART
+/// is probed.
+static UART_BASE_ADDRESS: AtomicUsize = AtomicUsize::new(0);
+
+/// The PLIC interrupt source of the console UART, or 0 if it has none.
+static UART_IRQ: AtomicU32 = AtomicU32::new(0);
+
+/// The number of bits register indexes are shifted by to form offsets, from
+/// the "reg-shift" property.
+static REGISTER_SHIFT: AtomicUsize = AtomicUsize::new(0);
+
+/// The width in bytes of each register access, from the "reg-io-width"
+/// property.
+static REGISTER_WIDTH: AtomicUsize = AtomicUsize::new(1);
+
+/// The number of received bytes buffered between the interrupt handler and
+/// console reads.
+const RECEIVE_BUFFER_CAPACITY: usize = 256;
+
+/// Bytes moved out of the receive FIFO that have not been read yet.
+static RECEIVE_BUFFER: BoundedQueue<u8, RECEIVE_BUFFER_CAPACITY> = BoundedQueue::new();
+
+/// Serializes moving bytes from the receive FIFO to `RECEIVE_BUFFER`, so
+/// bytes drained by the interrupt handler and a reader stay in order.
+static RECEIVE_LOCK: SpinLockIrqSave<()> = SpinLockIrqSave::new(());
+
+/// The reasons a UART could not be initialized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UartError {
+    /// No UART was probed as the console.
+    NotFound,
+
+    /// The UART's registers could not be mapped.
+    InvalidRegisters,
+
+    /// The "reg-io-width" property is neither 1 nor 4.
+    UnsupportedRegisterWidth(u32),
+
+    /// The UART node has no interrupt the PLIC can deliver.
+    NoInterrupt,
+
+    /// The UART's interrupt could not be registered or enabled.
+    Irq(IrqError),
+}
+
+impl fmt::Display for UartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "no NS16550A UART console"),
+            Self::InvalidRegisters => write!(f, "invalid register region"),
+            Self::UnsupportedRegisterWidth(width) => {
+                write!(f, "unsupported register width {}", width)
+            }
+            Self::NoInterrupt => write!(f, "no interrupt wired to the PLIC"),
+            Self::Irq(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Programs a UART for polled output and registers it as the stdout console
+/// if it is the console UART.
+///
+/// The UART named by the /chosen "stdout-path" is the console. Without a
+/// "stdout-path" the first UART probed is the console instead, and any
+/// other UART is skipped.
+///
+/// # Arguments
+///
+/// * `device` - The UART.
+fn probe(device: &Device) -> Result<(), ProbeError> {
+    if UART_BASE_ADDRESS.load(Ordering::Acquire) != 0 {
+        return Err(ProbeError::Skipped);
+    }
+
+    let dtb = device.dtb();
+    let chosen = dtb::chosen(dtb).unwrap_or_default();
+
+    if chosen.stdout_path.is_some() && chosen.stdout_node(dtb) != Some(*device.node()) {
+        return Err(ProbeError::Skipped);
+    }
+
+    let registers = device.first_reg()?;
+    let physical_address = registers.physical_address;
+    let base_address = memory::map_mmio(
+        PhysicalAddress::new(physical_address as usize),
+        registers.size as usize,
+    )
+    .map_err(|_| UartError::InvalidRegisters)?
+    .raw_address();
+
+    let register_shift = device
+        .node()
+        .property("reg-shift")
+        .map_or(0, |property| property.get_property_data_as_u32());
+
+    let register_width = device
+        .node()
+        .property("reg-io-width")
+        .map_or(1, |property| property.get_property_data_as_u32());
+
+    if register_width != 1 && register_width != 4 {
+        return Err(UartError::UnsupportedRegisterWidth(register_width).into());
+    }
+
+    REGISTER_SHIFT.store(register_shift as usize, Ordering::Relaxed);
+    REGISTER_WIDTH.store(register_width as usize, Ordering::Relaxed);
+
+    // Program the UART through the base address before publishing it, so no
+    // other hart writes to it halfway through.
+    write_register(base_address, IER, 0);
+    write_register(base_address, LCR, LCR_8N1);
+    write_register(base_address, FCR, FCR_ENABLE_AND_CLEAR_FIFOS);
+    write_register(base_address, MCR, MCR_DTR_RTS);
+
+    UART_IRQ.store(device.first_irq().unwrap_or(0), Ordering::Relaxed);
+    UART_BASE_ADDRESS.store(base_address, Ordering::Release);
+
+    // The table only fills up if drivers register more devices than the
+    // kernel has, in which case output stays on the boot console.
+    let _ = console::register(&UART_CONSOLE_DEVICE, ConsoleKind::Stdout);
+
+    debug_println!("Console switched to the UART at {:#x}.", physical_address);
+
+    Ok(())
+}
+
+/// Routes the console UART's receive interrupt to the calling hart and tells the console that input now raises notifications.
+///
+/// The console UART and the PLIC must have been probed first.
+///
+/// # Returns
+///
+/// The PLIC interrupt source of the UART.
+pub fn enable_receive_interrupt() -> Result<u32, UartError> {
+    let base_address = UART_BASE_ADDRESS.load(Ordering::Acquire);
+    if base_address == 0 {
+        return Err(UartError::NotFound);
+    }
+
+    let irq = UART_IRQ.load(Ordering::Relaxed);
+    if irq == 0 {
+        return Err(UartError::NoInterrupt);
+    }
+
+    irq::register_handler(irq, handle_receive_interrupt, 0).map_err(UartError::Irq)?;
+    irq::enable(irq).map_err(UartError::Irq)?;
+
+    console::enable_input_notifications();
+    write_register(base_address, IER, IER_RECEIVED_DATA_AVAILABLE);
+
+    Ok(irq)
+}
+
+/// Writes a byte to the UART, waiting for room in the transmit FIFO.
+///
+/// The byte is dropped if the UART is not initialized.
+pub fn write_byte(byte: u8) {
+    let base_address = UART_BASE_ADDRESS.load(Ordering::Acquire);
+    if base_address == 0 {
+        return;
+    }
+
+    while read_register(base_address, LSR) & LSR_THR_EMPTY == 0 {
+        core::hint::spin_loop();
+    }
+
+    write_register(base_address, THR, byte);
+}
+
+/// Reads a byte from the UART without waiting.
+///
+/// # Returns
+///
+/// The oldest byte in the receive FIFO, or `None` if it is empty or the UART
+/// is not initialized.
+pub fn read_byte() -> Option<u8> {
+    let base_address = UART_BASE_ADDRESS.load(Ordering::Acquire);
+    if base_address == 0 {
+        return None;
+    }
+
+    if read_register(base_address, LSR) & LSR_DATA_READY == 0 {
+        return None;
+    }
+
+    Some(read_register(base_address, RBR))
+}
+
+/// The UART as a console device.
+static UART_CONSOLE_DEVICE: ConsoleDevice = ConsoleDevice {
+    name: "uart",
+    write: write_to_console,
+    read: Some(read_from_console),
+};
+
+/// Writes bytes to the UART for `UART_CONSOLE_DEVICE`.
+///
+/// Line feeds are expanded to a carriage return and line feed, matching the
+/// output of the SBI debug console.
+fn write_to_console(bytes: &[u8]) {
+    for &byte in bytes {
+        if byte == b'\n' {
+            write_byte(b'\r');
+        }
+
+        write_byte(byte);
+    }
+}
+
+/// Reads the bytes waiting in the receive buffer and FIFO for
+/// `UART_CONSOLE_DEVICE`.
+///
+/// The FIFO is drained here as well as in the interrupt handler, so input
+/// keeps working with interrupts disabled, such as in the panic monitor.
+fn read_from_console(buffer: &mut [u8]) -> usize {
+    drain_receive_fifo();
+
+    let mut count = 0;
+
+    while count < buffer.len() {
+        let Some(byte) = RECEIVE_BUFFER.pop() else {
+            break;
+        };
+
+        buffer[count] = byte;
+        count += 1;
+    }
+
+    count
+}
+
+/// Handles the UART's PLIC interrupt by buffering the received bytes and
+/// waking console readers.
+fn handle_receive_interrupt(_irq: u32, _data: usize) {
+    drain_receive_fifo();
+    console::notify_input();
+}
+
+/// Moves every byte in the receive FIFO to `RECEIVE_BUFFER`.
+///
+/// Reading the FIFO empty also deasserts the receive interrupt. Bytes that
+/// arrive while the buffer is full are dropped.
+fn drain_receive_fifo() {
+    let _guard = RECEIVE_LOCK.lock();
+
+    while let Some(byte) = read_byte() {
+        let _ = RECEIVE_BUFFER.push(byte);
+    }
+}
+
+/// Reads a UART register.
+fn read_register(base_address: usize, register: usize) -> u8 {
+    let address = base_address + (register << REGISTER_SHIFT.load(Ordering::Relaxed));
+
+    unsafe {
+        match REGISTER_WIDTH.load(Ordering::Relaxed) {
+            4 => core::ptr::read_volatile(address as *const u32) as u8,
+            _ => core::ptr::read_volatile(address as *const u8),
+        }
+    }
+}
+
+/// Writes a UART register.
+fn write_register(base_address: usize, register: usize, value: u8) {
+    let address = base_address + (register << REGISTER_SHIFT.load(Ordering::Relaxed));
+
+    unsafe {
+        match REGISTER_WIDTH.load(Ordering::Relaxed) {
+            4 => core::ptr::write_volatile(address as *mut u32, value as u32),
+            _ => core::ptr::write_volatile(address as *mut u8, value),
+        }
+    }
+}
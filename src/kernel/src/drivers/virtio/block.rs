@@ -0,0 +1,413 @@
+//! Driver for virtio block devices.
+//!
+//! The first virtio block device probed is registered with the block layer
+//! as "vda" and driven through a single request queue. A request is a chain of
+//! a header naming the operation and sector, the data buffers split at page
+//! boundaries, and a status byte the device writes last. Up to `MAX_REQUESTS`
+//! requests are in flight at once, each in a `RequestSlot`, whose header and
+//! status byte live in the kernel image so the device can reach them by
+//! physical address.
+//!
+//! The device raises an interrupt through the PLIC when it returns requests,
+//! and the handler raises the block completion softirq, which frees their
+//! slots and reports them to the block layer.
+
+use super::{Buffer, INTERRUPT_USED_BUFFER, MmioTransport, VirtQueue, VirtioError};
+use crate::{
+    block::{self, BlockDevice, BlockError, BlockOperation, BlockRequest, RequestId},
+    irq,
+    memory::{self, PAGE_SIZE},
+    softirq::{self, Softirq},
+};
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU16, AtomicUsize, Ordering},
+};
+use kernel_lib::sync::SpinLockIrqSave;
+
+/// The size in bytes of the sectors requests address, whatever the block
+/// size of the device.
+pub const SECTOR_SIZE: usize = 512;
+
+/// The name the device is registered with the block layer under.
+pub const DEVICE_NAME: &str = "vda";
+
+/// The feature bit of devices that do not allow writes.
+const FEATURE_READ_ONLY: u64 = 1 << 5;
+
+/// The offset of the capacity in sectors in the device configuration.
+const CONFIG_CAPACITY_OFFSET: usize = 0;
+
+/// The request type that reads sectors.
+const REQUEST_TYPE_IN: u32 = 0;
+
+/// The request type that writes sectors.
+const REQUEST_TYPE_OUT: u32 = 1;
+
+/// The status of a request that succeeded.
+const STATUS_OK: u8 = 0;
+
+/// The status of a request type the device does not support.
+const STATUS_UNSUPPORTED: u8 = 2;
+
+/// The most requests in flight at once.
+const MAX_REQUESTS: usize = 8;
+
+/// The most sectors a single request transfers. The block layer splits larger
+/// transfers into several requests.
+const MAX_REQUEST_SECTORS: usize = 64;
+
+/// The most descriptors a request needs: the header, one for each page the
+/// data touches, and the status byte.
+const MAX_DESCRIPTORS_PER_REQUEST: usize = (MAX_REQUEST_SECTORS * SECTOR_SIZE) / PAGE_SIZE + 3;
+
+/// The queue virtio block devices take requests on.
+const REQUEST_QUEUE_INDEX: u16 = 0;
+
+/// `RequestSlot` states.
+const SLOT_FREE: u8 = 0;
+const SLOT_CLAIMED: u8 = 1;
+const SLOT_SUBMITTED: u8 = 2;
+
+/// The header at the start of every request.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct RequestHeader {
+    request_type: u32,
+    reserved: u32,
+
+    /// The first sector of the transfer.
+    sector: u64,
+}
+
+/// A request in flight, or room for one.
+///
+/// The alignment keeps the header and the status byte within one page.
+#[repr(C, align(32))]
+struct RequestSlot {
+    header: UnsafeCell<RequestHeader>,
+
+    /// The status byte the device writes once the request is done.
+    status: UnsafeCell<u8>,
+
+    /// One of the `SLOT_` states.
+    state: AtomicU8,
+
+    /// The token the queue returns the request with, once it is submitted.
+    token: AtomicU16,
+
+    /// The ID the block layer submitted the request with.
+    request_id: AtomicUsize,
+}
+
+// The header and status are only accessed by the code that claimed the slot,
+// and by the device while the request is in flight.
+unsafe impl Sync for RequestSlot {}
+
+/// The initialized block device.
+struct VirtioBlock {
+    transport: MmioTransport,
+    queue: VirtQueue,
+}
+
+/// The block device, or `None` before `probe`.
+static DEVICE: SpinLockIrqSave<Option<VirtioBlock>> = SpinLockIrqSave::new(None);
+
+/// The capacity of the device in sectors.
+static CAPACITY: AtomicUsize = AtomicUsize::new(0);
+
+/// True if the device does not allow writes.
+static IS_READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// The number of `REQUEST_SLOTS` in use, which the queue size may limit.
+static ACTIVE_SLOT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The slots of the requests in flight.
+static REQUEST_SLOTS: [RequestSlot; MAX_REQUESTS] = [const {
+    RequestSlot {
+        header: UnsafeCell::new(RequestHeader {
+            request_type: 0,
+            reserved: 0,
+            sector: 0,
+        }),
+        status: UnsafeCell::new(0),
+        state: AtomicU8::new(SLOT_FREE),
+        token: AtomicU16::new(0),
+        request_id: AtomicUsize::new(0),
+    }
+}; MAX_REQUESTS];
+
+/// The block layer's view of the device.
+struct VirtioBlockDevice;
+
+static BLOCK_DEVICE: VirtioBlockDevice = VirtioBlockDevice;
+
+impl BlockDevice for VirtioBlockDevice {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> usize {
+        CAPACITY.load(Ordering::Relaxed)
+    }
+
+    fn is_read_only(&self) -> bool {
+        IS_READ_ONLY.load(Ordering::Relaxed)
+    }
+
+    fn max_blocks_per_request(&self) -> usize {
+        MAX_REQUEST_SECTORS
+    }
+
+    fn submit(&self, id: RequestId, request: &BlockRequest) -> Result<(), BlockError> {
+        let slot = claim_slot().ok_or(BlockError::Busy)?;
+
+        let result = build_and_submit(slot, id, request);
+        if result.is_err() {
+            slot.state.store(SLOT_FREE, Ordering::Release);
+        }
+
+        result
+    }
+}
+
+/// Sets up a virtio block device, routes its interrupt to the calling hart,
+/// and registers it with the block layer. The PLIC must be initialized.
+///
+/// # Arguments
+///
+/// * `transport` - The register window of the device.
+///
+/// # Returns
+///
+/// The capacity of the device in sectors.
+pub fn probe(transport: MmioTransport) -> Result<usize, VirtioError> {
+    if DEVICE.lock().is_some() {
+        return Err(VirtioError::AlreadyInitialized);
+    }
+
+    let irq = transport.irq()?;
+
+    let features = transport.initialize(FEATURE_READ_ONLY)?;
+
+    let queue = match transport.setup_queue(REQUEST_QUEUE_INDEX) {
+        Ok(queue) => queue,
+        Err(error) => {
+            transport.fail();
+            return Err(error);
+        }
+    };
+
+    let slot_count = MAX_REQUESTS.min(queue.size() as usize / MAX_DESCRIPTORS_PER_REQUEST);
+    if slot_count == 0 {
+        transport.reset();
+        return Err(VirtioError::QueueUnavailable(REQUEST_QUEUE_INDEX));
+    }
+
+    let capacity = transport.read_config_u64(CONFIG_CAPACITY_OFFSET) as usize;
+
+    CAPACITY.store(capacity, Ordering::Relaxed);
+    IS_READ_ONLY.store(features & FEATURE_READ_ONLY != 0, Ordering::Relaxed);
+    ACTIVE_SLOT_COUNT.store(slot_count, Ordering::Relaxed);
+
+    *DEVICE.lock() = Some(VirtioBlock { transport, queue });
+
+    let enable_interrupt = || {
+        softirq::register(Softirq::BlockCompletion, process_completions);
+
+        irq::register_handler(irq, handle_interrupt, 0)?;
+        irq::enable(irq)
+    };
+
+    if let Err(error) = enable_interrupt() {
+        transport.reset();
+        *DEVICE.lock() = None;
+
+        return Err(VirtioError::Irq(error));
+    }
+
+    transport.driver_ok();
+
+    block::register(DEVICE_NAME, &BLOCK_DEVICE).map_err(VirtioError::Block)?;
+
+    Ok(capacity)
+}
+
+/// Returns true if the block device does not allow writes.
+pub fn is_read_only() -> bool {
+    IS_READ_ONLY.load(Ordering::Relaxed)
+}
+
+/// Fills a claimed slot and passes its request to the device.
+fn build_and_submit(
+    slot: &RequestSlot,
+    id: RequestId,
+    request: &BlockRequest,
+) -> Result<(), BlockError> {
+    let request_type = match request.operation {
+        BlockOperation::Read => REQUEST_TYPE_IN,
+        BlockOperation::Write => REQUEST_TYPE_OUT,
+    };
+
+    unsafe {
+        *slot.header.get() = RequestHeader {
+            request_type,
+            reserved: 0,
+            sector: request.first_block as u64,
+        };
+
+        // Anything else marks a request the device never finished.
+        *slot.status.get() = u8::MAX;
+    }
+
+    let physical_address =
+        |address: usize| memory::virtual_to_physical(address).ok_or(BlockError::BadBuffer);
+
+    let mut buffers = [Buffer {
+        physical_address: 0,
+        length: 0,
+        is_device_writable: false,
+    }; MAX_DESCRIPTORS_PER_REQUEST];
+
+    buffers[0] = Buffer {
+        physical_address: physical_address(slot.header.get() as usize)?,
+        length: size_of::<RequestHeader>() as u32,
+        is_device_writable: false,
+    };
+
+    let mut buffer_count = 1;
+    let mut address = request.buffer_address;
+    let end = request.buffer_address + request.block_count * SECTOR_SIZE;
+
+    // The pages of the buffer need not be contiguous in physical memory.
+    while address < end {
+        let segment_end = end.min((address + 1).next_multiple_of(PAGE_SIZE));
+
+        buffers[buffer_count] = Buffer {
+            physical_address: physical_address(address)?,
+            length: (segment_end - address) as u32,
+            is_device_writable: request_type == REQUEST_TYPE_IN,
+        };
+
+        buffer_count += 1;
+        address = segment_end;
+    }
+
+    buffers[buffer_count] = Buffer {
+        physical_address: physical_address(slot.status.get() as usize)?,
+        length: 1,
+        is_device_writable: true,
+    };
+
+    buffer_count += 1;
+
+    let mut device = DEVICE.lock();
+    let device = device.as_mut().ok_or(BlockError::Io)?;
+
+    // Every active slot can have its request in the queue at once.
+    let token = device
+        .queue
+        .add_buffers(&buffers[..buffer_count])
+        .expect("The queue has room for every request slot.");
+
+    // The interrupt handler looks for the token only once the request is
+    // submitted, and it cannot run before the device lock is released.
+    slot.token.store(token, Ordering::Relaxed);
+    slot.request_id.store(id, Ordering::Relaxed);
+    slot.state.store(SLOT_SUBMITTED, Ordering::Release);
+
+    device.transport.notify(device.queue.index());
+
+    Ok(())
+}
+
+/// Claims a free request slot.
+///
+/// # Returns
+///
+/// The slot, or `None` if every active slot is in use.
+fn claim_slot() -> Option<&'static RequestSlot> {
+    REQUEST_SLOTS[..ACTIVE_SLOT_COUNT.load(Ordering::Relaxed)]
+        .iter()
+        .find(|slot| {
+            slot.state
+                .compare_exchange(
+                    SLOT_FREE,
+                    SLOT_CLAIMED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        })
+}
+
+/// Handles the block device's interrupt by acknowledging it and leaving the
+/// returned requests to `process_completions`.
+fn handle_interrupt(_irq: u32, _data: usize) {
+    let mut device = DEVICE.lock();
+    let Some(device) = device.as_mut() else {
+        return;
+    };
+
+    if device.transport.acknowledge_interrupt() & INTERRUPT_USED_BUFFER != 0 {
+        softirq::raise(Softirq::BlockCompletion);
+    }
+}
+
+/// The block completion softirq. Frees the slots of the returned requests and
+/// reports the requests to the block layer.
+///
+/// # Arguments
+///
+/// * `budget` - The most requests to complete.
+///
+/// # Returns
+///
+/// True if returned requests are left.
+fn process_completions(budget: usize) -> bool {
+    let mut completed = [(0, Ok(())); MAX_REQUESTS];
+    let mut completed_count = 0;
+    let mut has_more = false;
+
+    {
+        let mut device = DEVICE.lock();
+        let Some(device) = device.as_mut() else {
+            return false;
+        };
+
+        while let Some(completion) = device.queue.pop_used() {
+            let slot = REQUEST_SLOTS.iter().find(|slot| {
+                slot.state.load(Ordering::Acquire) == SLOT_SUBMITTED
+                    && slot.token.load(Ordering::Relaxed) == completion.token
+            });
+
+            let Some(slot) = slot else {
+                continue;
+            };
+
+            let result = match unsafe { *slot.status.get() } {
+                STATUS_OK => Ok(()),
+                STATUS_UNSUPPORTED => Err(BlockError::Unsupported),
+                _ => Err(BlockError::Io),
+            };
+
+            completed[completed_count] = (slot.request_id.load(Ordering::Relaxed), result);
+            completed_count += 1;
+
+            slot.state.store(SLOT_FREE, Ordering::Release);
+
+            if completed_count == budget.min(MAX_REQUESTS) {
+                has_more = true;
+                break;
+            }
+        }
+    }
+
+    // Completing a request may submit the next one, which takes the device
+    // lock again.
+    for &(id, result) in &completed[..completed_count] {
+        block::complete(id, result);
+    }
+
+    has_more
+}
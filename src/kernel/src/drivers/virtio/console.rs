@@ -0,0 +1,582 @@
+//! Driver for virtio console devices.
+//!
+//! The first virtio console device probed is registered as a console
+//! device. It becomes the stdout console when no UART took that role, which
+//! keeps the kernel's I/O working on firmware without the SBI debug console
+//! extension. Otherwise it receives output alongside the UART.
+//!
+//! Every port of the device has a receive and a transmit queue. Devices with
+//! the multiport feature announce their ports through a pair of control
+//! queues after the driver reports ready. The driver opens up to `MAX_PORTS`
+//! of them, and the console writes to and reads from port 0. The other ports
+//! are reached through `write_port` and `read_port`.
+//!
+//! Output is written synchronously: each chunk is posted and the driver spins
+//! until the device returns it, so the console can be written from trap
+//! context. Input is moved out of the receive buffers by the interrupt
+//! handler, and by readers, so polling works without interrupts.
+
+use super::{Buffer, INTERRUPT_USED_BUFFER, MmioTransport, VirtQueue, VirtioError};
+use crate::{
+    console::{self, ConsoleDevice, ConsoleKind},
+    irq, memory,
+};
+use core::{cell::UnsafeCell, fmt};
+use kernel_lib::{ring_buffer::RingBuffer, sync::SpinLockIrqSave};
+
+/// The most ports the driver uses. Every port takes two queues, whose rings
+/// each take a frame from the small frame pool.
+pub const MAX_PORTS: usize = 2;
+
+/// The feature bit of devices with more than one port and control queues.
+const FEATURE_MULTIPORT: u64 = 1 << 1;
+
+/// The offset of the maximum number of ports in the device configuration.
+const CONFIG_MAX_PORTS_OFFSET: usize = 4;
+
+/// The size of every buffer passed to the device.
+const BUFFER_SIZE: usize = 128;
+
+/// The number of receive buffers posted for each port and for the control
+/// receive queue.
+const RECEIVE_BUFFER_COUNT: usize = 4;
+
+/// The number of received bytes each port holds until they are read.
+const INPUT_CAPACITY: usize = 256;
+
+/// The queue of the control messages from the device. The queue of those to
+/// the device follows it, like the transmit queue of a port follows its
+/// receive queue.
+const CONTROL_RECEIVE_QUEUE_INDEX: u16 = 2;
+
+/// The size of a control message without its trailing data.
+const CONTROL_MESSAGE_SIZE: usize = 8;
+
+/// The events of control messages.
+const EVENT_DEVICE_READY: u16 = 0;
+const EVENT_DEVICE_ADD: u16 = 1;
+const EVENT_DEVICE_REMOVE: u16 = 2;
+const EVENT_PORT_READY: u16 = 3;
+const EVENT_PORT_OPEN: u16 = 6;
+
+/// The port the console uses.
+const CONSOLE_PORT: usize = 0;
+
+/// The reasons a port could not be written or read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PortError {
+    /// No virtio console is initialized.
+    NoDevice,
+
+    /// The device has not added the port. Holds the port number.
+    NoPort(usize),
+}
+
+impl fmt::Display for PortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoDevice => write!(f, "no virtio console"),
+            Self::NoPort(port) => write!(f, "no port {}", port),
+        }
+    }
+}
+
+/// A buffer the device reads or writes.
+#[repr(C, align(128))]
+struct IoBuffer(UnsafeCell<[u8; BUFFER_SIZE]>);
+
+// A buffer is only accessed by the device while it is posted, and otherwise
+// by the driver with the device lock held.
+unsafe impl Sync for IoBuffer {}
+
+impl IoBuffer {
+    /// Returns the physical address of the buffer.
+    fn physical_address(&self) -> usize {
+        memory::virtual_to_physical(self.0.get() as usize)
+            .expect("The kernel image is mapped with 4KiB pages.")
+    }
+}
+
+/// A pair of queues and the buffers posted to them.
+struct Channel {
+    receive_queue: VirtQueue,
+    transmit_queue: VirtQueue,
+
+    /// The index of the channel's buffers in `RECEIVE_BUFFERS` and
+    /// `TRANSMIT_BUFFERS`.
+    index: usize,
+
+    /// The token each receive buffer was posted with.
+    receive_tokens: [Option<u16>; RECEIVE_BUFFER_COUNT],
+}
+
+impl Channel {
+    /// Sets up the queues of a channel and posts its receive buffers. The
+    /// device is not notified.
+    fn new(
+        transport: &MmioTransport,
+        receive_queue_index: u16,
+        index: usize,
+    ) -> Result<Self, VirtioError> {
+        let receive_queue = transport.setup_queue(receive_queue_index)?;
+        let transmit_queue = transport.setup_queue(receive_queue_index + 1)?;
+
+        let mut channel = Self {
+            receive_queue,
+            transmit_queue,
+            index,
+            receive_tokens: [None; RECEIVE_BUFFER_COUNT],
+        };
+
+        for buffer_index in 0..RECEIVE_BUFFER_COUNT {
+            channel.post_receive_buffer(buffer_index);
+        }
+
+        Ok(channel)
+    }
+
+    /// Posts a receive buffer to the device. The device is not notified.
+    fn post_receive_buffer(&mut self, buffer_index: usize) {
+        let buffer = Buffer {
+            physical_address: RECEIVE_BUFFERS[self.index][buffer_index].physical_address(),
+            length: BUFFER_SIZE as u32,
+            is_device_writable: true,
+        };
+
+        // Queues have at least `RECEIVE_BUFFER_COUNT` descriptors.
+        self.receive_tokens[buffer_index] = self.receive_queue.add_buffers(&[buffer]).ok();
+    }
+
+    /// Takes the buffers the device filled, passes their contents to a
+    /// callback, and posts them again.
+    ///
+    /// # Returns
+    ///
+    /// True if any buffer was filled.
+    fn receive(&mut self, transport: &MmioTransport, mut callback: impl FnMut(&[u8])) -> bool {
+        let mut has_received = false;
+
+        while let Some(completion) = self.receive_queue.pop_used() {
+            let Some(buffer_index) = self
+                .receive_tokens
+                .iter()
+                .position(|token| *token == Some(completion.token))
+            else {
+                continue;
+            };
+
+            let contents = unsafe { &*RECEIVE_BUFFERS[self.index][buffer_index].0.get() };
+            let length = (completion.written_length as usize).min(BUFFER_SIZE);
+
+            callback(&contents[..length]);
+
+            self.post_receive_buffer(buffer_index);
+            has_received = true;
+        }
+
+        if has_received {
+            transport.notify(self.receive_queue.index());
+        }
+
+        has_received
+    }
+
+    /// Sends bytes and spins until the device has taken them.
+    fn transmit(&mut self, transport: &MmioTransport, bytes: &[u8]) {
+        let buffer = &TRANSMIT_BUFFERS[self.index];
+        let contents = unsafe { &mut *buffer.0.get() };
+
+        for chunk in bytes.chunks(BUFFER_SIZE) {
+            contents[..chunk.len()].copy_from_slice(chunk);
+
+            let posted = Buffer {
+                physical_address: buffer.physical_address(),
+                length: chunk.len() as u32,
+                is_device_writable: false,
+            };
+
+            // The buffer is the only one ever posted to the queue, and it was
+            // returned before this chunk.
+            if self.transmit_queue.add_buffers(&[posted]).is_err() {
+                return;
+            }
+
+            transport.notify(self.transmit_queue.index());
+
+            while self.transmit_queue.pop_used().is_none() {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+/// A port of the device.
+struct Port {
+    channel: Channel,
+
+    /// True once the device has added the port. Always true without the
+    /// multiport feature.
+    is_present: bool,
+
+    /// The bytes received but not yet read.
+    input: RingBuffer<u8, INPUT_CAPACITY>,
+}
+
+/// The initialized console device.
+struct VirtioConsole {
+    transport: MmioTransport,
+
+    /// The ports the driver set up queues for.
+    ports: [Option<Port>; MAX_PORTS],
+
+    /// The control queues, with the multiport feature.
+    control: Option<Channel>,
+}
+
+impl VirtioConsole {
+    /// Moves received bytes to the ports' input, and handles the control
+    /// messages the device sent.
+    ///
+    /// # Returns
+    ///
+    /// True if the console port received bytes.
+    fn reap(&mut self) -> bool {
+        let transport = self.transport;
+        let mut has_console_input = false;
+
+        for (port_number, port) in self.ports.iter_mut().enumerate() {
+            let Some(port) = port else {
+                continue;
+            };
+
+            let input = &mut port.input;
+
+            let has_received = port.channel.receive(&transport, |bytes| {
+                // Input that does not fit is dropped.
+                for &byte in bytes {
+                    let _ = input.push_back(byte);
+                }
+            });
+
+            has_console_input |= has_received && port_number == CONSOLE_PORT;
+        }
+
+        // The device fills at most every posted buffer between two reaps.
+        let mut messages = [(0u32, 0u16); RECEIVE_BUFFER_COUNT];
+        let mut message_count = 0;
+
+        if let Some(control) = &mut self.control {
+            control.receive(&transport, |bytes| {
+                if bytes.len() >= CONTROL_MESSAGE_SIZE && message_count < messages.len() {
+                    messages[message_count] = (
+                        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                        u16::from_le_bytes([bytes[4], bytes[5]]),
+                    );
+                    message_count += 1;
+                }
+            });
+        }
+
+        for &(id, event) in &messages[..message_count] {
+            self.handle_control_message(id as usize, event);
+        }
+
+        has_console_input
+    }
+
+    /// Handles a control message from the device.
+    fn handle_control_message(&mut self, port_number: usize, event: u16) {
+        match event {
+            EVENT_DEVICE_ADD => {
+                let Some(Some(port)) = self.ports.get_mut(port_number) else {
+                    // Ports the driver has no queues for are refused.
+                    self.send_control_message(port_number, EVENT_PORT_READY, 0);
+                    return;
+                };
+
+                port.is_present = true;
+
+                self.send_control_message(port_number, EVENT_PORT_READY, 1);
+                self.send_control_message(port_number, EVENT_PORT_OPEN, 1);
+            }
+            EVENT_DEVICE_REMOVE => {
+                if let Some(Some(port)) = self.ports.get_mut(port_number) {
+                    port.is_present = false;
+                }
+            }
+            // The console is always port 0, and the host end opening or
+            // closing a port or naming it changes nothing for the driver.
+            _ => {}
+        }
+    }
+
+    /// Sends a control message to the device.
+    fn send_control_message(&mut self, port_number: usize, event: u16, value: u16) {
+        let transport = self.transport;
+
+        let Some(control) = &mut self.control else {
+            return;
+        };
+
+        let mut message = [0; CONTROL_MESSAGE_SIZE];
+        message[0..4].copy_from_slice(&(port_number as u32).to_le_bytes());
+        message[4..6].copy_from_slice(&event.to_le_bytes());
+        message[6..8].copy_from_slice(&value.to_le_bytes());
+
+        control.transmit(&transport, &message);
+    }
+
+    /// Returns a port the device has added.
+    fn port(&mut self, port_number: usize) -> Result<&mut Port, PortError> {
+        match self.ports.get_mut(port_number) {
+            Some(Some(port)) if port.is_present => Ok(port),
+            _ => Err(PortError::NoPort(port_number)),
+        }
+    }
+}
+
+/// The console device, or `None` before `probe`.
+static DEVICE: SpinLockIrqSave<Option<VirtioConsole>> = SpinLockIrqSave::new(None);
+
+/// The receive buffers of every port, followed by those of the control
+/// receive queue.
+static RECEIVE_BUFFERS: [[IoBuffer; RECEIVE_BUFFER_COUNT]; MAX_PORTS + 1] =
+    [const { [const { IoBuffer(UnsafeCell::new([0; BUFFER_SIZE])) }; RECEIVE_BUFFER_COUNT] };
+        MAX_PORTS + 1];
+
+/// The transmit buffer of every port, followed by that of the control
+/// transmit queue.
+static TRANSMIT_BUFFERS: [IoBuffer; MAX_PORTS + 1] =
+    [const { IoBuffer(UnsafeCell::new([0; BUFFER_SIZE])) }; MAX_PORTS + 1];
+
+/// The virtio console as a console device.
+static VIRTIO_CONSOLE_DEVICE: ConsoleDevice = ConsoleDevice {
+    name: "virtio-console",
+    write: write_to_console,
+    read: Some(read_from_console),
+};
+
+/// Returns the index of the receive queue of a port.
+fn receive_queue_index(port_number: usize) -> u16 {
+    // Port 0 comes before the control queues and the other ports after.
+    match port_number {
+        0 => 0,
+        _ => (port_number as u16 + 1) * 2,
+    }
+}
+
+/// Sets up a virtio console device, opens its ports, and registers it as a
+/// console device. The PLIC must be initialized. Input is polled if the
+/// device's interrupt cannot be routed.
+///
+/// # Arguments
+///
+/// * `transport` - The register window of the device.
+///
+/// # Returns
+///
+/// The number of ports the driver set up.
+pub fn probe(transport: MmioTransport) -> Result<usize, VirtioError> {
+    if DEVICE.lock().is_some() {
+        return Err(VirtioError::AlreadyInitialized);
+    }
+
+    let features = transport.initialize(FEATURE_MULTIPORT)?;
+
+    let port_count = if features & FEATURE_MULTIPORT != 0 {
+        (transport.read_config_u32(CONFIG_MAX_PORTS_OFFSET) as usize).clamp(1, MAX_PORTS)
+    } else {
+        1
+    };
+
+    let setup_channels = || {
+        let mut ports = [const { None }; MAX_PORTS];
+
+        for (port_number, port) in ports.iter_mut().enumerate().take(port_count) {
+            *port = Some(Port {
+                channel: Channel::new(&transport, receive_queue_index(port_number), port_number)?,
+                is_present: features & FEATURE_MULTIPORT == 0,
+                input: RingBuffer::new(),
+            });
+        }
+
+        let control = if features & FEATURE_MULTIPORT != 0 {
+            Some(Channel::new(
+                &transport,
+                CONTROL_RECEIVE_QUEUE_INDEX,
+                MAX_PORTS,
+            )?)
+        } else {
+            None
+        };
+
+        Ok((ports, control))
+    };
+
+    let (ports, control) = match setup_channels() {
+        Ok(channels) => channels,
+        Err(error) => {
+            transport.fail();
+            return Err(error);
+        }
+    };
+
+    *DEVICE.lock() = Some(VirtioConsole {
+        transport,
+        ports,
+        control,
+    });
+
+    let enable_interrupt = || {
+        let irq = transport.irq()?;
+
+        irq::register_handler(irq, handle_interrupt, 0)
+            .and_then(|()| irq::enable(irq))
+            .map_err(VirtioError::Irq)
+    };
+
+    let has_interrupt = enable_interrupt().is_ok();
+
+    transport.driver_ok();
+
+    {
+        let mut device = DEVICE.lock();
+        let device = device.as_mut().expect("The device was just stored.");
+
+        for port_number in 0..port_count {
+            transport.notify(receive_queue_index(port_number));
+        }
+
+        if device.control.is_some() {
+            transport.notify(CONTROL_RECEIVE_QUEUE_INDEX);
+            device.send_control_message(0, EVENT_DEVICE_READY, 1);
+
+            // The device answers with the ports it has, which are opened now
+            // so the console can be written right away.
+            device.reap();
+        }
+    }
+
+    if has_interrupt {
+        console::enable_input_notifications();
+    }
+
+    let kind = if console::has_stdout_console() {
+        ConsoleKind::Secondary
+    } else {
+        ConsoleKind::Stdout
+    };
+
+    // Another console device that already took the last slot keeps the
+    // output.
+    let _ = console::register(&VIRTIO_CONSOLE_DEVICE, kind);
+
+    Ok(port_count)
+}
+
+/// Writes bytes to a port.
+///
+/// # Arguments
+///
+/// * `port_number` - The port.
+/// * `bytes` - The bytes to write.
+#[allow(dead_code)]
+pub fn write_port(port_number: usize, bytes: &[u8]) -> Result<(), PortError> {
+    let mut device = DEVICE.lock();
+    let device = device.as_mut().ok_or(PortError::NoDevice)?;
+    let transport = device.transport;
+
+    device.reap();
+    device
+        .port(port_number)?
+        .channel
+        .transmit(&transport, bytes);
+
+    Ok(())
+}
+
+/// Reads the bytes waiting on a port without blocking.
+///
+/// # Arguments
+///
+/// * `port_number` - The port.
+/// * `buffer` - Receives the bytes.
+///
+/// # Returns
+///
+/// The number of bytes read, which is zero if no input is waiting.
+#[allow(dead_code)]
+pub fn read_port(port_number: usize, buffer: &mut [u8]) -> Result<usize, PortError> {
+    let mut device = DEVICE.lock();
+    let device = device.as_mut().ok_or(PortError::NoDevice)?;
+
+    device.reap();
+
+    let input = &mut device.port(port_number)?.input;
+    let mut count = 0;
+
+    while count < buffer.len() {
+        let Some(byte) = input.pop_front() else {
+            break;
+        };
+
+        buffer[count] = byte;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Writes bytes to the console port for `VIRTIO_CONSOLE_DEVICE`.
+///
+/// Line feeds are expanded to a carriage return and line feed, like the
+/// UART does.
+fn write_to_console(bytes: &[u8]) {
+    let mut chunk = [0; BUFFER_SIZE];
+    let mut length = 0;
+
+    for &byte in bytes {
+        if byte == b'\n' {
+            chunk[length] = b'\r';
+            length += 1;
+        }
+
+        chunk[length] = byte;
+        length += 1;
+
+        if length >= BUFFER_SIZE - 1 {
+            let _ = write_port(CONSOLE_PORT, &chunk[..length]);
+            length = 0;
+        }
+    }
+
+    if length > 0 {
+        let _ = write_port(CONSOLE_PORT, &chunk[..length]);
+    }
+}
+
+/// Reads the bytes waiting on the console port for `VIRTIO_CONSOLE_DEVICE`.
+fn read_from_console(buffer: &mut [u8]) -> usize {
+    read_port(CONSOLE_PORT, buffer).unwrap_or(0)
+}
+
+/// Handles the console device's interrupt by moving received bytes to the
+/// ports' input and waking console readers.
+fn handle_interrupt(_irq: u32, _data: usize) {
+    let has_console_input = {
+        let mut device = DEVICE.lock();
+        let Some(device) = device.as_mut() else {
+            return;
+        };
+
+        if device.transport.acknowledge_interrupt() & INTERRUPT_USED_BUFFER == 0 {
+            return;
+        }
+
+        device.reap()
+    };
+
+    if has_console_input {
+        console::notify_input();
+    }
+}
@@ -0,0 +1,561 @@
+//! The virtio-mmio transport.
+//!
+//! QEMU's virt machine places its virtio devices behind "virtio,mmio" register
+//! windows listed in the DTB. Every window exists whether or not a device is
+//! attached to it, and an empty one reports device ID 0. The device manager
+//! probes every window, and those with a device behind them are handed to the
+//! driver of the device's type as an `MmioTransport`, which drives the device
+//! through the initialization sequence of the virtio specification: reset,
+//! feature negotiation, virtqueue setup, and DRIVER_OK.
+//!
+//! Both the legacy register layout (version 1), which QEMU uses by default,
+//! and the modern one (version 2) are supported. They differ in how
+//! virtqueues are set up and in the FEATURES_OK step. The device drivers,
+//! such as `block`, `console`, `net`, and `rng`, are built on top of this module.
+//!
+//! The registers are reached through the direct physical memory mapping.
+
+pub mod block;
+pub mod console;
+pub mod net;
+mod queue;
+pub mod rng;
+
+pub use queue::{Buffer, MAX_QUEUE_SIZE, VirtQueue};
+
+use crate::{
+    block::BlockError,
+    debug_println,
+    devices::{Device, Driver, ProbeError, ProbeStage},
+    irq::IrqError,
+    memory::{PAGE_SIZE, physical_to_virtual},
+};
+use core::fmt;
+use queue::USED_RING_ALIGNMENT;
+
+/// The driver of virtio-mmio register windows, which hands the devices behind
+/// them to the drivers of their types.
+pub static DRIVER: Driver = Driver {
+    name: "virtio-mmio",
+    compatibles: &["virtio,mmio"],
+    stage: ProbeStage::Device,
+    probe,
+};
+
+/// The value of the magic register, "virt" in little-endian.
+const MAGIC_VALUE: u32 = 0x7472_6976;
+
+/// The version register value of the legacy register layout.
+const LEGACY_VERSION: u32 = 1;
+
+/// The version register value of the modern register layout.
+const MODERN_VERSION: u32 = 2;
+
+// Register offsets shared by both layouts.
+const MAGIC_VALUE_OFFSET: usize = 0x000;
+const VERSION_OFFSET: usize = 0x004;
+const DEVICE_ID_OFFSET: usize = 0x008;
+const VENDOR_ID_OFFSET: usize = 0x00C;
+const DEVICE_FEATURES_OFFSET: usize = 0x010;
+const DEVICE_FEATURES_SELECT_OFFSET: usize = 0x014;
+const DRIVER_FEATURES_OFFSET: usize = 0x020;
+const DRIVER_FEATURES_SELECT_OFFSET: usize = 0x024;
+const QUEUE_SELECT_OFFSET: usize = 0x030;
+const QUEUE_SIZE_MAX_OFFSET: usize = 0x034;
+const QUEUE_SIZE_OFFSET: usize = 0x038;
+const QUEUE_NOTIFY_OFFSET: usize = 0x050;
+const INTERRUPT_STATUS_OFFSET: usize = 0x060;
+const INTERRUPT_ACKNOWLEDGE_OFFSET: usize = 0x064;
+const STATUS_OFFSET: usize = 0x070;
+const CONFIG_OFFSET: usize = 0x100;
+
+// Register offsets of the legacy layout only.
+const LEGACY_GUEST_PAGE_SIZE_OFFSET: usize = 0x028;
+const LEGACY_QUEUE_ALIGN_OFFSET: usize = 0x03C;
+const LEGACY_QUEUE_PFN_OFFSET: usize = 0x040;
+
+// Register offsets of the modern layout only.
+const QUEUE_READY_OFFSET: usize = 0x044;
+const QUEUE_DESCRIPTOR_LOW_OFFSET: usize = 0x080;
+const QUEUE_DESCRIPTOR_HIGH_OFFSET: usize = 0x084;
+const QUEUE_DRIVER_LOW_OFFSET: usize = 0x090;
+const QUEUE_DRIVER_HIGH_OFFSET: usize = 0x094;
+const QUEUE_DEVICE_LOW_OFFSET: usize = 0x0A0;
+const QUEUE_DEVICE_HIGH_OFFSET: usize = 0x0A4;
+const CONFIG_GENERATION_OFFSET: usize = 0x0FC;
+
+/// Device status bit: the driver has noticed the device.
+const STATUS_ACKNOWLEDGE: u32 = 1;
+
+/// Device status bit: the driver knows how to drive the device.
+const STATUS_DRIVER: u32 = 2;
+
+/// Device status bit: the driver is ready to use the device.
+const STATUS_DRIVER_OK: u32 = 4;
+
+/// Device status bit: feature negotiation is complete. Modern layout only.
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// Device status bit: the driver gave up on the device.
+const STATUS_FAILED: u32 = 128;
+
+/// The feature bit modern devices require the driver to accept.
+pub const FEATURE_VERSION_1: u64 = 1 << 32;
+
+/// The interrupt status bit raised when a used ring was updated.
+pub const INTERRUPT_USED_BUFFER: u32 = 1;
+
+/// The interrupt status bit raised when the device configuration changed.
+#[allow(dead_code)]
+pub const INTERRUPT_CONFIGURATION_CHANGE: u32 = 2;
+
+/// The device IDs of the virtio device types the kernel knows.
+pub mod device_id {
+    pub const NETWORK: u32 = 1;
+    pub const BLOCK: u32 = 2;
+    pub const CONSOLE: u32 = 3;
+    pub const ENTROPY: u32 = 4;
+}
+
+/// The reasons a virtio device could not be found or set up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VirtioError {
+    /// A device of the same type is set up already.
+    AlreadyInitialized,
+
+    /// The registers are outside the direct physical memory mapping, or they
+    /// do not hold the virtio magic value.
+    InvalidRegisters,
+
+    /// The register layout version is neither 1 nor 2.
+    UnsupportedVersion(u32),
+
+    /// The device did not accept the negotiated features.
+    FeaturesRejected,
+
+    /// The device does not have the queue, or it is already in use.
+    QueueUnavailable(u16),
+
+    /// No frame is left for the rings of a queue.
+    OutOfMemory,
+
+    /// The node has no interrupt the PLIC can deliver.
+    NoInterrupt,
+
+    /// The device's interrupt could not be registered or enabled.
+    Irq(IrqError),
+
+    /// The block layer did not accept the device.
+    Block(BlockError),
+}
+
+impl fmt::Display for VirtioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyInitialized => write!(f, "a device of this type is set up already"),
+            Self::InvalidRegisters => write!(f, "invalid register region"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported virtio-mmio version {}", version)
+            }
+            Self::FeaturesRejected => write!(f, "the device rejected the features"),
+            Self::QueueUnavailable(index) => write!(f, "queue {} is unavailable", index),
+            Self::OutOfMemory => write!(f, "out of memory"),
+            Self::NoInterrupt => write!(f, "no interrupt wired to the PLIC"),
+            Self::Irq(error) => write!(f, "{}", error),
+            Self::Block(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// The register window of a virtio device.
+#[derive(Debug, Copy, Clone)]
+pub struct MmioTransport {
+    /// The physical address of the registers.
+    physical_address: usize,
+
+    /// The virtual address of the registers.
+    base_address: usize,
+
+    /// The register layout version.
+    version: u32,
+
+    /// The type of the device.
+    device_id: u32,
+
+    /// The PLIC interrupt source of the device, if the DTB names one.
+    irq: Option<u32>,
+}
+
+impl MmioTransport {
+    /// Checks a register window for a device.
+    ///
+    /// # Arguments
+    ///
+    /// * `physical_address` - The physical address of the registers.
+    /// * `irq` - The PLIC interrupt source of the window, if it has one.
+    ///
+    /// # Returns
+    ///
+    /// The transport, or `Ok(None)` if no device is attached to the window.
+    fn new(physical_address: usize, irq: Option<u32>) -> Result<Option<Self>, VirtioError> {
+        let base_address =
+            physical_to_virtual(physical_address).ok_or(VirtioError::InvalidRegisters)?;
+
+        let mut transport = Self {
+            physical_address,
+            base_address,
+            version: 0,
+            device_id: 0,
+            irq,
+        };
+
+        if transport.read_register(MAGIC_VALUE_OFFSET) != MAGIC_VALUE {
+            return Err(VirtioError::InvalidRegisters);
+        }
+
+        transport.version = transport.read_register(VERSION_OFFSET);
+        if transport.version != LEGACY_VERSION && transport.version != MODERN_VERSION {
+            return Err(VirtioError::UnsupportedVersion(transport.version));
+        }
+
+        transport.device_id = transport.read_register(DEVICE_ID_OFFSET);
+        if transport.device_id == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(transport))
+    }
+
+    /// Returns the physical address of the registers.
+    pub fn physical_address(&self) -> usize {
+        self.physical_address
+    }
+
+    /// Returns the register layout version, 1 for legacy or 2 for modern.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the type of the device, one of `device_id`.
+    pub fn device_id(&self) -> u32 {
+        self.device_id
+    }
+
+    /// Returns the vendor ID of the device.
+    pub fn vendor_id(&self) -> u32 {
+        self.read_register(VENDOR_ID_OFFSET)
+    }
+
+    /// Returns the PLIC interrupt source of the device.
+    pub fn irq(&self) -> Result<u32, VirtioError> {
+        self.irq.ok_or(VirtioError::NoInterrupt)
+    }
+
+    /// Resets the device and negotiates its features. Must be followed by
+    /// `setup_queue` for every queue the driver uses and then `driver_ok`.
+    ///
+    /// # Arguments
+    ///
+    /// * `supported_features` - The device specific features the driver
+    ///   supports. `FEATURE_VERSION_1` is added for modern devices.
+    ///
+    /// # Returns
+    ///
+    /// The features both the device and the driver support, which are now in
+    /// effect. The device is marked failed if an error is returned.
+    pub fn initialize(&self, supported_features: u64) -> Result<u64, VirtioError> {
+        self.reset();
+
+        self.set_status(STATUS_ACKNOWLEDGE);
+        self.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        let device_features = self.read_features();
+        let supported_features = if self.is_legacy() {
+            supported_features & !FEATURE_VERSION_1
+        } else {
+            supported_features | FEATURE_VERSION_1
+        };
+
+        let features = device_features & supported_features;
+
+        if !self.is_legacy() && features & FEATURE_VERSION_1 == 0 {
+            self.set_status(STATUS_FAILED);
+            return Err(VirtioError::FeaturesRejected);
+        }
+
+        self.write_features(features);
+
+        if self.is_legacy() {
+            // Legacy devices locate the rings by page number.
+            self.write_register(LEGACY_GUEST_PAGE_SIZE_OFFSET, PAGE_SIZE as u32);
+        } else {
+            self.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+
+            if self.read_register(STATUS_OFFSET) & STATUS_FEATURES_OK == 0 {
+                self.set_status(STATUS_FAILED);
+                return Err(VirtioError::FeaturesRejected);
+            }
+        }
+
+        Ok(features)
+    }
+
+    /// Allocates the rings of a queue and hands them to the device.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the queue in the device.
+    ///
+    /// # Returns
+    ///
+    /// The queue, whose size is the smaller of what the device offers and
+    /// `MAX_QUEUE_SIZE`.
+    pub fn setup_queue(&self, index: u16) -> Result<VirtQueue, VirtioError> {
+        self.write_register(QUEUE_SELECT_OFFSET, index as u32);
+
+        let is_in_use = if self.is_legacy() {
+            self.read_register(LEGACY_QUEUE_PFN_OFFSET) != 0
+        } else {
+            self.read_register(QUEUE_READY_OFFSET) != 0
+        };
+
+        let size_max = self.read_register(QUEUE_SIZE_MAX_OFFSET);
+
+        if is_in_use || size_max == 0 {
+            return Err(VirtioError::QueueUnavailable(index));
+        }
+
+        // Split rings need a power of two size.
+        let size = 1 << size_max.min(MAX_QUEUE_SIZE as u32).ilog2();
+
+        let queue = VirtQueue::new(index, size).ok_or(VirtioError::OutOfMemory)?;
+
+        self.write_register(QUEUE_SIZE_OFFSET, size as u32);
+
+        if self.is_legacy() {
+            self.write_register(LEGACY_QUEUE_ALIGN_OFFSET, USED_RING_ALIGNMENT as u32);
+            self.write_register(LEGACY_QUEUE_PFN_OFFSET, queue.ppn().raw_ppn() as u32);
+        } else {
+            self.write_address(
+                QUEUE_DESCRIPTOR_LOW_OFFSET,
+                QUEUE_DESCRIPTOR_HIGH_OFFSET,
+                queue.descriptor_table_address(),
+            );
+            self.write_address(
+                QUEUE_DRIVER_LOW_OFFSET,
+                QUEUE_DRIVER_HIGH_OFFSET,
+                queue.available_ring_address(),
+            );
+            self.write_address(
+                QUEUE_DEVICE_LOW_OFFSET,
+                QUEUE_DEVICE_HIGH_OFFSET,
+                queue.used_ring_address(),
+            );
+            self.write_register(QUEUE_READY_OFFSET, 1);
+        }
+
+        Ok(queue)
+    }
+
+    /// Tells the device that the driver is set up, after which it may use the
+    /// queues.
+    pub fn driver_ok(&self) {
+        let status = self.read_register(STATUS_OFFSET);
+
+        self.set_status(status | STATUS_DRIVER_OK);
+    }
+
+    /// Marks the device failed, so it stops using its queues.
+    pub fn fail(&self) {
+        let status = self.read_register(STATUS_OFFSET);
+
+        self.set_status(status | STATUS_FAILED);
+    }
+
+    /// Resets the device, which stops it from using its queues. The queues'
+    /// rings may be freed afterwards.
+    pub fn reset(&self) {
+        self.set_status(0);
+    }
+
+    /// Tells the device that a queue has new buffers.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the queue.
+    pub fn notify(&self, index: u16) {
+        self.write_register(QUEUE_NOTIFY_OFFSET, index as u32);
+    }
+
+    /// Reads and acknowledges the pending interrupt conditions of the device.
+    /// Called from the device's interrupt handler.
+    ///
+    /// # Returns
+    ///
+    /// The `INTERRUPT_USED_BUFFER` and `INTERRUPT_CONFIGURATION_CHANGE` bits
+    /// that were pending.
+    pub fn acknowledge_interrupt(&self) -> u32 {
+        let status = self.read_register(INTERRUPT_STATUS_OFFSET);
+
+        self.write_register(INTERRUPT_ACKNOWLEDGE_OFFSET, status);
+
+        status
+    }
+
+    /// Reads a byte of the device specific configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset of the byte in the configuration.
+    pub fn read_config_u8(&self, offset: usize) -> u8 {
+        let address = self.base_address + CONFIG_OFFSET + offset;
+
+        unsafe { core::ptr::read_volatile(address as *const u8) }
+    }
+
+    /// Reads an aligned u32 of the device specific configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset of the value in the configuration.
+    pub fn read_config_u32(&self, offset: usize) -> u32 {
+        self.read_register(CONFIG_OFFSET + offset)
+    }
+
+    /// Reads an aligned u64 of the device specific configuration. Modern
+    /// devices are read again if the configuration changed in between the
+    /// two halves.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset of the value in the configuration.
+    pub fn read_config_u64(&self, offset: usize) -> u64 {
+        loop {
+            let generation = self.config_generation();
+
+            let low = self.read_config_u32(offset) as u64;
+            let high = self.read_config_u32(offset + 4) as u64;
+
+            if generation == self.config_generation() {
+                return (high << 32) | low;
+            }
+        }
+    }
+
+    /// Returns the configuration generation, which modern devices change
+    /// whenever the configuration changes. Always 0 for legacy devices.
+    fn config_generation(&self) -> u32 {
+        if self.is_legacy() {
+            0
+        } else {
+            self.read_register(CONFIG_GENERATION_OFFSET)
+        }
+    }
+
+    fn is_legacy(&self) -> bool {
+        self.version == LEGACY_VERSION
+    }
+
+    fn set_status(&self, status: u32) {
+        self.write_register(STATUS_OFFSET, status);
+    }
+
+    /// Reads the 64 feature bits of the device, 32 at a time.
+    fn read_features(&self) -> u64 {
+        self.write_register(DEVICE_FEATURES_SELECT_OFFSET, 0);
+        let low = self.read_register(DEVICE_FEATURES_OFFSET) as u64;
+
+        self.write_register(DEVICE_FEATURES_SELECT_OFFSET, 1);
+        let high = self.read_register(DEVICE_FEATURES_OFFSET) as u64;
+
+        (high << 32) | low
+    }
+
+    /// Writes the 64 feature bits the driver accepts, 32 at a time.
+    fn write_features(&self, features: u64) {
+        self.write_register(DRIVER_FEATURES_SELECT_OFFSET, 0);
+        self.write_register(DRIVER_FEATURES_OFFSET, features as u32);
+
+        self.write_register(DRIVER_FEATURES_SELECT_OFFSET, 1);
+        self.write_register(DRIVER_FEATURES_OFFSET, (features >> 32) as u32);
+    }
+
+    /// Writes a physical address to a pair of 32 bit registers.
+    fn write_address(&self, low_offset: usize, high_offset: usize, address: usize) {
+        self.write_register(low_offset, address as u32);
+        self.write_register(high_offset, (address >> 32) as u32);
+    }
+
+    fn read_register(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base_address + offset) as *const u32) }
+    }
+
+    fn write_register(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base_address + offset) as *mut u32, value) }
+    }
+}
+
+/// Checks a virtio-mmio register window for a device and sets it up with the
+/// driver of its type. Windows without a device are skipped.
+///
+/// # Arguments
+///
+/// * `device` - The register window.
+fn probe(device: &Device) -> Result<(), ProbeError> {
+    let physical_address = device.first_reg()?.physical_address as usize;
+
+    let Some(transport) = MmioTransport::new(physical_address, device.first_irq())? else {
+        return Err(ProbeError::Skipped);
+    };
+
+    debug_println!(
+        "Virtio {} device at {:#x}, version {}, vendor {:#x}, interrupt {}.",
+        device_type_name(transport.device_id()),
+        transport.physical_address(),
+        transport.version(),
+        transport.vendor_id(),
+        transport.irq().unwrap_or(0)
+    );
+
+    match transport.device_id() {
+        device_id::NETWORK => {
+            let mac_address = net::probe(transport)?;
+            debug_println!("Virtio network device with MAC address {}.", mac_address);
+        }
+        device_id::BLOCK => {
+            let capacity = block::probe(transport)?;
+            debug_println!(
+                "Virtio block device with {} sectors{}.",
+                capacity,
+                if block::is_read_only() {
+                    " (read only)"
+                } else {
+                    ""
+                }
+            );
+        }
+        device_id::CONSOLE => {
+            let port_count = console::probe(transport)?;
+            debug_println!("Virtio console with {} ports.", port_count);
+        }
+        device_id::ENTROPY => rng::probe(transport)?,
+        _ => return Err(ProbeError::Skipped),
+    }
+
+    Ok(())
+}
+
+/// Returns the name of a virtio device type.
+///
+/// # Arguments
+///
+/// * `device_id` - The type of the device.
+pub fn device_type_name(device_id: u32) -> &'static str {
+    match device_id {
+        device_id::NETWORK => "network",
+        device_id::BLOCK => "block",
+        device_id::CONSOLE => "console",
+        device_id::ENTROPY => "entropy",
+        _ => "unknown",
+    }
+}
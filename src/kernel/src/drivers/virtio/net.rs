@@ -0,0 +1,551 @@
+//! Driver for virtio network devices.
+//!
+//! The first virtio network device probed exchanges Ethernet frames
+//! through two queues: the device fills the receive buffers the driver posts
+//! on the receive queue, and sends the frames the driver posts on the
+//! transmit queue. Every frame is preceded by a virtio-net header, which is
+//! left zeroed since the driver negotiates no offloads.
+//!
+//! The packet buffers live in the kernel image so the device can reach them
+//! by physical address. Each is aligned to its size so that it never crosses
+//! a page boundary.
+//!
+//! The device raises an interrupt through the PLIC when it returns buffers.
+//! The handler raises the network softirq, which queues received frames for
+//! `receive_frame` and frees sent buffers for `send_frame`, waking the
+//! threads waiting for either.
+
+use super::{Buffer, INTERRUPT_USED_BUFFER, MmioTransport, VirtQueue, VirtioError};
+use crate::{
+    irq, memory,
+    softirq::{self, Softirq},
+    task::WaitQueue,
+};
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use kernel_lib::{net::MacAddress, ring_buffer::RingBuffer, sync::SpinLockIrqSave};
+
+/// The largest Ethernet frame the driver sends or receives, without the
+/// frame check sequence.
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+/// The feature bit of devices that have a MAC address in their
+/// configuration.
+const FEATURE_MAC: u64 = 1 << 5;
+
+/// The offset of the MAC address in the device configuration.
+const CONFIG_MAC_OFFSET: usize = 0;
+
+/// The size of the header of legacy devices, which lacks the `num_buffers`
+/// field without the mergeable receive buffers feature.
+const LEGACY_HEADER_SIZE: usize = 10;
+
+/// The size of the header of modern devices.
+const MODERN_HEADER_SIZE: usize = 12;
+
+/// The size of a packet buffer, which holds a header and a frame.
+const PACKET_BUFFER_SIZE: usize = 2048;
+
+/// The number of receive buffers posted to the device.
+const RECEIVE_BUFFER_COUNT: usize = 16;
+
+/// The number of frames that can be in flight to the device at once.
+const TRANSMIT_BUFFER_COUNT: usize = 8;
+
+/// The queue the device places received frames on.
+const RECEIVE_QUEUE_INDEX: u16 = 0;
+
+/// The queue the device takes frames to send from.
+const TRANSMIT_QUEUE_INDEX: u16 = 1;
+
+/// The MAC address used when the device does not offer one. The locally
+/// administered bit is set.
+const DEFAULT_MAC_ADDRESS: MacAddress = MacAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+
+/// The reasons a frame could not be sent or received.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetError {
+    /// No network device is initialized.
+    NoDevice,
+
+    /// The frame is longer than `MAX_FRAME_SIZE`. Holds its length.
+    FrameTooLarge(usize),
+
+    /// The buffer cannot hold the received frame, which is dropped. Holds the
+    /// length of the frame.
+    BufferTooSmall(usize),
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoDevice => write!(f, "no network device"),
+            Self::FrameTooLarge(length) => write!(f, "the {} byte frame is too large", length),
+            Self::BufferTooSmall(length) => {
+                write!(f, "the buffer is too small for the {} byte frame", length)
+            }
+        }
+    }
+}
+
+/// The counters of the device.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NetStatistics {
+    pub received_frames: u64,
+    pub sent_frames: u64,
+
+    /// Frames that arrived too short to hold a header, or too long for the
+    /// buffer of their reader.
+    pub dropped_frames: u64,
+}
+
+/// A buffer holding a header and a frame.
+#[repr(C, align(2048))]
+struct PacketBuffer(UnsafeCell<[u8; PACKET_BUFFER_SIZE]>);
+
+// A buffer is only accessed by the device while it is posted, and otherwise
+// by the driver with the device lock held.
+unsafe impl Sync for PacketBuffer {}
+
+impl PacketBuffer {
+    /// Returns the physical address of the buffer.
+    fn physical_address(&self) -> usize {
+        memory::virtual_to_physical(self.0.get() as usize)
+            .expect("The kernel image is mapped with 4KiB pages.")
+    }
+}
+
+/// A frame the device has placed in a receive buffer.
+#[derive(Debug, Copy, Clone)]
+struct ReceivedFrame {
+    /// The index of the buffer in `RECEIVE_BUFFERS`.
+    buffer_index: usize,
+
+    /// The number of bytes the device wrote, including the header.
+    length: usize,
+}
+
+/// The initialized network device.
+struct VirtioNet {
+    transport: MmioTransport,
+    receive_queue: VirtQueue,
+    transmit_queue: VirtQueue,
+    mac_address: MacAddress,
+
+    /// The size of the header before every frame.
+    header_size: usize,
+
+    /// The token each receive buffer was posted with, or `None` if the
+    /// buffer holds a received frame.
+    receive_tokens: [Option<u16>; RECEIVE_BUFFER_COUNT],
+
+    /// The token each transmit buffer was posted with, or `None` if the
+    /// buffer is free.
+    transmit_tokens: [Option<u16>; TRANSMIT_BUFFER_COUNT],
+
+    /// The frames received but not yet read, oldest first.
+    received_frames: RingBuffer<ReceivedFrame, RECEIVE_BUFFER_COUNT>,
+}
+
+impl VirtioNet {
+    /// Posts a receive buffer to the device. The device is not notified.
+    fn post_receive_buffer(&mut self, buffer_index: usize) {
+        let physical_address = RECEIVE_BUFFERS[buffer_index].physical_address();
+
+        // Legacy devices expect the header in a descriptor of its own.
+        let buffers = [
+            Buffer {
+                physical_address,
+                length: self.header_size as u32,
+                is_device_writable: true,
+            },
+            Buffer {
+                physical_address: physical_address + self.header_size,
+                length: (PACKET_BUFFER_SIZE - self.header_size) as u32,
+                is_device_writable: true,
+            },
+        ];
+
+        // The queue has two descriptors for every receive buffer.
+        let token = self
+            .receive_queue
+            .add_buffers(&buffers)
+            .expect("The receive queue has room for every receive buffer.");
+
+        self.receive_tokens[buffer_index] = Some(token);
+    }
+
+    /// Takes the buffers the device has returned on both queues.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - The most receive buffers to take.
+    ///
+    /// # Returns
+    ///
+    /// True if a frame was received, true if a transmit buffer was freed, and
+    /// true if the budget ran out before every receive buffer was taken.
+    fn reap(&mut self, budget: usize) -> (bool, bool, bool) {
+        let mut has_received = false;
+        let mut has_sent = false;
+        let mut reaped_count = 0;
+
+        while reaped_count < budget
+            && let Some(completion) = self.receive_queue.pop_used()
+        {
+            reaped_count += 1;
+
+            let Some(buffer_index) = self
+                .receive_tokens
+                .iter()
+                .position(|token| *token == Some(completion.token))
+            else {
+                continue;
+            };
+
+            self.receive_tokens[buffer_index] = None;
+
+            let length = completion.written_length as usize;
+
+            if length <= self.header_size {
+                RECEIVED_DROPPED_FRAME_COUNT.fetch_add(1, Ordering::Relaxed);
+                self.post_receive_buffer(buffer_index);
+                continue;
+            }
+
+            RECEIVED_FRAME_COUNT.fetch_add(1, Ordering::Relaxed);
+
+            // A buffer is either posted or queued here, so there is room.
+            let _ = self.received_frames.push_back(ReceivedFrame {
+                buffer_index,
+                length,
+            });
+
+            has_received = true;
+        }
+
+        while let Some(completion) = self.transmit_queue.pop_used() {
+            if let Some(token) = self
+                .transmit_tokens
+                .iter_mut()
+                .find(|token| **token == Some(completion.token))
+            {
+                *token = None;
+                has_sent = true;
+            }
+        }
+
+        (has_received, has_sent, reaped_count == budget)
+    }
+}
+
+/// The network device, or `None` before `probe`.
+static DEVICE: SpinLockIrqSave<Option<VirtioNet>> = SpinLockIrqSave::new(None);
+
+/// The buffers the device receives frames into.
+static RECEIVE_BUFFERS: [PacketBuffer; RECEIVE_BUFFER_COUNT] =
+    [const { PacketBuffer(UnsafeCell::new([0; PACKET_BUFFER_SIZE])) }; RECEIVE_BUFFER_COUNT];
+
+/// The buffers the device sends frames from.
+static TRANSMIT_BUFFERS: [PacketBuffer; TRANSMIT_BUFFER_COUNT] =
+    [const { PacketBuffer(UnsafeCell::new([0; PACKET_BUFFER_SIZE])) }; TRANSMIT_BUFFER_COUNT];
+
+/// The threads waiting for a frame to arrive.
+static RECEIVE_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// The threads waiting for a transmit buffer to be freed.
+static TRANSMIT_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+static RECEIVED_FRAME_COUNT: AtomicU64 = AtomicU64::new(0);
+static SENT_FRAME_COUNT: AtomicU64 = AtomicU64::new(0);
+static RECEIVED_DROPPED_FRAME_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Sets up a virtio network device, posts its receive buffers, and routes
+/// its interrupt to the calling hart. The PLIC must be initialized.
+///
+/// # Arguments
+///
+/// * `transport` - The register window of the device.
+///
+/// # Returns
+///
+/// The MAC address of the device.
+pub fn probe(transport: MmioTransport) -> Result<MacAddress, VirtioError> {
+    if DEVICE.lock().is_some() {
+        return Err(VirtioError::AlreadyInitialized);
+    }
+
+    let irq = transport.irq()?;
+
+    let features = transport.initialize(FEATURE_MAC)?;
+
+    let setup_queues = || {
+        let receive_queue = transport.setup_queue(RECEIVE_QUEUE_INDEX)?;
+        let transmit_queue = transport.setup_queue(TRANSMIT_QUEUE_INDEX)?;
+
+        Ok((receive_queue, transmit_queue))
+    };
+
+    let (receive_queue, transmit_queue) = match setup_queues() {
+        Ok(queues) => queues,
+        Err(error) => {
+            transport.fail();
+            return Err(error);
+        }
+    };
+
+    // Every buffer takes two descriptors.
+    if (receive_queue.size() as usize) < RECEIVE_BUFFER_COUNT * 2 {
+        transport.reset();
+        return Err(VirtioError::QueueUnavailable(RECEIVE_QUEUE_INDEX));
+    }
+
+    if (transmit_queue.size() as usize) < TRANSMIT_BUFFER_COUNT * 2 {
+        transport.reset();
+        return Err(VirtioError::QueueUnavailable(TRANSMIT_QUEUE_INDEX));
+    }
+
+    let mac_address = if features & FEATURE_MAC != 0 {
+        MacAddress(core::array::from_fn(|index| {
+            transport.read_config_u8(CONFIG_MAC_OFFSET + index)
+        }))
+    } else {
+        DEFAULT_MAC_ADDRESS
+    };
+
+    let mut device = VirtioNet {
+        transport,
+        receive_queue,
+        transmit_queue,
+        mac_address,
+        header_size: if transport.is_legacy() {
+            LEGACY_HEADER_SIZE
+        } else {
+            MODERN_HEADER_SIZE
+        },
+        receive_tokens: [None; RECEIVE_BUFFER_COUNT],
+        transmit_tokens: [None; TRANSMIT_BUFFER_COUNT],
+        received_frames: RingBuffer::new(),
+    };
+
+    for buffer_index in 0..RECEIVE_BUFFER_COUNT {
+        device.post_receive_buffer(buffer_index);
+    }
+
+    *DEVICE.lock() = Some(device);
+
+    let enable_interrupt = || {
+        softirq::register(Softirq::Network, process_buffers);
+
+        irq::register_handler(irq, handle_interrupt, 0)?;
+        irq::enable(irq)
+    };
+
+    if let Err(error) = enable_interrupt() {
+        transport.reset();
+        *DEVICE.lock() = None;
+
+        return Err(VirtioError::Irq(error));
+    }
+
+    transport.driver_ok();
+    transport.notify(RECEIVE_QUEUE_INDEX);
+
+    Ok(mac_address)
+}
+
+/// Returns the MAC address of the network device.
+pub fn mac_address() -> Result<MacAddress, NetError> {
+    DEVICE
+        .lock()
+        .as_ref()
+        .map(|device| device.mac_address)
+        .ok_or(NetError::NoDevice)
+}
+
+/// Returns the counters of the network device.
+pub fn statistics() -> NetStatistics {
+    NetStatistics {
+        received_frames: RECEIVED_FRAME_COUNT.load(Ordering::Relaxed),
+        sent_frames: SENT_FRAME_COUNT.load(Ordering::Relaxed),
+        dropped_frames: RECEIVED_DROPPED_FRAME_COUNT.load(Ordering::Relaxed),
+    }
+}
+
+/// Sends an Ethernet frame, blocking while every transmit buffer is in
+/// flight. Must be called from a kernel thread.
+///
+/// # Arguments
+///
+/// * `frame` - The frame, starting with the destination MAC address and
+///   without the frame check sequence, which the device adds.
+pub fn send_frame(frame: &[u8]) -> Result<(), NetError> {
+    if frame.len() > MAX_FRAME_SIZE {
+        return Err(NetError::FrameTooLarge(frame.len()));
+    }
+
+    let mut result = None;
+
+    TRANSMIT_WAIT_QUEUE.wait_until(|| {
+        result = try_send_frame(frame);
+        result.is_some()
+    });
+
+    result.unwrap_or(Err(NetError::NoDevice))
+}
+
+/// Receives an Ethernet frame, blocking until one arrives. Must be called
+/// from a kernel thread.
+///
+/// # Arguments
+///
+/// * `buffer` - Receives the frame. A buffer of `MAX_FRAME_SIZE` bytes holds
+///   any frame.
+///
+/// # Returns
+///
+/// The length of the frame.
+pub fn receive_frame(buffer: &mut [u8]) -> Result<usize, NetError> {
+    let mut result = None;
+
+    RECEIVE_WAIT_QUEUE.wait_until(|| {
+        result = try_receive_frame(buffer).transpose();
+        result.is_some()
+    });
+
+    result.unwrap_or(Err(NetError::NoDevice))
+}
+
+/// Receives an Ethernet frame if one has arrived, without blocking.
+///
+/// # Arguments
+///
+/// * `buffer` - Receives the frame.
+///
+/// # Returns
+///
+/// The length of the frame, or `None` if no frame is waiting.
+#[allow(dead_code)]
+pub fn try_receive_frame(buffer: &mut [u8]) -> Result<Option<usize>, NetError> {
+    let mut device = DEVICE.lock();
+    let device = device.as_mut().ok_or(NetError::NoDevice)?;
+
+    let Some(frame) = device.received_frames.pop_front() else {
+        return Ok(None);
+    };
+
+    let length = frame.length - device.header_size;
+
+    let result = if length <= buffer.len() {
+        let contents = unsafe { &*RECEIVE_BUFFERS[frame.buffer_index].0.get() };
+
+        buffer[..length].copy_from_slice(&contents[device.header_size..frame.length]);
+        Ok(Some(length))
+    } else {
+        RECEIVED_DROPPED_FRAME_COUNT.fetch_add(1, Ordering::Relaxed);
+        Err(NetError::BufferTooSmall(length))
+    };
+
+    device.post_receive_buffer(frame.buffer_index);
+    device.transport.notify(RECEIVE_QUEUE_INDEX);
+
+    result
+}
+
+/// Posts a frame to the device if a transmit buffer is free.
+///
+/// # Returns
+///
+/// The result, or `None` if every transmit buffer is in flight.
+fn try_send_frame(frame: &[u8]) -> Option<Result<(), NetError>> {
+    let mut device = DEVICE.lock();
+    let Some(device) = device.as_mut() else {
+        return Some(Err(NetError::NoDevice));
+    };
+
+    let buffer_index = device
+        .transmit_tokens
+        .iter()
+        .position(|token| token.is_none())?;
+
+    let buffer = &TRANSMIT_BUFFERS[buffer_index];
+    let contents = unsafe { &mut *buffer.0.get() };
+
+    contents[..device.header_size].fill(0);
+    contents[device.header_size..device.header_size + frame.len()].copy_from_slice(frame);
+
+    let physical_address = buffer.physical_address();
+
+    let buffers = [
+        Buffer {
+            physical_address,
+            length: device.header_size as u32,
+            is_device_writable: false,
+        },
+        Buffer {
+            physical_address: physical_address + device.header_size,
+            length: frame.len() as u32,
+            is_device_writable: false,
+        },
+    ];
+
+    // The queue has two descriptors for every transmit buffer.
+    let token = device
+        .transmit_queue
+        .add_buffers(&buffers)
+        .expect("The transmit queue has room for every transmit buffer.");
+
+    device.transmit_tokens[buffer_index] = Some(token);
+    device.transport.notify(TRANSMIT_QUEUE_INDEX);
+
+    SENT_FRAME_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    Some(Ok(()))
+}
+
+/// Handles the network device's interrupt by acknowledging it and leaving the
+/// returned buffers to `process_buffers`.
+fn handle_interrupt(_irq: u32, _data: usize) {
+    let mut device = DEVICE.lock();
+    let Some(device) = device.as_mut() else {
+        return;
+    };
+
+    if device.transport.acknowledge_interrupt() & INTERRUPT_USED_BUFFER != 0 {
+        softirq::raise(Softirq::Network);
+    }
+}
+
+/// The network softirq. Takes the buffers the device returned and wakes the
+/// threads waiting for them.
+///
+/// # Arguments
+///
+/// * `budget` - The most received frames to take.
+///
+/// # Returns
+///
+/// True if received frames are left.
+fn process_buffers(budget: usize) -> bool {
+    let (has_received, has_sent, has_more) = {
+        let mut device = DEVICE.lock();
+        let Some(device) = device.as_mut() else {
+            return false;
+        };
+
+        device.reap(budget)
+    };
+
+    if has_received {
+        RECEIVE_WAIT_QUEUE.wake_all();
+    }
+
+    if has_sent {
+        TRANSMIT_WAIT_QUEUE.wake_all();
+    }
+
+    has_more
+}
@@ -0,0 +1,399 @@
+//! Split virtqueues.
+//!
+//! A split virtqueue is made of three rings in memory shared with the device:
+//! the descriptor table, which describes the buffers, the available ring, in
+//! which the driver passes chains of descriptors to the device, and the used
+//! ring, in which the device returns them once it has processed them. All
+//! three rings of a queue are placed in a single page of a `DmaBuffer`.
+
+use crate::memory::{DmaBuffer, PAGE_SIZE};
+use common_lib::memory::PhysicalPageNumber;
+use core::{
+    fmt,
+    sync::atomic::{Ordering, fence},
+};
+
+/// The largest queue size used, whatever the device offers. The rings of a
+/// queue of this size fit in one page.
+pub const MAX_QUEUE_SIZE: u16 = 128;
+
+/// The alignment of the used ring. The rings are packed into one page, so the
+/// used ring only gets the 4 byte alignment it needs rather than the page
+/// alignment legacy devices assume by default.
+pub const USED_RING_ALIGNMENT: usize = 4;
+
+/// The descriptor flag marking that the chain continues at `next`.
+const DESCRIPTOR_NEXT: u16 = 1;
+
+/// The descriptor flag marking a buffer the device writes rather than reads.
+const DESCRIPTOR_WRITE: u16 = 2;
+
+/// The size of the flags and index fields at the start of the available and
+/// used rings.
+const RING_HEADER_SIZE: usize = 4;
+
+/// An entry of the descriptor table.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct Descriptor {
+    /// The physical address of the buffer.
+    address: u64,
+
+    /// The length of the buffer in bytes.
+    length: u32,
+
+    flags: u16,
+
+    /// The index of the next descriptor of the chain, or of the next free
+    /// descriptor while the descriptor is free.
+    next: u16,
+}
+
+/// An entry of the used ring.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct UsedElement {
+    /// The index of the first descriptor of the returned chain.
+    id: u32,
+
+    /// The number of bytes the device wrote into the chain.
+    length: u32,
+}
+
+/// A buffer to pass to the device.
+#[derive(Debug, Copy, Clone)]
+pub struct Buffer {
+    /// The physical address of the buffer.
+    pub physical_address: usize,
+
+    /// The length of the buffer in bytes.
+    pub length: u32,
+
+    /// True if the device writes the buffer, false if it reads it.
+    pub is_device_writable: bool,
+}
+
+/// A chain of buffers the device has finished with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// The token `add_buffers` returned for the chain.
+    pub token: u16,
+
+    /// The number of bytes the device wrote into the chain.
+    pub written_length: u32,
+}
+
+/// The reasons buffers could not be added to a queue.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QueueError {
+    /// No buffers were given.
+    Empty,
+
+    /// Not enough descriptors are free for the buffers.
+    Full,
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no buffers given"),
+            Self::Full => write!(f, "the queue is full"),
+        }
+    }
+}
+
+/// A split virtqueue and the driver's view of it.
+pub struct VirtQueue {
+    /// The index of the queue in the device.
+    index: u16,
+
+    /// The number of descriptors, a power of two.
+    size: u16,
+
+    /// The page holding the rings, which is freed when the queue is dropped.
+    /// The device must have been reset first, so that it no longer uses them.
+    rings: DmaBuffer,
+
+    /// The virtual address of the page holding the rings.
+    base_address: usize,
+
+    /// The first descriptor of the free list, which is linked through `next`.
+    free_head: u16,
+
+    /// The number of descriptors in the free list.
+    free_count: u16,
+
+    /// The index the next chain is placed at in the available ring.
+    available_index: u16,
+
+    /// The index of the next entry of the used ring the driver has not seen.
+    last_used_index: u16,
+}
+
+// The rings are only reached through the queue, and the device only accesses
+// them in the ways the virtio specification allows.
+unsafe impl Send for VirtQueue {}
+
+impl VirtQueue {
+    /// Allocates and initializes the rings of a queue. The device is not told
+    /// about them.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the queue in the device.
+    /// * `size` - The number of descriptors, a power of two no larger than
+    ///   `MAX_QUEUE_SIZE`.
+    ///
+    /// # Returns
+    ///
+    /// The queue, or `None` if no frame is left for the rings.
+    pub fn new(index: u16, size: u16) -> Option<Self> {
+        assert!(
+            size.is_power_of_two() && size <= MAX_QUEUE_SIZE,
+            "Invalid virtqueue size {}.",
+            size
+        );
+
+        let rings = DmaBuffer::new(PAGE_SIZE, PAGE_SIZE)?;
+        let base_address = rings.virtual_address();
+
+        let queue = Self {
+            index,
+            size,
+            rings,
+            base_address,
+            free_head: 0,
+            free_count: size,
+            available_index: 0,
+            last_used_index: 0,
+        };
+
+        // The frame is zeroed, so only the free list needs to be linked.
+        for descriptor_index in 0..size {
+            queue.write_descriptor(
+                descriptor_index,
+                Descriptor {
+                    address: 0,
+                    length: 0,
+                    flags: 0,
+                    next: descriptor_index.wrapping_add(1),
+                },
+            );
+        }
+
+        Some(queue)
+    }
+
+    /// Returns the index of the queue in the device.
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Returns the number of descriptors of the queue.
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Returns the number of descriptors that are free.
+    #[allow(dead_code)]
+    pub fn free_descriptor_count(&self) -> u16 {
+        self.free_count
+    }
+
+    /// Returns the physical page number of the page holding the rings.
+    pub fn ppn(&self) -> PhysicalPageNumber {
+        self.rings.ppn()
+    }
+
+    /// Returns the physical address of the descriptor table.
+    pub fn descriptor_table_address(&self) -> usize {
+        self.rings.physical_address()
+    }
+
+    /// Returns the physical address of the available ring.
+    pub fn available_ring_address(&self) -> usize {
+        self.rings.physical_address() + self.available_ring_offset()
+    }
+
+    /// Returns the physical address of the used ring.
+    pub fn used_ring_address(&self) -> usize {
+        self.rings.physical_address() + self.used_ring_offset()
+    }
+
+    /// Passes a chain of buffers to the device. The device is not notified.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffers` - The buffers in the order the device processes them. The
+    ///   buffers the device reads must come before the ones it writes, and
+    ///   each must stay valid until the chain is returned by `pop_used`.
+    ///
+    /// # Returns
+    ///
+    /// The token `pop_used` returns the chain with.
+    pub fn add_buffers(&mut self, buffers: &[Buffer]) -> Result<u16, QueueError> {
+        if buffers.is_empty() {
+            return Err(QueueError::Empty);
+        }
+
+        if buffers.len() > self.free_count as usize {
+            return Err(QueueError::Full);
+        }
+
+        let head = self.free_head;
+        let mut descriptor_index = head;
+
+        for (position, buffer) in buffers.iter().enumerate() {
+            let next = self.read_descriptor(descriptor_index).next;
+            let is_last = position + 1 == buffers.len();
+
+            let mut flags = 0;
+            if buffer.is_device_writable {
+                flags |= DESCRIPTOR_WRITE;
+            }
+            if !is_last {
+                flags |= DESCRIPTOR_NEXT;
+            }
+
+            // The chain reuses the links of the free list, so `next` stays.
+            self.write_descriptor(
+                descriptor_index,
+                Descriptor {
+                    address: buffer.physical_address as u64,
+                    length: buffer.length,
+                    flags,
+                    next,
+                },
+            );
+
+            if !is_last {
+                descriptor_index = next;
+            } else {
+                self.free_head = next;
+            }
+        }
+
+        self.free_count -= buffers.len() as u16;
+
+        let ring_offset = self.available_ring_offset()
+            + RING_HEADER_SIZE
+            + (self.available_index % self.size) as usize * 2;
+        self.write_u16(ring_offset, head);
+
+        // The device must see the descriptors and the ring entry before the
+        // new index.
+        fence(Ordering::SeqCst);
+
+        self.available_index = self.available_index.wrapping_add(1);
+        self.write_u16(self.available_ring_offset() + 2, self.available_index);
+
+        fence(Ordering::SeqCst);
+
+        Ok(head)
+    }
+
+    /// Takes the next chain the device has finished with and frees its
+    /// descriptors.
+    ///
+    /// # Returns
+    ///
+    /// The chain, or `None` if the device has not returned another one.
+    pub fn pop_used(&mut self) -> Option<Completion> {
+        let used_index = self.read_u16(self.used_ring_offset() + 2);
+
+        if used_index == self.last_used_index {
+            return None;
+        }
+
+        // The entry must not be read before the index that publishes it.
+        fence(Ordering::SeqCst);
+
+        let element_offset = self.used_ring_offset()
+            + RING_HEADER_SIZE
+            + (self.last_used_index % self.size) as usize * size_of::<UsedElement>();
+        let element = unsafe {
+            core::ptr::read_volatile((self.base_address + element_offset) as *const UsedElement)
+        };
+
+        self.last_used_index = self.last_used_index.wrapping_add(1);
+
+        let head = element.id as u16;
+        self.free_chain(head);
+
+        Some(Completion {
+            token: head,
+            written_length: element.length,
+        })
+    }
+
+    /// Returns the descriptors of a chain to the free list.
+    fn free_chain(&mut self, head: u16) {
+        let mut descriptor_index = head;
+        let mut count = 1;
+
+        loop {
+            let descriptor = self.read_descriptor(descriptor_index);
+            if descriptor.flags & DESCRIPTOR_NEXT == 0 {
+                break;
+            }
+
+            descriptor_index = descriptor.next;
+            count += 1;
+        }
+
+        let mut last = self.read_descriptor(descriptor_index);
+        last.next = self.free_head;
+        self.write_descriptor(descriptor_index, last);
+
+        self.free_head = head;
+        self.free_count += count;
+    }
+
+    /// Returns the offset of the available ring in the frame, right after the
+    /// descriptor table.
+    fn available_ring_offset(&self) -> usize {
+        self.size as usize * size_of::<Descriptor>()
+    }
+
+    /// Returns the offset of the used ring in the frame, after the available
+    /// ring and its trailing `used_event` field.
+    fn used_ring_offset(&self) -> usize {
+        let available_ring_end =
+            self.available_ring_offset() + RING_HEADER_SIZE + self.size as usize * 2 + 2;
+
+        available_ring_end.next_multiple_of(USED_RING_ALIGNMENT)
+    }
+
+    fn read_descriptor(&self, descriptor_index: u16) -> Descriptor {
+        let address = self.base_address + descriptor_index as usize * size_of::<Descriptor>();
+
+        unsafe { core::ptr::read_volatile(address as *const Descriptor) }
+    }
+
+    fn write_descriptor(&self, descriptor_index: u16, descriptor: Descriptor) {
+        let address = self.base_address + descriptor_index as usize * size_of::<Descriptor>();
+
+        unsafe { core::ptr::write_volatile(address as *mut Descriptor, descriptor) }
+    }
+
+    fn read_u16(&self, offset: usize) -> u16 {
+        unsafe { core::ptr::read_volatile((self.base_address + offset) as *const u16) }
+    }
+
+    fn write_u16(&self, offset: usize, value: u16) {
+        unsafe { core::ptr::write_volatile((self.base_address + offset) as *mut u16, value) }
+    }
+}
+
+const _: () = assert!(
+    (MAX_QUEUE_SIZE as usize * size_of::<Descriptor>()
+        + RING_HEADER_SIZE
+        + MAX_QUEUE_SIZE as usize * 2
+        + 2)
+    .next_multiple_of(USED_RING_ALIGNMENT)
+        + RING_HEADER_SIZE
+        + MAX_QUEUE_SIZE as usize * size_of::<UsedElement>()
+        + 2
+        <= PAGE_SIZE,
+    "The rings of a queue of MAX_QUEUE_SIZE must fit in a page."
+);
@@ -0,0 +1,117 @@
+//! Driver for virtio entropy devices.
+//!
+//! The first virtio entropy device probed fills the buffers posted on
+//! its only queue with random bytes. Requests are served one at a time and
+//! the driver spins until the device returns the buffer, which QEMU does as
+//! soon as it is notified. The bytes seed the kernel's entropy pool rather
+//! than being handed out directly.
+
+use super::{Buffer, MmioTransport, VirtQueue, VirtioError};
+use crate::memory;
+use core::cell::UnsafeCell;
+use kernel_lib::sync::SpinLockIrqSave;
+
+/// The most bytes a single request returns.
+pub const MAX_REQUEST_SIZE: usize = 64;
+
+/// The index of the request queue.
+const REQUEST_QUEUE_INDEX: u16 = 0;
+
+/// The buffer the device writes random bytes into.
+#[repr(C, align(64))]
+struct RequestBuffer(UnsafeCell<[u8; MAX_REQUEST_SIZE]>);
+
+// The buffer is only accessed by the device while it is posted, and otherwise
+// by the driver with the device lock held.
+unsafe impl Sync for RequestBuffer {}
+
+/// The initialized entropy device.
+struct VirtioRng {
+    transport: MmioTransport,
+    request_queue: VirtQueue,
+}
+
+/// The entropy device, or `None` before `probe`.
+static DEVICE: SpinLockIrqSave<Option<VirtioRng>> = SpinLockIrqSave::new(None);
+
+static REQUEST_BUFFER: RequestBuffer = RequestBuffer(UnsafeCell::new([0; MAX_REQUEST_SIZE]));
+
+/// Sets up a virtio entropy device.
+///
+/// # Arguments
+///
+/// * `transport` - The register window of the device.
+pub fn probe(transport: MmioTransport) -> Result<(), VirtioError> {
+    if DEVICE.lock().is_some() {
+        return Err(VirtioError::AlreadyInitialized);
+    }
+
+    transport.initialize(0)?;
+
+    let request_queue = match transport.setup_queue(REQUEST_QUEUE_INDEX) {
+        Ok(queue) => queue,
+        Err(error) => {
+            transport.fail();
+            return Err(error);
+        }
+    };
+
+    transport.driver_ok();
+
+    *DEVICE.lock() = Some(VirtioRng {
+        transport,
+        request_queue,
+    });
+
+    Ok(())
+}
+
+/// Returns true if an entropy device is initialized.
+pub fn is_available() -> bool {
+    DEVICE.lock().is_some()
+}
+
+/// Asks the device for random bytes and spins until it answers.
+///
+/// # Arguments
+///
+/// * `buffer` - Receives the bytes. At most `MAX_REQUEST_SIZE` bytes are
+///   requested.
+///
+/// # Returns
+///
+/// The number of bytes received, which the device may make fewer than asked
+/// for, or `None` if no device is initialized.
+pub fn read(buffer: &mut [u8]) -> Option<usize> {
+    let mut device = DEVICE.lock();
+    let device = device.as_mut()?;
+
+    let length = buffer.len().min(MAX_REQUEST_SIZE);
+    let physical_address = memory::virtual_to_physical(REQUEST_BUFFER.0.get() as usize)
+        .expect("The kernel image is mapped with 4KiB pages.");
+
+    let request = Buffer {
+        physical_address,
+        length: length as u32,
+        is_device_writable: true,
+    };
+
+    // Requests are served one at a time, so the queue is empty.
+    device.request_queue.add_buffers(&[request]).ok()?;
+    device.transport.notify(REQUEST_QUEUE_INDEX);
+
+    let completion = loop {
+        if let Some(completion) = device.request_queue.pop_used() {
+            break completion;
+        }
+
+        core::hint::spin_loop();
+    };
+
+    let written_length = (completion.written_length as usize).min(length);
+    let contents = unsafe { &*REQUEST_BUFFER.0.get() };
+
+    buffer[..written_length].copy_from_slice(&contents[..written_length]);
+
+    Some(written_length)
+}
@@ -0,0 +1,142 @@
+//! The read-only FAT32 file system on a block device.
+
+use super::{FileSystem, FsError, Inode, NodeKind};
+use crate::block::{self, BlockDeviceHandle, BlockError};
+use core::fmt;
+use kernel_lib::{
+    fat32::{self, DirectoryEntry, Fat32, Fat32Error, SectorReader},
+    sync::Once,
+};
+
+/// The bit of an inode ID that is set for directories. The bits below it hold
+/// the first cluster.
+const DIRECTORY_ID_BIT: u64 = 1 << 32;
+
+impl SectorReader for BlockDeviceHandle {
+    type Error = BlockError;
+
+    fn read_sectors(&self, first_sector: usize, buffer: &mut [u8]) -> Result<(), BlockError> {
+        self.read_blocks(first_sector, buffer)
+    }
+}
+
+impl From<Fat32Error<BlockError>> for FsError {
+    fn from(error: Fat32Error<BlockError>) -> Self {
+        match error {
+            Fat32Error::NotFound => Self::NotFound,
+            Fat32Error::NotADirectory => Self::NotADirectory,
+            Fat32Error::IsADirectory => Self::IsADirectory,
+            error => Self::Fat32(error),
+        }
+    }
+}
+
+/// A FAT32 file system on a block device.
+pub struct Fat32FileSystem(Fat32<BlockDeviceHandle>);
+
+impl Fat32FileSystem {
+    /// Returns the size of a cluster in bytes.
+    pub fn cluster_size(&self) -> usize {
+        self.0.cluster_size()
+    }
+
+    /// Returns the number of data clusters.
+    pub fn cluster_count(&self) -> usize {
+        self.0.cluster_count()
+    }
+
+    /// Returns the inode of an entry.
+    fn inode(entry: &DirectoryEntry) -> Inode {
+        let id = entry.first_cluster() as u64;
+
+        if entry.is_directory() {
+            Inode {
+                id: id | DIRECTORY_ID_BIT,
+                kind: NodeKind::Directory,
+                size: 0,
+            }
+        } else {
+            Inode {
+                id,
+                kind: NodeKind::File,
+                size: entry.size(),
+            }
+        }
+    }
+
+    /// Returns the entry of an inode.
+    fn entry(&self, inode: &Inode) -> DirectoryEntry {
+        self.0.entry_at(
+            inode.id as u32,
+            inode.kind == NodeKind::Directory,
+            inode.size,
+        )
+    }
+}
+
+impl FileSystem for Fat32FileSystem {
+    fn type_name(&self) -> &'static str {
+        "fat32"
+    }
+
+    fn lookup(&self, path: &str) -> Result<Inode, FsError> {
+        Ok(Self::inode(&self.0.open(path)?))
+    }
+
+    fn read(&self, file: &Inode, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        Ok(self.0.read(&self.entry(file), offset, buffer)?)
+    }
+
+    fn for_each_entry(
+        &self,
+        directory: &Inode,
+        callback: &mut dyn FnMut(&dyn fmt::Display, &Inode),
+    ) -> Result<(), FsError> {
+        for entry in self.0.entries(&self.entry(directory))? {
+            let entry = entry?;
+
+            callback(&entry.name(), &Self::inode(&entry));
+        }
+
+        Ok(())
+    }
+}
+
+/// The FAT32 file system, once mounted.
+static FILE_SYSTEM: Once<Fat32FileSystem> = Once::new();
+
+/// Mounts the FAT32 file system on a block device.
+///
+/// Only one FAT32 file system can be mounted. Reading the device blocks, so
+/// this must be called from a kernel thread.
+///
+/// # Arguments
+///
+/// * `device_name` - The name of the block device.
+/// * `path` - The path to mount the file system at.
+///
+/// # Returns
+///
+/// The mounted file system.
+pub fn mount_device(
+    device_name: &str,
+    path: &'static str,
+) -> Result<&'static Fat32FileSystem, FsError> {
+    if FILE_SYSTEM.is_completed() {
+        return Err(FsError::AlreadyMounted);
+    }
+
+    let device = block::find(device_name).ok_or(FsError::NoDevice)?;
+
+    if device.block_size() != fat32::SECTOR_SIZE {
+        return Err(FsError::UnsupportedBlockSize(device.block_size()));
+    }
+
+    let file_system = Fat32::mount(device)?;
+
+    let file_system = FILE_SYSTEM.call_once(|| Fat32FileSystem(file_system));
+
+    super::mount(path, file_system)?;
+
+    Ok(file_system)
+}
@@ -0,0 +1,141 @@
+//! The initial ramdisk, a CPIO "newc" archive the bootloader loads into memory
+//! and names in the /chosen node of the DTB.
+//!
+//! The bootloader carves the archive's pages out of the memory map, so it
+//! stays in place for as long as the kernel runs and is read through the
+//! direct physical memory mapping. Only regular files and directories are
+//! visible; other entries, such as symbolic links, are skipped.
+
+use super::{FileSystem, FsError, Inode, NodeKind};
+use crate::memory;
+use common_lib::dtb::{self, Dtb};
+use core::fmt;
+use kernel_lib::{
+    cpio::{CpioArchive, CpioEntry, EntryKind},
+    sync::Once,
+};
+
+/// The shift of the path length in an inode ID. The bits below it hold the
+/// offset of the entry, or for a directory without an entry, of the first
+/// entry within it, whose path starts with the directory's path.
+const PATH_LENGTH_SHIFT: u32 = 32;
+
+/// The initial ramdisk.
+pub struct Initramfs(CpioArchive<'static>);
+
+impl Initramfs {
+    /// Returns the inode of an entry, or `None` for entries that are neither
+    /// files nor directories.
+    fn inode(entry: &CpioEntry) -> Option<Inode> {
+        let id = ((entry.path.len() as u64) << PATH_LENGTH_SHIFT) | entry.offset as u64;
+
+        match entry.kind {
+            EntryKind::File => Some(Inode {
+                id,
+                kind: NodeKind::File,
+                size: entry.data.len(),
+            }),
+            EntryKind::Directory => Some(Inode {
+                id,
+                kind: NodeKind::Directory,
+                size: 0,
+            }),
+            EntryKind::Other => None,
+        }
+    }
+
+    /// Returns the path of a directory inode.
+    fn directory_path(&self, directory: &Inode) -> Result<&'static str, FsError> {
+        let path_length = (directory.id >> PATH_LENGTH_SHIFT) as usize;
+        if path_length == 0 {
+            return Ok("");
+        }
+
+        self.0
+            .entry(directory.id as u32 as usize)
+            .and_then(|entry| entry.path.get(..path_length))
+            .ok_or(FsError::NotFound)
+    }
+}
+
+impl FileSystem for Initramfs {
+    fn type_name(&self) -> &'static str {
+        "initramfs"
+    }
+
+    fn lookup(&self, path: &str) -> Result<Inode, FsError> {
+        self.0
+            .find(path)
+            .as_ref()
+            .and_then(Self::inode)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn read(&self, file: &Inode, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let entry = self
+            .0
+            .entry(file.id as u32 as usize)
+            .filter(|entry| entry.kind == EntryKind::File)
+            .ok_or(FsError::NotFound)?;
+
+        let data = entry.data.get(offset..).unwrap_or(&[]);
+        let length = buffer.len().min(data.len());
+
+        buffer[..length].copy_from_slice(&data[..length]);
+
+        Ok(length)
+    }
+
+    fn for_each_entry(
+        &self,
+        directory: &Inode,
+        callback: &mut dyn FnMut(&dyn fmt::Display, &Inode),
+    ) -> Result<(), FsError> {
+        let path = self.directory_path(directory)?;
+
+        self.0.for_each_child(path, |name, entry| {
+            if let Some(inode) = Self::inode(&entry) {
+                callback(&name, &inode);
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// The initial ramdisk, once found.
+static INITRAMFS: Once<Initramfs> = Once::new();
+
+/// Finds the initial ramdisk the bootloader passed and mounts it at "/".
+///
+/// # Arguments
+///
+/// * `dtb` - The DTB, whose /chosen node has the physical address range of
+///   the initial ramdisk.
+///
+/// # Returns
+///
+/// The size of the archive in bytes, or `None` if the bootloader passed no
+/// initial ramdisk.
+pub fn mount_from_dtb(dtb: &Dtb) -> Result<Option<usize>, FsError> {
+    let Some((start, end)) = dtb::chosen(dtb).and_then(|chosen| chosen.initrd_range()) else {
+        return Ok(None);
+    };
+
+    let size = end.saturating_sub(start) as usize;
+
+    // The archive must be covered by the direct mapping as a whole.
+    let virtual_address = memory::physical_to_virtual(start as usize)
+        .filter(|_| memory::physical_to_virtual(start as usize + size.saturating_sub(1)).is_some())
+        .ok_or(FsError::NotFound)?;
+
+    // The bootloader carved the archive out of the memory map, so nothing
+    // else uses or frees its pages.
+    let data = unsafe { core::slice::from_raw_parts(virtual_address as *const u8, size) };
+
+    let archive = CpioArchive::parse(data).map_err(FsError::Cpio)?;
+
+    super::mount("/", INITRAMFS.call_once(|| Initramfs(archive)))?;
+
+    Ok(Some(size))
+}
@@ -0,0 +1,445 @@
+//! The virtual file system.
+//!
+//! File systems implement `FileSystem` and are mounted at a path with
+//! `mount`. `open` resolves a path through the mount whose path is the
+//! longest prefix of it, and returns a `Node` that reads the file or lists
+//! the directory, whichever file system it is on. Processes read and write
+//! nodes through an `OpenFile`, which keeps their offset.
+//!
+//! The initial ramdisk is mounted at "/" during boot when the bootloader
+//! passes one, a tmpfs at "/tmp", and the FAT32 file system on the root block
+//! device is mounted at "/", or at "/mnt" below the ramdisk, by a kernel
+//! thread. Reading FAT32 blocks on the block device, so it must be done from
+//! kernel threads. Only the tmpfs is writable.
+
+pub mod fat32;
+pub mod initramfs;
+mod open_file;
+pub mod tmpfs;
+
+pub use open_file::{OpenFile, SeekFrom};
+
+use crate::block::BlockError;
+use core::fmt;
+use kernel_lib::{cpio::CpioError, fat32::Fat32Error, sync::SpinLock, tmpfs::TmpfsError};
+
+/// The most file systems that can be mounted at once.
+pub const MAX_MOUNTS: usize = 4;
+
+/// The reasons a file system could not be mounted or a path not opened or
+/// read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FsError {
+    /// No file system is mounted at or above the path.
+    NotMounted,
+
+    /// A file system is mounted at the path already.
+    AlreadyMounted,
+
+    /// All `MAX_MOUNTS` mounts are in use.
+    TooManyMounts,
+
+    /// Nothing has the path.
+    NotFound,
+
+    /// A path component or the node to list is not a directory.
+    NotADirectory,
+
+    /// The node to read or write is a directory.
+    IsADirectory,
+
+    /// The directory has an entry with the name already.
+    AlreadyExists,
+
+    /// The directory to remove has entries.
+    NotEmpty,
+
+    /// The file system cannot be written.
+    ReadOnly,
+
+    /// The path names no file or directory to create, such as "/".
+    InvalidPath,
+
+    /// A seek would move the offset before the start of the file.
+    InvalidOffset,
+
+    /// All `MAX_OPEN_FILES` open files are in use.
+    TooManyOpenFiles,
+
+    /// No block device has the name.
+    NoDevice,
+
+    /// The blocks of the device are not FAT32 sectors. Holds the block size.
+    UnsupportedBlockSize(usize),
+
+    /// The FAT32 file system could not be mounted or read.
+    Fat32(Fat32Error<BlockError>),
+
+    /// The initial ramdisk is not a valid CPIO archive.
+    Cpio(CpioError),
+
+    /// The tmpfs could not be written.
+    Tmpfs(TmpfsError),
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotMounted => write!(f, "no file system is mounted there"),
+            Self::AlreadyMounted => write!(f, "a file system is mounted there already"),
+            Self::TooManyMounts => write!(f, "all {} mounts are in use", MAX_MOUNTS),
+            Self::NotFound => write!(f, "not found"),
+            Self::NotADirectory => write!(f, "not a directory"),
+            Self::IsADirectory => write!(f, "is a directory"),
+            Self::AlreadyExists => write!(f, "already exists"),
+            Self::NotEmpty => write!(f, "the directory is not empty"),
+            Self::ReadOnly => write!(f, "read-only file system"),
+            Self::InvalidPath => write!(f, "invalid path"),
+            Self::InvalidOffset => write!(f, "invalid offset"),
+            Self::TooManyOpenFiles => {
+                write!(f, "all {} open files are in use", open_file::MAX_OPEN_FILES)
+            }
+            Self::NoDevice => write!(f, "no such block device"),
+            Self::UnsupportedBlockSize(size) => write!(f, "unsupported block size {}", size),
+            Self::Fat32(error) => write!(f, "{}", error),
+            Self::Cpio(error) => write!(f, "{}", error),
+            Self::Tmpfs(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// The type of a node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Directory,
+}
+
+/// A file or directory as its file system identifies it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Inode {
+    /// A value the file system finds the node again by.
+    pub id: u64,
+
+    pub kind: NodeKind,
+
+    /// The size of a file in bytes. Always 0 for directories.
+    pub size: usize,
+}
+
+/// A file system that can be mounted.
+///
+/// The methods that change the file system fail with `FsError::ReadOnly`
+/// unless the file system implements them.
+pub trait FileSystem: Sync {
+    /// Returns the type of the file system, such as "fat32".
+    fn type_name(&self) -> &'static str;
+
+    /// Looks up a node by path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path relative to the root of the file system, with
+    ///   components separated by '/'. Empty components are ignored, so ""
+    ///   is the root directory.
+    fn lookup(&self, path: &str) -> Result<Inode, FsError>;
+
+    /// Reads part of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file, from `lookup` or `for_each_entry`.
+    /// * `offset` - The offset in the file to read from.
+    /// * `buffer` - Receives the bytes.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read, which is less than the length of `buffer`
+    /// only at the end of the file.
+    fn read(&self, file: &Inode, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError>;
+
+    /// Calls a function for every entry of a directory, other than "." and
+    /// "..".
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory, from `lookup` or `for_each_entry`.
+    /// * `callback` - Called with the name and the node of each entry.
+    fn for_each_entry(
+        &self,
+        directory: &Inode,
+        callback: &mut dyn FnMut(&dyn fmt::Display, &Inode),
+    ) -> Result<(), FsError>;
+
+    /// Writes part of a file, growing it if the write goes past its end.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file.
+    /// * `offset` - The offset in the file to write at.
+    /// * `data` - The bytes to write.
+    fn write(&self, _file: &Inode, _offset: usize, _data: &[u8]) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Changes the size of a file, discarding bytes past the new end or
+    /// adding zeros.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file.
+    /// * `size` - The new size in bytes.
+    fn truncate(&self, _file: &Inode, _size: usize) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Creates an empty file or directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory to create the node in.
+    /// * `name` - The name of the node.
+    /// * `kind` - The type of the node.
+    fn create(&self, _directory: &Inode, _name: &str, _kind: NodeKind) -> Result<Inode, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Removes a file or an empty directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory that holds the node.
+    /// * `name` - The name of the node.
+    fn remove(&self, _directory: &Inode, _name: &str) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+}
+
+/// A file system mounted at a path.
+#[derive(Copy, Clone)]
+struct Mount {
+    /// The absolute path, without a trailing '/' except for "/".
+    path: &'static str,
+
+    file_system: &'static dyn FileSystem,
+}
+
+/// The mounted file systems.
+static MOUNTS: SpinLock<[Option<Mount>; MAX_MOUNTS]> = SpinLock::new([None; MAX_MOUNTS]);
+
+/// An open file or directory.
+#[derive(Copy, Clone)]
+pub struct Node {
+    file_system: &'static dyn FileSystem,
+    inode: Inode,
+}
+
+impl Node {
+    /// Returns true if the node is a directory.
+    pub fn is_directory(&self) -> bool {
+        self.inode.kind == NodeKind::Directory
+    }
+
+    /// Returns the size of a file in bytes as of when it was opened or last
+    /// changed through this node, or 0 for a directory.
+    pub fn size(&self) -> usize {
+        self.inode.size
+    }
+
+    /// Reads part of a file, as `FileSystem::read` does.
+    pub fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        if self.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+
+        self.file_system.read(&self.inode, offset, buffer)
+    }
+
+    /// Writes part of a file, as `FileSystem::write` does.
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), FsError> {
+        if self.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+
+        self.file_system.write(&self.inode, offset, data)?;
+        self.inode.size = self.inode.size.max(offset + data.len());
+
+        Ok(())
+    }
+
+    /// Changes the size of a file, as `FileSystem::truncate` does.
+    pub fn truncate(&mut self, size: usize) -> Result<(), FsError> {
+        if self.is_directory() {
+            return Err(FsError::IsADirectory);
+        }
+
+        self.file_system.truncate(&self.inode, size)?;
+        self.inode.size = size;
+
+        Ok(())
+    }
+
+    /// Calls a function for every entry of a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Called with the name of each entry, its type, and its
+    ///   size. It must not use the file system.
+    pub fn for_each_entry(
+        &self,
+        mut callback: impl FnMut(&dyn fmt::Display, NodeKind, usize),
+    ) -> Result<(), FsError> {
+        if !self.is_directory() {
+            return Err(FsError::NotADirectory);
+        }
+
+        self.file_system
+            .for_each_entry(&self.inode, &mut |name, inode| {
+                callback(name, inode.kind, inode.size)
+            })
+    }
+}
+
+/// Mounts a file system at a path.
+///
+/// # Arguments
+///
+/// * `path` - The absolute path, such as "/" or "/mnt".
+/// * `file_system` - The file system.
+pub fn mount(path: &'static str, file_system: &'static dyn FileSystem) -> Result<(), FsError> {
+    let path = match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    };
+
+    let mut mounts = MOUNTS.lock();
+
+    if mounts.iter().flatten().any(|mount| mount.path == path) {
+        return Err(FsError::AlreadyMounted);
+    }
+
+    let slot = mounts
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(FsError::TooManyMounts)?;
+
+    *slot = Some(Mount { path, file_system });
+
+    Ok(())
+}
+
+/// Returns true if a file system is mounted at a path.
+pub fn is_mounted(path: &str) -> bool {
+    MOUNTS
+        .lock()
+        .iter()
+        .flatten()
+        .any(|mount| mount.path == path)
+}
+
+/// Calls a function for every mounted file system.
+///
+/// # Arguments
+///
+/// * `callback` - Called with the path and the type name of each mount.
+pub fn for_each_mount(mut callback: impl FnMut(&'static str, &'static str)) {
+    let mounts = *MOUNTS.lock();
+
+    for mount in mounts.iter().flatten() {
+        callback(mount.path, mount.file_system.type_name());
+    }
+}
+
+/// Opens a file or directory.
+///
+/// # Arguments
+///
+/// * `path` - The absolute path. Empty components are ignored.
+pub fn open(path: &str) -> Result<Node, FsError> {
+    let (mount, relative_path) = find_mount(path).ok_or(FsError::NotMounted)?;
+
+    Ok(Node {
+        file_system: mount.file_system,
+        inode: mount.file_system.lookup(relative_path)?,
+    })
+}
+
+/// Creates an empty file or directory.
+///
+/// # Arguments
+///
+/// * `path` - The absolute path of the node. Its parent directory must exist.
+/// * `kind` - The type of the node.
+pub fn create(path: &str, kind: NodeKind) -> Result<Node, FsError> {
+    let (directory, name) = open_parent(path)?;
+
+    Ok(Node {
+        file_system: directory.file_system,
+        inode: directory.file_system.create(&directory.inode, name, kind)?,
+    })
+}
+
+/// Removes a file or an empty directory.
+///
+/// # Arguments
+///
+/// * `path` - The absolute path of the node.
+pub fn remove(path: &str) -> Result<(), FsError> {
+    let (directory, name) = open_parent(path)?;
+
+    directory.file_system.remove(&directory.inode, name)
+}
+
+/// Opens the directory that holds a path.
+///
+/// # Returns
+///
+/// The directory and the last component of the path.
+fn open_parent(path: &str) -> Result<(Node, &str), FsError> {
+    let (parent, name) = path
+        .trim_end_matches('/')
+        .rsplit_once('/')
+        .ok_or(FsError::InvalidPath)?;
+
+    if name.is_empty() || is_mounted(path.trim_end_matches('/')) {
+        return Err(FsError::InvalidPath);
+    }
+
+    let directory = open(if parent.is_empty() { "/" } else { parent })?;
+
+    if !directory.is_directory() {
+        return Err(FsError::NotADirectory);
+    }
+
+    Ok((directory, name))
+}
+
+/// Finds the mount a path is on.
+///
+/// # Returns
+///
+/// The mount whose path is the longest prefix of the path at a component
+/// boundary, and the rest of the path.
+fn find_mount(path: &str) -> Option<(Mount, &str)> {
+    let mounts = *MOUNTS.lock();
+
+    mounts
+        .iter()
+        .flatten()
+        .filter_map(|mount| {
+            let relative_path = if mount.path == "/" {
+                path.strip_prefix('/')?
+            } else {
+                let rest = path.strip_prefix(mount.path)?;
+
+                if !rest.is_empty() && !rest.starts_with('/') {
+                    return None;
+                }
+
+                rest
+            };
+
+            Some((*mount, relative_path))
+        })
+        .max_by_key(|(mount, _)| mount.path.len())
+}
@@ -0,0 +1,158 @@
+//! Open files.
+//!
+//! Opening a node for a process takes a slot of `OPEN_FILES`, which holds the
+//! node and the offset reads and writes continue from. File descriptors refer
+//! to the slot through a `Ref`, so the descriptors `dup` and `fork` copy share
+//! the offset, and the slot is freed once the last of them is closed.
+
+use super::{FsError, Node};
+use kernel_lib::sync::{Ref, RefCount, RefCounted, SpinLock};
+
+/// The most files that can be open at once, across every process.
+pub const MAX_OPEN_FILES: usize = 32;
+
+/// Where `OpenFile::seek` measures the new offset from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// The start of the file.
+    Start(usize),
+
+    /// The current offset.
+    Current(isize),
+
+    /// The end of the file.
+    End(isize),
+}
+
+/// The node of an open file and the offset in it.
+#[derive(Copy, Clone)]
+struct OpenFileState {
+    node: Node,
+    offset: usize,
+}
+
+/// A slot of the open file table.
+pub struct OpenFile {
+    ref_count: RefCount,
+
+    /// The node and offset, or `None` while the slot is free.
+    state: SpinLock<Option<OpenFileState>>,
+}
+
+impl RefCounted for OpenFile {
+    fn ref_count(&self) -> &RefCount {
+        &self.ref_count
+    }
+
+    fn release(&'static self) {
+        *self.state.lock() = None;
+    }
+}
+
+/// Every open file.
+static OPEN_FILES: [OpenFile; MAX_OPEN_FILES] = [const {
+    OpenFile {
+        ref_count: RefCount::new(),
+        state: SpinLock::new(None),
+    }
+}; MAX_OPEN_FILES];
+
+impl OpenFile {
+    /// Opens a node at offset zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The file or directory, from `fs::open` or `fs::create`.
+    ///
+    /// # Returns
+    ///
+    /// The first reference to the open file.
+    pub fn open(node: Node) -> Result<Ref<Self>, FsError> {
+        let file = OPEN_FILES
+            .iter()
+            .find_map(Ref::claim)
+            .ok_or(FsError::TooManyOpenFiles)?;
+
+        *file.state.lock() = Some(OpenFileState { node, offset: 0 });
+
+        Ok(file)
+    }
+
+    /// Reads from the offset and moves it past the bytes read.
+    ///
+    /// The lock is not held while the file system reads, which may block, so
+    /// reads through descriptors that share the file at the same time may
+    /// read from the same offset.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Receives the bytes.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read, which is zero at the end of the file.
+    pub fn read(&self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let state = self.state();
+        let length = state.node.read(state.offset, buffer)?;
+
+        self.update(|current| current.offset = state.offset + length);
+
+        Ok(length)
+    }
+
+    /// Writes at the offset and moves it past the bytes written, growing the
+    /// file if the write goes past its end.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes to write.
+    pub fn write(&self, data: &[u8]) -> Result<(), FsError> {
+        let mut state = self.state();
+        state.node.write(state.offset, data)?;
+
+        self.update(|current| {
+            current.node = state.node;
+            current.offset = state.offset + data.len();
+        });
+
+        Ok(())
+    }
+
+    /// Moves the offset. The offset may be past the end of the file, where
+    /// reads return nothing and writes fill the gap with zeros.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The new offset and what it is measured from.
+    ///
+    /// # Returns
+    ///
+    /// The new offset from the start of the file.
+    pub fn seek(&self, position: SeekFrom) -> Result<usize, FsError> {
+        let mut guard = self.state.lock();
+        let state = guard.as_mut().expect("A claimed open file has a node.");
+
+        let offset = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => state.offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => state.node.size().checked_add_signed(delta),
+        }
+        .ok_or(FsError::InvalidOffset)?;
+
+        state.offset = offset;
+
+        Ok(offset)
+    }
+
+    /// Returns a copy of the node and offset.
+    fn state(&self) -> OpenFileState {
+        self.state.lock().expect("A claimed open file has a node.")
+    }
+
+    /// Changes the node or offset under the lock.
+    fn update(&self, f: impl FnOnce(&mut OpenFileState)) {
+        if let Some(state) = self.state.lock().as_mut() {
+            f(state);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! A writable file system in memory, mounted at "/tmp" during boot.
+//!
+//! Its contents live in .bss and are lost at shutdown. It gives tests and
+//! early user space somewhere to create files before the kernel can write to
+//! a disk.
+
+use super::{FileSystem, FsError, Inode, NodeKind};
+use core::fmt;
+use kernel_lib::{
+    sync::SpinLock,
+    tmpfs::{self, NodeId, Tmpfs, TmpfsError},
+};
+
+/// The most files and directories, including the root directory.
+const NODE_COUNT: usize = 64;
+
+/// The number of blocks of file contents, 64KiB in all.
+const BLOCK_COUNT: usize = 64;
+
+impl From<TmpfsError> for FsError {
+    fn from(error: TmpfsError) -> Self {
+        match error {
+            TmpfsError::NotFound => Self::NotFound,
+            TmpfsError::NotADirectory => Self::NotADirectory,
+            TmpfsError::IsADirectory => Self::IsADirectory,
+            TmpfsError::AlreadyExists => Self::AlreadyExists,
+            TmpfsError::NotEmpty => Self::NotEmpty,
+            error => Self::Tmpfs(error),
+        }
+    }
+}
+
+/// The tmpfs behind a lock.
+pub struct TmpfsFileSystem(SpinLock<Tmpfs<NODE_COUNT, BLOCK_COUNT>>);
+
+impl TmpfsFileSystem {
+    /// Returns the inode of a node.
+    fn inode(tmpfs: &Tmpfs<NODE_COUNT, BLOCK_COUNT>, node: NodeId) -> Result<Inode, FsError> {
+        let kind = match tmpfs.kind(node)? {
+            tmpfs::NodeKind::File => NodeKind::File,
+            tmpfs::NodeKind::Directory => NodeKind::Directory,
+        };
+
+        Ok(Inode {
+            id: node.0,
+            kind,
+            size: tmpfs.size(node)?,
+        })
+    }
+}
+
+impl FileSystem for TmpfsFileSystem {
+    fn type_name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn lookup(&self, path: &str) -> Result<Inode, FsError> {
+        let tmpfs = self.0.lock();
+
+        Self::inode(&tmpfs, tmpfs.lookup(path)?)
+    }
+
+    fn read(&self, file: &Inode, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        Ok(self.0.lock().read(NodeId(file.id), offset, buffer)?)
+    }
+
+    /// Calls the callback with the tmpfs locked.
+    fn for_each_entry(
+        &self,
+        directory: &Inode,
+        callback: &mut dyn FnMut(&dyn fmt::Display, &Inode),
+    ) -> Result<(), FsError> {
+        let tmpfs = self.0.lock();
+
+        tmpfs.for_each_child(NodeId(directory.id), |name, node| {
+            if let Ok(inode) = Self::inode(&tmpfs, node) {
+                callback(&name, &inode);
+            }
+        })?;
+
+        Ok(())
+    }
+
+    fn write(&self, file: &Inode, offset: usize, data: &[u8]) -> Result<(), FsError> {
+        Ok(self.0.lock().write(NodeId(file.id), offset, data)?)
+    }
+
+    fn truncate(&self, file: &Inode, size: usize) -> Result<(), FsError> {
+        Ok(self.0.lock().truncate(NodeId(file.id), size)?)
+    }
+
+    fn create(&self, directory: &Inode, name: &str, kind: NodeKind) -> Result<Inode, FsError> {
+        let kind = match kind {
+            NodeKind::File => tmpfs::NodeKind::File,
+            NodeKind::Directory => tmpfs::NodeKind::Directory,
+        };
+
+        let mut tmpfs = self.0.lock();
+        let node = tmpfs.create(NodeId(directory.id), name, kind)?;
+
+        Self::inode(&tmpfs, node)
+    }
+
+    fn remove(&self, directory: &Inode, name: &str) -> Result<(), FsError> {
+        Ok(self.0.lock().remove(NodeId(directory.id), name)?)
+    }
+}
+
+/// The tmpfs.
+static FILE_SYSTEM: TmpfsFileSystem = TmpfsFileSystem(SpinLock::new(Tmpfs::new()));
+
+/// Mounts the tmpfs.
+///
+/// # Arguments
+///
+/// * `path` - The path to mount the file system at.
+pub fn mount(path: &'static str) -> Result<(), FsError> {
+    super::mount(path, &FILE_SYSTEM)
+}
@@ -0,0 +1,217 @@
+//! Futexes, the kernel half of user space locks.
+//!
+//! User code keeps the state of a lock in a 32-bit word of its own memory and
+//! only enters the kernel when it has to sleep or wake a sleeper. `wait`
+//! blocks the calling thread on a word while it still holds an expected
+//! value, and `wake` wakes threads blocked on the same word.
+//!
+//! Words are identified by a `FutexKey` made of the frame that backs the word
+//! and the offset in it. A word in private memory also carries the address
+//! space, so copy-on-write pages that `fork` shares never mix the waiters of
+//! two processes, while a word in a shared memory object is the same futex in
+//! every address space that maps it.
+//!
+//! Waiters take slots of a fixed table, each with a wait queue of its own, so
+//! a wake only disturbs the threads it wakes.
+
+use crate::task::WaitQueue;
+use common_lib::memory::PhysicalPageNumber;
+use core::fmt;
+use kernel_lib::sync::SpinLock;
+
+/// The most threads that can wait on futexes at once.
+pub const MAX_FUTEX_WAITERS: usize = 16;
+
+/// The reasons a thread could not wait on a futex.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FutexError {
+    /// The word no longer holds the expected value.
+    ValueChanged,
+
+    /// All `MAX_FUTEX_WAITERS` waiter slots are in use.
+    TooManyWaiters,
+}
+
+impl fmt::Display for FutexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ValueChanged => write!(f, "the futex word changed"),
+            Self::TooManyWaiters => {
+                write!(f, "all {} futex waiter slots are in use", MAX_FUTEX_WAITERS)
+            }
+        }
+    }
+}
+
+/// Identifies a futex word.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FutexKey {
+    /// The root page table of the address space of a private word, or `None`
+    /// for a word in shared memory.
+    address_space: Option<PhysicalPageNumber>,
+
+    /// The frame that backs the word.
+    ppn: PhysicalPageNumber,
+
+    /// The offset of the word in the frame.
+    offset: usize,
+}
+
+impl FutexKey {
+    /// Creates the key of a word in memory private to one address space.
+    ///
+    /// # Arguments
+    ///
+    /// * `address_space` - The root page table of the address space.
+    /// * `ppn` - The frame that backs the word, which must not be shared
+    ///   copy-on-write.
+    /// * `offset` - The offset of the word in the frame.
+    pub fn private(
+        address_space: PhysicalPageNumber,
+        ppn: PhysicalPageNumber,
+        offset: usize,
+    ) -> Self {
+        Self {
+            address_space: Some(address_space),
+            ppn,
+            offset,
+        }
+    }
+
+    /// Creates the key of a word in a shared memory object.
+    ///
+    /// # Arguments
+    ///
+    /// * `ppn` - The frame that backs the word.
+    /// * `offset` - The offset of the word in the frame.
+    pub fn shared(ppn: PhysicalPageNumber, offset: usize) -> Self {
+        Self {
+            address_space: None,
+            ppn,
+            offset,
+        }
+    }
+}
+
+/// A thread waiting on a futex.
+#[derive(Copy, Clone)]
+struct FutexWaiter {
+    key: FutexKey,
+
+    /// Set by `wake` once the waiter is chosen to wake.
+    is_woken: bool,
+}
+
+/// The waiting threads, one slot each.
+static WAITERS: SpinLock<[Option<FutexWaiter>; MAX_FUTEX_WAITERS]> =
+    SpinLock::new([None; MAX_FUTEX_WAITERS]);
+
+/// The wait queue of every waiter slot, indexed like `WAITERS`.
+static WAIT_QUEUES: [WaitQueue; MAX_FUTEX_WAITERS] =
+    [const { WaitQueue::new() }; MAX_FUTEX_WAITERS];
+
+/// Blocks the calling kernel thread on a futex until `wake` chooses it.
+///
+/// The word is checked under the same lock `wake` takes, so a wake made after
+/// the word changes is never missed.
+///
+/// # Arguments
+///
+/// * `key` - The futex.
+/// * `is_expected` - Returns true if the word still holds the value the
+///   caller expects. It must not block.
+///
+/// # Returns
+///
+/// `FutexError::ValueChanged` without blocking if `is_expected` returns
+/// false.
+pub fn wait(key: FutexKey, is_expected: impl FnOnce() -> bool) -> Result<(), FutexError> {
+    let slot = {
+        let mut waiters = WAITERS.lock();
+
+        if !is_expected() {
+            return Err(FutexError::ValueChanged);
+        }
+
+        let slot = waiters
+            .iter()
+            .position(Option::is_none)
+            .ok_or(FutexError::TooManyWaiters)?;
+
+        waiters[slot] = Some(FutexWaiter {
+            key,
+            is_woken: false,
+        });
+
+        slot
+    };
+
+    WAIT_QUEUES[slot].wait_until(|| WAITERS.lock()[slot].is_some_and(|waiter| waiter.is_woken));
+
+    WAITERS.lock()[slot] = None;
+
+    Ok(())
+}
+
+/// Wakes threads blocked on a futex, in the order of their slots.
+///
+/// # Arguments
+///
+/// * `key` - The futex.
+/// * `count` - The most threads to wake.
+///
+/// # Returns
+///
+/// The number of threads woken.
+pub fn wake(key: FutexKey, count: usize) -> usize {
+    let mut is_chosen = [false; MAX_FUTEX_WAITERS];
+    let mut woken_count = 0;
+
+    {
+        let mut waiters = WAITERS.lock();
+
+        for (slot, waiter) in waiters.iter_mut().enumerate() {
+            if woken_count == count {
+                break;
+            }
+
+            if let Some(waiter) = waiter.as_mut()
+                && waiter.key == key
+                && !waiter.is_woken
+            {
+                waiter.is_woken = true;
+                is_chosen[slot] = true;
+                woken_count += 1;
+            }
+        }
+    }
+
+    for slot in (0..MAX_FUTEX_WAITERS).filter(|&slot| is_chosen[slot]) {
+        WAIT_QUEUES[slot].wake_all();
+    }
+
+    woken_count
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    kernel_test!(
+        fn test_futex_wait_returns_if_value_changed() {
+            let key = FutexKey::shared(PhysicalPageNumber::from_raw_physical_page_number(0), 0);
+
+            assert_eq!(wait(key, || false), Err(FutexError::ValueChanged));
+        }
+    );
+
+    kernel_test!(
+        fn test_futex_wake_without_waiters_wakes_nothing() {
+            let ppn = PhysicalPageNumber::from_raw_physical_page_number(0);
+
+            assert_eq!(wake(FutexKey::shared(ppn, 0), usize::MAX), 0);
+            assert_eq!(wake(FutexKey::private(ppn, ppn, 4), 1), 0);
+        }
+    );
+}
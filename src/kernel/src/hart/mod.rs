@@ -0,0 +1,236 @@
+//! Secondary hart bring-up and the global hart table.
+//!
+//! The boot hart enters the kernel through `kernel_main`. Every other hart is
+//! held by the SBI implementation until the boot hart starts it with the HSM
+//! extension. A started hart begins executing `_kernel_secondary_entrypoint`
+//! at its physical address with paging disabled, enables the same satp as the
+//! boot hart, switches to its own stack, and calls `kernel_secondary_main`.
+
+use crate::{
+    debug_println,
+    memory::{read_satp, virtual_to_physical},
+    percpu,
+    stack_guard::{GuardedStack, StackUsage},
+    time::Instant,
+};
+use common_lib::dtb::IsaFeatures;
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering, fence},
+    time::Duration,
+};
+use kernel_lib::sync::Once;
+use sbi_lib::hsm::{HartState, hart_get_status, hart_start};
+
+/// The maximum number of harts the kernel supports. Harts with an ID at or
+/// above this value are never started.
+pub const MAX_HART_COUNT: usize = 8;
+
+/// The size in bytes of the stack given to each secondary hart.
+const HART_STACK_SIZE: usize = 16 * 1024;
+
+/// How long the boot hart waits for started harts to register themselves
+/// before giving up on them.
+const HART_STARTUP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The information a secondary hart needs before it can run kernel code.
+///
+/// The layout of this structure is shared with `_kernel_secondary_entrypoint`,
+/// which reads it by physical address before paging is enabled.
+#[repr(C)]
+struct HartStartupInformation {
+    /// The satp value to install, shared with the boot hart.
+    satp: usize,
+
+    /// The virtual address of the top of the hart's stack.
+    stack_top: usize,
+
+    /// The virtual address to continue at once paging is enabled.
+    virtual_entry_address: usize,
+}
+
+/// The stack of each secondary hart, indexed by hart ID.
+static HART_STACKS: [GuardedStack<HART_STACK_SIZE>; MAX_HART_COUNT] =
+    [const { GuardedStack::new() }; MAX_HART_COUNT];
+
+/// The startup information of each started hart. Written once, before the
+/// hart is started.
+static HART_STARTUP_INFORMATION: [Once<HartStartupInformation>; MAX_HART_COUNT] =
+    [const { Once::new() }; MAX_HART_COUNT];
+
+/// The global hart table. A hart's entry is set once it is running kernel
+/// code.
+static ONLINE_HARTS: [AtomicBool; MAX_HART_COUNT] =
+    [const { AtomicBool::new(false) }; MAX_HART_COUNT];
+
+/// The bits of the `IsaFeatures` shared by every enabled hart.
+static ISA_FEATURES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the ID of the calling hart from its per-hart block.
+pub fn current_hart_id() -> usize {
+    percpu::current().hart_id
+}
+
+/// Marks a hart as online in the global hart table.
+///
+/// # Arguments
+///
+/// * `hart_id` - The ID of the calling hart.
+pub fn register_hart(hart_id: usize) {
+    if hart_id >= MAX_HART_COUNT {
+        debug_println!(
+            "Hart {} exceeds the maximum hart count of {}.",
+            hart_id,
+            MAX_HART_COUNT
+        );
+
+        return;
+    }
+
+    ONLINE_HARTS[hart_id].store(true, Ordering::Release);
+}
+
+/// Sets the ISA extensions that every enabled hart supports. Must be called
+/// before any code checks `isa_features`.
+///
+/// # Arguments
+///
+/// * `features` - The extensions common to all harts, usually the
+///   intersection of the features of every enabled cpu node in the device
+///   tree.
+pub fn set_isa_features(features: IsaFeatures) {
+    ISA_FEATURES.store(features.bits(), Ordering::Relaxed);
+}
+
+/// Returns the ISA extensions that every enabled hart supports.
+///
+/// Threads can run on any hart, so optional extensions are only used when all
+/// harts have them. The set is empty until `set_isa_features` is called.
+pub fn isa_features() -> IsaFeatures {
+    IsaFeatures::from_bits(ISA_FEATURES.load(Ordering::Relaxed))
+}
+
+/// Returns true if the hart has registered itself in the global hart table.
+pub fn is_hart_online(hart_id: usize) -> bool {
+    hart_id < MAX_HART_COUNT && ONLINE_HARTS[hart_id].load(Ordering::Acquire)
+}
+
+/// Returns the number of harts registered in the global hart table.
+pub fn online_hart_count() -> usize {
+    (0..MAX_HART_COUNT)
+        .filter(|&hart_id| is_hart_online(hart_id))
+        .count()
+}
+
+/// Returns how much of the stack a secondary hart was started on has been
+/// used.
+///
+/// # Arguments
+///
+/// * `hart_id` - The ID of the hart.
+///
+/// # Returns
+///
+/// The usage, or `None` if the hart is the boot hart or is not online.
+pub fn stack_usage(hart_id: usize) -> Option<StackUsage> {
+    // Only secondary harts have startup information, and their stacks are
+    // painted before it is written.
+    (is_hart_online(hart_id) && HART_STARTUP_INFORMATION[hart_id].is_completed())
+        .then(|| HART_STACKS[hart_id].usage())
+}
+
+/// Starts every stopped hart other than the boot hart and waits for each of
+/// them to register in the global hart table.
+///
+/// Only harts whose HSM state is stopped are started, so IDs of harts that are
+/// already running or that the SBI implementation does not know are skipped.
+///
+/// # Arguments
+///
+/// * `boot_hart_id` - The ID of the hart calling this function.
+/// * `hart_ids` - The IDs of every enabled hart in the system, usually taken
+///   from the cpu nodes of the device tree.
+pub fn start_secondary_harts(boot_hart_id: usize, hart_ids: impl IntoIterator<Item = usize>) {
+    unsafe extern "C" {
+        fn _kernel_secondary_entrypoint();
+        fn _kernel_secondary_virtual_entry();
+    }
+
+    register_hart(boot_hart_id);
+
+    // The entry point runs with paging disabled, so the SBI implementation
+    // needs its physical address.
+    let secondary_entrypoint_virtual_address = _kernel_secondary_entrypoint as *const () as usize;
+    let secondary_entrypoint_physical_address =
+        virtual_to_physical(secondary_entrypoint_virtual_address)
+            .expect("Secondary hart entry point is not mapped.");
+
+    let satp = read_satp();
+    let mut started_hart_count = 0;
+
+    for hart_id in hart_ids {
+        if hart_id == boot_hart_id {
+            continue;
+        }
+
+        if hart_id >= MAX_HART_COUNT {
+            debug_println!(
+                "Not starting hart {}, which exceeds the maximum hart count of {}.",
+                hart_id,
+                MAX_HART_COUNT
+            );
+
+            continue;
+        }
+
+        if hart_get_status(hart_id) != Ok(HartState::Stopped) {
+            continue;
+        }
+
+        // The hart finds its stack limit by looking for the guard page.
+        HART_STACKS[hart_id].protect();
+        HART_STACKS[hart_id].paint();
+
+        // Fill in the startup information for the hart before starting it.
+        let startup_information =
+            HART_STARTUP_INFORMATION[hart_id].call_once(|| HartStartupInformation {
+                satp,
+                stack_top: HART_STACKS[hart_id].top(),
+                virtual_entry_address: _kernel_secondary_virtual_entry as *const () as usize,
+            });
+
+        let startup_information_physical_address =
+            virtual_to_physical(startup_information as *const HartStartupInformation as usize)
+                .expect("Hart startup information is not mapped.");
+
+        // Make sure the startup information is visible in memory before the
+        // hart reads it with paging disabled.
+        fence(Ordering::SeqCst);
+
+        let start_result = hart_start(
+            hart_id,
+            secondary_entrypoint_physical_address,
+            startup_information_physical_address,
+        );
+
+        if let Err(error) = start_result {
+            debug_println!("Failed to start hart {}: {}.", hart_id, error);
+            continue;
+        }
+
+        started_hart_count += 1;
+    }
+
+    // Wait for the started harts to register themselves.
+    let expected_online_count = started_hart_count + 1;
+    let deadline = Instant::now() + HART_STARTUP_TIMEOUT;
+
+    while online_hart_count() < expected_online_count && Instant::now() < deadline {
+        core::hint::spin_loop();
+    }
+
+    debug_println!(
+        "{} of {} started harts are online.",
+        online_hart_count(),
+        expected_online_count
+    );
+}
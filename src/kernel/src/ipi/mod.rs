@@ -0,0 +1,145 @@
+//! Inter-hart messages delivered with supervisor software interrupts.
+//!
+//! Every hart owns a lock-free multi-producer single-consumer channel. A
+//! sender sends a message on the target hart's channel and raises a software
+//! interrupt on it through the SBI IPI extension. The target hart drains its
+//! channel in the software interrupt handler, the channel's only receiver.
+
+use crate::{
+    debug_println,
+    hart::{MAX_HART_COUNT, current_hart_id},
+};
+use core::fmt;
+use kernel_lib::sync::{MpscChannel, interrupts::SSTATUS_SIE};
+use sbi_lib::{SbiError, ipi::send_ipi_to_hart};
+
+/// The number of messages that can be waiting for a single hart.
+const IPI_QUEUE_CAPACITY: usize = 32;
+
+/// The supervisor software interrupt enable bit in the sie CSR.
+const SIE_SSIE: usize = 1 << 1;
+
+/// The supervisor software interrupt pending bit in the sip CSR.
+const SIP_SSIP: usize = 1 << 1;
+
+/// A request sent from one hart to another.
+#[derive(Debug, Copy, Clone)]
+pub enum IpiMessage {
+    /// Call `function` with `argument` on the target hart from the software
+    /// interrupt handler. The function must not block.
+    FunctionCall {
+        function: fn(argument: usize),
+        argument: usize,
+    },
+
+    /// Ask the target hart to pick a new thread to run.
+    Reschedule,
+}
+
+/// The reasons a message could not be delivered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IpiError {
+    /// The target hart ID is at or above `MAX_HART_COUNT`.
+    InvalidHart,
+
+    /// The target hart's queue has no free slots.
+    QueueFull,
+
+    /// The message was queued but the SBI implementation failed to interrupt
+    /// the target hart.
+    Sbi(SbiError),
+}
+
+impl fmt::Display for IpiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHart => write!(f, "invalid hart"),
+            Self::QueueFull => write!(f, "message queue full"),
+            Self::Sbi(error) => write!(f, "SBI error: {}", error),
+        }
+    }
+}
+
+/// The pending messages for each hart, indexed by hart ID.
+static IPI_QUEUES: [MpscChannel<IpiMessage, IPI_QUEUE_CAPACITY>; MAX_HART_COUNT] =
+    [const { MpscChannel::new() }; MAX_HART_COUNT];
+
+/// Enables supervisor software interrupts on the calling hart.
+///
+/// The trap vector must already be installed on this hart. Messages queued
+/// before this call are handled as soon as it returns.
+pub fn initialize() {
+    unsafe {
+        core::arch::asm!(
+            "csrs sie, {}",
+            "csrs sstatus, {}",
+            in(reg) SIE_SSIE,
+            in(reg) SSTATUS_SIE,
+            options(nomem, nostack)
+        );
+    }
+}
+
+/// Queues a message for a hart and interrupts it.
+///
+/// # Arguments
+///
+/// * `hart_id` - The hart that should handle the message. This may be the
+///   calling hart.
+/// * `message` - The message to deliver.
+pub fn send(hart_id: usize, message: IpiMessage) -> Result<(), IpiError> {
+    let queue = IPI_QUEUES.get(hart_id).ok_or(IpiError::InvalidHart)?;
+
+    queue.send(message).map_err(|_| IpiError::QueueFull)?;
+
+    send_ipi_to_hart(hart_id).map_err(IpiError::Sbi)
+}
+
+/// Asks a hart to call a function from its software interrupt handler.
+///
+/// # Arguments
+///
+/// * `hart_id` - The hart that should call the function.
+/// * `function` - The function to call. It must not block.
+/// * `argument` - The value passed to the function.
+pub fn call_on_hart(hart_id: usize, function: fn(usize), argument: usize) -> Result<(), IpiError> {
+    send(hart_id, IpiMessage::FunctionCall { function, argument })
+}
+
+/// Asks a hart to reschedule.
+///
+/// # Arguments
+///
+/// * `hart_id` - The hart that should reschedule.
+#[allow(dead_code)]
+pub fn request_reschedule(hart_id: usize) -> Result<(), IpiError> {
+    send(hart_id, IpiMessage::Reschedule)
+}
+
+/// Handles a supervisor software interrupt. Called from the trap handler.
+///
+/// The pending bit is cleared before the queue is drained, so a message
+/// queued while the handler runs either gets drained now or raises a new
+/// interrupt.
+pub fn handle_software_interrupt() {
+    unsafe {
+        core::arch::asm!("csrc sip, {}", in(reg) SIP_SSIP, options(nomem, nostack));
+    }
+
+    let hart_id = current_hart_id();
+    let Some(queue) = IPI_QUEUES.get(hart_id) else {
+        return;
+    };
+
+    // Only the hart that owns a channel receives from it, and it does so
+    // with interrupts disabled.
+    while let Some(message) = unsafe { queue.receive() } {
+        match message {
+            IpiMessage::FunctionCall { function, argument } => function(argument),
+            IpiMessage::Reschedule => {
+                // There is no scheduler yet, so there is nothing to switch to.
+                debug_println!("Hart {} received a reschedule request.", hart_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Device interrupt handling.
+//!
+//! Drivers register a handler for their PLIC interrupt source with
+//! `register_handler` and route the source to the calling hart with `enable`,
+//! instead of programming the PLIC themselves. The supervisor external
+//! interrupt handler claims every pending interrupt, calls the handler
+//! registered for its source with the data the driver registered it with,
+//! counts how often and for how long the source was handled, and completes
+//! the interrupt.
+
+use crate::{
+    debug_println,
+    drivers::plic::{self, MAX_INTERRUPT_SOURCES, PlicError},
+    hart::current_hart_id,
+    time::Instant,
+    trace_event,
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+use kernel_lib::sync::SpinLock;
+
+/// The priority `enable` gives an interrupt source. Every source shares it,
+/// so the PLIC delivers simultaneous interrupts in source order.
+const DEFAULT_PRIORITY: u32 = 1;
+
+/// The priority that keeps a source from interrupting any hart.
+const MASKED_PRIORITY: u32 = 0;
+
+/// The function called when an interrupt source raises an interrupt.
+///
+/// The handler receives the interrupt source number and the data it was
+/// registered with. It runs in trap context with interrupts disabled and must
+/// not block. The interrupt is completed once it returns, so the handler must
+/// clear the condition in the device.
+pub type IrqHandler = fn(irq: u32, data: usize);
+
+/// The handler and statistics of an interrupt source.
+struct IrqSlot {
+    /// The registered `IrqHandler`, or null if there is none.
+    handler: AtomicPtr<()>,
+
+    /// The data passed to the handler.
+    data: AtomicUsize,
+
+    /// The number of interrupts the handler was called for.
+    count: AtomicU64,
+
+    /// The time spent in the handler, in nanoseconds.
+    total_nanoseconds: AtomicU64,
+
+    /// The longest single call of the handler, in nanoseconds.
+    longest_nanoseconds: AtomicU64,
+}
+
+impl IrqSlot {
+    const fn new() -> Self {
+        Self {
+            handler: AtomicPtr::new(core::ptr::null_mut()),
+            data: AtomicUsize::new(0),
+            count: AtomicU64::new(0),
+            total_nanoseconds: AtomicU64::new(0),
+            longest_nanoseconds: AtomicU64::new(0),
+        }
+    }
+
+    fn statistics(&self) -> IrqStatistics {
+        IrqStatistics {
+            count: self.count.load(Ordering::Relaxed),
+            total_time: Duration::from_nanos(self.total_nanoseconds.load(Ordering::Relaxed)),
+            longest_time: Duration::from_nanos(self.longest_nanoseconds.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// The slot of every interrupt source, indexed by source number.
+static SLOTS: [IrqSlot; MAX_INTERRUPT_SOURCES] = [const { IrqSlot::new() }; MAX_INTERRUPT_SOURCES];
+
+/// Serializes registering and unregistering handlers, so the handler and data
+/// of a slot are only written by one hart at a time. Dispatching reads the
+/// slots without it.
+static REGISTRATION_LOCK: SpinLock<()> = SpinLock::new(());
+
+/// The number of claimed interrupts that had no handler.
+static UNHANDLED_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// The reasons an interrupt operation failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IrqError {
+    /// The PLIC rejected the operation.
+    Plic(PlicError),
+
+    /// The interrupt source already has a handler.
+    AlreadyRegistered,
+
+    /// The interrupt source has no handler.
+    NotRegistered,
+}
+
+impl fmt::Display for IrqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plic(error) => write!(f, "{}", error),
+            Self::AlreadyRegistered => write!(f, "interrupt already has a handler"),
+            Self::NotRegistered => write!(f, "interrupt has no handler"),
+        }
+    }
+}
+
+impl From<PlicError> for IrqError {
+    fn from(error: PlicError) -> Self {
+        Self::Plic(error)
+    }
+}
+
+/// How often an interrupt source was handled and for how long.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IrqStatistics {
+    /// The number of interrupts the handler was called for.
+    pub count: u64,
+
+    /// The time spent in the handler.
+    pub total_time: Duration,
+
+    /// The longest single call of the handler.
+    pub longest_time: Duration,
+}
+
+/// Registers the function called when an interrupt source raises an
+/// interrupt. The source stays masked until it is enabled with `enable`.
+///
+/// # Arguments
+///
+/// * `irq` - The interrupt source.
+/// * `handler` - The function to call from the external interrupt handler.
+/// * `data` - The value passed to the handler, such as the address of the
+///   device the source belongs to.
+pub fn register_handler(irq: u32, handler: IrqHandler, data: usize) -> Result<(), IrqError> {
+    plic::validate_interrupt(irq)?;
+
+    let _guard = REGISTRATION_LOCK.lock();
+    let slot = &SLOTS[irq as usize];
+
+    if !slot.handler.load(Ordering::Relaxed).is_null() {
+        return Err(IrqError::AlreadyRegistered);
+    }
+
+    // The release store of the handler publishes the data to dispatch.
+    slot.data.store(data, Ordering::Relaxed);
+    slot.handler.store(handler as *mut (), Ordering::Release);
+
+    Ok(())
+}
+
+/// Masks an interrupt source and removes its handler.
+///
+/// The handler may still be running on another hart when this returns.
+///
+/// # Arguments
+///
+/// * `irq` - The interrupt source.
+#[allow(dead_code)]
+pub fn unregister_handler(irq: u32) -> Result<(), IrqError> {
+    disable(irq)?;
+
+    let _guard = REGISTRATION_LOCK.lock();
+    let slot = &SLOTS[irq as usize];
+
+    if slot
+        .handler
+        .swap(core::ptr::null_mut(), Ordering::AcqRel)
+        .is_null()
+    {
+        return Err(IrqError::NotRegistered);
+    }
+
+    Ok(())
+}
+
+/// Routes an interrupt source to the calling hart and unmasks it.
+///
+/// # Arguments
+///
+/// * `irq` - The interrupt source, which must have a handler.
+pub fn enable(irq: u32) -> Result<(), IrqError> {
+    plic::validate_interrupt(irq)?;
+
+    if SLOTS[irq as usize]
+        .handler
+        .load(Ordering::Acquire)
+        .is_null()
+    {
+        return Err(IrqError::NotRegistered);
+    }
+
+    plic::enable(irq, current_hart_id())?;
+    plic::set_priority(irq, DEFAULT_PRIORITY)?;
+
+    Ok(())
+}
+
+/// Masks an interrupt source on every hart. An interrupt that is already
+/// pending may still be delivered.
+///
+/// # Arguments
+///
+/// * `irq` - The interrupt source.
+pub fn disable(irq: u32) -> Result<(), IrqError> {
+    plic::set_priority(irq, MASKED_PRIORITY)?;
+
+    Ok(())
+}
+
+/// Calls a function for every interrupt source that has a handler or was
+/// handled before.
+///
+/// # Arguments
+///
+/// * `f` - The function, which receives the source, the address of its
+///   handler or `None`, and its statistics.
+pub fn for_each_irq(mut f: impl FnMut(u32, Option<usize>, IrqStatistics)) {
+    for (irq, slot) in SLOTS.iter().enumerate() {
+        let handler = slot.handler.load(Ordering::Acquire);
+        let statistics = slot.statistics();
+
+        if !handler.is_null() || statistics.count != 0 {
+            f(
+                irq as u32,
+                (!handler.is_null()).then_some(handler as usize),
+                statistics,
+            );
+        }
+    }
+}
+
+/// Returns the number of claimed interrupts that had no handler.
+pub fn unhandled_count() -> u64 {
+    UNHANDLED_COUNT.load(Ordering::Relaxed)
+}
+
+/// Handles a supervisor external interrupt. Called from the trap handler.
+///
+/// Every pending interrupt is claimed, passed to its handler, and completed.
+/// A source without a handler is disabled on the calling hart so it cannot
+/// interrupt it again.
+pub fn handle_external_interrupt() {
+    while let Some(irq) = plic::claim() {
+        dispatch(irq);
+
+        plic::complete(irq);
+    }
+}
+
+/// Calls the handler of a claimed interrupt and records the time it took.
+fn dispatch(irq: u32) {
+    let Some(slot) = SLOTS.get(irq as usize) else {
+        return;
+    };
+
+    let handler = slot.handler.load(Ordering::Acquire);
+
+    if handler.is_null() {
+        UNHANDLED_COUNT.fetch_add(1, Ordering::Relaxed);
+        debug_println!("No handler for external interrupt {}; disabling it.", irq);
+
+        let _ = plic::disable(irq, current_hart_id());
+        return;
+    }
+
+    // The pointer was created from an `IrqHandler` in `register_handler`.
+    let handler: IrqHandler = unsafe { core::mem::transmute(handler) };
+    let data = slot.data.load(Ordering::Relaxed);
+
+    let start = Instant::now();
+    handler(irq, data);
+    let nanoseconds = (Instant::now() - start).as_nanos() as u64;
+
+    trace_event!("irq", "interrupt {} handled in {} ns", irq, nanoseconds);
+
+    slot.count.fetch_add(1, Ordering::Relaxed);
+    slot.total_nanoseconds
+        .fetch_add(nanoseconds, Ordering::Relaxed);
+    slot.longest_nanoseconds
+        .fetch_max(nanoseconds, Ordering::Relaxed);
+}
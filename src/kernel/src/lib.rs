@@ -1,30 +1,490 @@
 #![no_std]
 
+mod backtrace;
+mod block;
+mod cmdline;
+mod console;
+mod devices;
+mod drivers;
+mod fs;
+mod futex;
+mod hart;
+mod ipi;
+mod irq;
+mod log;
+mod memory;
+mod monitor;
+mod net;
+mod percpu;
+mod pipe;
+mod pmu;
+mod process;
+mod random;
+mod rcu;
 mod sbi;
+mod softirq;
+mod stack_guard;
+mod symbols;
+mod syscall;
+mod task;
+#[cfg(feature = "kernel-tests")]
+mod testing;
+mod time;
+mod timer;
+mod tlb;
+mod trace;
+mod trap;
+mod user;
+mod workqueue;
 
-use core::{arch::global_asm, panic::PanicInfo};
+use boot_lib::memory::mmu;
+use common_lib::{
+    boot_info::{BootInfo, BootInfoError, BootMilestone},
+    capture_registers,
+    dtb::{self, Dtb, IsaFeatures},
+};
+use core::{
+    arch::global_asm,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use devices::ProbeStage;
+use drivers::{plic, uart, virtio};
+use stack_guard::GuardedStack;
 
 #[unsafe(no_mangle)]
-pub fn kernel_main(
-    hart_id: usize,
-    dtb_physical_address: usize,
-    root_page_table_physical_address: usize,
-) -> ! {
+#[cfg_attr(feature = "kernel-tests", allow(unreachable_code, unused_variables))]
+pub fn kernel_main(hart_id: usize, boot_info_physical_address: usize) -> ! {
+    let kernel_entered = time::Instant::now();
+
+    console::initialize();
+
     debug_println!("\nWelcome to the kernel! :)\n");
 
     debug_println!("Hart ID: {}", hart_id);
-    debug_println!("DTB physical address: {:#x}", dtb_physical_address);
+
+    // The paging mode places the direct physical memory mapping, so it is
+    // read from satp before the mapping is used.
+    memory::initialize_paging_mode();
+
+    // The boot stage hands over a BootInfo in a page of its own, which the
+    // direct physical memory mapping reaches.
+    let boot_info = match memory::physical_to_virtual(boot_info_physical_address)
+        .ok_or(BootInfoError::InvalidAddress(boot_info_physical_address))
+        .and_then(|address| unsafe { BootInfo::from_address(address) })
+    {
+        Ok(boot_info) => boot_info,
+        Err(error) => panic!(
+            "Invalid boot information at {:#x}: {}.",
+            boot_info_physical_address, error
+        ),
+    };
+
+    if boot_info.hart_id != hart_id {
+        panic!(
+            "The boot information is from hart {}, but the kernel started on hart {}.",
+            boot_info.hart_id, hart_id
+        );
+    }
+
+    // Backtraces from here on name the functions of the slid kernel.
+    symbols::set_kernel_slide(boot_info.kernel_slide);
+    time::boot_timeline::initialize(&boot_info.timeline, kernel_entered);
+
+    print_boot_info(boot_info);
+
+    percpu::initialize(hart_id);
+    task::initialize_hart(hart_id);
+
+    // Let spin locks catch a hart taking a lock it already holds.
+    kernel_lib::sync::set_hart_id_source(hart::current_hart_id);
+
+    // The boot stage hands over a copy of the DTB mapped at the layout's
+    // virtual address. Its physical address is only used for reporting.
+    let dtb_virtual_address = memory::layout().dtb_virtual_address;
+    let dtb = match unsafe { Dtb::from_address(dtb_virtual_address) } {
+        Ok(dtb) => dtb,
+        Err(error) => panic!(
+            "Invalid DTB at {:#x} (physical {:#x}): {}.",
+            dtb_virtual_address, boot_info.dtb_physical_address, error
+        ),
+    };
+
+    memory::initialize(&dtb, boot_info);
+    tlb::initialize();
+    BOOT_HART_STACK.protect();
+    BOOT_HART_STACK.paint();
+    stack_guard::initialize_hart(hart_id);
+    trap::initialize();
+    ipi::initialize();
+
+    // Optional extensions are only used when every hart that may run kernel
+    // code supports them. They are known before any device is probed, so
+    // device registers are mapped with the I/O memory type under Svpbmt.
+    let isa_features = dtb::cpus(&dtb)
+        .filter(|cpu| cpu.is_enabled())
+        .map(|cpu| cpu.isa_features())
+        .reduce(|common_features, features| common_features & features)
+        .unwrap_or_default();
+
+    hart::set_isa_features(isa_features);
+    mmu::set_svpbmt_enabled(isa_features.contains(IsaFeatures::SVPBMT));
+
+    let device_count = devices::scan(&dtb);
+
+    // Move the console off the SBI debug console as early as possible.
+    devices::probe(ProbeStage::Console);
+
+    if !console::has_stdout_console() {
+        debug_println!("Using the SBI debug console.");
+    }
+
+    debug_println!("Found {} devices with drivers.", device_count);
+
+    console::for_each_device(|device, is_active| {
+        debug_println!(
+            "Console device {}{}.",
+            device.name,
+            if is_active { "" } else { " (inactive)" }
+        );
+    });
+
+    print_chosen(&dtb);
+    print_cpus(&dtb);
+
+    time::initialize(&dtb, hart_id);
+
+    debug_println!("Common ISA extensions: {}", isa_features);
+
+    let enabled_hart_ids = dtb::cpus(&dtb)
+        .filter(|cpu| cpu.is_enabled())
+        .map(|cpu| cpu.hart_id as usize);
+
+    // Secondary harts take external interrupts as soon as they start, so the
+    // PLIC has to be set up before them.
+    devices::probe(ProbeStage::InterruptController);
+
+    if let Err(error) = plic::initialize_hart() {
+        debug_println!("External interrupts unavailable: {}.", error);
+    }
+
+    match uart::enable_receive_interrupt() {
+        Ok(irq) => debug_println!("UART input on interrupt {}.", irq),
+        Err(error) => debug_println!("Polling UART input: {}.", error),
+    }
+
+    devices::probe(ProbeStage::Device);
+
+    let has_initramfs = match fs::initramfs::mount_from_dtb(&dtb) {
+        Ok(Some(size)) => {
+            debug_println!("Mounted the {} byte initial ramdisk at /.", size);
+            true
+        }
+        Ok(None) => false,
+        Err(error) => {
+            debug_println!("Failed to mount the initial ramdisk: {}.", error);
+            false
+        }
+    };
+
+    if let Err(error) = fs::tmpfs::mount("/tmp") {
+        debug_println!("Failed to mount the tmpfs: {}.", error);
+    }
+
+    random::initialize(&dtb);
+    pmu::initialize();
+
+    debug_println!("Memory usage:\n{}", memory::stats());
+
+    hart::start_secondary_harts(hart_id, enabled_hart_ids);
+    greet_secondary_harts(hart_id);
+
+    timer::set_tick_callback(print_uptime);
+    timer::initialize();
+
+    workqueue::initialize();
+
+    // A test build runs the kernel tests instead of the rest of boot.
+    #[cfg(feature = "kernel-tests")]
+    testing::run_tests(&dtb);
+
+    if let Err(error) = task::spawn_kernel_thread("monitor", run_monitor, 0) {
+        debug_println!("Failed to start the monitor thread: {}.", error);
+    }
+
+    if let Err(error) = task::spawn_kernel_thread("mount", mount_root_file_system, 0) {
+        debug_println!("Failed to start the mount thread: {}.", error);
+    }
+
+    if virtio::net::mac_address().is_ok() {
+        net::initialize();
+    }
+
+    const INIT_PATH: &str = "/init";
+
+    // The first process is /init from the initial ramdisk, which needs no
+    // disk driver, or the embedded init program without one.
+    let first_program = if has_initramfs && fs::open(INIT_PATH).is_ok() {
+        INIT_PATH
+    } else {
+        "init"
+    };
+
+    match process::spawn(first_program) {
+        Ok(process::INIT_PROCESS_ID) => {}
+        Ok(id) => debug_println!(
+            "\"{}\" started as process {} instead of {}, so orphans are not adopted.",
+            first_program,
+            id,
+            process::INIT_PROCESS_ID
+        ),
+        Err(error) => debug_println!("Failed to start \"{}\": {}.", first_program, error),
+    }
+
+    time::boot_timeline::record(BootMilestone::KernelIdle);
+    time::boot_timeline::print_summary();
+
+    idle_loop();
+}
+
+/// Entry point for secondary harts once paging is enabled and they are running
+/// on their own stack. Called from `_kernel_secondary_virtual_entry`.
+///
+/// # Arguments
+///
+/// * `hart_id` - The hardware thread ID that called this function.
+#[unsafe(no_mangle)]
+pub fn kernel_secondary_main(hart_id: usize) -> ! {
+    percpu::initialize(hart_id);
+    task::initialize_hart(hart_id);
+    stack_guard::initialize_hart(hart_id);
+
+    trap::initialize();
+    ipi::initialize();
+
+    // A hart without a supervisor PLIC context simply takes no external
+    // interrupts.
+    let _ = plic::initialize_hart();
+
+    hart::register_hart(hart_id);
+
+    debug_println!("Hart {} online.", hart_id);
+
+    idle_loop();
+}
+
+/// The idle task of every hart. Runs ready threads and otherwise waits for an
+/// interrupt.
+fn idle_loop() -> ! {
+    loop {
+        task::yield_now();
+
+        unsafe {
+            core::arch::asm!("wfi", options(nomem, nostack));
+        }
+    }
+}
+
+/// Kernel thread that runs the monitor on the console.
+///
+/// The thread blocks between lines until the console notifies it of input,
+/// or polls between short sleeps if the console device cannot interrupt.
+fn run_monitor(_argument: usize) {
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    let mut monitor = monitor::Monitor::new();
+
+    loop {
+        // Commands are run one line at a time, so the console may still hold
+        // input after one runs.
+        if !monitor.poll() {
+            console::wait_for_input(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Kernel thread that mounts the FAT32 file system on the block device named
+/// by the "root" command line option, or on the virtio block device. The file
+/// system is mounted at "/", or at "/mnt" if the initial ramdisk is there.
+fn mount_root_file_system(_argument: usize) {
+    let device_name = cmdline::command_line()
+        .get_str("root")
+        .unwrap_or(virtio::block::DEVICE_NAME);
+
+    let path = if fs::is_mounted("/") { "/mnt" } else { "/" };
+
+    match fs::fat32::mount_device(device_name, path) {
+        Ok(file_system) => debug_println!(
+            "Mounted the FAT32 file system on {} at {}: {} clusters of {} bytes.",
+            device_name,
+            path,
+            file_system.cluster_count(),
+            file_system.cluster_size()
+        ),
+        Err(error) => debug_println!("No FAT32 file system on {}: {}.", device_name, error),
+    }
+}
+
+/// Logs the information the boot stage handed over.
+fn print_boot_info(boot_info: &BootInfo) {
+    debug_println!("Boot information version {}:", boot_info.version());
+    debug_println!(
+        "  DTB: {:#x} ({} bytes)",
+        boot_info.dtb_physical_address,
+        boot_info.dtb_size
+    );
+    debug_println!(
+        "  Root page table: {:#x}",
+        boot_info.root_page_table_physical_address
+    );
+    debug_println!(
+        "  Paging mode: {} (the hart supports up to {})",
+        boot_info.paging_mode(),
+        boot_info.highest_paging_mode()
+    );
+    debug_println!(
+        "  Boot image: {:#x} ({} bytes)",
+        boot_info.boot_physical_base,
+        boot_info.boot_size
+    );
+    debug_println!(
+        "  Kernel image: {:#x} mapped at {:#x}, slid by {:#x} ({} bytes)",
+        boot_info.kernel_physical_base,
+        boot_info.kernel_virtual_base,
+        boot_info.kernel_slide,
+        boot_info.kernel_size
+    );
+    debug_println!(
+        "  Boot allocator watermark: {:#x}",
+        boot_info.allocator_watermark
+    );
+    debug_println!("  Boot console: {:?}", boot_info.console());
+    debug_println!("  Usable memory:");
+
+    for region in boot_info.memory_regions() {
+        debug_println!("    {:#x}-{:#x}", region.start, region.end());
+    }
+
+    debug_println!("  Allocated by the boot stage:");
+
+    for region in boot_info.allocated_regions() {
+        debug_println!("    {:#x}-{:#x}", region.start, region.end());
+    }
+}
+
+/// Logs the boot parameters from the /chosen node of the DTB and retains the
+/// kernel command line.
+fn print_chosen(dtb: &Dtb) {
+    let Some(chosen) = dtb::chosen(dtb) else {
+        debug_println!("The DTB has no /chosen node.");
+        return;
+    };
+
+    cmdline::initialize(chosen.bootargs.unwrap_or(""));
+
+    let command_line = cmdline::command_line();
+    debug_println!("Kernel command line: \"{}\"", command_line.as_str());
+
+    for option in command_line.options() {
+        match option.value {
+            Some(value) => debug_println!("  {} = {}", option.key, value),
+            None => debug_println!("  {}", option.key),
+        }
+    }
+
+    if let Some(stdout_path) = chosen.stdout_path {
+        debug_println!("stdout-path: {}", stdout_path);
+    }
+
+    if let Some((initrd_start, initrd_end)) = chosen.initrd_range() {
+        debug_println!("initrd: {:#x}-{:#x}", initrd_start, initrd_end);
+    }
+}
+
+/// Logs every hart described in the /cpus node of the DTB.
+fn print_cpus(dtb: &Dtb) {
+    debug_println!("CPUs:");
+
+    for cpu in dtb::cpus(dtb) {
+        debug_println!(
+            "  Hart {}: isa {}, extensions {}, mmu {}, timebase {} Hz{}",
+            cpu.hart_id,
+            cpu.isa.unwrap_or("unknown"),
+            cpu.isa_features(),
+            cpu.mmu_type.unwrap_or("unknown"),
+            cpu.timebase_frequency.unwrap_or(0),
+            if cpu.is_enabled() { "" } else { " (disabled)" }
+        );
+    }
+}
+
+/// Sends a function call IPI to every online secondary hart as a check that
+/// inter-hart messages are delivered.
+fn greet_secondary_harts(boot_hart_id: usize) {
+    for hart_id in 0..hart::MAX_HART_COUNT {
+        if hart_id == boot_hart_id || !hart::is_hart_online(hart_id) {
+            continue;
+        }
+
+        if let Err(error) = ipi::call_on_hart(hart_id, print_ipi_greeting, boot_hart_id) {
+            debug_println!("Failed to send an IPI to hart {}: {}.", hart_id, error);
+        }
+    }
+}
+
+/// IPI function call target that reports which hart received the message.
+fn print_ipi_greeting(sender_hart_id: usize) {
     debug_println!(
-        "Root page table physical address: {:#x}",
-        root_page_table_physical_address
+        "Hart {} received an IPI from hart {}.",
+        hart::current_hart_id(),
+        sender_hart_id
     );
+}
+
+/// Tick callback that prints the uptime every ten seconds as a sign of life.
+/// The print is deferred to a worker thread to keep the timer interrupt
+/// short.
+fn print_uptime(tick_count: u64) {
+    const TICKS_BETWEEN_PRINTS: u64 = 10 * timer::TICKS_PER_SECOND;
 
-    loop {}
+    if tick_count.is_multiple_of(TICKS_BETWEEN_PRINTS) {
+        let _ = workqueue::queue_work(
+            |_| debug_println!("Uptime: {} seconds.", time::uptime().as_secs()),
+            0,
+        );
+    }
 }
 
+/// Set by a panic on a hart that does not have a per-hart block yet.
+static IS_EARLY_PANIC_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+
 #[panic_handler]
+#[cfg_attr(feature = "kernel-tests", allow(unreachable_code))]
 fn panic(info: &PanicInfo) -> ! {
-    debug_println!("\n\n===== KERNEL PANIC =====");
+    let registers = capture_registers!();
+
+    // A second panic on this hart came from the panic path itself, so only
+    // its message is printed.
+    let is_panicking = percpu::try_current().map_or(&IS_EARLY_PANIC_IN_PROGRESS, |per_hart| {
+        &per_hart.is_panicking
+    });
+    let is_nested_panic = is_panicking.swap(true, Ordering::AcqRel);
+
+    if is_nested_panic {
+        debug_println!("\n===== NESTED KERNEL PANIC =====\n{}", info);
+        halt();
+    }
+
+    // Repeat the end of the log first so the panic report is the last thing
+    // on the console. The heading bypasses the log so it is not part of the
+    // tail.
+    const PANIC_LOG_TAIL_LINE_COUNT: usize = 20;
+
+    console::write_bytes(b"\n\n===== LOG TAIL =====\n");
+    log::print_tail(PANIC_LOG_TAIL_LINE_COUNT);
+
+    debug_println!("\n===== KERNEL PANIC =====");
 
     // Print location information if available.
     if let Some(location) = info.location() {
@@ -41,12 +501,58 @@ fn panic(info: &PanicInfo) -> ! {
     // Print the panic message directly.
     debug_println!("Panic message: {}", info);
 
+    debug_println!("\nRegisters:\n{}", registers);
+
+    debug_println!("\nBacktrace:");
+    backtrace::print(registers.frame_pointer, registers.stack_pointer);
+
     debug_println!("=========================\n");
 
-    // Halt the kernel.
-    loop {}
+    // Stop taking interrupts so that nothing else runs on this hart.
+    unsafe {
+        core::arch::asm!("csrci sstatus, 0x2", options(nomem, nostack));
+    }
+
+    #[cfg(feature = "kernel-tests")]
+    testing::fail_running_test();
+
+    #[cfg(feature = "shutdown-on-panic")]
+    {
+        let error =
+            sbi_lib::system_reset::shutdown(sbi_lib::system_reset::RESET_REASON_SYSTEM_FAILURE);
+        debug_println!("SBI shutdown failed: {}.", error);
+    }
+
+    // Let the state of the kernel be inspected from the console. This returns
+    // on every hart but the first to panic.
+    monitor::enter_from_panic();
+
+    halt();
+}
+
+/// Stops taking interrupts and parks this hart forever.
+fn halt() -> ! {
+    unsafe {
+        core::arch::asm!("csrci sstatus, 0x2", options(nomem, nostack));
+    }
+
+    // Pending interrupts still end wfi while they are disabled, so wait in a
+    // loop.
+    loop {
+        unsafe {
+            core::arch::asm!("wfi", options(nomem, nostack));
+        }
+    }
 }
 
+/// The size in bytes of the stack the boot hart runs the kernel on.
+const BOOT_HART_STACK_SIZE: usize = 32 * 1024;
+
+/// The stack the boot hart switches to when it enters the kernel. The boot
+/// stack is only reachable through the boot stage's identity mapping, which
+/// `memory::initialize` removes.
+static BOOT_HART_STACK: GuardedStack<BOOT_HART_STACK_SIZE> = GuardedStack::new();
+
 global_asm!(
     "
     .global _kernel_entrypoint
@@ -57,12 +563,67 @@ global_asm!(
     
     _kernel_entrypoint:
         // - a0 = hart_id
-        // - a1 = dtb_physical_address
-        // - a2 = root_page_table_physical_address
+        // - a1 = boot_info_physical_address
+
+        // Leave the boot stack for the kernel's own, and start a new chain of
+        // frames on it.
+        la sp, {boot_hart_stack}
+        li t0, {boot_hart_stack_size}
+        add sp, sp, t0
+        mv fp, zero
+
+        mv tp, zero     // No per-hart block until percpu::initialize.
         jal kernel_main
 
     infinite:   // Infinite loop if kernel_main returns.
         wfi
         j infinite
+    ",
+    boot_hart_stack = sym BOOT_HART_STACK,
+    boot_hart_stack_size = const size_of::<GuardedStack<BOOT_HART_STACK_SIZE>>(),
+);
+
+global_asm!(
+    "
+    .global _kernel_secondary_entrypoint
+    .global _kernel_secondary_virtual_entry
+
+    .extern kernel_secondary_main
+
+    .section .text.kernel_secondary_entrypoint
+
+    // Secondary harts started through SBI HSM begin here at the physical
+    // address of this code with paging and interrupts disabled.
+    // - a0 = hart_id
+    // - a1 = physical address of the hart's HartStartupInformation
+    .balign 4
+    _kernel_secondary_entrypoint:
+        ld t0, 0(a1)    // satp shared with the boot hart.
+        ld sp, 8(a1)    // Virtual address of the top of this hart's stack.
+        ld t1, 16(a1)   // Virtual address of _kernel_secondary_virtual_entry.
+
+        // This code is not mapped at its physical address, so the first
+        // instruction fetch after enabling paging raises an instruction page
+        // fault. Point stvec at the virtual entry so that fault lands there.
+        csrw stvec, t1
+
+        sfence.vma
+        csrw satp, t0
+
+        // Only reached if this code happens to be identity mapped.
+        jr t1
+
+    .balign 4
+    _kernel_secondary_virtual_entry:
+        // Discard any translations cached before satp was written.
+        sfence.vma
+
+        // - a0 = hart_id (preserved across the page fault)
+        mv tp, zero     // No per-hart block until percpu::initialize.
+        call kernel_secondary_main
+
+    secondary_infinite:   // Infinite loop if kernel_secondary_main returns.
+        wfi
+        j secondary_infinite
     "
 );
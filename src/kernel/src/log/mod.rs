@@ -0,0 +1,225 @@
+//! The kernel log.
+//!
+//! Everything printed to the console with `debug_print!` is also recorded in a
+//! ring buffer, with every line stamped with the time since the `time` CSR
+//! started counting. The log can be replayed with the monitor's `dmesg`
+//! command, and the panic handler repeats its last lines.
+//!
+//! The buffer is shared by every hart. Printing does not wait for it: the
+//! text is sent as records on a lock-free channel, and whichever hart holds
+//! the buffer lock receives them into the buffer. A hart that finds the lock
+//! taken leaves its records to the holder, which checks the channel again
+//! after unlocking. The lock is held with interrupts disabled on the calling
+//! hart, so printing from a trap handler cannot deadlock against the code it
+//! interrupted.
+
+use crate::{console, time};
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, Ordering, fence},
+};
+use kernel_lib::{
+    log_buffer::LogBuffer,
+    sync::{MpscChannel, disable_interrupts, restore_interrupts},
+};
+
+/// The number of bytes of log text retained.
+const LOG_BUFFER_SIZE: usize = 16 * 1024;
+
+/// The most bytes of text a record holds. Longer text is split into several.
+const LOG_RECORD_SIZE: usize = 64;
+
+/// The number of records that can wait for the buffer.
+const PENDING_RECORD_CAPACITY: usize = 64;
+
+/// The number of spin iterations `print_tail` waits for the lock before
+/// giving up, in case the panicking hart already holds it.
+const PANIC_LOCK_SPIN_LIMIT: usize = 10_000_000;
+
+/// The log buffer and the flag that guards it.
+struct KernelLog {
+    is_locked: AtomicBool,
+    buffer: UnsafeCell<LogBuffer<LOG_BUFFER_SIZE>>,
+}
+
+// The buffer is only accessed through a `LogBufferGuard`, which holds the
+// lock.
+unsafe impl Sync for KernelLog {}
+
+static KERNEL_LOG: KernelLog = KernelLog {
+    is_locked: AtomicBool::new(false),
+    buffer: UnsafeCell::new(LogBuffer::new()),
+};
+
+/// A piece of console output waiting to be written to the buffer.
+#[derive(Copy, Clone)]
+struct LogRecord {
+    /// The time the text was written.
+    timestamp_microseconds: u64,
+
+    /// The number of bytes of `bytes` in use.
+    length: usize,
+
+    bytes: [u8; LOG_RECORD_SIZE],
+}
+
+/// The records sent by every hart, received by the holder of the buffer lock.
+static PENDING_RECORDS: MpscChannel<LogRecord, PENDING_RECORD_CAPACITY> = MpscChannel::new();
+
+/// Appends console output to the log.
+///
+/// # Arguments
+///
+/// * `text` - The text written to the console.
+pub fn record(text: &[u8]) {
+    let timestamp_microseconds = uptime_microseconds();
+
+    for (index, chunk) in text.chunks(LOG_RECORD_SIZE).enumerate() {
+        let mut record = LogRecord {
+            timestamp_microseconds,
+            length: chunk.len(),
+            bytes: [0; LOG_RECORD_SIZE],
+        };
+        record.bytes[..chunk.len()].copy_from_slice(chunk);
+
+        if PENDING_RECORDS.send(record).is_err() {
+            // The channel is full, so wait for the buffer and write the rest
+            // of the text directly, after the records already waiting.
+            if let Some(mut guard) = LogBufferGuard::acquire(usize::MAX) {
+                guard.receive_pending_records();
+                guard
+                    .buffer()
+                    .write(&text[index * LOG_RECORD_SIZE..], timestamp_microseconds);
+            }
+
+            return;
+        }
+    }
+
+    write_pending_records();
+}
+
+/// Writes the records waiting in the channel to the buffer, unless another
+/// hart holds the lock and will do it instead.
+fn write_pending_records() {
+    loop {
+        // Pairs with the fence below on the lock holder, so either this hart
+        // takes the lock or the holder sees the records it sent.
+        fence(Ordering::SeqCst);
+
+        let Some(mut guard) = LogBufferGuard::acquire(1) else {
+            return;
+        };
+
+        guard.receive_pending_records();
+        drop(guard);
+
+        fence(Ordering::SeqCst);
+
+        if PENDING_RECORDS.is_empty() {
+            return;
+        }
+    }
+}
+
+/// Writes the whole retained log to the console without recording it again.
+pub fn replay() {
+    if let Some(mut guard) = LogBufferGuard::acquire(usize::MAX) {
+        guard.receive_pending_records();
+
+        let (first, second) = guard.buffer().contents();
+
+        console::write_bytes(first);
+        console::write_bytes(second);
+    }
+}
+
+/// Writes the newest lines of the log to the console without recording them
+/// again. Used by the panic handler, so it gives up if the lock stays held.
+///
+/// # Arguments
+///
+/// * `line_count` - The number of lines to write.
+pub fn print_tail(line_count: usize) {
+    if let Some(mut guard) = LogBufferGuard::acquire(PANIC_LOCK_SPIN_LIMIT) {
+        guard.receive_pending_records();
+
+        let (first, second) = guard.buffer().tail(line_count);
+
+        console::write_bytes(first);
+        console::write_bytes(second);
+    }
+}
+
+/// Returns the time since the `time` CSR read zero in microseconds.
+fn uptime_microseconds() -> u64 {
+    time::uptime().as_micros() as u64
+}
+
+/// Exclusive access to the log buffer. Interrupts stay disabled on the
+/// calling hart until the guard is dropped.
+struct LogBufferGuard {
+    /// Whether interrupts were enabled before `acquire` disabled them.
+    were_interrupts_enabled: bool,
+}
+
+impl LogBufferGuard {
+    /// Disables interrupts and takes the lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `spin_limit` - The number of attempts to take the lock.
+    ///
+    /// # Returns
+    ///
+    /// The guard, or `None` with interrupts restored if the lock could not be
+    /// taken.
+    fn acquire(spin_limit: usize) -> Option<Self> {
+        let guard = Self {
+            were_interrupts_enabled: disable_interrupts(),
+        };
+
+        // The exchange is strong, so a single attempt only fails if another
+        // hart holds the lock.
+        for _ in 0..spin_limit {
+            if KERNEL_LOG
+                .is_locked
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Some(guard);
+            }
+
+            core::hint::spin_loop();
+        }
+
+        // Restore interrupts without releasing a lock that was never taken.
+        restore_interrupts(guard.were_interrupts_enabled);
+        core::mem::forget(guard);
+
+        None
+    }
+
+    /// Returns the log buffer.
+    fn buffer(&mut self) -> &mut LogBuffer<LOG_BUFFER_SIZE> {
+        unsafe { &mut *KERNEL_LOG.buffer.get() }
+    }
+
+    /// Writes every record waiting in the channel to the buffer.
+    fn receive_pending_records(&mut self) {
+        // Holding the lock makes this hart the channel's only receiver.
+        while let Some(record) = unsafe { PENDING_RECORDS.receive() } {
+            self.buffer().write(
+                &record.bytes[..record.length],
+                record.timestamp_microseconds,
+            );
+        }
+    }
+}
+
+impl Drop for LogBufferGuard {
+    fn drop(&mut self) {
+        KERNEL_LOG.is_locked.store(false, Ordering::Release);
+        restore_interrupts(self.were_interrupts_enabled);
+    }
+}
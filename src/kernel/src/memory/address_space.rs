@@ -0,0 +1,860 @@
+//! Address spaces for user code.
+//!
+//! Each `AddressSpace` has its own root page table. The lower half of the
+//! address space holds user mappings, while the root entries of the upper half
+//! are copied from the kernel's root page table, so the kernel stays mapped
+//! while a user address space is active. Mappings the kernel later adds below
+//! an upper half root entry show up in every address space, but new upper
+//! half root entries do not.
+//!
+//! Besides the pages it maps, an address space holds a list of virtual memory
+//! areas, ranges of user addresses whose pages are mapped with zeroed frames
+//! only when they are first touched. `map_anonymous` reserves such an area,
+//! and the fault on the first access to one of its pages lands in
+//! `resolve_page_fault`, which maps the page.
+//!
+//! `fork` shares every user page of an address space with the copy instead of
+//! copying it. Writable pages become read only in both and are marked with
+//! `SOFTWARE_COPY_ON_WRITE`, and `resolve_page_fault` gives the first writer
+//! to one a page of its own. Pages from `map_shared` are marked with
+//! `SOFTWARE_SHARED` instead and stay shared, writable or not, in both.
+//!
+//! `clone_page_table` and `destroy_page_table` do the page table work of
+//! `fork` and of dropping an address space. Every page table below a user
+//! root entry belongs to that root alone and goes back to the frame pool when
+//! it is destroyed, while the kernel half and any global entries are shared
+//! and left alone.
+
+use super::{
+    PAGE_SIZE, asid,
+    frame_pool::{self, FramePoolAllocator},
+    kernel_root_page_table_ppn, physical_to_virtual, satp_for,
+};
+use crate::tlb;
+use boot_lib::memory::mmu::{
+    MapError, MemoryType, PageTable, PageTableEntry, PageTableEntryFlags, allocate_vpn,
+    page_table_pointer, paging_mode, unmap_vpn,
+};
+use common_lib::memory::{PhysicalPageNumber, VirtualPageNumber};
+use core::ops::Range;
+use kernel_lib::virtual_memory_area::{AreaError, VirtualMemoryArea, VirtualMemoryAreaList};
+
+/// The number of entries in every page table.
+const PAGE_TABLE_ENTRY_COUNT: usize = 512;
+
+/// The first root page table entry of the upper, kernel half of the address
+/// space.
+const KERNEL_HALF_FIRST_ROOT_ENTRY: usize = PAGE_TABLE_ENTRY_COUNT / 2;
+
+/// The RSW bits of a leaf entry whose page is shared copy-on-write. The page
+/// is writable, but the entry is not until the share is broken.
+const SOFTWARE_COPY_ON_WRITE: u64 = 0b01;
+
+/// The RSW bits of a leaf entry whose page belongs to a shared memory object.
+/// `fork` shares the page as it is rather than copy-on-write.
+const SOFTWARE_SHARED: u64 = 0b10;
+
+/// The most virtual memory areas an address space can hold.
+const MAX_AREAS: usize = 16;
+
+/// The user addresses `map_anonymous` and `map_shared` place areas within,
+/// between 64GiB and 128GiB, well away from program images and the stack.
+const ANONYMOUS_AREA_WINDOW: Range<usize> = (1 << 36)..(1 << 37);
+
+/// The first virtual address past the user mappings, the end of the lower
+/// half of the sv39 address space. The lower half of the larger paging modes
+/// reaches further, but user mappings stay below this in every mode.
+pub const USER_ADDRESS_LIMIT: usize = 1 << 38;
+
+/// The access a user page allows.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UserPageAccess {
+    /// Readable and executable, for code.
+    ReadExecute,
+
+    /// Readable only, for constant data.
+    Read,
+
+    /// Readable and writable, for data and stacks.
+    ReadWrite,
+}
+
+/// The kind of access that caused a page fault.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FaultAccess {
+    /// A load.
+    Read,
+
+    /// A store or atomic memory operation.
+    Write,
+
+    /// An instruction fetch.
+    Execute,
+}
+
+impl UserPageAccess {
+    /// Returns true if a page with this access allows a kind of access.
+    fn allows(self, access: FaultAccess) -> bool {
+        match access {
+            FaultAccess::Read => true,
+            FaultAccess::Write => self == Self::ReadWrite,
+            FaultAccess::Execute => self == Self::ReadExecute,
+        }
+    }
+
+    /// Returns the page table entry flags of a user page with this access.
+    fn flags(self) -> PageTableEntryFlags {
+        PageTableEntryFlags {
+            readable: true,
+            writable: self == Self::ReadWrite,
+            executable: self == Self::ReadExecute,
+            user: true,
+            global: false,
+            memory_type: MemoryType::Pma,
+        }
+    }
+}
+
+/// A user address space with its own root page table.
+///
+/// Every page table and user page comes from the frame pool and its reference
+/// is freed when the address space is dropped. User pages may be shared with
+/// other address spaces, while page tables never are. An address space must
+/// not be dropped while it is active on any hart.
+pub struct AddressSpace {
+    root_page_table_ppn: PhysicalPageNumber,
+
+    /// The ASID that tags the translations of the address space, or 0 if
+    /// every ASID was taken when it was created.
+    asid: usize,
+
+    /// The areas whose pages are mapped when first touched.
+    areas: VirtualMemoryAreaList<UserPageAccess, MAX_AREAS>,
+}
+
+impl AddressSpace {
+    /// Creates an address space that maps only the kernel.
+    ///
+    /// # Returns
+    ///
+    /// The address space, or `MapError::OutOfMemory` if the frame pool is
+    /// empty.
+    pub fn new() -> Result<Self, MapError> {
+        let root_page_table_ppn = frame_pool::allocate_page_table().ok_or(MapError::OutOfMemory)?;
+
+        let kernel_root_page_table = unsafe { &*page_table_pointer(kernel_root_page_table_ppn()) };
+        let root_page_table = unsafe { &mut *page_table_pointer(root_page_table_ppn) };
+
+        for index in KERNEL_HALF_FIRST_ROOT_ENTRY..PAGE_TABLE_ENTRY_COUNT {
+            root_page_table.set_entry(index, *kernel_root_page_table.get_entry(index));
+        }
+
+        Ok(Self {
+            root_page_table_ppn,
+            asid: asid::allocate(),
+            areas: VirtualMemoryAreaList::new(),
+        })
+    }
+
+    /// Maps a new zeroed page for user code.
+    ///
+    /// # Arguments
+    ///
+    /// * `virtual_address` - The page aligned user address to map the page at.
+    /// * `access` - The access the page allows user code.
+    ///
+    /// # Returns
+    ///
+    /// A kernel pointer to the contents of the page, through which the caller
+    /// fills it in. Fails with `MapError::AlreadyMapped` if the page is
+    /// mapped already and with `MapError::OutOfMemory` if the frame pool is
+    /// empty.
+    ///
+    /// # Panics
+    ///
+    /// If `virtual_address` is not a page aligned address below
+    /// `USER_ADDRESS_LIMIT`.
+    pub fn map_user_page(
+        &mut self,
+        virtual_address: usize,
+        access: UserPageAccess,
+    ) -> Result<*mut u8, MapError> {
+        assert!(
+            virtual_address < USER_ADDRESS_LIMIT && virtual_address.is_multiple_of(PAGE_SIZE),
+            "{:#x} is not a page aligned user address.",
+            virtual_address
+        );
+
+        let ppn = frame_pool::allocate_frame().ok_or(MapError::OutOfMemory)?;
+
+        let result = allocate_vpn(
+            self.root_page_table(),
+            VirtualPageNumber::from_virtual_address(virtual_address),
+            Some(ppn),
+            &access.flags(),
+            &mut FramePoolAllocator,
+        );
+
+        if let Err(error) = result {
+            frame_pool::free_frame(ppn);
+            return Err(error);
+        }
+
+        // Frames in the pool are RAM in the kernel image, which the direct
+        // mapping covers.
+        Ok(physical_to_virtual(ppn.to_physical_address())
+            .expect("Frame pool frames are in the direct mapping.") as *mut u8)
+    }
+
+    /// Reserves an area of user addresses at a fixed place. Its pages are
+    /// mapped with zeroed frames when they are first touched.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_address` - The page aligned first user address of the area.
+    /// * `length` - The size of the area in bytes, a nonzero multiple of
+    ///   `PAGE_SIZE`.
+    /// * `access` - The access the pages of the area allow user code.
+    ///
+    /// # Returns
+    ///
+    /// `MapError::AlreadyMapped` if the area overlaps another area and
+    /// `MapError::OutOfMemory` if the address space holds `MAX_AREAS` areas.
+    ///
+    /// # Panics
+    ///
+    /// If the area is empty, not page aligned, or not below
+    /// `USER_ADDRESS_LIMIT`.
+    pub fn reserve_area(
+        &mut self,
+        start_address: usize,
+        length: usize,
+        access: UserPageAccess,
+    ) -> Result<(), MapError> {
+        let end_address = start_address
+            .checked_add(length)
+            .filter(|&end_address| end_address <= USER_ADDRESS_LIMIT);
+
+        assert!(
+            length > 0
+                && start_address.is_multiple_of(PAGE_SIZE)
+                && length.is_multiple_of(PAGE_SIZE)
+                && end_address.is_some(),
+            "{:#x} with length {:#x} is not a page aligned user area.",
+            start_address,
+            length
+        );
+
+        self.areas
+            .insert(VirtualMemoryArea {
+                start: start_address,
+                end: start_address + length,
+                attributes: access,
+            })
+            .map_err(|error| match error {
+                AreaError::Full => MapError::OutOfMemory,
+                AreaError::Empty | AreaError::Overlaps => MapError::AlreadyMapped,
+            })
+    }
+
+    /// Reserves an area of zeroed user memory wherever there is room for it.
+    /// Its pages are mapped when they are first touched.
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - The size of the area in bytes, which is rounded up to a
+    ///   whole number of pages.
+    /// * `access` - The access the pages of the area allow user code.
+    ///
+    /// # Returns
+    ///
+    /// The page aligned first user address of the area, or
+    /// `MapError::OutOfMemory` if there is no room for it.
+    ///
+    /// # Panics
+    ///
+    /// If `length` is zero.
+    pub fn map_anonymous(
+        &mut self,
+        length: usize,
+        access: UserPageAccess,
+    ) -> Result<usize, MapError> {
+        assert!(length > 0, "An anonymous area cannot be empty.");
+
+        let length = length
+            .checked_next_multiple_of(PAGE_SIZE)
+            .ok_or(MapError::OutOfMemory)?;
+
+        let start_address = self
+            .areas
+            .find_free_range(length, ANONYMOUS_AREA_WINDOW)
+            .ok_or(MapError::OutOfMemory)?;
+
+        self.reserve_area(start_address, length, access)?;
+
+        Ok(start_address)
+    }
+
+    /// Maps frames that other address spaces may map too, such as those of a
+    /// shared memory object, wherever there is room for them. Each frame
+    /// gains a reference, which the address space frees when it is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - The frames to map at consecutive pages, each allocated
+    ///   from the frame pool.
+    /// * `access` - The access the pages allow user code.
+    ///
+    /// # Returns
+    ///
+    /// The page aligned first user address of the pages, or
+    /// `MapError::OutOfMemory` if there is no room for them or the frame pool
+    /// runs out of page tables.
+    ///
+    /// # Panics
+    ///
+    /// If `frames` is empty.
+    pub fn map_shared(
+        &mut self,
+        frames: &[PhysicalPageNumber],
+        access: UserPageAccess,
+    ) -> Result<usize, MapError> {
+        assert!(!frames.is_empty(), "A shared mapping cannot be empty.");
+
+        let length = frames.len() * PAGE_SIZE;
+
+        let start_address = self
+            .areas
+            .find_free_range(length, ANONYMOUS_AREA_WINDOW)
+            .ok_or(MapError::OutOfMemory)?;
+
+        for (index, &ppn) in frames.iter().enumerate() {
+            if let Err(error) = self.map_shared_page(start_address + index * PAGE_SIZE, ppn, access)
+            {
+                self.unmap_shared_pages(start_address, index);
+                return Err(error);
+            }
+        }
+
+        // The area keeps later mappings out of the pages. Every page of it is
+        // mapped, so it never maps a zeroed page of its own.
+        if let Err(error) = self.reserve_area(start_address, length, access) {
+            self.unmap_shared_pages(start_address, frames.len());
+            return Err(error);
+        }
+
+        self.flush(start_address, length);
+
+        Ok(start_address)
+    }
+
+    /// Resolves a page fault raised by user code, either by mapping the first
+    /// page of an area to be touched or by breaking a copy-on-write share.
+    ///
+    /// This address space must be the one active on the calling hart.
+    ///
+    /// # Arguments
+    ///
+    /// * `virtual_address` - The user address that faulted.
+    /// * `access` - The kind of access that faulted.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the access can be retried, `false` if the address space does
+    /// not allow it, or `MapError::OutOfMemory` if the frame pool is empty.
+    pub fn resolve_page_fault(
+        &mut self,
+        virtual_address: usize,
+        access: FaultAccess,
+    ) -> Result<bool, MapError> {
+        if virtual_address >= USER_ADDRESS_LIMIT {
+            return Ok(false);
+        }
+
+        // A mapped page only faults on an access its entry does not allow,
+        // of which only a write to a copy-on-write page can be fixed.
+        if self.leaf_entry_mut(virtual_address).is_some() {
+            return match access {
+                FaultAccess::Write => self.resolve_copy_on_write(virtual_address),
+                FaultAccess::Read | FaultAccess::Execute => Ok(false),
+            };
+        }
+
+        let Some(area) = self.areas.find(virtual_address).copied() else {
+            return Ok(false);
+        };
+
+        if !area.attributes.allows(access) {
+            return Ok(false);
+        }
+
+        let page_address = virtual_address & !(PAGE_SIZE - 1);
+        self.map_user_page(page_address, area.attributes)?;
+
+        self.flush(page_address, PAGE_SIZE);
+
+        Ok(true)
+    }
+
+    /// Creates a copy of this address space that shares every user page with
+    /// it copy-on-write. The page tables and areas are copied, while writable
+    /// pages become read only in both address spaces until either writes to
+    /// them.
+    ///
+    /// This address space must be the one active on the calling hart and on
+    /// no other hart, since only the calling hart's stale writable
+    /// translations are flushed.
+    ///
+    /// # Returns
+    ///
+    /// The copy, or `MapError::OutOfMemory` if the frame pool runs out.
+    pub fn fork(&mut self) -> Result<Self, MapError> {
+        let result = clone_page_table(self.root_page_table_ppn);
+
+        // Writable pages became read only even if the copy failed part way.
+        self.flush(0, USER_ADDRESS_LIMIT);
+
+        Ok(Self {
+            root_page_table_ppn: result?,
+            asid: asid::allocate(),
+            areas: self.areas,
+        })
+    }
+
+    /// Gives this address space its own copy of a page shared copy-on-write,
+    /// so that user code can write to it. The page is copied only if another
+    /// address space still shares it.
+    ///
+    /// # Arguments
+    ///
+    /// * `virtual_address` - Any user address within the page.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the page is writable now, `false` if it is not mapped or not
+    /// copy-on-write, or `MapError::OutOfMemory` if the frame pool is empty.
+    fn resolve_copy_on_write(&mut self, virtual_address: usize) -> Result<bool, MapError> {
+        let Some(entry) = self.leaf_entry_mut(virtual_address) else {
+            return Ok(false);
+        };
+
+        if entry.get_software_bits() != SOFTWARE_COPY_ON_WRITE {
+            return Ok(false);
+        }
+
+        let shared_ppn = entry.get_ppn();
+
+        // The copy is made before the reference is freed, so the last owner
+        // to fault always finds the page intact and takes it over.
+        if frame_pool::frame_reference_count(shared_ppn) > 1 {
+            let ppn = frame_pool::allocate_frame().ok_or(MapError::OutOfMemory)?;
+
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    page_table_pointer(shared_ppn) as *const u8,
+                    page_table_pointer(ppn) as *mut u8,
+                    PAGE_SIZE,
+                );
+            }
+
+            entry.set_ppn(ppn);
+            frame_pool::free_frame(shared_ppn);
+        }
+
+        entry.set_writable(true);
+        entry.set_software_bits(0);
+
+        let page_address = virtual_address & !(PAGE_SIZE - 1);
+        self.flush(page_address, PAGE_SIZE);
+
+        Ok(true)
+    }
+
+    /// Maps a shared frame at a user page and adds a reference to it.
+    fn map_shared_page(
+        &mut self,
+        virtual_address: usize,
+        ppn: PhysicalPageNumber,
+        access: UserPageAccess,
+    ) -> Result<(), MapError> {
+        allocate_vpn(
+            self.root_page_table(),
+            VirtualPageNumber::from_virtual_address(virtual_address),
+            Some(ppn),
+            &access.flags(),
+            &mut FramePoolAllocator,
+        )?;
+
+        frame_pool::add_frame_reference(ppn);
+
+        self.leaf_entry_mut(virtual_address)
+            .expect("The page was just mapped.")
+            .set_software_bits(SOFTWARE_SHARED);
+
+        Ok(())
+    }
+
+    /// Unmaps consecutive pages mapped by `map_shared_page` and frees their
+    /// references.
+    fn unmap_shared_pages(&mut self, start_address: usize, page_count: usize) {
+        for index in 0..page_count {
+            let vpn = VirtualPageNumber::from_virtual_address(start_address + index * PAGE_SIZE);
+
+            if let Ok(ppn) = unmap_vpn(self.root_page_table(), vpn) {
+                frame_pool::free_frame(ppn);
+            }
+        }
+    }
+
+    /// Returns the satp value that selects this address space and its ASID.
+    pub fn satp(&self) -> usize {
+        satp_for(self.root_page_table_ppn, self.asid)
+    }
+
+    /// Returns the root page table, which identifies the address space for as
+    /// long as it exists.
+    pub fn root_page_table_ppn(&self) -> PhysicalPageNumber {
+        self.root_page_table_ppn
+    }
+
+    /// Finds the frame that backs a mapped user page.
+    ///
+    /// # Arguments
+    ///
+    /// * `virtual_address` - Any user address within the page.
+    ///
+    /// # Returns
+    ///
+    /// The frame and true if it belongs to a shared memory object, or `None`
+    /// if the page is not mapped.
+    pub fn user_frame(&mut self, virtual_address: usize) -> Option<(PhysicalPageNumber, bool)> {
+        if virtual_address >= USER_ADDRESS_LIMIT {
+            return None;
+        }
+
+        let entry = self.leaf_entry_mut(virtual_address)?;
+
+        Some((
+            entry.get_ppn(),
+            entry.get_software_bits() == SOFTWARE_SHARED,
+        ))
+    }
+
+    /// Returns the root page table for changing mappings.
+    fn root_page_table(&mut self) -> &mut PageTable {
+        unsafe { &mut *page_table_pointer(self.root_page_table_ppn) }
+    }
+
+    /// Flushes the translations of a range of user addresses whose mappings
+    /// changed.
+    ///
+    /// Translations tagged with the ASID stay cached on every hart the
+    /// address space ran on, so they are flushed on every hart. An address
+    /// space without an ASID flushes the TLB whenever it is installed, so
+    /// only the calling hart needs the flush.
+    fn flush(&self, start_address: usize, length: usize) {
+        match self.asid {
+            0 => tlb::flush_local(start_address, length, None),
+            asid => tlb::shootdown(start_address..start_address + length, Some(asid)),
+        }
+    }
+
+    /// Returns the leaf entry that maps a user address, or `None` if the
+    /// address is not mapped.
+    fn leaf_entry_mut(&mut self, virtual_address: usize) -> Option<&mut PageTableEntry> {
+        let mut page_table = self.root_page_table();
+
+        for level in (0..=paging_mode().root_level()).rev() {
+            let entry_size = PAGE_SIZE << (9 * level);
+            let index = (virtual_address / entry_size) % PAGE_TABLE_ENTRY_COUNT;
+            let entry = page_table.get_entry_mut(index);
+
+            if !entry.is_valid() {
+                return None;
+            }
+
+            if level == 0 || entry.is_leaf() {
+                return Some(entry);
+            }
+
+            page_table = unsafe { &mut *page_table_pointer(entry.get_ppn()) };
+        }
+
+        None
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        destroy_page_table(self.root_page_table_ppn, true);
+
+        // Translations tagged with the ASID are flushed when it is recycled.
+        asid::free(self.asid);
+    }
+}
+
+/// Creates a root page table with the same mappings as another.
+///
+/// The non-global user mappings are copied deeply: every page table below
+/// them is copied into a new one, and every page they map gets another
+/// reference and is shared with the copy. Writable pages are made read only
+/// and copy-on-write in both, except for the pages of shared memory objects.
+/// The kernel half and global entries are copied as they are, so the page
+/// tables below them stay shared.
+///
+/// # Arguments
+///
+/// * `source_root_ppn` - The root page table to copy. Its writable user
+///   pages become read only, so the caller must flush their translations
+///   whether or not the copy succeeds.
+///
+/// # Returns
+///
+/// The root page table of the copy, or `MapError::OutOfMemory` if the frame
+/// pool runs out, in which case everything the copy allocated or referenced
+/// so far has been freed again.
+pub fn clone_page_table(
+    source_root_ppn: PhysicalPageNumber,
+) -> Result<PhysicalPageNumber, MapError> {
+    let destination_root_ppn = frame_pool::allocate_page_table().ok_or(MapError::OutOfMemory)?;
+
+    let source_root = unsafe { &*page_table_pointer(source_root_ppn) };
+    let destination_root = unsafe { &mut *page_table_pointer(destination_root_ppn) };
+
+    for index in KERNEL_HALF_FIRST_ROOT_ENTRY..PAGE_TABLE_ENTRY_COUNT {
+        destination_root.set_entry(index, *source_root.get_entry(index));
+    }
+
+    // Every page table of the copy is linked in as soon as it is allocated
+    // and every shared page is referenced as soon as it is mapped, so
+    // destroying a partial copy frees it.
+    let result = clone_page_table_entries(
+        source_root_ppn,
+        destination_root_ppn,
+        0..KERNEL_HALF_FIRST_ROOT_ENTRY,
+        paging_mode().root_level(),
+    );
+
+    if result.is_err() {
+        destroy_page_table(destination_root_ppn, true);
+    }
+
+    result.map(|()| destination_root_ppn)
+}
+
+/// Frees a root page table and every page table below its non-global user
+/// entries.
+///
+/// The page tables below the kernel half and global entries are shared with
+/// other roots and are left alone, as are the pages they map.
+///
+/// # Arguments
+///
+/// * `root_ppn` - The root page table, which must not be active on any hart.
+/// * `free_leaf_frames` - Whether to also free a reference to every page the
+///   non-global user entries map, for roots whose pages came from the frame
+///   pool. Roots that map memory they do not own, like MMIO, pass false.
+pub fn destroy_page_table(root_ppn: PhysicalPageNumber, free_leaf_frames: bool) {
+    destroy_page_table_entries(
+        root_ppn,
+        0..KERNEL_HALF_FIRST_ROOT_ENTRY,
+        paging_mode().root_level(),
+        free_leaf_frames,
+    );
+
+    frame_pool::free_frame(root_ppn);
+}
+
+/// Copies page table entries into another page table of the same level for
+/// `clone_page_table`. Global entries are copied as they are, without
+/// referencing their page or copying their page table, since
+/// `destroy_page_table` leaves them alone.
+///
+/// # Arguments
+///
+/// * `source_ppn` - The page table to copy from.
+/// * `destination_ppn` - The page table to copy into. Its entries in
+///   `indices` must be invalid.
+/// * `indices` - The entries to copy.
+/// * `level` - The level of both page tables, where level 0 tables map pages.
+fn clone_page_table_entries(
+    source_ppn: PhysicalPageNumber,
+    destination_ppn: PhysicalPageNumber,
+    indices: Range<usize>,
+    level: usize,
+) -> Result<(), MapError> {
+    let source = unsafe { &mut *page_table_pointer(source_ppn) };
+    let destination = unsafe { &mut *page_table_pointer(destination_ppn) };
+
+    for index in indices {
+        let entry = source.get_entry_mut(index);
+
+        if !entry.is_valid() {
+            continue;
+        }
+
+        if entry.is_global() {
+            destination.set_entry(index, *entry);
+            continue;
+        }
+
+        // User address spaces only contain 4KiB pages, whose leaf entries are
+        // at level 0.
+        if level == 0 || entry.is_leaf() {
+            if entry.is_writable() && entry.get_software_bits() != SOFTWARE_SHARED {
+                entry.set_writable(false);
+                entry.set_software_bits(SOFTWARE_COPY_ON_WRITE);
+            }
+
+            frame_pool::add_frame_reference(entry.get_ppn());
+            destination.set_entry(index, *entry);
+        } else {
+            let ppn = frame_pool::allocate_page_table().ok_or(MapError::OutOfMemory)?;
+
+            let mut copied_entry = *entry;
+            copied_entry.set_ppn(ppn);
+            destination.set_entry(index, copied_entry);
+
+            clone_page_table_entries(entry.get_ppn(), ppn, 0..PAGE_TABLE_ENTRY_COUNT, level - 1)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Frees the page tables below non-global page table entries for
+/// `destroy_page_table`, without freeing the page table holding them.
+///
+/// # Arguments
+///
+/// * `ppn` - The page table holding the entries.
+/// * `indices` - The entries to free.
+/// * `level` - The level of the page table, where level 0 tables map pages.
+/// * `free_leaf_frames` - Whether to free a reference to every page mapped.
+fn destroy_page_table_entries(
+    ppn: PhysicalPageNumber,
+    indices: Range<usize>,
+    level: usize,
+    free_leaf_frames: bool,
+) {
+    let page_table = unsafe { &*page_table_pointer(ppn) };
+
+    for index in indices {
+        let entry = page_table.get_entry(index);
+
+        // Global entries are shared, so `clone_page_table` copies them
+        // without taking ownership of what they map.
+        if !entry.is_valid() || entry.is_global() {
+            continue;
+        }
+
+        // User address spaces only contain 4KiB pages, whose leaf entries are
+        // at level 0.
+        if level == 0 || entry.is_leaf() {
+            if free_leaf_frames {
+                frame_pool::free_frame(entry.get_ppn());
+            }
+        } else {
+            destroy_page_table_entries(
+                entry.get_ppn(),
+                0..PAGE_TABLE_ENTRY_COUNT,
+                level - 1,
+                free_leaf_frames,
+            );
+
+            frame_pool::free_frame(entry.get_ppn());
+        }
+    }
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+    use boot_lib::memory::mmu::walk;
+    use common_lib::memory::VirtualAddress;
+
+    kernel_test!(
+        fn test_cloned_page_table_is_destroyed_without_leaks() {
+            let allocated_frames = frame_pool::allocated_frame_count();
+
+            let mut address_space = AddressSpace::new().unwrap();
+            address_space
+                .map_user_page(1 << 30, UserPageAccess::ReadWrite)
+                .unwrap();
+            let (page_ppn, _) = address_space.user_frame(1 << 30).unwrap();
+
+            // The root, level 1, and level 0 page tables and the page.
+            assert_eq!(frame_pool::allocated_frame_count(), allocated_frames + 4);
+
+            let copy_ppn = clone_page_table(address_space.root_page_table_ppn()).unwrap();
+
+            assert_eq!(frame_pool::allocated_frame_count(), allocated_frames + 7);
+            assert_eq!(frame_pool::frame_reference_count(page_ppn), 2);
+
+            let root = unsafe { &*page_table_pointer(address_space.root_page_table_ppn()) };
+            let copy = unsafe { &*page_table_pointer(copy_ppn) };
+            assert_eq!(
+                copy.get_entry(KERNEL_HALF_FIRST_ROOT_ENTRY).get_ppn(),
+                root.get_entry(KERNEL_HALF_FIRST_ROOT_ENTRY).get_ppn()
+            );
+
+            destroy_page_table(copy_ppn, true);
+
+            assert_eq!(frame_pool::allocated_frame_count(), allocated_frames + 4);
+            assert_eq!(frame_pool::frame_reference_count(page_ppn), 1);
+
+            drop(address_space);
+            assert_eq!(frame_pool::allocated_frame_count(), allocated_frames);
+        }
+    );
+
+    kernel_test!(
+        fn test_cloned_page_table_keeps_global_mappings() {
+            let allocated_frames = frame_pool::allocated_frame_count();
+
+            let mut address_space = AddressSpace::new().unwrap();
+            let page_ppn = frame_pool::allocate_frame().unwrap();
+            let virtual_address = 1 << 30;
+
+            let flags = PageTableEntryFlags {
+                readable: true,
+                writable: false,
+                executable: false,
+                user: false,
+                global: true,
+                memory_type: MemoryType::Pma,
+            };
+
+            allocate_vpn(
+                address_space.root_page_table(),
+                VirtualPageNumber::from_virtual_address(virtual_address),
+                Some(page_ppn),
+                &flags,
+                &mut FramePoolAllocator,
+            )
+            .unwrap();
+
+            let copy_ppn = clone_page_table(address_space.root_page_table_ppn()).unwrap();
+
+            // The global page is mapped in the copy without another reference.
+            let copy = unsafe { &*page_table_pointer(copy_ppn) };
+            let translation = walk(copy, VirtualAddress::new(virtual_address)).unwrap();
+
+            assert_eq!(
+                translation.physical_address.raw_address(),
+                page_ppn.to_physical_address()
+            );
+            assert!(translation.flags.global);
+            assert_eq!(frame_pool::frame_reference_count(page_ppn), 1);
+
+            destroy_page_table(copy_ppn, true);
+            drop(address_space);
+
+            assert_eq!(frame_pool::frame_reference_count(page_ppn), 1);
+
+            frame_pool::free_frame(page_ppn);
+            assert_eq!(frame_pool::allocated_frame_count(), allocated_frames);
+        }
+    );
+}
@@ -0,0 +1,103 @@
+//! Address space identifiers for user address spaces.
+//!
+//! Every user address space takes an ASID for its lifetime and installs it in
+//! satp along with its root page table, so the translations of different
+//! address spaces never mix in a hart's TLB and switching between them needs
+//! no flush. The kernel's own address space keeps ASID 0, which also goes to
+//! any user address space created while every other ASID is taken. Such an
+//! untagged address space flushes the TLB whenever it is installed.
+//!
+//! An ASID may still have translations cached on any hart after its address
+//! space is dropped, so an ASID that is handed out again is flushed on every
+//! hart before its new owner can run.
+
+use super::{USER_ADDRESS_LIMIT, read_satp};
+use crate::{debug_println, tlb};
+use kernel_lib::{asid::AsidAllocator, sync::SpinLock};
+
+/// The position of the ASID field in satp.
+const SATP_ASID_SHIFT: usize = 44;
+
+/// The ASID field of satp, which is 16 bits wide on RV64. A hart may
+/// implement fewer bits, in which case the rest read as zero.
+const SATP_ASID_MASK: usize = 0xffff << SATP_ASID_SHIFT;
+
+/// The number of 64-bit words the allocator tracks ASIDs in, which caps the
+/// kernel at 256 ASIDs however many the hardware supports.
+const ASID_BITMAP_WORDS: usize = 4;
+
+static ALLOCATOR: SpinLock<AsidAllocator<ASID_BITMAP_WORDS>> = SpinLock::new(AsidAllocator::new());
+
+/// Finds how many ASIDs the calling hart supports. Every hart is assumed to
+/// support the same number. Must be called while the kernel's own address
+/// space is installed, before any user address space is created.
+pub(super) fn initialize() {
+    let satp = read_satp();
+    let probed_satp: usize;
+
+    // Only the ASID bits the hart implements keep the ones written to them.
+    // The root page table stays the same, so no translation changes.
+    unsafe {
+        core::arch::asm!(
+            "csrw satp, {probe}",
+            "csrr {probed}, satp",
+            "csrw satp, {satp}",
+            probe = in(reg) satp | SATP_ASID_MASK,
+            probed = out(reg) probed_satp,
+            satp = in(reg) satp,
+            options(nostack)
+        );
+    }
+
+    let asid_bits = (probed_satp & SATP_ASID_MASK).count_ones();
+
+    let mut allocator = ALLOCATOR.lock();
+    allocator.set_asid_count(1 << asid_bits);
+
+    debug_println!(
+        "{} ASID bits, {} ASIDs for user address spaces.",
+        asid_bits,
+        allocator.capacity()
+    );
+}
+
+/// Takes an ASID for a new user address space, flushing it on every hart if
+/// it was used before.
+///
+/// # Returns
+///
+/// The ASID, or 0 if every ASID is taken.
+pub(super) fn allocate() -> usize {
+    let Some(asid) = ALLOCATOR.lock().allocate() else {
+        return 0;
+    };
+
+    // The kernel is mapped the same way in every address space, so only the
+    // user half can hold stale translations.
+    if asid.is_recycled {
+        tlb::shootdown(0..USER_ADDRESS_LIMIT, Some(asid.asid));
+    }
+
+    asid.asid
+}
+
+/// Returns the ASID of a dropped user address space.
+///
+/// # Arguments
+///
+/// * `asid` - The ASID `allocate` returned, which is ignored if it is 0.
+pub(super) fn free(asid: usize) {
+    if asid != 0 {
+        ALLOCATOR.lock().free(asid);
+    }
+}
+
+/// Returns the satp value with an ASID in its ASID field.
+pub(super) fn with_asid(satp: usize, asid: usize) -> usize {
+    (satp & !SATP_ASID_MASK) | (asid << SATP_ASID_SHIFT)
+}
+
+/// Returns the ASID in a satp value.
+pub(super) fn asid_from_satp(satp: usize) -> usize {
+    (satp & SATP_ASID_MASK) >> SATP_ASID_SHIFT
+}
@@ -0,0 +1,140 @@
+//! Removal of the mappings the boot stage made for itself.
+//!
+//! The boot stage identity maps its own sections, executable and writable
+//! where they need to be, so that it keeps running once it enables paging,
+//! and the kernel starts on the same root page table. The boot hart leaves
+//! the boot stack in `_kernel_entrypoint`, after which nothing uses the lower
+//! half of the kernel's address space. `remove_boot_identity_mapping` clears
+//! its root entries and hands the boot image and the page tables behind the
+//! entries back as free memory.
+
+use super::{
+    PAGE_SIZE,
+    frames::{FrameOwner, frame_for},
+    kernel_root_page_table_ppn,
+};
+use crate::debug_println;
+use boot_lib::memory::{
+    memory_map::MemoryMap,
+    mmu::{PageTable, page_table_pointer, paging_mode},
+};
+use common_lib::{
+    boot_info::BootInfo,
+    memory::{PhysicalAddress, PhysicalPageNumber},
+};
+
+/// The number of entries in every page table.
+const PAGE_TABLE_ENTRY_COUNT: usize = 512;
+
+/// The first root page table entry of the upper, kernel half of the address
+/// space. Every entry below it belongs to the boot stage until it is removed.
+const KERNEL_HALF_FIRST_ROOT_ENTRY: usize = PAGE_TABLE_ENTRY_COUNT / 2;
+
+/// Removes the lower half of the kernel's address space, which only holds the
+/// boot stage's identity mapping. Called by `memory::initialize` on the boot
+/// hart before any other hart starts, so only the local TLB is flushed.
+///
+/// # Arguments
+///
+/// * `boot_info` - The information the boot stage handed over, which locates
+///   the boot image.
+///
+/// # Returns
+///
+/// The memory that is free now: the boot image and the page tables of the
+/// identity mapping.
+pub(super) fn remove_boot_identity_mapping(boot_info: &BootInfo) -> MemoryMap {
+    let mut reclaimed_memory = MemoryMap::new();
+
+    let kernel_root_page_table = unsafe { &mut *page_table_pointer(kernel_root_page_table_ppn()) };
+
+    for index in 0..KERNEL_HALF_FIRST_ROOT_ENTRY {
+        let entry = kernel_root_page_table.get_entry_mut(index);
+
+        if !entry.is_valid() {
+            continue;
+        }
+
+        if !entry.is_leaf() {
+            reclaim_page_table_level(
+                entry.get_ppn(),
+                paging_mode().root_level() - 1,
+                &mut reclaimed_memory,
+            );
+        }
+
+        entry.clear();
+    }
+
+    // Secondary harts are not running yet, so no other TLB can hold the
+    // translations.
+    unsafe {
+        core::arch::asm!("sfence.vma", options(nostack));
+    }
+
+    reclaim_range(
+        boot_info.boot_physical_base,
+        boot_info.boot_size,
+        &mut reclaimed_memory,
+    );
+
+    debug_println!(
+        "Removed the boot identity mapping and reclaimed {} KiB.",
+        reclaimed_memory.total_size() / 1024
+    );
+
+    reclaimed_memory
+}
+
+/// Reclaims a page table the boot stage allocated and every lower level page
+/// table it points to. The pages its leaf entries map are not reclaimed.
+///
+/// # Arguments
+///
+/// * `ppn` - The page table.
+/// * `level` - The level of the page table, where level 0 tables map pages.
+/// * `reclaimed_memory` - The memory map to add the page tables to.
+fn reclaim_page_table_level(
+    ppn: PhysicalPageNumber,
+    level: usize,
+    reclaimed_memory: &mut MemoryMap,
+) {
+    if level > 0 {
+        let page_table: &PageTable = unsafe { &*page_table_pointer(ppn) };
+
+        for entry in page_table.get_entries() {
+            if entry.is_valid() && !entry.is_leaf() {
+                reclaim_page_table_level(entry.get_ppn(), level - 1, reclaimed_memory);
+            }
+        }
+    }
+
+    reclaim_range(PhysicalAddress::from(ppn), PAGE_SIZE, reclaimed_memory);
+}
+
+/// Adds a range of physical memory to the reclaimed memory and marks its
+/// frames free.
+///
+/// # Arguments
+///
+/// * `start_address` - The page aligned first address of the range.
+/// * `size` - The number of bytes of the range.
+/// * `reclaimed_memory` - The memory map to add the range to.
+fn reclaim_range(start_address: PhysicalAddress, size: usize, reclaimed_memory: &mut MemoryMap) {
+    if let Err(error) = reclaimed_memory.add_region(start_address, size) {
+        debug_println!(
+            "Not reclaiming {:#x}-{:#x}: {}.",
+            start_address,
+            start_address + size,
+            error
+        );
+
+        return;
+    }
+
+    for offset in (0..size).step_by(PAGE_SIZE) {
+        if let Some(frame) = frame_for((start_address + offset).ppn()) {
+            frame.set_owner(FrameOwner::Unknown);
+        }
+    }
+}
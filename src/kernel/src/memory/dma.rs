@@ -0,0 +1,114 @@
+//! Buffers shared with devices by their physical address.
+//!
+//! A `DmaBuffer` is made of physically contiguous frames from the frame pool,
+//! so a device can be handed a single physical address for all of it. The
+//! frames are reached through the direct physical memory mapping, stay
+//! allocated, and so are never handed out again, until the buffer is dropped.
+//! The harts and devices of the supported platforms are cache coherent, so
+//! the buffer needs no cache maintenance.
+
+use super::{
+    PAGE_SIZE,
+    frame_pool::{allocate_contiguous_frames, free_frame},
+    frames::{Frame, FrameFlags, frame_for},
+    physical_to_virtual,
+};
+use common_lib::memory::PhysicalPageNumber;
+
+/// Zeroed memory a device accesses by its physical address.
+pub struct DmaBuffer {
+    /// The first frame of the buffer.
+    first_ppn: PhysicalPageNumber,
+
+    /// The number of frames of the buffer.
+    frame_count: usize,
+
+    /// The virtual address of the buffer in the direct physical memory
+    /// mapping.
+    virtual_address: usize,
+}
+
+// The buffer owns its frames, which are only reached through it and the
+// device.
+unsafe impl Send for DmaBuffer {}
+
+impl DmaBuffer {
+    /// Allocates a zeroed buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - The number of bytes of the buffer, which is rounded up to
+    ///   whole pages.
+    /// * `alignment` - The physical alignment of the buffer in bytes. Must be
+    ///   a power of two. Alignments below a page are rounded up to a page.
+    ///
+    /// # Returns
+    ///
+    /// The buffer, or `None` if `length` is zero or the frame pool has no
+    /// physically contiguous run of free frames that large.
+    pub fn new(length: usize, alignment: usize) -> Option<Self> {
+        let frame_count = length.div_ceil(PAGE_SIZE);
+        let first_ppn = allocate_contiguous_frames(frame_count, alignment)?;
+
+        for frame in frames_of(first_ppn, frame_count) {
+            frame.insert_flags(FrameFlags::DMA);
+        }
+
+        let virtual_address = physical_to_virtual(first_ppn.to_physical_address())
+            .expect("Frames are covered by the direct mapping.");
+
+        Some(Self {
+            first_ppn,
+            frame_count,
+            virtual_address,
+        })
+    }
+
+    /// Returns the physical address of the buffer, which the device uses.
+    pub fn physical_address(&self) -> usize {
+        self.first_ppn.to_physical_address()
+    }
+
+    /// Returns the physical page number of the first frame of the buffer.
+    pub fn ppn(&self) -> PhysicalPageNumber {
+        self.first_ppn
+    }
+
+    /// Returns the virtual address of the buffer, which the kernel uses. The
+    /// device may write the buffer at any time, so its contents are accessed
+    /// with volatile reads and writes.
+    pub fn virtual_address(&self) -> usize {
+        self.virtual_address
+    }
+
+    /// Returns the number of bytes of the buffer, a multiple of the page
+    /// size.
+    #[allow(dead_code)]
+    pub fn size(&self) -> usize {
+        self.frame_count * PAGE_SIZE
+    }
+}
+
+impl Drop for DmaBuffer {
+    /// Frees the frames of the buffer. The device must no longer use them.
+    fn drop(&mut self) {
+        for frame in frames_of(self.first_ppn, self.frame_count) {
+            frame.remove_flags(FrameFlags::DMA);
+        }
+
+        for offset in 0..self.frame_count {
+            free_frame(PhysicalPageNumber::from_raw_physical_page_number(
+                self.first_ppn.raw_ppn() + offset,
+            ));
+        }
+    }
+}
+
+/// Returns the metadata of a run of frames of the frame pool.
+fn frames_of(
+    first_ppn: PhysicalPageNumber,
+    frame_count: usize,
+) -> impl Iterator<Item = &'static Frame> {
+    (first_ppn.raw_ppn()..first_ppn.raw_ppn() + frame_count)
+        .filter_map(|raw_ppn| frame_for(PhysicalPageNumber::from_raw_physical_page_number(raw_ppn)))
+}
@@ -0,0 +1,156 @@
+//! Debugging aids for the frame pool, enabled by the `frame-debug` feature.
+//!
+//! The kernel has no heap, so the frame pool is where drivers and address
+//! spaces get memory they free again, and where their use-after-free bugs and
+//! leaks show up. With the feature enabled:
+//!
+//! * A frame is filled with `POISON_BYTE` when its last reference is freed,
+//!   and the poison is checked when the frame is allocated again. A frame
+//!   that was written after it was freed panics with where it was allocated
+//!   and freed.
+//! * Every allocation and final free records the return address of the call
+//!   into the pool, which the monitor's `frames` command prints next to every
+//!   outstanding frame.
+//!
+//! Call sites are found by following the frame pointer, so the functions that
+//! record them are never inlined while the feature is enabled.
+
+use super::{PAGE_SIZE, frame_pool::FRAME_POOL_SIZE};
+use crate::symbols::Symbolized;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The byte freed frames are filled with. Its pattern is unlikely to be a
+/// valid pointer or a common integer.
+const POISON_BYTE: u8 = 0xa5;
+
+/// The return address of the call that last allocated every frame of the
+/// pool, or zero if it was never allocated.
+static ALLOCATION_SITES: [AtomicUsize; FRAME_POOL_SIZE] =
+    [const { AtomicUsize::new(0) }; FRAME_POOL_SIZE];
+
+/// The return address of the call that freed the last reference to every
+/// frame of the pool, or zero if it was never freed.
+static FREE_SITES: [AtomicUsize; FRAME_POOL_SIZE] =
+    [const { AtomicUsize::new(0) }; FRAME_POOL_SIZE];
+
+/// A bit for every frame that has been poisoned since it was last allocated,
+/// indexed like the pool.
+static POISONED_FRAMES: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the return address of the function this is inlined into.
+///
+/// The calling function must be marked `#[inline(never)]` so that it has a
+/// frame of its own, and the kernel must be built with frame pointers.
+#[inline(always)]
+pub(super) fn return_address() -> usize {
+    let return_address: usize;
+
+    // The return address is saved directly below the frame pointer.
+    unsafe {
+        core::arch::asm!(
+            "ld {}, -8(s0)",
+            out(reg) return_address,
+            options(readonly, nostack)
+        );
+    }
+
+    return_address
+}
+
+/// Records where a frame was allocated.
+///
+/// # Arguments
+///
+/// * `index` - The index of the frame in the pool.
+/// * `call_site` - The return address of the call that allocated it.
+pub(super) fn record_allocation(index: usize, call_site: usize) {
+    ALLOCATION_SITES[index].store(call_site, Ordering::Relaxed);
+}
+
+/// Returns where a frame was last allocated.
+///
+/// # Arguments
+///
+/// * `index` - The index of the frame in the pool.
+///
+/// # Returns
+///
+/// The return address of the call that allocated the frame, or `None` if it
+/// is not known.
+pub(super) fn allocation_site(index: usize) -> Option<usize> {
+    match ALLOCATION_SITES[index].load(Ordering::Relaxed) {
+        0 => None,
+        call_site => Some(call_site),
+    }
+}
+
+/// Fills a frame whose last reference was just freed with the poison and
+/// records where it was freed. Must be called before the frame is returned
+/// to the pool.
+///
+/// # Arguments
+///
+/// * `index` - The index of the frame in the pool.
+/// * `frame` - The contents of the frame.
+/// * `call_site` - The return address of the call that freed it.
+pub(super) fn poison(index: usize, frame: &mut [u8; PAGE_SIZE], call_site: usize) {
+    frame.fill(POISON_BYTE);
+
+    FREE_SITES[index].store(call_site, Ordering::Relaxed);
+    POISONED_FRAMES.fetch_or(1 << index, Ordering::Relaxed);
+}
+
+/// Checks that a frame taken from the pool still holds the poison it was
+/// filled with when it was freed. Must be called before the frame is zeroed.
+///
+/// # Arguments
+///
+/// * `index` - The index of the frame in the pool.
+/// * `frame` - The contents of the frame.
+/// * `physical_address` - The physical address of the frame, for the report.
+///
+/// # Panics
+///
+/// If the frame was written after it was freed.
+pub(super) fn check_poison(index: usize, frame: &[u8; PAGE_SIZE], physical_address: usize) {
+    if POISONED_FRAMES.fetch_and(!(1 << index), Ordering::Relaxed) & (1 << index) == 0 {
+        return;
+    }
+
+    let Some(offset) = frame.iter().position(|&byte| byte != POISON_BYTE) else {
+        return;
+    };
+
+    panic!(
+        "Frame {:#x} was written at offset {:#x} after it was freed. It was allocated by {} and freed by {}.",
+        physical_address,
+        offset,
+        Symbolized(ALLOCATION_SITES[index].load(Ordering::Relaxed)),
+        Symbolized(FREE_SITES[index].load(Ordering::Relaxed))
+    );
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::{
+        super::{
+            frame_pool::{allocate_frame, free_frame},
+            physical_to_virtual,
+        },
+        *,
+    };
+    use crate::kernel_test;
+
+    kernel_test!(
+        fn test_freed_frame_is_poisoned() {
+            let ppn = allocate_frame().unwrap();
+            let address = physical_to_virtual(ppn.to_physical_address()).unwrap();
+            let frame = address as *const [u8; PAGE_SIZE];
+
+            assert!(unsafe { &*frame }.iter().all(|&byte| byte == 0));
+
+            free_frame(ppn);
+            assert!(unsafe { &*frame }.iter().all(|&byte| byte == POISON_BYTE));
+        }
+    );
+}
@@ -0,0 +1,467 @@
+//! A small pool of physical frames inside the kernel image.
+//!
+//! The kernel's physical allocator never takes frames back, so memory that is
+//! freed again, such as the page tables and pages of user address spaces and
+//! the rings of virtqueues, comes from a fixed pool of page aligned frames in
+//! .bss.
+//!
+//! Every frame has a reference count in its `Frame` metadata, so address
+//! spaces can share pages copy-on-write. A frame returns to the pool when its
+//! last reference is freed.
+//!
+//! With the `frame-debug` feature, freed frames are poisoned and allocations
+//! remember their call sites, as described in `frame_debug`.
+
+#[cfg(feature = "frame-debug")]
+use super::frame_debug;
+use super::{
+    PAGE_SIZE,
+    frames::{Frame as FrameMetadata, FrameFlags, FrameOwner, frame_for},
+    virtual_to_physical,
+};
+use crate::trace_event;
+use boot_lib::memory::physical_memory_allocator::PhysicalMemoryAllocator;
+use common_lib::memory::{MemoryRegion, PhysicalAddress, PhysicalPageNumber};
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// The number of frames in the pool. The allocation bitmap is a single word.
+pub(super) const FRAME_POOL_SIZE: usize = 64;
+
+/// A single page aligned frame.
+#[repr(C, align(4096))]
+struct Frame(UnsafeCell<[u8; PAGE_SIZE]>);
+
+// Each frame is owned by whoever allocated it.
+unsafe impl Sync for Frame {}
+
+/// The frames of the pool.
+static FRAMES: [Frame; FRAME_POOL_SIZE] =
+    [const { Frame(UnsafeCell::new([0; PAGE_SIZE])) }; FRAME_POOL_SIZE];
+
+/// A bit for every allocated frame, indexed like `FRAMES`.
+static ALLOCATED_FRAMES: AtomicU64 = AtomicU64::new(0);
+
+/// An allocated frame of the pool, as reported by `for_each_allocated_frame`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AllocatedFrame {
+    pub ppn: PhysicalPageNumber,
+    pub reference_count: usize,
+    pub is_page_table: bool,
+
+    /// The return address of the call that allocated the frame, which is
+    /// only known with the `frame-debug` feature.
+    pub allocation_site: Option<usize>,
+}
+
+/// Allocates a zeroed frame with a single reference.
+///
+/// # Returns
+///
+/// The physical page number of the frame, or `None` if the pool is empty.
+#[cfg_attr(feature = "frame-debug", inline(never))]
+pub fn allocate_frame() -> Option<PhysicalPageNumber> {
+    let allocated_frames = ALLOCATED_FRAMES
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |allocated_frames| {
+            (allocated_frames != u64::MAX).then(|| allocated_frames | (allocated_frames + 1))
+        })
+        .ok()?;
+
+    // The lowest clear bit is the one that was just set.
+    let index = allocated_frames.trailing_ones() as usize;
+    let ppn = initialize_frame(index);
+
+    #[cfg(feature = "frame-debug")]
+    record_allocation_site(ppn, 1, frame_debug::return_address());
+
+    trace_event!("frames", "allocated frame {:#x}", ppn.raw_ppn());
+
+    Some(ppn)
+}
+
+/// Allocates physically contiguous zeroed frames whose first frame is
+/// aligned. Each frame has a single reference and is freed on its own.
+///
+/// # Arguments
+///
+/// * `frame_count` - The number of frames to allocate.
+/// * `alignment` - The physical alignment of the first frame in bytes. Must be
+///   a power of two. Alignments below a page are rounded up to a page.
+///
+/// # Returns
+///
+/// The physical page number of the first frame, or `None` if `frame_count`
+/// is zero, `alignment` is not a power of two, or the pool has no run of
+/// free frames that are contiguous in physical memory.
+#[cfg_attr(feature = "frame-debug", inline(never))]
+pub fn allocate_contiguous_frames(
+    frame_count: usize,
+    alignment: usize,
+) -> Option<PhysicalPageNumber> {
+    let first_ppn = allocate_contiguous_frames_in_range(
+        frame_count,
+        alignment,
+        PhysicalAddress::new(0),
+        PhysicalAddress::new(usize::MAX),
+    )?;
+
+    #[cfg(feature = "frame-debug")]
+    record_allocation_site(first_ppn, frame_count, frame_debug::return_address());
+
+    Some(first_ppn)
+}
+
+/// Allocates physically contiguous zeroed frames whose first frame is
+/// aligned and that all lie within a range of physical addresses.
+///
+/// # Arguments
+///
+/// * `frame_count` - The number of frames to allocate.
+/// * `alignment` - The physical alignment of the first frame in bytes. Must be
+///   a power of two. Alignments below a page are rounded up to a page.
+/// * `start_address` - The lowest physical address the frames may use.
+/// * `end_address` - The physical address the frames must end at or before.
+///
+/// # Returns
+///
+/// The physical page number of the first frame, or `None` if no run of free
+/// frames fits.
+fn allocate_contiguous_frames_in_range(
+    frame_count: usize,
+    alignment: usize,
+    start_address: PhysicalAddress,
+    end_address: PhysicalAddress,
+) -> Option<PhysicalPageNumber> {
+    if frame_count == 0 || frame_count > FRAME_POOL_SIZE || !alignment.is_power_of_two() {
+        return None;
+    }
+
+    let alignment = alignment.max(PAGE_SIZE);
+    let run_mask = u64::MAX >> (FRAME_POOL_SIZE - frame_count);
+
+    // The highest physical address the first frame may have.
+    let last_start_address = end_address
+        .raw_address()
+        .checked_sub(frame_count * PAGE_SIZE)?;
+
+    let mut allocated_frames = ALLOCATED_FRAMES.load(Ordering::Acquire);
+
+    let first_index = loop {
+        let first_index = (0..=FRAME_POOL_SIZE - frame_count).find(|&first_index| {
+            allocated_frames & (run_mask << first_index) == 0
+                && is_contiguous_run(first_index, frame_count, alignment)
+                && (start_address.raw_address()..=last_start_address)
+                    .contains(&frame_physical_address(first_index))
+        })?;
+
+        match ALLOCATED_FRAMES.compare_exchange_weak(
+            allocated_frames,
+            allocated_frames | (run_mask << first_index),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => break first_index,
+            Err(current_allocated_frames) => allocated_frames = current_allocated_frames,
+        }
+    };
+
+    let first_ppn = initialize_frame(first_index);
+
+    for index in first_index + 1..first_index + frame_count {
+        initialize_frame(index);
+    }
+
+    Some(first_ppn)
+}
+
+/// Allocates a zeroed frame for a page table, flagged as one in its metadata.
+///
+/// # Returns
+///
+/// The physical page number of the frame, or `None` if the pool is empty.
+#[cfg_attr(feature = "frame-debug", inline(never))]
+pub(super) fn allocate_page_table() -> Option<PhysicalPageNumber> {
+    let ppn = allocate_frame()?;
+    frame_metadata(ppn).insert_flags(FrameFlags::PAGE_TABLE);
+
+    #[cfg(feature = "frame-debug")]
+    record_allocation_site(ppn, 1, frame_debug::return_address());
+
+    Some(ppn)
+}
+
+/// Frees a reference to a frame, returning the frame to the pool if it was
+/// the last one.
+///
+/// # Arguments
+///
+/// * `ppn` - A frame returned by `allocate_frame` that the caller no longer
+///   maps or otherwise uses.
+///
+/// # Panics
+///
+/// If the frame is not an allocated frame of the pool.
+#[cfg_attr(feature = "frame-debug", inline(never))]
+pub fn free_frame(ppn: PhysicalPageNumber) {
+    let index = frame_index(ppn);
+    let metadata = frame_metadata(ppn);
+
+    let reference_count = metadata
+        .drop_reference()
+        .unwrap_or_else(|| panic!("Frame {:#x} was freed twice.", ppn.raw_ppn()));
+
+    if reference_count > 1 {
+        return;
+    }
+
+    // The frame is part of the kernel image again until it is reallocated.
+    metadata.set_owner(FrameOwner::Kernel);
+    metadata.remove_flags(FrameFlags::PAGE_TABLE);
+
+    // The frame is poisoned while it is still allocated, so no other hart
+    // can take it first.
+    #[cfg(feature = "frame-debug")]
+    frame_debug::poison(
+        index,
+        unsafe { &mut *FRAMES[index].0.get() },
+        frame_debug::return_address(),
+    );
+
+    let allocated_frames = ALLOCATED_FRAMES.fetch_and(!(1 << index), Ordering::AcqRel);
+
+    if allocated_frames & (1 << index) == 0 {
+        panic!("Frame {:#x} was freed twice.", ppn.raw_ppn());
+    }
+
+    trace_event!("frames", "freed frame {:#x}", ppn.raw_ppn());
+}
+
+/// Adds a reference to an allocated frame, which must then be freed once
+/// more before it returns to the pool.
+///
+/// # Arguments
+///
+/// * `ppn` - A frame returned by `allocate_frame` that is still allocated.
+///
+/// # Panics
+///
+/// If the frame is not an allocated frame of the pool.
+pub fn add_frame_reference(ppn: PhysicalPageNumber) {
+    let previous_count = frame_metadata(ppn).add_reference();
+
+    assert!(
+        previous_count != 0,
+        "Frame {:#x} is not allocated.",
+        ppn.raw_ppn()
+    );
+}
+
+/// Returns the number of references to a frame, which is zero if it is free.
+///
+/// # Panics
+///
+/// If the frame is not in the pool.
+pub fn frame_reference_count(ppn: PhysicalPageNumber) -> usize {
+    frame_metadata(ppn).reference_count()
+}
+
+/// Returns the number of frames that are allocated.
+pub fn allocated_frame_count() -> usize {
+    ALLOCATED_FRAMES.load(Ordering::Relaxed).count_ones() as usize
+}
+
+/// Calls a function for every allocated frame of the pool, in the order of
+/// the pool. Frames allocated or freed during the walk may be missed.
+pub fn for_each_allocated_frame(mut callback: impl FnMut(&AllocatedFrame)) {
+    let allocated_frames = ALLOCATED_FRAMES.load(Ordering::Acquire);
+
+    for index in (0..FRAME_POOL_SIZE).filter(|index| allocated_frames & (1 << index) != 0) {
+        let ppn = PhysicalPageNumber::from_physical_address(frame_physical_address(index));
+        let metadata = frame_metadata(ppn);
+
+        #[cfg(feature = "frame-debug")]
+        let allocation_site = frame_debug::allocation_site(index);
+        #[cfg(not(feature = "frame-debug"))]
+        let allocation_site = None;
+
+        callback(&AllocatedFrame {
+            ppn,
+            reference_count: metadata.reference_count(),
+            is_page_table: metadata.flags().contains(FrameFlags::PAGE_TABLE),
+            allocation_site,
+        });
+    }
+}
+
+/// Zeroes a frame that was just marked allocated and gives it a single
+/// reference.
+///
+/// # Returns
+///
+/// The physical page number of the frame.
+fn initialize_frame(index: usize) -> PhysicalPageNumber {
+    let frame = &FRAMES[index];
+
+    #[cfg(feature = "frame-debug")]
+    frame_debug::check_poison(
+        index,
+        unsafe { &*frame.0.get() },
+        frame_physical_address(index),
+    );
+
+    unsafe {
+        (*frame.0.get()).fill(0);
+    }
+
+    let ppn = PhysicalPageNumber::from_physical_address(frame_physical_address(index));
+
+    let metadata = frame_metadata(ppn);
+    metadata.set_reference_count(1);
+    metadata.set_owner(FrameOwner::FramePool);
+
+    ppn
+}
+
+/// Records where a run of frames that was just allocated was allocated.
+///
+/// # Arguments
+///
+/// * `first_ppn` - The first frame of the run.
+/// * `frame_count` - The number of frames in the run.
+/// * `call_site` - The return address of the call that allocated them.
+#[cfg(feature = "frame-debug")]
+fn record_allocation_site(first_ppn: PhysicalPageNumber, frame_count: usize, call_site: usize) {
+    let first_index = frame_index(first_ppn);
+
+    for index in first_index..first_index + frame_count {
+        frame_debug::record_allocation(index, call_site);
+    }
+}
+
+/// Returns the physical address of a frame of the pool.
+fn frame_physical_address(index: usize) -> usize {
+    virtual_to_physical(FRAMES[index].0.get() as usize)
+        .expect("The kernel image is mapped with 4KiB pages.")
+}
+
+/// Returns true if a run of frames of the pool is contiguous in physical
+/// memory and its first frame is aligned.
+fn is_contiguous_run(first_index: usize, frame_count: usize, alignment: usize) -> bool {
+    let first_physical_address = frame_physical_address(first_index);
+
+    first_physical_address.is_multiple_of(alignment)
+        && (1..frame_count).all(|offset| {
+            frame_physical_address(first_index + offset)
+                == first_physical_address + offset * PAGE_SIZE
+        })
+}
+
+/// Returns the index in `FRAMES` of a frame.
+///
+/// # Panics
+///
+/// If the frame is not in the pool.
+fn frame_index(ppn: PhysicalPageNumber) -> usize {
+    FRAMES
+        .iter()
+        .position(|frame| {
+            virtual_to_physical(frame.0.get() as usize) == Some(ppn.to_physical_address())
+        })
+        .unwrap_or_else(|| panic!("Frame {:#x} is not in the frame pool.", ppn.raw_ppn()))
+}
+
+/// Returns the metadata of a frame of the pool.
+///
+/// # Panics
+///
+/// If the frame is not in the pool, or if it has no metadata, which means the
+/// RAM holding the kernel image is missing from the DTB.
+fn frame_metadata(ppn: PhysicalPageNumber) -> &'static FrameMetadata {
+    frame_index(ppn);
+
+    frame_for(ppn).unwrap_or_else(|| panic!("Frame {:#x} has no metadata.", ppn.raw_ppn()))
+}
+
+/// Lets the shared mmu code allocate page tables and pages from the frame
+/// pool.
+///
+/// Pages are returned by their physical address, as the mmu code expects.
+pub struct FramePoolAllocator;
+
+impl PhysicalMemoryAllocator for FramePoolAllocator {
+    // The kernel always passes the frame of a leaf page to the mmu code, so
+    // the mmu code only allocates page tables.
+    #[cfg_attr(feature = "frame-debug", inline(never))]
+    fn allocate_page(&mut self) -> Option<PhysicalAddress> {
+        let ppn = allocate_page_table()?;
+
+        #[cfg(feature = "frame-debug")]
+        record_allocation_site(ppn, 1, frame_debug::return_address());
+
+        Some(ppn.into())
+    }
+
+    #[cfg_attr(feature = "frame-debug", inline(never))]
+    fn allocate_contiguous_in_range(
+        &mut self,
+        page_count: usize,
+        alignment: usize,
+        start_address: PhysicalAddress,
+        end_address: PhysicalAddress,
+    ) -> Option<PhysicalAddress> {
+        let first_ppn =
+            allocate_contiguous_frames_in_range(page_count, alignment, start_address, end_address)?;
+
+        #[cfg(feature = "frame-debug")]
+        record_allocation_site(first_ppn, page_count, frame_debug::return_address());
+
+        Some(first_ppn.into())
+    }
+
+    fn total_memory_size(&self) -> usize {
+        FRAME_POOL_SIZE * PAGE_SIZE
+    }
+
+    fn allocated_memory_size(&self) -> usize {
+        allocated_frame_count() * PAGE_SIZE
+    }
+
+    // The frames are scattered over the kernel image's physical pages, so
+    // the pool has no regions to report.
+    fn memory_regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        core::iter::empty()
+    }
+
+    fn allocated_regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        core::iter::empty()
+    }
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    kernel_test!(
+        fn test_frame_returns_to_pool_after_last_reference() {
+            let allocated_before = allocated_frame_count();
+            let ppn = allocate_frame().unwrap();
+
+            assert_eq!(allocated_frame_count(), allocated_before + 1);
+            assert_eq!(frame_reference_count(ppn), 1);
+
+            add_frame_reference(ppn);
+            free_frame(ppn);
+
+            assert_eq!(frame_reference_count(ppn), 1);
+            assert_eq!(allocated_frame_count(), allocated_before + 1);
+
+            free_frame(ppn);
+
+            assert_eq!(frame_reference_count(ppn), 0);
+            assert_eq!(allocated_frame_count(), allocated_before);
+        }
+    );
+}
@@ -0,0 +1,419 @@
+//! Metadata for every physical frame of RAM.
+//!
+//! `initialize` finds the RAM in the DTB's memory nodes and gives each of its
+//! 4KiB frames a `Frame` in a fixed array, so `frame_for` finds the metadata
+//! of a frame from its physical page number. The kernel image, the frames
+//! the boot stage allocated, the DTB copy, the initial ramdisk, and the
+//! firmware's reserved memory are marked with their owners, and reserved
+//! frames are flagged so they are never handed out. The rest of RAM is
+//! `FrameOwner::Unknown`, which is free unless the firmware did not report
+//! it.
+//!
+//! The array lives in .bss since there is no heap, so RAM past
+//! `MAX_FRAMES` frames has no metadata.
+
+use super::{PAGE_SIZE, layout, virtual_to_physical};
+use crate::debug_println;
+use common_lib::{
+    boot_info::BootInfo,
+    dtb::{self, Dtb, walk_memory_reservation_entries},
+    memory::PhysicalPageNumber,
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, AtomicU16, AtomicU32, AtomicUsize, Ordering},
+};
+
+/// The most frames with metadata, which covers 256MiB of RAM.
+pub const MAX_FRAMES: usize = 65536;
+
+/// The most separate ranges of RAM with metadata.
+const MAX_RAM_REGIONS: usize = 8;
+
+/// What uses a frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameOwner {
+    /// Free RAM, or RAM no other owner claims.
+    Unknown = 0,
+
+    /// Memory the firmware reserved.
+    Firmware = 1,
+
+    /// The kernel image, and frames the kernel's physical allocator handed
+    /// out.
+    Kernel = 2,
+
+    /// The kernel's copy of the DTB.
+    Dtb = 3,
+
+    /// The initial ramdisk.
+    Initrd = 4,
+
+    /// An allocated frame of the frame pool, which is part of the kernel
+    /// image.
+    FramePool = 5,
+
+    /// A frame the boot stage allocated, such as one of its page tables.
+    Boot = 6,
+}
+
+impl FrameOwner {
+    /// Every owner, ordered by value.
+    pub const ALL: [Self; 7] = [
+        Self::Unknown,
+        Self::Firmware,
+        Self::Kernel,
+        Self::Dtb,
+        Self::Initrd,
+        Self::FramePool,
+        Self::Boot,
+    ];
+
+    fn from_raw(raw: u8) -> Self {
+        match raw {
+            1 => Self::Firmware,
+            2 => Self::Kernel,
+            3 => Self::Dtb,
+            4 => Self::Initrd,
+            5 => Self::FramePool,
+            6 => Self::Boot,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for FrameOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Unknown => "unknown",
+            Self::Firmware => "firmware",
+            Self::Kernel => "kernel",
+            Self::Dtb => "dtb",
+            Self::Initrd => "initrd",
+            Self::FramePool => "frame pool",
+            Self::Boot => "boot",
+        };
+
+        f.pad(name)
+    }
+}
+
+/// A set of frame flags.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FrameFlags(u16);
+
+impl FrameFlags {
+    /// The frame must never be allocated, such as memory the firmware uses.
+    pub const RESERVED: Self = Self(1 << 0);
+
+    /// The frame holds a page table.
+    pub const PAGE_TABLE: Self = Self(1 << 1);
+
+    /// The frame is part of a `DmaBuffer` a device may access.
+    pub const DMA: Self = Self(1 << 2);
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// The metadata of a frame.
+pub struct Frame {
+    /// The number of owners of the frame, such as the page tables that map
+    /// it. Zero while the frame is free.
+    reference_count: AtomicU32,
+
+    /// The bits of the frame's `FrameFlags`.
+    flags: AtomicU16,
+
+    /// The frame's `FrameOwner`.
+    owner: AtomicU8,
+}
+
+impl Frame {
+    /// Returns the number of references to the frame.
+    pub fn reference_count(&self) -> usize {
+        self.reference_count.load(Ordering::Acquire) as usize
+    }
+
+    /// Sets the reference count of a frame that was just allocated.
+    pub(super) fn set_reference_count(&self, count: usize) {
+        self.reference_count.store(count as u32, Ordering::Release);
+    }
+
+    /// Adds a reference to the frame.
+    ///
+    /// # Returns
+    ///
+    /// The number of references before.
+    pub(super) fn add_reference(&self) -> usize {
+        self.reference_count.fetch_add(1, Ordering::AcqRel) as usize
+    }
+
+    /// Drops a reference to the frame.
+    ///
+    /// # Returns
+    ///
+    /// The number of references before, or `None` if there were none.
+    pub(super) fn drop_reference(&self) -> Option<usize> {
+        self.reference_count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                count.checked_sub(1)
+            })
+            .ok()
+            .map(|count| count as usize)
+    }
+
+    /// Returns the flags of the frame.
+    pub fn flags(&self) -> FrameFlags {
+        FrameFlags(self.flags.load(Ordering::Acquire))
+    }
+
+    /// Adds flags to the frame.
+    pub fn insert_flags(&self, flags: FrameFlags) {
+        self.flags.fetch_or(flags.0, Ordering::AcqRel);
+    }
+
+    /// Removes flags from the frame.
+    pub fn remove_flags(&self, flags: FrameFlags) {
+        self.flags.fetch_and(!flags.0, Ordering::AcqRel);
+    }
+
+    /// Returns what uses the frame.
+    pub fn owner(&self) -> FrameOwner {
+        FrameOwner::from_raw(self.owner.load(Ordering::Acquire))
+    }
+
+    /// Sets what uses the frame.
+    pub fn set_owner(&self, owner: FrameOwner) {
+        self.owner.store(owner as u8, Ordering::Release);
+    }
+}
+
+/// A range of RAM with metadata.
+struct RamRegion {
+    first_ppn: AtomicUsize,
+    frame_count: AtomicUsize,
+
+    /// The index in `FRAMES` of the metadata of the first frame.
+    first_index: AtomicUsize,
+}
+
+/// The metadata of every frame, ordered like `RAM_REGIONS`.
+static FRAMES: [Frame; MAX_FRAMES] = [const {
+    Frame {
+        reference_count: AtomicU32::new(0),
+        flags: AtomicU16::new(0),
+        owner: AtomicU8::new(0),
+    }
+}; MAX_FRAMES];
+
+/// The ranges of RAM with metadata. Only the first `RAM_REGION_COUNT` are
+/// valid.
+static RAM_REGIONS: [RamRegion; MAX_RAM_REGIONS] = [const {
+    RamRegion {
+        first_ppn: AtomicUsize::new(0),
+        frame_count: AtomicUsize::new(0),
+        first_index: AtomicUsize::new(0),
+    }
+}; MAX_RAM_REGIONS];
+
+static RAM_REGION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Gives every frame of RAM in the DTB metadata and marks the frames the
+/// kernel image, the boot stage, the DTB copy, the initial ramdisk, and the
+/// firmware use. Called by `memory::initialize` before any frame is
+/// allocated.
+///
+/// # Arguments
+///
+/// * `dtb` - The Device Tree Blob describing the RAM.
+/// * `boot_info` - The information the boot stage handed over, which records
+///   the memory the boot stage allocated.
+pub(super) fn initialize(dtb: &Dtb, boot_info: &BootInfo) {
+    let mut region_count = 0;
+    let mut frame_count = 0;
+    let mut untracked_size = 0;
+
+    let memory_nodes = dtb
+        .nodes()
+        .filter(|node| node.name == "memory" || node.name.starts_with("memory@"));
+
+    for node in memory_nodes {
+        let Some(property) = node.property("reg") else {
+            continue;
+        };
+
+        property.get_property_data_as_reg(&node.parent_cell_info(), |address, size| {
+            // Only whole frames have metadata.
+            let first_ppn = (address as usize).div_ceil(PAGE_SIZE);
+            let end_ppn = (address as usize).saturating_add(size as usize) / PAGE_SIZE;
+            let region_frame_count = end_ppn.saturating_sub(first_ppn);
+
+            let tracked_frame_count = if region_count < MAX_RAM_REGIONS {
+                region_frame_count.min(MAX_FRAMES - frame_count)
+            } else {
+                0
+            };
+
+            untracked_size += (region_frame_count - tracked_frame_count) * PAGE_SIZE;
+
+            if tracked_frame_count == 0 {
+                return;
+            }
+
+            let region = &RAM_REGIONS[region_count];
+            region.first_ppn.store(first_ppn, Ordering::Relaxed);
+            region
+                .frame_count
+                .store(tracked_frame_count, Ordering::Relaxed);
+            region.first_index.store(frame_count, Ordering::Relaxed);
+
+            region_count += 1;
+            frame_count += tracked_frame_count;
+        });
+    }
+
+    RAM_REGION_COUNT.store(region_count, Ordering::Release);
+
+    unsafe extern "C" {
+        static _kernel_start: u8;
+        static _kernel_end: u8;
+    }
+
+    let kernel_start = &raw const _kernel_start as usize;
+    let kernel_end = &raw const _kernel_end as usize + 1;
+
+    // The kernel image and the DTB copy are among the boot stage's
+    // allocations, so they are marked afterwards to keep their own owners.
+    for region in boot_info.allocated_regions() {
+        mark_physical_range(
+            region.start as u64,
+            region.start.saturating_add(region.size) as u64,
+            FrameOwner::Boot,
+            FrameFlags::default(),
+        );
+    }
+
+    mark_mapped_range(kernel_start, kernel_end, FrameOwner::Kernel);
+
+    let dtb_start = layout().dtb_virtual_address;
+    let dtb_end = dtb_start + dtb.total_size();
+    mark_mapped_range(dtb_start, dtb_end, FrameOwner::Dtb);
+
+    if let Some((initrd_start, initrd_end)) =
+        dtb::chosen(dtb).and_then(|chosen| chosen.initrd_range())
+    {
+        mark_physical_range(
+            initrd_start,
+            initrd_end,
+            FrameOwner::Initrd,
+            FrameFlags::RESERVED,
+        );
+    }
+
+    walk_memory_reservation_entries(dtb, |entry| {
+        mark_physical_range(
+            entry.address,
+            entry.address.saturating_add(entry.size),
+            FrameOwner::Firmware,
+            FrameFlags::RESERVED,
+        );
+    });
+
+    for node in dtb
+        .root_node()
+        .and_then(|root_node| root_node.child("reserved-memory"))
+        .into_iter()
+        .flat_map(|node| node.children())
+    {
+        if let Some(property) = node.property("reg") {
+            property.get_property_data_as_reg(&node.parent_cell_info(), |address, size| {
+                mark_physical_range(
+                    address,
+                    address.saturating_add(size),
+                    FrameOwner::Firmware,
+                    FrameFlags::RESERVED,
+                );
+            });
+        }
+    }
+
+    debug_println!(
+        "Frame metadata for {} frames in {} RAM regions.",
+        frame_count,
+        region_count
+    );
+
+    if untracked_size != 0 {
+        debug_println!("{} bytes of RAM have no frame metadata.", untracked_size);
+    }
+}
+
+/// Finds the metadata of a frame.
+///
+/// # Arguments
+///
+/// * `ppn` - The physical page number of the frame.
+///
+/// # Returns
+///
+/// The metadata, or `None` if the frame is not RAM with metadata.
+pub fn frame_for(ppn: PhysicalPageNumber) -> Option<&'static Frame> {
+    let ppn = ppn.raw_ppn();
+
+    RAM_REGIONS[..RAM_REGION_COUNT.load(Ordering::Acquire)]
+        .iter()
+        .find_map(|region| {
+            let offset = ppn.checked_sub(region.first_ppn.load(Ordering::Relaxed))?;
+
+            (offset < region.frame_count.load(Ordering::Relaxed))
+                .then(|| &FRAMES[region.first_index.load(Ordering::Relaxed) + offset])
+        })
+}
+
+/// Calls a function for every range of RAM with metadata.
+///
+/// # Arguments
+///
+/// * `callback` - Called with the physical page number of the first frame and
+///   the number of frames of each range.
+pub fn for_each_ram_region(mut callback: impl FnMut(PhysicalPageNumber, usize)) {
+    for region in &RAM_REGIONS[..RAM_REGION_COUNT.load(Ordering::Acquire)] {
+        callback(
+            PhysicalPageNumber::from_raw_physical_page_number(
+                region.first_ppn.load(Ordering::Relaxed),
+            ),
+            region.frame_count.load(Ordering::Relaxed),
+        );
+    }
+}
+
+/// Sets the owner of the frames behind a range of kernel virtual addresses
+/// mapped with 4KiB pages.
+fn mark_mapped_range(start_address: usize, end_address: usize, owner: FrameOwner) {
+    let start_address = start_address & !(PAGE_SIZE - 1);
+
+    for virtual_address in (start_address..end_address).step_by(PAGE_SIZE) {
+        if let Some(frame) = virtual_to_physical(virtual_address).and_then(|physical_address| {
+            frame_for(PhysicalPageNumber::from_physical_address(physical_address))
+        }) {
+            frame.set_owner(owner);
+        }
+    }
+}
+
+/// Sets the owner and adds flags to the frames of a range of physical
+/// addresses, including the frames the range only partly covers.
+fn mark_physical_range(start_address: u64, end_address: u64, owner: FrameOwner, flags: FrameFlags) {
+    let first_ppn = start_address as usize / PAGE_SIZE;
+    let end_ppn = (end_address as usize).div_ceil(PAGE_SIZE);
+
+    for ppn in first_ppn..end_ppn {
+        if let Some(frame) = frame_for(PhysicalPageNumber::from_raw_physical_page_number(ppn)) {
+            frame.set_owner(owner);
+            frame.insert_flags(flags);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Mappings of device registers in a window of the kernel's address space.
+//!
+//! `map_mmio` maps the registers of a device at the next free addresses of
+//! the window, readable, writable, never executable, and global, with the
+//! I/O memory type if every hart implements Svpbmt. The window
+//! has a root page table entry of its own, installed by `initialize` before
+//! any user address space copies the kernel's root entries, so the mappings
+//! show up in every address space. Mappings are never removed, so the
+//! window's page tables come from the physical allocator rather than the
+//! frame pool.
+
+use super::{
+    PAGE_SIZE, kernel_root_page_table_ppn, layout, physical_allocator::page_table_allocator,
+};
+use boot_lib::memory::{
+    mmu::{self, MapError, page_table_pointer},
+    physical_memory_allocator::PhysicalMemoryAllocator,
+};
+use common_lib::memory::{PhysicalAddress, VirtualAddress};
+use kernel_lib::sync::SpinLock;
+
+/// The first virtual address of the window that is not mapped yet, set to the
+/// start of the window by `initialize`. Held while a mapping is added, which
+/// serializes changes to the window's page tables.
+static NEXT_VIRTUAL_ADDRESS: SpinLock<VirtualAddress> = SpinLock::new(VirtualAddress::new(0));
+
+/// Installs the root page table entry of the window. Called by
+/// `memory::initialize`.
+///
+/// # Panics
+///
+/// If the physical allocator is empty.
+pub(super) fn initialize() {
+    let layout = layout();
+
+    let window_table_ppn = page_table_allocator()
+        .allocate_page()
+        .expect("The physical allocator is empty during boot.")
+        .ppn();
+
+    let kernel_root_page_table = unsafe { &mut *page_table_pointer(kernel_root_page_table_ppn()) };
+    let root_index = layout.root_page_table_index(layout.mmio_window_base_virtual_address);
+
+    let entry = kernel_root_page_table.get_entry_mut(root_index);
+    entry.set_valid(true);
+    entry.set_ppn(window_table_ppn);
+
+    *NEXT_VIRTUAL_ADDRESS.lock() = VirtualAddress::new(layout.mmio_window_base_virtual_address);
+}
+
+/// Maps the registers of a device into the kernel's address space.
+///
+/// The registers are mapped from the start of the page holding
+/// `physical_address` to the end of the page holding its last byte. The
+/// mapping is shared by every hart and address space and stays for as long
+/// as the kernel runs.
+///
+/// # Arguments
+///
+/// * `physical_address` - The physical address of the registers.
+/// * `length` - The number of bytes of the registers.
+///
+/// # Returns
+///
+/// The virtual address of the registers, with the same offset into its page
+/// as `physical_address`. Fails with `MapError::OutOfMemory` if the window
+/// is full or no frame is left for a page table.
+pub fn map_mmio(
+    physical_address: PhysicalAddress,
+    length: usize,
+) -> Result<VirtualAddress, MapError> {
+    let page_offset = physical_address.page_offset();
+    let mapped_length = (page_offset + length.max(1)).next_multiple_of(PAGE_SIZE);
+
+    let mut next_virtual_address = NEXT_VIRTUAL_ADDRESS.lock();
+    let virtual_address = *next_virtual_address;
+
+    let layout = layout();
+    let window_end_address =
+        VirtualAddress::new(layout.mmio_window_base_virtual_address + layout.mmio_window_size);
+    if window_end_address - virtual_address < mapped_length {
+        return Err(MapError::OutOfMemory);
+    }
+
+    let kernel_root_page_table = unsafe { &mut *page_table_pointer(kernel_root_page_table_ppn()) };
+
+    // The addresses of a mapping that fails partway are not reused, since
+    // some of its pages stay mapped.
+    *next_virtual_address += mapped_length;
+
+    mmu::map_mmio(
+        kernel_root_page_table,
+        physical_address.align_down(PAGE_SIZE),
+        virtual_address,
+        mapped_length,
+        &mut page_table_allocator(),
+    )?;
+
+    Ok(virtual_address + page_offset)
+}
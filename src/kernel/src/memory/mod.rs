@@ -0,0 +1,305 @@
+//! Kernel view of physical memory and the active page tables.
+
+mod address_space;
+mod asid;
+mod boot_mapping;
+mod dma;
+#[cfg(feature = "frame-debug")]
+mod frame_debug;
+mod frame_pool;
+mod frames;
+mod mmio;
+mod physical_allocator;
+mod shared_memory;
+mod stats;
+
+pub use address_space::{AddressSpace, FaultAccess, USER_ADDRESS_LIMIT, UserPageAccess};
+pub use dma::DmaBuffer;
+pub use frame_pool::for_each_allocated_frame;
+pub use mmio::map_mmio;
+pub use shared_memory::{MAX_SHARED_MEMORY_NAME_LENGTH, SharedMemory, SharedMemoryError};
+pub use stats::stats;
+
+use boot_lib::memory::mmu::{
+    MapError, PageTable, Translation, page_table_pointer, paging_mode, set_paging_mode,
+    set_physical_memory_offset, unmap_vpn, walk,
+};
+use common_lib::{
+    boot_info::BootInfo,
+    dtb::Dtb,
+    layout::{Layout, PagingMode},
+    memory::{PhysicalPageNumber, VirtualAddress, VirtualPageNumber},
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The size of a base page in bytes.
+pub const PAGE_SIZE: usize = 4096;
+
+/// The satp value of the kernel's own address space, which the boot stage
+/// built and every hart starts on.
+static KERNEL_SATP: AtomicUsize = AtomicUsize::new(0);
+
+/// Records the paging mode the boot stage enabled, which decides how many
+/// levels of page tables the shared mmu code walks and where `layout` places
+/// the windows of the kernel half. Must be called on the boot hart before any
+/// page table is walked or any window is used, the direct physical memory
+/// mapping included.
+///
+/// # Panics
+///
+/// If satp selects no paging mode.
+pub fn initialize_paging_mode() {
+    let satp = read_satp();
+
+    match PagingMode::from_satp(satp) {
+        Some(paging_mode) => set_paging_mode(paging_mode),
+        None => panic!("satp {:#x} selects no paging mode.", satp),
+    }
+}
+
+/// Returns the layout of the kernel half in the paging mode the kernel runs
+/// in.
+pub fn layout() -> Layout {
+    Layout::new(paging_mode())
+}
+
+/// Prepares the shared mmu code to access page tables through the direct
+/// physical memory mapping, records the kernel's address space, gives every
+/// frame of RAM its metadata, removes the boot stage's identity mapping,
+/// starts the physical allocator on the RAM the boot stage left free or no
+/// longer needs, and reserves the window `map_mmio` maps device
+/// registers in. Must be called on the boot hart, off the boot stack, before
+/// any other hart starts, any frame is allocated, or any user address space
+/// is created.
+///
+/// # Arguments
+///
+/// * `dtb` - The Device Tree Blob describing the RAM.
+/// * `boot_info` - The information the boot stage handed over.
+///
+/// # Panics
+///
+/// If the boot information names a paging mode other than the one satp
+/// selects.
+pub fn initialize(dtb: &Dtb, boot_info: &BootInfo) {
+    if boot_info.paging_mode() != paging_mode() {
+        panic!(
+            "The boot stage recorded {} paging, but satp selects {}.",
+            boot_info.paging_mode(),
+            paging_mode()
+        );
+    }
+
+    set_physical_memory_offset(layout().direct_map_base_virtual_address);
+
+    KERNEL_SATP.store(read_satp(), Ordering::Relaxed);
+    asid::initialize();
+
+    frames::initialize(dtb, boot_info);
+
+    let reclaimed_memory = boot_mapping::remove_boot_identity_mapping(boot_info);
+    physical_allocator::initialize(boot_info, &reclaimed_memory);
+
+    mmio::initialize();
+}
+
+/// Returns the physical page number of the kernel's root page table.
+pub fn kernel_root_page_table_ppn() -> PhysicalPageNumber {
+    ppn_from_satp(KERNEL_SATP.load(Ordering::Relaxed))
+}
+
+/// Returns the satp value of the kernel's own address space.
+pub fn kernel_satp() -> usize {
+    KERNEL_SATP.load(Ordering::Relaxed)
+}
+
+/// Removes a 4KiB page from the kernel's half of the address space on every
+/// hart, such as to turn it into a guard page. The memory behind it is not
+/// reused.
+///
+/// # Arguments
+///
+/// * `virtual_address` - The page aligned kernel address of the page.
+///
+/// # Returns
+///
+/// `MapError::NotMapped` if the page is not mapped and
+/// `MapError::WouldSplitSuperpage` if it is part of a larger page.
+pub fn unmap_kernel_page(virtual_address: usize) -> Result<(), MapError> {
+    // The kernel's lower level page tables are shared by every address
+    // space, so the change shows up in all of them.
+    let kernel_root_page_table = unsafe { &mut *page_table_pointer(kernel_root_page_table_ppn()) };
+
+    unmap_vpn(
+        kernel_root_page_table,
+        VirtualPageNumber::from_virtual_address(virtual_address),
+    )
+    .map(|_| ())
+}
+
+/// Reads the raw value of the satp CSR on this hart.
+pub fn read_satp() -> usize {
+    let satp: usize;
+    unsafe {
+        core::arch::asm!("csrr {}, satp", out(reg) satp, options(nomem, nostack));
+    }
+
+    satp
+}
+
+/// Installs an address space in satp on this hart.
+///
+/// Translations are tagged with the ASID in satp, so the TLB is only flushed
+/// when a user address space without an ASID of its own is installed, since
+/// it shares ASID 0 with every other such address space.
+///
+/// # Safety
+///
+/// The page tables must map the kernel exactly like the kernel's own address
+/// space does.
+pub unsafe fn write_satp(satp: usize) {
+    let is_untagged_user_address_space = asid::asid_from_satp(satp) == 0 && satp != kernel_satp();
+
+    unsafe {
+        core::arch::asm!("csrw satp, {}", in(reg) satp, options(nostack));
+
+        if is_untagged_user_address_space {
+            core::arch::asm!("sfence.vma", options(nostack));
+        }
+    }
+}
+
+/// Returns the satp value that selects a root page table and ASID in the
+/// paging mode the kernel runs in.
+fn satp_for(root_page_table_ppn: PhysicalPageNumber, asid: usize) -> usize {
+    asid::with_asid(paging_mode().satp(root_page_table_ppn.raw_ppn()), asid)
+}
+
+/// Returns the root page table a satp value selects.
+fn ppn_from_satp(satp: usize) -> PhysicalPageNumber {
+    // The PPN occupies the lower 44 bits of satp.
+    const SATP_PPN_MASK: usize = (1 << 44) - 1;
+
+    PhysicalPageNumber::from_raw_physical_page_number(satp & SATP_PPN_MASK)
+}
+
+/// Returns the physical page number of the root page table currently
+/// installed in satp on this hart.
+pub fn active_root_page_table_ppn() -> PhysicalPageNumber {
+    ppn_from_satp(read_satp())
+}
+
+/// Returns a reference to the root page table currently installed in satp on
+/// this hart.
+pub fn active_root_page_table() -> &'static PageTable {
+    unsafe { &*page_table_pointer(active_root_page_table_ppn()) }
+}
+
+/// Translates a kernel virtual address into a physical address using the
+/// active page tables.
+///
+/// # Arguments
+///
+/// * `virtual_address` - Any virtual address, including ones mapped with
+///   superpages such as the direct physical memory mapping.
+///
+/// # Returns
+///
+/// The physical address, or `None` if the address is not mapped.
+pub fn virtual_to_physical(virtual_address: usize) -> Option<usize> {
+    active_translation(virtual_address)
+        .map(|translation| translation.physical_address.raw_address())
+}
+
+/// Returns the virtual address through which a physical address is accessed
+/// in the direct physical memory mapping.
+///
+/// The direct mapping covers device registers as well as RAM, so drivers may
+/// reach MMIO regions the direct map covers through it. `map_mmio` maps
+/// registers at any physical address instead.
+///
+/// # Arguments
+///
+/// * `physical_address` - A physical address within the direct map, which
+///   covers the first 128GiB of physical memory in sv39 and all of it in the
+///   larger modes.
+///
+/// # Returns
+///
+/// The virtual address, or `None` if the physical address is not covered by
+/// the direct mapping.
+pub fn physical_to_virtual(physical_address: usize) -> Option<usize> {
+    let layout = layout();
+
+    if physical_address >= layout.direct_map_size {
+        return None;
+    }
+
+    Some(layout.direct_map_base_virtual_address + physical_address)
+}
+
+/// Returns true if a virtual address is mapped readable in the active page
+/// tables.
+///
+/// # Arguments
+///
+/// * `virtual_address` - Any virtual address. Addresses that are not
+///   canonical in the paging mode are never readable.
+pub fn is_readable(virtual_address: usize) -> bool {
+    active_translation(virtual_address).is_some_and(|translation| translation.flags.readable)
+}
+
+/// Returns true if user code may access a virtual address in the active page
+/// tables.
+///
+/// # Arguments
+///
+/// * `virtual_address` - Any virtual address.
+/// * `is_write` - True to check for write access rather than read access.
+pub fn is_user_accessible(virtual_address: usize, is_write: bool) -> bool {
+    active_translation(virtual_address).is_some_and(|translation| {
+        translation.flags.user
+            && if is_write {
+                translation.flags.writable
+            } else {
+                translation.flags.readable
+            }
+    })
+}
+
+/// Walks the active page tables for a virtual address, following superpage
+/// mappings.
+///
+/// # Returns
+///
+/// The translation, or `None` if the address is not canonical or not mapped.
+fn active_translation(virtual_address: usize) -> Option<Translation> {
+    if !paging_mode().is_canonical(virtual_address) {
+        return None;
+    }
+
+    walk(
+        active_root_page_table(),
+        VirtualAddress::new(virtual_address),
+    )
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    kernel_test!(
+        fn test_direct_map_translates_through_root_leaves() {
+            let physical_address = virtual_to_physical(kernel_satp as *const () as usize).unwrap();
+            let virtual_address = physical_to_virtual(physical_address).unwrap();
+
+            let translation = active_translation(virtual_address).unwrap();
+
+            assert_eq!(translation.level as usize, paging_mode().root_level());
+            assert_eq!(translation.physical_address.raw_address(), physical_address);
+            assert!(translation.flags.readable && translation.flags.global);
+            assert!(!translation.flags.user);
+        }
+    );
+}
@@ -0,0 +1,179 @@
+//! The allocator of the RAM the boot stage left free.
+//!
+//! The boot stage hands over the usable RAM and the ranges its own allocator
+//! handed out, such as its page tables, the boot information, and the DTB
+//! copy. `initialize` builds a memory map of the usable RAM with those ranges
+//! carved out, adds the boot stage memory the kernel reclaimed, and bump
+//! allocates from the result, so no page the kernel still uses is handed out
+//! a second time. Frames are never freed, so the
+//! allocator only backs memory kept for as long as the kernel runs, such as
+//! the page tables of the MMIO window. Memory that is freed again comes from
+//! the frame pool.
+
+use super::{
+    PAGE_SIZE,
+    frames::{FrameFlags, FrameOwner, frame_for},
+    physical_to_virtual,
+};
+use crate::debug_println;
+use boot_lib::memory::{
+    memory_map::MemoryMap,
+    physical_memory_allocator::{PhysicalBumpAllocator, PhysicalMemoryAllocator},
+};
+use common_lib::{
+    boot_info::BootInfo,
+    memory::{MemoryRegion, PhysicalAddress},
+};
+use kernel_lib::sync::{SpinLock, SpinLockGuard};
+
+/// The allocator, which is empty until `initialize` runs.
+static ALLOCATOR: SpinLock<PhysicalBumpAllocator> = SpinLock::new(PhysicalBumpAllocator::new());
+
+/// Hands the allocator the usable RAM the boot stage found, less the ranges
+/// the boot stage allocated, plus the memory reclaimed from the boot stage.
+/// Called by `memory::initialize` after the frames have their metadata.
+///
+/// # Arguments
+///
+/// * `boot_info` - The information the boot stage handed over.
+/// * `reclaimed_memory` - Memory of the boot stage that is no longer used,
+///   which may overlap the allocated ranges.
+pub(super) fn initialize(boot_info: &BootInfo, reclaimed_memory: &MemoryMap) {
+    let mut memory_map = MemoryMap::new();
+
+    for region in boot_info.memory_regions() {
+        if let Err(error) = memory_map.add_region(PhysicalAddress::new(region.start), region.size) {
+            debug_println!(
+                "Dropping usable memory {:#x}-{:#x}: {}.",
+                region.start,
+                region.end(),
+                error
+            );
+        }
+    }
+
+    for region in boot_info.allocated_regions() {
+        if let Err(error) =
+            memory_map.carve_out_region(PhysicalAddress::new(region.start), region.size)
+        {
+            debug_println!(
+                "Carving out boot allocation {:#x}-{:#x}: {}.",
+                region.start,
+                region.end(),
+                error
+            );
+        }
+    }
+
+    for region in reclaimed_memory.regions() {
+        if let Err(error) = memory_map.add_region(PhysicalAddress::new(region.start), region.size) {
+            debug_println!(
+                "Dropping reclaimed memory {:#x}-{:#x}: {}.",
+                region.start,
+                region.end(),
+                error
+            );
+        }
+    }
+
+    let mut allocator = ALLOCATOR.lock();
+    allocator.reset(memory_map.get_regions(), memory_map.get_region_count());
+
+    debug_println!(
+        "Physical memory allocator has {} KiB free in {} regions.",
+        allocator.available_memory_size() / 1024,
+        memory_map.get_region_count()
+    );
+}
+
+/// Returns the number of bytes of RAM the allocator started with.
+pub fn total_memory_size() -> usize {
+    ALLOCATOR.lock().total_memory_size()
+}
+
+/// Returns the number of bytes of RAM the allocator handed out.
+pub fn allocated_memory_size() -> usize {
+    ALLOCATOR.lock().allocated_memory_size()
+}
+
+/// Locks the allocator for the mmu code to allocate page tables that are
+/// never freed.
+pub(super) fn page_table_allocator() -> PageTableAllocator {
+    PageTableAllocator(ALLOCATOR.lock())
+}
+
+/// The allocator as the mmu code sees it, holding the allocator's lock.
+///
+/// Every page it hands out is zeroed and flagged as a page table owned by the
+/// kernel.
+pub(super) struct PageTableAllocator(SpinLockGuard<'static, PhysicalBumpAllocator>);
+
+impl PageTableAllocator {
+    /// Zeroes allocated pages through the direct physical memory mapping and
+    /// records them as page tables.
+    ///
+    /// # Arguments
+    ///
+    /// * `physical_address` - The physical address of the first page.
+    /// * `page_count` - The number of pages.
+    fn prepare_pages(physical_address: PhysicalAddress, page_count: usize) {
+        for page_index in 0..page_count {
+            let page_address = physical_address + page_index * PAGE_SIZE;
+
+            let virtual_address = physical_to_virtual(page_address.raw_address())
+                .expect("The boot stage only hands over directly mapped memory.");
+
+            unsafe {
+                core::ptr::write_bytes(virtual_address as *mut u8, 0, PAGE_SIZE);
+            }
+
+            if let Some(frame) = frame_for(page_address.ppn()) {
+                frame.set_owner(FrameOwner::Kernel);
+                frame.insert_flags(FrameFlags::PAGE_TABLE);
+            }
+        }
+    }
+}
+
+impl PhysicalMemoryAllocator for PageTableAllocator {
+    fn allocate_page(&mut self) -> Option<PhysicalAddress> {
+        let physical_address = self.0.allocate_page()?;
+        Self::prepare_pages(physical_address, 1);
+
+        Some(physical_address)
+    }
+
+    fn allocate_contiguous_in_range(
+        &mut self,
+        page_count: usize,
+        alignment: usize,
+        start_address: PhysicalAddress,
+        end_address: PhysicalAddress,
+    ) -> Option<PhysicalAddress> {
+        let physical_address = self.0.allocate_contiguous_in_range(
+            page_count,
+            alignment,
+            start_address,
+            end_address,
+        )?;
+        Self::prepare_pages(physical_address, page_count);
+
+        Some(physical_address)
+    }
+
+    fn total_memory_size(&self) -> usize {
+        self.0.total_memory_size()
+    }
+
+    fn allocated_memory_size(&self) -> usize {
+        self.0.allocated_memory_size()
+    }
+
+    fn memory_regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        self.0.memory_regions()
+    }
+
+    fn allocated_regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        self.0.allocated_regions()
+    }
+}
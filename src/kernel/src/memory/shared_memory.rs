@@ -0,0 +1,308 @@
+//! Shared memory objects.
+//!
+//! A shared memory object is a set of zeroed frames from the frame pool that
+//! any number of user address spaces map at once. `SharedMemory::create`
+//! takes an object from a static pool, optionally under a name that
+//! `SharedMemory::open` finds it by, and allocates every frame up front.
+//! Processes hold objects as file descriptors, so anonymous objects are
+//! shared through the descriptors `fork` copies.
+//!
+//! The object holds one reference to each of its frames and every mapping
+//! holds another, so the frames outlive the object while any address space
+//! still maps them. Each mapping chooses its own access, so one process may
+//! map an object read only while another writes to it.
+
+use super::{AddressSpace, PAGE_SIZE, UserPageAccess, frame_pool};
+use boot_lib::memory::mmu::MapError;
+use common_lib::memory::PhysicalPageNumber;
+use core::fmt;
+use kernel_lib::sync::{Ref, RefCount, RefCounted, SpinLock};
+
+/// The most shared memory objects that can exist at once.
+pub const MAX_SHARED_MEMORY_OBJECTS: usize = 4;
+
+/// The most pages a shared memory object can hold.
+pub const MAX_SHARED_MEMORY_PAGES: usize = 4;
+
+/// The longest name of a shared memory object in bytes.
+pub const MAX_SHARED_MEMORY_NAME_LENGTH: usize = 16;
+
+/// The reasons a shared memory object could not be created or opened.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SharedMemoryError {
+    /// All `MAX_SHARED_MEMORY_OBJECTS` objects are in use.
+    TooManyObjects,
+
+    /// The object would be empty or larger than `MAX_SHARED_MEMORY_PAGES`
+    /// pages.
+    InvalidLength(usize),
+
+    /// The name is empty or longer than `MAX_SHARED_MEMORY_NAME_LENGTH`.
+    InvalidName,
+
+    /// Another object has the name.
+    AlreadyExists,
+
+    /// No object has the name.
+    NotFound,
+
+    /// The frame pool ran out before every page was allocated.
+    OutOfMemory,
+}
+
+impl fmt::Display for SharedMemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyObjects => write!(
+                f,
+                "all {} shared memory objects are in use",
+                MAX_SHARED_MEMORY_OBJECTS
+            ),
+            Self::InvalidLength(length) => write!(
+                f,
+                "{:#x} bytes is not between one and {} pages",
+                length, MAX_SHARED_MEMORY_PAGES
+            ),
+            Self::InvalidName => write!(
+                f,
+                "the name is empty or longer than {} bytes",
+                MAX_SHARED_MEMORY_NAME_LENGTH
+            ),
+            Self::AlreadyExists => write!(f, "a shared memory object has the name"),
+            Self::NotFound => write!(f, "no shared memory object has the name"),
+            Self::OutOfMemory => write!(f, "the frame pool is empty"),
+        }
+    }
+}
+
+/// The name and frames of a shared memory object.
+struct SharedMemoryState {
+    /// The name, of which the first `name_length` bytes are used. An object
+    /// with a `name_length` of zero is anonymous.
+    name: [u8; MAX_SHARED_MEMORY_NAME_LENGTH],
+    name_length: usize,
+
+    /// The frames, of which the first `page_count` are allocated.
+    frames: [PhysicalPageNumber; MAX_SHARED_MEMORY_PAGES],
+    page_count: usize,
+}
+
+impl SharedMemoryState {
+    const fn new() -> Self {
+        Self {
+            name: [0; MAX_SHARED_MEMORY_NAME_LENGTH],
+            name_length: 0,
+            frames: [PhysicalPageNumber::from_raw_physical_page_number(0); MAX_SHARED_MEMORY_PAGES],
+            page_count: 0,
+        }
+    }
+
+    /// Returns the name, which is empty for an anonymous object.
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_length]
+    }
+}
+
+/// A slot of the shared memory object pool.
+pub struct SharedMemory {
+    ref_count: RefCount,
+    state: SpinLock<SharedMemoryState>,
+}
+
+impl RefCounted for SharedMemory {
+    fn ref_count(&self) -> &RefCount {
+        &self.ref_count
+    }
+
+    fn release(&'static self) {
+        let mut state = self.state.lock();
+
+        // Address spaces that still map a frame keep it until they drop it.
+        for &ppn in &state.frames[..state.page_count] {
+            frame_pool::free_frame(ppn);
+        }
+
+        *state = SharedMemoryState::new();
+    }
+}
+
+/// Every shared memory object.
+static SHARED_MEMORY_OBJECTS: [SharedMemory; MAX_SHARED_MEMORY_OBJECTS] = [const {
+    SharedMemory {
+        ref_count: RefCount::new(),
+        state: SpinLock::new(SharedMemoryState::new()),
+    }
+};
+    MAX_SHARED_MEMORY_OBJECTS];
+
+/// Held while named objects are looked up or created, so two objects never
+/// get the same name.
+static NAMES_LOCK: SpinLock<()> = SpinLock::new(());
+
+impl SharedMemory {
+    /// Creates a zeroed shared memory object.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name `open` finds the object by, or `None` for an
+    ///   anonymous object.
+    /// * `length` - The size of the object in bytes, which is rounded up to a
+    ///   whole number of pages.
+    ///
+    /// # Returns
+    ///
+    /// The first reference to the object.
+    pub fn create(name: Option<&str>, length: usize) -> Result<Ref<Self>, SharedMemoryError> {
+        let page_count = length.div_ceil(PAGE_SIZE);
+
+        if page_count == 0 || page_count > MAX_SHARED_MEMORY_PAGES {
+            return Err(SharedMemoryError::InvalidLength(length));
+        }
+
+        let Some(name) = name else {
+            return Self::claim(&[], page_count);
+        };
+
+        validate_name(name)?;
+
+        let _names = NAMES_LOCK.lock();
+
+        if find(name.as_bytes()).is_some() {
+            return Err(SharedMemoryError::AlreadyExists);
+        }
+
+        Self::claim(name.as_bytes(), page_count)
+    }
+
+    /// Opens the shared memory object with a name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the object was created with.
+    ///
+    /// # Returns
+    ///
+    /// A new reference to the object.
+    pub fn open(name: &str) -> Result<Ref<Self>, SharedMemoryError> {
+        validate_name(name)?;
+
+        let _names = NAMES_LOCK.lock();
+
+        find(name.as_bytes()).ok_or(SharedMemoryError::NotFound)
+    }
+
+    /// Maps every page of the object into an address space, wherever there
+    /// is room for them.
+    ///
+    /// # Arguments
+    ///
+    /// * `address_space` - The address space to map the object into.
+    /// * `access` - The access the mapping allows user code, independent of
+    ///   any other mapping of the object.
+    ///
+    /// # Returns
+    ///
+    /// The page aligned first user address of the mapping.
+    pub fn map(
+        &self,
+        address_space: &mut AddressSpace,
+        access: UserPageAccess,
+    ) -> Result<usize, MapError> {
+        let state = self.state.lock();
+
+        address_space.map_shared(&state.frames[..state.page_count], access)
+    }
+
+    /// Takes a free object and allocates its frames.
+    fn claim(name: &[u8], page_count: usize) -> Result<Ref<Self>, SharedMemoryError> {
+        let object = SHARED_MEMORY_OBJECTS
+            .iter()
+            .find_map(Ref::claim)
+            .ok_or(SharedMemoryError::TooManyObjects)?;
+
+        let mut state = object.state.lock();
+
+        // Dropping the object on failure frees the frames allocated so far.
+        while state.page_count < page_count {
+            let Some(ppn) = frame_pool::allocate_frame() else {
+                drop(state);
+                return Err(SharedMemoryError::OutOfMemory);
+            };
+
+            let index = state.page_count;
+            state.frames[index] = ppn;
+            state.page_count += 1;
+        }
+
+        state.name[..name.len()].copy_from_slice(name);
+        state.name_length = name.len();
+
+        drop(state);
+
+        Ok(object)
+    }
+}
+
+/// Checks that a name fits an object.
+fn validate_name(name: &str) -> Result<(), SharedMemoryError> {
+    if name.is_empty() || name.len() > MAX_SHARED_MEMORY_NAME_LENGTH {
+        return Err(SharedMemoryError::InvalidName);
+    }
+
+    Ok(())
+}
+
+/// Finds the live object with a name. The caller must hold `NAMES_LOCK`.
+fn find(name: &[u8]) -> Option<Ref<SharedMemory>> {
+    SHARED_MEMORY_OBJECTS
+        .iter()
+        .filter_map(Ref::try_acquire)
+        .find(|object| object.state.lock().name() == name)
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    kernel_test!(
+        fn test_shared_memory_open_finds_named_object() {
+            let object = SharedMemory::create(Some("kernel-test"), 1).unwrap();
+            assert_eq!(object.state.lock().page_count, 1);
+
+            assert_eq!(
+                SharedMemory::create(Some("kernel-test"), PAGE_SIZE).err(),
+                Some(SharedMemoryError::AlreadyExists)
+            );
+
+            let opened = SharedMemory::open("kernel-test").unwrap();
+            assert!(Ref::ptr_eq(&object, &opened));
+
+            // The name goes away with the last reference.
+            drop(opened);
+            drop(object);
+
+            assert_eq!(
+                SharedMemory::open("kernel-test").err(),
+                Some(SharedMemoryError::NotFound)
+            );
+        }
+    );
+
+    kernel_test!(
+        fn test_shared_memory_frees_frames_on_release() {
+            let allocated_frames = frame_pool::allocated_frame_count();
+
+            let object = SharedMemory::create(None, 2 * PAGE_SIZE).unwrap();
+            assert_eq!(frame_pool::allocated_frame_count(), allocated_frames + 2);
+
+            drop(object);
+            assert_eq!(frame_pool::allocated_frame_count(), allocated_frames);
+
+            assert_eq!(
+                SharedMemory::create(None, 0).err(),
+                Some(SharedMemoryError::InvalidLength(0))
+            );
+        }
+    );
+}
@@ -0,0 +1,131 @@
+//! Statistics of how physical memory is used.
+//!
+//! `stats` counts the frames of RAM by their owner and flags, and adds the
+//! use of the frame pool and the physical allocator, so a report before and after a change shows where
+//! memory went. The kernel has no heap, so there is no heap usage to report.
+
+use super::{
+    PAGE_SIZE,
+    frame_pool::{FRAME_POOL_SIZE, allocated_frame_count},
+    frames::{FrameFlags, FrameOwner, for_each_ram_region, frame_for},
+    physical_allocator,
+};
+use common_lib::memory::PhysicalPageNumber;
+use core::fmt;
+
+/// How physical memory is used, in bytes.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// The RAM with frame metadata.
+    pub total_size: usize,
+
+    /// The RAM that must never be allocated, such as firmware memory and the
+    /// initial ramdisk.
+    pub reserved_size: usize,
+
+    /// The RAM of each `FrameOwner`, indexed by its value.
+    pub owner_sizes: [usize; FrameOwner::ALL.len()],
+
+    /// The memory of the frame pool, which is part of the kernel image.
+    pub frame_pool_size: usize,
+
+    /// The memory of the frame pool that is allocated.
+    pub frame_pool_allocated_size: usize,
+
+    /// The RAM the physical allocator started with.
+    pub allocator_size: usize,
+
+    /// The RAM the physical allocator handed out.
+    pub allocator_allocated_size: usize,
+
+    /// The memory holding page tables the kernel allocated.
+    pub page_table_size: usize,
+}
+
+impl MemoryStats {
+    /// Returns the RAM of an owner.
+    pub fn owner_size(&self, owner: FrameOwner) -> usize {
+        self.owner_sizes[owner as usize]
+    }
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  {:<16} {:>10} KiB", "total", self.total_size / 1024)?;
+        writeln!(
+            f,
+            "  {:<16} {:>10} KiB",
+            "reserved",
+            self.reserved_size / 1024
+        )?;
+
+        for owner in FrameOwner::ALL {
+            writeln!(
+                f,
+                "  {:<16} {:>10} KiB",
+                owner,
+                self.owner_size(owner) / 1024
+            )?;
+        }
+
+        writeln!(
+            f,
+            "  {:<16} {:>10} KiB of {} KiB",
+            "frame pool used",
+            self.frame_pool_allocated_size / 1024,
+            self.frame_pool_size / 1024
+        )?;
+        writeln!(
+            f,
+            "  {:<16} {:>10} KiB of {} KiB",
+            "allocator used",
+            self.allocator_allocated_size / 1024,
+            self.allocator_size / 1024
+        )?;
+        write!(
+            f,
+            "  {:<16} {:>10} KiB",
+            "page tables",
+            self.page_table_size / 1024
+        )
+    }
+}
+
+/// Counts how physical memory is used.
+///
+/// # Returns
+///
+/// The statistics. Frames change owner while they are counted, so the
+/// numbers are only consistent while nothing allocates or frees memory.
+pub fn stats() -> MemoryStats {
+    let mut stats = MemoryStats {
+        frame_pool_size: FRAME_POOL_SIZE * PAGE_SIZE,
+        frame_pool_allocated_size: allocated_frame_count() * PAGE_SIZE,
+        allocator_size: physical_allocator::total_memory_size(),
+        allocator_allocated_size: physical_allocator::allocated_memory_size(),
+        ..MemoryStats::default()
+    };
+
+    for_each_ram_region(|first_ppn, frame_count| {
+        stats.total_size += frame_count * PAGE_SIZE;
+
+        for raw_ppn in first_ppn.raw_ppn()..first_ppn.raw_ppn() + frame_count {
+            let Some(frame) = frame_for(PhysicalPageNumber::from_raw_physical_page_number(raw_ppn))
+            else {
+                continue;
+            };
+
+            stats.owner_sizes[frame.owner() as usize] += PAGE_SIZE;
+
+            if frame.flags().contains(FrameFlags::RESERVED) {
+                stats.reserved_size += PAGE_SIZE;
+            }
+
+            if frame.flags().contains(FrameFlags::PAGE_TABLE) {
+                stats.page_table_size += PAGE_SIZE;
+            }
+        }
+    });
+
+    stats
+}
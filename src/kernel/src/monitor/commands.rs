@@ -0,0 +1,829 @@
+use super::{COMMANDS, parse_number};
+use crate::{
+    block,
+    console::ConsoleWriter,
+    debug_print, debug_println, devices,
+    drivers::virtio,
+    fs::{self, FsError, NodeKind},
+    hart, irq, log,
+    memory::{self, active_root_page_table},
+    net, percpu,
+    pmu::{self, PmuEvent},
+    process, random, softirq,
+    stack_guard::{self, StackOwner},
+    symbols::Symbolized,
+    task, trace,
+};
+use boot_lib::memory::page_table_dump::{Leaf, for_each_leaf, write_leaf_ranges};
+use common_lib::dtb::{self, Dtb, walk_memory_reservation_entries};
+use core::{sync::atomic::Ordering, time::Duration};
+use kernel_lib::net::Ipv4Address;
+use sbi_lib::{hsm::hart_get_status, system_reset};
+
+/// The number of bytes `md` dumps when no length is given.
+const DEFAULT_DUMP_LENGTH: usize = 64;
+
+/// The most bytes a single `md` dumps.
+const MAX_DUMP_LENGTH: usize = 4096;
+
+/// The number of bytes on each line of a memory dump.
+const BYTES_PER_LINE: usize = 16;
+
+/// The size of the smallest page.
+const PAGE_SIZE: usize = 4096;
+
+/// Lists every command with its usage.
+pub fn help(_arguments: &mut dyn Iterator<Item = &str>) {
+    for command in COMMANDS {
+        debug_println!("  {:<24}{}", command.usage, command.description);
+    }
+}
+
+/// Dumps memory as hexadecimal bytes and ASCII.
+///
+/// Every page of the range is checked to be mapped readable first, so a typo
+/// does not page fault the kernel.
+pub fn memory_dump(arguments: &mut dyn Iterator<Item = &str>) {
+    let Some(address) = arguments.next().and_then(parse_number) else {
+        debug_println!("Usage: md <address> [length]");
+        return;
+    };
+
+    let length = match arguments.next() {
+        Some(text) => match parse_number(text) {
+            Some(length) => length.min(MAX_DUMP_LENGTH),
+            None => {
+                debug_println!("Invalid length \"{}\".", text);
+                return;
+            }
+        },
+        None => DEFAULT_DUMP_LENGTH,
+    };
+
+    let Some(end) = address.checked_add(length) else {
+        debug_println!("The range wraps around the address space.");
+        return;
+    };
+
+    if let Some(unreadable_address) = (address..end)
+        .step_by(PAGE_SIZE)
+        .chain(core::iter::once(end.saturating_sub(1)))
+        .find(|&address| !memory::is_readable(address))
+    {
+        debug_println!("{:#x} is not mapped readable.", unreadable_address);
+        return;
+    }
+
+    print_hex_dump(address, length, |address| unsafe {
+        core::ptr::read_volatile(address as *const u8)
+    });
+}
+
+/// Dumps blocks of a block device as hexadecimal bytes and ASCII, or lists the
+/// block devices when no device is named.
+pub fn block_dump(arguments: &mut dyn Iterator<Item = &str>) {
+    let Some(name) = arguments.next() else {
+        block::for_each_device(|device| {
+            debug_println!(
+                "{:<8} {} blocks of {} bytes{}",
+                device.name(),
+                device.block_count(),
+                device.block_size(),
+                if device.is_read_only() {
+                    " (read only)"
+                } else {
+                    ""
+                }
+            );
+        });
+        return;
+    };
+
+    let Some(device) = block::find(name) else {
+        debug_println!("No block device \"{}\".", name);
+        return;
+    };
+
+    let Some(first_block) = arguments.next().and_then(parse_number) else {
+        debug_println!("Usage: blk [<device> <block> [count]]");
+        return;
+    };
+
+    let block_size = device.block_size();
+    if block_size > MAX_DUMP_LENGTH {
+        debug_println!("Blocks of {} bytes are too large to dump.", block_size);
+        return;
+    }
+
+    let count = match arguments.next() {
+        Some(text) => match parse_number(text) {
+            Some(count) => count.clamp(1, MAX_DUMP_LENGTH / block_size),
+            None => {
+                debug_println!("Invalid count \"{}\".", text);
+                return;
+            }
+        },
+        None => 1,
+    };
+
+    let mut buffer = [0u8; MAX_DUMP_LENGTH];
+    let buffer = &mut buffer[..count * block_size];
+
+    if let Err(error) = device.read_blocks(first_block, buffer) {
+        debug_println!("Failed to read block {}: {}.", first_block, error);
+        return;
+    }
+
+    // The dump is labeled with byte offsets on the device.
+    let start = first_block * block_size;
+    print_hex_dump(start, buffer.len(), |offset| buffer[offset - start]);
+}
+
+/// Lists the devices the device manager found, with their drivers, register
+/// addresses, interrupts, and states.
+pub fn devices(_arguments: &mut dyn Iterator<Item = &str>) {
+    devices::for_each_device(|device, driver_name, state| {
+        debug_print!("{:<24} {:<12}", device.name(), driver_name);
+
+        match device.regs().first() {
+            Some(range) => debug_print!(" {:#012x}", range.physical_address),
+            None => debug_print!(" {:<12}", "-"),
+        }
+
+        match device.first_irq() {
+            Some(irq) => debug_print!(" irq {:<4}", irq),
+            None => debug_print!(" {:<8}", "-"),
+        }
+
+        debug_println!(" {}", state);
+    });
+}
+
+/// Prints the MAC address and the counters of the network device, the
+/// addresses of the interface, and the ARP cache.
+pub fn network(_arguments: &mut dyn Iterator<Item = &str>) {
+    let mac_address = match virtio::net::mac_address() {
+        Ok(mac_address) => mac_address,
+        Err(error) => {
+            debug_println!("{}.", error);
+            return;
+        }
+    };
+
+    let statistics = virtio::net::statistics();
+
+    debug_println!("MAC address:     {}", mac_address);
+    debug_println!("Received frames: {}", statistics.received_frames);
+    debug_println!("Sent frames:     {}", statistics.sent_frames);
+    debug_println!("Dropped frames:  {}", statistics.dropped_frames);
+
+    match net::configuration() {
+        Some(configuration) => {
+            debug_println!(
+                "Address:         {}/{}",
+                configuration.address,
+                configuration.prefix_length
+            );
+
+            if let Some(gateway) = configuration.gateway {
+                debug_println!("Gateway:         {}", gateway);
+            }
+        }
+        None => debug_println!("Address:         none"),
+    }
+
+    debug_println!("ARP cache:");
+
+    net::arp::for_each_entry(|address, mac_address| {
+        debug_println!("  {:<16}{}", address, mac_address);
+    });
+}
+
+/// Pings an IPv4 address once a second and prints the round trip times.
+pub fn ping(arguments: &mut dyn Iterator<Item = &str>) {
+    const DEFAULT_COUNT: usize = 4;
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    let Some(destination) = arguments.next().and_then(Ipv4Address::parse) else {
+        debug_println!("Usage: ping <address> [count]");
+        return;
+    };
+
+    let count = match arguments.next() {
+        Some(text) => match parse_number(text) {
+            Some(count) => count,
+            None => {
+                debug_println!("Invalid count \"{}\".", text);
+                return;
+            }
+        },
+        None => DEFAULT_COUNT,
+    };
+
+    for index in 0..count {
+        match net::icmp::ping(destination, TIMEOUT) {
+            Ok((sequence, round_trip_time)) => debug_println!(
+                "Reply from {}: sequence {}, time {}us",
+                destination,
+                sequence,
+                round_trip_time.as_micros()
+            ),
+            Err(error) => debug_println!("No reply from {}: {}.", destination, error),
+        }
+
+        if index + 1 < count {
+            task::sleep(TIMEOUT);
+        }
+    }
+}
+
+/// Prints random bytes from the entropy pool.
+pub fn random_bytes(arguments: &mut dyn Iterator<Item = &str>) {
+    const DEFAULT_LENGTH: usize = 16;
+
+    let length = match arguments.next() {
+        Some(text) => match parse_number(text) {
+            Some(length) => length.min(MAX_DUMP_LENGTH),
+            None => {
+                debug_println!("Invalid length \"{}\".", text);
+                return;
+            }
+        },
+        None => DEFAULT_LENGTH,
+    };
+
+    let mut buffer = [0u8; MAX_DUMP_LENGTH];
+    let buffer = &mut buffer[..length];
+
+    random::random_bytes(buffer);
+
+    if !random::is_seeded() {
+        debug_println!("The entropy pool is not seeded, so these bytes are predictable.");
+    }
+
+    print_hex_dump(0, buffer.len(), |offset| buffer[offset]);
+}
+
+/// Prints the retained kernel log.
+pub fn kernel_log(_arguments: &mut dyn Iterator<Item = &str>) {
+    log::replay();
+}
+
+/// Prints the mappings of the active page tables, merging neighboring pages
+/// that map contiguous physical memory with the same flags into one line. With
+/// "ranges", merges neighboring entries of the same size with the same flags
+/// instead, whatever physical memory they map.
+pub fn page_tables(arguments: &mut dyn Iterator<Item = &str>) {
+    match arguments.next() {
+        None => {}
+        Some("ranges") => {
+            let _ = write_leaf_ranges(&mut ConsoleWriter, active_root_page_table(), 2);
+            return;
+        }
+        Some(_) => {
+            debug_println!("Usage: pt [ranges]");
+            return;
+        }
+    }
+
+    debug_println!(
+        "Virtual address range                    Physical address    Size        Flags"
+    );
+
+    let mut pending_mapping: Option<Mapping> = None;
+
+    for_each_leaf(active_root_page_table(), &mut |leaf| {
+        let mapping = Mapping::from(leaf);
+
+        match &mut pending_mapping {
+            Some(pending) if pending.can_extend_with(&mapping) => pending.size += mapping.size,
+            _ => {
+                if let Some(pending) = pending_mapping.replace(mapping) {
+                    pending.print();
+                }
+            }
+        }
+    });
+
+    if let Some(pending) = pending_mapping {
+        pending.print();
+    }
+}
+
+/// Prints the physical memory, memory reservations, and reserved memory
+/// regions described by the DTB.
+pub fn memory_map(_arguments: &mut dyn Iterator<Item = &str>) {
+    let Some(dtb) = kernel_dtb() else {
+        debug_println!("The DTB is not available.");
+        return;
+    };
+
+    debug_println!("Memory:");
+    for node in dtb
+        .nodes()
+        .filter(|node| node.name == "memory" || node.name.starts_with("memory@"))
+    {
+        print_reg_ranges(node);
+    }
+
+    debug_println!("Memory reservations:");
+    walk_memory_reservation_entries(&dtb, |entry| {
+        print_range(entry.address, entry.size, "");
+    });
+
+    debug_println!("Reserved memory:");
+    for node in dtb
+        .root_node()
+        .and_then(|root_node| root_node.child("reserved-memory"))
+        .into_iter()
+        .flat_map(|node| node.children())
+    {
+        print_reg_ranges(node);
+    }
+}
+
+/// Prints how physical memory is used.
+pub fn memory_stats(_arguments: &mut dyn Iterator<Item = &str>) {
+    debug_println!("{}", memory::stats());
+}
+
+/// Lists the allocated frames of the frame pool with their references and,
+/// with the `frame-debug` feature, the code that allocated them.
+pub fn allocated_frames(_arguments: &mut dyn Iterator<Item = &str>) {
+    let mut frame_count = 0;
+    let mut are_sites_known = false;
+
+    memory::for_each_allocated_frame(|frame| {
+        frame_count += 1;
+
+        debug_print!(
+            "  {:#014x} {:>2} refs {:<10}",
+            frame.ppn.to_physical_address(),
+            frame.reference_count,
+            if frame.is_page_table {
+                "page table"
+            } else {
+                ""
+            }
+        );
+
+        match frame.allocation_site {
+            Some(address) => {
+                are_sites_known = true;
+                debug_println!(" {}", Symbolized(address));
+            }
+            None => debug_println!(),
+        }
+    });
+
+    debug_println!("  {} frames allocated.", frame_count);
+
+    if frame_count != 0 && !are_sites_known {
+        debug_println!("  Build with the frame-debug feature to see where they were allocated.");
+    }
+}
+
+/// Lists every hart in the DTB with its kernel and SBI state.
+pub fn harts(_arguments: &mut dyn Iterator<Item = &str>) {
+    let Some(dtb) = kernel_dtb() else {
+        debug_println!("The DTB is not available.");
+        return;
+    };
+
+    let current_hart_id = hart::current_hart_id();
+
+    for cpu in dtb::cpus(&dtb) {
+        let hart_id = cpu.hart_id as usize;
+
+        debug_print!(
+            "  Hart {}: {}, {}",
+            hart_id,
+            if cpu.is_enabled() {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            if hart::is_hart_online(hart_id) {
+                "online"
+            } else {
+                "offline"
+            }
+        );
+
+        match hart_get_status(hart_id) {
+            Ok(state) => debug_print!(", HSM state {:?}", state),
+            Err(error) => debug_print!(", HSM state unknown ({})", error),
+        }
+
+        if let Some(per_hart) = percpu::get(hart_id)
+            && hart::is_hart_online(hart_id)
+        {
+            debug_print!(
+                ", {} traps, {} timer ticks",
+                per_hart.trap_count.load(Ordering::Relaxed),
+                per_hart.timer_tick_count.load(Ordering::Relaxed)
+            );
+        }
+
+        if hart_id == current_hart_id {
+            debug_print!(" (current)");
+        }
+
+        debug_println!();
+    }
+}
+
+/// Lists every device interrupt that has a handler or was handled, with how
+/// often and for how long it was handled.
+pub fn interrupts(_arguments: &mut dyn Iterator<Item = &str>) {
+    irq::for_each_irq(|irq, handler, statistics| {
+        debug_print!(
+            "  {:>4}: {} interrupts, {} us total, {} us longest",
+            irq,
+            statistics.count,
+            statistics.total_time.as_micros(),
+            statistics.longest_time.as_micros()
+        );
+
+        match handler {
+            Some(address) => debug_println!(", handler {}", Symbolized(address)),
+            None => debug_println!(", no handler"),
+        }
+    });
+
+    debug_println!("  {} interrupts without a handler", irq::unhandled_count());
+    debug_println!(
+        "  {} interrupt exits deferred softirqs",
+        softirq::deferred_count()
+    );
+}
+
+/// Lists every task with its state and the CPU time it used.
+pub fn tasks(_arguments: &mut dyn Iterator<Item = &str>) {
+    task::for_each_task(|id, name, state, cpu_time| {
+        debug_println!(
+            "  {:>3} {:<16} {:>8} ms {:?}",
+            id,
+            name,
+            cpu_time.as_millis(),
+            state
+        );
+    });
+}
+
+/// Lists how busy every online hart has been since it started scheduling
+/// tasks.
+pub fn cpu_usage(_arguments: &mut dyn Iterator<Item = &str>) {
+    for hart_id in (0..hart::MAX_HART_COUNT).filter(|&hart_id| hart::is_hart_online(hart_id)) {
+        let Some(usage) = task::hart_cpu_usage(hart_id) else {
+            continue;
+        };
+
+        debug_println!(
+            "  Hart {}: {:>3}% busy, {} ms busy, {} ms idle",
+            hart_id,
+            usage.busy_percent(),
+            usage.busy_time.as_millis(),
+            usage.idle_time.as_millis()
+        );
+    }
+}
+
+/// Lists the kernel stacks with the most of each that has been used.
+pub fn stacks(_arguments: &mut dyn Iterator<Item = &str>) {
+    stack_guard::for_each_stack(|owner, usage| {
+        match owner {
+            StackOwner::BootHart => debug_print!("  {:<28}", "Boot hart"),
+            StackOwner::Hart(hart_id) => debug_print!("  Hart {:<23}", hart_id),
+            StackOwner::Overflow(hart_id) => debug_print!("  Overflow {:<19}", hart_id),
+            StackOwner::Thread { id, name } => debug_print!("  Thread {:>3} {:<16}", id, name),
+        }
+
+        debug_println!(
+            " {:>6} of {:>6} bytes used ({:>3}%)",
+            usage.high_water_mark,
+            usage.size,
+            usage.used_percent()
+        );
+    });
+}
+
+/// Lists the performance counters and the events counted in every critical
+/// section measured with `pmu::measure`, averaged over its runs.
+pub fn performance_counters(_arguments: &mut dyn Iterator<Item = &str>) {
+    let result = pmu::for_each_counter(|index, info| {
+        if info.is_firmware() {
+            debug_println!("  Counter {:>2}: firmware", index);
+        } else {
+            debug_println!(
+                "  Counter {:>2}: CSR {:#x}, {} bits",
+                index,
+                info.csr(),
+                info.width()
+            );
+        }
+    });
+
+    if let Err(error) = result {
+        debug_println!("  Failed to describe the counters: {}.", error);
+    }
+
+    pmu::for_each_section(|section| {
+        debug_println!("  Section {}, {} runs:", section.name, section.run_count);
+
+        for event in PmuEvent::ALL {
+            match section.totals.get(event) {
+                Some(total) => debug_println!(
+                    "    {:<16} {:>12} per run",
+                    event,
+                    total / section.run_count
+                ),
+                None => debug_println!("    {:<16} {:>12}", event, "not counted"),
+            }
+        }
+    });
+}
+
+/// Prints the trace events every hart recorded, or clears them.
+pub fn trace_events(arguments: &mut dyn Iterator<Item = &str>) {
+    match arguments.next() {
+        None => trace::dump(),
+        Some("clear") => trace::clear(),
+        Some(_) => debug_println!("Usage: trace [clear]"),
+    }
+}
+
+/// Lists the user processes with their parents and programs.
+pub fn processes(_arguments: &mut dyn Iterator<Item = &str>) {
+    process::for_each_process(|id, parent_id, name, exit_code| {
+        match parent_id {
+            Some(parent_id) => debug_print!("  {:>3} {:<16} parent {:<3}", id, name, parent_id),
+            None => debug_print!("  {:>3} {:<16} {:<10}", id, name, ""),
+        }
+
+        match exit_code {
+            Some(exit_code) => debug_println!(" zombie, exited with code {}", exit_code),
+            None => debug_println!(),
+        }
+    });
+}
+
+/// Starts a process running a program.
+pub fn run(arguments: &mut dyn Iterator<Item = &str>) {
+    let Some(name) = arguments.next() else {
+        debug_println!("Usage: run <program>");
+        return;
+    };
+
+    match process::spawn(name) {
+        Ok(id) => debug_println!("Started process {}.", id),
+        Err(error) => debug_println!("Failed to start \"{}\": {}.", name, error),
+    }
+}
+
+/// Lists a directory, or prints the size of a file.
+pub fn list_directory(arguments: &mut dyn Iterator<Item = &str>) {
+    let path = arguments.next().unwrap_or("/");
+
+    let node = match fs::open(path) {
+        Ok(node) => node,
+        Err(error) => {
+            debug_println!("Failed to open \"{}\": {}.", path, error);
+            return;
+        }
+    };
+
+    if !node.is_directory() {
+        debug_println!("{:>10} {}", node.size(), path);
+        return;
+    }
+
+    let result = node.for_each_entry(|name, kind, size| match kind {
+        NodeKind::Directory => debug_println!("{:>10} {}/", "", name),
+        NodeKind::File => debug_println!("{:>10} {}", size, name),
+    });
+
+    if let Err(error) = result {
+        debug_println!("Failed to read \"{}\": {}.", path, error);
+    }
+}
+
+/// Lists the mounted file systems.
+pub fn list_mounts(_arguments: &mut dyn Iterator<Item = &str>) {
+    fs::for_each_mount(|path, type_name| debug_println!("{:<10} {}", path, type_name));
+}
+
+/// Prints a file.
+pub fn print_file(arguments: &mut dyn Iterator<Item = &str>) {
+    let Some(path) = arguments.next() else {
+        debug_println!("Usage: cat <path>");
+        return;
+    };
+
+    let node = match fs::open(path) {
+        Ok(node) => node,
+        Err(error) => {
+            debug_println!("Failed to open \"{}\": {}.", path, error);
+            return;
+        }
+    };
+
+    let mut buffer = [0; 256];
+    let mut offset = 0;
+    let mut last_byte = b'\n';
+
+    loop {
+        let length = match node.read(offset, &mut buffer) {
+            Ok(0) => break,
+            Ok(length) => length,
+            Err(error) => {
+                debug_println!("\nFailed to read \"{}\": {}.", path, error);
+                return;
+            }
+        };
+
+        for &byte in &buffer[..length] {
+            let is_text = byte.is_ascii_graphic() || byte == b' ' || byte == b'\n';
+            debug_print!("{}", if is_text { byte as char } else { '.' });
+        }
+
+        offset += length;
+        last_byte = buffer[length - 1];
+    }
+
+    if last_byte != b'\n' {
+        debug_println!();
+    }
+}
+
+/// Replaces the contents of a file with the rest of the arguments, separated
+/// by spaces, and a line break.
+pub fn write_file(arguments: &mut dyn Iterator<Item = &str>) {
+    let Some(path) = arguments.next() else {
+        debug_println!("Usage: write <path> [text...]");
+        return;
+    };
+
+    let result = fs::open(path)
+        .or_else(|error| match error {
+            FsError::NotFound => fs::create(path, NodeKind::File),
+            error => Err(error),
+        })
+        .and_then(|mut node| {
+            node.truncate(0)?;
+
+            let mut offset = 0;
+            for (index, word) in arguments.enumerate() {
+                if index > 0 {
+                    node.write(offset, b" ")?;
+                    offset += 1;
+                }
+
+                node.write(offset, word.as_bytes())?;
+                offset += word.len();
+            }
+
+            node.write(offset, b"\n")
+        });
+
+    if let Err(error) = result {
+        debug_println!("Failed to write \"{}\": {}.", path, error);
+    }
+}
+
+/// Creates a directory.
+pub fn make_directory(arguments: &mut dyn Iterator<Item = &str>) {
+    let Some(path) = arguments.next() else {
+        debug_println!("Usage: mkdir <path>");
+        return;
+    };
+
+    if let Err(error) = fs::create(path, NodeKind::Directory) {
+        debug_println!("Failed to create \"{}\": {}.", path, error);
+    }
+}
+
+/// Removes a file or an empty directory.
+pub fn remove(arguments: &mut dyn Iterator<Item = &str>) {
+    let Some(path) = arguments.next() else {
+        debug_println!("Usage: rm <path>");
+        return;
+    };
+
+    if let Err(error) = fs::remove(path) {
+        debug_println!("Failed to remove \"{}\": {}.", path, error);
+    }
+}
+
+/// Powers off the system.
+pub fn shutdown(_arguments: &mut dyn Iterator<Item = &str>) {
+    let error = system_reset::shutdown(system_reset::RESET_REASON_NO_REASON);
+    debug_println!("SBI shutdown failed: {}.", error);
+}
+
+/// A run of virtual memory mapped to contiguous physical memory with the same
+/// flags.
+struct Mapping {
+    virtual_address: usize,
+    physical_address: usize,
+    size: usize,
+    flags: [u8; 5],
+}
+
+impl From<&Leaf> for Mapping {
+    fn from(leaf: &Leaf) -> Self {
+        Self {
+            virtual_address: leaf.virtual_address,
+            physical_address: leaf.entry.get_ppn().to_physical_address(),
+            size: leaf.size(),
+            flags: leaf.flag_characters(),
+        }
+    }
+}
+
+impl Mapping {
+    /// Returns true if `next` starts where this mapping ends, both virtually
+    /// and physically, and has the same flags.
+    fn can_extend_with(&self, next: &Mapping) -> bool {
+        self.virtual_address.wrapping_add(self.size) == next.virtual_address
+            && self.physical_address + self.size == next.physical_address
+            && self.flags == next.flags
+    }
+
+    fn print(&self) {
+        debug_println!(
+            "{:#018x}-{:#018x}  {:#018x}  {:#010x}  {}",
+            self.virtual_address,
+            self.virtual_address.wrapping_add(self.size - 1),
+            self.physical_address,
+            self.size,
+            core::str::from_utf8(&self.flags).unwrap_or("?????")
+        );
+    }
+}
+
+/// Prints bytes as lines of hexadecimal and ASCII.
+///
+/// # Arguments
+///
+/// * `address` - The address of the first byte, which labels the lines.
+/// * `length` - The number of bytes.
+/// * `read` - Reads the byte at an address.
+fn print_hex_dump(address: usize, length: usize, read: impl Fn(usize) -> u8) {
+    let end = address + length;
+
+    for line_address in (address..end).step_by(BYTES_PER_LINE) {
+        let line_end = end.min(line_address + BYTES_PER_LINE);
+
+        debug_print!("{:016x}: ", line_address);
+
+        for column in 0..BYTES_PER_LINE {
+            match line_address + column {
+                byte_address if byte_address < line_end => {
+                    debug_print!("{:02x} ", read(byte_address))
+                }
+                _ => debug_print!("   "),
+            }
+        }
+
+        debug_print!(" ");
+
+        for byte_address in line_address..line_end {
+            let byte = read(byte_address);
+            let character = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+
+            debug_print!("{}", character);
+        }
+
+        debug_println!();
+    }
+}
+
+/// Prints the "reg" ranges of a DTB node.
+fn print_reg_ranges(node: dtb::DtbNode) {
+    if let Some(property) = node.property("reg") {
+        property.get_property_data_as_reg(&node.parent_cell_info(), |address, size| {
+            print_range(address, size, node.name);
+        });
+    }
+}
+
+/// Prints a physical address range.
+fn print_range(address: u64, size: u64, label: &str) {
+    debug_println!(
+        "  {:#x}-{:#x}, size: {:#x} {}",
+        address,
+        address + size.saturating_sub(1),
+        size,
+        label
+    );
+}
+
+/// Returns the kernel's copy of the DTB.
+fn kernel_dtb() -> Option<Dtb<'static>> {
+    unsafe { Dtb::from_address(memory::layout().dtb_virtual_address) }.ok()
+}
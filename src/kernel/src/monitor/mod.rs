@@ -0,0 +1,277 @@
+//! An interactive debug monitor on the kernel console.
+//!
+//! The monitor runs in its own kernel thread, and the panic handler
+//! hands the console to it so the state of a failed kernel can still be
+//! inspected. Each line typed is split on whitespace into a command and its
+//! arguments. Numbers are decimal, or hexadecimal with a "0x" prefix.
+
+mod commands;
+
+use crate::{console::LineReader, debug_print, debug_println};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The longest command line the monitor accepts.
+const LINE_CAPACITY: usize = 128;
+
+/// The text printed before every command line.
+const PROMPT: &str = "monitor> ";
+
+/// Set once a panic has entered the monitor, so a panic inside the monitor
+/// halts instead of entering it again.
+static ENTERED_FROM_PANIC: AtomicBool = AtomicBool::new(false);
+
+/// A command the monitor understands.
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    run: fn(arguments: &mut dyn Iterator<Item = &str>),
+}
+
+/// Every command, in the order `help` lists them.
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "help",
+        description: "List the commands.",
+        run: commands::help,
+    },
+    Command {
+        name: "md",
+        usage: "md <address> [length]",
+        description: "Dump memory at a virtual address. The length defaults to 64 bytes.",
+        run: commands::memory_dump,
+    },
+    Command {
+        name: "dmesg",
+        usage: "dmesg",
+        description: "Print the kernel log.",
+        run: commands::kernel_log,
+    },
+    Command {
+        name: "pt",
+        usage: "pt [ranges]",
+        description: "Print the mappings of the active page tables, or runs of entries by size and flags.",
+        run: commands::page_tables,
+    },
+    Command {
+        name: "mem",
+        usage: "mem",
+        description: "Print the physical memory map from the DTB.",
+        run: commands::memory_map,
+    },
+    Command {
+        name: "meminfo",
+        usage: "meminfo",
+        description: "Print how physical memory is used.",
+        run: commands::memory_stats,
+    },
+    Command {
+        name: "frames",
+        usage: "frames",
+        description: "List the allocated frames of the frame pool and where they were allocated.",
+        run: commands::allocated_frames,
+    },
+    Command {
+        name: "harts",
+        usage: "harts",
+        description: "List the harts and their states.",
+        run: commands::harts,
+    },
+    Command {
+        name: "irqs",
+        usage: "irqs",
+        description: "List the device interrupts with their handlers and statistics.",
+        run: commands::interrupts,
+    },
+    Command {
+        name: "pmu",
+        usage: "pmu",
+        description: "List the performance counters and the events counted in measured sections.",
+        run: commands::performance_counters,
+    },
+    Command {
+        name: "trace",
+        usage: "trace [clear]",
+        description: "Print the trace events of every hart, or clear them.",
+        run: commands::trace_events,
+    },
+    Command {
+        name: "tasks",
+        usage: "tasks",
+        description: "List the idle tasks and kernel threads with their CPU time.",
+        run: commands::tasks,
+    },
+    Command {
+        name: "cpu",
+        usage: "cpu",
+        description: "Print how busy and idle each hart has been.",
+        run: commands::cpu_usage,
+    },
+    Command {
+        name: "stacks",
+        usage: "stacks",
+        description: "Print the most each kernel stack has been used.",
+        run: commands::stacks,
+    },
+    Command {
+        name: "ps",
+        usage: "ps",
+        description: "List the user processes.",
+        run: commands::processes,
+    },
+    Command {
+        name: "run",
+        usage: "run <program>",
+        description: "Start a process running an embedded program or a file like /bin/init.",
+        run: commands::run,
+    },
+    Command {
+        name: "ls",
+        usage: "ls [path]",
+        description: "List a directory. The path defaults to /.",
+        run: commands::list_directory,
+    },
+    Command {
+        name: "mounts",
+        usage: "mounts",
+        description: "List the mounted file systems.",
+        run: commands::list_mounts,
+    },
+    Command {
+        name: "cat",
+        usage: "cat <path>",
+        description: "Print a file. Bytes other than ASCII text print as '.'.",
+        run: commands::print_file,
+    },
+    Command {
+        name: "write",
+        usage: "write <path> [text...]",
+        description: "Replace the contents of a file with a line of text, creating the file if needed.",
+        run: commands::write_file,
+    },
+    Command {
+        name: "mkdir",
+        usage: "mkdir <path>",
+        description: "Create a directory.",
+        run: commands::make_directory,
+    },
+    Command {
+        name: "rm",
+        usage: "rm <path>",
+        description: "Remove a file or an empty directory.",
+        run: commands::remove,
+    },
+    Command {
+        name: "devices",
+        usage: "devices",
+        description: "List the devices in the DTB that have a driver and their states.",
+        run: commands::devices,
+    },
+    Command {
+        name: "blk",
+        usage: "blk [<device> <block> [count]]",
+        description: "Dump blocks of a block device, or list the devices. The count defaults to 1.",
+        run: commands::block_dump,
+    },
+    Command {
+        name: "net",
+        usage: "net",
+        description: "Print the network device, the interface addresses, and the ARP cache.",
+        run: commands::network,
+    },
+    Command {
+        name: "ping",
+        usage: "ping <address> [count]",
+        description: "Ping an IPv4 address. The count defaults to 4.",
+        run: commands::ping,
+    },
+    Command {
+        name: "random",
+        usage: "random [length]",
+        description: "Print random bytes from the entropy pool. The length defaults to 16 bytes.",
+        run: commands::random_bytes,
+    },
+    Command {
+        name: "shutdown",
+        usage: "shutdown",
+        description: "Power off the system.",
+        run: commands::shutdown,
+    },
+];
+
+/// The state of a monitor session.
+pub struct Monitor {
+    line_reader: LineReader<LINE_CAPACITY>,
+}
+
+impl Monitor {
+    /// Creates a monitor and prints the first prompt.
+    pub fn new() -> Self {
+        debug_println!("Kernel monitor ready. Type \"help\" for a list of commands.");
+        debug_print!("{}", PROMPT);
+
+        Self {
+            line_reader: LineReader::new(),
+        }
+    }
+
+    /// Runs the command typed so far if its line is complete, without
+    /// blocking.
+    ///
+    /// # Returns
+    ///
+    /// True if a command ran. The console may hold more input in that case.
+    pub fn poll(&mut self) -> bool {
+        let Some(line) = self.line_reader.poll_line() else {
+            return false;
+        };
+
+        execute(line);
+        debug_print!("{}", PROMPT);
+
+        true
+    }
+
+    /// Runs commands forever, spinning while waiting for input.
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.poll();
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Hands the console to the monitor after a panic.
+///
+/// Only the first hart to panic enters the monitor. Other harts, and a panic
+/// raised by the monitor itself, return so the caller can halt.
+pub fn enter_from_panic() {
+    if ENTERED_FROM_PANIC.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    Monitor::new().run();
+}
+
+/// Parses and runs a command line.
+fn execute(line: &str) {
+    let mut words = line.split_whitespace();
+
+    let Some(name) = words.next() else {
+        return;
+    };
+
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => (command.run)(&mut words),
+        None => debug_println!("Unknown command \"{}\". Type \"help\" for a list.", name),
+    }
+}
+
+/// Parses a number in decimal, or in hexadecimal with a "0x" prefix.
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => usize::from_str_radix(digits, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
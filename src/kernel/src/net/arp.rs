@@ -0,0 +1,177 @@
+//! Resolving IPv4 addresses on the subnet to hardware addresses with ARP.
+//!
+//! Resolved addresses are kept in a small cache. Entries do not expire but
+//! are replaced oldest first, and refreshed whenever their host sends an ARP
+//! packet to the interface.
+
+use super::device;
+use crate::task;
+use core::time::Duration;
+use kernel_lib::{
+    net::{
+        Ipv4Address, MacAddress,
+        arp::{self, ArpOperation, ArpPacket},
+        ethernet::{self, ETHER_TYPE_ARP},
+    },
+    sync::SpinLock,
+};
+
+/// The number of cached addresses.
+const CACHE_SIZE: usize = 8;
+
+/// The number of requests sent before a host is deemed unreachable.
+const REQUEST_COUNT: usize = 3;
+
+/// How long to wait for a reply to each request.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How often the cache is checked for a reply while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The size of an ARP frame. Shorter than the minimum Ethernet frame, which
+/// the device pads.
+const FRAME_SIZE: usize = ethernet::HEADER_SIZE + arp::PACKET_SIZE;
+
+/// Recently resolved addresses.
+struct ArpCache {
+    entries: [Option<(Ipv4Address, MacAddress)>; CACHE_SIZE],
+
+    /// The entry replaced next when a new address is cached.
+    next_index: usize,
+}
+
+impl ArpCache {
+    /// Returns the hardware address of an IPv4 address, if it is cached.
+    fn get(&self, address: Ipv4Address) -> Option<MacAddress> {
+        self.entries
+            .iter()
+            .flatten()
+            .find(|(cached_address, _)| *cached_address == address)
+            .map(|(_, mac_address)| *mac_address)
+    }
+
+    /// Caches the hardware address of an IPv4 address, replacing the oldest
+    /// entry if the address is not cached yet.
+    fn insert(&mut self, address: Ipv4Address, mac_address: MacAddress) {
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .flatten()
+            .find(|(cached_address, _)| *cached_address == address)
+        {
+            entry.1 = mac_address;
+            return;
+        }
+
+        self.entries[self.next_index] = Some((address, mac_address));
+        self.next_index = (self.next_index + 1) % CACHE_SIZE;
+    }
+}
+
+static CACHE: SpinLock<ArpCache> = SpinLock::new(ArpCache {
+    entries: [None; CACHE_SIZE],
+    next_index: 0,
+});
+
+/// Calls a callback with every cached address and its hardware address.
+pub fn for_each_entry(mut callback: impl FnMut(Ipv4Address, MacAddress)) {
+    // The entries are copied so the callback runs without the lock.
+    let entries = CACHE.lock().entries;
+
+    for (address, mac_address) in entries.into_iter().flatten() {
+        callback(address, mac_address);
+    }
+}
+
+/// Caches a hardware address learned from a received packet.
+pub(super) fn learn(address: Ipv4Address, mac_address: MacAddress) {
+    CACHE.lock().insert(address, mac_address);
+}
+
+/// Finds the hardware address of a host on the subnet, sending requests and
+/// waiting for a reply if it is not cached. Blocks the calling kernel thread,
+/// which must not be the "net" thread.
+///
+/// # Arguments
+///
+/// * `address` - The address of the host.
+/// * `source` - The address of the interface.
+///
+/// # Returns
+///
+/// The hardware address, or `None` if the host did not reply.
+pub(super) fn resolve(address: Ipv4Address, source: Ipv4Address) -> Option<MacAddress> {
+    if let Some(mac_address) = CACHE.lock().get(address) {
+        return Some(mac_address);
+    }
+
+    let source_mac = device::mac_address().ok()?;
+
+    for _ in 0..REQUEST_COUNT {
+        let request = ArpPacket {
+            operation: ArpOperation::Request,
+            sender_mac: source_mac,
+            sender_ip: source,
+            target_mac: MacAddress::default(),
+            target_ip: address,
+        };
+
+        send_packet(&request, MacAddress::BROADCAST).ok()?;
+
+        for _ in 0..REPLY_TIMEOUT.as_millis() / POLL_INTERVAL.as_millis() {
+            task::sleep(POLL_INTERVAL);
+
+            if let Some(mac_address) = CACHE.lock().get(address) {
+                return Some(mac_address);
+            }
+        }
+    }
+
+    None
+}
+
+/// Handles a received ARP packet, caching the sender of packets addressed to
+/// the interface and replying to requests for its address.
+///
+/// # Arguments
+///
+/// * `bytes` - The packet.
+/// * `mac_address` - The hardware address of the interface.
+pub(super) fn handle_packet(bytes: &[u8], mac_address: MacAddress) {
+    let Some(packet) = ArpPacket::parse(bytes) else {
+        return;
+    };
+
+    let Some(configuration) = super::configuration() else {
+        return;
+    };
+
+    if packet.target_ip != configuration.address {
+        return;
+    }
+
+    learn(packet.sender_ip, packet.sender_mac);
+
+    if packet.operation == ArpOperation::Request {
+        let reply = ArpPacket {
+            operation: ArpOperation::Reply,
+            sender_mac: mac_address,
+            sender_ip: configuration.address,
+            target_mac: packet.sender_mac,
+            target_ip: packet.sender_ip,
+        };
+
+        // A lost reply is answered by the host asking again.
+        let _ = send_packet(&reply, packet.sender_mac);
+    }
+}
+
+/// Sends an ARP packet.
+fn send_packet(packet: &ArpPacket, destination: MacAddress) -> Result<(), device::NetError> {
+    let mut frame = [0; FRAME_SIZE];
+
+    ethernet::write_header(&mut frame, destination, packet.sender_mac, ETHER_TYPE_ARP);
+    packet.write(&mut frame[ethernet::HEADER_SIZE..]);
+
+    device::send_frame(&frame)
+}
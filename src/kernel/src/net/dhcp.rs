@@ -0,0 +1,153 @@
+//! A DHCP client that configures the interface at boot.
+//!
+//! The lease is taken once and never renewed, which is enough for QEMU's user
+//! mode network, whose leases outlast any test run.
+
+use super::{Configuration, configure, device, udp::UdpSocket};
+use crate::{debug_println, random, task, time::Instant};
+use core::time::Duration;
+use kernel_lib::net::{
+    Ipv4Address,
+    dhcp::{self, DhcpMessage, MessageType},
+};
+
+/// The number of times the exchange is tried before giving up.
+const ATTEMPT_COUNT: usize = 4;
+
+/// How long to wait for each reply of the server.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the socket is checked for a reply while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The prefix length used when the server sends no subnet mask.
+const DEFAULT_PREFIX_LENGTH: u8 = 24;
+
+/// Kernel thread that leases an address and configures the interface with
+/// it.
+pub fn run(_argument: usize) {
+    let Ok(mac_address) = device::mac_address() else {
+        return;
+    };
+
+    let socket = match UdpSocket::bind(dhcp::CLIENT_PORT) {
+        Ok(socket) => socket,
+        Err(error) => {
+            debug_println!("DHCP failed: {}.", error);
+            return;
+        }
+    };
+
+    let transaction_id = random::random_u64() as u32;
+
+    let mut message = [0; dhcp::MESSAGE_SIZE];
+
+    for _ in 0..ATTEMPT_COUNT {
+        let length = dhcp::write_discover(&mut message, transaction_id, mac_address);
+
+        if let Err(error) = socket.send_to(
+            &message[..length],
+            Ipv4Address::BROADCAST,
+            dhcp::SERVER_PORT,
+        ) {
+            debug_println!("DHCP failed: {}.", error);
+            return;
+        }
+
+        let Some(offer) = receive_reply(&socket, transaction_id, MessageType::Offer) else {
+            continue;
+        };
+
+        let Some(server_identifier) = offer.server_identifier else {
+            continue;
+        };
+
+        let length = dhcp::write_request(
+            &mut message,
+            transaction_id,
+            mac_address,
+            offer.your_address,
+            server_identifier,
+        );
+
+        if let Err(error) = socket.send_to(
+            &message[..length],
+            Ipv4Address::BROADCAST,
+            dhcp::SERVER_PORT,
+        ) {
+            debug_println!("DHCP failed: {}.", error);
+            return;
+        }
+
+        let Some(acknowledgment) = receive_reply(&socket, transaction_id, MessageType::Acknowledge)
+        else {
+            continue;
+        };
+
+        configure(Configuration {
+            address: acknowledgment.your_address,
+            prefix_length: acknowledgment
+                .subnet_mask
+                .and_then(|mask| mask.prefix_length())
+                .unwrap_or(DEFAULT_PREFIX_LENGTH),
+            gateway: acknowledgment.router,
+        });
+
+        if let Some(lease_time) = acknowledgment.lease_time {
+            debug_println!(
+                "Leased from DHCP server {} for {} seconds.",
+                server_identifier,
+                lease_time
+            );
+        }
+
+        return;
+    }
+
+    debug_println!("No DHCP server answered.");
+}
+
+/// Waits for the server's reply in a transaction.
+///
+/// # Arguments
+///
+/// * `socket` - The client's socket.
+/// * `transaction_id` - The transaction.
+/// * `message_type` - The type of reply expected.
+///
+/// # Returns
+///
+/// The reply, or `None` if none arrived in time or the server refused the
+/// request.
+fn receive_reply(
+    socket: &UdpSocket,
+    transaction_id: u32,
+    message_type: MessageType,
+) -> Option<DhcpMessage> {
+    let start = Instant::now();
+    let mut buffer = [0; dhcp::MESSAGE_SIZE * 2];
+
+    while start.elapsed() < REPLY_TIMEOUT {
+        while let Some((length, _, _)) = socket.try_receive_from(&mut buffer) {
+            let Some(reply) = DhcpMessage::parse(&buffer[..length]) else {
+                continue;
+            };
+
+            if reply.transaction_id != transaction_id {
+                continue;
+            }
+
+            if reply.message_type == message_type {
+                return Some(reply);
+            }
+
+            if reply.message_type == MessageType::NegativeAcknowledge {
+                return None;
+            }
+        }
+
+        task::sleep(POLL_INTERVAL);
+    }
+
+    None
+}
@@ -0,0 +1,135 @@
+//! Answering pings, and pinging other hosts.
+
+use super::{NetworkError, PAYLOAD_OFFSET, arp, device, send_ipv4_packet};
+use crate::{task, time::Instant};
+use core::{
+    sync::atomic::{AtomicU16, AtomicU32, Ordering},
+    time::Duration,
+};
+use kernel_lib::net::{
+    Ipv4Address, MacAddress,
+    icmp::{self, EchoMessage},
+    ipv4::{Ipv4Packet, PROTOCOL_ICMP},
+};
+
+/// The identifier of the kernel's echo requests.
+const ECHO_IDENTIFIER: u16 = 0x5243;
+
+/// The data of the kernel's echo requests.
+const ECHO_DATA: &[u8] = b"riscos ping";
+
+/// How often `ping` checks for the reply while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The sequence number of the next echo request.
+static NEXT_SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+/// The sequence number of the last echo reply received, or `u32::MAX` if
+/// none has been.
+static LAST_REPLY_SEQUENCE: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// Sends an echo request and waits for the reply. Blocks the calling kernel
+/// thread, which must not be the "net" thread. Pings from several threads at
+/// once may miss each other's replies.
+///
+/// # Arguments
+///
+/// * `destination` - The host to ping.
+/// * `timeout` - How long to wait for the reply.
+///
+/// # Returns
+///
+/// The sequence number of the request and the round trip time.
+pub fn ping(destination: Ipv4Address, timeout: Duration) -> Result<(u16, Duration), NetworkError> {
+    let (destination_mac, source) = super::route(destination)?;
+    let sequence = NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+
+    let mut frame = [0; device::MAX_FRAME_SIZE];
+    let length = icmp::write_echo(
+        &mut frame[PAYLOAD_OFFSET..],
+        true,
+        ECHO_IDENTIFIER,
+        sequence,
+        ECHO_DATA,
+    );
+
+    let start = Instant::now();
+
+    send_ipv4_packet(
+        &mut frame,
+        destination_mac,
+        source,
+        destination,
+        PROTOCOL_ICMP,
+        length,
+    )?;
+
+    while start.elapsed() < timeout {
+        if LAST_REPLY_SEQUENCE.load(Ordering::Acquire) == sequence as u32 {
+            return Ok((sequence, start.elapsed()));
+        }
+
+        task::sleep(POLL_INTERVAL);
+    }
+
+    Err(NetworkError::TimedOut)
+}
+
+/// Handles a received ICMP message, replying to echo requests to the
+/// interface's address and recording the replies to `ping`.
+///
+/// # Arguments
+///
+/// * `packet` - The IPv4 packet that carried the message.
+/// * `source_mac` - The hardware address of the host that sent the frame.
+pub(super) fn handle_packet(packet: &Ipv4Packet, source_mac: MacAddress) {
+    let Some(message) = EchoMessage::parse(packet.payload) else {
+        return;
+    };
+
+    if !message.is_request {
+        if message.identifier == ECHO_IDENTIFIER {
+            LAST_REPLY_SEQUENCE.store(message.sequence as u32, Ordering::Release);
+        }
+
+        return;
+    }
+
+    // Broadcast pings are not answered.
+    let Some(configuration) = super::configuration() else {
+        return;
+    };
+
+    if packet.destination != configuration.address {
+        return;
+    }
+
+    // The host asking is on the subnet, or the frame came through the
+    // gateway, so its sender is the next hop back either way. Replying there
+    // directly keeps this thread from waiting on ARP.
+    if packet
+        .source
+        .is_in_subnet(configuration.address, configuration.prefix_length)
+    {
+        arp::learn(packet.source, source_mac);
+    }
+
+    let mut frame = [0; device::MAX_FRAME_SIZE];
+    let length = icmp::write_echo(
+        &mut frame[PAYLOAD_OFFSET..],
+        false,
+        message.identifier,
+        message.sequence,
+        message.data,
+    );
+
+    // A lost reply is a lost ping, which the host notices.
+    let _ = send_ipv4_packet(
+        &mut frame,
+        source_mac,
+        configuration.address,
+        packet.source,
+        PROTOCOL_ICMP,
+        length,
+    );
+}
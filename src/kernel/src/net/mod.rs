@@ -0,0 +1,309 @@
+//! The IPv4 network stack on the virtio network device.
+//!
+//! The interface is configured by the "ip" command line option, such as
+//! "ip=10.0.2.15/24" with an optional "gateway=10.0.2.2", or by DHCP when the
+//! option is absent or "ip=dhcp". The "net" kernel thread receives every
+//! frame, answers ARP requests and pings, and queues UDP datagrams on the
+//! sockets bound to their ports.
+//!
+//! Sending resolves the next hop with ARP and may block, so it must be done
+//! from kernel threads other than the "net" thread, which only replies to
+//! the hardware address a frame came from.
+
+pub mod arp;
+pub mod dhcp;
+pub mod icmp;
+pub mod udp;
+
+use crate::{cmdline, debug_println, drivers::virtio::net as device, task};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU16, Ordering},
+};
+use kernel_lib::{
+    net::{
+        Ipv4Address, MacAddress,
+        ethernet::{self, ETHER_TYPE_ARP, ETHER_TYPE_IPV4, EthernetFrame},
+        ipv4::{self, Ipv4Packet, PROTOCOL_ICMP, PROTOCOL_UDP},
+    },
+    sync::SpinLock,
+};
+
+/// The offset of the IPv4 payload in a frame.
+const PAYLOAD_OFFSET: usize = ethernet::HEADER_SIZE + ipv4::HEADER_SIZE;
+
+/// The largest IPv4 payload a frame holds.
+pub const MAX_PAYLOAD_SIZE: usize = device::MAX_FRAME_SIZE - PAYLOAD_OFFSET;
+
+/// The reasons a packet could not be sent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    /// The interface has no address yet.
+    NotConfigured,
+
+    /// The destination is outside the subnet and there is no gateway, or the
+    /// next hop did not answer ARP requests.
+    Unreachable(Ipv4Address),
+
+    /// No reply arrived in time.
+    TimedOut,
+
+    /// The payload does not fit in a frame. Holds its length.
+    PayloadTooLarge(usize),
+
+    /// A socket is bound to the port already.
+    AddressInUse(u16),
+
+    /// All sockets are in use.
+    TooManySockets,
+
+    /// The network device failed.
+    Device(device::NetError),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotConfigured => write!(f, "the interface has no address"),
+            Self::Unreachable(address) => write!(f, "{} is unreachable", address),
+            Self::TimedOut => write!(f, "timed out"),
+            Self::PayloadTooLarge(length) => {
+                write!(f, "the {} byte payload is too large", length)
+            }
+            Self::AddressInUse(port) => write!(f, "port {} is in use", port),
+            Self::TooManySockets => write!(f, "all {} sockets are in use", udp::MAX_SOCKETS),
+            Self::Device(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<device::NetError> for NetworkError {
+    fn from(error: device::NetError) -> Self {
+        Self::Device(error)
+    }
+}
+
+/// The addresses of the interface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Configuration {
+    pub address: Ipv4Address,
+    pub prefix_length: u8,
+
+    /// The router for destinations outside the subnet, if there is one.
+    pub gateway: Option<Ipv4Address>,
+}
+
+impl Configuration {
+    /// Returns the broadcast address of the subnet.
+    pub fn subnet_broadcast(&self) -> Ipv4Address {
+        let mask = u32::from_be_bytes(Ipv4Address::mask(self.prefix_length).0);
+
+        Ipv4Address((u32::from_be_bytes(self.address.0) | !mask).to_be_bytes())
+    }
+}
+
+/// The configuration of the interface, or `None` before it has an address.
+static CONFIGURATION: SpinLock<Option<Configuration>> = SpinLock::new(None);
+
+/// The identification of the next IPv4 packet sent.
+static NEXT_IDENTIFICATION: AtomicU16 = AtomicU16::new(1);
+
+/// Starts the network stack on the initialized network device: the "net"
+/// thread, and the DHCP client unless the command line gives an address.
+pub fn initialize() {
+    let command_line = cmdline::command_line();
+
+    match command_line.get_str("ip") {
+        None | Some("dhcp") => {
+            if let Err(error) = task::spawn_kernel_thread("dhcp", dhcp::run, 0) {
+                debug_println!("Failed to start the DHCP thread: {}.", error);
+            }
+        }
+        Some(text) => match Ipv4Address::parse_with_prefix(text) {
+            Some((address, prefix_length)) => {
+                let gateway = command_line.get_str("gateway").and_then(Ipv4Address::parse);
+
+                configure(Configuration {
+                    address,
+                    prefix_length,
+                    gateway,
+                });
+            }
+            None => debug_println!("Invalid \"ip\" option \"{}\".", text),
+        },
+    }
+
+    if let Err(error) = task::spawn_kernel_thread("net", receive_frames, 0) {
+        debug_println!("Failed to start the network thread: {}.", error);
+    }
+}
+
+/// Returns the configuration of the interface, or `None` before it has an
+/// address.
+pub fn configuration() -> Option<Configuration> {
+    *CONFIGURATION.lock()
+}
+
+/// Sets the addresses of the interface.
+fn configure(configuration: Configuration) {
+    *CONFIGURATION.lock() = Some(configuration);
+
+    match configuration.gateway {
+        Some(gateway) => debug_println!(
+            "Network interface at {}/{} with gateway {}.",
+            configuration.address,
+            configuration.prefix_length,
+            gateway
+        ),
+        None => debug_println!(
+            "Network interface at {}/{}.",
+            configuration.address,
+            configuration.prefix_length
+        ),
+    }
+}
+
+/// Kernel thread that receives every frame and handles it.
+fn receive_frames(_argument: usize) {
+    let mut frame = [0; device::MAX_FRAME_SIZE];
+
+    loop {
+        match device::receive_frame(&mut frame) {
+            Ok(length) => handle_frame(&frame[..length]),
+            Err(device::NetError::NoDevice) => return,
+            Err(_) => {}
+        }
+    }
+}
+
+/// Handles a received frame.
+fn handle_frame(bytes: &[u8]) {
+    let Some(frame) = EthernetFrame::parse(bytes) else {
+        return;
+    };
+
+    let Ok(mac_address) = device::mac_address() else {
+        return;
+    };
+
+    if frame.destination != mac_address && frame.destination != MacAddress::BROADCAST {
+        return;
+    }
+
+    match frame.ether_type {
+        ETHER_TYPE_ARP => arp::handle_packet(frame.payload, mac_address),
+        ETHER_TYPE_IPV4 => {
+            if let Some(packet) = Ipv4Packet::parse(frame.payload) {
+                handle_ipv4_packet(&packet, frame.source);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Handles a received IPv4 packet addressed to the interface's hardware
+/// address.
+///
+/// # Arguments
+///
+/// * `packet` - The packet.
+/// * `source_mac` - The hardware address of the host that sent the frame.
+fn handle_ipv4_packet(packet: &Ipv4Packet, source_mac: MacAddress) {
+    // Before it has an address, the interface takes only broadcasts, which
+    // carry the DHCP server's replies.
+    let is_for_interface = packet.destination == Ipv4Address::BROADCAST
+        || configuration().is_some_and(|configuration| {
+            packet.destination == configuration.address
+                || packet.destination == configuration.subnet_broadcast()
+        });
+
+    if !is_for_interface {
+        return;
+    }
+
+    match packet.protocol {
+        PROTOCOL_ICMP => icmp::handle_packet(packet, source_mac),
+        PROTOCOL_UDP => udp::handle_packet(packet),
+        _ => {}
+    }
+}
+
+/// Finds the hardware address to send a packet to and the source address to
+/// send it from, resolving the next hop with ARP if needed.
+///
+/// Packets to the limited broadcast address are sent to every station, from
+/// the unspecified address if the interface has none yet.
+///
+/// # Arguments
+///
+/// * `destination` - The destination of the packet.
+///
+/// # Returns
+///
+/// The hardware address of the next hop and the source address.
+fn route(destination: Ipv4Address) -> Result<(MacAddress, Ipv4Address), NetworkError> {
+    let configuration = configuration();
+
+    if destination == Ipv4Address::BROADCAST {
+        let source = configuration.map_or(Ipv4Address::UNSPECIFIED, |configuration| {
+            configuration.address
+        });
+
+        return Ok((MacAddress::BROADCAST, source));
+    }
+
+    let configuration = configuration.ok_or(NetworkError::NotConfigured)?;
+
+    if destination == configuration.subnet_broadcast() {
+        return Ok((MacAddress::BROADCAST, configuration.address));
+    }
+
+    let next_hop = if destination.is_in_subnet(configuration.address, configuration.prefix_length) {
+        destination
+    } else {
+        configuration
+            .gateway
+            .ok_or(NetworkError::Unreachable(destination))?
+    };
+
+    let destination_mac =
+        arp::resolve(next_hop, configuration.address).ok_or(NetworkError::Unreachable(next_hop))?;
+
+    Ok((destination_mac, configuration.address))
+}
+
+/// Sends an IPv4 packet whose payload is already in a frame.
+///
+/// # Arguments
+///
+/// * `frame` - The frame, with the payload at `PAYLOAD_OFFSET`.
+/// * `destination_mac` - The hardware address of the next hop.
+/// * `source` - The source address of the packet.
+/// * `destination` - The destination address of the packet.
+/// * `protocol` - The protocol of the payload.
+/// * `payload_length` - The length of the payload.
+fn send_ipv4_packet(
+    frame: &mut [u8; device::MAX_FRAME_SIZE],
+    destination_mac: MacAddress,
+    source: Ipv4Address,
+    destination: Ipv4Address,
+    protocol: u8,
+    payload_length: usize,
+) -> Result<(), NetworkError> {
+    let source_mac = device::mac_address()?;
+    let identification = NEXT_IDENTIFICATION.fetch_add(1, Ordering::Relaxed);
+
+    ethernet::write_header(frame, destination_mac, source_mac, ETHER_TYPE_IPV4);
+    ipv4::write_header(
+        &mut frame[ethernet::HEADER_SIZE..],
+        source,
+        destination,
+        protocol,
+        payload_length,
+        identification,
+    );
+
+    Ok(device::send_frame(
+        &frame[..PAYLOAD_OFFSET + payload_length],
+    )?)
+}
@@ -0,0 +1,246 @@
+//! UDP sockets for kernel threads.
+//!
+//! Each bound socket queues up to `QUEUE_LENGTH` received datagrams. The
+//! "net" thread drops datagrams for ports no socket is bound to, and
+//! datagrams that arrive while the socket's queue is full.
+
+use super::{MAX_PAYLOAD_SIZE, NetworkError, PAYLOAD_OFFSET, send_ipv4_packet};
+use crate::{drivers::virtio::net as device, task::WaitQueue};
+use core::sync::atomic::{AtomicU16, Ordering};
+use kernel_lib::{
+    net::{
+        Ipv4Address,
+        ipv4::{Ipv4Packet, PROTOCOL_UDP},
+        udp::{self, UdpDatagram},
+    },
+    ring_buffer::RingBuffer,
+    sync::SpinLock,
+};
+
+/// The most sockets that can be bound at once.
+pub const MAX_SOCKETS: usize = 4;
+
+/// The largest datagram payload that can be sent or received.
+pub const MAX_DATAGRAM_SIZE: usize = MAX_PAYLOAD_SIZE - udp::HEADER_SIZE;
+
+/// The number of received datagrams each socket queues.
+const QUEUE_LENGTH: usize = 4;
+
+/// The first port handed out to sockets bound to port 0.
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// A received datagram.
+#[derive(Copy, Clone)]
+struct Datagram {
+    source: Ipv4Address,
+    source_port: u16,
+    length: usize,
+    data: [u8; MAX_DATAGRAM_SIZE],
+}
+
+/// A socket slot.
+struct Socket {
+    /// The port the socket is bound to, or `None` if the slot is free.
+    port: Option<u16>,
+
+    /// The datagrams received but not yet read, oldest first.
+    datagrams: RingBuffer<Datagram, QUEUE_LENGTH>,
+}
+
+static SOCKETS: SpinLock<[Socket; MAX_SOCKETS]> = SpinLock::new(
+    [const {
+        Socket {
+            port: None,
+            datagrams: RingBuffer::new(),
+        }
+    }; MAX_SOCKETS],
+);
+
+/// The threads waiting for a datagram to arrive on any socket.
+static RECEIVE_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// The port offered next to a socket bound to port 0.
+static NEXT_EPHEMERAL_PORT: AtomicU16 = AtomicU16::new(FIRST_EPHEMERAL_PORT);
+
+/// A bound UDP socket. The port is released when the socket is dropped.
+pub struct UdpSocket {
+    /// The index of the socket's slot in `SOCKETS`.
+    index: usize,
+
+    port: u16,
+}
+
+impl UdpSocket {
+    /// Binds a socket to a port.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The port to receive datagrams on and send them from, or 0
+    ///   for any free port.
+    pub fn bind(port: u16) -> Result<Self, NetworkError> {
+        let mut sockets = SOCKETS.lock();
+
+        let is_bound = |port| sockets.iter().any(|socket| socket.port == Some(port));
+
+        let port = if port == 0 {
+            (FIRST_EPHEMERAL_PORT..=u16::MAX)
+                .map(|_| next_ephemeral_port())
+                .find(|&port| !is_bound(port))
+                .ok_or(NetworkError::TooManySockets)?
+        } else if is_bound(port) {
+            return Err(NetworkError::AddressInUse(port));
+        } else {
+            port
+        };
+
+        let index = sockets
+            .iter()
+            .position(|socket| socket.port.is_none())
+            .ok_or(NetworkError::TooManySockets)?;
+
+        sockets[index].port = Some(port);
+        sockets[index].datagrams = RingBuffer::new();
+
+        Ok(Self { index, port })
+    }
+
+    /// Returns the port the socket is bound to.
+    #[allow(dead_code)]
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Sends a datagram. Blocks the calling kernel thread while the
+    /// destination is resolved.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The payload of the datagram.
+    /// * `destination` - The address to send to.
+    /// * `destination_port` - The port to send to.
+    pub fn send_to(
+        &self,
+        data: &[u8],
+        destination: Ipv4Address,
+        destination_port: u16,
+    ) -> Result<(), NetworkError> {
+        if data.len() > MAX_DATAGRAM_SIZE {
+            return Err(NetworkError::PayloadTooLarge(data.len()));
+        }
+
+        let (destination_mac, source) = super::route(destination)?;
+
+        let mut frame = [0; device::MAX_FRAME_SIZE];
+        let length = udp::HEADER_SIZE + data.len();
+        let datagram = &mut frame[PAYLOAD_OFFSET..PAYLOAD_OFFSET + length];
+
+        datagram[udp::HEADER_SIZE..].copy_from_slice(data);
+        udp::write_header(datagram, source, destination, self.port, destination_port);
+
+        send_ipv4_packet(
+            &mut frame,
+            destination_mac,
+            source,
+            destination,
+            PROTOCOL_UDP,
+            length,
+        )
+    }
+
+    /// Receives a datagram, blocking the calling kernel thread until one
+    /// arrives.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Receives the payload. The rest of a longer payload is
+    ///   discarded.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes received, and the address and port of the sender.
+    #[allow(dead_code)]
+    pub fn receive_from(&self, buffer: &mut [u8]) -> (usize, Ipv4Address, u16) {
+        let mut result = None;
+
+        RECEIVE_WAIT_QUEUE.wait_until(|| {
+            result = self.try_receive_from(buffer);
+            result.is_some()
+        });
+
+        result.expect("The wait ends once a datagram is received.")
+    }
+
+    /// Receives a datagram if one has arrived, without blocking.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Receives the payload. The rest of a longer payload is
+    ///   discarded.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes received, and the address and port of the sender,
+    /// or `None` if no datagram is waiting.
+    pub fn try_receive_from(&self, buffer: &mut [u8]) -> Option<(usize, Ipv4Address, u16)> {
+        let datagram = SOCKETS.lock()[self.index].datagrams.pop_front()?;
+        let length = datagram.length.min(buffer.len());
+
+        buffer[..length].copy_from_slice(&datagram.data[..length]);
+
+        Some((length, datagram.source, datagram.source_port))
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        SOCKETS.lock()[self.index].port = None;
+    }
+}
+
+/// Returns the next port in the ephemeral range.
+fn next_ephemeral_port() -> u16 {
+    NEXT_EPHEMERAL_PORT
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |port| {
+            Some(port.checked_add(1).unwrap_or(FIRST_EPHEMERAL_PORT))
+        })
+        .unwrap_or(FIRST_EPHEMERAL_PORT)
+}
+
+/// Handles a received UDP datagram by queueing it on the socket bound to its
+/// destination port.
+///
+/// # Arguments
+///
+/// * `packet` - The IPv4 packet that carried the datagram.
+pub(super) fn handle_packet(packet: &Ipv4Packet) {
+    let Some(datagram) = UdpDatagram::parse(packet.payload, packet.source, packet.destination)
+    else {
+        return;
+    };
+
+    {
+        let mut sockets = SOCKETS.lock();
+
+        let Some(socket) = sockets
+            .iter_mut()
+            .find(|socket| socket.port == Some(datagram.destination_port))
+        else {
+            return;
+        };
+
+        let mut received = Datagram {
+            source: packet.source,
+            source_port: datagram.source_port,
+            length: datagram.payload.len(),
+            data: [0; MAX_DATAGRAM_SIZE],
+        };
+
+        received.data[..datagram.payload.len()].copy_from_slice(datagram.payload);
+
+        if socket.datagrams.push_back(received).is_err() {
+            return;
+        }
+    }
+
+    RECEIVE_WAIT_QUEUE.wake_all();
+}
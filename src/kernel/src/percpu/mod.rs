@@ -0,0 +1,169 @@
+//! Per-hart data reached through the tp register.
+//!
+//! Every hart claims the `PerHart` block for its hart ID as the first thing it
+//! does in kernel code and keeps a pointer to it in tp. The kernel does not use
+//! thread local storage, so tp is otherwise unused in kernel code. User code
+//! owns tp while it runs, so the trap entry finds the block through sscratch
+//! for traps from user mode. The entry points clear tp so that code running
+//! before `initialize` can tell that there is no block yet.
+//!
+//! Fields are read with the `per_hart!` macro:
+//!
+//! ```ignore
+//! per_hart!(trap_count).fetch_add(1, Ordering::Relaxed);
+//! ```
+
+use crate::hart::MAX_HART_COUNT;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize};
+
+/// The data owned by a single hart.
+///
+/// Fields are atomics so that other harts, such as the monitor's `harts`
+/// command, may read them while the owning hart updates them.
+pub struct PerHart {
+    /// The ID of the hart that owns the block.
+    pub hart_id: usize,
+
+    /// The task running on the hart, or null before the hart schedules tasks.
+    pub current_task: AtomicPtr<()>,
+
+    /// The task the hart last switched away from, until the task switched to
+    /// has queued it again.
+    pub previous_task: AtomicPtr<()>,
+
+    /// True if `previous_task` yielded and goes back into the run queue.
+    pub should_requeue_previous_task: AtomicBool,
+
+    /// The number of traps the hart has taken.
+    pub trap_count: AtomicU64,
+
+    /// The number of timer interrupts the hart has handled.
+    pub timer_tick_count: AtomicU64,
+
+    /// The `time` CSR reading at the last task switch on the hart, or zero
+    /// before the hart schedules tasks.
+    pub last_switch_time: AtomicU64,
+
+    /// The `time` CSR ticks the hart spent in tasks other than its idle task,
+    /// up to the last switch.
+    pub busy_time: AtomicU64,
+
+    /// The `time` CSR ticks the hart spent in its idle task, up to the last
+    /// switch.
+    pub idle_time: AtomicU64,
+
+    /// Set while the hart prints a panic report.
+    pub is_panicking: AtomicBool,
+
+    /// The kernel stack pointer the trap entry switches to for a trap from
+    /// user mode. Written by the trap return path before it enters user mode.
+    pub kernel_stack_top: AtomicUsize,
+
+    /// Scratch space where the trap entry keeps the user stack pointer while
+    /// it switches to the kernel stack.
+    pub user_stack_pointer: AtomicUsize,
+
+    /// The lowest address of the stack the hart runs on, just above its guard
+    /// page, or zero if it is unknown. A trap from kernel mode whose trap
+    /// frame would reach below it switches to the overflow stack and clears
+    /// it.
+    pub stack_limit: AtomicUsize,
+
+    /// The stack pointer the trap entry switches to when the stack of the
+    /// trapping code has overflowed.
+    pub overflow_stack_top: AtomicUsize,
+}
+
+impl PerHart {
+    const fn new(hart_id: usize) -> Self {
+        Self {
+            hart_id,
+            current_task: AtomicPtr::new(core::ptr::null_mut()),
+            previous_task: AtomicPtr::new(core::ptr::null_mut()),
+            should_requeue_previous_task: AtomicBool::new(false),
+            trap_count: AtomicU64::new(0),
+            timer_tick_count: AtomicU64::new(0),
+            last_switch_time: AtomicU64::new(0),
+            busy_time: AtomicU64::new(0),
+            idle_time: AtomicU64::new(0),
+            is_panicking: AtomicBool::new(false),
+            kernel_stack_top: AtomicUsize::new(0),
+            user_stack_pointer: AtomicUsize::new(0),
+            stack_limit: AtomicUsize::new(0),
+            overflow_stack_top: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// The blocks of every hart, indexed by hart ID.
+static PER_HART_BLOCKS: [PerHart; MAX_HART_COUNT] = {
+    let mut blocks = [const { PerHart::new(0) }; MAX_HART_COUNT];
+
+    let mut hart_id = 0;
+    while hart_id < MAX_HART_COUNT {
+        blocks[hart_id] = PerHart::new(hart_id);
+        hart_id += 1;
+    }
+
+    blocks
+};
+
+/// Points tp at the block of the calling hart.
+///
+/// # Arguments
+///
+/// * `hart_id` - The ID of the calling hart.
+///
+/// # Panics
+///
+/// If `hart_id` is not below `MAX_HART_COUNT`.
+pub fn initialize(hart_id: usize) {
+    let Some(block) = PER_HART_BLOCKS.get(hart_id) else {
+        panic!(
+            "Hart {} exceeds the maximum hart count of {}.",
+            hart_id, MAX_HART_COUNT
+        );
+    };
+
+    unsafe {
+        core::arch::asm!(
+            "mv tp, {}",
+            in(reg) block as *const PerHart as usize,
+            options(nomem, nostack)
+        );
+    }
+}
+
+/// Returns the block of the calling hart, or `None` if `initialize` has not
+/// run on it yet.
+pub fn try_current() -> Option<&'static PerHart> {
+    let block: *const PerHart;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) block, options(nomem, nostack));
+    }
+
+    // tp is either zero or was set by `initialize` to one of the blocks.
+    unsafe { block.as_ref() }
+}
+
+/// Returns the block of the calling hart.
+///
+/// # Panics
+///
+/// If `initialize` has not run on the calling hart.
+pub fn current() -> &'static PerHart {
+    try_current().expect("The calling hart has no per-hart block.")
+}
+
+/// Returns the block of any hart, such as to read its counters.
+pub fn get(hart_id: usize) -> Option<&'static PerHart> {
+    PER_HART_BLOCKS.get(hart_id)
+}
+
+/// Returns a reference to a field of the calling hart's `PerHart` block.
+#[macro_export]
+macro_rules! per_hart {
+    ($field:ident) => {
+        &$crate::percpu::current().$field
+    };
+}
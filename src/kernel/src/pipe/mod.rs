@@ -0,0 +1,302 @@
+//! Anonymous pipes.
+//!
+//! A pipe is a byte buffer with a read end and a write end, which processes
+//! hold as file descriptors. `create` takes a pipe from a static pool and
+//! returns one `PipeReader` and one `PipeWriter`. Copies of the ends, such as
+//! the ones `dup` and `fork` make, are counted, so reads return zero once
+//! every write end is closed and writes fail once every read end is closed.
+//!
+//! Reads block until data arrives and writes block until there is room, on
+//! the pipe's wait queues, so they must be made from kernel threads. The pipe
+//! returns to the pool once both ends are closed.
+
+use crate::task::WaitQueue;
+use core::fmt;
+use kernel_lib::{
+    ring_buffer::RingBuffer,
+    sync::{Ref, RefCount, RefCounted, SpinLock},
+};
+
+/// The most pipes that can exist at once.
+pub const MAX_PIPES: usize = 8;
+
+/// The number of bytes a pipe holds before writes block.
+const PIPE_CAPACITY: usize = 1024;
+
+/// The reasons a pipe could not be created or written.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PipeError {
+    /// All `MAX_PIPES` pipes are in use.
+    TooManyPipes,
+
+    /// Every read end of the pipe is closed, so written bytes would never be
+    /// read.
+    Broken,
+}
+
+impl fmt::Display for PipeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyPipes => write!(f, "all {} pipes are in use", MAX_PIPES),
+            Self::Broken => write!(f, "the pipe has no readers"),
+        }
+    }
+}
+
+/// The buffered bytes of a pipe and the number of open ends.
+struct PipeState {
+    buffer: RingBuffer<u8, PIPE_CAPACITY>,
+    reader_count: usize,
+    writer_count: usize,
+}
+
+impl PipeState {
+    const fn new() -> Self {
+        Self {
+            buffer: RingBuffer::new(),
+            reader_count: 0,
+            writer_count: 0,
+        }
+    }
+}
+
+/// A slot of the pipe pool.
+pub struct Pipe {
+    ref_count: RefCount,
+    state: SpinLock<PipeState>,
+
+    /// The readers waiting for bytes or for the last writer to close.
+    readable: WaitQueue,
+
+    /// The writers waiting for room or for the last reader to close.
+    writable: WaitQueue,
+}
+
+impl RefCounted for Pipe {
+    fn ref_count(&self) -> &RefCount {
+        &self.ref_count
+    }
+
+    fn release(&'static self) {
+        *self.state.lock() = PipeState::new();
+    }
+}
+
+/// Every pipe.
+static PIPES: [Pipe; MAX_PIPES] = [const {
+    Pipe {
+        ref_count: RefCount::new(),
+        state: SpinLock::new(PipeState::new()),
+        readable: WaitQueue::new(),
+        writable: WaitQueue::new(),
+    }
+}; MAX_PIPES];
+
+/// Creates an empty pipe.
+///
+/// # Returns
+///
+/// The read end and the write end.
+pub fn create() -> Result<(PipeReader, PipeWriter), PipeError> {
+    let pipe = PIPES
+        .iter()
+        .find_map(Ref::claim)
+        .ok_or(PipeError::TooManyPipes)?;
+
+    {
+        let mut state = pipe.state.lock();
+        state.reader_count = 1;
+        state.writer_count = 1;
+    }
+
+    Ok((PipeReader { pipe: pipe.clone() }, PipeWriter { pipe }))
+}
+
+/// The read end of a pipe.
+pub struct PipeReader {
+    pipe: Ref<Pipe>,
+}
+
+impl PipeReader {
+    /// Reads the bytes waiting in the pipe, blocking the calling kernel thread
+    /// until there are some or every write end is closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Receives the bytes.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read, which is zero only if `buffer` is empty or
+    /// every write end is closed.
+    pub fn read(&self, buffer: &mut [u8]) -> usize {
+        if buffer.is_empty() {
+            return 0;
+        }
+
+        loop {
+            self.pipe.readable.wait_until(|| {
+                let state = self.pipe.state.lock();
+
+                !state.buffer.is_empty() || state.writer_count == 0
+            });
+
+            let (length, has_writers) = {
+                let mut state = self.pipe.state.lock();
+                let mut length = 0;
+
+                while length < buffer.len() {
+                    let Some(byte) = state.buffer.pop_front() else {
+                        break;
+                    };
+
+                    buffer[length] = byte;
+                    length += 1;
+                }
+
+                (length, state.writer_count != 0)
+            };
+
+            // Another reader may have taken the bytes first.
+            if length != 0 || !has_writers {
+                self.pipe.writable.wake_all();
+                return length;
+            }
+        }
+    }
+}
+
+impl Clone for PipeReader {
+    fn clone(&self) -> Self {
+        self.pipe.state.lock().reader_count += 1;
+
+        Self {
+            pipe: self.pipe.clone(),
+        }
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let is_last = {
+            let mut state = self.pipe.state.lock();
+            state.reader_count -= 1;
+            state.reader_count == 0
+        };
+
+        if is_last {
+            self.pipe.writable.wake_all();
+        }
+    }
+}
+
+/// The write end of a pipe.
+pub struct PipeWriter {
+    pipe: Ref<Pipe>,
+}
+
+impl PipeWriter {
+    /// Writes bytes to the pipe, blocking the calling kernel thread while it
+    /// is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes to write.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes written, which is less than the length of `data`
+    /// only if every read end was closed partway through.
+    /// `PipeError::Broken` if every read end was closed before anything was
+    /// written.
+    pub fn write(&self, data: &[u8]) -> Result<usize, PipeError> {
+        let mut written = 0;
+
+        while written < data.len() {
+            self.pipe.writable.wait_until(|| {
+                let state = self.pipe.state.lock();
+
+                !state.buffer.is_full() || state.reader_count == 0
+            });
+
+            {
+                let mut state = self.pipe.state.lock();
+
+                if state.reader_count == 0 {
+                    return match written {
+                        0 => Err(PipeError::Broken),
+                        written => Ok(written),
+                    };
+                }
+
+                while written < data.len() && state.buffer.push_back(data[written]).is_ok() {
+                    written += 1;
+                }
+            }
+
+            self.pipe.readable.wake_all();
+        }
+
+        Ok(written)
+    }
+}
+
+impl Clone for PipeWriter {
+    fn clone(&self) -> Self {
+        self.pipe.state.lock().writer_count += 1;
+
+        Self {
+            pipe: self.pipe.clone(),
+        }
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        let is_last = {
+            let mut state = self.pipe.state.lock();
+            state.writer_count -= 1;
+            state.writer_count == 0
+        };
+
+        if is_last {
+            self.pipe.readable.wake_all();
+        }
+    }
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    kernel_test!(
+        fn test_pipe_reads_written_bytes_then_end() {
+            let (reader, writer) = create().unwrap();
+
+            assert_eq!(writer.write(b"hello"), Ok(5));
+
+            let mut buffer = [0u8; 16];
+            assert_eq!(reader.read(&mut buffer), 5);
+            assert_eq!(&buffer[..5], b"hello");
+
+            // With the only write end closed, a read returns the end of the
+            // pipe instead of blocking.
+            drop(writer);
+            assert_eq!(reader.read(&mut buffer), 0);
+        }
+    );
+
+    kernel_test!(
+        fn test_pipe_write_without_readers_fails() {
+            let (reader, writer) = create().unwrap();
+            let second_reader = reader.clone();
+
+            drop(reader);
+            assert_eq!(writer.write(b"x"), Ok(1));
+
+            drop(second_reader);
+            assert_eq!(writer.write(b"x"), Err(PipeError::Broken));
+        }
+    );
+}
@@ -0,0 +1,367 @@
+//! Performance counters through the SBI PMU extension.
+//!
+//! `measure` counts cycles, retired instructions, and cache events while a
+//! critical section runs and adds the counts to the statistics of the
+//! section, which the monitor's `pmu` command reports. The SBI implementation
+//! picks a counter for each event when the section starts and releases it
+//! when the section ends, so sections never keep counters busy between runs.
+//! Events without a counter on the platform are left out.
+//!
+//! Counters belong to the hart they are started on, so a section runs with
+//! interrupts disabled and must not block or yield. The counts include the
+//! SBI calls that start the counters after the first one.
+
+use crate::debug_println;
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use kernel_lib::sync::{SpinLock, disable_interrupts, restore_interrupts};
+use sbi_lib::{
+    SbiError, SbiResult,
+    pmu::{
+        CONFIG_FLAG_CLEAR_VALUE, CounterInfo, EVENT_TYPE_HARDWARE_CACHE,
+        EVENT_TYPE_HARDWARE_GENERAL, START_FLAG_SET_INIT_VALUE, STOP_FLAG_RESET,
+        counter_config_matching, counter_fw_read, counter_get_info, counter_start, counter_stop,
+        event_index, num_counters,
+    },
+};
+
+/// The most critical sections whose statistics are kept.
+pub const MAX_PMU_SECTIONS: usize = 8;
+
+/// An event a counter can count.
+#[repr(usize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PmuEvent {
+    Cycles = 0,
+    Instructions = 1,
+    CacheReferences = 2,
+    CacheMisses = 3,
+    L1DataReadMisses = 4,
+}
+
+impl PmuEvent {
+    /// Every event, in the order of their values.
+    pub const ALL: [Self; 5] = [
+        Self::Cycles,
+        Self::Instructions,
+        Self::CacheReferences,
+        Self::CacheMisses,
+        Self::L1DataReadMisses,
+    ];
+
+    /// Returns the SBI event index of the event.
+    fn event_index(self) -> usize {
+        // The cache event code holds the cache in bits 3 and up, the
+        // operation in bits 1 and 2, and whether it counts misses in bit 0.
+        const L1_DATA_CACHE: usize = 0;
+        const READ: usize = 0;
+        const MISS: usize = 1;
+
+        match self {
+            Self::Cycles => event_index(EVENT_TYPE_HARDWARE_GENERAL, 1),
+            Self::Instructions => event_index(EVENT_TYPE_HARDWARE_GENERAL, 2),
+            Self::CacheReferences => event_index(EVENT_TYPE_HARDWARE_GENERAL, 3),
+            Self::CacheMisses => event_index(EVENT_TYPE_HARDWARE_GENERAL, 4),
+            Self::L1DataReadMisses => event_index(
+                EVENT_TYPE_HARDWARE_CACHE,
+                (L1_DATA_CACHE << 3) | (READ << 1) | MISS,
+            ),
+        }
+    }
+}
+
+impl fmt::Display for PmuEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Cycles => "cycles",
+            Self::Instructions => "instructions",
+            Self::CacheReferences => "cache references",
+            Self::CacheMisses => "cache misses",
+            Self::L1DataReadMisses => "L1D read misses",
+        };
+
+        f.pad(name)
+    }
+}
+
+/// A count of every event, or `None` for the events no counter counted.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PmuCounts {
+    counts: [Option<u64>; PmuEvent::ALL.len()],
+}
+
+impl PmuCounts {
+    /// Returns the count of an event, or `None` if no counter counted it.
+    pub fn get(&self, event: PmuEvent) -> Option<u64> {
+        self.counts[event as usize]
+    }
+}
+
+/// The statistics of a critical section.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PmuSection {
+    pub name: &'static str,
+
+    /// The number of times the section ran.
+    pub run_count: u64,
+
+    /// The events counted over every run.
+    pub totals: PmuCounts,
+}
+
+/// A counter configured for an event.
+#[derive(Copy, Clone)]
+struct Counter {
+    index: usize,
+    info: CounterInfo,
+}
+
+/// The number of counters the SBI implementation reported, or zero if it
+/// has no PMU extension.
+static COUNTER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The statistics of every section measured so far.
+static SECTIONS: SpinLock<[Option<PmuSection>; MAX_PMU_SECTIONS]> =
+    SpinLock::new([None; MAX_PMU_SECTIONS]);
+
+/// Finds the counters of the SBI implementation.
+pub fn initialize() {
+    match num_counters() {
+        Ok(counter_count) => {
+            COUNTER_COUNT.store(counter_count, Ordering::Relaxed);
+            debug_println!("{} performance counters.", counter_count);
+        }
+        Err(error) => debug_println!("No performance counters: {}.", error),
+    }
+}
+
+/// Runs a critical section on the calling hart while counting its events,
+/// and adds the counts to the section's statistics.
+///
+/// # Arguments
+///
+/// * `name` - The name the statistics are kept under. Sections past
+///   `MAX_PMU_SECTIONS` names are run without being measured.
+/// * `section` - The code to measure. It runs with interrupts disabled and
+///   must not block or yield.
+///
+/// # Returns
+///
+/// The value `section` returns.
+pub fn measure<R>(name: &'static str, section: impl FnOnce() -> R) -> R {
+    let were_interrupts_enabled = disable_interrupts();
+
+    let counters = start_counters();
+    let result = section();
+    let counts = stop_counters(&counters);
+
+    restore_interrupts(were_interrupts_enabled);
+
+    record(name, &counts);
+
+    result
+}
+
+/// Calls a function for every counter the SBI implementation has.
+///
+/// # Arguments
+///
+/// * `callback` - Called with the index and description of each counter.
+///
+/// # Returns
+///
+/// The error of the first counter that could not be described.
+pub fn for_each_counter(mut callback: impl FnMut(usize, CounterInfo)) -> SbiResult<()> {
+    for index in 0..COUNTER_COUNT.load(Ordering::Relaxed) {
+        callback(index, counter_get_info(index)?);
+    }
+
+    Ok(())
+}
+
+/// Calls a function for every section measured so far, in the order they
+/// were first measured.
+pub fn for_each_section(mut callback: impl FnMut(&PmuSection)) {
+    // The statistics are copied so the callback can take its time.
+    let sections = *SECTIONS.lock();
+
+    for section in sections.iter().flatten() {
+        callback(section);
+    }
+}
+
+/// Configures and starts a counter for every event a counter can count.
+fn start_counters() -> [Option<Counter>; PmuEvent::ALL.len()] {
+    let mut counters = [None; PmuEvent::ALL.len()];
+    let counter_count = COUNTER_COUNT.load(Ordering::Relaxed);
+
+    if counter_count == 0 {
+        return counters;
+    }
+
+    let counter_mask = match counter_count {
+        count if count >= usize::BITS as usize => usize::MAX,
+        count => (1 << count) - 1,
+    };
+
+    for event in PmuEvent::ALL {
+        let Ok(index) = counter_config_matching(
+            0,
+            counter_mask,
+            CONFIG_FLAG_CLEAR_VALUE,
+            event.event_index(),
+            0,
+        ) else {
+            continue;
+        };
+
+        let counter = counter_get_info(index)
+            .and_then(|info| counter_start(index, 1, START_FLAG_SET_INIT_VALUE, 0).map(|()| info));
+
+        match counter {
+            Ok(info) => counters[event as usize] = Some(Counter { index, info }),
+            Err(_) => release_counter(index),
+        }
+    }
+
+    counters
+}
+
+/// Reads and releases the counters `start_counters` started.
+fn stop_counters(counters: &[Option<Counter>; PmuEvent::ALL.len()]) -> PmuCounts {
+    let mut counts = PmuCounts::default();
+
+    // Every counter is read before any is stopped, so none of them counts
+    // the calls that stop the others.
+    for (count, counter) in counts.counts.iter_mut().zip(counters) {
+        *count = counter.and_then(|counter| read_counter(&counter));
+    }
+
+    for counter in counters.iter().flatten() {
+        release_counter(counter.index);
+    }
+
+    counts
+}
+
+/// Reads the value of a running counter.
+fn read_counter(counter: &Counter) -> Option<u64> {
+    if counter.info.is_firmware() {
+        return counter_fw_read(counter.index).ok();
+    }
+
+    let value = read_counter_csr(counter.info.csr())?;
+
+    match counter.info.width() {
+        64 => Some(value),
+        width => Some(value & ((1 << width) - 1)),
+    }
+}
+
+/// Stops a counter and releases it for other events.
+fn release_counter(index: usize) {
+    match counter_stop(index, 1, STOP_FLAG_RESET) {
+        Ok(()) | Err(SbiError::AlreadyStopped) => {}
+        Err(error) => debug_println!(
+            "Failed to release performance counter {}: {}.",
+            index,
+            error
+        ),
+    }
+}
+
+/// Adds the counts of one run to the statistics of a section.
+fn record(name: &'static str, counts: &PmuCounts) {
+    let mut sections = SECTIONS.lock();
+
+    if let Some(section) = sections
+        .iter_mut()
+        .flatten()
+        .find(|section| section.name == name)
+    {
+        for (total, count) in section.totals.counts.iter_mut().zip(counts.counts) {
+            // An event only keeps a total while every run counted it.
+            *total = total.zip(count).map(|(total, count)| total + count);
+        }
+
+        section.run_count += 1;
+        return;
+    }
+
+    if let Some(slot) = sections.iter_mut().find(|slot| slot.is_none()) {
+        *slot = Some(PmuSection {
+            name,
+            run_count: 1,
+            totals: *counts,
+        });
+    }
+}
+
+/// Reads a hardware counter CSR by number.
+///
+/// # Returns
+///
+/// The value, or `None` if the number is not one of the unprivileged counter
+/// CSRs from cycle to hpmcounter31.
+fn read_counter_csr(csr: usize) -> Option<u64> {
+    macro_rules! read_csr {
+        ($($number:literal),*) => {
+            match csr {
+                $(
+                    $number => {
+                        let value: u64;
+                        unsafe {
+                            core::arch::asm!(
+                                "csrr {value}, {csr}",
+                                value = out(reg) value,
+                                csr = const $number,
+                                options(nomem, nostack)
+                            );
+                        }
+
+                        Some(value)
+                    }
+                )*
+                _ => None,
+            }
+        };
+    }
+
+    read_csr!(
+        0xc00, 0xc01, 0xc02, 0xc03, 0xc04, 0xc05, 0xc06, 0xc07, 0xc08, 0xc09, 0xc0a, 0xc0b, 0xc0c,
+        0xc0d, 0xc0e, 0xc0f, 0xc10, 0xc11, 0xc12, 0xc13, 0xc14, 0xc15, 0xc16, 0xc17, 0xc18, 0xc19,
+        0xc1a, 0xc1b, 0xc1c, 0xc1d, 0xc1e, 0xc1f
+    )
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    kernel_test!(
+        fn test_pmu_measure_records_every_run() {
+            let run_count = || {
+                let mut run_count = 0;
+                for_each_section(|section| {
+                    if section.name == "kernel-test" {
+                        run_count = section.run_count;
+                    }
+                });
+
+                run_count
+            };
+
+            let previous_run_count = run_count();
+
+            assert_eq!(measure("kernel-test", || 42), 42);
+            measure("kernel-test", || {
+                core::hint::black_box(0u64..1000).sum::<u64>()
+            });
+
+            assert_eq!(run_count(), previous_run_count + 2);
+        }
+    );
+}
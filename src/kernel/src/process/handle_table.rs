@@ -0,0 +1,85 @@
+use crate::{
+    fs::OpenFile,
+    memory::SharedMemory,
+    pipe::{PipeReader, PipeWriter},
+};
+use kernel_lib::sync::Ref;
+
+/// The most handles a process can have open.
+pub const MAX_HANDLES: usize = 16;
+
+/// An object a process refers to by a handle number.
+#[derive(Clone)]
+pub enum Handle {
+    /// The kernel console. Writes go to every active console device and reads
+    /// take console input.
+    Console,
+
+    /// A file or directory opened from the file system. Copies of the handle
+    /// share the offset.
+    File(Ref<OpenFile>),
+
+    /// The read end of a pipe.
+    PipeReader(PipeReader),
+
+    /// The write end of a pipe.
+    PipeWriter(PipeWriter),
+
+    /// A shared memory object, which is mapped rather than read or written.
+    SharedMemory(Ref<SharedMemory>),
+}
+
+/// The handles a process has open, indexed by handle number. Handle numbers
+/// are the file descriptors of the system call ABI.
+#[derive(Clone)]
+pub struct HandleTable {
+    handles: [Option<Handle>; MAX_HANDLES],
+}
+
+impl HandleTable {
+    /// Creates a table with the console open as standard input, output, and
+    /// error.
+    pub fn with_console() -> Self {
+        let mut handles = [const { None }; MAX_HANDLES];
+
+        handles[0] = Some(Handle::Console);
+        handles[1] = Some(Handle::Console);
+        handles[2] = Some(Handle::Console);
+
+        Self { handles }
+    }
+
+    /// Returns the object a handle number refers to, or `None` if the number
+    /// is not open.
+    pub fn get(&self, number: usize) -> Option<Handle> {
+        self.handles.get(number).cloned().flatten()
+    }
+
+    /// Opens a handle at the lowest free number.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The object the handle refers to.
+    ///
+    /// # Returns
+    ///
+    /// The handle number, or the object back if every number is open.
+    pub fn insert(&mut self, handle: Handle) -> Result<usize, Handle> {
+        match self.handles.iter().position(Option::is_none) {
+            Some(number) => {
+                self.handles[number] = Some(handle);
+                Ok(number)
+            }
+            None => Err(handle),
+        }
+    }
+
+    /// Closes a handle number.
+    ///
+    /// # Returns
+    ///
+    /// The object the number referred to, or `None` if it was not open.
+    pub fn remove(&mut self, number: usize) -> Option<Handle> {
+        self.handles.get_mut(number)?.take()
+    }
+}
@@ -0,0 +1,805 @@
+//! User processes.
+//!
+//! A `Process` owns a user address space, the handles it has open, and the
+//! register state its thread enters user mode with. Every process runs on a
+//! kernel thread of its own. The thread's stack is the process's kernel stack,
+//! which holds the live trap frame whenever the process is in the kernel, and
+//! the thread's task runs in the process's address space, so the scheduler
+//! installs it whenever the thread is switched to.
+//!
+//! Processes are started from a program with `spawn`, copied with
+//! `fork_current`, and replaced by another program with `exec_current`. A
+//! process ends with `exit_current`, which frees its address space and closes
+//! its handles, and its thread and kernel stack end with it.
+//!
+//! A process a parent forked stays a zombie after it exits, holding its exit
+//! code, until the parent collects it with `wait_child_current` and its ID is
+//! freed. The children of a process that exits first are adopted by the
+//! first process, `INIT_PROCESS_ID`, or are freed as soon as they exit if it
+//! has ended too. Processes without a parent, such as the ones `spawn`
+//! starts, are freed as soon as they exit.
+//!
+//! A forked process shares its memory with its parent copy-on-write, and the
+//! stack and the memory from `map_anonymous_current` are only allocated as
+//! they are touched. The page faults both cause are passed to
+//! `resolve_page_fault`.
+
+mod handle_table;
+
+pub use handle_table::Handle;
+
+use handle_table::HandleTable;
+
+use crate::{
+    debug_println,
+    futex::FutexKey,
+    memory::{
+        self, AddressSpace, FaultAccess, PAGE_SIZE, SharedMemory, USER_ADDRESS_LIMIT,
+        UserPageAccess,
+    },
+    pmu,
+    task::{self, WaitQueue},
+    trap::trap_frame::TrapFrame,
+    user::{
+        self,
+        elf::{self, LoadError},
+        programs::{self, ProgramError},
+    },
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use kernel_lib::sync::SpinLock;
+
+/// The most processes that can exist at the same time. Each needs a kernel
+/// thread.
+pub const MAX_PROCESSES: usize = task::MAX_KERNEL_THREADS;
+
+/// The user address just past the stack every program starts with.
+const USER_STACK_TOP: usize = USER_ADDRESS_LIMIT;
+
+/// The size of the stack every program starts with. Its pages are only
+/// allocated once the stack grows into them.
+const USER_STACK_SIZE: usize = 16 * PAGE_SIZE;
+
+/// The ID of the first process, which adopts the children of processes
+/// that exit before them. Process IDs start here, since `fork` returns zero
+/// to the copy.
+pub const INIT_PROCESS_ID: usize = 1;
+
+/// The value in `TASK_PROCESS_IDS` of a task that runs no process.
+const NO_PROCESS: usize = usize::MAX;
+
+/// The index of a0 in the trap frame registers.
+const A0_REGISTER: usize = 10;
+
+/// The reasons a process could not be started or replaced.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProcessError {
+    /// All `MAX_PROCESSES` processes or every kernel thread exist already.
+    TooManyProcesses,
+
+    /// The frame pool ran out while building an address space.
+    OutOfMemory,
+
+    /// The program could not be found.
+    Program(ProgramError),
+
+    /// The program could not be loaded.
+    Load(LoadError),
+
+    /// The process has no child to wait for.
+    NoChild,
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyProcesses => write!(f, "all {} processes are in use", MAX_PROCESSES),
+            Self::OutOfMemory => write!(f, "out of memory"),
+            Self::Program(error) => write!(f, "{}", error),
+            Self::Load(error) => write!(f, "failed to load the program: {:?}", error),
+            Self::NoChild => write!(f, "no such child process"),
+        }
+    }
+}
+
+/// A user program and everything it owns.
+pub struct Process {
+    /// The name of the program the process runs.
+    name: &'static str,
+
+    /// The process that forked this one, if any.
+    parent_id: Option<usize>,
+
+    address_space: AddressSpace,
+    handles: HandleTable,
+
+    /// The user register state the process's thread enters user mode with.
+    initial_trap_frame: TrapFrame,
+}
+
+/// What is left of a process that exited until its parent collects it.
+struct Zombie {
+    name: &'static str,
+    parent_id: Option<usize>,
+    exit_code: isize,
+}
+
+/// A slot of the process table. There is no heap to keep the process in, so
+/// every slot is as large as a running process.
+#[allow(clippy::large_enum_variant)]
+enum ProcessSlot {
+    Free,
+    Running(Process),
+    Zombie(Zombie),
+}
+
+impl ProcessSlot {
+    /// Returns the process, or `None` if the slot is free or holds a zombie.
+    fn process(&self) -> Option<&Process> {
+        match self {
+            Self::Running(process) => Some(process),
+            Self::Free | Self::Zombie(_) => None,
+        }
+    }
+
+    /// Returns the process for changing, or `None` if the slot is free or
+    /// holds a zombie.
+    fn process_mut(&mut self) -> Option<&mut Process> {
+        match self {
+            Self::Running(process) => Some(process),
+            Self::Free | Self::Zombie(_) => None,
+        }
+    }
+
+    /// Returns the parent of the process or zombie in the slot.
+    fn parent_id(&self) -> Option<usize> {
+        match self {
+            Self::Running(process) => process.parent_id,
+            Self::Zombie(zombie) => zombie.parent_id,
+            Self::Free => None,
+        }
+    }
+}
+
+/// Every process, indexed by process ID less `INIT_PROCESS_ID`.
+static PROCESSES: [SpinLock<ProcessSlot>; MAX_PROCESSES] =
+    [const { SpinLock::new(ProcessSlot::Free) }; MAX_PROCESSES];
+
+/// The queue every process waits on for its children to exit, indexed like
+/// `PROCESSES`.
+static CHILD_EXIT_QUEUES: [WaitQueue; MAX_PROCESSES] = [const { WaitQueue::new() }; MAX_PROCESSES];
+
+/// The ID of the process each task runs, indexed by task ID, or `NO_PROCESS`.
+static TASK_PROCESS_IDS: [AtomicUsize; task::MAX_TASK_COUNT] =
+    [const { AtomicUsize::new(NO_PROCESS) }; task::MAX_TASK_COUNT];
+
+/// Starts a process running a program.
+///
+/// # Arguments
+///
+/// * `name` - The name of an embedded program, or the path of a program on
+///   the root file system.
+///
+/// # Returns
+///
+/// The ID of the new process.
+pub fn spawn(name: &str) -> Result<usize, ProcessError> {
+    let program = programs::find(name).map_err(ProcessError::Program)?;
+    let (address_space, initial_trap_frame) = load_program(program.image)?;
+
+    start(Process {
+        name: program.name,
+        parent_id: None,
+        address_space,
+        handles: HandleTable::with_console(),
+        initial_trap_frame,
+    })
+}
+
+/// Starts a copy of the calling process with a copy of its handles and its
+/// memory, which the two share copy-on-write. The copy continues from the
+/// same user register state, except that a0 is zero.
+///
+/// # Arguments
+///
+/// * `trap_frame` - The user register state of the calling process.
+///
+/// # Returns
+///
+/// The ID of the new process.
+///
+/// # Panics
+///
+/// If the calling thread does not run a process.
+pub fn fork_current(trap_frame: &TrapFrame) -> Result<usize, ProcessError> {
+    let parent_id = current_id().expect("The calling thread does not run a process.");
+
+    let mut initial_trap_frame = *trap_frame;
+    initial_trap_frame.registers[A0_REGISTER] = 0;
+
+    let child = {
+        let mut guard = slot(parent_id).lock();
+        let parent = guard.process_mut().expect("The current process exists.");
+
+        Process {
+            name: parent.name,
+            parent_id: Some(parent_id),
+            address_space: pmu::measure("fork", || parent.address_space.fork())
+                .map_err(|_| ProcessError::OutOfMemory)?,
+            handles: parent.handles.clone(),
+            initial_trap_frame,
+        }
+    };
+
+    start(child)
+}
+
+/// Replaces the program the calling process runs with another program. The
+/// process keeps its ID and handles.
+///
+/// # Arguments
+///
+/// * `name` - The name of an embedded program, or the path of a program on
+///   the root file system.
+/// * `trap_frame` - The user register state of the calling process, which is
+///   replaced by the initial state of the new program on success.
+///
+/// # Returns
+///
+/// An error, with the calling process unchanged, if the program could not be
+/// loaded.
+///
+/// # Panics
+///
+/// If the calling thread does not run a process.
+pub fn exec_current(name: &str, trap_frame: &mut TrapFrame) -> Result<(), ProcessError> {
+    let id = current_id().expect("The calling thread does not run a process.");
+
+    let program = programs::find(name).map_err(ProcessError::Program)?;
+    let (address_space, initial_trap_frame) = load_program(program.image)?;
+
+    let previous_address_space = {
+        let mut guard = slot(id).lock();
+        let process = guard.process_mut().expect("The current process exists.");
+
+        // The process's thread is the only one that runs in its address
+        // space, so the previous one is unused once the new one is active.
+        unsafe {
+            task::set_current_address_space(address_space.satp());
+        }
+
+        process.name = program.name;
+        process.initial_trap_frame = initial_trap_frame;
+
+        core::mem::replace(&mut process.address_space, address_space)
+    };
+
+    drop(previous_address_space);
+
+    *trap_frame = initial_trap_frame;
+
+    Ok(())
+}
+
+/// Ends the calling process, freeing everything it owns, and its thread with
+/// it. A process with a parent stays a zombie until the parent collects its
+/// exit code.
+///
+/// # Arguments
+///
+/// * `exit_code` - The exit code to report.
+///
+/// # Panics
+///
+/// If the calling thread does not run a process.
+pub fn exit_current(exit_code: isize) -> ! {
+    let task_id = task::current_task_id().expect("The calling hart has no task.");
+    let id = TASK_PROCESS_IDS[task_id].swap(NO_PROCESS, Ordering::Relaxed);
+
+    if id == NO_PROCESS {
+        panic!("The calling thread does not run a process.");
+    }
+
+    unsafe {
+        task::set_current_address_space(memory::kernel_satp());
+    }
+
+    let process = {
+        let mut guard = slot(id).lock();
+
+        let ProcessSlot::Running(process) = core::mem::replace(&mut *guard, ProcessSlot::Free)
+        else {
+            panic!("The current process exists.");
+        };
+
+        // A parent that exits at the same time hands this process to init
+        // under this lock, so the parent read here is current.
+        if let Some(parent_id) = process.parent_id {
+            *guard = ProcessSlot::Zombie(Zombie {
+                name: process.name,
+                parent_id: Some(parent_id),
+                exit_code,
+            });
+        }
+
+        process
+    };
+
+    debug_println!(
+        "Process {} ({}) exited with code {}.",
+        id,
+        process.name,
+        exit_code
+    );
+
+    let parent_id = process.parent_id;
+
+    // Closing the handles may wake threads waiting on pipes, so the process
+    // is unlocked first.
+    drop(process);
+
+    abandon_children(id);
+
+    if let Some(parent_id) = parent_id {
+        child_exit_queue(parent_id).wake_all();
+    }
+
+    task::exit_current();
+}
+
+/// Waits for a child of the calling process to exit and frees it.
+///
+/// # Arguments
+///
+/// * `child_id` - The child to wait for, or `None` for any child.
+/// * `is_blocking` - False to return at once if no child has exited yet.
+///
+/// # Returns
+///
+/// The ID and exit code of the child, `None` if no child has exited and
+/// `is_blocking` is false, or `ProcessError::NoChild` if the calling process
+/// has no such child.
+///
+/// # Panics
+///
+/// If the calling thread does not run a process.
+pub fn wait_child_current(
+    child_id: Option<usize>,
+    is_blocking: bool,
+) -> Result<Option<(usize, isize)>, ProcessError> {
+    let id = current_id().expect("The calling thread does not run a process.");
+
+    let mut result = Err(ProcessError::NoChild);
+
+    child_exit_queue(id).wait_until(|| {
+        result = find_exited_child(id, child_id);
+
+        !is_blocking || !matches!(result, Ok(None))
+    });
+
+    let Some(exited_id) = result? else {
+        return Ok(None);
+    };
+
+    // The children of a process only change hands when it exits, so nobody
+    // else frees the zombie.
+    let ProcessSlot::Zombie(zombie) =
+        core::mem::replace(&mut *slot(exited_id).lock(), ProcessSlot::Free)
+    else {
+        panic!("Process {} exited but is not a zombie.", exited_id);
+    };
+
+    Ok(Some((exited_id, zombie.exit_code)))
+}
+
+/// Reserves zeroed memory in the calling process. Its pages are only
+/// allocated once they are touched.
+///
+/// # Arguments
+///
+/// * `length` - The nonzero size of the memory in bytes.
+/// * `access` - The access the memory allows.
+///
+/// # Returns
+///
+/// The page aligned user address of the memory.
+///
+/// # Panics
+///
+/// If the calling thread does not run a process.
+pub fn map_anonymous_current(length: usize, access: UserPageAccess) -> Result<usize, ProcessError> {
+    let id = current_id().expect("The calling thread does not run a process.");
+
+    let mut guard = slot(id).lock();
+    let process = guard.process_mut().expect("The current process exists.");
+
+    process
+        .address_space
+        .map_anonymous(length, access)
+        .map_err(|_| ProcessError::OutOfMemory)
+}
+
+/// Maps a shared memory object into the calling process.
+///
+/// # Arguments
+///
+/// * `shared_memory` - The object to map.
+/// * `access` - The access the mapping allows.
+///
+/// # Returns
+///
+/// The page aligned user address of the mapping.
+///
+/// # Panics
+///
+/// If the calling thread does not run a process.
+pub fn map_shared_memory_current(
+    shared_memory: &SharedMemory,
+    access: UserPageAccess,
+) -> Result<usize, ProcessError> {
+    let id = current_id().expect("The calling thread does not run a process.");
+
+    let mut guard = slot(id).lock();
+    let process = guard.process_mut().expect("The current process exists.");
+
+    shared_memory
+        .map(&mut process.address_space, access)
+        .map_err(|_| ProcessError::OutOfMemory)
+}
+
+/// Returns the key of a futex word of the calling process.
+///
+/// # Arguments
+///
+/// * `virtual_address` - The user address of the word, whose page must be
+///   mapped and must not be shared copy-on-write.
+///
+/// # Returns
+///
+/// The key, or `None` if the page is not mapped.
+///
+/// # Panics
+///
+/// If the calling thread does not run a process.
+pub fn futex_key_current(virtual_address: usize) -> Option<FutexKey> {
+    let id = current_id().expect("The calling thread does not run a process.");
+
+    let mut guard = slot(id).lock();
+    let process = guard.process_mut().expect("The current process exists.");
+
+    let (ppn, is_shared) = process.address_space.user_frame(virtual_address)?;
+    let offset = virtual_address % PAGE_SIZE;
+
+    Some(match is_shared {
+        true => FutexKey::shared(ppn, offset),
+        false => FutexKey::private(process.address_space.root_page_table_ppn(), ppn, offset),
+    })
+}
+
+/// Handles a page fault raised by the calling process, by mapping a page it
+/// reserved or breaking a copy-on-write share.
+///
+/// # Arguments
+///
+/// * `virtual_address` - The user address that faulted.
+/// * `access` - The kind of access that faulted.
+///
+/// # Returns
+///
+/// `true` if the access can be retried, or `false` if the access is not
+/// allowed or no frame is left for the page.
+///
+/// # Panics
+///
+/// If the calling thread does not run a process.
+pub fn resolve_page_fault(virtual_address: usize, access: FaultAccess) -> bool {
+    let id = current_id().expect("The calling thread does not run a process.");
+
+    let mut guard = slot(id).lock();
+    let process = guard.process_mut().expect("The current process exists.");
+
+    match process
+        .address_space
+        .resolve_page_fault(virtual_address, access)
+    {
+        Ok(is_resolved) => is_resolved,
+        Err(error) => {
+            debug_println!(
+                "Process {} could not map the page at {:#x}: {:?}.",
+                id,
+                virtual_address,
+                error
+            );
+
+            false
+        }
+    }
+}
+
+/// Returns the ID of the process the calling thread runs, or `None` if it is
+/// a kernel thread.
+pub fn current_id() -> Option<usize> {
+    let task_id = task::current_task_id()?;
+
+    match TASK_PROCESS_IDS[task_id].load(Ordering::Relaxed) {
+        NO_PROCESS => None,
+        id => Some(id),
+    }
+}
+
+/// Returns the object a handle number of the calling process refers to.
+///
+/// # Returns
+///
+/// The object, or `None` if the number is not open or the calling thread
+/// does not run a process.
+pub fn current_handle(number: usize) -> Option<Handle> {
+    let id = current_id()?;
+
+    slot(id).lock().process()?.handles.get(number)
+}
+
+/// Opens a handle of the calling process at its lowest free handle number.
+///
+/// # Arguments
+///
+/// * `handle` - The object the handle refers to.
+///
+/// # Returns
+///
+/// The handle number, or the object back if every number is open or the
+/// calling thread does not run a process.
+pub fn insert_current_handle(handle: Handle) -> Result<usize, Handle> {
+    let Some(id) = current_id() else {
+        return Err(handle);
+    };
+
+    let mut guard = slot(id).lock();
+
+    match guard.process_mut() {
+        Some(process) => process.handles.insert(handle),
+        None => Err(handle),
+    }
+}
+
+/// Closes a handle number of the calling process.
+///
+/// # Returns
+///
+/// The object the number referred to, which the caller drops after the
+/// process is unlocked, or `None` if the number is not open or the calling
+/// thread does not run a process.
+pub fn remove_current_handle(number: usize) -> Option<Handle> {
+    let id = current_id()?;
+
+    slot(id).lock().process_mut()?.handles.remove(number)
+}
+
+/// Calls a function for every process that exists, including zombies.
+///
+/// # Arguments
+///
+/// * `callback` - Called with the ID, parent ID, and program name of each
+///   process, and with the exit code of a zombie.
+pub fn for_each_process(
+    mut callback: impl FnMut(usize, Option<usize>, &'static str, Option<isize>),
+) {
+    for (index, slot) in PROCESSES.iter().enumerate() {
+        let id = index + INIT_PROCESS_ID;
+
+        match &*slot.lock() {
+            ProcessSlot::Running(process) => callback(id, process.parent_id, process.name, None),
+            ProcessSlot::Zombie(zombie) => {
+                callback(id, zombie.parent_id, zombie.name, Some(zombie.exit_code))
+            }
+            ProcessSlot::Free => {}
+        }
+    }
+}
+
+/// Returns the slot of a process ID.
+///
+/// # Panics
+///
+/// If no process can have the ID.
+fn slot(id: usize) -> &'static SpinLock<ProcessSlot> {
+    &PROCESSES[id - INIT_PROCESS_ID]
+}
+
+/// Finds a child of a process that has exited.
+///
+/// # Arguments
+///
+/// * `parent_id` - The process whose children to look through.
+/// * `child_id` - The child to look for, or `None` for any child.
+///
+/// # Returns
+///
+/// The ID of the exited child, `None` if the children have not exited yet,
+/// or `ProcessError::NoChild` if the process has no such child.
+fn find_exited_child(
+    parent_id: usize,
+    child_id: Option<usize>,
+) -> Result<Option<usize>, ProcessError> {
+    let mut has_child = false;
+
+    for (index, slot) in PROCESSES.iter().enumerate() {
+        let id = index + INIT_PROCESS_ID;
+
+        if child_id.is_some_and(|child_id| child_id != id) {
+            continue;
+        }
+
+        let guard = slot.lock();
+
+        if guard.parent_id() != Some(parent_id) {
+            continue;
+        }
+
+        if let ProcessSlot::Zombie(_) = *guard {
+            return Ok(Some(id));
+        }
+
+        has_child = true;
+    }
+
+    match has_child {
+        true => Ok(None),
+        false => Err(ProcessError::NoChild),
+    }
+}
+
+/// Hands the children of an exiting process to init, or frees the ones that
+/// exited already if init has ended too.
+///
+/// # Arguments
+///
+/// * `id` - The exiting process, which must no longer be running.
+fn abandon_children(id: usize) {
+    // Holding init's slot keeps it from exiting before its new children are
+    // recorded, which would leave them with a parent that never collects
+    // them.
+    let mut init_guard = slot(INIT_PROCESS_ID).lock();
+
+    let init_id = match *init_guard {
+        ProcessSlot::Running(_) if id != INIT_PROCESS_ID => Some(INIT_PROCESS_ID),
+        _ => None,
+    };
+
+    // A process that took the ID of an init that ended may be a child too.
+    hand_over_child(&mut init_guard, id, None);
+
+    let mut has_zombie_children = false;
+
+    for slot in &PROCESSES[1..] {
+        has_zombie_children |= hand_over_child(&mut slot.lock(), id, init_id);
+    }
+
+    drop(init_guard);
+
+    if has_zombie_children {
+        child_exit_queue(INIT_PROCESS_ID).wake_all();
+    }
+}
+
+/// Gives the process or zombie in a slot a new parent if it is a child of an
+/// exiting process. A zombie left without a parent is freed.
+///
+/// # Arguments
+///
+/// * `slot` - The slot.
+/// * `id` - The exiting process.
+/// * `new_parent_id` - The new parent, or `None` for none.
+///
+/// # Returns
+///
+/// True if a zombie was handed to the new parent.
+fn hand_over_child(slot: &mut ProcessSlot, id: usize, new_parent_id: Option<usize>) -> bool {
+    match slot {
+        ProcessSlot::Running(process) if process.parent_id == Some(id) => {
+            process.parent_id = new_parent_id;
+            false
+        }
+        ProcessSlot::Zombie(zombie) if zombie.parent_id == Some(id) => {
+            zombie.parent_id = new_parent_id;
+
+            if new_parent_id.is_none() {
+                *slot = ProcessSlot::Free;
+            }
+
+            new_parent_id.is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Returns the queue a process waits on for its children to exit.
+fn child_exit_queue(id: usize) -> &'static WaitQueue {
+    &CHILD_EXIT_QUEUES[id - INIT_PROCESS_ID]
+}
+
+/// Creates the address space of a program and its initial user register
+/// state.
+///
+/// # Arguments
+///
+/// * `image` - The program's ELF image.
+fn load_program(image: &[u8]) -> Result<(AddressSpace, TrapFrame), ProcessError> {
+    let mut address_space = AddressSpace::new().map_err(|_| ProcessError::OutOfMemory)?;
+
+    let entry_point = elf::load(&mut address_space, image).map_err(ProcessError::Load)?;
+
+    address_space
+        .reserve_area(
+            USER_STACK_TOP - USER_STACK_SIZE,
+            USER_STACK_SIZE,
+            UserPageAccess::ReadWrite,
+        )
+        .map_err(|error| ProcessError::Load(LoadError::Map(error)))?;
+
+    Ok((
+        address_space,
+        user::initial_trap_frame(entry_point, USER_STACK_TOP),
+    ))
+}
+
+/// Gives a process an ID and starts its thread.
+///
+/// # Returns
+///
+/// The ID of the process.
+fn start(process: Process) -> Result<usize, ProcessError> {
+    let name = process.name;
+    let mut process = Some(process);
+
+    let id = PROCESSES
+        .iter()
+        .position(|slot| {
+            let mut guard = slot.lock();
+            let is_free = matches!(*guard, ProcessSlot::Free);
+
+            if is_free {
+                *guard = ProcessSlot::Running(process.take().expect("Only one slot is taken."));
+            }
+
+            is_free
+        })
+        .ok_or(ProcessError::TooManyProcesses)?
+        + INIT_PROCESS_ID;
+
+    if task::spawn_kernel_thread(name, run_process, id).is_err() {
+        drop(core::mem::replace(&mut *slot(id).lock(), ProcessSlot::Free));
+        return Err(ProcessError::TooManyProcesses);
+    }
+
+    Ok(id)
+}
+
+/// The kernel thread of a process, which enters user mode in the process's
+/// address space.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the process.
+fn run_process(id: usize) {
+    let task_id = task::current_task_id().expect("The calling hart has no task.");
+    TASK_PROCESS_IDS[task_id].store(id, Ordering::Relaxed);
+
+    let initial_trap_frame = {
+        let guard = slot(id).lock();
+        let process = guard
+            .process()
+            .expect("A process exists until its thread ends it.");
+
+        unsafe {
+            task::set_current_address_space(process.address_space.satp());
+        }
+
+        process.initial_trap_frame
+    };
+
+    user::enter(&initial_trap_frame);
+}
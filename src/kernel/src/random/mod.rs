@@ -0,0 +1,90 @@
+//! The kernel's entropy pool.
+//!
+//! `initialize` seeds the pool from the "rng-seed" property of the /chosen
+//! node and from the virtio entropy device, whichever are present, and mixes
+//! in the boot time, which is guessable but costs nothing. Without either
+//! source the pool still produces bytes, but they are predictable, which
+//! `is_seeded` reports.
+//!
+//! `random_bytes` and `random_u64` may be called from any context.
+
+use crate::{debug_println, drivers::virtio, time::Instant};
+use common_lib::dtb::{self, Dtb};
+use kernel_lib::{
+    random::{EntropyPool, SEED_SIZE},
+    sync::SpinLockIrqSave,
+};
+
+static POOL: SpinLockIrqSave<EntropyPool> = SpinLockIrqSave::new(EntropyPool::new());
+
+/// Seeds the pool. The virtio entropy device must be initialized first if
+/// there is one.
+///
+/// # Arguments
+///
+/// * `dtb` - The Device Tree Blob, for its "rng-seed" property.
+pub fn initialize(dtb: &Dtb) {
+    add_entropy(&Instant::now().ticks().to_le_bytes(), 0);
+
+    if let Some(seed) = dtb::chosen(dtb).and_then(|chosen| chosen.rng_seed) {
+        add_entropy(seed, seed.len());
+    }
+
+    if virtio::rng::is_available() {
+        reseed();
+    }
+
+    if !is_seeded() {
+        debug_println!("The entropy pool has no source of entropy.");
+    }
+}
+
+/// Mixes bytes into the pool.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to mix in.
+/// * `credited_bytes` - How many bytes of entropy they are worth.
+pub fn add_entropy(bytes: &[u8], credited_bytes: usize) {
+    POOL.lock().add_entropy(bytes, credited_bytes);
+}
+
+/// Mixes `SEED_SIZE` bytes from the virtio entropy device into the pool.
+///
+/// # Returns
+///
+/// The number of bytes the device supplied.
+pub fn reseed() -> usize {
+    let mut seed = [0; SEED_SIZE];
+    let mut length = 0;
+
+    while length < seed.len() {
+        match virtio::rng::read(&mut seed[length..]) {
+            Some(0) | None => break,
+            Some(count) => length += count,
+        }
+    }
+
+    add_entropy(&seed[..length], length);
+
+    length
+}
+
+/// Returns true once the pool has been seeded from a source of entropy.
+pub fn is_seeded() -> bool {
+    POOL.lock().is_seeded()
+}
+
+/// Fills a buffer with random bytes.
+///
+/// # Arguments
+///
+/// * `buffer` - The buffer to fill.
+pub fn random_bytes(buffer: &mut [u8]) {
+    POOL.lock().fill_bytes(buffer);
+}
+
+/// Returns a random u64.
+pub fn random_u64() -> u64 {
+    POOL.lock().next_u64()
+}
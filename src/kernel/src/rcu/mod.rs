@@ -0,0 +1,211 @@
+//! Read-copy-update for read-mostly data.
+//!
+//! Readers of RCU protected data take no lock. They enter a read-side
+//! critical section with `rcu_read_lock` and read the data through
+//! `rcu_dereference`. A writer publishes a new copy with `rcu_assign_pointer`
+//! and calls `synchronize_rcu` before it reuses the old copy, which waits
+//! until every reader that could still see it is done.
+//!
+//! Scheduling is cooperative, and a reader must not block or yield, so a
+//! hart that switches tasks cannot be inside a read-side critical section.
+//! Switching tasks, which the idle loop does on every wakeup, and taking a
+//! trap from user mode are therefore quiescent states. `synchronize_rcu`
+//! starts a new grace period and waits until every online hart has reported
+//! a quiescent state in it.
+
+use crate::{
+    hart::{MAX_HART_COUNT, current_hart_id, is_hart_online},
+    percpu, task,
+};
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+};
+
+/// The number of the newest grace period.
+static GRACE_PERIOD: AtomicU64 = AtomicU64::new(0);
+
+/// The newest grace period each hart has reported a quiescent state in,
+/// indexed by hart ID.
+static QUIESCENT_GRACE_PERIODS: [AtomicU64; MAX_HART_COUNT] =
+    [const { AtomicU64::new(0) }; MAX_HART_COUNT];
+
+/// The read-side critical section nesting depth of each hart, indexed by
+/// hart ID.
+static READ_LOCK_DEPTHS: [AtomicUsize; MAX_HART_COUNT] =
+    [const { AtomicUsize::new(0) }; MAX_HART_COUNT];
+
+/// A read-side critical section, which ends when the guard is dropped.
+///
+/// The guard stays on the hart that created it, since the code holding it
+/// cannot be switched away.
+#[allow(dead_code)]
+pub struct RcuReadGuard {
+    hart_id: usize,
+
+    /// Keeps the guard from being sent to another hart.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for RcuReadGuard {
+    fn drop(&mut self) {
+        READ_LOCK_DEPTHS[self.hart_id].fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// Enters a read-side critical section. Critical sections nest.
+///
+/// The caller must not block or yield until the guard is dropped.
+///
+/// # Returns
+///
+/// The guard that ends the critical section.
+#[allow(dead_code)]
+pub fn rcu_read_lock() -> RcuReadGuard {
+    let hart_id = current_hart_id();
+
+    READ_LOCK_DEPTHS[hart_id].fetch_add(1, Ordering::Acquire);
+
+    RcuReadGuard {
+        hart_id,
+        _not_send: PhantomData,
+    }
+}
+
+/// Reads an RCU protected pointer inside a read-side critical section.
+///
+/// # Arguments
+///
+/// * `pointer` - The pointer, written with `rcu_assign_pointer`.
+/// * `_guard` - The critical section the reference is valid in.
+///
+/// # Returns
+///
+/// The data the pointer points to, or `None` if it is null.
+#[allow(dead_code)]
+pub fn rcu_dereference<'guard, T>(
+    pointer: &AtomicPtr<T>,
+    _guard: &'guard RcuReadGuard,
+) -> Option<&'guard T> {
+    // The acquire load pairs with the release store in `rcu_assign_pointer`,
+    // so the data is initialized. The writer keeps it alive until a grace
+    // period has passed, which this critical section delays.
+    unsafe { pointer.load(Ordering::Acquire).as_ref() }
+}
+
+/// Publishes new data to RCU readers.
+///
+/// The data must be fully initialized before the call. Readers may see the
+/// old data until `synchronize_rcu` returns, so the writer must not free or
+/// change it before then.
+///
+/// # Arguments
+///
+/// * `pointer` - The pointer readers read with `rcu_dereference`.
+/// * `value` - The new data, or null.
+///
+/// # Returns
+///
+/// The old data.
+#[allow(dead_code)]
+pub fn rcu_assign_pointer<T>(pointer: &AtomicPtr<T>, value: *mut T) -> *mut T {
+    pointer.swap(value, Ordering::AcqRel)
+}
+
+/// Waits until every read-side critical section that started before the
+/// call has ended, so data unpublished before the call can be freed.
+///
+/// The calling task yields until every other online hart has switched tasks
+/// or returned to user mode.
+///
+/// # Panics
+///
+/// If called inside a read-side critical section, which would never end.
+#[allow(dead_code)]
+pub fn synchronize_rcu() {
+    let hart_id = current_hart_id();
+
+    if READ_LOCK_DEPTHS[hart_id].load(Ordering::Relaxed) != 0 {
+        panic!("synchronize_rcu called inside an RCU read-side critical section.");
+    }
+
+    let grace_period = GRACE_PERIOD.fetch_add(1, Ordering::AcqRel) + 1;
+
+    loop {
+        // Yielding reports a quiescent state for the calling hart.
+        task::yield_now();
+
+        let has_grace_period_passed = (0..MAX_HART_COUNT)
+            .filter(|&hart_id| is_hart_online(hart_id))
+            .all(|hart_id| {
+                QUIESCENT_GRACE_PERIODS[hart_id].load(Ordering::Acquire) >= grace_period
+            });
+
+        if has_grace_period_passed {
+            return;
+        }
+    }
+}
+
+/// Reports that the calling hart is outside of every read-side critical
+/// section. Called by the scheduler on every task switch and by the trap
+/// handler for traps from user mode.
+///
+/// # Panics
+///
+/// If the hart is inside a read-side critical section, since a reader must
+/// not block or yield.
+pub fn report_quiescent_state() {
+    let Some(per_hart) = percpu::try_current() else {
+        return;
+    };
+
+    let hart_id = per_hart.hart_id;
+
+    if READ_LOCK_DEPTHS[hart_id].load(Ordering::Relaxed) != 0 {
+        panic!("Switched tasks inside an RCU read-side critical section.");
+    }
+
+    // The release store makes the accesses of the finished critical sections
+    // visible to the writer that sees the grace period passed.
+    QUIESCENT_GRACE_PERIODS[hart_id].store(GRACE_PERIOD.load(Ordering::Acquire), Ordering::Release);
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    /// The two copies `test_synchronize_rcu_retires_old_copy` switches
+    /// between.
+    static COPIES: [AtomicU64; 2] = [AtomicU64::new(1), AtomicU64::new(2)];
+
+    static CURRENT_COPY: AtomicPtr<AtomicU64> = AtomicPtr::new(core::ptr::null_mut());
+
+    /// Reads the current copy in a critical section.
+    fn read_current_copy() -> Option<u64> {
+        let guard = rcu_read_lock();
+        let _nested_guard = rcu_read_lock();
+
+        rcu_dereference(&CURRENT_COPY, &guard).map(|copy| copy.load(Ordering::Relaxed))
+    }
+
+    kernel_test!(
+        fn test_synchronize_rcu_retires_old_copy() {
+            let first = &COPIES[0] as *const AtomicU64 as *mut AtomicU64;
+            let second = &COPIES[1] as *const AtomicU64 as *mut AtomicU64;
+
+            rcu_assign_pointer(&CURRENT_COPY, first);
+            assert_eq!(read_current_copy(), Some(1));
+
+            let old = rcu_assign_pointer(&CURRENT_COPY, second);
+            synchronize_rcu();
+
+            // No reader can see the old copy anymore, so it can be reused.
+            assert_eq!(old, first);
+            COPIES[0].store(3, Ordering::Relaxed);
+
+            assert_eq!(read_current_copy(), Some(2));
+        }
+    );
+}
@@ -1,88 +1,82 @@
-#![allow(unused)]
+//! The SBI debug console as a console device of the kernel.
+//!
+//! The SBI implementation accesses buffers by their physical address, so the
+//! kernel's virtual buffers are translated before they are handed to it.
 
-use super::sbi_calls::{sbi_call_1, sbi_call_3};
-use core::fmt::{self, Write};
+use crate::{console::ConsoleDevice, memory::virtual_to_physical};
+use sbi_lib::{
+    SbiError, SbiResult,
+    debug_console::{console_read, console_write, console_write_byte},
+};
 
-const DEBUG_CONSOLE_EXTENSION_ID: i32 = 0x4442434E;
+/// The size of the pages the kernel image is mapped with.
+const PAGE_SIZE: usize = 4096;
 
-const CONSOLE_WRITE_ID: i32 = 0x0;
-const CONSOLE_WRITE_BYTE_ID: i32 = 0x2;
-
-#[inline(always)]
-pub fn sbi_debug_console_write(buffer: &[u8]) -> (isize, usize) {
-    let num_bytes = buffer.len();
-    let buffer_addr = buffer.as_ptr() as usize;
+/// Writes bytes to the debug console.
+///
+/// The SBI implementation accesses the buffer by physical address, so only
+/// the part of the buffer on its first page is written.
+///
+/// # Returns
+///
+/// The number of bytes written, which may be fewer than the length of the
+/// buffer.
+#[allow(dead_code)]
+pub fn sbi_debug_console_write(buffer: &[u8]) -> SbiResult<usize> {
+    let (physical_address, num_bytes) = physical_buffer(buffer.as_ptr() as usize, buffer.len())?;
 
-    sbi_call_3(
-        DEBUG_CONSOLE_EXTENSION_ID as isize,
-        CONSOLE_WRITE_ID as isize,
-        num_bytes,
-        buffer_addr,
-        0,
-    )
+    console_write(physical_address, num_bytes)
 }
 
-#[inline(always)]
-pub fn sbi_debug_console_write_byte(byte: u8) -> (isize, usize) {
-    sbi_call_1(
-        DEBUG_CONSOLE_EXTENSION_ID as isize,
-        CONSOLE_WRITE_BYTE_ID as isize,
-        byte as usize,
-    )
+/// Reads the bytes waiting on the debug console without blocking.
+///
+/// The SBI implementation accesses the buffer by physical address, so only
+/// the part of the buffer on its first page is filled.
+///
+/// # Returns
+///
+/// The number of bytes read, which is zero if no input is waiting.
+pub fn sbi_debug_console_read(buffer: &mut [u8]) -> SbiResult<usize> {
+    let (physical_address, num_bytes) =
+        physical_buffer(buffer.as_mut_ptr() as usize, buffer.len())?;
+
+    console_read(physical_address, num_bytes)
 }
 
-/// A formatter that writes directly to the SBI debug console.
-pub struct DebugConsoleWriter;
+/// The SBI debug console as a console device.
+pub static SBI_CONSOLE_DEVICE: ConsoleDevice = ConsoleDevice {
+    name: "sbi",
+    write: write_to_console,
+    read: Some(read_from_console),
+};
 
-impl Write for DebugConsoleWriter {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        // Write each byte individually using the byte-by-byte function.
-        for byte in s.bytes() {
-            sbi_debug_console_write_byte(byte);
+/// Writes bytes to the debug console for `SBI_CONSOLE_DEVICE`, one at a time
+/// so that a partial write never drops bytes.
+fn write_to_console(bytes: &[u8]) {
+    for &byte in bytes {
+        if console_write_byte(byte).is_err() {
+            return;
         }
-
-        Ok(())
     }
 }
 
-/// Prints formatted text to the SBI debug console without heap allocations.
-///
-/// This macro works similar to `format!` but writes directly to the debug
-/// console.
-///
-/// # Examples
-///
-/// ```
-/// debug_print!("Hello, {}!", "world");
-/// debug_println!("Value = {}", 42);
-/// ```
-#[macro_export]
-macro_rules! debug_print {
-    ($($arg:tt)*) => {{
-        use core::fmt::Write;
-        use $crate::sbi::debug_console::DebugConsoleWriter;
-        let _ = write!(DebugConsoleWriter, $($arg)*);
-    }};
+/// Reads the waiting bytes for `SBI_CONSOLE_DEVICE`, treating errors as no
+/// input.
+fn read_from_console(buffer: &mut [u8]) -> usize {
+    sbi_debug_console_read(buffer).unwrap_or(0)
 }
 
-/// Prints formatted text to the SBI debug console, followed by a newline.
-///
-/// This macro works similar to `format!` but writes directly to the debug
-/// console.
+/// Translates a kernel buffer into the physical memory the SBI implementation
+/// accesses.
 ///
-/// # Examples
+/// # Returns
 ///
-/// ```
-/// debug_println!("Hello, {}!", "world");
-/// debug_println!("Value = {}", 42);
-/// ```
-#[macro_export]
-macro_rules! debug_println {
-    () => {
-        $crate::debug_print!("\n")
-    };
-    ($($arg:tt)*) => {{
-        $crate::debug_print!($($arg)*);
-        $crate::debug_print!("\n");
-    }};
+/// The physical address of the buffer and the number of its bytes up to the
+/// end of its first page, which are the only ones known to be physically
+/// contiguous.
+fn physical_buffer(address: usize, length: usize) -> SbiResult<(usize, usize)> {
+    let physical_address = virtual_to_physical(address).ok_or(SbiError::InvalidAddress)?;
+    let bytes_on_page = PAGE_SIZE - address % PAGE_SIZE;
+
+    Ok((physical_address, length.min(bytes_on_page)))
 }
@@ -1,2 +1,3 @@
+//! The parts of the SBI the kernel builds on, beyond the calls in `sbi_lib`.
+
 pub mod debug_console;
-pub mod sbi_calls;
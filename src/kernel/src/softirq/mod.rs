@@ -0,0 +1,124 @@
+//! Softirqs, the bottom halves of device interrupts.
+//!
+//! An interrupt handler acknowledges its device and raises a softirq on the
+//! calling hart with `raise`, leaving the slower processing, such as taking
+//! received frames or completing block requests, to the softirq's handler.
+//! Pending softirqs run on interrupt exit, before the trap returns to the
+//! interrupted code, so the work is done before any thread could run and the
+//! hard interrupt handler stays short.
+//!
+//! Every handler call gets a budget of work units, and the pending softirqs
+//! are run at most `MAX_ROUNDS` times per interrupt exit. Whatever is left
+//! stays pending until the next interrupt exit on the hart, at the latest the
+//! next timer tick, so a busy device cannot keep the hart from returning to
+//! its threads.
+
+use crate::hart::{MAX_HART_COUNT, current_hart_id};
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+/// The most work units, such as frames or requests, a handler processes per
+/// call.
+const BUDGET: usize = 64;
+
+/// The most times the pending softirqs are run per interrupt exit.
+const MAX_ROUNDS: usize = 4;
+
+/// The kinds of deferred interrupt work, in the order they run.
+#[repr(usize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Softirq {
+    /// Frames the network device received or finished sending.
+    Network = 0,
+
+    /// Requests the block device completed.
+    BlockCompletion = 1,
+}
+
+/// The number of kinds of softirqs.
+const SOFTIRQ_COUNT: usize = 2;
+
+/// The function that processes a softirq.
+///
+/// The handler receives the most work units it may process. It runs in trap
+/// context with interrupts disabled and must not block.
+///
+/// # Returns
+///
+/// True if work is left after the budget ran out, which raises the softirq
+/// again.
+pub type SoftirqHandler = fn(budget: usize) -> bool;
+
+/// The registered `SoftirqHandler` of each softirq, or null if there is
+/// none.
+static HANDLERS: [AtomicPtr<()>; SOFTIRQ_COUNT] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; SOFTIRQ_COUNT];
+
+/// The pending softirqs of each hart as a bitmask, indexed by hart ID.
+static PENDING: [AtomicUsize; MAX_HART_COUNT] = [const { AtomicUsize::new(0) }; MAX_HART_COUNT];
+
+/// The number of interrupt exits that left softirqs pending because the
+/// rounds ran out.
+static DEFERRED_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Registers the function that processes a softirq, replacing any handler
+/// registered before.
+///
+/// # Arguments
+///
+/// * `softirq` - The softirq.
+/// * `handler` - The function to call on interrupt exit while the softirq is
+///   pending.
+pub fn register(softirq: Softirq, handler: SoftirqHandler) {
+    HANDLERS[softirq as usize].store(handler as *mut (), Ordering::Release);
+}
+
+/// Marks a softirq pending on the calling hart, so its handler runs when the
+/// current interrupt exits.
+///
+/// # Arguments
+///
+/// * `softirq` - The softirq.
+pub fn raise(softirq: Softirq) {
+    PENDING[current_hart_id()].fetch_or(1 << softirq as usize, Ordering::Relaxed);
+}
+
+/// Returns the number of interrupt exits that left softirqs pending for a
+/// later one.
+pub fn deferred_count() -> u64 {
+    DEFERRED_COUNT.load(Ordering::Relaxed)
+}
+
+/// Runs the pending softirqs of the calling hart. Called by the trap handler
+/// on interrupt exit, with interrupts disabled.
+pub fn run_pending() {
+    let pending = &PENDING[current_hart_id()];
+
+    for _ in 0..MAX_ROUNDS {
+        let mut softirqs = pending.swap(0, Ordering::Relaxed);
+
+        if softirqs == 0 {
+            return;
+        }
+
+        while softirqs != 0 {
+            let index = softirqs.trailing_zeros() as usize;
+            softirqs &= softirqs - 1;
+
+            let handler = HANDLERS[index].load(Ordering::Acquire);
+            if handler.is_null() {
+                continue;
+            }
+
+            // The pointer was created from a `SoftirqHandler` in `register`.
+            let handler: SoftirqHandler = unsafe { core::mem::transmute(handler) };
+
+            if handler(BUDGET) {
+                pending.fetch_or(1 << index, Ordering::Relaxed);
+            }
+        }
+    }
+
+    if pending.load(Ordering::Relaxed) != 0 {
+        DEFERRED_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,343 @@
+//! Guard pages below kernel stacks.
+//!
+//! Every kernel stack has an unmapped guard page below it, so that a stack
+//! overflow faults instead of silently overwriting whatever lies below. The
+//! stacks of every hart and of kernel threads are `GuardedStack`s, whose
+//! guard page is unmapped before the stack's limit is recorded. The boot hart
+//! switches to its stack when it enters the kernel and protects it once the
+//! kernel's page tables can be changed.
+//!
+//! The fault of an overflow cannot be handled on the stack that overflowed.
+//! The trap entry compares the stack pointer of a trap from kernel mode with
+//! the `stack_limit` of the hart's `PerHart` block and continues on the hart's
+//! overflow stack when the trap frame would not fit above the limit. The trap
+//! handler then reports the overflow with `report_overflow`.
+//!
+//! To show how close stacks come to overflowing, every stack is painted with
+//! `STACK_PAINT` before it is used, and the deepest word that no longer holds
+//! the paint marks the most the stack has been used. `for_each_stack` reports
+//! this high-water mark for every kernel stack, which the monitor's `stacks`
+//! command prints, so that their sizes can be tuned.
+
+use crate::{
+    BOOT_HART_STACK, backtrace, debug_println,
+    hart::{self, MAX_HART_COUNT, current_hart_id},
+    memory::{self, PAGE_SIZE},
+    percpu, task,
+    trap::{print_trap_frame, trap_cause::TrapCause, trap_frame::TrapFrame},
+};
+use boot_lib::memory::mmu::MapError;
+use core::{cell::UnsafeCell, sync::atomic::Ordering};
+
+/// The size in bytes of the stack each hart reports an overflow on.
+const OVERFLOW_STACK_SIZE: usize = 16 * 1024;
+
+/// How far below the stack pointer `initialize_hart` looks for the guard page
+/// of the stack the hart runs on.
+const MAX_STACK_SEARCH_SIZE: usize = 64 * 1024;
+
+/// The index of s0, the frame pointer, in the trap frame registers.
+const FRAME_POINTER_REGISTER: usize = 8;
+
+/// The index of sp in the trap frame registers.
+const STACK_POINTER_REGISTER: usize = 2;
+
+/// The word unused stacks are painted with.
+const STACK_PAINT: u64 = 0x57ac_57ac_57ac_57ac;
+
+/// The code a kernel stack belongs to, as reported by `for_each_stack`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StackOwner {
+    /// The stack the boot hart entered the kernel on, which its idle task
+    /// runs on.
+    BootHart,
+
+    /// The stack a secondary hart was started on, which its idle task runs
+    /// on.
+    Hart(usize),
+
+    /// The stack a hart reports an overflow of its stack on.
+    Overflow(usize),
+
+    /// The stack of a kernel thread.
+    Thread { id: usize, name: &'static str },
+}
+
+/// How much of a stack has been used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StackUsage {
+    /// The size of the stack in bytes.
+    pub size: usize,
+
+    /// The most bytes of the stack in use at once since it was painted.
+    pub high_water_mark: usize,
+}
+
+impl StackUsage {
+    /// Returns the high-water mark in percent of the size.
+    pub fn used_percent(&self) -> usize {
+        self.high_water_mark * 100 / self.size
+    }
+}
+
+/// A page aligned stack of `SIZE` bytes with a guard page below it.
+#[repr(C, align(4096))]
+pub struct GuardedStack<const SIZE: usize> {
+    guard_page: UnsafeCell<[u8; PAGE_SIZE]>,
+    stack: UnsafeCell<[u8; SIZE]>,
+}
+
+// The guard page is never accessed, and only the code a stack is given to
+// ever touches the stack's memory.
+unsafe impl<const SIZE: usize> Sync for GuardedStack<SIZE> {}
+
+impl<const SIZE: usize> GuardedStack<SIZE> {
+    /// Creates a stack, whose guard page is still mapped.
+    pub const fn new() -> Self {
+        assert!(
+            SIZE.is_multiple_of(PAGE_SIZE),
+            "A guarded stack must be a whole number of pages."
+        );
+
+        Self {
+            guard_page: UnsafeCell::new([0; PAGE_SIZE]),
+            stack: UnsafeCell::new([0; SIZE]),
+        }
+    }
+
+    /// Returns the lowest address of the stack, just above its guard page.
+    pub fn bottom(&self) -> usize {
+        self.stack.get() as usize
+    }
+
+    /// Returns the virtual address just past the end of the stack.
+    pub fn top(&self) -> usize {
+        self.bottom() + SIZE
+    }
+
+    /// Fills the stack with `STACK_PAINT`. If the calling code runs on the
+    /// stack, only the part below the stack pointer is filled.
+    pub fn paint(&self) {
+        let stack_pointer: usize;
+        unsafe {
+            core::arch::asm!("mv {}, sp", out(reg) stack_pointer, options(nomem, nostack));
+        }
+
+        let end = if (self.bottom()..self.top()).contains(&stack_pointer) {
+            stack_pointer & !(size_of::<u64>() - 1)
+        } else {
+            self.top()
+        };
+
+        // The loop is written in assembly so that it cannot call a function,
+        // whose frame would be painted over while the stack is in use.
+        unsafe {
+            core::arch::asm!(
+                "2:",
+                "bgeu {address}, {end}, 3f",
+                "sd {paint}, 0({address})",
+                "addi {address}, {address}, 8",
+                "j 2b",
+                "3:",
+                address = inout(reg) self.bottom() => _,
+                end = in(reg) end,
+                paint = in(reg) STACK_PAINT,
+                options(nostack)
+            );
+        }
+    }
+
+    /// Returns how much of the stack has been used since it was painted. The
+    /// stack may be in use by another hart while it is read.
+    pub fn usage(&self) -> StackUsage {
+        let unused_size = (self.bottom()..self.top())
+            .step_by(size_of::<u64>())
+            .take_while(
+                |&address| unsafe { (address as *const u64).read_volatile() } == STACK_PAINT,
+            )
+            .count()
+            * size_of::<u64>();
+
+        StackUsage {
+            size: SIZE,
+            high_water_mark: SIZE - unused_size,
+        }
+    }
+
+    /// Unmaps the guard page of the stack on every hart. Does nothing if it
+    /// is unmapped already.
+    ///
+    /// # Panics
+    ///
+    /// If the guard page is not mapped with a 4KiB page of its own.
+    pub fn protect(&self) {
+        match memory::unmap_kernel_page(self.guard_page.get() as usize) {
+            Ok(()) | Err(MapError::NotMapped) => {}
+            Err(error) => panic!(
+                "The guard page at {:#x} could not be unmapped: {:?}.",
+                self.guard_page.get() as usize,
+                error
+            ),
+        }
+    }
+}
+
+/// The stack each hart continues on after its stack overflowed, indexed by
+/// hart ID.
+static OVERFLOW_STACKS: [GuardedStack<OVERFLOW_STACK_SIZE>; MAX_HART_COUNT] =
+    [const { GuardedStack::new() }; MAX_HART_COUNT];
+
+/// Prepares the calling hart to catch overflows of the stack it runs on.
+/// Must be called once on every hart after `task::initialize_hart` and
+/// `memory::initialize`, while the hart still runs on the stack it entered
+/// the kernel with.
+///
+/// # Arguments
+///
+/// * `hart_id` - The ID of the calling hart.
+pub fn initialize_hart(hart_id: usize) {
+    let overflow_stack = &OVERFLOW_STACKS[hart_id];
+    overflow_stack.protect();
+    overflow_stack.paint();
+
+    percpu::current()
+        .overflow_stack_top
+        .store(overflow_stack.top(), Ordering::Relaxed);
+
+    let stack_pointer: usize;
+    unsafe {
+        core::arch::asm!("mv {}, sp", out(reg) stack_pointer, options(nomem, nostack));
+    }
+
+    match find_stack_limit(stack_pointer) {
+        Some(stack_limit) => task::set_current_stack_limit(stack_limit),
+        None => debug_println!(
+            "Hart {} found no guard page below its stack at {:#x}.",
+            hart_id,
+            stack_pointer
+        ),
+    }
+}
+
+/// Calls a function for every kernel stack in use with how much of it has
+/// been used, first the stacks of the harts and then those of kernel threads.
+pub fn for_each_stack(mut callback: impl FnMut(StackOwner, StackUsage)) {
+    callback(StackOwner::BootHart, BOOT_HART_STACK.usage());
+
+    for hart_id in 0..MAX_HART_COUNT {
+        if let Some(usage) = hart::stack_usage(hart_id) {
+            callback(StackOwner::Hart(hart_id), usage);
+        }
+    }
+
+    for (hart_id, overflow_stack) in OVERFLOW_STACKS.iter().enumerate() {
+        if hart::is_hart_online(hart_id) {
+            callback(StackOwner::Overflow(hart_id), overflow_stack.usage());
+        }
+    }
+
+    task::for_each_thread_stack(|id, name, usage| {
+        callback(StackOwner::Thread { id, name }, usage);
+    });
+}
+
+/// Returns true if the trap entry moved a trap to the calling hart's overflow
+/// stack, which it only does when the stack of the trapping code overflowed.
+///
+/// # Arguments
+///
+/// * `trap_frame` - The trap frame passed to the trap handler.
+pub fn is_overflow_trap(trap_frame: &TrapFrame) -> bool {
+    let Some(per_hart) = percpu::try_current() else {
+        return false;
+    };
+
+    let overflow_stack = &OVERFLOW_STACKS[per_hart.hart_id];
+    let trap_frame_address = trap_frame as *const TrapFrame as usize;
+
+    (overflow_stack.bottom()..overflow_stack.top()).contains(&trap_frame_address)
+}
+
+/// Returns true if an address is in the guard page of the stack the calling
+/// hart runs on.
+pub fn is_guard_page(virtual_address: usize) -> bool {
+    let stack_limit = task::current_stack_limit();
+
+    stack_limit != 0 && (stack_limit - PAGE_SIZE..stack_limit).contains(&virtual_address)
+}
+
+/// Reports an overflow of the calling hart's stack with a backtrace of the
+/// overflowed stack and halts the hart.
+///
+/// # Arguments
+///
+/// * `trap_frame` - The register state of the code whose stack overflowed.
+/// * `cause` - The decoded cause of the trap.
+pub fn report_overflow(trap_frame: &TrapFrame, cause: TrapCause) -> ! {
+    let hart_id = current_hart_id();
+
+    debug_println!("\n\n===== KERNEL STACK OVERFLOW =====");
+    debug_println!("Kernel stack overflow on hart {}.", hart_id);
+    debug_println!();
+    print_trap_frame(trap_frame, cause);
+
+    // The stack pointer is in the guard page, which cannot be read, so the
+    // backtrace starts at the bottom of the stack.
+    debug_println!("\nBacktrace:");
+    backtrace::print(
+        trap_frame.registers[FRAME_POINTER_REGISTER],
+        trap_frame.registers[STACK_POINTER_REGISTER].max(task::current_stack_limit()),
+    );
+
+    debug_println!("=================================\n");
+
+    panic!("Kernel stack overflow on hart {}.", hart_id);
+}
+
+/// Finds the lowest address of the stack containing a stack pointer, which
+/// is the top of the first unmapped page below it.
+///
+/// # Returns
+///
+/// The address, or `None` if every page within `MAX_STACK_SEARCH_SIZE` below
+/// the stack pointer is mapped.
+fn find_stack_limit(stack_pointer: usize) -> Option<usize> {
+    let mut stack_limit = stack_pointer & !(PAGE_SIZE - 1);
+
+    while memory::is_readable(stack_limit - PAGE_SIZE) {
+        stack_limit -= PAGE_SIZE;
+
+        if stack_pointer - stack_limit >= MAX_STACK_SEARCH_SIZE {
+            return None;
+        }
+    }
+
+    Some(stack_limit)
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    static TEST_STACK: GuardedStack<PAGE_SIZE> = GuardedStack::new();
+
+    kernel_test!(
+        fn test_stack_usage_reports_deepest_write() {
+            TEST_STACK.paint();
+            assert_eq!(TEST_STACK.usage().high_water_mark, 0);
+
+            let address = TEST_STACK.top() - 1000;
+            unsafe {
+                (address as *mut u8).write_volatile(0);
+            }
+
+            let usage = TEST_STACK.usage();
+            assert_eq!(usage.high_water_mark, 1000);
+            assert_eq!(usage.used_percent(), 24);
+
+            TEST_STACK.paint();
+            assert_eq!(TEST_STACK.usage().high_water_mark, 0);
+        }
+    );
+}
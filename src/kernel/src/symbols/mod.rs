@@ -0,0 +1,76 @@
+//! Names for kernel code addresses, read from the symbol table the build links
+//! into the `.kernel_symbols` section.
+//!
+//! The section is empty when the kernel is linked without running
+//! `scripts/generate-symbol-table.sh`, in which case nothing is symbolized.
+//!
+//! The table holds the addresses the kernel was linked at, so `lookup`
+//! subtracts the slide the boot stage mapped the kernel with.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use kernel_lib::symbol_table::SymbolTable;
+
+/// The number of bytes the kernel runs above its link address.
+static KERNEL_SLIDE: AtomicUsize = AtomicUsize::new(0);
+
+/// Records the slide the boot stage mapped the kernel with.
+///
+/// # Arguments
+///
+/// * `slide` - The number of bytes the kernel runs above its link address.
+pub fn set_kernel_slide(slide: usize) {
+    KERNEL_SLIDE.store(slide, Ordering::Relaxed);
+}
+
+/// Returns the symbol table embedded in the kernel image, or `None` if the
+/// image was linked without one.
+pub fn kernel_symbol_table() -> Option<SymbolTable<'static>> {
+    unsafe extern "C" {
+        static _kernel_symbols_start: u8;
+        static _kernel_symbols_end: u8;
+    }
+
+    let start = &raw const _kernel_symbols_start;
+    let end = &raw const _kernel_symbols_end;
+
+    // The linker script places both symbols around the read only symbol table
+    // section, which is part of the mapped kernel image.
+    let bytes = unsafe { core::slice::from_raw_parts(start, end as usize - start as usize) };
+
+    SymbolTable::parse(bytes)
+}
+
+/// Finds the kernel function containing an address.
+///
+/// # Arguments
+///
+/// * `address` - Any address, such as a return address or sepc.
+///
+/// # Returns
+///
+/// The name of the function and the offset of `address` into it, or `None` if
+/// the address is not within a known function.
+pub fn lookup(address: usize) -> Option<(&'static str, usize)> {
+    let linked_address = address.wrapping_sub(KERNEL_SLIDE.load(Ordering::Relaxed)) as u64;
+    let symbol = kernel_symbol_table()?.lookup(linked_address)?;
+
+    Some((symbol.name, (linked_address - symbol.address) as usize))
+}
+
+/// Formats an address followed by the function containing it, such as
+/// `0xffffffc000001042 <kernel::kernel_main+0x42>`.
+pub struct Symbolized(pub usize);
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{:#018x}", self.0)?;
+
+        match lookup(self.0) {
+            Some((name, offset)) => write!(formatter, " <{}+{:#x}>", name, offset),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,704 @@
+//! System calls from user programs.
+//!
+//! The trap handler passes every `ecall` from user mode to `handle_syscall`,
+//! which looks up the handler of the number in a7 in `SYSCALL_TABLE` and
+//! returns its encoded result in a0. The register ABI and the system call
+//! numbers are shared with user programs through `common_lib::syscall`.
+
+use crate::{
+    console,
+    fs::{self, FsError, NodeKind, OpenFile, SeekFrom},
+    futex::{self, FutexError, FutexKey},
+    log,
+    memory::{
+        FaultAccess, MAX_SHARED_MEMORY_NAME_LENGTH, SharedMemory, SharedMemoryError, UserPageAccess,
+    },
+    pipe::{self, PipeError},
+    process::{self, Handle, ProcessError},
+    task,
+    trap::trap_frame::TrapFrame,
+    user::{self, elf::LoadError, programs::ProgramError},
+};
+use boot_lib::memory::mmu::MapError;
+use common_lib::syscall::{
+    MAX_SYSCALL_ARGUMENTS, OPEN_CREATE, OPEN_TRUNCATE, PROTECTION_EXECUTE, PROTECTION_READ,
+    PROTECTION_WRITE, SEEK_CURRENT, SEEK_END, SEEK_SET, SyscallError, SyscallNumber, WAIT_NO_HANG,
+    encode_result,
+};
+use core::time::Duration;
+
+/// The index of a0 in the trap frame registers. The arguments are in a0
+/// through a5 and the result goes in a0.
+const FIRST_ARGUMENT_REGISTER: usize = 10;
+
+/// The index of a7 in the trap frame registers, which holds the number.
+const NUMBER_REGISTER: usize = 17;
+
+/// The size of the kernel buffer user data is read and written through.
+const TRANSFER_CHUNK_SIZE: usize = 128;
+
+/// The longest program name or path `exec` accepts.
+const MAX_PROGRAM_NAME_LENGTH: usize = 32;
+
+/// The longest path `open` accepts.
+const MAX_PATH_LENGTH: usize = 128;
+
+/// How often a console read checks for input when no device notifies it.
+const CONSOLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A system call handler.
+///
+/// Handlers receive the user register state, which they may change, and the
+/// six argument registers, and return the result to encode into a0.
+type SyscallHandler =
+    fn(&mut TrapFrame, &[usize; MAX_SYSCALL_ARGUMENTS]) -> Result<usize, SyscallError>;
+
+/// The handler of every system call, in the order of `SyscallNumber`.
+static SYSCALL_TABLE: [SyscallHandler; SyscallNumber::COUNT] = [
+    sys_write,
+    sys_exit,
+    sys_yield,
+    sys_fork,
+    sys_exec,
+    sys_map_anonymous,
+    sys_open,
+    sys_read,
+    sys_close,
+    sys_seek,
+    sys_dup,
+    sys_pipe,
+    sys_shm_create,
+    sys_shm_open,
+    sys_shm_map,
+    sys_futex_wait,
+    sys_futex_wake,
+    sys_wait,
+];
+
+impl From<ProcessError> for SyscallError {
+    fn from(error: ProcessError) -> Self {
+        match error {
+            ProcessError::TooManyProcesses
+            | ProcessError::OutOfMemory
+            | ProcessError::Load(LoadError::Map(MapError::OutOfMemory))
+            | ProcessError::Program(ProgramError::TooManyPrograms) => Self::OutOfMemory,
+            ProcessError::Program(
+                ProgramError::NotFound | ProgramError::File(FsError::NotMounted),
+            ) => Self::NotFound,
+            ProcessError::Load(_) | ProcessError::Program(_) => Self::InvalidArgument,
+            ProcessError::NoChild => Self::NoChild,
+        }
+    }
+}
+
+impl From<FsError> for SyscallError {
+    fn from(error: FsError) -> Self {
+        match error {
+            FsError::NotMounted | FsError::NotFound | FsError::NotADirectory => Self::NotFound,
+            FsError::IsADirectory => Self::IsADirectory,
+            FsError::ReadOnly => Self::ReadOnly,
+            FsError::TooManyOpenFiles => Self::TooManyOpenFiles,
+            FsError::Tmpfs(_) => Self::OutOfMemory,
+            FsError::Fat32(_) | FsError::Cpio(_) => Self::IoError,
+            FsError::AlreadyMounted
+            | FsError::TooManyMounts
+            | FsError::AlreadyExists
+            | FsError::NotEmpty
+            | FsError::InvalidPath
+            | FsError::InvalidOffset
+            | FsError::NoDevice
+            | FsError::UnsupportedBlockSize(_) => Self::InvalidArgument,
+        }
+    }
+}
+
+impl From<PipeError> for SyscallError {
+    fn from(error: PipeError) -> Self {
+        match error {
+            PipeError::TooManyPipes => Self::TooManyOpenFiles,
+            PipeError::Broken => Self::BrokenPipe,
+        }
+    }
+}
+
+impl From<SharedMemoryError> for SyscallError {
+    fn from(error: SharedMemoryError) -> Self {
+        match error {
+            SharedMemoryError::TooManyObjects | SharedMemoryError::OutOfMemory => Self::OutOfMemory,
+            SharedMemoryError::InvalidLength(_) | SharedMemoryError::InvalidName => {
+                Self::InvalidArgument
+            }
+            SharedMemoryError::AlreadyExists => Self::AlreadyExists,
+            SharedMemoryError::NotFound => Self::NotFound,
+        }
+    }
+}
+
+impl From<FutexError> for SyscallError {
+    fn from(error: FutexError) -> Self {
+        match error {
+            FutexError::ValueChanged => Self::TryAgain,
+            FutexError::TooManyWaiters => Self::OutOfMemory,
+        }
+    }
+}
+
+/// Handles an `ecall` from user mode. Called from the trap handler.
+///
+/// # Arguments
+///
+/// * `trap_frame` - The user register state. a0 receives the result and sepc
+///   is moved past the `ecall`.
+pub fn handle_syscall(trap_frame: &mut TrapFrame) {
+    // Return to the instruction after the ecall, which is always 4 bytes.
+    trap_frame.sepc += 4;
+
+    let number = trap_frame.registers[NUMBER_REGISTER];
+
+    let mut arguments = [0; MAX_SYSCALL_ARGUMENTS];
+    arguments.copy_from_slice(
+        &trap_frame.registers
+            [FIRST_ARGUMENT_REGISTER..FIRST_ARGUMENT_REGISTER + MAX_SYSCALL_ARGUMENTS],
+    );
+
+    let result = match SyscallNumber::from_raw(number) {
+        Some(syscall) => SYSCALL_TABLE[syscall as usize](trap_frame, &arguments),
+        None => Err(SyscallError::NoSuchSyscall),
+    };
+
+    trap_frame.registers[FIRST_ARGUMENT_REGISTER] = encode_result(result);
+}
+
+/// `write(fd, buffer, length)`: writes user bytes to an open handle.
+///
+/// # Returns
+///
+/// The number of bytes written, which is always `length`.
+fn sys_write(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [handle_number, user_address, length, ..] = *arguments;
+
+    let handle = process::current_handle(handle_number).ok_or(SyscallError::BadFileDescriptor)?;
+
+    user_address
+        .checked_add(length)
+        .ok_or(SyscallError::BadAddress)?;
+
+    let mut buffer = [0u8; TRANSFER_CHUNK_SIZE];
+    let mut offset = 0;
+
+    while offset < length {
+        let chunk_length = (length - offset).min(TRANSFER_CHUNK_SIZE);
+        let chunk = &mut buffer[..chunk_length];
+
+        user::copy_from_user(user_address + offset, chunk)?;
+
+        match &handle {
+            Handle::Console => {
+                log::record(chunk);
+                console::write_bytes(chunk);
+            }
+            Handle::File(file) => file.write(chunk)?,
+            Handle::PipeWriter(writer) => {
+                let written = writer.write(chunk)?;
+
+                // The last reader closed partway through the chunk.
+                if written < chunk_length {
+                    return Ok(offset + written);
+                }
+            }
+            Handle::PipeReader(_) | Handle::SharedMemory(_) => {
+                return Err(SyscallError::BadFileDescriptor);
+            }
+        }
+
+        offset += chunk_length;
+    }
+
+    Ok(length)
+}
+
+/// `read(fd, buffer, length)`: reads bytes from an open handle into user
+/// memory. A console or pipe read waits until input arrives and returns what
+/// is waiting, while a file read fills the buffer unless the file ends first.
+///
+/// # Returns
+///
+/// The number of bytes read, which is zero at the end of a file or once
+/// every write end of a pipe is closed.
+fn sys_read(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [handle_number, user_address, length, ..] = *arguments;
+
+    let handle = process::current_handle(handle_number).ok_or(SyscallError::BadFileDescriptor)?;
+
+    user_address
+        .checked_add(length)
+        .ok_or(SyscallError::BadAddress)?;
+
+    let mut buffer = [0u8; TRANSFER_CHUNK_SIZE];
+
+    match handle {
+        Handle::Console => {
+            if length == 0 {
+                return Ok(0);
+            }
+
+            let chunk = &mut buffer[..length.min(TRANSFER_CHUNK_SIZE)];
+
+            let read_length = loop {
+                match console::poll_input(chunk) {
+                    0 => console::wait_for_input(CONSOLE_POLL_INTERVAL),
+                    read_length => break read_length,
+                }
+            };
+
+            user::copy_to_user(user_address, &chunk[..read_length])?;
+
+            Ok(read_length)
+        }
+        Handle::File(file) => {
+            let mut offset = 0;
+
+            while offset < length {
+                let chunk = &mut buffer[..(length - offset).min(TRANSFER_CHUNK_SIZE)];
+                let read_length = file.read(chunk)?;
+
+                user::copy_to_user(user_address + offset, &chunk[..read_length])?;
+                offset += read_length;
+
+                if read_length < chunk.len() {
+                    break;
+                }
+            }
+
+            Ok(offset)
+        }
+        Handle::PipeReader(reader) => {
+            let chunk = &mut buffer[..length.min(TRANSFER_CHUNK_SIZE)];
+            let read_length = reader.read(chunk);
+
+            user::copy_to_user(user_address, &chunk[..read_length])?;
+
+            Ok(read_length)
+        }
+        Handle::PipeWriter(_) | Handle::SharedMemory(_) => Err(SyscallError::BadFileDescriptor),
+    }
+}
+
+/// `exit(code)`: ends the calling process. Does not return.
+fn sys_exit(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    process::exit_current(arguments[0] as isize);
+}
+
+/// `yield()`: lets other threads run before returning.
+fn sys_yield(
+    _trap_frame: &mut TrapFrame,
+    _arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    task::yield_now();
+
+    Ok(0)
+}
+
+/// `wait(pid, status, flags)`: collects a child of the calling process that
+/// exited, waiting for one unless `flags` holds `WAIT_NO_HANG`. A `pid` of
+/// zero collects any child, and a nonzero `status` receives the exit code as
+/// a dword.
+///
+/// # Returns
+///
+/// The ID of the child, or zero if none has exited and the call did not
+/// wait.
+fn sys_wait(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [child_id, user_address, flags, ..] = *arguments;
+
+    if flags & !WAIT_NO_HANG != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let child_id = (child_id != 0).then_some(child_id);
+
+    let Some((child_id, exit_code)) =
+        process::wait_child_current(child_id, flags & WAIT_NO_HANG == 0)?
+    else {
+        return Ok(0);
+    };
+
+    // The child is collected already, so its exit code is lost if the
+    // address is bad.
+    if user_address != 0 {
+        user::copy_to_user(user_address, &exit_code.to_ne_bytes())?;
+    }
+
+    Ok(child_id)
+}
+
+/// `fork()`: starts a copy of the calling process.
+///
+/// # Returns
+///
+/// The ID of the new process. The new process sees zero instead.
+fn sys_fork(
+    trap_frame: &mut TrapFrame,
+    _arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    // sepc is already past the ecall, so the copy continues after it too.
+    Ok(process::fork_current(trap_frame)?)
+}
+
+/// `exec(name, name_length)`: replaces the calling program with the named
+/// embedded program, or with the program at a path on the root file system.
+///
+/// # Returns
+///
+/// Zero in a0 of the new program, which starts with every other register but
+/// sp zero too.
+fn sys_exec(
+    trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [user_address, length, ..] = *arguments;
+
+    if length > MAX_PROGRAM_NAME_LENGTH {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let mut buffer = [0u8; MAX_PROGRAM_NAME_LENGTH];
+    let name = &mut buffer[..length];
+    user::copy_from_user(user_address, name)?;
+
+    let name = core::str::from_utf8(name).map_err(|_| SyscallError::InvalidArgument)?;
+
+    process::exec_current(name, trap_frame)?;
+
+    Ok(0)
+}
+
+/// `map_anonymous(length, protection)`: reserves zeroed memory in the calling
+/// process. The memory must be readable and cannot be both writable and
+/// executable.
+///
+/// # Returns
+///
+/// The page aligned user address of the memory.
+fn sys_map_anonymous(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [length, protection, ..] = *arguments;
+
+    let access = user_page_access(protection)?;
+
+    if length == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    Ok(process::map_anonymous_current(length, access)?)
+}
+
+/// `open(path, path_length, flags)`: opens a file or directory by absolute
+/// path. With `OPEN_CREATE` a missing file is created, and with
+/// `OPEN_TRUNCATE` the file is emptied.
+///
+/// # Returns
+///
+/// The new file descriptor, the lowest that was free.
+fn sys_open(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [user_address, length, flags, ..] = *arguments;
+
+    if length > MAX_PATH_LENGTH || flags & !(OPEN_CREATE | OPEN_TRUNCATE) != 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let mut buffer = [0u8; MAX_PATH_LENGTH];
+    let path = &mut buffer[..length];
+    user::copy_from_user(user_address, path)?;
+
+    let path = core::str::from_utf8(path).map_err(|_| SyscallError::InvalidArgument)?;
+
+    if !path.starts_with('/') {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let mut node = match fs::open(path) {
+        Err(FsError::NotFound) if flags & OPEN_CREATE != 0 => fs::create(path, NodeKind::File)?,
+        result => result?,
+    };
+
+    if flags & OPEN_TRUNCATE != 0 {
+        node.truncate(0)?;
+    }
+
+    let file = OpenFile::open(node)?;
+
+    process::insert_current_handle(Handle::File(file)).map_err(|_| SyscallError::TooManyOpenFiles)
+}
+
+/// `close(fd)`: closes a file descriptor. The file stays open while other
+/// descriptors refer to it.
+fn sys_close(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    process::remove_current_handle(arguments[0]).ok_or(SyscallError::BadFileDescriptor)?;
+
+    Ok(0)
+}
+
+/// `seek(fd, offset, origin)`: moves the offset of a file descriptor, and of
+/// every descriptor that shares it.
+///
+/// # Returns
+///
+/// The new offset from the start of the file.
+fn sys_seek(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [handle_number, offset, origin, ..] = *arguments;
+
+    let handle = process::current_handle(handle_number).ok_or(SyscallError::BadFileDescriptor)?;
+
+    let position = match origin {
+        SEEK_SET => SeekFrom::Start(offset),
+        SEEK_CURRENT => SeekFrom::Current(offset as isize),
+        SEEK_END => SeekFrom::End(offset as isize),
+        _ => return Err(SyscallError::InvalidArgument),
+    };
+
+    match handle {
+        Handle::Console
+        | Handle::PipeReader(_)
+        | Handle::PipeWriter(_)
+        | Handle::SharedMemory(_) => Err(SyscallError::NotSeekable),
+        Handle::File(file) => Ok(file.seek(position)?),
+    }
+}
+
+/// `dup(fd)`: opens another file descriptor on the same object.
+///
+/// # Returns
+///
+/// The new file descriptor, the lowest that was free.
+fn sys_dup(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let handle = process::current_handle(arguments[0]).ok_or(SyscallError::BadFileDescriptor)?;
+
+    process::insert_current_handle(handle).map_err(|_| SyscallError::TooManyOpenFiles)
+}
+
+/// `pipe(fds)`: creates a pipe and opens its read end and its write end at
+/// the two lowest free file descriptors, which are stored as two dwords at
+/// `fds`.
+fn sys_pipe(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let user_address = arguments[0];
+
+    let (reader, writer) = pipe::create()?;
+
+    let read_number = process::insert_current_handle(Handle::PipeReader(reader))
+        .map_err(|_| SyscallError::TooManyOpenFiles)?;
+
+    let write_number = match process::insert_current_handle(Handle::PipeWriter(writer)) {
+        Ok(write_number) => write_number,
+        Err(_) => {
+            process::remove_current_handle(read_number);
+            return Err(SyscallError::TooManyOpenFiles);
+        }
+    };
+
+    let mut numbers = [0u8; 2 * size_of::<usize>()];
+    numbers[..size_of::<usize>()].copy_from_slice(&read_number.to_ne_bytes());
+    numbers[size_of::<usize>()..].copy_from_slice(&write_number.to_ne_bytes());
+
+    if let Err(error) = user::copy_to_user(user_address, &numbers) {
+        process::remove_current_handle(read_number);
+        process::remove_current_handle(write_number);
+
+        return Err(error);
+    }
+
+    Ok(0)
+}
+
+/// `shm_create(name, name_length, length)`: creates a zeroed shared memory
+/// object, named unless `name_length` is zero, and opens a file descriptor on
+/// it.
+///
+/// # Returns
+///
+/// The new file descriptor, the lowest that was free.
+fn sys_shm_create(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [user_address, name_length, length, ..] = *arguments;
+
+    let mut buffer = [0u8; MAX_SHARED_MEMORY_NAME_LENGTH];
+    let name = match name_length {
+        0 => None,
+        _ => Some(copy_shared_memory_name(
+            user_address,
+            name_length,
+            &mut buffer,
+        )?),
+    };
+
+    let shared_memory = SharedMemory::create(name, length)?;
+
+    process::insert_current_handle(Handle::SharedMemory(shared_memory))
+        .map_err(|_| SyscallError::TooManyOpenFiles)
+}
+
+/// `shm_open(name, name_length)`: opens a file descriptor on the shared
+/// memory object with a name.
+///
+/// # Returns
+///
+/// The new file descriptor, the lowest that was free.
+fn sys_shm_open(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [user_address, name_length, ..] = *arguments;
+
+    let mut buffer = [0u8; MAX_SHARED_MEMORY_NAME_LENGTH];
+    let name = copy_shared_memory_name(user_address, name_length, &mut buffer)?;
+
+    let shared_memory = SharedMemory::open(name)?;
+
+    process::insert_current_handle(Handle::SharedMemory(shared_memory))
+        .map_err(|_| SyscallError::TooManyOpenFiles)
+}
+
+/// `shm_map(fd, protection)`: maps the whole shared memory object of a file
+/// descriptor into the calling process. The mapping must be readable and
+/// cannot be both writable and executable, and is independent of the
+/// protection of any other mapping of the object.
+///
+/// # Returns
+///
+/// The page aligned user address of the mapping.
+fn sys_shm_map(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [handle_number, protection, ..] = *arguments;
+
+    let access = user_page_access(protection)?;
+
+    let Some(Handle::SharedMemory(shared_memory)) = process::current_handle(handle_number) else {
+        return Err(SyscallError::BadFileDescriptor);
+    };
+
+    Ok(process::map_shared_memory_current(&shared_memory, access)?)
+}
+
+/// `futex_wait(address, expected)`: blocks the calling process until
+/// `futex_wake` is called on the 32-bit word at `address`, unless the word no
+/// longer holds `expected`.
+fn sys_futex_wait(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [user_address, expected, ..] = *arguments;
+
+    let key = futex_key(user_address)?;
+
+    futex::wait(key, || {
+        let mut word = [0u8; size_of::<u32>()];
+
+        user::copy_from_user(user_address, &mut word).is_ok()
+            && u32::from_ne_bytes(word) == expected as u32
+    })?;
+
+    Ok(0)
+}
+
+/// `futex_wake(address, count)`: wakes processes blocked in `futex_wait` on
+/// the 32-bit word at `address`.
+///
+/// # Returns
+///
+/// The number of processes woken, at most `count`.
+fn sys_futex_wake(
+    _trap_frame: &mut TrapFrame,
+    arguments: &[usize; MAX_SYSCALL_ARGUMENTS],
+) -> Result<usize, SyscallError> {
+    let [user_address, count, ..] = *arguments;
+
+    let key = futex_key(user_address)?;
+
+    Ok(futex::wake(key, count))
+}
+
+/// Finds the key of a futex word of the calling process. The word's page is
+/// made writable first, so that it is not shared copy-on-write and its frame
+/// stays the same while the process waits.
+fn futex_key(user_address: usize) -> Result<FutexKey, SyscallError> {
+    if !user_address.is_multiple_of(align_of::<u32>()) {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    user::prepare_user_access(user_address, size_of::<u32>(), FaultAccess::Write)?;
+
+    process::futex_key_current(user_address).ok_or(SyscallError::BadAddress)
+}
+
+/// Decodes the `PROTECTION_*` bits of a mapping. The memory must be readable
+/// and cannot be both writable and executable.
+fn user_page_access(protection: usize) -> Result<UserPageAccess, SyscallError> {
+    const READ_WRITE: usize = PROTECTION_READ | PROTECTION_WRITE;
+    const READ_EXECUTE: usize = PROTECTION_READ | PROTECTION_EXECUTE;
+
+    match protection {
+        PROTECTION_READ => Ok(UserPageAccess::Read),
+        READ_WRITE => Ok(UserPageAccess::ReadWrite),
+        READ_EXECUTE => Ok(UserPageAccess::ReadExecute),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+/// Copies a shared memory object name from user memory.
+///
+/// # Arguments
+///
+/// * `user_address` - The user address of the name.
+/// * `length` - The length of the name in bytes.
+/// * `buffer` - Receives the name.
+///
+/// # Returns
+///
+/// The name, within `buffer`.
+fn copy_shared_memory_name(
+    user_address: usize,
+    length: usize,
+    buffer: &mut [u8; MAX_SHARED_MEMORY_NAME_LENGTH],
+) -> Result<&str, SyscallError> {
+    if length == 0 || length > MAX_SHARED_MEMORY_NAME_LENGTH {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let name = &mut buffer[..length];
+    user::copy_from_user(user_address, name)?;
+
+    core::str::from_utf8(name).map_err(|_| SyscallError::InvalidArgument)
+}
@@ -0,0 +1,133 @@
+//! CPU time accounting for harts and tasks.
+//!
+//! Every switch charges the time since the previous switch on the hart to the
+//! task switched away from, and to the hart's busy or idle time depending on
+//! whether that task was the hart's idle task. Interrupts count toward the
+//! task they interrupted. Times are kept in `time` CSR ticks and the time
+//! since the last switch is added when they are read, so a hart that idles
+//! without switching still shows its idle time growing.
+//!
+//! Times are read while other harts update them, so a report is a close
+//! estimate rather than an exact snapshot.
+
+use super::Task;
+use crate::{
+    hart::MAX_HART_COUNT,
+    percpu::{self, PerHart},
+    time::Instant,
+};
+use core::{sync::atomic::Ordering, time::Duration};
+
+/// How a hart spent its time since it started scheduling tasks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HartCpuUsage {
+    /// The time spent in tasks other than the hart's idle task.
+    pub busy_time: Duration,
+
+    /// The time spent in the hart's idle task.
+    pub idle_time: Duration,
+}
+
+impl HartCpuUsage {
+    /// Returns the share of the time the hart was busy, in percent, or zero
+    /// if no time has been accounted yet.
+    pub fn busy_percent(&self) -> u32 {
+        let total_time = self.busy_time + self.idle_time;
+
+        if total_time.is_zero() {
+            return 0;
+        }
+
+        (self.busy_time.as_nanos() * 100 / total_time.as_nanos()) as u32
+    }
+}
+
+/// Returns how a hart spent its time.
+///
+/// # Arguments
+///
+/// * `hart_id` - The ID of the hart.
+///
+/// # Returns
+///
+/// The usage, or `None` if the hart has not started scheduling tasks.
+pub fn hart_cpu_usage(hart_id: usize) -> Option<HartCpuUsage> {
+    let per_hart = percpu::get(hart_id)?;
+    let last_switch_time = per_hart.last_switch_time.load(Ordering::Relaxed);
+
+    if last_switch_time == 0 {
+        return None;
+    }
+
+    let mut busy_time = per_hart.busy_time.load(Ordering::Relaxed);
+    let mut idle_time = per_hart.idle_time.load(Ordering::Relaxed);
+
+    let running_time = Instant::now().ticks().saturating_sub(last_switch_time);
+
+    match current_task_of(per_hart) {
+        Some(task) if task.is_idle() => idle_time += running_time,
+        Some(_) => busy_time += running_time,
+        None => {}
+    }
+
+    Some(HartCpuUsage {
+        busy_time: ticks_to_duration(busy_time),
+        idle_time: ticks_to_duration(idle_time),
+    })
+}
+
+/// Starts accounting time on the calling hart, from now on to its idle task.
+pub(super) fn start_hart(per_hart: &PerHart) {
+    per_hart
+        .last_switch_time
+        .store(Instant::now().ticks(), Ordering::Relaxed);
+}
+
+/// Charges the time since the last switch on the calling hart to the task
+/// the hart is switching away from. Interrupts must be disabled.
+///
+/// # Arguments
+///
+/// * `per_hart` - The block of the calling hart.
+/// * `current` - The task the hart is switching away from.
+pub(super) fn charge_switch(per_hart: &PerHart, current: &Task) {
+    let now = Instant::now().ticks();
+    let elapsed = now.saturating_sub(per_hart.last_switch_time.swap(now, Ordering::Relaxed));
+
+    current.cpu_time.fetch_add(elapsed, Ordering::Relaxed);
+
+    if current.is_idle() {
+        per_hart.idle_time.fetch_add(elapsed, Ordering::Relaxed);
+    } else {
+        per_hart.busy_time.fetch_add(elapsed, Ordering::Relaxed);
+    }
+}
+
+/// Returns the CPU time a task has used, including the time since it was
+/// last switched to if it is running.
+pub(super) fn task_cpu_time(task: &Task) -> Duration {
+    let mut cpu_time = task.cpu_time.load(Ordering::Relaxed);
+    let now = Instant::now().ticks();
+
+    for per_hart in (0..MAX_HART_COUNT).filter_map(percpu::get) {
+        if current_task_of(per_hart).is_some_and(|running| core::ptr::eq(running, task)) {
+            cpu_time += now.saturating_sub(per_hart.last_switch_time.load(Ordering::Relaxed));
+        }
+    }
+
+    ticks_to_duration(cpu_time)
+}
+
+/// Returns the task running on a hart.
+fn current_task_of(per_hart: &PerHart) -> Option<&'static Task> {
+    let task = per_hart.current_task.load(Ordering::Acquire) as *const Task;
+
+    // The pointer is either null or was stored from an element of `TASKS`
+    // by the owning hart.
+    unsafe { task.as_ref() }
+}
+
+/// Converts a number of `time` CSR ticks to a duration.
+fn ticks_to_duration(ticks: u64) -> Duration {
+    Instant::from_ticks(ticks) - Instant::ZERO
+}
@@ -0,0 +1,93 @@
+use core::arch::global_asm;
+
+/// The registers a task keeps across a call to `switch_context`.
+///
+/// Only the callee-saved registers need to be kept, since the switch looks
+/// like an ordinary function call to the code that makes it. tp is not part of
+/// the context because it belongs to the hart, not the task.
+///
+/// The layout of this structure is shared with `_kernel_switch_context`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Context {
+    /// The address the switch returns to.
+    pub ra: usize,
+
+    /// The stack pointer.
+    pub sp: usize,
+
+    /// s0 through s11.
+    pub s: [usize; 12],
+}
+
+impl Context {
+    /// Creates an empty context, which is filled in when a task is switched
+    /// away from.
+    pub const fn new() -> Self {
+        Self {
+            ra: 0,
+            sp: 0,
+            s: [0; 12],
+        }
+    }
+}
+
+unsafe extern "C" {
+    fn _kernel_switch_context(old: *mut Context, new: *const Context);
+}
+
+/// Saves the calling task's registers in `old` and continues from the
+/// registers in `new`. Returns when something switches back to `old`.
+///
+/// # Safety
+///
+/// `new` must hold a context saved by an earlier switch or set up to start a
+/// task, and its stack must not be in use by any other hart.
+pub unsafe fn switch_context(old: *mut Context, new: *const Context) {
+    unsafe { _kernel_switch_context(old, new) }
+}
+
+// _kernel_switch_context:
+// - a0 = the Context to save the current registers in.
+// - a1 = the Context to load registers from.
+global_asm!(
+    "
+    .global _kernel_switch_context
+
+    .section .text.kernel_switch_context
+
+    .balign 4
+    _kernel_switch_context:
+        sd ra, 0*8(a0)
+        sd sp, 1*8(a0)
+        sd s0, 2*8(a0)
+        sd s1, 3*8(a0)
+        sd s2, 4*8(a0)
+        sd s3, 5*8(a0)
+        sd s4, 6*8(a0)
+        sd s5, 7*8(a0)
+        sd s6, 8*8(a0)
+        sd s7, 9*8(a0)
+        sd s8, 10*8(a0)
+        sd s9, 11*8(a0)
+        sd s10, 12*8(a0)
+        sd s11, 13*8(a0)
+
+        ld ra, 0*8(a1)
+        ld sp, 1*8(a1)
+        ld s0, 2*8(a1)
+        ld s1, 3*8(a1)
+        ld s2, 4*8(a1)
+        ld s3, 5*8(a1)
+        ld s4, 6*8(a1)
+        ld s5, 7*8(a1)
+        ld s6, 8*8(a1)
+        ld s7, 9*8(a1)
+        ld s8, 10*8(a1)
+        ld s9, 11*8(a1)
+        ld s10, 12*8(a1)
+        ld s11, 13*8(a1)
+
+        ret
+    "
+);
@@ -0,0 +1,621 @@
+//! Kernel threads and the context switch between them.
+//!
+//! Every hart runs an idle task, which is the code that entered the kernel on
+//! that hart, plus any kernel threads started with `spawn_kernel_thread`.
+//! Scheduling is cooperative: a task runs until it calls `yield_now`, blocks
+//! in `sleep` or on a `WaitQueue`, or exits. Ready threads wait in a single
+//! run queue shared by all harts, so a thread may continue on a different
+//! hart after it yields. A hart runs its idle task when the run queue is
+//! empty.
+//!
+//! Each task runs in an address space. Kernel threads use the kernel's own,
+//! while a thread running a user process sets the process's address space
+//! with `set_current_address_space`. A switch installs the next task's
+//! address space when it differs from the current one.
+//!
+//! Every switch charges the time since the previous one to the task switched
+//! away from and to its hart, which `hart_cpu_usage` and `for_each_task`
+//! report.
+//!
+//! The kernel does not receive the physical memory allocator from the boot
+//! stage yet, so thread stacks come from a fixed pool in the kernel image.
+//! Each has a guard page below it, and a switch records the next task's stack
+//! limit for the trap entry to catch overflows with.
+
+mod accounting;
+mod context;
+mod sleep;
+mod wait_queue;
+
+pub use accounting::hart_cpu_usage;
+pub use sleep::{handle_timer_tick, sleep};
+pub use wait_queue::WaitQueue;
+
+use crate::{
+    hart::MAX_HART_COUNT,
+    memory, percpu, rcu,
+    stack_guard::{GuardedStack, StackUsage},
+    trace_event,
+};
+use context::{Context, switch_context};
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+use kernel_lib::sync::{BoundedQueue, disable_interrupts, restore_interrupts};
+
+/// The most kernel threads that can exist at the same time.
+pub const MAX_KERNEL_THREADS: usize = 16;
+
+/// The size in bytes of the stack given to each kernel thread.
+const KERNEL_THREAD_STACK_SIZE: usize = 16 * 1024;
+
+/// The number of task slots. The first `MAX_HART_COUNT` slots hold the idle
+/// tasks, indexed by hart ID, and the rest hold kernel threads.
+pub const MAX_TASK_COUNT: usize = MAX_HART_COUNT + MAX_KERNEL_THREADS;
+
+/// The capacity of the run queue, which must be a power of two that can hold
+/// every kernel thread.
+const RUN_QUEUE_CAPACITY: usize = MAX_KERNEL_THREADS.next_power_of_two();
+
+/// The life cycle of a task slot.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// The slot holds no task.
+    Free = 0,
+
+    /// The task is waiting to run.
+    Ready = 1,
+
+    /// The task is running on a hart.
+    Running = 2,
+
+    /// The task has exited but may still be running on its stack.
+    Exited = 3,
+
+    /// The task is waiting to be woken by a timer or a `WaitQueue`.
+    Blocked = 4,
+}
+
+impl TaskState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Ready,
+            2 => Self::Running,
+            3 => Self::Exited,
+            4 => Self::Blocked,
+            _ => Self::Free,
+        }
+    }
+}
+
+/// Reasons a kernel thread could not be started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// All `MAX_KERNEL_THREADS` threads exist already.
+    TooManyThreads,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyThreads => write!(
+                formatter,
+                "all {} kernel threads are in use",
+                MAX_KERNEL_THREADS
+            ),
+        }
+    }
+}
+
+/// A thread of control with its own saved registers.
+struct Task {
+    id: usize,
+    state: AtomicU8,
+
+    /// Set while a hart runs on the task's stack, including the part of a
+    /// switch away from the task before its registers are saved.
+    is_on_cpu: AtomicBool,
+
+    /// Written only while the slot is claimed and not yet queued.
+    name: UnsafeCell<&'static str>,
+
+    /// The satp value of the task's address space, or zero for the kernel's
+    /// own address space.
+    satp: AtomicUsize,
+
+    /// The lowest address of the task's stack, just above its guard page, or
+    /// zero if it is unknown.
+    stack_limit: AtomicUsize,
+
+    /// The `time` CSR ticks the task has run for, up to the last switch away
+    /// from it.
+    cpu_time: AtomicU64,
+
+    /// Written only by the hart switching away from the task.
+    context: UnsafeCell<Context>,
+}
+
+// The fields in `UnsafeCell`s are only written by the single hart that owns
+// the task at that moment, as described on each field.
+unsafe impl Sync for Task {}
+
+impl Task {
+    const fn new(id: usize) -> Self {
+        Self {
+            id,
+            state: AtomicU8::new(TaskState::Free as u8),
+            is_on_cpu: AtomicBool::new(false),
+            name: UnsafeCell::new(""),
+            satp: AtomicUsize::new(0),
+            stack_limit: AtomicUsize::new(0),
+            cpu_time: AtomicU64::new(0),
+            context: UnsafeCell::new(Context::new()),
+        }
+    }
+
+    fn state(&self) -> TaskState {
+        TaskState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    fn set_state(&self, state: TaskState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    fn is_idle(&self) -> bool {
+        self.id < MAX_HART_COUNT
+    }
+
+    fn bit(&self) -> u64 {
+        1 << self.id
+    }
+
+    /// Returns the satp value of the task's address space.
+    fn resolved_satp(&self) -> usize {
+        match self.satp.load(Ordering::Relaxed) {
+            0 => memory::kernel_satp(),
+            satp => satp,
+        }
+    }
+}
+
+static TASKS: [Task; MAX_TASK_COUNT] = {
+    let mut tasks = [const { Task::new(0) }; MAX_TASK_COUNT];
+
+    let mut id = 0;
+    while id < MAX_TASK_COUNT {
+        tasks[id] = Task::new(id);
+        id += 1;
+    }
+
+    tasks
+};
+
+static THREAD_STACKS: [GuardedStack<KERNEL_THREAD_STACK_SIZE>; MAX_KERNEL_THREADS] =
+    [const { GuardedStack::new() }; MAX_KERNEL_THREADS];
+
+// Wait queues and the timer wheel track tasks in 64 bit masks.
+const _: () = assert!(MAX_TASK_COUNT <= 64);
+
+/// The IDs of the threads waiting to run.
+static RUN_QUEUE: BoundedQueue<usize, RUN_QUEUE_CAPACITY> = BoundedQueue::new();
+
+/// Turns the code running on the calling hart into the hart's idle task. Must
+/// be called once on every hart before it calls `yield_now`.
+///
+/// # Arguments
+///
+/// * `hart_id` - The ID of the calling hart.
+pub fn initialize_hart(hart_id: usize) {
+    let idle_task = &TASKS[hart_id];
+
+    unsafe {
+        *idle_task.name.get() = "idle";
+    }
+
+    idle_task.set_state(TaskState::Running);
+    idle_task.is_on_cpu.store(true, Ordering::Relaxed);
+
+    let per_hart = percpu::current();
+
+    per_hart
+        .current_task
+        .store(idle_task as *const Task as *mut (), Ordering::Release);
+
+    accounting::start_hart(per_hart);
+}
+
+/// Starts a kernel thread. The thread first runs on whichever hart next
+/// yields.
+///
+/// # Arguments
+///
+/// * `name` - A name for the thread, shown by the monitor.
+/// * `entry` - The function the thread runs. The thread exits when it
+///   returns.
+/// * `argument` - The value passed to `entry`.
+///
+/// # Returns
+///
+/// The ID of the new thread.
+pub fn spawn_kernel_thread(
+    name: &'static str,
+    entry: fn(usize),
+    argument: usize,
+) -> Result<usize, SpawnError> {
+    unsafe extern "C" {
+        fn _kernel_thread_entry();
+    }
+
+    let task = TASKS[MAX_HART_COUNT..]
+        .iter()
+        .find(|task| {
+            task.state
+                .compare_exchange(
+                    TaskState::Free as u8,
+                    TaskState::Ready as u8,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        })
+        .ok_or(SpawnError::TooManyThreads)?;
+
+    let stack = &THREAD_STACKS[task.id - MAX_HART_COUNT];
+    stack.protect();
+    stack.paint();
+
+    // The thread starts in `_kernel_thread_entry`, which finds its entry point
+    // and argument in s1 and s2. A zero frame pointer ends backtraces there.
+    let mut context = Context::new();
+    context.ra = _kernel_thread_entry as *const () as usize;
+    context.sp = stack.top();
+    context.s[1] = entry as usize;
+    context.s[2] = argument;
+
+    // The slot was claimed above and is not in the run queue yet.
+    unsafe {
+        *task.name.get() = name;
+        *task.context.get() = context;
+    }
+
+    task.satp.store(0, Ordering::Relaxed);
+    task.stack_limit.store(stack.bottom(), Ordering::Relaxed);
+    task.cpu_time.store(0, Ordering::Relaxed);
+
+    RUN_QUEUE
+        .push(task.id)
+        .expect("The run queue holds every kernel thread.");
+
+    Ok(task.id)
+}
+
+/// Lets the next ready task run on the calling hart. Returns when the calling
+/// task is scheduled again, possibly on another hart.
+pub fn yield_now() {
+    schedule();
+}
+
+/// Ends the calling kernel thread.
+///
+/// # Panics
+///
+/// If called by an idle task.
+pub fn exit_current() -> ! {
+    let task = current_task().expect("The calling hart has no task.");
+
+    if task.is_idle() {
+        panic!("The idle task of a hart cannot exit.");
+    }
+
+    task.set_state(TaskState::Exited);
+    schedule();
+
+    unreachable!("An exited task was scheduled again.");
+}
+
+/// Sets the address space of the calling task and installs it on the calling
+/// hart. The task runs in it from then on, on whichever hart it is switched
+/// to.
+///
+/// # Arguments
+///
+/// * `satp` - The satp value of the address space.
+///
+/// # Safety
+///
+/// The address space must map the kernel like the kernel's own address space
+/// does and must stay alive until the task sets another one or exits.
+///
+/// # Panics
+///
+/// If the calling hart has no task.
+pub unsafe fn set_current_address_space(satp: usize) {
+    let task = current_task().expect("The calling hart has no task.");
+
+    task.satp.store(satp, Ordering::Relaxed);
+
+    unsafe {
+        memory::write_satp(task.resolved_satp());
+    }
+}
+
+/// Records the lowest address of the calling task's stack, for a task whose
+/// stack the scheduler did not set up, such as an idle task.
+///
+/// # Arguments
+///
+/// * `stack_limit` - The lowest address of the stack, just above its guard
+///   page.
+///
+/// # Panics
+///
+/// If the calling hart has no task.
+pub fn set_current_stack_limit(stack_limit: usize) {
+    let task = current_task().expect("The calling hart has no task.");
+
+    task.stack_limit.store(stack_limit, Ordering::Relaxed);
+    percpu::current()
+        .stack_limit
+        .store(stack_limit, Ordering::Relaxed);
+}
+
+/// Returns the lowest address of the calling task's stack, just above its
+/// guard page, or zero if it is unknown.
+pub fn current_stack_limit() -> usize {
+    current_task().map_or(0, |task| task.stack_limit.load(Ordering::Relaxed))
+}
+
+/// Returns the ID of the task running on the calling hart, or `None` if the
+/// hart has not called `initialize_hart`.
+pub fn current_task_id() -> Option<usize> {
+    current_task().map(|task| task.id)
+}
+
+/// Calls a function for every task that exists.
+///
+/// # Arguments
+///
+/// * `callback` - Called with the ID, name, state, and CPU time used of each
+///   task.
+pub fn for_each_task(mut callback: impl FnMut(usize, &'static str, TaskState, Duration)) {
+    for task in &TASKS {
+        let state = task.state();
+
+        if state != TaskState::Free {
+            // A name is written before the task becomes visible to anyone
+            // but its creator, so at worst it is read as it is replaced.
+            callback(
+                task.id,
+                unsafe { *task.name.get() },
+                state,
+                accounting::task_cpu_time(task),
+            );
+        }
+    }
+}
+
+/// Calls a function for every kernel thread with how much of its stack it
+/// has used.
+///
+/// # Arguments
+///
+/// * `callback` - Called with the ID, name, and stack usage of each thread.
+pub fn for_each_thread_stack(mut callback: impl FnMut(usize, &'static str, StackUsage)) {
+    for (task, stack) in TASKS[MAX_HART_COUNT..].iter().zip(&THREAD_STACKS) {
+        if task.state() != TaskState::Free {
+            // As in `for_each_task`, the name is at worst read as it is
+            // replaced.
+            callback(task.id, unsafe { *task.name.get() }, stack.usage());
+        }
+    }
+}
+
+/// Returns the task running on the calling hart.
+fn current_task() -> Option<&'static Task> {
+    let task = percpu::current().current_task.load(Ordering::Acquire) as *const Task;
+
+    // The pointer is either null or was stored from an element of `TASKS`.
+    unsafe { task.as_ref() }
+}
+
+/// Switches from the calling task to the next task in the run queue, or to
+/// the hart's idle task if the queue is empty.
+fn schedule() {
+    let were_interrupts_enabled = disable_interrupts();
+    switch_to_next();
+    restore_interrupts(were_interrupts_enabled);
+}
+
+/// Returns the calling thread marked as blocked, for it to register with
+/// whatever will wake it before it calls `switch_to_next`.
+///
+/// Interrupts must stay disabled from this call until the switch, so a wake
+/// from an interrupt on the same hart cannot spin waiting for the switch.
+///
+/// # Panics
+///
+/// If called by an idle task, which must never block.
+fn prepare_to_block() -> &'static Task {
+    let task = current_task().expect("The calling hart has no task.");
+
+    if task.is_idle() {
+        panic!("The idle task of a hart cannot block.");
+    }
+
+    task.set_state(TaskState::Blocked);
+    task
+}
+
+/// Makes a blocked task ready to run. Does nothing if the task is not
+/// blocked.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the task to wake.
+fn wake(id: usize) {
+    let task = &TASKS[id];
+
+    if task
+        .state
+        .compare_exchange(
+            TaskState::Blocked as u8,
+            TaskState::Ready as u8,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        )
+        .is_err()
+    {
+        return;
+    }
+
+    // The task may have marked itself blocked without having switched away
+    // yet. It must not be resumed until its registers are saved.
+    while task.is_on_cpu.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+
+    RUN_QUEUE
+        .push(id)
+        .expect("The run queue holds every kernel thread.");
+}
+
+/// Switches from the calling task to the next task in the run queue, or to
+/// the hart's idle task if the queue is empty. A running task is queued again,
+/// while a blocked or exited one is not. Interrupts must be disabled.
+fn switch_to_next() {
+    rcu::report_quiescent_state();
+
+    let per_hart = percpu::current();
+    let Some(current) = current_task() else {
+        return;
+    };
+
+    let next = match RUN_QUEUE.pop() {
+        Some(id) => &TASKS[id],
+        None if current.is_idle() => return,
+        None => &TASKS[per_hart.hart_id],
+    };
+
+    let should_requeue_current = current.state() == TaskState::Running && !current.is_idle();
+
+    if current.state() == TaskState::Running {
+        current.set_state(TaskState::Ready);
+    }
+
+    next.is_on_cpu.store(true, Ordering::Relaxed);
+    next.set_state(TaskState::Running);
+
+    accounting::charge_switch(per_hart, current);
+
+    trace_event!(
+        "sched",
+        "switch from task {} to task {}",
+        current.id,
+        next.id
+    );
+
+    per_hart
+        .previous_task
+        .store(current as *const Task as *mut (), Ordering::Relaxed);
+    per_hart
+        .should_requeue_previous_task
+        .store(should_requeue_current, Ordering::Relaxed);
+    per_hart
+        .current_task
+        .store(next as *const Task as *mut (), Ordering::Release);
+
+    // Kernel addresses are mapped the same way in every address space, so
+    // the switch keeps running across the change. Tasks in the same address
+    // space share its satp value, ASID included, so switching between them
+    // leaves satp alone.
+    let next_satp = next.resolved_satp();
+
+    if next_satp != current.resolved_satp() {
+        unsafe {
+            memory::write_satp(next_satp);
+        }
+    }
+
+    per_hart
+        .stack_limit
+        .store(next.stack_limit.load(Ordering::Relaxed), Ordering::Relaxed);
+
+    // The current task is only queued again by `finish_switch`, once its
+    // registers are saved, so no other hart can resume it before then.
+    unsafe {
+        switch_context(current.context.get(), next.context.get());
+    }
+
+    finish_switch();
+}
+
+/// Completes a switch on the task that was switched to, by queueing the task
+/// that was switched away from or freeing its slot if it exited.
+fn finish_switch() {
+    let per_hart = percpu::current();
+    let previous = per_hart
+        .previous_task
+        .swap(core::ptr::null_mut(), Ordering::Relaxed) as *const Task;
+
+    // The pointer was stored from an element of `TASKS` by `switch_to_next`.
+    let Some(previous) = (unsafe { previous.as_ref() }) else {
+        return;
+    };
+
+    let has_exited = previous.state() == TaskState::Exited;
+    previous.is_on_cpu.store(false, Ordering::Release);
+
+    if has_exited {
+        previous.set_state(TaskState::Free);
+    } else if per_hart
+        .should_requeue_previous_task
+        .load(Ordering::Relaxed)
+    {
+        RUN_QUEUE
+            .push(previous.id)
+            .expect("The run queue holds every kernel thread.");
+    }
+}
+
+/// The Rust side of a new kernel thread. Called by `_kernel_thread_entry` on
+/// the thread's own stack.
+///
+/// # Arguments
+///
+/// * `entry` - The `fn(usize)` given to `spawn_kernel_thread`.
+/// * `argument` - The value to pass to `entry`.
+#[unsafe(no_mangle)]
+extern "C" fn kernel_thread_start(entry: usize, argument: usize) -> ! {
+    finish_switch();
+
+    // `schedule` disabled interrupts before switching to the new thread.
+    restore_interrupts(true);
+
+    // The value was stored from a `fn(usize)` by `spawn_kernel_thread`.
+    let entry: fn(usize) = unsafe { core::mem::transmute(entry) };
+    entry(argument);
+
+    exit_current();
+}
+
+core::arch::global_asm!(
+    "
+    .global _kernel_thread_entry
+
+    .extern kernel_thread_start
+
+    .section .text.kernel_thread_entry
+
+    .balign 4
+    _kernel_thread_entry:
+        // - s1 = entry function
+        // - s2 = argument
+        mv a0, s1
+        mv a1, s2
+        call kernel_thread_start
+    "
+);
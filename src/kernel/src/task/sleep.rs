@@ -0,0 +1,57 @@
+//! Sleeping kernel threads, woken by a timer wheel that advances on every
+//! timer interrupt.
+
+use super::{MAX_TASK_COUNT, prepare_to_block, switch_to_next, wake};
+use crate::{time::Instant, timer};
+use core::time::Duration;
+use kernel_lib::{
+    sync::{SpinLockIrqSave, disable_interrupts, restore_interrupts},
+    timer_wheel::{MAX_TIMERS, TimerWheel},
+};
+
+/// The number of slots of the timer wheel. One turn covers this many timer
+/// ticks.
+const TIMER_WHEEL_SLOT_COUNT: usize = 64;
+
+// Task IDs double as timer IDs.
+const _: () = assert!(MAX_TASK_COUNT <= MAX_TIMERS);
+
+/// The wake up time of every sleeping task, keyed by task ID.
+static TIMER_WHEEL: SpinLockIrqSave<TimerWheel<TIMER_WHEEL_SLOT_COUNT>> =
+    SpinLockIrqSave::new(TimerWheel::new());
+
+/// Blocks the calling kernel thread for at least a duration.
+///
+/// The thread is woken by the first timer tick at or after the wake up time,
+/// so the delay is rounded up to whole ticks.
+///
+/// # Arguments
+///
+/// * `duration` - How long to sleep.
+///
+/// # Panics
+///
+/// If called by an idle task.
+pub fn sleep(duration: Duration) {
+    let wake_time = Instant::now().saturating_add(duration);
+
+    let were_interrupts_enabled = disable_interrupts();
+
+    let task = prepare_to_block();
+    // The wake up time falls inside its tick, so the tick after it is the
+    // first one guaranteed to be at or after it.
+    TIMER_WHEEL
+        .lock()
+        .insert(task.id, timer::tick_number(wake_time) + 1);
+
+    switch_to_next();
+    restore_interrupts(were_interrupts_enabled);
+}
+
+/// Wakes every thread whose sleep has ended. Called on every timer
+/// interrupt.
+pub fn handle_timer_tick() {
+    let current_tick = timer::tick_number(Instant::now());
+
+    TIMER_WHEEL.lock().advance(current_tick, wake);
+}
@@ -0,0 +1,129 @@
+use super::{Task, TaskState, prepare_to_block, switch_to_next, wake};
+use core::sync::atomic::{AtomicU64, Ordering};
+use kernel_lib::sync::{disable_interrupts, restore_interrupts};
+
+/// A set of kernel threads waiting for a condition to become true.
+///
+/// Threads block in `wait_until` instead of spinning. Code that makes the
+/// condition true calls `wake_all` or `wake_one` afterwards. A thread checks
+/// its condition again after registering as a waiter, so a wake between the
+/// first check and blocking is never lost.
+///
+/// # Examples
+///
+/// ```ignore
+/// static DATA_READY: AtomicBool = AtomicBool::new(false);
+/// static DATA_READY_QUEUE: WaitQueue = WaitQueue::new();
+///
+/// // Consumer thread.
+/// DATA_READY_QUEUE.wait_until(|| DATA_READY.load(Ordering::Acquire));
+///
+/// // Producer, which may be an interrupt handler.
+/// DATA_READY.store(true, Ordering::Release);
+/// DATA_READY_QUEUE.wake_all();
+/// ```
+pub struct WaitQueue {
+    /// A bit for every waiting task, indexed by task ID.
+    waiters: AtomicU64,
+}
+
+impl WaitQueue {
+    /// Creates a queue with no waiters.
+    pub const fn new() -> Self {
+        Self {
+            waiters: AtomicU64::new(0),
+        }
+    }
+
+    /// Blocks the calling kernel thread until a condition is true.
+    ///
+    /// # Arguments
+    ///
+    /// * `condition` - Checked before blocking and again after every wake.
+    ///   It must not block.
+    ///
+    /// # Panics
+    ///
+    /// If the condition is false and the caller is an idle task.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        while !condition() {
+            let were_interrupts_enabled = disable_interrupts();
+
+            let task = prepare_to_block();
+            self.waiters.fetch_or(task.bit(), Ordering::AcqRel);
+
+            // The condition may have become true before this task was
+            // registered, in which case nobody will wake it.
+            if condition() && self.cancel_wait(task) {
+                restore_interrupts(were_interrupts_enabled);
+                return;
+            }
+
+            switch_to_next();
+            restore_interrupts(were_interrupts_enabled);
+        }
+    }
+
+    /// Wakes every waiting thread.
+    pub fn wake_all(&self) {
+        let mut waiters = self.waiters.swap(0, Ordering::AcqRel);
+
+        while waiters != 0 {
+            wake(waiters.trailing_zeros() as usize);
+            waiters &= waiters - 1;
+        }
+    }
+
+    /// Wakes the waiting thread with the lowest task ID.
+    ///
+    /// # Returns
+    ///
+    /// True if a thread was woken.
+    #[allow(dead_code)]
+    pub fn wake_one(&self) -> bool {
+        let taken = self
+            .waiters
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |waiters| {
+                (waiters != 0).then(|| waiters & (waiters - 1))
+            });
+
+        match taken {
+            Ok(waiters) => {
+                wake(waiters.trailing_zeros() as usize);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Returns true if any thread is waiting.
+    #[allow(dead_code)]
+    pub fn has_waiters(&self) -> bool {
+        self.waiters.load(Ordering::Acquire) != 0
+    }
+
+    /// Takes a task that is about to block back out of the queue.
+    ///
+    /// # Returns
+    ///
+    /// True if the task keeps running. False if a wake already claimed it, in
+    /// which case it must still switch away so the waker can queue it.
+    fn cancel_wait(&self, task: &Task) -> bool {
+        self.waiters.fetch_and(!task.bit(), Ordering::AcqRel);
+
+        task.state
+            .compare_exchange(
+                TaskState::Blocked as u8,
+                TaskState::Running as u8,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
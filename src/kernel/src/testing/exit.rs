@@ -0,0 +1,85 @@
+//! Exiting QEMU with a status code.
+//!
+//! QEMU's virt machine has a SiFive test device, a single register that stops
+//! QEMU with an exit code when written. It is found through its "sifive,test0"
+//! compatible string and reached through the direct physical memory mapping.
+//! Without it the machine is shut down through the SBI system reset
+//! extension, which QEMU turns into an exit code of zero whatever the reset
+//! reason, so a failure is only visible on the console.
+
+use crate::{debug_println, memory::physical_to_virtual};
+use common_lib::dtb::Dtb;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use sbi_lib::system_reset::{self, RESET_REASON_NO_REASON, RESET_REASON_SYSTEM_FAILURE};
+
+/// The compatible string of the SiFive test device.
+const TEST_DEVICE_COMPATIBLE: &str = "sifive,test0";
+
+/// The value that makes the test device exit QEMU with status zero.
+const FINISHER_PASS: u32 = 0x5555;
+
+/// The value that makes the test device exit QEMU with the status in the
+/// upper 16 bits of the written value.
+const FINISHER_FAIL: u32 = 0x3333;
+
+/// The virtual address of the test device's register, or 0 if the machine
+/// has none or `initialize` has not run.
+static TEST_DEVICE_ADDRESS: AtomicUsize = AtomicUsize::new(0);
+
+/// The outcome QEMU reports when it exits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Exit with status zero.
+    Success,
+
+    /// Exit with a nonzero status. A status of zero is reported as one.
+    Failure(u16),
+}
+
+/// Finds the test device.
+///
+/// # Arguments
+///
+/// * `dtb` - The Device Tree Blob.
+pub(super) fn initialize(dtb: &Dtb) {
+    let physical_address = dtb
+        .compatible_nodes(TEST_DEVICE_COMPATIBLE)
+        .filter(|node| node.is_enabled())
+        .find_map(|node| node.translate_address(node.first_reg()?.0));
+
+    match physical_address.and_then(|address| physical_to_virtual(address as usize)) {
+        Some(address) => TEST_DEVICE_ADDRESS.store(address, Ordering::Release),
+        None => debug_println!("No SiFive test device, exiting through SBI."),
+    }
+}
+
+/// Stops QEMU with a status. The hart halts if neither the test device nor
+/// SBI stops the machine.
+///
+/// # Arguments
+///
+/// * `status` - The status QEMU exits with.
+pub fn exit_qemu(status: ExitStatus) -> ! {
+    let address = TEST_DEVICE_ADDRESS.load(Ordering::Acquire);
+
+    if address != 0 {
+        let value = match status {
+            ExitStatus::Success => FINISHER_PASS,
+            ExitStatus::Failure(code) => FINISHER_FAIL | (u32::from(code.max(1)) << 16),
+        };
+
+        unsafe {
+            (address as *mut u32).write_volatile(value);
+        }
+    }
+
+    let reason = match status {
+        ExitStatus::Success => RESET_REASON_NO_REASON,
+        ExitStatus::Failure(_) => RESET_REASON_SYSTEM_FAILURE,
+    };
+
+    let error = system_reset::shutdown(reason);
+    debug_println!("SBI shutdown failed: {}.", error);
+
+    crate::halt();
+}
@@ -0,0 +1,142 @@
+//! The in-kernel test runner, built with the "kernel-tests" feature.
+//!
+//! Host tests cannot reach code that needs the hardware, such as page table
+//! switches, traps, or device registers, so such code is tested inside the
+//! kernel instead. `kernel_test!` defines a test and registers it in the
+//! `.kernel_tests` section, which the linker script gathers between
+//! `_kernel_tests_start` and `_kernel_tests_end`. Once the kernel has
+//! initialized its subsystems, `run_tests` calls every test in turn, reports
+//! each one on the console, and exits QEMU with a status a script can check.
+//!
+//! A test fails by panicking. The panic handler reports the running test
+//! through `fail_running_test` and exits QEMU with a failure status, so the
+//! tests after it do not run.
+//!
+//! Tests live in a module at the bottom of the file they test, the same way
+//! host tests do:
+//!
+//! ```ignore
+//! #[cfg(feature = "kernel-tests")]
+//! mod kernel_tests {
+//!     use crate::kernel_test;
+//!
+//!     kernel_test!(
+//!         fn test_addition() {
+//!             assert_eq!(1 + 1, 2);
+//!         }
+//!     );
+//! }
+//! ```
+
+mod exit;
+
+pub use exit::{ExitStatus, exit_qemu};
+
+use crate::debug_println;
+use common_lib::dtb::Dtb;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A test registered with `kernel_test!`.
+pub struct KernelTest {
+    /// The path of the test function, such as
+    /// `kernel::time::kernel_tests::test_now_is_monotonic`.
+    pub name: &'static str,
+
+    /// The test function, which panics if the test fails.
+    pub function: fn(),
+}
+
+/// Marks that no test is running in `RUNNING_TEST`.
+const NO_TEST: usize = usize::MAX;
+
+/// The index of the running test in `tests()`, or `NO_TEST`.
+static RUNNING_TEST: AtomicUsize = AtomicUsize::new(NO_TEST);
+
+/// Defines a kernel test and registers it with the runner.
+///
+/// The test is an ordinary function that takes no arguments and panics if it
+/// fails. It runs on the boot hart with interrupts enabled and every
+/// subsystem initialized, before the first process is started.
+#[macro_export]
+macro_rules! kernel_test {
+    ($(#[$attribute:meta])* fn $name:ident() $body:block) => {
+        $(#[$attribute])*
+        fn $name() $body
+
+        const _: () = {
+            #[used]
+            #[unsafe(link_section = ".kernel_tests")]
+            static TEST: $crate::testing::KernelTest = $crate::testing::KernelTest {
+                name: concat!(module_path!(), "::", stringify!($name)),
+                function: $name,
+            };
+        };
+    };
+}
+
+/// Returns every registered test, in link order.
+fn tests() -> &'static [KernelTest] {
+    unsafe extern "C" {
+        static _kernel_tests_start: u8;
+        static _kernel_tests_end: u8;
+    }
+
+    let start = (&raw const _kernel_tests_start).cast::<KernelTest>();
+    let end = (&raw const _kernel_tests_end).cast::<KernelTest>();
+
+    // The linker script places both symbols around the section that holds
+    // only the `KernelTest` statics of `kernel_test!`, which are aligned to
+    // and sized as a `KernelTest`.
+    unsafe {
+        core::slice::from_raw_parts(
+            start,
+            (end as usize - start as usize) / size_of::<KernelTest>(),
+        )
+    }
+}
+
+/// Runs every registered test and exits QEMU with the outcome.
+///
+/// A failing test panics, and the panic handler exits QEMU instead, so this
+/// only reaches the end when every test passed.
+///
+/// # Arguments
+///
+/// * `dtb` - The Device Tree Blob, to find the device that exits QEMU.
+pub fn run_tests(dtb: &Dtb) -> ! {
+    exit::initialize(dtb);
+
+    let tests = tests();
+
+    debug_println!("\nRunning {} kernel tests.", tests.len());
+
+    for (index, test) in tests.iter().enumerate() {
+        debug_println!("test {} ...", test.name);
+
+        RUNNING_TEST.store(index, Ordering::Release);
+        (test.function)();
+        RUNNING_TEST.store(NO_TEST, Ordering::Release);
+
+        debug_println!("test {} ... ok", test.name);
+    }
+
+    debug_println!("\nKernel test result: ok. {} passed.", tests.len());
+
+    exit_qemu(ExitStatus::Success);
+}
+
+/// Reports the running test as failed and exits QEMU with a failure status.
+/// Called by the panic handler after it printed the panic.
+///
+/// A panic outside of a test also fails the run, since the tests cannot be
+/// trusted once the kernel panicked.
+pub fn fail_running_test() -> ! {
+    match tests().get(RUNNING_TEST.load(Ordering::Acquire)) {
+        Some(test) => debug_println!("test {} ... FAILED", test.name),
+        None => debug_println!("The kernel panicked outside of a kernel test."),
+    }
+
+    debug_println!("\nKernel test result: FAILED.");
+
+    exit_qemu(ExitStatus::Failure(1));
+}
@@ -0,0 +1,57 @@
+//! The boot profiler.
+//!
+//! The boot stage hands over the times it reached its milestones, and the
+//! kernel adds its own with `record`. `print_summary` converts them once
+//! `time::initialize` knows the timebase frequency.
+
+use super::Instant;
+use crate::debug_println;
+use common_lib::boot_info::{BootMilestone, BootTimeline};
+use kernel_lib::sync::SpinLock;
+
+/// The times of every milestone reached so far.
+static TIMELINE: SpinLock<BootTimeline> = SpinLock::new(BootTimeline::new());
+
+/// Starts the kernel's timeline from the boot stage's.
+///
+/// # Arguments
+///
+/// * `boot_timeline` - The milestones the boot stage recorded.
+/// * `kernel_entered` - The time the kernel started running.
+pub fn initialize(boot_timeline: &BootTimeline, kernel_entered: Instant) {
+    let mut timeline = TIMELINE.lock();
+    *timeline = *boot_timeline;
+    timeline.record(BootMilestone::KernelEntered, kernel_entered.ticks());
+}
+
+/// Records that a milestone was reached now.
+///
+/// # Arguments
+///
+/// * `milestone` - The milestone.
+pub fn record(milestone: BootMilestone) {
+    TIMELINE.lock().record(milestone, Instant::now().ticks());
+}
+
+/// Prints the time of every milestone reached since the `time` CSR read
+/// zero, and since the milestone before it.
+pub fn print_summary() {
+    let timeline = *TIMELINE.lock();
+
+    debug_println!("Boot timing:");
+
+    let mut previous = Instant::ZERO;
+
+    for (milestone, ticks) in timeline.milestones() {
+        let instant = Instant::from_ticks(ticks);
+
+        debug_println!(
+            "  {}: {} us (+{} us)",
+            milestone,
+            instant.duration_since(Instant::ZERO).as_micros(),
+            instant.duration_since(previous).as_micros()
+        );
+
+        previous = instant;
+    }
+}
@@ -0,0 +1,224 @@
+//! The monotonic clock.
+//!
+//! Time is read from the `time` CSR, which counts at the "timebase-frequency"
+//! the device tree gives for the harts and is synchronized across them.
+//! `Instant` wraps a reading of the counter and converts to and from
+//! `Duration`, so code outside this module and the timer hardware never deals
+//! with raw tick counts.
+
+pub mod boot_timeline;
+
+use crate::debug_println;
+use common_lib::dtb::{self, Dtb};
+use core::{
+    ops::{Add, AddAssign, Sub, SubAssign},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use kernel_lib::time::{duration_to_ticks, ticks_to_duration};
+
+/// The frequency of the `time` CSR in hertz assumed until `initialize` finds
+/// one in the device tree. This is the "timebase-frequency" QEMU's virt
+/// machine reports.
+const DEFAULT_TIMEBASE_FREQUENCY: u64 = 10_000_000;
+
+/// The frequency of the `time` CSR in hertz.
+static TIMEBASE_FREQUENCY: AtomicU64 = AtomicU64::new(DEFAULT_TIMEBASE_FREQUENCY);
+
+/// A point in time, measured by the `time` CSR.
+///
+/// Instants are only meaningful relative to each other. The counter started
+/// at an unspecified point before the kernel, usually when the machine reset.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    ticks: u64,
+}
+
+impl Instant {
+    /// The instant at which the `time` CSR read zero.
+    pub const ZERO: Self = Self { ticks: 0 };
+
+    /// Returns the current time.
+    pub fn now() -> Self {
+        Self { ticks: read_time() }
+    }
+
+    /// Creates an instant from a raw reading of the `time` CSR.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticks` - The value of the `time` CSR.
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Self { ticks }
+    }
+
+    /// Returns the raw value of the `time` CSR at this instant, for
+    /// programming timer hardware.
+    pub const fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Returns the time that passed from an earlier instant to this one.
+    ///
+    /// # Arguments
+    ///
+    /// * `earlier` - The earlier instant.
+    ///
+    /// # Returns
+    ///
+    /// The elapsed time, or zero if `earlier` is later than this instant.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        ticks_to_duration(
+            self.ticks.saturating_sub(earlier.ticks),
+            timebase_frequency(),
+        )
+    }
+
+    /// Returns the time that passed since this instant.
+    #[allow(dead_code)]
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    /// Returns the instant a duration after this one, or `None` if the
+    /// counter cannot represent it.
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let ticks = duration_to_ticks(duration, timebase_frequency());
+
+        self.ticks.checked_add(ticks).map(Instant::from_ticks)
+    }
+
+    /// Returns the instant a duration before this one, or `None` if it is
+    /// before `Instant::ZERO`.
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let ticks = duration_to_ticks(duration, timebase_frequency());
+
+        self.ticks.checked_sub(ticks).map(Instant::from_ticks)
+    }
+
+    /// Returns the instant a duration after this one, clamped to the last
+    /// instant the counter can represent.
+    pub fn saturating_add(&self, duration: Duration) -> Instant {
+        self.checked_add(duration)
+            .unwrap_or(Instant::from_ticks(u64::MAX))
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// # Panics
+    ///
+    /// If the result overflows the counter.
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration)
+            .expect("Overflow when adding a duration to an instant.")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    /// # Panics
+    ///
+    /// If the result is before `Instant::ZERO`.
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration)
+            .expect("Overflow when subtracting a duration from an instant.")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    /// Returns the time between two instants, saturating at zero like
+    /// `duration_since`.
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Reads the frequency of the `time` CSR from the device tree.
+///
+/// The boot hart's "timebase-frequency" is used, which a cpu node inherits
+/// from /cpus when it has none of its own. The default frequency is kept if
+/// the device tree gives none.
+///
+/// # Arguments
+///
+/// * `dtb` - The Device Tree Blob describing the harts.
+/// * `boot_hart_id` - The hart ID of the boot hart.
+pub fn initialize(dtb: &Dtb, boot_hart_id: usize) {
+    let frequency = dtb::cpus(dtb)
+        .find(|cpu| cpu.hart_id == boot_hart_id as u64)
+        .and_then(|cpu| cpu.timebase_frequency)
+        .filter(|&frequency| frequency != 0);
+
+    match frequency {
+        Some(frequency) => TIMEBASE_FREQUENCY.store(frequency, Ordering::Relaxed),
+        None => debug_println!(
+            "No timebase-frequency for hart {}, assuming {} Hz.",
+            boot_hart_id,
+            DEFAULT_TIMEBASE_FREQUENCY
+        ),
+    }
+}
+
+/// Returns the frequency of the `time` CSR in hertz.
+pub fn timebase_frequency() -> u64 {
+    TIMEBASE_FREQUENCY.load(Ordering::Relaxed)
+}
+
+/// Returns the time since the `time` CSR read zero, which is close to the
+/// time since the machine reset.
+pub fn uptime() -> Duration {
+    Instant::now().duration_since(Instant::ZERO)
+}
+
+/// Reads the current value of the `time` CSR.
+fn read_time() -> u64 {
+    let time: u64;
+    unsafe {
+        core::arch::asm!("rdtime {}", out(reg) time, options(nomem, nostack));
+    }
+
+    time
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    kernel_test!(
+        fn test_now_is_monotonic() {
+            let earlier = Instant::now();
+            let later = Instant::now();
+
+            assert!(later >= earlier);
+            assert!(uptime() >= earlier.duration_since(Instant::ZERO));
+        }
+    );
+
+    kernel_test!(
+        fn test_instant_duration_arithmetic() {
+            let instant = Instant::now();
+            let duration = Duration::from_secs(1);
+
+            assert_eq!((instant + duration) - instant, duration);
+            assert_eq!((instant + duration).duration_since(instant), duration);
+        }
+    );
+}
@@ -0,0 +1,141 @@
+//! The periodic timer tick.
+//!
+//! Each hart that calls `initialize` programs a timer event and enables
+//! supervisor timer interrupts. Timer events are written to the stimecmp CSR
+//! when every hart has the Sstc extension and go through the SBI TIME
+//! extension otherwise. Every interrupt
+//! schedules the next event one tick interval later, counts the tick globally
+//! and on the hart, wakes sleeping threads, and forwards it to the registered
+//! tick callback.
+
+use crate::{
+    debug_println, hart, per_hart, task,
+    time::{self, Instant},
+};
+use common_lib::dtb::IsaFeatures;
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use kernel_lib::sync::interrupts::SSTATUS_SIE;
+use sbi_lib::timer::set_timer;
+
+/// The number of timer ticks per second.
+pub const TICKS_PER_SECOND: u64 = 100;
+
+/// The supervisor timer interrupt enable bit in the sie CSR.
+const SIE_STIE: usize = 1 << 5;
+
+/// The CSR number of stimecmp from the Sstc extension. The number is used
+/// instead of the name so the assembler does not need Sstc enabled.
+const CSR_STIMECMP: usize = 0x14D;
+
+/// The function called on every timer tick.
+///
+/// The callback receives the total number of ticks counted so far, including
+/// the current one.
+pub type TickCallback = fn(tick_count: u64);
+
+/// The number of timer interrupts handled across all harts.
+static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// The registered `TickCallback`, or null if there is none.
+static TICK_CALLBACK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Returns the number of whole tick intervals from `Instant::ZERO` to an
+/// instant.
+///
+/// Timer interrupts are scheduled relative to the previous one rather than
+/// on interval boundaries, so the tick number seen by consecutive interrupts
+/// may skip a value.
+///
+/// # Arguments
+///
+/// * `instant` - The instant.
+pub fn tick_number(instant: Instant) -> u64 {
+    instant.ticks() / tick_interval()
+}
+
+/// Registers the function called on every timer tick, replacing any callback
+/// registered before.
+///
+/// # Arguments
+///
+/// * `callback` - The function to call from the timer interrupt handler. It
+///   runs in trap context with interrupts disabled and must not block.
+pub fn set_tick_callback(callback: TickCallback) {
+    TICK_CALLBACK.store(callback as *mut (), Ordering::Release);
+}
+
+/// Schedules the first timer event and enables timer interrupts on the
+/// calling hart.
+///
+/// The trap vector must already be installed on this hart, and
+/// `time::initialize` must have run.
+pub fn initialize() {
+    schedule_next_tick();
+
+    unsafe {
+        core::arch::asm!(
+            "csrs sie, {}",
+            "csrs sstatus, {}",
+            in(reg) SIE_STIE,
+            in(reg) SSTATUS_SIE,
+            options(nomem, nostack)
+        );
+    }
+
+    debug_println!(
+        "Timer interrupts enabled at {} ticks per second.",
+        TICKS_PER_SECOND
+    );
+}
+
+/// Handles a supervisor timer interrupt. Called from the trap handler.
+///
+/// Programming the next timer event clears the pending interrupt, so this must
+/// run before interrupts are enabled again.
+pub fn handle_timer_interrupt() {
+    schedule_next_tick();
+
+    let tick_count = TICK_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+    per_hart!(timer_tick_count).fetch_add(1, Ordering::Relaxed);
+
+    task::handle_timer_tick();
+
+    let callback = TICK_CALLBACK.load(Ordering::Acquire);
+    if !callback.is_null() {
+        // The pointer was created from a `TickCallback` in `set_tick_callback`.
+        let callback: TickCallback = unsafe { core::mem::transmute(callback) };
+
+        callback(tick_count);
+    }
+}
+
+/// Returns the number of `time` CSR ticks between two timer interrupts.
+fn tick_interval() -> u64 {
+    (time::timebase_frequency() / TICKS_PER_SECOND).max(1)
+}
+
+/// Programs the next timer event one tick interval from now on the calling
+/// hart.
+fn schedule_next_tick() {
+    let next_tick_time = Instant::now().ticks() + tick_interval();
+
+    // With Sstc the timer compare register can be written directly, which
+    // avoids a trap into the SBI implementation on every tick. The SBI
+    // implementation enables supervisor access to it when the hart has Sstc.
+    if hart::isa_features().contains(IsaFeatures::SSTC) {
+        unsafe {
+            core::arch::asm!(
+                "csrw {csr}, {value}",
+                csr = const CSR_STIMECMP,
+                value = in(reg) next_tick_time,
+                options(nomem, nostack)
+            );
+        }
+
+        return;
+    }
+
+    if let Err(error) = set_timer(next_tick_time) {
+        panic!("SBI set_timer failed: {}.", error);
+    }
+}
@@ -0,0 +1,119 @@
+//! TLB maintenance across harts.
+//!
+//! Every hart caches address translations in its own TLB, so changing or
+//! removing a mapping on one hart leaves stale translations on the others. A
+//! shootdown flushes the range locally with `sfence.vma` and then asks every
+//! other online hart to do the same through the SBI RFENCE extension.
+
+use crate::hart::{MAX_HART_COUNT, current_hart_id, is_hart_online};
+use boot_lib::memory::mmu::{paging_mode, set_tlb_flush_handler};
+use common_lib::memory::VirtualPageNumber;
+use core::ops::Range;
+use sbi_lib::rfence::{remote_sfence_vma, remote_sfence_vma_asid};
+
+/// The size of a base page in bytes.
+const PAGE_SIZE: usize = 4096;
+
+/// Ranges with more pages than this are flushed locally with a single full
+/// `sfence.vma` instead of one instruction per page.
+const LOCAL_FLUSH_PAGE_LIMIT: usize = 64;
+
+/// Routes every mapping change made through the shared mmu code to
+/// `shootdown`.
+pub fn initialize() {
+    set_tlb_flush_handler(flush_changed_pages);
+}
+
+/// Invalidates the translations for a range of virtual addresses on every
+/// online hart.
+///
+/// # Arguments
+///
+/// * `range` - The virtual addresses whose mappings changed. The range is
+///   widened to whole pages.
+/// * `asid` - The address space to flush, or `None` to flush the range in
+///   every address space.
+pub fn shootdown(range: Range<usize>, asid: Option<usize>) {
+    if range.is_empty() {
+        return;
+    }
+
+    let start_address = range.start & !(PAGE_SIZE - 1);
+    let size = range.end - start_address;
+
+    flush_local(start_address, size, asid);
+
+    // Every online hart other than this one needs the same flush. Hart IDs are
+    // below MAX_HART_COUNT, so the mask always starts at hart 0.
+    let current_hart_id = current_hart_id();
+    let hart_mask = (0..MAX_HART_COUNT)
+        .filter(|&hart_id| hart_id != current_hart_id && is_hart_online(hart_id))
+        .fold(0, |mask, hart_id| mask | (1 << hart_id));
+
+    if hart_mask == 0 {
+        return;
+    }
+
+    let result = match asid {
+        Some(asid) => remote_sfence_vma_asid(hart_mask, 0, start_address, size, asid),
+        None => remote_sfence_vma(hart_mask, 0, start_address, size),
+    };
+
+    // Carrying on with stale translations on other harts would corrupt memory
+    // in ways that are very hard to trace back here.
+    if let Err(error) = result {
+        panic!("Remote TLB shootdown failed: {}.", error);
+    }
+}
+
+/// Invalidates the translations for a range of virtual addresses on the
+/// calling hart only, for mappings that no other hart can have cached.
+///
+/// # Arguments
+///
+/// * `start_address` - The page aligned first virtual address to flush.
+/// * `size` - The number of bytes to flush.
+/// * `asid` - The address space to flush, or `None` to flush the range in
+///   every address space.
+pub fn flush_local(start_address: usize, size: usize, asid: Option<usize>) {
+    let page_count = size.div_ceil(PAGE_SIZE);
+
+    // The ASID operand of sfence.vma is x0 to flush every address space.
+    let asid = asid.unwrap_or(0);
+
+    if page_count > LOCAL_FLUSH_PAGE_LIMIT {
+        unsafe {
+            if asid == 0 {
+                core::arch::asm!("sfence.vma", options(nostack));
+            } else {
+                core::arch::asm!("sfence.vma zero, {}", in(reg) asid, options(nostack));
+            }
+        }
+
+        return;
+    }
+
+    for page_index in 0..page_count {
+        let address = start_address + page_index * PAGE_SIZE;
+
+        unsafe {
+            if asid == 0 {
+                core::arch::asm!("sfence.vma {}, zero", in(reg) address, options(nostack));
+            } else {
+                core::arch::asm!("sfence.vma {}, {}", in(reg) address, in(reg) asid, options(nostack));
+            }
+        }
+    }
+}
+
+/// `TlbFlushHandler` registered with the shared mmu code.
+fn flush_changed_pages(start_vpn: VirtualPageNumber, page_count: usize) {
+    // A VPN built from page table indices only holds the translated bits of
+    // the address, so sign extend them to get the canonical address.
+    let canonical_address = paging_mode().sign_extend(start_vpn.to_virtual_address());
+
+    shootdown(
+        canonical_address..canonical_address + page_count * PAGE_SIZE,
+        None,
+    );
+}
@@ -0,0 +1,219 @@
+//! Event tracing into per-hart ring buffers.
+//!
+//! `trace_event!` records an event without formatting it: an entry holds the
+//! time, the tracepoint that recorded it, and up to `MAX_TRACE_ARGUMENTS`
+//! integer arguments. Each tracepoint is a static made by the macro, holding
+//! its subsystem and a function that formats the arguments with the format
+//! string, so the text is only produced when the buffers are dumped. Every
+//! hart records into a buffer of its own, which the hart ID of an entry is
+//! implied by, and the oldest entries are overwritten once it is full.
+//!
+//! Events show what the scheduler, interrupt handling, and the frame pool are
+//! doing without printing to the console, which would slow them down and
+//! change their timing. The monitor's `trace` command dumps the buffers.
+
+use crate::{debug_println, hart::MAX_HART_COUNT, percpu, time::Instant};
+use core::fmt::{self, Write};
+use kernel_lib::{ring_buffer::RingBuffer, sync::SpinLockIrqSave};
+
+/// The most arguments a trace event records.
+pub const MAX_TRACE_ARGUMENTS: usize = 4;
+
+/// The number of events each hart's buffer holds.
+const TRACE_BUFFER_CAPACITY: usize = 64;
+
+/// A place in the code that records events, made by `trace_event!`.
+pub struct Tracepoint {
+    /// The part of the kernel the events come from, such as "sched".
+    pub subsystem: &'static str,
+
+    /// Writes the message of an event from its arguments.
+    pub format: fn(&mut dyn Write, &[u64; MAX_TRACE_ARGUMENTS]) -> fmt::Result,
+}
+
+/// An event as it is recorded.
+#[derive(Copy, Clone)]
+struct TraceEvent {
+    /// The `time` CSR reading when the event was recorded.
+    timestamp: u64,
+
+    tracepoint: &'static Tracepoint,
+    arguments: [u64; MAX_TRACE_ARGUMENTS],
+}
+
+/// The events of one hart.
+#[derive(Copy, Clone)]
+struct TraceBuffer {
+    events: RingBuffer<TraceEvent, TRACE_BUFFER_CAPACITY>,
+
+    /// The number of events overwritten since the buffer was last cleared.
+    overwritten_count: u64,
+}
+
+impl TraceBuffer {
+    const fn new() -> Self {
+        Self {
+            events: RingBuffer::new(),
+            overwritten_count: 0,
+        }
+    }
+}
+
+/// The buffer of every hart, indexed by hart ID. Only the owning hart writes
+/// to a buffer, so its lock is only contended while it is dumped.
+static TRACE_BUFFERS: [SpinLockIrqSave<TraceBuffer>; MAX_HART_COUNT] =
+    [const { SpinLockIrqSave::new(TraceBuffer::new()) }; MAX_HART_COUNT];
+
+/// Records an event of a tracepoint in the calling hart's buffer. Called by
+/// `trace_event!`.
+///
+/// # Arguments
+///
+/// * `tracepoint` - The tracepoint recording the event.
+/// * `arguments` - The values to format the message with. Values past
+///   `MAX_TRACE_ARGUMENTS` are dropped.
+pub fn record(tracepoint: &'static Tracepoint, arguments: &[u64]) {
+    // Events from before the hart has its per-hart block are dropped.
+    let Some(per_hart) = percpu::try_current() else {
+        return;
+    };
+
+    let buffer = &TRACE_BUFFERS[per_hart.hart_id];
+
+    let mut event = TraceEvent {
+        timestamp: Instant::now().ticks(),
+        tracepoint,
+        arguments: [0; MAX_TRACE_ARGUMENTS],
+    };
+
+    let argument_count = arguments.len().min(MAX_TRACE_ARGUMENTS);
+    event.arguments[..argument_count].copy_from_slice(&arguments[..argument_count]);
+
+    let mut buffer = buffer.lock();
+
+    if buffer.events.is_full() {
+        buffer.events.pop_front();
+        buffer.overwritten_count += 1;
+    }
+
+    let _ = buffer.events.push_back(event);
+}
+
+/// Prints the events of every hart to the console, oldest first.
+pub fn dump() {
+    for (hart_id, buffer) in TRACE_BUFFERS.iter().enumerate() {
+        // The buffer is copied so the hart can go on recording while it is
+        // printed.
+        let mut buffer = *buffer.lock();
+
+        if buffer.events.is_empty() {
+            continue;
+        }
+
+        debug_println!(
+            "  Hart {}: {} events, {} older ones overwritten",
+            hart_id,
+            buffer.events.len(),
+            buffer.overwritten_count
+        );
+
+        while let Some(event) = buffer.events.pop_front() {
+            let microseconds = (Instant::from_ticks(event.timestamp) - Instant::ZERO).as_micros();
+
+            debug_println!(
+                "    [{:>5}.{:06}] {:<8} {}",
+                microseconds / 1_000_000,
+                microseconds % 1_000_000,
+                event.tracepoint.subsystem,
+                Message(&event)
+            );
+        }
+    }
+}
+
+/// Removes the events of every hart.
+pub fn clear() {
+    for buffer in &TRACE_BUFFERS {
+        *buffer.lock() = TraceBuffer::new();
+    }
+}
+
+/// Formats the message of an event.
+struct Message<'a>(&'a TraceEvent);
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.0.tracepoint.format)(f, &self.0.arguments)
+    }
+}
+
+/// Records an event in the calling hart's trace buffer.
+///
+/// The arguments are cast to `u64` with `as`, so they must be integers,
+/// `bool`s, or `char`s, and at most `MAX_TRACE_ARGUMENTS` of them. The format
+/// string is only applied when the buffers are dumped.
+///
+/// # Examples
+///
+/// ```ignore
+/// trace_event!("irq", "interrupt {} handled in {} ns", irq, nanoseconds);
+/// ```
+#[macro_export]
+macro_rules! trace_event {
+    (@argument $arguments:ident, $argument:expr) => {
+        $arguments.next().unwrap_or(0)
+    };
+    ($subsystem:literal, $format:literal $(, $argument:expr)* $(,)?) => {{
+        const _: () = assert!(
+            <[&str]>::len(&[$(stringify!($argument)),*]) <= $crate::trace::MAX_TRACE_ARGUMENTS,
+            "A trace event takes at most MAX_TRACE_ARGUMENTS arguments."
+        );
+
+        static TRACEPOINT: $crate::trace::Tracepoint = $crate::trace::Tracepoint {
+            subsystem: $subsystem,
+            format: |writer, arguments| {
+                let _arguments = &mut arguments.iter().copied();
+
+                core::fmt::Write::write_fmt(
+                    writer,
+                    format_args!(
+                        $format
+                        $(, $crate::trace_event!(@argument _arguments, $argument))*
+                    ),
+                )
+            },
+        };
+
+        $crate::trace::record(&TRACEPOINT, &[$(($argument) as u64),*]);
+    }};
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+
+    kernel_test!(
+        fn test_trace_event_overwrites_oldest_events() {
+            clear();
+
+            for index in 0..TRACE_BUFFER_CAPACITY + 2 {
+                crate::trace_event!("test", "event {} of {}", index, TRACE_BUFFER_CAPACITY + 2);
+            }
+
+            let buffer = *TRACE_BUFFERS[percpu::current().hart_id].lock();
+            assert_eq!(buffer.events.len(), TRACE_BUFFER_CAPACITY);
+            assert_eq!(buffer.overwritten_count, 2);
+
+            let mut events = buffer.events;
+            let oldest = events.pop_front().unwrap();
+            assert_eq!(
+                oldest.arguments,
+                [2, TRACE_BUFFER_CAPACITY as u64 + 2, 0, 0]
+            );
+            assert_eq!(oldest.tracepoint.subsystem, "test");
+
+            clear();
+        }
+    );
+}
@@ -0,0 +1,200 @@
+use crate::percpu::PerHart;
+use core::{arch::global_asm, mem::offset_of};
+
+// The trap entry point installed in stvec. It pushes a `TrapFrame` onto the
+// kernel stack, calls `kernel_trap_handler` with a pointer to it, and then
+// restores the (possibly modified) state and returns with sret.
+//
+// sscratch is zero while the hart runs kernel code and holds the hart's
+// `PerHart` block while it runs user code. A trap from user mode swaps it
+// with tp to recover the block and switches to the kernel stack recorded in
+// the block. A trap from kernel mode stays on the current stack, unless the
+// trap frame would reach below the stack limit in the block. The stack has
+// then overflowed into its guard page, and the trap continues on the hart's
+// overflow stack with the limit cleared, so the handler can report it.
+//
+// `_kernel_trap_return` is the second half on its own. Jumping to it with sp
+// pointing at a trap frame enters the state described by the frame, which is
+// how the kernel first enters user mode.
+//
+// The trap frame layout is:
+// - 0*8 through 31*8: general purpose registers x0 through x31.
+// - 32*8: sstatus.
+// - 33*8: sepc.
+// - 34*8: scause.
+// - 35*8: stval.
+global_asm!(
+    "
+    .global _kernel_trap_entry
+    .global _kernel_trap_return
+
+    .extern kernel_trap_handler
+
+    .section .text.kernel_trap_entry
+
+    // stvec in direct mode requires the base address to be 4-byte aligned.
+    .balign 4
+    _kernel_trap_entry:
+        csrrw tp, sscratch, tp
+        beqz tp, 1f
+
+        // The trap came from user mode: tp holds the per-hart block and
+        // sscratch the user tp. Reserve the trap frame on the kernel stack
+        // and save the user sp and tp in it.
+        sd sp, {user_stack_pointer}(tp)
+        ld sp, {kernel_stack_top}(tp)
+        addi sp, sp, -288
+
+        sd t0, 5*8(sp)
+        ld t0, {user_stack_pointer}(tp)
+        sd t0, 2*8(sp)
+        csrr t0, sscratch
+        sd t0, 4*8(sp)
+        csrw sscratch, zero
+        j 2f
+
+    1:
+        // The trap came from kernel mode: swap tp back, leaving sscratch
+        // zero, and reserve the trap frame on the current stack.
+        csrrw tp, sscratch, tp
+        addi sp, sp, -288
+
+        // Check the frame against the stack limit, keeping t0 in sscratch
+        // meanwhile. There is no limit before tp holds the per-hart block.
+        csrw sscratch, t0
+        beqz tp, 5f
+        ld t0, {stack_limit}(tp)
+        bgeu sp, t0, 5f
+
+        // The stack overflowed: move the frame to the overflow stack.
+        addi t0, sp, 288
+        ld sp, {overflow_stack_top}(tp)
+        addi sp, sp, -288
+        sd t0, 2*8(sp)
+        sd zero, {stack_limit}(tp)
+
+        csrrw t0, sscratch, zero
+        sd t0, 5*8(sp)
+        sd tp, 4*8(sp)
+        j 2f
+
+    5:
+        csrrw t0, sscratch, zero
+        sd t0, 5*8(sp)
+        addi t0, sp, 288
+        sd t0, 2*8(sp)
+        sd tp, 4*8(sp)
+
+    2:
+        // Save every other general purpose register except x0.
+        sd x1, 1*8(sp)
+        sd x3, 3*8(sp)
+        sd x6, 6*8(sp)
+        sd x7, 7*8(sp)
+        sd x8, 8*8(sp)
+        sd x9, 9*8(sp)
+        sd x10, 10*8(sp)
+        sd x11, 11*8(sp)
+        sd x12, 12*8(sp)
+        sd x13, 13*8(sp)
+        sd x14, 14*8(sp)
+        sd x15, 15*8(sp)
+        sd x16, 16*8(sp)
+        sd x17, 17*8(sp)
+        sd x18, 18*8(sp)
+        sd x19, 19*8(sp)
+        sd x20, 20*8(sp)
+        sd x21, 21*8(sp)
+        sd x22, 22*8(sp)
+        sd x23, 23*8(sp)
+        sd x24, 24*8(sp)
+        sd x25, 25*8(sp)
+        sd x26, 26*8(sp)
+        sd x27, 27*8(sp)
+        sd x28, 28*8(sp)
+        sd x29, 29*8(sp)
+        sd x30, 30*8(sp)
+        sd x31, 31*8(sp)
+        sd zero, 0*8(sp)
+
+        // Save the trap CSRs.
+        csrr t0, sstatus
+        sd t0, 32*8(sp)
+        csrr t0, sepc
+        sd t0, 33*8(sp)
+        csrr t0, scause
+        sd t0, 34*8(sp)
+        csrr t0, stval
+        sd t0, 35*8(sp)
+
+        // Call the Rust trap handler with a pointer to the trap frame.
+        mv a0, sp
+        call kernel_trap_handler
+
+    _kernel_trap_return:
+        // Restore sstatus and sepc, which the handler may have modified.
+        // Interrupts stay disabled until sret, since the saved sstatus has
+        // SIE clear.
+        ld t0, 32*8(sp)
+        csrw sstatus, t0
+        ld t1, 33*8(sp)
+        csrw sepc, t1
+
+        // A return to kernel mode keeps the hart's tp. A return to user mode
+        // leaves the per-hart block in sscratch and records the kernel stack
+        // the next trap from user mode starts on.
+        andi t0, t0, {sstatus_spp}
+        beqz t0, 3f
+
+        sd tp, 4*8(sp)
+        j 4f
+
+    3:
+        addi t0, sp, 288
+        sd t0, {kernel_stack_top}(tp)
+        csrw sscratch, tp
+
+    4:
+        // Restore every general purpose register except x0 and sp.
+        ld x1, 1*8(sp)
+        ld x3, 3*8(sp)
+        ld x4, 4*8(sp)
+        ld x5, 5*8(sp)
+        ld x6, 6*8(sp)
+        ld x7, 7*8(sp)
+        ld x8, 8*8(sp)
+        ld x9, 9*8(sp)
+        ld x10, 10*8(sp)
+        ld x11, 11*8(sp)
+        ld x12, 12*8(sp)
+        ld x13, 13*8(sp)
+        ld x14, 14*8(sp)
+        ld x15, 15*8(sp)
+        ld x16, 16*8(sp)
+        ld x17, 17*8(sp)
+        ld x18, 18*8(sp)
+        ld x19, 19*8(sp)
+        ld x20, 20*8(sp)
+        ld x21, 21*8(sp)
+        ld x22, 22*8(sp)
+        ld x23, 23*8(sp)
+        ld x24, 24*8(sp)
+        ld x25, 25*8(sp)
+        ld x26, 26*8(sp)
+        ld x27, 27*8(sp)
+        ld x28, 28*8(sp)
+        ld x29, 29*8(sp)
+        ld x30, 30*8(sp)
+        ld x31, 31*8(sp)
+
+        // Restore sp last, which also releases a trap frame on the kernel
+        // stack, and return to the interrupted code.
+        ld sp, 2*8(sp)
+        sret
+    ",
+    kernel_stack_top = const offset_of!(PerHart, kernel_stack_top),
+    user_stack_pointer = const offset_of!(PerHart, user_stack_pointer),
+    stack_limit = const offset_of!(PerHart, stack_limit),
+    overflow_stack_top = const offset_of!(PerHart, overflow_stack_top),
+    sstatus_spp = const super::SSTATUS_SPP,
+);
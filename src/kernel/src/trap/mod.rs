@@ -0,0 +1,170 @@
+//! Supervisor trap handling.
+//!
+//! This module installs the kernel trap vector in stvec and dispatches every
+//! trap taken in supervisor mode based on the value of scause. Interrupts are
+//! handled the same way whichever mode they interrupt, system calls go to the
+//! `syscall` module, and other exceptions raised by user code go to the
+//! `user` module. Kernel traps that have no handler
+//! produce a decoded dump of the cause and register state before the kernel
+//! panics, rather than leaving the hart to hang silently. A trap whose kernel
+//! stack overflowed into its guard page is reported by `stack_guard`.
+
+mod entry;
+mod page_fault;
+pub mod trap_cause;
+pub mod trap_frame;
+
+use crate::{
+    backtrace, debug_print, debug_println, ipi, irq, percpu, rcu, softirq, stack_guard,
+    symbols::Symbolized, syscall, timer, user,
+};
+use core::sync::atomic::Ordering;
+use trap_cause::TrapCause;
+use trap_frame::{REGISTER_NAMES, TrapFrame};
+
+/// The sstatus bit that holds the privilege mode the trap was taken from. It
+/// is set for supervisor mode and clear for user mode.
+pub const SSTATUS_SPP: usize = 1 << 8;
+
+/// Installs the kernel trap vector on the current hart.
+///
+/// stvec is configured in direct mode so that every trap, regardless of cause,
+/// enters `_kernel_trap_entry`. sscratch is cleared, which tells the trap
+/// entry that the hart is running kernel code.
+pub fn initialize() {
+    unsafe extern "C" {
+        fn _kernel_trap_entry();
+    }
+
+    // The lower two bits of stvec select the mode. The trap entry is 4-byte
+    // aligned, so its address already encodes direct mode (0).
+    let trap_entry_address = _kernel_trap_entry as *const () as usize;
+
+    unsafe {
+        core::arch::asm!(
+            "csrw stvec, {}",
+            "csrw sscratch, zero",
+            in(reg) trap_entry_address,
+            options(nomem, nostack)
+        );
+    }
+
+    debug_println!("Trap vector installed at {:#x}.", trap_entry_address);
+}
+
+/// The Rust side of the trap entry. Called by `_kernel_trap_entry` with a
+/// pointer to the trap frame it saved on the stack.
+///
+/// # Arguments
+///
+/// * `trap_frame` - The register state of the interrupted code. Changes made
+///   to the registers, sstatus, or sepc are restored when the trap returns.
+#[unsafe(no_mangle)]
+extern "C" fn kernel_trap_handler(trap_frame: &mut TrapFrame) {
+    let cause = TrapCause::from_scause(trap_frame.scause);
+
+    if stack_guard::is_overflow_trap(trap_frame) {
+        stack_guard::report_overflow(trap_frame, cause);
+    }
+
+    if let Some(per_hart) = percpu::try_current() {
+        per_hart.trap_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let is_from_user_mode = trap_frame.sstatus & SSTATUS_SPP == 0;
+
+    // User code cannot be inside a read-side critical section.
+    if is_from_user_mode {
+        rcu::report_quiescent_state();
+    }
+
+    match cause {
+        TrapCause::SupervisorSoftwareInterrupt => ipi::handle_software_interrupt(),
+        TrapCause::SupervisorTimerInterrupt => timer::handle_timer_interrupt(),
+        TrapCause::SupervisorExternalInterrupt => irq::handle_external_interrupt(),
+        TrapCause::EnvironmentCallFromUserMode => syscall::handle_syscall(trap_frame),
+        _ if is_from_user_mode && !cause.is_interrupt() => {
+            user::handle_exception(trap_frame, cause)
+        }
+        TrapCause::Breakpoint => handle_breakpoint(trap_frame),
+        TrapCause::InstructionPageFault | TrapCause::LoadPageFault | TrapCause::StorePageFault => {
+            page_fault::handle_page_fault(trap_frame, cause)
+        }
+        _ => handle_unhandled_trap(trap_frame, cause),
+    }
+
+    if cause.is_interrupt() {
+        softirq::run_pending();
+    }
+}
+
+/// Reports an ebreak instruction and resumes execution after it.
+fn handle_breakpoint(trap_frame: &mut TrapFrame) {
+    debug_println!("Breakpoint at {:#x}.", trap_frame.sepc);
+
+    trap_frame.sepc += instruction_length(trap_frame.sepc);
+}
+
+/// Returns the length in bytes of the instruction at the given address.
+///
+/// Standard instructions have their lowest two bits set to 0b11, while
+/// compressed instructions use any other value and are only 2 bytes long.
+fn instruction_length(instruction_address: usize) -> usize {
+    let low_halfword = unsafe { core::ptr::read_volatile(instruction_address as *const u16) };
+
+    if low_halfword & 0b11 == 0b11 { 4 } else { 2 }
+}
+
+/// Prints a decoded dump of the trap and halts the kernel via a panic.
+fn handle_unhandled_trap(trap_frame: &TrapFrame, cause: TrapCause) -> ! {
+    debug_println!("\n\n===== UNHANDLED TRAP =====");
+    print_trap_frame(trap_frame, cause);
+
+    // The trap entry code does not create a frame, so the interrupted code's
+    // callers are walked from its own s0 and sp.
+    debug_println!("Backtrace:");
+    backtrace::print(trap_frame.registers[8], trap_frame.registers[2]);
+    debug_println!("==========================\n");
+
+    panic!("Unhandled trap: {}.", cause);
+}
+
+/// Prints the trap cause, trap CSRs, and every general purpose register in a
+/// trap frame.
+///
+/// # Arguments
+///
+/// * `trap_frame` - The trap frame to print.
+/// * `cause` - The decoded cause of the trap.
+pub fn print_trap_frame(trap_frame: &TrapFrame, cause: TrapCause) {
+    let kind = if cause.is_interrupt() {
+        "interrupt"
+    } else {
+        "exception"
+    };
+
+    debug_println!(
+        "Cause:   {} ({}, scause: {:#018x})",
+        cause,
+        kind,
+        trap_frame.scause
+    );
+    debug_println!("sepc:    {}", Symbolized(trap_frame.sepc));
+    debug_println!("ra:      {}", Symbolized(trap_frame.registers[1]));
+    debug_println!("stval:   {:#018x}", trap_frame.stval);
+    debug_println!("sstatus: {:#018x}", trap_frame.sstatus);
+    debug_println!();
+
+    // Print the registers four per line, skipping x0 which is always zero.
+    for (index, name) in REGISTER_NAMES.iter().enumerate().skip(1) {
+        debug_print!("{:>4}: {:#018x}", name, trap_frame.registers[index]);
+
+        if index % 4 == 0 {
+            debug_println!();
+        } else {
+            debug_print!("  ");
+        }
+    }
+
+    debug_println!();
+}
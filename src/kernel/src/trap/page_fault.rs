@@ -0,0 +1,166 @@
+use super::{SSTATUS_SPP, print_trap_frame, trap_cause::TrapCause, trap_frame::TrapFrame};
+use crate::{debug_print, debug_println, memory::active_root_page_table_ppn, stack_guard};
+use boot_lib::memory::mmu::{
+    PageTableEntry, WalkStep, page_table_pointer, paging_mode, walk_steps,
+};
+use common_lib::memory::{PhysicalPageNumber, VirtualAddress};
+
+/// Handles instruction, load, and store page faults raised by kernel code.
+///
+/// Every such page fault is fatal. An access to the guard page of the
+/// running stack is reported as a stack overflow. Otherwise, before halting,
+/// this prints the faulting address, the kind of access, and each
+/// step of the page table walk for the faulting address so that mistakes in
+/// the page table setup can be tracked down.
+///
+/// # Arguments
+///
+/// * `trap_frame` - The register state at the time of the fault.
+/// * `cause` - The decoded page fault cause.
+pub fn handle_page_fault(trap_frame: &mut TrapFrame, cause: TrapCause) -> ! {
+    let faulting_address = trap_frame.stval;
+
+    if stack_guard::is_guard_page(faulting_address) {
+        stack_guard::report_overflow(trap_frame, cause);
+    }
+
+    let root_page_table_ppn = active_root_page_table_ppn();
+
+    debug_println!("\n\n===== PAGE FAULT =====");
+    debug_println!(
+        "Faulting address:    {:#018x} ({} access)",
+        faulting_address,
+        access_type(cause)
+    );
+    debug_println!("Instruction address: {:#018x}", trap_frame.sepc);
+    debug_println!(
+        "Privilege mode:      {}",
+        if trap_frame.sstatus & SSTATUS_SPP != 0 {
+            "supervisor"
+        } else {
+            "user"
+        }
+    );
+    debug_println!(
+        "Root page table:     {:#018x} (physical)",
+        root_page_table_ppn.to_physical_address()
+    );
+    debug_println!();
+
+    print_page_table_walk(root_page_table_ppn, faulting_address);
+
+    debug_println!();
+    print_trap_frame(trap_frame, cause);
+    debug_println!("======================\n");
+
+    panic!("Unhandled {} at {:#x}.", cause, faulting_address);
+}
+
+/// Returns a description of the memory access that caused a page fault.
+fn access_type(cause: TrapCause) -> &'static str {
+    match cause {
+        TrapCause::InstructionPageFault => "execute",
+        TrapCause::LoadPageFault => "read",
+        TrapCause::StorePageFault => "write",
+        _ => "unknown",
+    }
+}
+
+/// Walks the page tables for a virtual address with `walk_steps` and prints
+/// every entry visited, stopping at the first invalid entry or at the leaf
+/// entry.
+///
+/// # Arguments
+///
+/// * `root_page_table_ppn` - The physical page number of the root page table.
+/// * `virtual_address` - The virtual address to walk.
+fn print_page_table_walk(root_page_table_ppn: PhysicalPageNumber, virtual_address: usize) {
+    // Every bit above the translated bits must be a copy of the highest
+    // translated bit. The hardware raises a page fault for any other address
+    // without walking the tables.
+    if !paging_mode().is_canonical(virtual_address) {
+        debug_println!(
+            "Address is not a canonical {} virtual address.",
+            paging_mode()
+        );
+        return;
+    }
+
+    debug_println!("Page table walk:");
+
+    let root_page_table = unsafe { &*page_table_pointer(root_page_table_ppn) };
+    let mut page_table_ppn = root_page_table_ppn;
+    let mut last_step: Option<WalkStep> = None;
+
+    let translation = walk_steps(
+        root_page_table,
+        VirtualAddress::new(virtual_address),
+        &mut |step| {
+            debug_print!(
+                "  L{} entry {:>3} in table {:#x}: ",
+                step.level,
+                step.index,
+                page_table_ppn.to_physical_address()
+            );
+
+            let entry = &step.entry;
+
+            if !entry.is_valid() {
+                debug_println!("invalid");
+            } else {
+                let kind = if entry.is_leaf() { "leaf" } else { "table" };
+
+                debug_print!("{} -> {:#x} ", kind, entry.get_ppn().to_physical_address());
+                print_entry_flags(entry);
+                debug_println!();
+            }
+
+            page_table_ppn = entry.get_ppn();
+            last_step = Some(*step);
+        },
+    );
+
+    let Some(step) = last_step else {
+        return;
+    };
+
+    if translation.is_some() {
+        debug_println!(
+            "Translation succeeded at level {}, so the fault is caused by the permission or \
+             accessed/dirty bits of the leaf entry.",
+            step.level
+        );
+    } else if !step.entry.is_valid() {
+        debug_println!("Translation failed at level {}.", step.level);
+    } else if step.entry.is_leaf() {
+        debug_println!(
+            "Translation failed because the level {} leaf entry maps a misaligned superpage.",
+            step.level
+        );
+    } else {
+        debug_println!("Translation failed because the level 0 entry is not a leaf.");
+    }
+}
+
+/// Prints the flags of a page table entry in the form `[VRWXUGAD]`, using `-`
+/// for flags that are not set.
+fn print_entry_flags(entry: &PageTableEntry) {
+    let flags = [
+        (entry.is_valid(), 'V'),
+        (entry.is_readable(), 'R'),
+        (entry.is_writable(), 'W'),
+        (entry.is_executable(), 'X'),
+        (entry.is_user(), 'U'),
+        (entry.is_global(), 'G'),
+        (entry.is_accessed(), 'A'),
+        (entry.is_dirty(), 'D'),
+    ];
+
+    debug_print!("[");
+
+    for (is_set, letter) in flags {
+        debug_print!("{}", if is_set { letter } else { '-' });
+    }
+
+    debug_print!("]");
+}
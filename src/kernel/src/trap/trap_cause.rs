@@ -0,0 +1,112 @@
+use core::fmt;
+
+/// The decoded value of the scause CSR.
+///
+/// The most significant bit of scause distinguishes interrupts from
+/// exceptions, and the remaining bits hold the cause code as defined by the
+/// RISC-V privileged specification.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TrapCause {
+    SupervisorSoftwareInterrupt,
+    SupervisorTimerInterrupt,
+    SupervisorExternalInterrupt,
+    UnknownInterrupt(usize),
+
+    InstructionAddressMisaligned,
+    InstructionAccessFault,
+    IllegalInstruction,
+    Breakpoint,
+    LoadAddressMisaligned,
+    LoadAccessFault,
+    StoreAddressMisaligned,
+    StoreAccessFault,
+    EnvironmentCallFromUserMode,
+    EnvironmentCallFromSupervisorMode,
+    InstructionPageFault,
+    LoadPageFault,
+    StorePageFault,
+    UnknownException(usize),
+}
+
+impl TrapCause {
+    /// The bit in scause that is set when the trap was caused by an interrupt.
+    const INTERRUPT_BIT: usize = 1 << (usize::BITS - 1);
+
+    /// Decodes a raw scause value.
+    ///
+    /// # Arguments
+    ///
+    /// * `scause` - The raw value read from the scause CSR.
+    ///
+    /// # Returns
+    ///
+    /// The decoded trap cause. Cause codes that are reserved or not relevant
+    /// to supervisor mode are returned as `UnknownInterrupt` or
+    /// `UnknownException` with the raw code.
+    pub const fn from_scause(scause: usize) -> Self {
+        let code = scause & !Self::INTERRUPT_BIT;
+
+        if scause & Self::INTERRUPT_BIT != 0 {
+            match code {
+                1 => Self::SupervisorSoftwareInterrupt,
+                5 => Self::SupervisorTimerInterrupt,
+                9 => Self::SupervisorExternalInterrupt,
+                _ => Self::UnknownInterrupt(code),
+            }
+        } else {
+            match code {
+                0 => Self::InstructionAddressMisaligned,
+                1 => Self::InstructionAccessFault,
+                2 => Self::IllegalInstruction,
+                3 => Self::Breakpoint,
+                4 => Self::LoadAddressMisaligned,
+                5 => Self::LoadAccessFault,
+                6 => Self::StoreAddressMisaligned,
+                7 => Self::StoreAccessFault,
+                8 => Self::EnvironmentCallFromUserMode,
+                9 => Self::EnvironmentCallFromSupervisorMode,
+                12 => Self::InstructionPageFault,
+                13 => Self::LoadPageFault,
+                15 => Self::StorePageFault,
+                _ => Self::UnknownException(code),
+            }
+        }
+    }
+
+    /// Returns true if this cause is an asynchronous interrupt rather than a
+    /// synchronous exception.
+    pub const fn is_interrupt(&self) -> bool {
+        matches!(
+            self,
+            Self::SupervisorSoftwareInterrupt
+                | Self::SupervisorTimerInterrupt
+                | Self::SupervisorExternalInterrupt
+                | Self::UnknownInterrupt(_)
+        )
+    }
+}
+
+impl fmt::Display for TrapCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SupervisorSoftwareInterrupt => write!(f, "supervisor software interrupt"),
+            Self::SupervisorTimerInterrupt => write!(f, "supervisor timer interrupt"),
+            Self::SupervisorExternalInterrupt => write!(f, "supervisor external interrupt"),
+            Self::UnknownInterrupt(code) => write!(f, "unknown interrupt {}", code),
+            Self::InstructionAddressMisaligned => write!(f, "instruction address misaligned"),
+            Self::InstructionAccessFault => write!(f, "instruction access fault"),
+            Self::IllegalInstruction => write!(f, "illegal instruction"),
+            Self::Breakpoint => write!(f, "breakpoint"),
+            Self::LoadAddressMisaligned => write!(f, "load address misaligned"),
+            Self::LoadAccessFault => write!(f, "load access fault"),
+            Self::StoreAddressMisaligned => write!(f, "store/AMO address misaligned"),
+            Self::StoreAccessFault => write!(f, "store/AMO access fault"),
+            Self::EnvironmentCallFromUserMode => write!(f, "environment call from U-mode"),
+            Self::EnvironmentCallFromSupervisorMode => write!(f, "environment call from S-mode"),
+            Self::InstructionPageFault => write!(f, "instruction page fault"),
+            Self::LoadPageFault => write!(f, "load page fault"),
+            Self::StorePageFault => write!(f, "store/AMO page fault"),
+            Self::UnknownException(code) => write!(f, "unknown exception {}", code),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+/// The complete register state of a hart at the moment a trap was taken.
+///
+/// The layout of this structure is shared with the assembly trap entry in
+/// `entry.rs`, which saves every general purpose register followed by the trap
+/// related CSRs. Any change to the field order or size must be mirrored in the
+/// assembly.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TrapFrame {
+    /// General purpose registers x0 through x31. The slot for x0 is always
+    /// zero and the slot for x2 (sp) holds the stack pointer from before the
+    /// trap frame was pushed.
+    pub registers: [usize; 32],
+
+    /// The value of the sstatus CSR when the trap was taken.
+    pub sstatus: usize,
+
+    /// The value of the sepc CSR, which is the address of the instruction that
+    /// was interrupted or caused the exception. The trap entry restores sepc
+    /// from this field, so handlers can modify it to skip an instruction.
+    pub sepc: usize,
+
+    /// The value of the scause CSR describing why the trap was taken.
+    pub scause: usize,
+
+    /// The value of the stval CSR, which holds exception specific information
+    /// such as the faulting address.
+    pub stval: usize,
+}
+
+/// The ABI names of the general purpose registers, indexed by register number.
+pub const REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+impl TrapFrame {
+    /// The size of the trap frame in bytes. This must match the stack space
+    /// reserved by the assembly trap entry.
+    pub const SIZE: usize = core::mem::size_of::<TrapFrame>();
+}
+
+// The assembly trap entry hard codes the trap frame layout.
+const _: () = assert!(TrapFrame::SIZE == 36 * 8);
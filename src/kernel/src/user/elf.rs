@@ -0,0 +1,139 @@
+//! Loading ELF executables into user address spaces.
+
+use crate::memory::{AddressSpace, PAGE_SIZE, USER_ADDRESS_LIMIT, UserPageAccess};
+use boot_lib::memory::mmu::MapError;
+use common_lib::elf::{ElfError, ElfFile, ProgramHeader, SegmentFlags};
+
+/// The reasons an executable could not be loaded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The file is not a valid RISC-V ELF64 executable.
+    Elf(ElfError),
+
+    /// The file is a position independent executable, which would need its
+    /// relocations applied. Executables are loaded at the addresses they
+    /// were linked at.
+    PositionIndependent,
+
+    /// A segment extends past the user half of the address space. Holds the
+    /// segment's virtual address.
+    SegmentOutsideUserSpace(u64),
+
+    /// A segment is both writable and executable, which user pages never are.
+    /// Holds the segment's virtual address.
+    WritableAndExecutable(u64),
+
+    /// The entry point is not inside an executable segment.
+    BadEntryPoint(u64),
+
+    /// A page could not be mapped. Segments that share a page fail with
+    /// `MapError::AlreadyMapped`.
+    Map(MapError),
+}
+
+/// Loads a static executable into an address space.
+///
+/// Every loadable segment is mapped with the access its flags ask for, its
+/// contents are copied in, and the rest of its memory, such as BSS, is left
+/// zeroed. Each page belongs to a single segment.
+///
+/// # Arguments
+///
+/// * `address_space` - The address space to load into. On failure it may
+///   hold some of the segments and should be dropped.
+/// * `image` - The whole executable file.
+///
+/// # Returns
+///
+/// The entry point of the executable.
+pub fn load(address_space: &mut AddressSpace, image: &[u8]) -> Result<usize, LoadError> {
+    let file = ElfFile::parse(image).map_err(LoadError::Elf)?;
+
+    if file.is_position_independent() {
+        return Err(LoadError::PositionIndependent);
+    }
+
+    for segment in file.load_segments() {
+        if segment.end_address() > USER_ADDRESS_LIMIT as u64 {
+            return Err(LoadError::SegmentOutsideUserSpace(segment.virtual_address));
+        }
+
+        if segment.flags.writable && segment.flags.executable {
+            return Err(LoadError::WritableAndExecutable(segment.virtual_address));
+        }
+    }
+
+    let entry_point = file.entry_point();
+
+    let is_entry_point_executable = file.load_segments().any(|segment| {
+        segment.flags.executable
+            && (segment.virtual_address..segment.end_address()).contains(&entry_point)
+    });
+
+    if !is_entry_point_executable {
+        return Err(LoadError::BadEntryPoint(entry_point));
+    }
+
+    for segment in file.load_segments() {
+        load_segment(address_space, &segment, file.segment_data(&segment))?;
+    }
+
+    Ok(entry_point as usize)
+}
+
+/// Maps the pages of a segment and copies its contents into them.
+///
+/// # Arguments
+///
+/// * `address_space` - The address space to load into.
+/// * `segment` - A loadable segment within the user half of the address
+///   space.
+/// * `contents` - The bytes of the segment stored in the file.
+fn load_segment(
+    address_space: &mut AddressSpace,
+    segment: &ProgramHeader,
+    contents: &[u8],
+) -> Result<(), LoadError> {
+    let access = page_access(segment.flags);
+
+    let start_address = segment.virtual_address as usize;
+    let end_address = segment.end_address() as usize;
+    let contents_end_address = start_address + contents.len();
+
+    let first_page = start_address & !(PAGE_SIZE - 1);
+
+    for page_address in (first_page..end_address).step_by(PAGE_SIZE) {
+        let page = address_space
+            .map_user_page(page_address, access)
+            .map_err(LoadError::Map)?;
+
+        // New pages are zeroed, so only the part of the page that the file
+        // covers needs to be written.
+        let copy_start = start_address.max(page_address);
+        let copy_end = contents_end_address.min(page_address + PAGE_SIZE);
+
+        if copy_start < copy_end {
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    contents[copy_start - start_address..].as_ptr(),
+                    page.add(copy_start - page_address),
+                    copy_end - copy_start,
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the user page access of a segment that is not both writable and
+/// executable.
+fn page_access(flags: SegmentFlags) -> UserPageAccess {
+    if flags.executable {
+        UserPageAccess::ReadExecute
+    } else if flags.writable {
+        UserPageAccess::ReadWrite
+    } else {
+        UserPageAccess::Read
+    }
+}
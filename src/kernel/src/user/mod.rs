@@ -0,0 +1,226 @@
+//! Running code in user mode.
+//!
+//! A kernel thread becomes a user thread by calling `enter` with the user
+//! register state to start from, which returns to user mode with sret. The
+//! `process` module sets up that state and the address space, loading the
+//! program with `elf::load` from the executables embedded by `programs`.
+//!
+//! Traps from user mode come back to the kernel trap handler on the thread's
+//! kernel stack. Interrupts are handled as usual and return to user mode, an
+//! `ecall` is a system call handled by the `syscall` module, and any other
+//! exception goes to `handle_exception`, which resolves the page faults of
+//! demand paging and copy-on-write and kills the process on anything else.
+
+pub mod elf;
+pub mod programs;
+
+use crate::{
+    debug_println,
+    memory::{self, FaultAccess, PAGE_SIZE, USER_ADDRESS_LIMIT},
+    process,
+    trap::{SSTATUS_SPP, print_trap_frame, trap_cause::TrapCause, trap_frame::TrapFrame},
+};
+use common_lib::syscall::SyscallError;
+use kernel_lib::sync::{disable_interrupts, interrupts::SSTATUS_SIE};
+
+/// The sstatus bit that holds the interrupt enable bit restored by sret.
+const SSTATUS_SPIE: usize = 1 << 5;
+
+/// The sstatus bit that lets supervisor code access user pages.
+const SSTATUS_SUM: usize = 1 << 18;
+
+unsafe extern "C" {
+    static _kernel_trap_return: u8;
+}
+
+/// Returns the register state a user program starts with: every register
+/// zero except sp, and interrupts enabled once it runs.
+///
+/// # Arguments
+///
+/// * `entry_point` - The user address to start at.
+/// * `stack_pointer` - The initial user stack pointer.
+pub fn initial_trap_frame(entry_point: usize, stack_pointer: usize) -> TrapFrame {
+    let sstatus: usize;
+    unsafe {
+        core::arch::asm!("csrr {}, sstatus", out(reg) sstatus, options(nomem, nostack));
+    }
+
+    let mut trap_frame = TrapFrame {
+        registers: [0; 32],
+        sstatus: (sstatus & !(SSTATUS_SPP | SSTATUS_SIE | SSTATUS_SUM)) | SSTATUS_SPIE,
+        sepc: entry_point,
+        scause: 0,
+        stval: 0,
+    };
+
+    trap_frame.registers[2] = stack_pointer;
+    trap_frame
+}
+
+/// Turns the calling kernel thread into a user thread. The user address
+/// space must already be the calling task's address space.
+///
+/// # Arguments
+///
+/// * `initial_trap_frame` - The user register state to start from, which
+///   must return to user mode.
+pub fn enter(initial_trap_frame: &TrapFrame) -> ! {
+    // The trap return path must not be interrupted once it starts preparing
+    // sscratch for user mode. sret enables interrupts again.
+    let _ = disable_interrupts();
+
+    let trap_frame = *initial_trap_frame;
+
+    // The program may have been written by another hart, so its code must be
+    // made visible to instruction fetches on this one.
+    unsafe {
+        core::arch::asm!("fence.i", options(nostack));
+    }
+
+    // The trap return path releases the frame by loading the user sp, and
+    // the next trap from user mode reuses the kernel stack from the top of
+    // the frame down.
+    unsafe {
+        core::arch::asm!(
+            "mv sp, {}",
+            "jr {}",
+            in(reg) &trap_frame as *const TrapFrame,
+            in(reg) &raw const _kernel_trap_return,
+            options(noreturn)
+        );
+    }
+}
+
+/// Handles an exception other than `ecall` raised by user code. Called from
+/// the trap handler.
+///
+/// A page fault that the process's address space can resolve, such as the
+/// first touch of a reserved page or a store to a page shared copy-on-write,
+/// returns to retry the access. Any other exception ends the process.
+///
+/// # Arguments
+///
+/// * `trap_frame` - The user register state.
+/// * `cause` - The decoded cause of the exception.
+pub fn handle_exception(trap_frame: &mut TrapFrame, cause: TrapCause) {
+    let access = match cause {
+        TrapCause::LoadPageFault => Some(FaultAccess::Read),
+        TrapCause::StorePageFault => Some(FaultAccess::Write),
+        TrapCause::InstructionPageFault => Some(FaultAccess::Execute),
+        _ => None,
+    };
+
+    if access.is_some_and(|access| process::resolve_page_fault(trap_frame.stval, access)) {
+        return;
+    }
+
+    debug_println!("\n\n===== USER EXCEPTION =====");
+    print_trap_frame(trap_frame, cause);
+    debug_println!("==========================\n");
+
+    process::exit_current(-1);
+}
+
+/// Copies bytes from the calling thread's user address space.
+///
+/// # Arguments
+///
+/// * `user_address` - The user address to copy from.
+/// * `buffer` - Receives the bytes. Its length is the number of bytes copied.
+///
+/// # Returns
+///
+/// `SyscallError::BadAddress` without copying anything if any byte of the
+/// range is not readable by user code. Reserved pages that were never touched
+/// are mapped first.
+pub fn copy_from_user(user_address: usize, buffer: &mut [u8]) -> Result<(), SyscallError> {
+    if buffer.is_empty() {
+        return Ok(());
+    }
+
+    prepare_user_access(user_address, buffer.len(), FaultAccess::Read)?;
+
+    // Supervisor code may only access user pages while sstatus.SUM is set.
+    unsafe {
+        core::arch::asm!("csrs sstatus, {}", in(reg) SSTATUS_SUM, options(nomem, nostack));
+
+        core::ptr::copy_nonoverlapping(
+            user_address as *const u8,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+        );
+
+        core::arch::asm!("csrc sstatus, {}", in(reg) SSTATUS_SUM, options(nomem, nostack));
+    }
+
+    Ok(())
+}
+
+/// Copies bytes to the calling thread's user address space.
+///
+/// # Arguments
+///
+/// * `user_address` - The user address to copy to.
+/// * `data` - The bytes to copy.
+///
+/// # Returns
+///
+/// `SyscallError::BadAddress` without copying anything if any byte of the
+/// range is not writable by user code. Reserved pages that were never touched
+/// are mapped first, and pages shared copy-on-write are copied.
+pub fn copy_to_user(user_address: usize, data: &[u8]) -> Result<(), SyscallError> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    prepare_user_access(user_address, data.len(), FaultAccess::Write)?;
+
+    unsafe {
+        core::arch::asm!("csrs sstatus, {}", in(reg) SSTATUS_SUM, options(nomem, nostack));
+
+        core::ptr::copy_nonoverlapping(data.as_ptr(), user_address as *mut u8, data.len());
+
+        core::arch::asm!("csrc sstatus, {}", in(reg) SSTATUS_SUM, options(nomem, nostack));
+    }
+
+    Ok(())
+}
+
+/// Makes a nonempty range of the calling thread's user address space
+/// accessible to the kernel. Reserved pages that were never touched are
+/// mapped, and for writes, pages shared copy-on-write are copied.
+///
+/// # Arguments
+///
+/// * `user_address` - The first user address of the range.
+/// * `length` - The length of the range in bytes.
+/// * `access` - `FaultAccess::Read` or `FaultAccess::Write`.
+///
+/// # Returns
+///
+/// `SyscallError::BadAddress` if any byte of the range does not allow the
+/// access to user code.
+pub fn prepare_user_access(
+    user_address: usize,
+    length: usize,
+    access: FaultAccess,
+) -> Result<(), SyscallError> {
+    let end_address = user_address
+        .checked_add(length)
+        .filter(|&end_address| end_address <= USER_ADDRESS_LIMIT)
+        .ok_or(SyscallError::BadAddress)?;
+
+    let is_write = access == FaultAccess::Write;
+    let first_page = user_address & !(PAGE_SIZE - 1);
+
+    let is_accessible = (first_page..end_address).step_by(PAGE_SIZE).all(|page| {
+        memory::is_user_accessible(page, is_write) || process::resolve_page_fault(page, access)
+    });
+
+    if !is_accessible {
+        return Err(SyscallError::BadAddress);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1010 @@
+//! User programs.
+//!
+//! Programs are either small ELF executables assembled into the kernel's read
+//! only data, or ELF executables on a mounted file system. `find` looks up
+//! both: names starting with '/' are file system paths, and any other name is
+//! an embedded program.
+//!
+//! Programs from the file system are read into one of `MAX_LOADED_PROGRAMS`
+//! slots in the kernel image the first time they run, and stay there, so
+//! every `Program` lives as long as the kernel.
+
+use crate::{
+    fs::{self, FsError},
+    memory::PAGE_SIZE,
+};
+use common_lib::syscall::{
+    OPEN_CREATE, OPEN_TRUNCATE, PROTECTION_READ, PROTECTION_WRITE, SEEK_SET, STDOUT, SyscallError,
+    SyscallNumber,
+};
+use core::{
+    arch::global_asm,
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// The user address the code segment of every embedded program is linked at.
+const CODE_ADDRESS: usize = 0x1_0000;
+
+/// The user address the data segment of every embedded program is linked at.
+const DATA_ADDRESS: usize = 0x2_0000;
+
+/// The most programs that can be loaded from the file system.
+const MAX_LOADED_PROGRAMS: usize = 4;
+
+/// The largest program file that can be loaded.
+const MAX_LOADED_PROGRAM_SIZE: usize = 64 * 1024;
+
+/// The longest path a program can be loaded from.
+const MAX_PATH_LENGTH: usize = 32;
+
+/// `LoadedProgram` states.
+const SLOT_FREE: u8 = 0;
+const SLOT_LOADING: u8 = 1;
+const SLOT_LOADED: u8 = 2;
+
+/// A program.
+#[derive(Debug, Copy, Clone)]
+pub struct Program {
+    /// The name `find` looks the program up by.
+    pub name: &'static str,
+
+    /// The ELF image of the program.
+    pub image: &'static [u8],
+}
+
+/// The reasons a program could not be found.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProgramError {
+    /// No embedded program or file has the name.
+    NotFound,
+
+    /// The path is longer than `MAX_PATH_LENGTH`.
+    PathTooLong,
+
+    /// The file is larger than `MAX_LOADED_PROGRAM_SIZE`. Holds its size.
+    TooLarge(usize),
+
+    /// Every slot for programs from the file system is in use.
+    TooManyPrograms,
+
+    /// The file could not be read.
+    File(FsError),
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "no such program"),
+            Self::PathTooLong => write!(f, "the path is too long"),
+            Self::TooLarge(size) => write!(f, "the program is too large ({} bytes)", size),
+            Self::TooManyPrograms => {
+                write!(f, "all {} program slots are in use", MAX_LOADED_PROGRAMS)
+            }
+            Self::File(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// A program loaded from the file system, or room for one.
+struct LoadedProgram {
+    /// One of the `SLOT_` states.
+    state: AtomicU8,
+
+    /// The path the program was loaded from.
+    path: UnsafeCell<[u8; MAX_PATH_LENGTH]>,
+    path_length: UnsafeCell<usize>,
+
+    /// The contents of the file.
+    image: UnsafeCell<[u8; MAX_LOADED_PROGRAM_SIZE]>,
+    image_length: UnsafeCell<usize>,
+}
+
+// A slot is only written by the thread that claimed it while it is loading,
+// and never again once it is loaded.
+unsafe impl Sync for LoadedProgram {}
+
+impl LoadedProgram {
+    /// Returns the program of a loaded slot.
+    fn program(&'static self) -> Program {
+        unsafe {
+            let path = &(&*self.path.get())[..*self.path_length.get()];
+            let image = &(&*self.image.get())[..*self.image_length.get()];
+
+            Program {
+                name: core::str::from_utf8_unchecked(path),
+                image,
+            }
+        }
+    }
+}
+
+/// The programs loaded from the file system.
+static LOADED_PROGRAMS: [LoadedProgram; MAX_LOADED_PROGRAMS] = [const {
+    LoadedProgram {
+        state: AtomicU8::new(SLOT_FREE),
+        path: UnsafeCell::new([0; MAX_PATH_LENGTH]),
+        path_length: UnsafeCell::new(0),
+        image: UnsafeCell::new([0; MAX_LOADED_PROGRAM_SIZE]),
+        image_length: UnsafeCell::new(0),
+    }
+}; MAX_LOADED_PROGRAMS];
+
+/// Defines an embedded program, laid out as an ELF executable by hand.
+///
+/// The executable has a readable and executable code segment, which starts
+/// with the ELF headers like in a linked executable, and a readable and
+/// writable data segment followed by `bss_size` bytes of BSS. Code addresses
+/// use pc relative addressing, while data addresses are absolute, based on
+/// `{data_address}`. The bodies must not define the numeric labels 91 through
+/// 95, which the headers use.
+macro_rules! embedded_program {
+    (
+        $start:ident,
+        $end:ident,
+        code: $code:literal,
+        data: $data:literal,
+        bss_size: $bss_size:literal
+        $(, $($operands:tt)*)?
+    ) => {
+        unsafe extern "C" {
+            static $start: u8;
+            static $end: u8;
+        }
+
+        global_asm!(
+            concat!(".section .rodata.", stringify!($start)),
+            ".balign 8",
+            concat!(".global ", stringify!($start)),
+            concat!(".global ", stringify!($end)),
+            concat!(stringify!($start), ":"),
+            "91:",
+            // ELF header: 64-bit, little-endian, version 1, executable,
+            // RISC-V, with two program headers.
+            ".byte 0x7F, 0x45, 0x4C, 0x46, 2, 1, 1, 0",
+            ".zero 8",
+            ".half 2",
+            ".half 243",
+            ".word 1",
+            ".dword {code_address} + (93f - 91b)",
+            ".dword 92f - 91b",
+            ".dword 0",
+            ".word 0",
+            ".half 64, 56, 2, 0, 0, 0",
+            "92:",
+            // The code segment: PT_LOAD, readable and executable.
+            ".word 1, 5",
+            ".dword 0",
+            ".dword {code_address}, {code_address}",
+            ".dword 94f - 91b",
+            ".dword 94f - 91b",
+            ".dword 4096",
+            // The data segment: PT_LOAD, readable and writable.
+            ".word 1, 6",
+            ".dword 94f - 91b",
+            ".dword {data_address}, {data_address}",
+            ".dword 95f - 94f",
+            concat!(".dword 95f - 94f + ", $bss_size),
+            ".dword 4096",
+            "93:",
+            $code,
+            ".balign 8",
+            "94:",
+            $data,
+            "95:",
+            concat!(stringify!($end), ":"),
+            code_address = const CODE_ADDRESS,
+            data_address = const DATA_ADDRESS,
+            $($($operands)*)?
+        );
+    };
+}
+
+/// Looks up a program, loading it from the file system if its name is a path
+/// that has not been loaded yet. Loading from a block device blocks, so it
+/// must be done from a kernel thread.
+///
+/// # Arguments
+///
+/// * `name` - The name of an embedded program, or the path of a file.
+pub fn find(name: &str) -> Result<Program, ProgramError> {
+    if name.starts_with('/') {
+        load(name)
+    } else {
+        find_embedded(name).ok_or(ProgramError::NotFound)
+    }
+}
+
+/// Looks up an embedded program.
+///
+/// # Arguments
+///
+/// * `name` - The name of the program.
+///
+/// # Returns
+///
+/// The program, or `None` if no program has the name.
+fn find_embedded(name: &str) -> Option<Program> {
+    let (name, start, end) = match name {
+        "init" => (
+            "init",
+            &raw const _user_program_init_start,
+            &raw const _user_program_init_end,
+        ),
+        "hello" => (
+            "hello",
+            &raw const _user_program_hello_start,
+            &raw const _user_program_hello_end,
+        ),
+        "fork-test" => (
+            "fork-test",
+            &raw const _user_program_fork_test_start,
+            &raw const _user_program_fork_test_end,
+        ),
+        "file-test" => (
+            "file-test",
+            &raw const _user_program_file_test_start,
+            &raw const _user_program_file_test_end,
+        ),
+        "pipe-test" => (
+            "pipe-test",
+            &raw const _user_program_pipe_test_start,
+            &raw const _user_program_pipe_test_end,
+        ),
+        "shm-test" => (
+            "shm-test",
+            &raw const _user_program_shm_test_start,
+            &raw const _user_program_shm_test_end,
+        ),
+        "futex-test" => (
+            "futex-test",
+            &raw const _user_program_futex_test_start,
+            &raw const _user_program_futex_test_end,
+        ),
+        _ => return None,
+    };
+
+    Some(Program {
+        name,
+        image: unsafe { core::slice::from_raw_parts(start, end as usize - start as usize) },
+    })
+}
+
+/// Finds the program loaded from a path, or else loads it into a free slot.
+fn load(path: &str) -> Result<Program, ProgramError> {
+    if path.len() > MAX_PATH_LENGTH {
+        return Err(ProgramError::PathTooLong);
+    }
+
+    let loaded = LOADED_PROGRAMS.iter().find(|slot| {
+        slot.state.load(Ordering::Acquire) == SLOT_LOADED && slot.program().name == path
+    });
+
+    if let Some(slot) = loaded {
+        return Ok(slot.program());
+    }
+
+    let file = fs::open(path).map_err(|error| match error {
+        FsError::NotFound | FsError::NotADirectory => ProgramError::NotFound,
+        error => ProgramError::File(error),
+    })?;
+
+    if file.is_directory() {
+        return Err(ProgramError::NotFound);
+    }
+
+    if file.size() > MAX_LOADED_PROGRAM_SIZE {
+        return Err(ProgramError::TooLarge(file.size()));
+    }
+
+    let slot = LOADED_PROGRAMS
+        .iter()
+        .find(|slot| {
+            slot.state
+                .compare_exchange(
+                    SLOT_FREE,
+                    SLOT_LOADING,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        })
+        .ok_or(ProgramError::TooManyPrograms)?;
+
+    // Two threads may load the same path at once, which only wastes a slot.
+    let image = unsafe { &mut (&mut *slot.image.get())[..file.size()] };
+
+    // A file that shrank since it was opened loads only as far as it goes,
+    // which the ELF loader rejects if anything is missing.
+    let length = match file.read(0, image) {
+        Ok(length) => length,
+        Err(error) => {
+            slot.state.store(SLOT_FREE, Ordering::Release);
+
+            return Err(ProgramError::File(error));
+        }
+    };
+
+    unsafe {
+        (&mut *slot.path.get())[..path.len()].copy_from_slice(path.as_bytes());
+        *slot.path_length.get() = path.len();
+        *slot.image_length.get() = length;
+    }
+
+    slot.state.store(SLOT_LOADED, Ordering::Release);
+
+    Ok(slot.program())
+}
+
+// Sums the numbers from an initialized data word down to 1 into a BSS word,
+// and copies the sum into the second page of two pages of anonymous memory,
+// which is only allocated by that store. Then writes a greeting to the
+// console, yields, and exits with the sum read back, 55, or with the error
+// code if the memory could not be reserved.
+embedded_program!(
+    _user_program_hello_start,
+    _user_program_hello_end,
+    code: "
+        li t2, {data_address}
+        ld t1, 0(t2)
+        li t0, 0
+
+    1:
+        add t0, t0, t1
+        addi t1, t1, -1
+        bnez t1, 1b
+
+        sd t0, 8(t2)
+
+        li a0, {page_size} * 2
+        li a1, {read_write}
+        li a7, {map_anonymous}
+        ecall
+
+        bltz a0, 5f
+
+        li t1, {page_size}
+        add t3, a0, t1
+        ld t0, 8(t2)
+        sd t0, 0(t3)
+
+        li a0, {stdout}
+        lla a1, 3f
+        lla a2, 4f
+        sub a2, a2, a1
+        li a7, {write}
+        ecall
+
+        li a7, {yield_}
+        ecall
+
+        ld a0, 0(t3)
+
+    5:
+        li a7, {exit}
+        ecall
+
+    2:
+        j 2b
+
+    3:
+        .ascii \"Hello from user mode!\\n\"
+    4:
+    ",
+    data: ".dword 10",
+    bss_size: "8",
+    stdout = const STDOUT,
+    page_size = const PAGE_SIZE,
+    read_write = const PROTECTION_READ | PROTECTION_WRITE,
+    write = const SyscallNumber::Write as usize,
+    map_anonymous = const SyscallNumber::MapAnonymous as usize,
+    yield_ = const SyscallNumber::Yield as usize,
+    exit = const SyscallNumber::Exit as usize,
+);
+
+// Forks after setting a data word to 1, and both processes then store to the
+// word, which they share copy-on-write. The child stores 2 and replaces itself
+// with the hello program. The parent stores 3, yields to let the child run,
+// and exits with 1 if its word changed. Otherwise it writes a greeting, waits
+// for the child, and exits with 0 if the child exited with hello's 55, or
+// with 1 if not. Either exits with the error code if its system call fails.
+embedded_program!(
+    _user_program_fork_test_start,
+    _user_program_fork_test_end,
+    code: "
+        li t2, {data_address}
+        li t0, 1
+        sd t0, 0(t2)
+
+        li a7, {fork}
+        ecall
+
+        bltz a0, 2f
+        beqz a0, 1f
+        mv s0, a0
+
+        li t0, 3
+        sd t0, 0(t2)
+
+        li a7, {yield_}
+        ecall
+
+        ld t1, 0(t2)
+        li a0, 1
+        bne t0, t1, 2f
+
+        li a0, {stdout}
+        lla a1, 3f
+        lla a2, 4f
+        sub a2, a2, a1
+        li a7, {write}
+        ecall
+
+        mv a0, s0
+        li a1, {data_address} + 8
+        li a2, 0
+        li a7, {wait}
+        ecall
+
+        bltz a0, 2f
+
+        li t2, {data_address}
+        ld t1, 8(t2)
+        li t0, 55
+        li a0, 1
+        bne t0, t1, 2f
+
+        li a0, 0
+        j 2f
+
+    1:
+        li t0, 2
+        sd t0, 0(t2)
+
+        lla a0, 5f
+        lla a1, 6f
+        sub a1, a1, a0
+        li a7, {exec}
+        ecall
+
+    2:
+        li a7, {exit}
+        ecall
+
+    3:
+        .ascii \"Hello from the parent process!\\n\"
+    4:
+
+    5:
+        .ascii \"hello\"
+    6:
+    ",
+    data: ".dword 0, 0",
+    bss_size: "0",
+    stdout = const STDOUT,
+    write = const SyscallNumber::Write as usize,
+    yield_ = const SyscallNumber::Yield as usize,
+    fork = const SyscallNumber::Fork as usize,
+    exec = const SyscallNumber::Exec as usize,
+    wait = const SyscallNumber::Wait as usize,
+    exit = const SyscallNumber::Exit as usize,
+);
+
+// Creates "/tmp/file-test" and writes a greeting to it, then duplicates the
+// descriptor and rewinds the file through the duplicate, which shares the
+// offset. After closing the duplicate, reads the greeting back into the data
+// segment through the first descriptor, writes it to the console, and exits
+// with 0. Exits with the error code if a system call fails.
+embedded_program!(
+    _user_program_file_test_start,
+    _user_program_file_test_end,
+    code: "
+        lla a0, 5f
+        lla a1, 6f
+        sub a1, a1, a0
+        li a2, {create_truncate}
+        li a7, {open}
+        ecall
+
+        bltz a0, 2f
+        mv s0, a0
+
+        lla a1, 3f
+        lla a2, 4f
+        sub a2, a2, a1
+        li a7, {write}
+        ecall
+
+        bltz a0, 2f
+
+        mv a0, s0
+        li a7, {dup}
+        ecall
+
+        bltz a0, 2f
+        mv s1, a0
+
+        li a1, 0
+        li a2, {seek_set}
+        li a7, {seek}
+        ecall
+
+        bltz a0, 2f
+
+        mv a0, s1
+        li a7, {close}
+        ecall
+
+        bltz a0, 2f
+
+        mv a0, s0
+        li a1, {data_address}
+        li a2, 64
+        li a7, {read}
+        ecall
+
+        bltz a0, 2f
+
+        mv a2, a0
+        li a0, {stdout}
+        li a1, {data_address}
+        li a7, {write}
+        ecall
+
+        bltz a0, 2f
+
+        mv a0, s0
+        li a7, {close}
+        ecall
+
+    2:
+        li a7, {exit}
+        ecall
+
+    1:
+        j 1b
+
+    3:
+        .ascii \"Hello from a file!\\n\"
+    4:
+
+    5:
+        .ascii \"/tmp/file-test\"
+    6:
+    ",
+    data: ".zero 64",
+    bss_size: "0",
+    stdout = const STDOUT,
+    create_truncate = const OPEN_CREATE | OPEN_TRUNCATE,
+    seek_set = const SEEK_SET,
+    open = const SyscallNumber::Open as usize,
+    read = const SyscallNumber::Read as usize,
+    write = const SyscallNumber::Write as usize,
+    seek = const SyscallNumber::Seek as usize,
+    close = const SyscallNumber::Close as usize,
+    dup = const SyscallNumber::Dup as usize,
+    exit = const SyscallNumber::Exit as usize,
+);
+
+// Creates a pipe and forks. The child closes the read end, moves the write end
+// to standard output by closing it and duplicating the write end into the
+// freed descriptor, and replaces itself with the hello program, whose greeting
+// goes into the pipe. The parent closes the write end and copies whatever it
+// reads from the pipe to the console until the child's end closes, then exits
+// with 0. Either exits with the error code if its system call fails.
+embedded_program!(
+    _user_program_pipe_test_start,
+    _user_program_pipe_test_end,
+    code: "
+        li a0, {data_address}
+        li a7, {pipe}
+        ecall
+
+        bltz a0, 2f
+
+        li t2, {data_address}
+        ld s0, 0(t2)
+        ld s1, 8(t2)
+
+        li a7, {fork}
+        ecall
+
+        bltz a0, 2f
+        beqz a0, 1f
+
+        mv a0, s1
+        li a7, {close}
+        ecall
+
+        bltz a0, 2f
+
+    3:
+        mv a0, s0
+        li a1, {data_address} + 16
+        li a2, 64
+        li a7, {read}
+        ecall
+
+        blez a0, 2f
+
+        mv a2, a0
+        li a0, {stdout}
+        li a1, {data_address} + 16
+        li a7, {write}
+        ecall
+
+        bltz a0, 2f
+        j 3b
+
+    1:
+        mv a0, s0
+        li a7, {close}
+        ecall
+
+        li a0, {stdout}
+        li a7, {close}
+        ecall
+
+        mv a0, s1
+        li a7, {dup}
+        ecall
+
+        bltz a0, 2f
+
+        mv a0, s1
+        li a7, {close}
+        ecall
+
+        lla a0, 5f
+        lla a1, 6f
+        sub a1, a1, a0
+        li a7, {exec}
+        ecall
+
+    2:
+        li a7, {exit}
+        ecall
+
+    4:
+        j 4b
+
+    5:
+        .ascii \"hello\"
+    6:
+    ",
+    data: ".zero 80",
+    bss_size: "0",
+    stdout = const STDOUT,
+    pipe = const SyscallNumber::Pipe as usize,
+    fork = const SyscallNumber::Fork as usize,
+    read = const SyscallNumber::Read as usize,
+    write = const SyscallNumber::Write as usize,
+    close = const SyscallNumber::Close as usize,
+    dup = const SyscallNumber::Dup as usize,
+    exec = const SyscallNumber::Exec as usize,
+    exit = const SyscallNumber::Exit as usize,
+);
+
+// Creates a named shared memory object, maps it read only, and forks. The
+// child opens the object by name, maps it again readable and writable, copies
+// a message into it after the first dword, and then sets the first dword. The
+// parent yields until it sees the first dword set through its own read only
+// mapping, writes the message from there to the console, and exits with 0.
+// Either exits with the error code if its system call fails, and the parent
+// exits with 1 if the child never sets the dword.
+embedded_program!(
+    _user_program_shm_test_start,
+    _user_program_shm_test_end,
+    code: "
+        lla a0, 5f
+        lla a1, 6f
+        sub a1, a1, a0
+        li a2, 4096
+        li a7, {shm_create}
+        ecall
+
+        bltz a0, 2f
+
+        li a1, {read_only}
+        li a7, {shm_map}
+        ecall
+
+        bltz a0, 2f
+        mv s0, a0
+
+        li a7, {fork}
+        ecall
+
+        bltz a0, 2f
+        beqz a0, 1f
+
+        li s1, 10000
+
+    3:
+        ld t0, 0(s0)
+        bnez t0, 4f
+
+        addi s1, s1, -1
+        li a0, 1
+        beqz s1, 2f
+
+        li a7, {yield_}
+        ecall
+
+        j 3b
+
+    4:
+        fence r, r
+
+        li a0, {stdout}
+        addi a1, s0, 8
+        lla t0, 7f
+        lla a2, 8f
+        sub a2, a2, t0
+        li a7, {write}
+        ecall
+
+        bltz a0, 2f
+        li a0, 0
+        j 2f
+
+    1:
+        lla a0, 5f
+        lla a1, 6f
+        sub a1, a1, a0
+        li a7, {shm_open}
+        ecall
+
+        bltz a0, 2f
+
+        li a1, {read_write}
+        li a7, {shm_map}
+        ecall
+
+        bltz a0, 2f
+
+        addi t1, a0, 8
+        lla t2, 7f
+        lla t3, 8f
+
+    9:
+        lbu t4, 0(t2)
+        sb t4, 0(t1)
+        addi t1, t1, 1
+        addi t2, t2, 1
+        bltu t2, t3, 9b
+
+        fence w, w
+
+        li t4, 1
+        sd t4, 0(a0)
+
+        li a0, 0
+
+    2:
+        li a7, {exit}
+        ecall
+
+    10:
+        j 10b
+
+    5:
+        .ascii \"shm-test\"
+    6:
+    7:
+        .ascii \"Hello through shared memory!\\n\"
+    8:
+    ",
+    data: ".zero 8",
+    bss_size: "0",
+    stdout = const STDOUT,
+    read_only = const PROTECTION_READ,
+    read_write = const PROTECTION_READ | PROTECTION_WRITE,
+    shm_create = const SyscallNumber::ShmCreate as usize,
+    shm_open = const SyscallNumber::ShmOpen as usize,
+    shm_map = const SyscallNumber::ShmMap as usize,
+    fork = const SyscallNumber::Fork as usize,
+    yield_ = const SyscallNumber::Yield as usize,
+    write = const SyscallNumber::Write as usize,
+    exit = const SyscallNumber::Exit as usize,
+);
+
+// Creates an anonymous shared memory object, maps it, and forks. The child
+// waits on the first word of the memory with futex_wait for as long as the
+// word is zero, retrying if the parent changed it first, then writes a
+// message and exits with 0. The parent yields a
+// few times so the child is likely asleep, sets the word, wakes the child
+// with futex_wake, and exits with 0. Either exits with the error code if its
+// system call fails.
+embedded_program!(
+    _user_program_futex_test_start,
+    _user_program_futex_test_end,
+    code: "
+        li a0, 0
+        li a1, 0
+        li a2, 4096
+        li a7, {shm_create}
+        ecall
+
+        bltz a0, 2f
+
+        li a1, {read_write}
+        li a7, {shm_map}
+        ecall
+
+        bltz a0, 2f
+        mv s0, a0
+
+        li a7, {fork}
+        ecall
+
+        bltz a0, 2f
+        beqz a0, 1f
+
+        li s1, 8
+
+    3:
+        li a7, {yield_}
+        ecall
+
+        addi s1, s1, -1
+        bnez s1, 3b
+
+        li t0, 1
+        sw t0, 0(s0)
+
+        mv a0, s0
+        li a1, 1
+        li a7, {futex_wake}
+        ecall
+
+        bltz a0, 2f
+        li a0, 0
+        j 2f
+
+    1:
+        lw t0, 0(s0)
+        bnez t0, 4f
+
+        mv a0, s0
+        li a1, 0
+        li a7, {futex_wait}
+        ecall
+
+        li t0, {try_again}
+        beq a0, t0, 1b
+        bltz a0, 2f
+        j 1b
+
+    4:
+        li a0, {stdout}
+        lla a1, 5f
+        lla a2, 6f
+        sub a2, a2, a1
+        li a7, {write}
+        ecall
+
+        bltz a0, 2f
+        li a0, 0
+
+    2:
+        li a7, {exit}
+        ecall
+
+    7:
+        j 7b
+
+    5:
+        .ascii \"Woken by futex_wake!\\n\"
+    6:
+    ",
+    data: ".zero 8",
+    bss_size: "0",
+    stdout = const STDOUT,
+    read_write = const PROTECTION_READ | PROTECTION_WRITE,
+    try_again = const -(SyscallError::TryAgain.code() as isize),
+    shm_create = const SyscallNumber::ShmCreate as usize,
+    shm_map = const SyscallNumber::ShmMap as usize,
+    fork = const SyscallNumber::Fork as usize,
+    yield_ = const SyscallNumber::Yield as usize,
+    futex_wait = const SyscallNumber::FutexWait as usize,
+    futex_wake = const SyscallNumber::FutexWake as usize,
+    write = const SyscallNumber::Write as usize,
+    exit = const SyscallNumber::Exit as usize,
+);
+
+// The first process, and an end-to-end check of the path into and out of user
+// mode. Writes a greeting and forks. The child writes a greeting of its own,
+// yields, and exits with 7. The parent yields, waits for the child, and exits
+// with 0 after writing that the check passed if the child exited with 7, or
+// with 1 if not. Either exits with the error code if its system call fails.
+embedded_program!(
+    _user_program_init_start,
+    _user_program_init_end,
+    code: "
+        li a0, {stdout}
+        lla a1, 3f
+        lla a2, 4f
+        sub a2, a2, a1
+        li a7, {write}
+        ecall
+
+        bltz a0, 2f
+
+        li a7, {fork}
+        ecall
+
+        bltz a0, 2f
+        beqz a0, 1f
+        mv s0, a0
+
+        li a7, {yield_}
+        ecall
+
+        mv a0, s0
+        li a1, {data_address}
+        li a2, 0
+        li a7, {wait}
+        ecall
+
+        bltz a0, 2f
+
+        li t2, {data_address}
+        ld t1, 0(t2)
+        li t0, 7
+        li a0, 1
+        bne t0, t1, 2f
+
+        li a0, {stdout}
+        lla a1, 7f
+        lla a2, 8f
+        sub a2, a2, a1
+        li a7, {write}
+        ecall
+
+        bltz a0, 2f
+        li a0, 0
+        j 2f
+
+    1:
+        li a0, {stdout}
+        lla a1, 5f
+        lla a2, 6f
+        sub a2, a2, a1
+        li a7, {write}
+        ecall
+
+        bltz a0, 2f
+
+        li a7, {yield_}
+        ecall
+
+        li a0, 7
+
+    2:
+        li a7, {exit}
+        ecall
+
+    9:
+        j 9b
+
+    3:
+        .ascii \"init: entered user mode\\n\"
+    4:
+    5:
+        .ascii \"init: the forked child is running\\n\"
+    6:
+    7:
+        .ascii \"init: the child exited with 7, user mode works\\n\"
+    8:
+    ",
+    data: ".dword 0",
+    bss_size: "0",
+    stdout = const STDOUT,
+    write = const SyscallNumber::Write as usize,
+    yield_ = const SyscallNumber::Yield as usize,
+    fork = const SyscallNumber::Fork as usize,
+    wait = const SyscallNumber::Wait as usize,
+    exit = const SyscallNumber::Exit as usize,
+);
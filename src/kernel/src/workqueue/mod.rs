@@ -0,0 +1,252 @@
+//! Deferred work.
+//!
+//! Code that must not block or take long, such as an interrupt handler,
+//! queues work with `queue_work` to run later on a kernel worker thread. Each
+//! hart has its own queue, which work queued on the hart goes to. The worker
+//! threads take turns draining the queues, and a queue is drained by one
+//! worker at a time, so the work queued on a hart runs in the order it was
+//! queued. `flush` waits for the work queued so far, and `drain` also for the
+//! work that work queues.
+//!
+//! The kernel has no heap, so a work item is a function and an argument
+//! rather than a boxed closure. A closure that captures nothing converts to
+//! the function:
+//!
+//! ```ignore
+//! workqueue::queue_work(|device_index| refill_receive_buffers(device_index), 0)?;
+//! ```
+
+use crate::{
+    debug_println,
+    hart::{MAX_HART_COUNT, current_hart_id},
+    task::{self, WaitQueue},
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use kernel_lib::{ring_buffer::RingBuffer, sync::SpinLockIrqSave};
+
+/// The most work items each hart's queue holds.
+const QUEUE_CAPACITY: usize = 64;
+
+/// The number of worker threads. Threads are not bound to harts, so a few
+/// workers serve the queues of every hart.
+const WORKER_COUNT: usize = 2;
+
+/// Reasons work could not be queued.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WorkQueueError {
+    /// The calling hart's queue holds `QUEUE_CAPACITY` items already.
+    Full,
+}
+
+impl fmt::Display for WorkQueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "the work queue is full"),
+        }
+    }
+}
+
+/// A function to call later with its argument.
+#[derive(Copy, Clone)]
+struct WorkItem {
+    function: fn(usize),
+    argument: usize,
+}
+
+/// The work queued on a hart.
+struct HartQueue {
+    items: RingBuffer<WorkItem, QUEUE_CAPACITY>,
+
+    /// The number of items ever queued.
+    queued_count: u64,
+
+    /// Set while a worker runs the queue's items.
+    is_busy: bool,
+}
+
+impl HartQueue {
+    const fn new() -> Self {
+        Self {
+            items: RingBuffer::new(),
+            queued_count: 0,
+            is_busy: false,
+        }
+    }
+
+    /// Returns true if the queue has items and no worker runs them.
+    fn needs_worker(&self) -> bool {
+        !self.is_busy && !self.items.is_empty()
+    }
+}
+
+/// The queue of each hart, indexed by hart ID.
+static QUEUES: [SpinLockIrqSave<HartQueue>; MAX_HART_COUNT] =
+    [const { SpinLockIrqSave::new(HartQueue::new()) }; MAX_HART_COUNT];
+
+/// The number of items of each hart's queue that have run, indexed by hart
+/// ID. Items run in queue order, so the first this many queued items are
+/// done.
+static COMPLETED_COUNTS: [AtomicU64; MAX_HART_COUNT] =
+    [const { AtomicU64::new(0) }; MAX_HART_COUNT];
+
+/// The idle workers, woken when work is queued.
+static WORK_AVAILABLE: WaitQueue = WaitQueue::new();
+
+/// The threads in `flush` and `drain`, woken when an item has run.
+static WORK_COMPLETED: WaitQueue = WaitQueue::new();
+
+/// Starts the worker threads.
+pub fn initialize() {
+    for _ in 0..WORKER_COUNT {
+        if let Err(error) = task::spawn_kernel_thread("worker", run_worker, 0) {
+            debug_println!("Failed to start a work queue worker: {}.", error);
+        }
+    }
+}
+
+/// Queues a function to run on a worker thread. Can be called from interrupt
+/// handlers.
+///
+/// # Arguments
+///
+/// * `function` - The function to call. It runs in a kernel thread and may
+///   block, but must not call `flush` or `drain`.
+/// * `argument` - The value passed to `function`.
+pub fn queue_work(function: fn(usize), argument: usize) -> Result<(), WorkQueueError> {
+    {
+        let mut queue = QUEUES[current_hart_id()].lock();
+
+        queue
+            .items
+            .push_back(WorkItem { function, argument })
+            .map_err(|_| WorkQueueError::Full)?;
+
+        queue.queued_count += 1;
+    }
+
+    WORK_AVAILABLE.wake_one();
+
+    Ok(())
+}
+
+/// Blocks the calling kernel thread until all work queued before the call,
+/// on any hart, has run.
+#[allow(dead_code)]
+pub fn flush() {
+    let queued_counts: [u64; MAX_HART_COUNT] =
+        core::array::from_fn(|hart_id| QUEUES[hart_id].lock().queued_count);
+
+    WORK_COMPLETED.wait_until(|| {
+        COMPLETED_COUNTS
+            .iter()
+            .zip(queued_counts)
+            .all(|(completed_count, queued_count)| {
+                completed_count.load(Ordering::Acquire) >= queued_count
+            })
+    });
+}
+
+/// Blocks the calling kernel thread until every queue is empty, running
+/// `flush` again for as long as the work flushed queues more.
+#[allow(dead_code)]
+pub fn drain() {
+    while QUEUES.iter().enumerate().any(|(hart_id, queue)| {
+        queue.lock().queued_count > COMPLETED_COUNTS[hart_id].load(Ordering::Acquire)
+    }) {
+        flush();
+    }
+}
+
+/// The entry point of a worker thread. Runs the items of every queue no other
+/// worker is running, and waits for more once none is left.
+fn run_worker(_argument: usize) {
+    loop {
+        WORK_AVAILABLE.wait_until(|| QUEUES.iter().any(|queue| queue.lock().needs_worker()));
+
+        // Start with the queue of the current hart, whose items were most
+        // likely queued by code that just ran here.
+        let first_hart_id = current_hart_id();
+
+        for offset in 0..MAX_HART_COUNT {
+            run_queue((first_hart_id + offset) % MAX_HART_COUNT);
+        }
+    }
+}
+
+/// Runs the items of a hart's queue until it is empty, unless another worker
+/// runs them already.
+///
+/// # Arguments
+///
+/// * `hart_id` - The hart whose queue is run.
+fn run_queue(hart_id: usize) {
+    let queue = &QUEUES[hart_id];
+
+    {
+        let mut queue = queue.lock();
+
+        if !queue.needs_worker() {
+            return;
+        }
+
+        queue.is_busy = true;
+    }
+
+    loop {
+        // The queue is marked idle under the same lock that finds it empty,
+        // so an item queued afterwards wakes a worker that can take it.
+        let item = {
+            let mut queue = queue.lock();
+            let item = queue.items.pop_front();
+
+            if item.is_none() {
+                queue.is_busy = false;
+            }
+
+            item
+        };
+
+        let Some(item) = item else {
+            return;
+        };
+
+        (item.function)(item.argument);
+
+        COMPLETED_COUNTS[hart_id].fetch_add(1, Ordering::Release);
+        WORK_COMPLETED.wake_all();
+    }
+}
+
+#[cfg(feature = "kernel-tests")]
+mod kernel_tests {
+    use super::*;
+    use crate::kernel_test;
+    use core::sync::atomic::AtomicUsize;
+
+    /// The arguments of the items `test_work_runs_in_queue_order` ran, one
+    /// decimal digit each.
+    static RUN_ORDER: AtomicUsize = AtomicUsize::new(0);
+
+    fn record_argument(argument: usize) {
+        let run_order = RUN_ORDER.load(Ordering::Relaxed);
+
+        RUN_ORDER.store(run_order * 10 + argument, Ordering::Relaxed);
+    }
+
+    kernel_test!(
+        fn test_work_runs_in_queue_order() {
+            for argument in 1..=3 {
+                queue_work(record_argument, argument).unwrap();
+            }
+
+            // The tests run on the idle task, which cannot block in `flush`,
+            // so it yields to the workers until they are done.
+            while RUN_ORDER.load(Ordering::Relaxed) != 123 {
+                task::yield_now();
+            }
+        }
+    );
+}
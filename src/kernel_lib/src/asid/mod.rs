@@ -0,0 +1,208 @@
+//! Allocation of address space identifiers.
+//!
+//! An address space identifier (ASID) tags the translations a hart caches, so
+//! switching between address spaces with different ASIDs needs no TLB flush.
+//! ASID 0 is never handed out, leaving it for the kernel's own address space
+//! and for any address space that finds every other ASID taken.
+//!
+//! Translations tagged with a freed ASID may linger in the TLB of any hart,
+//! so an ASID given out again is reported as recycled and its new owner must
+//! flush it first. The allocator moves on from the last ASID it gave out
+//! rather than reusing the lowest free one, which puts off recycling until
+//! every ASID has been used once.
+
+/// The number of ASIDs tracked by each word of the bitmaps.
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+/// An ASID handed out by `AsidAllocator::allocate`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Asid {
+    /// The ASID, which is never zero.
+    pub asid: usize,
+
+    /// True if the ASID was given out before, so stale translations tagged
+    /// with it may still be cached.
+    pub is_recycled: bool,
+}
+
+/// Hands out up to `WORDS * 64` ASIDs, of which ASID 0 is reserved.
+#[derive(Debug, Clone)]
+pub struct AsidAllocator<const WORDS: usize> {
+    /// One bit for every ASID owned by an address space.
+    in_use: [u64; WORDS],
+
+    /// One bit for every ASID given out at least once.
+    was_used: [u64; WORDS],
+
+    /// The number of ASIDs the hardware supports, including ASID 0, capped
+    /// to what the bitmaps track.
+    asid_count: usize,
+
+    /// The ASID the search for a free one starts at.
+    next_asid: usize,
+}
+
+impl<const WORDS: usize> AsidAllocator<WORDS> {
+    /// Creates an allocator with no ASIDs to hand out until
+    /// `set_asid_count` is called.
+    pub const fn new() -> Self {
+        Self {
+            in_use: [0; WORDS],
+            was_used: [0; WORDS],
+            asid_count: 0,
+            next_asid: 1,
+        }
+    }
+
+    /// Sets the number of ASIDs the hardware supports. Must be called before
+    /// any ASID is allocated.
+    ///
+    /// # Arguments
+    ///
+    /// * `asid_count` - The number of ASIDs, including ASID 0. Any more than
+    ///   `WORDS * 64` are never handed out.
+    pub fn set_asid_count(&mut self, asid_count: usize) {
+        self.asid_count = asid_count.min(WORDS * BITS_PER_WORD);
+    }
+
+    /// Returns the number of ASIDs the allocator can hand out, which excludes
+    /// ASID 0.
+    pub fn capacity(&self) -> usize {
+        self.asid_count.saturating_sub(1)
+    }
+
+    /// Takes a free ASID.
+    ///
+    /// # Returns
+    ///
+    /// The ASID, or `None` if every ASID is in use.
+    pub fn allocate(&mut self) -> Option<Asid> {
+        let capacity = self.capacity();
+
+        let asid = (0..capacity)
+            .map(|step| 1 + (self.next_asid - 1 + step) % capacity)
+            .find(|&asid| !is_set(&self.in_use, asid))?;
+
+        let is_recycled = is_set(&self.was_used, asid);
+
+        set(&mut self.in_use, asid, true);
+        set(&mut self.was_used, asid, true);
+
+        self.next_asid = if asid == capacity { 1 } else { asid + 1 };
+
+        Some(Asid { asid, is_recycled })
+    }
+
+    /// Returns an ASID so that it can be handed out again.
+    ///
+    /// # Arguments
+    ///
+    /// * `asid` - An ASID returned by `allocate` and not freed since.
+    ///
+    /// # Panics
+    ///
+    /// If the ASID is not in use.
+    pub fn free(&mut self, asid: usize) {
+        if asid == 0 || asid >= self.asid_count || !is_set(&self.in_use, asid) {
+            panic!("ASID {} is not in use.", asid);
+        }
+
+        set(&mut self.in_use, asid, false);
+    }
+}
+
+impl<const WORDS: usize> Default for AsidAllocator<WORDS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns true if an ASID's bit is set in a bitmap.
+fn is_set(bitmap: &[u64], asid: usize) -> bool {
+    bitmap[asid / BITS_PER_WORD] & (1 << (asid % BITS_PER_WORD)) != 0
+}
+
+/// Sets or clears an ASID's bit in a bitmap.
+fn set(bitmap: &mut [u64], asid: usize, value: bool) {
+    let bit = 1 << (asid % BITS_PER_WORD);
+
+    if value {
+        bitmap[asid / BITS_PER_WORD] |= bit;
+    } else {
+        bitmap[asid / BITS_PER_WORD] &= !bit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocator(asid_count: usize) -> AsidAllocator<2> {
+        let mut allocator = AsidAllocator::new();
+        allocator.set_asid_count(asid_count);
+
+        allocator
+    }
+
+    #[test]
+    fn test_allocate_skips_asid_zero_and_fresh_asids_are_not_recycled() {
+        let mut allocator = allocator(4);
+
+        let asids: Vec<Asid> = (0..3).map(|_| allocator.allocate().unwrap()).collect();
+
+        assert_eq!(
+            asids.iter().map(|asid| asid.asid).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert!(asids.iter().all(|asid| !asid.is_recycled));
+        assert_eq!(allocator.allocate(), None);
+    }
+
+    #[test]
+    fn test_allocate_moves_on_before_recycling() {
+        let mut allocator = allocator(4);
+
+        let first = allocator.allocate().unwrap();
+        allocator.free(first.asid);
+
+        // ASID 1 is free again, but the unused ASIDs come first.
+        assert_eq!(
+            allocator.allocate(),
+            Some(Asid {
+                asid: 2,
+                is_recycled: false
+            })
+        );
+        assert_eq!(
+            allocator.allocate(),
+            Some(Asid {
+                asid: 3,
+                is_recycled: false
+            })
+        );
+        assert_eq!(
+            allocator.allocate(),
+            Some(Asid {
+                asid: 1,
+                is_recycled: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_asid_count_is_capped_and_zero_hands_out_nothing() {
+        assert_eq!(allocator(1 << 16).capacity(), 127);
+        assert_eq!(allocator(0).capacity(), 0);
+        assert_eq!(allocator(1).allocate(), None);
+        assert_eq!(AsidAllocator::<2>::new().allocate(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ASID 2 is not in use.")]
+    fn test_free_rejects_asids_not_in_use() {
+        let mut allocator = allocator(4);
+        allocator.allocate().unwrap();
+
+        allocator.free(2);
+    }
+}
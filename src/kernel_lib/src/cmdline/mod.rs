@@ -0,0 +1,364 @@
+//! Kernel command line parsing.
+//!
+//! The command line is a whitespace separated list of options. Each option is
+//! either a boolean flag such as `quiet` or a `key=value` pair such as
+//! `loglevel=7`. Values that contain whitespace can be wrapped in double
+//! quotes, as in `init="/bin/sh -x"`. When an option appears more than once,
+//! the last occurrence wins.
+
+/// A single option on the command line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CommandLineOption<'a> {
+    /// The text before the first '='.
+    pub key: &'a str,
+
+    /// The text after the first '=' with surrounding double quotes removed,
+    /// or `None` for a flag without a value.
+    pub value: Option<&'a str>,
+}
+
+/// A parsed view of the kernel command line. Parsing is done lazily and never
+/// allocates.
+#[derive(Debug, Copy, Clone)]
+pub struct CommandLine<'a> {
+    text: &'a str,
+}
+
+impl<'a> CommandLine<'a> {
+    /// Creates a parser for the given command line.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The raw command line, such as the "bootargs" property of
+    ///   the device tree's /chosen node.
+    pub const fn new(text: &'a str) -> Self {
+        Self { text }
+    }
+
+    /// Returns the raw command line.
+    pub const fn as_str(&self) -> &'a str {
+        self.text
+    }
+
+    /// Returns an iterator over every option in the order it appears.
+    pub fn options(&self) -> CommandLineOptionIter<'a> {
+        CommandLineOptionIter {
+            remaining: self.text,
+        }
+    }
+
+    /// Finds the last occurrence of an option.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The option name.
+    pub fn get(&self, key: &str) -> Option<CommandLineOption<'a>> {
+        self.options().filter(|option| option.key == key).last()
+    }
+
+    /// Returns true if the option is present, with or without a value.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The option name.
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value of a `key=value` option.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The option name.
+    ///
+    /// # Returns
+    ///
+    /// The value, or `None` if the option is missing or is a flag without a
+    /// value.
+    pub fn get_str(&self, key: &str) -> Option<&'a str> {
+        self.get(key)?.value
+    }
+
+    /// Returns the value of a boolean option.
+    ///
+    /// A flag without a value is true. Values of `1`, `y`, `yes`, `on`, and
+    /// `true` are true, and `0`, `n`, `no`, `off`, and `false` are false,
+    /// ignoring case.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The option name.
+    ///
+    /// # Returns
+    ///
+    /// The value, or `None` if the option is missing or its value is not a
+    /// recognized boolean.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        let Some(value) = self.get(key)?.value else {
+            return Some(true);
+        };
+
+        const TRUE_VALUES: [&str; 5] = ["1", "y", "yes", "on", "true"];
+        const FALSE_VALUES: [&str; 5] = ["0", "n", "no", "off", "false"];
+
+        if TRUE_VALUES
+            .iter()
+            .any(|text| value.eq_ignore_ascii_case(text))
+        {
+            Some(true)
+        } else if FALSE_VALUES
+            .iter()
+            .any(|text| value.eq_ignore_ascii_case(text))
+        {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of a numeric option.
+    ///
+    /// Values are decimal unless prefixed with `0x` for hexadecimal or `0b`
+    /// for binary. Underscores between digits are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The option name.
+    ///
+    /// # Returns
+    ///
+    /// The value, or `None` if the option is missing or its value is not a
+    /// valid number.
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        parse_u64(self.get_str(key)?)
+    }
+
+    /// Returns the value of a size option in bytes, such as `mem=512M`.
+    ///
+    /// The number may be followed by a `K`, `M`, `G`, or `T` suffix, ignoring
+    /// case, that multiplies it by the matching power of 1024.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The option name.
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes, or `None` if the option is missing, its value is
+    /// not a valid size, or the size does not fit in a u64.
+    pub fn get_size(&self, key: &str) -> Option<u64> {
+        parse_size(self.get_str(key)?)
+    }
+}
+
+/// Iterator over the options of a command line.
+///
+/// Created by `CommandLine::options`.
+pub struct CommandLineOptionIter<'a> {
+    remaining: &'a str,
+}
+
+impl<'a> Iterator for CommandLineOptionIter<'a> {
+    type Item = CommandLineOption<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let trimmed = self.remaining.trim_start();
+
+        if trimmed.is_empty() {
+            self.remaining = trimmed;
+            return None;
+        }
+
+        // The option ends at the first whitespace outside of double quotes.
+        let mut inside_quotes = false;
+        let mut option_length = trimmed.len();
+
+        for (index, character) in trimmed.char_indices() {
+            if character == '"' {
+                inside_quotes = !inside_quotes;
+            } else if character.is_whitespace() && !inside_quotes {
+                option_length = index;
+                break;
+            }
+        }
+
+        let option_text = &trimmed[..option_length];
+        self.remaining = &trimmed[option_length..];
+
+        let option = match option_text.split_once('=') {
+            Some((key, value)) => CommandLineOption {
+                key,
+                value: Some(strip_quotes(value)),
+            },
+            None => CommandLineOption {
+                key: option_text,
+                value: None,
+            },
+        };
+
+        Some(option)
+    }
+}
+
+/// Removes a pair of double quotes that surround the entire value.
+fn strip_quotes(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Parses a decimal, `0x` hexadecimal, or `0b` binary number.
+fn parse_u64(text: &str) -> Option<u64> {
+    let (digits, radix) =
+        if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            (digits, 16)
+        } else if let Some(digits) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+            (digits, 2)
+        } else {
+            (text, 10)
+        };
+
+    if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+        return None;
+    }
+
+    let mut value: u64 = 0;
+    for character in digits.chars().filter(|&character| character != '_') {
+        let digit = character.to_digit(radix)?;
+
+        value = value.checked_mul(radix as u64)?.checked_add(digit as u64)?;
+    }
+
+    Some(value)
+}
+
+/// Parses a number with an optional binary size suffix.
+fn parse_size(text: &str) -> Option<u64> {
+    let shift = match text.chars().last()? {
+        'k' | 'K' => 10,
+        'm' | 'M' => 20,
+        'g' | 'G' => 30,
+        't' | 'T' => 40,
+        _ => 0,
+    };
+
+    let number_text = if shift == 0 {
+        text
+    } else {
+        &text[..text.len() - 1]
+    };
+
+    let number = parse_u64(number_text)?;
+
+    number.checked_mul(1 << shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_options_flags_and_values() {
+        let command_line = CommandLine::new("  console=ttyS0 quiet  loglevel=7 ");
+        let options: Vec<_> = command_line.options().collect();
+
+        assert_eq!(
+            options,
+            [
+                CommandLineOption {
+                    key: "console",
+                    value: Some("ttyS0"),
+                },
+                CommandLineOption {
+                    key: "quiet",
+                    value: None,
+                },
+                CommandLineOption {
+                    key: "loglevel",
+                    value: Some("7"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_options_empty() {
+        assert_eq!(CommandLine::new("").options().count(), 0);
+        assert_eq!(CommandLine::new("   \t ").options().count(), 0);
+    }
+
+    #[test]
+    fn test_options_quoted_value() {
+        let command_line = CommandLine::new(r#"init="/bin/sh -x" root=/dev/vda"#);
+
+        assert_eq!(command_line.get_str("init"), Some("/bin/sh -x"));
+        assert_eq!(command_line.get_str("root"), Some("/dev/vda"));
+    }
+
+    #[test]
+    fn test_options_value_containing_equals() {
+        let command_line = CommandLine::new("options=a=b");
+
+        assert_eq!(command_line.get_str("options"), Some("a=b"));
+    }
+
+    #[test]
+    fn test_get_last_occurrence_wins() {
+        let command_line = CommandLine::new("loglevel=3 loglevel=7");
+
+        assert_eq!(command_line.get_u64("loglevel"), Some(7));
+    }
+
+    #[test]
+    fn test_get_str_missing_or_flag() {
+        let command_line = CommandLine::new("quiet");
+
+        assert_eq!(command_line.get_str("quiet"), None);
+        assert_eq!(command_line.get_str("missing"), None);
+        assert!(command_line.contains("quiet"));
+        assert!(!command_line.contains("missing"));
+    }
+
+    #[test]
+    fn test_get_bool() {
+        let command_line = CommandLine::new("a b=1 c=off d=YES e=maybe f=False");
+
+        assert_eq!(command_line.get_bool("a"), Some(true));
+        assert_eq!(command_line.get_bool("b"), Some(true));
+        assert_eq!(command_line.get_bool("c"), Some(false));
+        assert_eq!(command_line.get_bool("d"), Some(true));
+        assert_eq!(command_line.get_bool("e"), None);
+        assert_eq!(command_line.get_bool("f"), Some(false));
+        assert_eq!(command_line.get_bool("missing"), None);
+    }
+
+    #[test]
+    fn test_get_u64() {
+        let command_line =
+            CommandLine::new("a=42 b=0x1F c=0b101 d=1_000 e=abc f=0x g=18446744073709551616 h");
+
+        assert_eq!(command_line.get_u64("a"), Some(42));
+        assert_eq!(command_line.get_u64("b"), Some(0x1F));
+        assert_eq!(command_line.get_u64("c"), Some(0b101));
+        assert_eq!(command_line.get_u64("d"), Some(1000));
+        assert_eq!(command_line.get_u64("e"), None);
+        assert_eq!(command_line.get_u64("f"), None);
+        assert_eq!(command_line.get_u64("g"), None);
+        assert_eq!(command_line.get_u64("h"), None);
+    }
+
+    #[test]
+    fn test_get_size() {
+        let command_line = CommandLine::new("a=4096 b=512M c=2g d=0x10K e=16Q f=K g=17179869184G");
+
+        assert_eq!(command_line.get_size("a"), Some(4096));
+        assert_eq!(command_line.get_size("b"), Some(512 * 1024 * 1024));
+        assert_eq!(command_line.get_size("c"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(command_line.get_size("d"), Some(0x10 * 1024));
+        assert_eq!(command_line.get_size("e"), None);
+        assert_eq!(command_line.get_size("f"), None);
+        assert_eq!(command_line.get_size("g"), None);
+    }
+}
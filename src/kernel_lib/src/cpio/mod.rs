@@ -0,0 +1,479 @@
+//! CPIO archives in the "newc" format.
+//!
+//! This is the format of Linux initial ramdisks, as written by
+//! `cpio -H newc`. Every entry is a 110 byte header of ASCII hexadecimal
+//! fields, followed by the path and the contents, each padded to a multiple of
+//! 4 bytes. The archive ends with an entry named "TRAILER!!!".
+//!
+//! `CpioArchive` works on a byte slice holding the whole archive without
+//! allocating. Paths are compared without a leading "/" or "./", so "init",
+//! "./init", and "/init" all name the same entry. Directories need not have
+//! entries of their own: any prefix of an entry's path is a directory too.
+
+use core::fmt;
+
+/// The magic number of "newc" headers, and of "crc" headers, which only add a
+/// checksum of the contents.
+const MAGIC_NEWC: &[u8; 6] = b"070701";
+const MAGIC_CRC: &[u8; 6] = b"070702";
+
+/// The size in bytes of an entry header.
+const HEADER_SIZE: usize = 110;
+
+/// The alignment of paths and contents.
+const ALIGNMENT: usize = 4;
+
+/// The path of the entry that ends the archive.
+const TRAILER_PATH: &str = "TRAILER!!!";
+
+/// The offsets of the header fields used, each 8 hexadecimal digits.
+const MODE_OFFSET: usize = 14;
+const FILE_SIZE_OFFSET: usize = 54;
+const PATH_SIZE_OFFSET: usize = 94;
+
+/// The bits of the mode that hold the type of the entry.
+const MODE_TYPE_MASK: u32 = 0o170000;
+
+/// The mode types of directories and regular files.
+const MODE_DIRECTORY: u32 = 0o040000;
+const MODE_REGULAR_FILE: u32 = 0o100000;
+
+/// The reasons an archive could not be read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CpioError {
+    /// An entry does not start with the "newc" magic number. Holds the offset
+    /// of the entry.
+    BadMagic(usize),
+
+    /// A header field is not hexadecimal. Holds the offset of the entry.
+    BadHeader(usize),
+
+    /// An entry extends past the end of the archive. Holds the offset of the
+    /// entry.
+    Truncated(usize),
+
+    /// A path is not UTF-8 or not terminated. Holds the offset of the entry.
+    BadPath(usize),
+}
+
+impl fmt::Display for CpioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic(offset) => write!(f, "bad magic at offset {:#x}", offset),
+            Self::BadHeader(offset) => write!(f, "bad header at offset {:#x}", offset),
+            Self::Truncated(offset) => write!(f, "entry at offset {:#x} is truncated", offset),
+            Self::BadPath(offset) => write!(f, "bad path at offset {:#x}", offset),
+        }
+    }
+}
+
+/// The type of an entry.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+
+    /// Anything else, such as a symbolic link or a device node.
+    Other,
+}
+
+/// An entry of an archive.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CpioEntry<'a> {
+    /// The path, without a leading "/" or "./". The root directory has an
+    /// empty path.
+    pub path: &'a str,
+
+    pub kind: EntryKind,
+
+    /// The contents. Empty for directories.
+    pub data: &'a [u8],
+
+    /// The offset of the entry's header in the archive, which identifies the
+    /// entry.
+    pub offset: usize,
+}
+
+impl<'a> CpioEntry<'a> {
+    /// Returns the last component of the path.
+    pub fn name(&self) -> &'a str {
+        self.path.rsplit('/').next().unwrap_or(self.path)
+    }
+}
+
+/// A validated CPIO archive.
+#[derive(Debug, Copy, Clone)]
+pub struct CpioArchive<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> CpioArchive<'a> {
+    /// Validates every entry of an archive.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The archive. Anything after the trailer, such as padding, is
+    ///   ignored.
+    pub fn parse(data: &'a [u8]) -> Result<Self, CpioError> {
+        let archive = Self { data };
+
+        let mut offset = 0;
+        while let Some((_, next_offset)) = archive.entry_at(offset)? {
+            offset = next_offset;
+        }
+
+        Ok(archive)
+    }
+
+    /// Returns the entries in archive order, without the trailer.
+    pub fn entries(&self) -> impl Iterator<Item = CpioEntry<'a>> + 'a {
+        let archive = *self;
+        let mut offset = Some(0);
+
+        core::iter::from_fn(move || {
+            // The archive was validated by `parse`.
+            let (entry, next_offset) = archive.entry_at(offset?).ok().flatten()?;
+
+            offset = Some(next_offset);
+            Some(entry)
+        })
+    }
+
+    /// Returns the entry at a header offset, as returned in `CpioEntry::offset`.
+    pub fn entry(&self, offset: usize) -> Option<CpioEntry<'a>> {
+        self.entry_at(offset).ok().flatten().map(|(entry, _)| entry)
+    }
+
+    /// Looks up an entry by path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path. Leading and trailing '/' and empty components are
+    ///   ignored.
+    ///
+    /// # Returns
+    ///
+    /// The entry. A directory without an entry of its own gets a made up
+    /// entry with the path of the directory and the offset of the first
+    /// entry within it, or 0 for the root directory. `None` if nothing has
+    /// the path.
+    pub fn find(&self, path: &str) -> Option<CpioEntry<'a>> {
+        let path = path.trim_matches('/');
+
+        if let Some(entry) = self.entries().find(|entry| paths_equal(entry.path, path)) {
+            return Some(entry);
+        }
+
+        if path.is_empty() {
+            return Some(implicit_directory("", 0));
+        }
+
+        self.entries()
+            .find(|entry| strip_directory(path, entry.path).is_some())
+            .map(|entry| implicit_directory(&entry.path[..path.len()], entry.offset))
+    }
+
+    /// Calls a function for every entry directly within a directory, including
+    /// directories that have no entries of their own, once each.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The path of the directory, as for `find`.
+    /// * `callback` - Called with the name of each entry and the entry, which
+    ///   is made up like in `find` for directories without an entry.
+    pub fn for_each_child(
+        &self,
+        directory: &str,
+        mut callback: impl FnMut(&'a str, CpioEntry<'a>),
+    ) {
+        let directory = directory.trim_matches('/');
+
+        for (index, entry) in self.entries().enumerate() {
+            let Some(relative) = strip_directory(directory, entry.path) else {
+                continue;
+            };
+
+            match relative.split_once('/') {
+                None => callback(relative, entry),
+                Some((name, _)) => {
+                    // Only the first entry below a directory without an entry
+                    // of its own stands for it.
+                    let is_first = !self.entries().take(index).any(|earlier| {
+                        strip_directory(directory, earlier.path).is_some_and(|earlier_relative| {
+                            earlier_relative == name
+                                || earlier_relative
+                                    .split_once('/')
+                                    .is_some_and(|(earlier_name, _)| earlier_name == name)
+                        })
+                    });
+
+                    let has_entry = self
+                        .entries()
+                        .skip(index)
+                        .any(|later| strip_directory(directory, later.path) == Some(name));
+
+                    if is_first && !has_entry {
+                        let path_length = entry.path.len() - relative.len() + name.len();
+
+                        callback(
+                            name,
+                            implicit_directory(&entry.path[..path_length], entry.offset),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the entry at an offset.
+    ///
+    /// # Returns
+    ///
+    /// The entry and the offset of the next one, or `None` at the trailer or
+    /// the end of the data.
+    fn entry_at(&self, offset: usize) -> Result<Option<(CpioEntry<'a>, usize)>, CpioError> {
+        if offset >= self.data.len() {
+            return Ok(None);
+        }
+
+        let header = self
+            .data
+            .get(offset..offset + HEADER_SIZE)
+            .ok_or(CpioError::Truncated(offset))?;
+
+        if &header[..6] != MAGIC_NEWC && &header[..6] != MAGIC_CRC {
+            return Err(CpioError::BadMagic(offset));
+        }
+
+        let field = |field_offset: usize| {
+            core::str::from_utf8(&header[field_offset..field_offset + 8])
+                .ok()
+                .and_then(|text| u32::from_str_radix(text, 16).ok())
+                .ok_or(CpioError::BadHeader(offset))
+        };
+
+        let mode = field(MODE_OFFSET)?;
+        let file_size = field(FILE_SIZE_OFFSET)? as usize;
+        let path_size = field(PATH_SIZE_OFFSET)? as usize;
+
+        let path_start = offset + HEADER_SIZE;
+        let path_bytes = self
+            .data
+            .get(path_start..path_start + path_size)
+            .ok_or(CpioError::Truncated(offset))?;
+
+        // The size counts the terminating 0.
+        let path = match path_bytes.split_last() {
+            Some((0, path)) => {
+                core::str::from_utf8(path).map_err(|_| CpioError::BadPath(offset))?
+            }
+            _ => return Err(CpioError::BadPath(offset)),
+        };
+
+        if path == TRAILER_PATH {
+            return Ok(None);
+        }
+
+        let data_start = (path_start + path_size).next_multiple_of(ALIGNMENT);
+        let data = self
+            .data
+            .get(data_start..data_start + file_size)
+            .ok_or(CpioError::Truncated(offset))?;
+
+        let kind = match mode & MODE_TYPE_MASK {
+            MODE_DIRECTORY => EntryKind::Directory,
+            MODE_REGULAR_FILE => EntryKind::File,
+            _ => EntryKind::Other,
+        };
+
+        let entry = CpioEntry {
+            path: normalize(path),
+            kind,
+            data,
+            offset,
+        };
+
+        Ok(Some((
+            entry,
+            (data_start + file_size).next_multiple_of(ALIGNMENT),
+        )))
+    }
+}
+
+/// Makes up the entry of a directory that has no entry of its own.
+///
+/// # Arguments
+///
+/// * `path` - The path of the directory.
+/// * `offset` - The offset of the first entry within the directory.
+fn implicit_directory(path: &str, offset: usize) -> CpioEntry<'_> {
+    CpioEntry {
+        path,
+        kind: EntryKind::Directory,
+        data: &[],
+        offset,
+    }
+}
+
+/// Strips a leading "./" or "/" from a path, and a lone ".", which is the root
+/// directory.
+fn normalize(path: &str) -> &str {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    let path = path.trim_start_matches('/');
+
+    if path == "." { "" } else { path }
+}
+
+/// Returns true if two paths name the same entry, ignoring empty components.
+fn paths_equal(left: &str, right: &str) -> bool {
+    left.split('/')
+        .filter(|component| !component.is_empty())
+        .eq(right.split('/').filter(|component| !component.is_empty()))
+}
+
+/// Returns a path relative to a directory.
+///
+/// # Returns
+///
+/// The rest of the path, or `None` if the path is not within the directory.
+fn strip_directory<'a>(directory: &str, path: &'a str) -> Option<&'a str> {
+    if directory.is_empty() {
+        return (!path.is_empty()).then_some(path);
+    }
+
+    path.strip_prefix(directory)?
+        .strip_prefix('/')
+        .filter(|relative| !relative.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Appends an entry to an archive being built.
+    fn push_entry(archive: &mut Vec<u8>, path: &str, mode: u32, data: &[u8]) {
+        let fields = [
+            0,
+            mode,
+            0,
+            0,
+            1,
+            0,
+            data.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            path.len() as u32 + 1,
+            0,
+        ];
+
+        archive.extend_from_slice(MAGIC_NEWC);
+        for field in fields {
+            archive.extend_from_slice(format!("{:08X}", field).as_bytes());
+        }
+
+        archive.extend_from_slice(path.as_bytes());
+        archive.push(0);
+        archive.resize(archive.len().next_multiple_of(ALIGNMENT), 0);
+
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(ALIGNMENT), 0);
+    }
+
+    fn sample_archive() -> Vec<u8> {
+        let mut archive = Vec::new();
+
+        push_entry(&mut archive, ".", MODE_DIRECTORY | 0o755, b"");
+        push_entry(
+            &mut archive,
+            "./init",
+            MODE_REGULAR_FILE | 0o755,
+            b"\x7FELF init",
+        );
+        push_entry(&mut archive, "bin", MODE_DIRECTORY | 0o755, b"");
+        push_entry(&mut archive, "bin/sh", MODE_REGULAR_FILE | 0o755, b"shell");
+        push_entry(&mut archive, "etc/motd", MODE_REGULAR_FILE | 0o644, b"Hi!");
+        push_entry(&mut archive, "etc/rc/boot", MODE_REGULAR_FILE | 0o644, b"");
+        push_entry(&mut archive, TRAILER_PATH, 0, b"");
+
+        // Archives are usually padded to a block size.
+        archive.resize(archive.len() + 512, 0);
+        archive
+    }
+
+    #[test]
+    fn test_parse_reads_every_entry() {
+        let data = sample_archive();
+        let archive = CpioArchive::parse(&data).unwrap();
+
+        let paths: Vec<_> = archive.entries().map(|entry| entry.path).collect();
+        assert_eq!(
+            paths,
+            ["", "init", "bin", "bin/sh", "etc/motd", "etc/rc/boot"]
+        );
+
+        let init = archive.find("/init").unwrap();
+        assert_eq!(init.kind, EntryKind::File);
+        assert_eq!(init.data, b"\x7FELF init");
+        assert_eq!(archive.entry(init.offset), Some(init));
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_archives() {
+        let mut data = sample_archive();
+
+        data[0] = b'1';
+        assert_eq!(
+            CpioArchive::parse(&data).err(),
+            Some(CpioError::BadMagic(0))
+        );
+
+        let data = sample_archive();
+        assert_eq!(
+            CpioArchive::parse(&data[..200]).err(),
+            Some(CpioError::Truncated(0x70))
+        );
+    }
+
+    #[test]
+    fn test_find_makes_up_implicit_directories() {
+        let data = sample_archive();
+        let archive = CpioArchive::parse(&data).unwrap();
+
+        let motd = archive.find("etc/motd").unwrap();
+        let etc = archive.find("etc").unwrap();
+        assert_eq!(etc.kind, EntryKind::Directory);
+        assert_eq!((etc.path, etc.offset), ("etc", motd.offset));
+
+        let rc = archive.find("/etc/rc/").unwrap();
+        assert_eq!(rc.kind, EntryKind::Directory);
+        assert_eq!(rc.path, "etc/rc");
+
+        assert_eq!(archive.find("").unwrap().kind, EntryKind::Directory);
+        assert_eq!(archive.find("bin//sh").unwrap().data, b"shell");
+        assert_eq!(archive.find("etc/missing"), None);
+        assert_eq!(archive.find("et"), None);
+    }
+
+    #[test]
+    fn test_for_each_child_lists_each_name_once() {
+        let data = sample_archive();
+        let archive = CpioArchive::parse(&data).unwrap();
+
+        let mut names = Vec::new();
+        archive.for_each_child("/", |name, entry| names.push((name, entry.kind)));
+        assert_eq!(
+            names,
+            [
+                ("init", EntryKind::File),
+                ("bin", EntryKind::Directory),
+                ("etc", EntryKind::Directory)
+            ]
+        );
+
+        let mut names = Vec::new();
+        archive.for_each_child("etc", |name, entry| names.push((name, entry.path)));
+        assert_eq!(names, [("motd", "etc/motd"), ("rc", "etc/rc")]);
+    }
+}
@@ -0,0 +1,1243 @@
+//! Read-only FAT32 file systems.
+//!
+//! `Fat32::mount` finds the file system either at the start of the device or
+//! in the first FAT32 partition of an MBR partition table, and validates its
+//! boot sector. Directories are read with `Fat32::entries`, which joins long
+//! file name entries to the short entries they belong to, and files with
+//! `Fat32::read`. Nothing is cached and nothing is allocated: every lookup
+//! reads the sectors it needs through the `SectorReader` the file system was
+//! mounted on.
+//!
+//! Only 512 byte sectors are supported.
+
+use core::{char, fmt};
+
+/// The size in bytes of a sector.
+pub const SECTOR_SIZE: usize = 512;
+
+/// The most UTF-16 code units a long file name holds.
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// The size in bytes of a directory entry.
+const DIRECTORY_ENTRY_SIZE: usize = 32;
+
+/// The signature at the end of boot sectors and partition tables.
+const BOOT_SIGNATURE: u16 = 0xAA55;
+
+/// The offset of the boot signature in a sector.
+const BOOT_SIGNATURE_OFFSET: usize = 510;
+
+/// The offset of the first entry of an MBR partition table.
+const PARTITION_TABLE_OFFSET: usize = 446;
+
+/// The number of entries of an MBR partition table.
+const PARTITION_COUNT: usize = 4;
+
+/// The size in bytes of an MBR partition table entry.
+const PARTITION_ENTRY_SIZE: usize = 16;
+
+/// The MBR partition types of FAT32 partitions, addressed by CHS and by LBA.
+const PARTITION_TYPES_FAT32: [u8; 2] = [0x0B, 0x0C];
+
+/// The bits of a FAT entry that hold the next cluster.
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+
+/// FAT entries from this value up end a cluster chain.
+const END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+
+/// The number of the first cluster of the data region.
+const FIRST_DATA_CLUSTER: u32 = 2;
+
+/// The first name byte of a deleted entry.
+const DELETED_ENTRY: u8 = 0xE5;
+
+/// The first name byte of a short name that starts with 0xE5, which would
+/// otherwise mark a deleted entry.
+const ESCAPED_DELETED_ENTRY: u8 = 0x05;
+
+/// The directory entry attribute bits.
+const ATTRIBUTE_VOLUME_ID: u8 = 0x08;
+const ATTRIBUTE_DIRECTORY: u8 = 0x10;
+
+/// The attributes of a long file name entry.
+const ATTRIBUTE_LONG_NAME: u8 = 0x0F;
+
+/// The sequence number bit of the last long file name entry of a name, which
+/// comes first in the directory.
+const LAST_LONG_NAME_ENTRY: u8 = 0x40;
+
+/// The bits of a long file name entry's sequence number that hold its
+/// ordinal, starting at 1.
+const LONG_NAME_ORDINAL_MASK: u8 = 0x1F;
+
+/// The number of UTF-16 code units of a long file name entry.
+const LONG_NAME_UNITS_PER_ENTRY: usize = 13;
+
+/// The offsets of the UTF-16 code units in a long file name entry.
+const LONG_NAME_UNIT_OFFSETS: [usize; LONG_NAME_UNITS_PER_ENTRY] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// The short entry flags marking a base name and an extension stored in lower
+/// case.
+const LOWER_CASE_BASE: u8 = 0x08;
+const LOWER_CASE_EXTENSION: u8 = 0x10;
+
+/// Reads sectors from the device a file system is on.
+pub trait SectorReader {
+    type Error;
+
+    /// Reads consecutive sectors.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_sector` - The first sector to read.
+    /// * `buffer` - Receives the sectors. Its length is a whole number of
+    ///   sectors.
+    fn read_sectors(&self, first_sector: usize, buffer: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+/// The reasons a file system could not be mounted or read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fat32Error<E> {
+    /// The device could not be read.
+    Io(E),
+
+    /// Neither the start of the device nor a partition holds a FAT32 boot
+    /// sector.
+    NotFat32,
+
+    /// The sectors are not `SECTOR_SIZE` bytes. Holds the size found.
+    UnsupportedSectorSize(u16),
+
+    /// A cluster chain or directory entry points outside the file system, or
+    /// a chain is shorter than its file.
+    Corrupt,
+
+    /// No entry has the name.
+    NotFound,
+
+    /// A path component or the entry to list is not a directory.
+    NotADirectory,
+
+    /// The entry to read is a directory.
+    IsADirectory,
+}
+
+impl<E: fmt::Display> fmt::Display for Fat32Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::NotFat32 => write!(f, "no FAT32 file system"),
+            Self::UnsupportedSectorSize(size) => write!(f, "unsupported sector size {}", size),
+            Self::Corrupt => write!(f, "the file system is corrupt"),
+            Self::NotFound => write!(f, "not found"),
+            Self::NotADirectory => write!(f, "not a directory"),
+            Self::IsADirectory => write!(f, "is a directory"),
+        }
+    }
+}
+
+/// A file or directory.
+#[derive(Debug, Clone)]
+pub struct DirectoryEntry {
+    /// The UTF-16 code units of the name.
+    name: [u16; MAX_NAME_LENGTH],
+    name_length: usize,
+
+    attributes: u8,
+
+    /// The first cluster of the contents, or 0 for an empty file.
+    first_cluster: u32,
+
+    /// The size of a file in bytes. Always 0 for directories.
+    size: u32,
+}
+
+impl DirectoryEntry {
+    /// Returns the name, which displays as text.
+    pub fn name(&self) -> EntryName<'_> {
+        EntryName(&self.name[..self.name_length])
+    }
+
+    /// Returns true if the name equals a name, ignoring the case of ASCII
+    /// letters like FAT does.
+    pub fn name_matches(&self, name: &str) -> bool {
+        let mut units = name.encode_utf16();
+
+        self.name[..self.name_length].iter().all(|&unit| {
+            units
+                .next()
+                .is_some_and(|other| fold_case(unit) == fold_case(other))
+        }) && units.next().is_none()
+    }
+
+    /// Returns true if the entry is a directory.
+    pub fn is_directory(&self) -> bool {
+        self.attributes & ATTRIBUTE_DIRECTORY != 0
+    }
+
+    /// Returns the size of a file in bytes, or 0 for a directory.
+    pub fn size(&self) -> usize {
+        self.size as usize
+    }
+
+    /// Returns the first cluster of the contents, which `Fat32::entry_at`
+    /// takes to find the entry again. 0 for an empty file.
+    pub fn first_cluster(&self) -> u32 {
+        self.first_cluster
+    }
+}
+
+/// The name of a `DirectoryEntry`.
+#[derive(Debug, Copy, Clone)]
+pub struct EntryName<'a>(&'a [u16]);
+
+impl fmt::Display for EntryName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for character in char::decode_utf16(self.0.iter().copied()) {
+            write!(f, "{}", character.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A mounted FAT32 file system.
+#[derive(Debug, Copy, Clone)]
+pub struct Fat32<R> {
+    reader: R,
+
+    /// The first sector of the first FAT.
+    fat_start_sector: usize,
+
+    /// The first sector of cluster 2.
+    data_start_sector: usize,
+
+    sectors_per_cluster: usize,
+
+    /// The number of clusters of the data region.
+    cluster_count: u32,
+
+    /// The first cluster of the root directory.
+    root_cluster: u32,
+}
+
+impl<R: SectorReader> Fat32<R> {
+    /// Mounts the file system at the start of a device, or else in the first
+    /// FAT32 partition of its MBR partition table.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reads the sectors of the device.
+    pub fn mount(reader: R) -> Result<Self, Fat32Error<R::Error>> {
+        let mut sector = [0u8; SECTOR_SIZE];
+        reader
+            .read_sectors(0, &mut sector)
+            .map_err(Fat32Error::Io)?;
+
+        match Self::from_boot_sector(reader, 0, &sector) {
+            Err((reader, Fat32Error::NotFat32)) => {
+                let partition_start = find_fat32_partition(&sector).ok_or(Fat32Error::NotFat32)?;
+
+                let mut boot_sector = [0u8; SECTOR_SIZE];
+                reader
+                    .read_sectors(partition_start, &mut boot_sector)
+                    .map_err(Fat32Error::Io)?;
+
+                Self::from_boot_sector(reader, partition_start, &boot_sector)
+                    .map_err(|(_, error)| error)
+            }
+            result => result.map_err(|(_, error)| error),
+        }
+    }
+
+    /// Returns the size of a cluster in bytes.
+    pub fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster * SECTOR_SIZE
+    }
+
+    /// Returns the number of clusters of the data region.
+    pub fn cluster_count(&self) -> usize {
+        self.cluster_count as usize
+    }
+
+    /// Returns the entry of the root directory, which has an empty name.
+    pub fn root(&self) -> DirectoryEntry {
+        self.entry_at(self.root_cluster, true, 0)
+    }
+
+    /// Returns an unnamed entry for contents that start at a cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_cluster` - The first cluster, from
+    ///   `DirectoryEntry::first_cluster`.
+    /// * `is_directory` - True if the contents are a directory.
+    /// * `size` - The size of a file in bytes. Ignored for a directory.
+    pub fn entry_at(&self, first_cluster: u32, is_directory: bool, size: usize) -> DirectoryEntry {
+        DirectoryEntry {
+            name: [0; MAX_NAME_LENGTH],
+            name_length: 0,
+            attributes: if is_directory { ATTRIBUTE_DIRECTORY } else { 0 },
+            first_cluster,
+            size: if is_directory { 0 } else { size as u32 },
+        }
+    }
+
+    /// Looks up a file or directory by path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The names of the directories leading to the entry and of
+    ///   the entry, separated by '/'. A leading '/' and empty names are
+    ///   ignored, so "" and "/" are the root directory.
+    pub fn open(&self, path: &str) -> Result<DirectoryEntry, Fat32Error<R::Error>> {
+        let mut entry = self.root();
+
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let mut found = None;
+
+            for child in self.entries(&entry)? {
+                let child = child?;
+
+                if child.name_matches(name) {
+                    found = Some(child);
+                    break;
+                }
+            }
+
+            entry = found.ok_or(Fat32Error::NotFound)?;
+        }
+
+        Ok(entry)
+    }
+
+    /// Lists the entries of a directory, other than "." and "..".
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory.
+    pub fn entries<'a>(
+        &'a self,
+        directory: &DirectoryEntry,
+    ) -> Result<DirectoryIterator<'a, R>, Fat32Error<R::Error>> {
+        if !directory.is_directory() {
+            return Err(Fat32Error::NotADirectory);
+        }
+
+        Ok(DirectoryIterator {
+            file_system: self,
+            cluster: directory.first_cluster,
+            visited_cluster_count: 0,
+            sector_index: 0,
+            entry_index: 0,
+            sector: [0; SECTOR_SIZE],
+            is_sector_loaded: false,
+            long_name: LongName::new(),
+            is_finished: false,
+        })
+    }
+
+    /// Reads part of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file.
+    /// * `offset` - The offset in the file to read from.
+    /// * `buffer` - Receives the bytes.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read, which is less than the length of `buffer`
+    /// only at the end of the file.
+    pub fn read(
+        &self,
+        file: &DirectoryEntry,
+        offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<usize, Fat32Error<R::Error>> {
+        if file.is_directory() {
+            return Err(Fat32Error::IsADirectory);
+        }
+
+        let length = buffer.len().min(file.size().saturating_sub(offset));
+        if length == 0 {
+            return Ok(0);
+        }
+
+        let cluster_size = self.cluster_size();
+
+        let mut cluster = file.first_cluster;
+        for _ in 0..offset / cluster_size {
+            cluster = self.next_cluster(cluster)?.ok_or(Fat32Error::Corrupt)?;
+        }
+
+        let mut done = 0;
+        let mut offset_in_cluster = offset % cluster_size;
+
+        loop {
+            let first_sector = self.cluster_sector(cluster)?;
+            let chunk_length = (length - done).min(cluster_size - offset_in_cluster);
+
+            self.read_bytes(
+                first_sector,
+                offset_in_cluster,
+                &mut buffer[done..done + chunk_length],
+            )?;
+
+            done += chunk_length;
+            offset_in_cluster = 0;
+
+            if done == length {
+                return Ok(done);
+            }
+
+            cluster = self.next_cluster(cluster)?.ok_or(Fat32Error::Corrupt)?;
+        }
+    }
+
+    /// Validates a boot sector and mounts the file system it describes.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reads the sectors of the device.
+    /// * `start_sector` - The sector of the boot sector on the device.
+    /// * `sector` - The boot sector.
+    ///
+    /// # Returns
+    ///
+    /// The file system, or the reader back with the reason the sector was
+    /// rejected.
+    fn from_boot_sector(
+        reader: R,
+        start_sector: usize,
+        sector: &[u8; SECTOR_SIZE],
+    ) -> Result<Self, (R, Fat32Error<R::Error>)> {
+        let bytes_per_sector = read_u16(sector, 11);
+        let sectors_per_cluster = sector[13] as usize;
+        let reserved_sector_count = read_u16(sector, 14) as usize;
+        let fat_count = sector[16] as usize;
+        let root_entry_count = read_u16(sector, 17);
+        let total_sectors_16 = read_u16(sector, 19);
+        let sectors_per_fat_16 = read_u16(sector, 22);
+        let total_sectors_32 = read_u32(sector, 32);
+        let sectors_per_fat = read_u32(sector, 36) as usize;
+        let root_cluster = read_u32(sector, 44);
+
+        // FAT12 and FAT16 have a fixed root directory and a 16-bit FAT size,
+        // and FAT32 has neither.
+        let is_fat32 = read_u16(sector, BOOT_SIGNATURE_OFFSET) == BOOT_SIGNATURE
+            && sectors_per_cluster.is_power_of_two()
+            && reserved_sector_count != 0
+            && fat_count != 0
+            && root_entry_count == 0
+            && sectors_per_fat_16 == 0
+            && sectors_per_fat != 0
+            && root_cluster >= FIRST_DATA_CLUSTER;
+
+        if !is_fat32 {
+            return Err((reader, Fat32Error::NotFat32));
+        }
+
+        if bytes_per_sector as usize != SECTOR_SIZE {
+            return Err((reader, Fat32Error::UnsupportedSectorSize(bytes_per_sector)));
+        }
+
+        let total_sectors = if total_sectors_16 != 0 {
+            total_sectors_16 as usize
+        } else {
+            total_sectors_32 as usize
+        };
+
+        let data_offset = reserved_sector_count + fat_count * sectors_per_fat;
+        let Some(data_sector_count) = total_sectors.checked_sub(data_offset) else {
+            return Err((reader, Fat32Error::Corrupt));
+        };
+
+        // Cluster numbers only have 28 bits.
+        let cluster_count = (data_sector_count / sectors_per_cluster)
+            .min((FAT_ENTRY_MASK - FIRST_DATA_CLUSTER) as usize) as u32;
+
+        // Every cluster must have an entry in the FAT.
+        let fat_entry_count = sectors_per_fat * SECTOR_SIZE / size_of::<u32>();
+        if (cluster_count + FIRST_DATA_CLUSTER) as usize > fat_entry_count
+            || root_cluster >= cluster_count + FIRST_DATA_CLUSTER
+        {
+            return Err((reader, Fat32Error::Corrupt));
+        }
+
+        Ok(Self {
+            reader,
+            fat_start_sector: start_sector + reserved_sector_count,
+            data_start_sector: start_sector + data_offset,
+            sectors_per_cluster,
+            cluster_count,
+            root_cluster,
+        })
+    }
+
+    /// Returns the first sector of a cluster.
+    fn cluster_sector(&self, cluster: u32) -> Result<usize, Fat32Error<R::Error>> {
+        if !self.is_valid_cluster(cluster) {
+            return Err(Fat32Error::Corrupt);
+        }
+
+        Ok(self.data_start_sector
+            + (cluster - FIRST_DATA_CLUSTER) as usize * self.sectors_per_cluster)
+    }
+
+    /// Looks up the cluster that follows a cluster in its chain.
+    ///
+    /// # Returns
+    ///
+    /// The next cluster, or `None` at the end of the chain.
+    fn next_cluster(&self, cluster: u32) -> Result<Option<u32>, Fat32Error<R::Error>> {
+        if !self.is_valid_cluster(cluster) {
+            return Err(Fat32Error::Corrupt);
+        }
+
+        let entry_offset = cluster as usize * size_of::<u32>();
+
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.reader
+            .read_sectors(
+                self.fat_start_sector + entry_offset / SECTOR_SIZE,
+                &mut sector,
+            )
+            .map_err(Fat32Error::Io)?;
+
+        let next = read_u32(&sector, entry_offset % SECTOR_SIZE) & FAT_ENTRY_MASK;
+
+        if next >= END_OF_CHAIN {
+            Ok(None)
+        } else if self.is_valid_cluster(next) {
+            Ok(Some(next))
+        } else {
+            // Free and bad clusters have no place in a chain.
+            Err(Fat32Error::Corrupt)
+        }
+    }
+
+    /// Returns true if a cluster is in the data region.
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (FIRST_DATA_CLUSTER..self.cluster_count + FIRST_DATA_CLUSTER).contains(&cluster)
+    }
+
+    /// Reads bytes that lie within consecutive sectors, reading whole sectors
+    /// straight into the buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_sector` - The sector the offset counts from.
+    /// * `offset` - The offset of the first byte from the start of
+    ///   `first_sector`.
+    /// * `buffer` - Receives the bytes.
+    fn read_bytes(
+        &self,
+        first_sector: usize,
+        offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<(), Fat32Error<R::Error>> {
+        let mut sector = first_sector + offset / SECTOR_SIZE;
+        let mut offset_in_sector = offset % SECTOR_SIZE;
+        let mut done = 0;
+
+        while done < buffer.len() {
+            let remaining = buffer.len() - done;
+
+            if offset_in_sector == 0 && remaining >= SECTOR_SIZE {
+                let whole_length = remaining - remaining % SECTOR_SIZE;
+
+                self.reader
+                    .read_sectors(sector, &mut buffer[done..done + whole_length])
+                    .map_err(Fat32Error::Io)?;
+
+                sector += whole_length / SECTOR_SIZE;
+                done += whole_length;
+            } else {
+                let mut bounce = [0u8; SECTOR_SIZE];
+                self.reader
+                    .read_sectors(sector, &mut bounce)
+                    .map_err(Fat32Error::Io)?;
+
+                let length = remaining.min(SECTOR_SIZE - offset_in_sector);
+                buffer[done..done + length]
+                    .copy_from_slice(&bounce[offset_in_sector..offset_in_sector + length]);
+
+                sector += 1;
+                done += length;
+                offset_in_sector = 0;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The entries of a directory, from `Fat32::entries`.
+pub struct DirectoryIterator<'a, R> {
+    file_system: &'a Fat32<R>,
+
+    /// The cluster being read.
+    cluster: u32,
+
+    /// The number of clusters of the directory read so far, which catches
+    /// chains that loop.
+    visited_cluster_count: u32,
+
+    /// The index of the sector being read within the cluster.
+    sector_index: usize,
+
+    /// The index of the next entry within the sector.
+    entry_index: usize,
+
+    /// The sector being read.
+    sector: [u8; SECTOR_SIZE],
+    is_sector_loaded: bool,
+
+    /// The long file name entries seen since the last short entry.
+    long_name: LongName,
+
+    is_finished: bool,
+}
+
+impl<R: SectorReader> DirectoryIterator<'_, R> {
+    /// Returns the next raw entry of the directory, loading sectors and
+    /// following the cluster chain as needed.
+    ///
+    /// # Returns
+    ///
+    /// The entry, or `None` past the last cluster.
+    fn next_raw_entry(
+        &mut self,
+    ) -> Result<Option<[u8; DIRECTORY_ENTRY_SIZE]>, Fat32Error<R::Error>> {
+        const ENTRIES_PER_SECTOR: usize = SECTOR_SIZE / DIRECTORY_ENTRY_SIZE;
+
+        if self.entry_index == ENTRIES_PER_SECTOR {
+            self.entry_index = 0;
+            self.sector_index += 1;
+            self.is_sector_loaded = false;
+
+            if self.sector_index == self.file_system.sectors_per_cluster {
+                self.sector_index = 0;
+
+                match self.file_system.next_cluster(self.cluster)? {
+                    Some(next) => self.cluster = next,
+                    None => return Ok(None),
+                }
+            }
+        }
+
+        if !self.is_sector_loaded {
+            if self.sector_index == 0 {
+                self.visited_cluster_count += 1;
+
+                if self.visited_cluster_count > self.file_system.cluster_count {
+                    return Err(Fat32Error::Corrupt);
+                }
+            }
+
+            let sector = self.file_system.cluster_sector(self.cluster)? + self.sector_index;
+            self.file_system
+                .reader
+                .read_sectors(sector, &mut self.sector)
+                .map_err(Fat32Error::Io)?;
+
+            self.is_sector_loaded = true;
+        }
+
+        let offset = self.entry_index * DIRECTORY_ENTRY_SIZE;
+        self.entry_index += 1;
+
+        Ok(Some(
+            self.sector[offset..offset + DIRECTORY_ENTRY_SIZE]
+                .try_into()
+                .unwrap(),
+        ))
+    }
+
+    /// Returns the next file or directory of the directory.
+    fn next_entry(&mut self) -> Result<Option<DirectoryEntry>, Fat32Error<R::Error>> {
+        while let Some(raw) = self.next_raw_entry()? {
+            let attributes = raw[11];
+
+            match raw[0] {
+                // No entries follow the first free one.
+                0 => return Ok(None),
+                DELETED_ENTRY => {
+                    self.long_name.reset();
+                    continue;
+                }
+                _ => {}
+            }
+
+            if attributes == ATTRIBUTE_LONG_NAME {
+                self.long_name.add(&raw);
+                continue;
+            }
+
+            let long_name = core::mem::replace(&mut self.long_name, LongName::new());
+
+            if attributes & ATTRIBUTE_VOLUME_ID != 0 || raw[0] == b'.' {
+                continue;
+            }
+
+            let mut entry = DirectoryEntry {
+                name: [0; MAX_NAME_LENGTH],
+                name_length: 0,
+                attributes,
+                first_cluster: ((read_u16(&raw, 20) as u32) << 16 | read_u16(&raw, 26) as u32)
+                    & FAT_ENTRY_MASK,
+                size: read_u32(&raw, 28),
+            };
+
+            if entry.is_directory() {
+                entry.size = 0;
+            }
+
+            if !long_name.complete_name(&raw, &mut entry) {
+                short_name(&raw, &mut entry);
+            }
+
+            return Ok(Some(entry));
+        }
+
+        Ok(None)
+    }
+}
+
+impl<R: SectorReader> Iterator for DirectoryIterator<'_, R> {
+    type Item = Result<DirectoryEntry, Fat32Error<R::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+
+        let result = self.next_entry().transpose();
+
+        // Errors end the directory too, as reading on would likely fail again.
+        if !matches!(result, Some(Ok(_))) {
+            self.is_finished = true;
+        }
+
+        result
+    }
+}
+
+/// A long file name being assembled from its entries, which come in reverse
+/// order before the short entry they belong to.
+#[derive(Debug, Clone)]
+struct LongName {
+    units: [u16; LONG_NAME_UNITS_PER_ENTRY * 20],
+
+    /// The ordinal the next entry must have, or 0 if no name is being
+    /// assembled or every entry has been seen.
+    next_ordinal: u8,
+
+    /// The number of entries of the name, or 0 if no name is being assembled.
+    entry_count: u8,
+
+    /// The checksum of the short name the entries belong to.
+    checksum: u8,
+}
+
+impl LongName {
+    fn new() -> Self {
+        Self {
+            units: [0; LONG_NAME_UNITS_PER_ENTRY * 20],
+            next_ordinal: 0,
+            entry_count: 0,
+            checksum: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.next_ordinal = 0;
+        self.entry_count = 0;
+    }
+
+    /// Adds a long file name entry. Entries out of order discard the name.
+    fn add(&mut self, raw: &[u8; DIRECTORY_ENTRY_SIZE]) {
+        let sequence = raw[0];
+        let ordinal = sequence & LONG_NAME_ORDINAL_MASK;
+        let checksum = raw[13];
+
+        if sequence & LAST_LONG_NAME_ENTRY != 0 {
+            if ordinal == 0 || ordinal as usize * LONG_NAME_UNITS_PER_ENTRY > self.units.len() {
+                self.reset();
+                return;
+            }
+
+            self.entry_count = ordinal;
+            self.checksum = checksum;
+        } else if self.entry_count == 0 || ordinal != self.next_ordinal || checksum != self.checksum
+        {
+            self.reset();
+            return;
+        }
+
+        let start = (ordinal as usize - 1) * LONG_NAME_UNITS_PER_ENTRY;
+        for (index, &offset) in LONG_NAME_UNIT_OFFSETS.iter().enumerate() {
+            self.units[start + index] = read_u16(raw, offset);
+        }
+
+        self.next_ordinal = ordinal - 1;
+    }
+
+    /// Gives an entry the assembled name if it belongs to the entry's short
+    /// name.
+    ///
+    /// # Returns
+    ///
+    /// True if the entry got the name.
+    fn complete_name(&self, raw: &[u8; DIRECTORY_ENTRY_SIZE], entry: &mut DirectoryEntry) -> bool {
+        if self.entry_count == 0 || self.next_ordinal != 0 || self.checksum != checksum(raw) {
+            return false;
+        }
+
+        // The name ends at a 0 unit, unless it fills its entries exactly.
+        let units = &self.units[..self.entry_count as usize * LONG_NAME_UNITS_PER_ENTRY];
+        let length = units
+            .iter()
+            .position(|&unit| unit == 0)
+            .unwrap_or(units.len());
+
+        if length == 0 || length > MAX_NAME_LENGTH {
+            return false;
+        }
+
+        entry.name[..length].copy_from_slice(&units[..length]);
+        entry.name_length = length;
+
+        true
+    }
+}
+
+/// Gives an entry its short name, such as "README.TXT".
+fn short_name(raw: &[u8; DIRECTORY_ENTRY_SIZE], entry: &mut DirectoryEntry) {
+    let case_flags = raw[12];
+
+    let mut base = [0u8; 8];
+    base.copy_from_slice(&raw[0..8]);
+    if base[0] == ESCAPED_DELETED_ENTRY {
+        base[0] = DELETED_ENTRY;
+    }
+
+    let extension = &raw[8..11];
+
+    let mut push = |byte: u8, is_lower_case: bool| {
+        let byte = if is_lower_case {
+            byte.to_ascii_lowercase()
+        } else {
+            byte
+        };
+
+        // Bytes past ASCII are in an unknown code page.
+        entry.name[entry.name_length] = if byte.is_ascii() { byte as u16 } else { 0xFFFD };
+        entry.name_length += 1;
+    };
+
+    for &byte in base.iter().filter(|&&byte| byte != b' ') {
+        push(byte, case_flags & LOWER_CASE_BASE != 0);
+    }
+
+    if extension.iter().any(|&byte| byte != b' ') {
+        push(b'.', false);
+
+        for &byte in extension.iter().filter(|&&byte| byte != b' ') {
+            push(byte, case_flags & LOWER_CASE_EXTENSION != 0);
+        }
+    }
+}
+
+/// Computes the checksum of a short name that its long file name entries
+/// hold.
+fn checksum(raw: &[u8; DIRECTORY_ENTRY_SIZE]) -> u8 {
+    raw[..11]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// Finds the first FAT32 partition of an MBR partition table.
+///
+/// # Returns
+///
+/// The first sector of the partition, or `None` if the sector holds no
+/// partition table or the table no FAT32 partition.
+fn find_fat32_partition(sector: &[u8; SECTOR_SIZE]) -> Option<usize> {
+    if read_u16(sector, BOOT_SIGNATURE_OFFSET) != BOOT_SIGNATURE {
+        return None;
+    }
+
+    (0..PARTITION_COUNT)
+        .map(|index| PARTITION_TABLE_OFFSET + index * PARTITION_ENTRY_SIZE)
+        .find(|&offset| {
+            PARTITION_TYPES_FAT32.contains(&sector[offset + 4]) && read_u32(sector, offset + 8) != 0
+        })
+        .map(|offset| read_u32(sector, offset + 8) as usize)
+}
+
+/// Folds ASCII letters of a UTF-16 code unit to lower case.
+fn fold_case(unit: u16) -> u16 {
+    match u8::try_from(unit) {
+        Ok(byte) => byte.to_ascii_lowercase() as u16,
+        Err(_) => unit,
+    }
+}
+
+/// Reads a little-endian u16 at an offset that is known to be in bounds.
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Reads a little-endian u32 at an offset that is known to be in bounds.
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESERVED_SECTORS: usize = 4;
+    const SECTORS_PER_FAT: usize = 2;
+    const TOTAL_SECTORS: usize = 128;
+    const ROOT_CLUSTER: u32 = 2;
+
+    /// A disk image in memory.
+    struct MemoryDisk(Vec<u8>);
+
+    impl SectorReader for &MemoryDisk {
+        type Error = ();
+
+        fn read_sectors(&self, first_sector: usize, buffer: &mut [u8]) -> Result<(), ()> {
+            let start = first_sector * SECTOR_SIZE;
+            let source = self.0.get(start..start + buffer.len()).ok_or(())?;
+
+            buffer.copy_from_slice(source);
+            Ok(())
+        }
+    }
+
+    /// Builds a file system of 1 sector clusters, starting at a sector of a
+    /// disk.
+    struct ImageBuilder {
+        disk: Vec<u8>,
+        start_sector: usize,
+        next_free_cluster: u32,
+    }
+
+    impl ImageBuilder {
+        fn new(start_sector: usize) -> Self {
+            let mut builder = Self {
+                disk: vec![0; (start_sector + TOTAL_SECTORS) * SECTOR_SIZE],
+                start_sector,
+                next_free_cluster: ROOT_CLUSTER + 1,
+            };
+
+            let boot = &mut builder.disk[start_sector * SECTOR_SIZE..];
+            boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+            boot[13] = 1;
+            boot[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+            boot[16] = 1;
+            boot[32..36].copy_from_slice(&(TOTAL_SECTORS as u32).to_le_bytes());
+            boot[36..40].copy_from_slice(&(SECTORS_PER_FAT as u32).to_le_bytes());
+            boot[44..48].copy_from_slice(&ROOT_CLUSTER.to_le_bytes());
+            boot[510..512].copy_from_slice(&BOOT_SIGNATURE.to_le_bytes());
+
+            builder.set_fat_entry(ROOT_CLUSTER, END_OF_CHAIN);
+            builder
+        }
+
+        fn set_fat_entry(&mut self, cluster: u32, value: u32) {
+            let offset =
+                (self.start_sector + RESERVED_SECTORS) * SECTOR_SIZE + cluster as usize * 4;
+            self.disk[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        fn cluster_offset(&self, cluster: u32) -> usize {
+            (self.start_sector + RESERVED_SECTORS + SECTORS_PER_FAT + cluster as usize - 2)
+                * SECTOR_SIZE
+        }
+
+        /// Stores contents in a new chain of clusters.
+        ///
+        /// # Returns
+        ///
+        /// The first cluster of the chain.
+        fn allocate_chain(&mut self, contents: &[u8]) -> u32 {
+            let first = self.next_free_cluster;
+            let chunks: Vec<&[u8]> = contents.chunks(SECTOR_SIZE).collect();
+
+            for (index, chunk) in chunks.iter().enumerate() {
+                let cluster = first + index as u32;
+                let offset = self.cluster_offset(cluster);
+                self.disk[offset..offset + chunk.len()].copy_from_slice(chunk);
+
+                let next = if index + 1 == chunks.len() {
+                    END_OF_CHAIN
+                } else {
+                    cluster + 1
+                };
+                self.set_fat_entry(cluster, next);
+            }
+
+            self.next_free_cluster += chunks.len() as u32;
+            first
+        }
+
+        /// Appends raw entries to the directory starting at a cluster, which
+        /// must fit in its first cluster.
+        fn append_entries(&mut self, directory_cluster: u32, entries: &[[u8; 32]]) {
+            let offset = self.cluster_offset(directory_cluster);
+            let first_position = (0..SECTOR_SIZE / 32)
+                .find(|index| self.disk[offset + index * 32] == 0)
+                .unwrap();
+
+            for (position, entry) in (first_position..).zip(entries) {
+                let start = offset + position * 32;
+                self.disk[start..start + 32].copy_from_slice(entry);
+            }
+        }
+
+        /// Adds a file or directory with a short name and, if given, a long
+        /// name.
+        fn add(
+            &mut self,
+            directory_cluster: u32,
+            short: &[u8; 11],
+            long: Option<&str>,
+            attributes: u8,
+            first_cluster: u32,
+            size: u32,
+        ) {
+            let mut short_entry = [0u8; 32];
+            short_entry[..11].copy_from_slice(short);
+            short_entry[11] = attributes;
+            short_entry[20..22].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
+            short_entry[26..28].copy_from_slice(&(first_cluster as u16).to_le_bytes());
+            short_entry[28..32].copy_from_slice(&size.to_le_bytes());
+
+            let mut entries = Vec::new();
+
+            if let Some(long) = long {
+                let mut units: Vec<u16> = long.encode_utf16().collect();
+                if !units.len().is_multiple_of(13) {
+                    units.push(0);
+                }
+                while !units.len().is_multiple_of(13) {
+                    units.push(0xFFFF);
+                }
+
+                let count = units.len() / 13;
+                for ordinal in (1..=count).rev() {
+                    let mut entry = [0u8; 32];
+                    entry[0] = ordinal as u8;
+                    if ordinal == count {
+                        entry[0] |= LAST_LONG_NAME_ENTRY;
+                    }
+                    entry[11] = ATTRIBUTE_LONG_NAME;
+                    entry[13] = checksum(&short_entry);
+
+                    for (index, &offset) in LONG_NAME_UNIT_OFFSETS.iter().enumerate() {
+                        let unit = units[(ordinal - 1) * 13 + index];
+                        entry[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+                    }
+
+                    entries.push(entry);
+                }
+            }
+
+            entries.push(short_entry);
+            self.append_entries(directory_cluster, &entries);
+        }
+
+        fn add_file(&mut self, directory: u32, short: &[u8; 11], long: Option<&str>, data: &[u8]) {
+            let first_cluster = if data.is_empty() {
+                0
+            } else {
+                self.allocate_chain(data)
+            };
+
+            self.add(
+                directory,
+                short,
+                long,
+                0x20,
+                first_cluster,
+                data.len() as u32,
+            );
+        }
+
+        fn add_directory(&mut self, directory: u32, short: &[u8; 11]) -> u32 {
+            let cluster = self.allocate_chain(&[0; SECTOR_SIZE]);
+
+            let mut dot = [b' '; 11];
+            dot[0] = b'.';
+            self.add(cluster, &dot, None, ATTRIBUTE_DIRECTORY, cluster, 0);
+
+            self.add(directory, short, None, ATTRIBUTE_DIRECTORY, cluster, 0);
+            cluster
+        }
+    }
+
+    fn names(file_system: &Fat32<&MemoryDisk>, directory: &DirectoryEntry) -> Vec<String> {
+        file_system
+            .entries(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().name().to_string())
+            .collect()
+    }
+
+    fn sample_image(start_sector: usize) -> MemoryDisk {
+        let mut builder = ImageBuilder::new(start_sector);
+
+        builder.add(
+            ROOT_CLUSTER,
+            b"DISK       ",
+            None,
+            ATTRIBUTE_VOLUME_ID,
+            0,
+            0,
+        );
+        builder.add_file(ROOT_CLUSTER, b"README  TXT", None, b"Hello, FAT32!\n");
+        builder.add_file(
+            ROOT_CLUSTER,
+            b"ALONGF~1BIN",
+            Some("A long file name.binary"),
+            &(0..1300u32).map(|value| value as u8).collect::<Vec<_>>(),
+        );
+
+        let bin = builder.add_directory(ROOT_CLUSTER, b"BIN        ");
+        builder.add_file(bin, b"INIT       ", Some("init"), b"\x7FELF");
+        builder.add_file(bin, b"EMPTY      ", None, b"");
+
+        MemoryDisk(builder.disk)
+    }
+
+    #[test]
+    fn test_mount_rejects_disks_without_fat32() {
+        let disk = MemoryDisk(vec![0; TOTAL_SECTORS * SECTOR_SIZE]);
+
+        assert_eq!(Fat32::mount(&disk).err(), Some(Fat32Error::NotFat32));
+    }
+
+    #[test]
+    fn test_mount_finds_partition() {
+        let mut disk = sample_image(8);
+
+        let entry = PARTITION_TABLE_OFFSET;
+        disk.0[entry + 4] = 0x0C;
+        disk.0[entry + 8..entry + 12].copy_from_slice(&8u32.to_le_bytes());
+        disk.0[510..512].copy_from_slice(&BOOT_SIGNATURE.to_le_bytes());
+
+        let file_system = Fat32::mount(&disk).unwrap();
+        let root = file_system.root();
+
+        assert_eq!(
+            names(&file_system, &root),
+            ["README.TXT", "A long file name.binary", "BIN"]
+        );
+    }
+
+    #[test]
+    fn test_entries_join_long_names() {
+        let disk = sample_image(0);
+        let file_system = Fat32::mount(&disk).unwrap();
+
+        assert_eq!(file_system.cluster_size(), SECTOR_SIZE);
+
+        let bin = file_system.open("/bin").unwrap();
+        assert!(bin.is_directory());
+        assert_eq!(names(&file_system, &bin), ["init", "EMPTY"]);
+    }
+
+    #[test]
+    fn test_long_name_with_bad_checksum_falls_back_to_short_name() {
+        let mut builder = ImageBuilder::new(0);
+        builder.add_file(ROOT_CLUSTER, b"REAL    TXT", Some("real.txt"), b"1");
+
+        // Corrupt the checksum of the only long name entry.
+        let offset = builder.cluster_offset(ROOT_CLUSTER);
+        builder.disk[offset + 13] ^= 1;
+
+        let disk = MemoryDisk(builder.disk);
+        let file_system = Fat32::mount(&disk).unwrap();
+
+        assert_eq!(names(&file_system, &file_system.root()), ["REAL.TXT"]);
+    }
+
+    #[test]
+    fn test_open_ignores_case_and_empty_components() {
+        let disk = sample_image(0);
+        let file_system = Fat32::mount(&disk).unwrap();
+
+        let file = file_system.open("//BIN/Init").unwrap();
+        assert_eq!(file.name().to_string(), "init");
+        assert_eq!(file.size(), 4);
+
+        assert!(file_system.open("/").unwrap().is_directory());
+        assert_eq!(
+            file_system.open("/bin/missing").err(),
+            Some(Fat32Error::NotFound)
+        );
+        assert_eq!(
+            file_system.open("/readme.txt/x").err(),
+            Some(Fat32Error::NotADirectory)
+        );
+    }
+
+    #[test]
+    fn test_read_follows_cluster_chain() {
+        let disk = sample_image(0);
+        let file_system = Fat32::mount(&disk).unwrap();
+        let expected: Vec<u8> = (0..1300u32).map(|value| value as u8).collect();
+
+        let file = file_system.open("a long file name.BINARY").unwrap();
+        let mut buffer = vec![0u8; 2000];
+        assert_eq!(file_system.read(&file, 0, &mut buffer), Ok(1300));
+        assert_eq!(&buffer[..1300], &expected[..]);
+
+        // A read that starts and ends within clusters.
+        let mut buffer = [0u8; 700];
+        assert_eq!(file_system.read(&file, 300, &mut buffer), Ok(700));
+        assert_eq!(&buffer[..], &expected[300..1000]);
+
+        // The entry can be found again from its first cluster.
+        let again = file_system.entry_at(file.first_cluster(), false, file.size());
+        let mut buffer_again = [0u8; 700];
+        assert_eq!(file_system.read(&again, 300, &mut buffer_again), Ok(700));
+        assert_eq!(buffer_again, buffer);
+
+        assert_eq!(file_system.read(&file, 1300, &mut buffer), Ok(0));
+
+        let empty = file_system.open("/bin/empty").unwrap();
+        assert_eq!(file_system.read(&empty, 0, &mut buffer), Ok(0));
+
+        let bin = file_system.open("/bin").unwrap();
+        assert_eq!(
+            file_system.read(&bin, 0, &mut buffer),
+            Err(Fat32Error::IsADirectory)
+        );
+    }
+
+    #[test]
+    fn test_looping_chain_is_corrupt() {
+        let mut builder = ImageBuilder::new(0);
+        builder.set_fat_entry(ROOT_CLUSTER, ROOT_CLUSTER);
+
+        // Fill the root cluster, so the iterator follows the chain.
+        let offset = builder.cluster_offset(ROOT_CLUSTER);
+        for entry in builder.disk[offset..offset + SECTOR_SIZE].chunks_mut(32) {
+            entry[0] = DELETED_ENTRY;
+        }
+
+        let disk = MemoryDisk(builder.disk);
+        let file_system = Fat32::mount(&disk).unwrap();
+
+        let result: Vec<_> = file_system.entries(&file_system.root()).unwrap().collect();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].as_ref().err(), Some(&Fat32Error::Corrupt));
+    }
+}
@@ -1 +1,18 @@
 #![cfg_attr(not(test), no_std)]
+
+pub mod asid;
+pub mod cmdline;
+pub mod cpio;
+pub mod fat32;
+pub mod line_editor;
+pub mod log_buffer;
+pub mod mmio;
+pub mod net;
+pub mod random;
+pub mod ring_buffer;
+pub mod symbol_table;
+pub mod sync;
+pub mod time;
+pub mod timer_wheel;
+pub mod tmpfs;
+pub mod virtual_memory_area;
@@ -0,0 +1,179 @@
+//! Line editing for console input.
+//!
+//! A `LineEditor` turns the bytes typed on a terminal into lines. It supports
+//! erasing the last character with backspace or delete and erasing the whole
+//! line with Ctrl-U. Either a carriage return or a line feed ends a line, and
+//! the line feed of a carriage return and line feed pair is ignored. Only
+//! printable ASCII is stored, so a line is always valid UTF-8.
+
+/// The ASCII backspace control character.
+const BACKSPACE: u8 = 0x08;
+
+/// The ASCII delete character, sent by most terminals for the backspace key.
+const DELETE: u8 = 0x7f;
+
+/// The ASCII negative acknowledge character, sent for Ctrl-U.
+const KILL_LINE: u8 = 0x15;
+
+/// The sequence that moves the cursor back over a character and blanks it.
+const ERASE_SEQUENCE: &[u8] = b"\x08 \x08";
+
+/// A fixed capacity line buffer with basic editing.
+pub struct LineEditor<const CAPACITY: usize> {
+    buffer: [u8; CAPACITY],
+    length: usize,
+
+    /// True once the current line has been ended. The next byte starts a new
+    /// line.
+    is_complete: bool,
+
+    /// True if the last byte was a carriage return, so that a following line
+    /// feed does not end an empty line.
+    last_was_carriage_return: bool,
+}
+
+impl<const CAPACITY: usize> LineEditor<CAPACITY> {
+    /// Creates an empty editor.
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; CAPACITY],
+            length: 0,
+            is_complete: false,
+            last_was_carriage_return: false,
+        }
+    }
+
+    /// Processes a byte typed on the terminal.
+    ///
+    /// # Arguments
+    ///
+    /// * `byte` - The byte received from the terminal.
+    /// * `echo` - Called with the bytes to send back to the terminal so that
+    ///   it shows the line being edited.
+    ///
+    /// # Returns
+    ///
+    /// True if the byte ended the line, which is then available from `line`
+    /// until the next byte is processed.
+    pub fn push_byte(&mut self, byte: u8, mut echo: impl FnMut(&[u8])) -> bool {
+        if self.is_complete {
+            self.clear();
+        }
+
+        let follows_carriage_return = self.last_was_carriage_return;
+        self.last_was_carriage_return = byte == b'\r';
+
+        match byte {
+            b'\n' if follows_carriage_return => {}
+            b'\r' | b'\n' => {
+                echo(b"\n");
+                self.is_complete = true;
+            }
+            BACKSPACE | DELETE if self.length > 0 => {
+                self.length -= 1;
+                echo(ERASE_SEQUENCE);
+            }
+            KILL_LINE => {
+                while self.length > 0 {
+                    self.length -= 1;
+                    echo(ERASE_SEQUENCE);
+                }
+            }
+            // Characters that do not fit are dropped without an echo so the
+            // terminal shows what was kept.
+            b' '..=b'~' if self.length < CAPACITY => {
+                self.buffer[self.length] = byte;
+                self.length += 1;
+                echo(&[byte]);
+            }
+            _ => {}
+        }
+
+        self.is_complete
+    }
+
+    /// Returns the line being edited, or the completed line after
+    /// `push_byte` returned true.
+    pub fn line(&self) -> &str {
+        // Only printable ASCII is stored, so the conversion cannot fail.
+        core::str::from_utf8(&self.buffer[..self.length]).unwrap_or("")
+    }
+
+    /// Discards the line being edited.
+    pub fn clear(&mut self) {
+        self.length = 0;
+        self.is_complete = false;
+    }
+}
+
+impl<const CAPACITY: usize> Default for LineEditor<CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{string::String, vec::Vec};
+
+    /// Feeds bytes to an editor and returns the completed lines and the
+    /// echoed output.
+    fn type_bytes<const CAPACITY: usize>(
+        editor: &mut LineEditor<CAPACITY>,
+        bytes: &[u8],
+    ) -> (Vec<String>, Vec<u8>) {
+        let mut lines = Vec::new();
+        let mut echoed = Vec::new();
+
+        for &byte in bytes {
+            if editor.push_byte(byte, |echo| echoed.extend_from_slice(echo)) {
+                lines.push(editor.line().into());
+            }
+        }
+
+        (lines, echoed)
+    }
+
+    #[test]
+    fn test_lines_and_echo() {
+        let mut editor = LineEditor::<32>::new();
+
+        let (lines, echoed) = type_bytes(&mut editor, b"md 0x80\rhelp\n");
+
+        assert_eq!(lines, ["md 0x80", "help"]);
+        assert_eq!(echoed, b"md 0x80\nhelp\n");
+    }
+
+    #[test]
+    fn test_carriage_return_line_feed_ends_one_line() {
+        let mut editor = LineEditor::<32>::new();
+
+        let (lines, _) = type_bytes(&mut editor, b"a\r\nb\r\n\r\n");
+
+        assert_eq!(lines, ["a", "b", ""]);
+    }
+
+    #[test]
+    fn test_erase_and_kill() {
+        let mut editor = LineEditor::<32>::new();
+
+        let (lines, echoed) = type_bytes(&mut editor, b"ab\x7fc\x08\x08\x08d");
+        assert!(lines.is_empty());
+        assert_eq!(editor.line(), "d");
+        assert_eq!(echoed, b"ab\x08 \x08c\x08 \x08\x08 \x08d");
+
+        let (lines, _) = type_bytes(&mut editor, b"ef\x15gh\r");
+        assert_eq!(lines, ["gh"]);
+    }
+
+    #[test]
+    fn test_capacity_and_control_bytes() {
+        let mut editor = LineEditor::<4>::new();
+
+        let (lines, echoed) = type_bytes(&mut editor, b"ab\x1b\x00cdef\r");
+
+        assert_eq!(lines, ["abcd"]);
+        assert_eq!(echoed, b"abcd\n");
+    }
+}
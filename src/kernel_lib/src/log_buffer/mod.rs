@@ -0,0 +1,255 @@
+//! A fixed size ring buffer of log text.
+//!
+//! Every line written to a `LogBuffer` is prefixed with the time it was
+//! started, formatted like "[    1.234567] ". Once the buffer is full the
+//! oldest text is overwritten. Readers only ever see whole lines, except for
+//! the newest line while it is still being written.
+
+use core::fmt::{self, Write};
+
+/// A ring buffer holding the most recent `CAPACITY` bytes of log text.
+pub struct LogBuffer<const CAPACITY: usize> {
+    bytes: [u8; CAPACITY],
+
+    /// The number of bytes written since the buffer was created. The next
+    /// byte is stored at `total_written % CAPACITY`.
+    total_written: usize,
+
+    /// True if the next byte written starts a new line.
+    at_line_start: bool,
+}
+
+impl<const CAPACITY: usize> LogBuffer<CAPACITY> {
+    /// Creates an empty log buffer.
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; CAPACITY],
+            total_written: 0,
+            at_line_start: true,
+        }
+    }
+
+    /// Appends text to the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to append. It may contain any number of lines and
+    ///   may end partway through a line.
+    /// * `timestamp_microseconds` - The time the text was written, used for
+    ///   every line the text starts.
+    pub fn write(&mut self, text: &[u8], timestamp_microseconds: u64) {
+        for &byte in text {
+            if self.at_line_start {
+                self.at_line_start = false;
+
+                let _ = write!(
+                    ByteWriter(self),
+                    "[{:>5}.{:06}] ",
+                    timestamp_microseconds / 1_000_000,
+                    timestamp_microseconds % 1_000_000
+                );
+            }
+
+            self.push(byte);
+            self.at_line_start = byte == b'\n';
+        }
+    }
+
+    /// Returns the retained log from its oldest whole line to the newest
+    /// byte.
+    ///
+    /// # Returns
+    ///
+    /// The text in two parts since it may wrap around the end of the buffer.
+    /// The second part is empty if it does not.
+    pub fn contents(&self) -> (&[u8], &[u8]) {
+        self.slices_from(self.oldest_line_start())
+    }
+
+    /// Returns the newest lines of the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `line_count` - The number of lines to return. A line that is still
+    ///   being written counts as a line.
+    ///
+    /// # Returns
+    ///
+    /// The text in two parts like `contents`. Fewer lines are returned if the
+    /// buffer does not retain that many.
+    pub fn tail(&self, line_count: usize) -> (&[u8], &[u8]) {
+        if line_count == 0 {
+            return (&[], &[]);
+        }
+
+        let oldest_line_start = self.oldest_line_start();
+        let mut start = self.total_written;
+        let mut remaining_line_count = line_count;
+
+        while start > oldest_line_start {
+            // The line feed ending the newest line does not start a line.
+            if self.byte_at(start - 1) == b'\n' && start != self.total_written {
+                remaining_line_count -= 1;
+
+                if remaining_line_count == 0 {
+                    break;
+                }
+            }
+
+            start -= 1;
+        }
+
+        self.slices_from(start)
+    }
+
+    /// Returns the number of bytes written since the buffer was created,
+    /// including bytes that have since been overwritten.
+    pub fn total_written(&self) -> usize {
+        self.total_written
+    }
+
+    /// Stores a byte, overwriting the oldest byte once the buffer is full.
+    fn push(&mut self, byte: u8) {
+        if CAPACITY == 0 {
+            return;
+        }
+
+        self.bytes[self.total_written % CAPACITY] = byte;
+        self.total_written += 1;
+    }
+
+    /// Returns the byte at a position counted from the first byte ever
+    /// written. The position must still be retained.
+    fn byte_at(&self, position: usize) -> u8 {
+        self.bytes[position % CAPACITY]
+    }
+
+    /// Returns the position of the first retained byte that starts a line.
+    fn oldest_line_start(&self) -> usize {
+        if self.total_written <= CAPACITY {
+            return 0;
+        }
+
+        // The oldest retained line has lost its start to the wrap, so skip to
+        // the line after it.
+        let mut position = self.total_written - CAPACITY;
+
+        while position < self.total_written {
+            position += 1;
+
+            if self.byte_at(position - 1) == b'\n' {
+                break;
+            }
+        }
+
+        position
+    }
+
+    /// Returns the bytes from a position to the newest byte as two slices.
+    fn slices_from(&self, start: usize) -> (&[u8], &[u8]) {
+        let length = self.total_written - start;
+        if length == 0 {
+            return (&[], &[]);
+        }
+
+        let first_index = start % CAPACITY;
+
+        if first_index + length <= CAPACITY {
+            (&self.bytes[first_index..first_index + length], &[])
+        } else {
+            (
+                &self.bytes[first_index..],
+                &self.bytes[..first_index + length - CAPACITY],
+            )
+        }
+    }
+}
+
+impl<const CAPACITY: usize> Default for LogBuffer<CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Formats text straight into a log buffer without adding timestamps.
+struct ByteWriter<'a, const CAPACITY: usize>(&'a mut LogBuffer<CAPACITY>);
+
+impl<const CAPACITY: usize> Write for ByteWriter<'_, CAPACITY> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0.push(byte);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn joined((first, second): (&[u8], &[u8])) -> Vec<u8> {
+        [first, second].concat()
+    }
+
+    #[test]
+    fn test_timestamps_start_each_line() {
+        let mut log_buffer = LogBuffer::<256>::new();
+
+        log_buffer.write(b"Booting", 1_500);
+        log_buffer.write(b" done.\nSecond line\nThi", 2_000_001);
+        log_buffer.write(b"rd\n", 3_000_000);
+
+        assert_eq!(
+            joined(log_buffer.contents()),
+            b"[    0.001500] Booting done.\n[    2.000001] Second line\n[    2.000001] Third\n"
+        );
+    }
+
+    #[test]
+    fn test_wrap_keeps_whole_lines() {
+        let mut log_buffer = LogBuffer::<64>::new();
+
+        for line in ["first lin\n", "second ln\n", "third lin\n", "fourth ln\n"] {
+            log_buffer.write(line.as_bytes(), 0);
+        }
+
+        // Each line is 25 bytes with its timestamp, so the first two lines
+        // have been partly overwritten.
+        assert_eq!(log_buffer.total_written(), 100);
+        assert_eq!(
+            joined(log_buffer.contents()),
+            b"[    0.000000] third lin\n[    0.000000] fourth ln\n"
+        );
+    }
+
+    #[test]
+    fn test_tail() {
+        let mut log_buffer = LogBuffer::<256>::new();
+        log_buffer.write(b"a\nb\nc\nd", 0);
+
+        assert_eq!(joined(log_buffer.tail(0)), b"");
+        assert_eq!(joined(log_buffer.tail(1)), b"[    0.000000] d");
+        assert_eq!(
+            joined(log_buffer.tail(2)),
+            b"[    0.000000] c\n[    0.000000] d"
+        );
+
+        log_buffer.write(b"\n", 0);
+        assert_eq!(joined(log_buffer.tail(1)), b"[    0.000000] d\n");
+        assert_eq!(joined(log_buffer.tail(10)), joined(log_buffer.contents()));
+    }
+
+    #[test]
+    fn test_tail_after_wrap() {
+        let mut log_buffer = LogBuffer::<40>::new();
+
+        for _ in 0..10 {
+            log_buffer.write(b"0123456789\n", 0);
+        }
+
+        // Only one whole 26 byte line fits once the partial line is skipped.
+        assert_eq!(joined(log_buffer.tail(5)), b"[    0.000000] 0123456789\n");
+    }
+}
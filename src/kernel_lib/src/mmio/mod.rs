@@ -0,0 +1,132 @@
+//! Volatile accessors for memory mapped device registers.
+//!
+//! A device's registers are described by a `#[repr(C)]` struct of
+//! `ReadOnly`, `WriteOnly`, and `ReadWrite` fields laid out like the device's
+//! register block, and reached through a reference created with `at` on the
+//! address the registers are mapped at. Every access is a single volatile
+//! load or store of the register's width, so the compiler never merges,
+//! splits, reorders, or drops it.
+//!
+//! ```ignore
+//! #[repr(C)]
+//! struct Registers {
+//!     data: ReadWrite<u8>,
+//!     interrupt_enable: ReadWrite<u8>,
+//! }
+//!
+//! let registers = unsafe { &*(base_address as *const Registers) };
+//! registers.interrupt_enable.write(1);
+//! ```
+
+use core::cell::UnsafeCell;
+
+/// A register the driver may only read.
+#[repr(transparent)]
+pub struct ReadOnly<T: Copy>(UnsafeCell<T>);
+
+/// A register the driver may only write.
+#[repr(transparent)]
+pub struct WriteOnly<T: Copy>(UnsafeCell<T>);
+
+/// A register the driver may read and write.
+#[repr(transparent)]
+pub struct ReadWrite<T: Copy>(UnsafeCell<T>);
+
+// Registers are shared with the device, and every access is a single
+// volatile load or store that the hardware serializes.
+unsafe impl<T: Copy> Sync for ReadOnly<T> {}
+unsafe impl<T: Copy> Sync for WriteOnly<T> {}
+unsafe impl<T: Copy> Sync for ReadWrite<T> {}
+
+/// Implements `at` for a register type.
+macro_rules! impl_at {
+    ($register:ident) => {
+        impl<T: Copy> $register<T> {
+            /// Returns the register at an address.
+            ///
+            /// # Arguments
+            ///
+            /// * `address` - The virtual address the register is mapped at.
+            ///
+            /// # Safety
+            ///
+            /// The address must be aligned for `T` and mapped to the register
+            /// for as long as the reference is used.
+            pub unsafe fn at<'a>(address: usize) -> &'a Self {
+                unsafe { &*(address as *const Self) }
+            }
+        }
+    };
+}
+
+impl_at!(ReadOnly);
+impl_at!(WriteOnly);
+impl_at!(ReadWrite);
+
+impl<T: Copy> ReadOnly<T> {
+    /// Reads the register.
+    pub fn read(&self) -> T {
+        unsafe { core::ptr::read_volatile(self.0.get()) }
+    }
+}
+
+impl<T: Copy> WriteOnly<T> {
+    /// Writes the register.
+    pub fn write(&self, value: T) {
+        unsafe { core::ptr::write_volatile(self.0.get(), value) }
+    }
+}
+
+impl<T: Copy> ReadWrite<T> {
+    /// Reads the register.
+    pub fn read(&self) -> T {
+        unsafe { core::ptr::read_volatile(self.0.get()) }
+    }
+
+    /// Writes the register.
+    pub fn write(&self, value: T) {
+        unsafe { core::ptr::write_volatile(self.0.get(), value) }
+    }
+
+    /// Reads the register, changes the value, and writes it back. The two
+    /// accesses are not atomic.
+    ///
+    /// # Arguments
+    ///
+    /// * `modify` - Returns the value to write from the value read.
+    pub fn modify(&self, modify: impl FnOnce(T) -> T) {
+        self.write(modify(self.read()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Registers {
+        status: ReadOnly<u32>,
+        command: WriteOnly<u32>,
+        control: ReadWrite<u8>,
+    }
+
+    #[test]
+    fn test_registers_access_memory() {
+        let mut memory = [0u32; 3];
+        memory[0] = 0xDEAD_BEEF;
+
+        let registers = unsafe { &*(memory.as_mut_ptr() as *const Registers) };
+
+        assert_eq!(registers.status.read(), 0xDEAD_BEEF);
+
+        registers.command.write(0x1234_5678);
+        registers.control.write(0b0101);
+        registers.control.modify(|value| value | 0b1000);
+
+        assert_eq!(registers.control.read(), 0b1101);
+
+        let command = unsafe { ReadWrite::<u32>::at(memory.as_ptr() as usize + 4) };
+        assert_eq!(command.read(), 0x1234_5678);
+        assert_eq!(memory[2] & 0xFF, 0b1101);
+    }
+}
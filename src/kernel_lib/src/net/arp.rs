@@ -0,0 +1,107 @@
+//! ARP packets that map IPv4 addresses to Ethernet addresses.
+
+use super::{Ipv4Address, MacAddress, ethernet::ETHER_TYPE_IPV4, read_u16, write_u16};
+
+/// The size of an ARP packet for IPv4 over Ethernet.
+pub const PACKET_SIZE: usize = 28;
+
+/// The hardware type of Ethernet.
+const HARDWARE_TYPE_ETHERNET: u16 = 1;
+
+/// The operation of a packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArpOperation {
+    /// Asks for the Ethernet address of the target IPv4 address.
+    Request = 1,
+
+    /// Answers a request with the sender's addresses.
+    Reply = 2,
+}
+
+/// An ARP packet for IPv4 over Ethernet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ArpPacket {
+    pub operation: ArpOperation,
+    pub sender_mac: MacAddress,
+    pub sender_ip: Ipv4Address,
+
+    /// Unknown, and zero, in requests.
+    pub target_mac: MacAddress,
+    pub target_ip: Ipv4Address,
+}
+
+impl ArpPacket {
+    /// Parses a packet.
+    ///
+    /// # Returns
+    ///
+    /// The packet, or `None` if it is truncated, not for IPv4 over Ethernet,
+    /// or neither a request nor a reply.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < PACKET_SIZE
+            || read_u16(bytes, 0) != HARDWARE_TYPE_ETHERNET
+            || read_u16(bytes, 2) != ETHER_TYPE_IPV4
+            || bytes[4] != 6
+            || bytes[5] != 4
+        {
+            return None;
+        }
+
+        let operation = match read_u16(bytes, 6) {
+            1 => ArpOperation::Request,
+            2 => ArpOperation::Reply,
+            _ => return None,
+        };
+
+        Some(Self {
+            operation,
+            sender_mac: MacAddress(bytes[8..14].try_into().ok()?),
+            sender_ip: Ipv4Address(bytes[14..18].try_into().ok()?),
+            target_mac: MacAddress(bytes[18..24].try_into().ok()?),
+            target_ip: Ipv4Address(bytes[24..28].try_into().ok()?),
+        })
+    }
+
+    /// Writes the packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Receives the packet, at least `PACKET_SIZE` bytes long.
+    pub fn write(&self, bytes: &mut [u8]) {
+        write_u16(bytes, 0, HARDWARE_TYPE_ETHERNET);
+        write_u16(bytes, 2, ETHER_TYPE_IPV4);
+        bytes[4] = 6;
+        bytes[5] = 4;
+        write_u16(bytes, 6, self.operation as u16);
+        bytes[8..14].copy_from_slice(&self.sender_mac.0);
+        bytes[14..18].copy_from_slice(&self.sender_ip.0);
+        bytes[18..24].copy_from_slice(&self.target_mac.0);
+        bytes[24..28].copy_from_slice(&self.target_ip.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_round_trip() {
+        let packet = ArpPacket {
+            operation: ArpOperation::Reply,
+            sender_mac: MacAddress([0x52, 0x55, 10, 0, 2, 2]),
+            sender_ip: Ipv4Address([10, 0, 2, 2]),
+            target_mac: MacAddress([0x52, 0x54, 0, 0x12, 0x34, 0x56]),
+            target_ip: Ipv4Address([10, 0, 2, 15]),
+        };
+
+        let mut bytes = [0; PACKET_SIZE];
+        packet.write(&mut bytes);
+
+        assert_eq!(&bytes[..8], &[0, 1, 8, 0, 6, 4, 0, 2]);
+        assert_eq!(ArpPacket::parse(&bytes), Some(packet));
+
+        bytes[7] = 3;
+        assert_eq!(ArpPacket::parse(&bytes), None);
+        assert_eq!(ArpPacket::parse(&bytes[..PACKET_SIZE - 1]), None);
+    }
+}
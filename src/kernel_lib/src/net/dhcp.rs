@@ -0,0 +1,344 @@
+//! DHCP client messages, which lease an IPv4 address and its configuration
+//! from a server.
+//!
+//! A client broadcasts a discover, the server offers an address, the client
+//! requests it, and the server acknowledges the lease. Messages travel in UDP
+//! datagrams between `CLIENT_PORT` and `SERVER_PORT`.
+
+use super::{Ipv4Address, MacAddress, read_u32, write_u16, write_u32};
+
+/// The UDP port of clients.
+pub const CLIENT_PORT: u16 = 68;
+
+/// The UDP port of servers.
+pub const SERVER_PORT: u16 = 67;
+
+/// The size of the messages the client sends, the minimum size of a BOOTP
+/// message that some servers insist on.
+pub const MESSAGE_SIZE: usize = 300;
+
+/// The size of the fixed BOOTP fields before the magic cookie.
+const FIXED_SIZE: usize = 236;
+
+/// The value that starts the options and sets DHCP apart from BOOTP.
+const MAGIC_COOKIE: u32 = 0x6382_5363;
+
+/// The BOOTP operations.
+const OPERATION_REQUEST: u8 = 1;
+const OPERATION_REPLY: u8 = 2;
+
+/// The flag asking the server to broadcast its replies, since the client
+/// cannot receive packets sent to an address it does not have yet.
+const FLAG_BROADCAST: u16 = 0x8000;
+
+/// The options the client reads or writes.
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_IDENTIFIER: u8 = 54;
+const OPTION_PARAMETER_REQUEST_LIST: u8 = 55;
+const OPTION_END: u8 = 255;
+
+/// The type of a DHCP message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MessageType {
+    Discover = 1,
+    Offer = 2,
+    Request = 3,
+    Decline = 4,
+    Acknowledge = 5,
+    NegativeAcknowledge = 6,
+    Release = 7,
+}
+
+impl MessageType {
+    /// Returns the message type of an option value.
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Self::Discover,
+            2 => Self::Offer,
+            3 => Self::Request,
+            4 => Self::Decline,
+            5 => Self::Acknowledge,
+            6 => Self::NegativeAcknowledge,
+            7 => Self::Release,
+            _ => return None,
+        })
+    }
+}
+
+/// A message received from a server.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DhcpMessage {
+    pub message_type: MessageType,
+
+    /// The transaction the message answers.
+    pub transaction_id: u32,
+
+    /// The address offered or leased to the client.
+    pub your_address: Ipv4Address,
+
+    pub server_identifier: Option<Ipv4Address>,
+    pub subnet_mask: Option<Ipv4Address>,
+
+    /// The first router, if the server named any.
+    pub router: Option<Ipv4Address>,
+
+    /// The length of the lease in seconds.
+    pub lease_time: Option<u32>,
+}
+
+impl DhcpMessage {
+    /// Parses a server's reply.
+    ///
+    /// # Returns
+    ///
+    /// The message, or `None` if it is malformed, not a reply, or has no
+    /// message type.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < FIXED_SIZE + 4
+            || bytes[0] != OPERATION_REPLY
+            || read_u32(bytes, FIXED_SIZE) != MAGIC_COOKIE
+        {
+            return None;
+        }
+
+        let mut message_type = None;
+        let mut server_identifier = None;
+        let mut subnet_mask = None;
+        let mut router = None;
+        let mut lease_time = None;
+
+        let mut options = &bytes[FIXED_SIZE + 4..];
+
+        while let Some((&code, rest)) = options.split_first() {
+            match code {
+                OPTION_PAD => {
+                    options = rest;
+                    continue;
+                }
+                OPTION_END => break,
+                _ => {}
+            }
+
+            let (&length, rest) = rest.split_first()?;
+            let value = rest.get(..length as usize)?;
+            options = &rest[length as usize..];
+
+            let address = || Some(Ipv4Address(value.get(..4)?.try_into().ok()?));
+
+            match code {
+                OPTION_MESSAGE_TYPE => message_type = MessageType::from_u8(*value.first()?),
+                OPTION_SERVER_IDENTIFIER => server_identifier = address(),
+                OPTION_SUBNET_MASK => subnet_mask = address(),
+                OPTION_ROUTER => router = address(),
+                OPTION_LEASE_TIME => lease_time = (value.len() >= 4).then(|| read_u32(value, 0)),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            message_type: message_type?,
+            transaction_id: read_u32(bytes, 4),
+            your_address: Ipv4Address(bytes[16..20].try_into().ok()?),
+            server_identifier,
+            subnet_mask,
+            router,
+            lease_time,
+        })
+    }
+}
+
+/// Writes a discover, which asks servers for offers.
+///
+/// # Arguments
+///
+/// * `bytes` - Receives the message, at least `MESSAGE_SIZE` bytes long.
+/// * `transaction_id` - A random number that replies echo back.
+/// * `mac_address` - The address of the client's interface.
+///
+/// # Returns
+///
+/// The size of the message.
+pub fn write_discover(bytes: &mut [u8], transaction_id: u32, mac_address: MacAddress) -> usize {
+    let mut offset = write_fixed(bytes, transaction_id, mac_address);
+
+    offset = write_option(
+        bytes,
+        offset,
+        OPTION_MESSAGE_TYPE,
+        &[MessageType::Discover as u8],
+    );
+    offset = write_parameter_request_list(bytes, offset);
+    bytes[offset] = OPTION_END;
+
+    MESSAGE_SIZE
+}
+
+/// Writes a request, which accepts a server's offer.
+///
+/// # Arguments
+///
+/// * `bytes` - Receives the message, at least `MESSAGE_SIZE` bytes long.
+/// * `transaction_id` - The transaction of the offer.
+/// * `mac_address` - The address of the client's interface.
+/// * `requested_address` - The offered address.
+/// * `server_identifier` - The identifier of the server that offered it.
+///
+/// # Returns
+///
+/// The size of the message.
+pub fn write_request(
+    bytes: &mut [u8],
+    transaction_id: u32,
+    mac_address: MacAddress,
+    requested_address: Ipv4Address,
+    server_identifier: Ipv4Address,
+) -> usize {
+    let mut offset = write_fixed(bytes, transaction_id, mac_address);
+
+    offset = write_option(
+        bytes,
+        offset,
+        OPTION_MESSAGE_TYPE,
+        &[MessageType::Request as u8],
+    );
+    offset = write_option(
+        bytes,
+        offset,
+        OPTION_REQUESTED_ADDRESS,
+        &requested_address.0,
+    );
+    offset = write_option(
+        bytes,
+        offset,
+        OPTION_SERVER_IDENTIFIER,
+        &server_identifier.0,
+    );
+    offset = write_parameter_request_list(bytes, offset);
+    bytes[offset] = OPTION_END;
+
+    MESSAGE_SIZE
+}
+
+/// Clears a message and writes its fixed fields and magic cookie.
+///
+/// # Returns
+///
+/// The offset of the options.
+fn write_fixed(bytes: &mut [u8], transaction_id: u32, mac_address: MacAddress) -> usize {
+    bytes[..MESSAGE_SIZE].fill(0);
+
+    bytes[0] = OPERATION_REQUEST;
+    bytes[1] = 1;
+    bytes[2] = 6;
+    write_u32(bytes, 4, transaction_id);
+    write_u16(bytes, 10, FLAG_BROADCAST);
+    bytes[28..34].copy_from_slice(&mac_address.0);
+    write_u32(bytes, FIXED_SIZE, MAGIC_COOKIE);
+
+    FIXED_SIZE + 4
+}
+
+/// Writes the options the client wants the server to send.
+fn write_parameter_request_list(bytes: &mut [u8], offset: usize) -> usize {
+    write_option(
+        bytes,
+        offset,
+        OPTION_PARAMETER_REQUEST_LIST,
+        &[OPTION_SUBNET_MASK, OPTION_ROUTER, OPTION_LEASE_TIME],
+    )
+}
+
+/// Writes an option.
+///
+/// # Returns
+///
+/// The offset after the option.
+fn write_option(bytes: &mut [u8], offset: usize, code: u8, value: &[u8]) -> usize {
+    bytes[offset] = code;
+    bytes[offset + 1] = value.len() as u8;
+    bytes[offset + 2..offset + 2 + value.len()].copy_from_slice(value);
+
+    offset + 2 + value.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC_ADDRESS: MacAddress = MacAddress([0x52, 0x54, 0, 0x12, 0x34, 0x56]);
+
+    #[test]
+    fn test_write_discover() {
+        let mut bytes = [0xAA; MESSAGE_SIZE];
+        assert_eq!(
+            write_discover(&mut bytes, 0xDEAD_BEEF, MAC_ADDRESS),
+            MESSAGE_SIZE
+        );
+
+        assert_eq!(&bytes[..4], &[OPERATION_REQUEST, 1, 6, 0]);
+        assert_eq!(read_u32(&bytes, 4), 0xDEAD_BEEF);
+        assert_eq!(&bytes[28..34], &MAC_ADDRESS.0);
+        assert_eq!(read_u32(&bytes, FIXED_SIZE), MAGIC_COOKIE);
+        assert_eq!(&bytes[FIXED_SIZE + 4..FIXED_SIZE + 7], &[53, 1, 1]);
+        assert_eq!(bytes[MESSAGE_SIZE - 1], 0);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        // Turn a request into the server's acknowledgment.
+        let mut bytes = [0; MESSAGE_SIZE];
+        write_request(
+            &mut bytes,
+            7,
+            MAC_ADDRESS,
+            Ipv4Address([10, 0, 2, 15]),
+            Ipv4Address([10, 0, 2, 2]),
+        );
+
+        bytes[0] = OPERATION_REPLY;
+        bytes[16..20].copy_from_slice(&[10, 0, 2, 15]);
+
+        let mut offset = FIXED_SIZE + 4;
+        offset = write_option(&mut bytes, offset, OPTION_MESSAGE_TYPE, &[5]);
+        offset = write_option(&mut bytes, offset, OPTION_SERVER_IDENTIFIER, &[10, 0, 2, 2]);
+        bytes[offset] = OPTION_PAD;
+        offset = write_option(
+            &mut bytes,
+            offset + 1,
+            OPTION_SUBNET_MASK,
+            &[255, 255, 255, 0],
+        );
+        offset = write_option(
+            &mut bytes,
+            offset,
+            OPTION_ROUTER,
+            &[10, 0, 2, 2, 10, 0, 2, 3],
+        );
+        offset = write_option(
+            &mut bytes,
+            offset,
+            OPTION_LEASE_TIME,
+            &86400u32.to_be_bytes(),
+        );
+        bytes[offset] = OPTION_END;
+
+        let message = DhcpMessage::parse(&bytes).unwrap();
+        assert_eq!(message.message_type, MessageType::Acknowledge);
+        assert_eq!(message.transaction_id, 7);
+        assert_eq!(message.your_address, Ipv4Address([10, 0, 2, 15]));
+        assert_eq!(message.server_identifier, Some(Ipv4Address([10, 0, 2, 2])));
+        assert_eq!(message.subnet_mask, Some(Ipv4Address([255, 255, 255, 0])));
+        assert_eq!(message.router, Some(Ipv4Address([10, 0, 2, 2])));
+        assert_eq!(message.lease_time, Some(86400));
+
+        // An option that runs past the end of the message is malformed.
+        bytes[offset] = OPTION_ROUTER;
+        bytes[offset + 1] = 255;
+        assert_eq!(DhcpMessage::parse(&bytes), None);
+    }
+}
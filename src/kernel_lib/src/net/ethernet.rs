@@ -0,0 +1,85 @@
+//! Ethernet II frames.
+
+use super::{MacAddress, read_u16, write_u16};
+
+/// The size of the header: the destination and source addresses and the
+/// EtherType.
+pub const HEADER_SIZE: usize = 14;
+
+/// The EtherType of IPv4 packets.
+pub const ETHER_TYPE_IPV4: u16 = 0x0800;
+
+/// The EtherType of ARP packets.
+pub const ETHER_TYPE_ARP: u16 = 0x0806;
+
+/// A received frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EthernetFrame<'a> {
+    pub destination: MacAddress,
+    pub source: MacAddress,
+    pub ether_type: u16,
+
+    /// Everything after the header, which may include padding.
+    pub payload: &'a [u8],
+}
+
+impl<'a> EthernetFrame<'a> {
+    /// Parses a frame without its frame check sequence.
+    ///
+    /// # Returns
+    ///
+    /// The frame, or `None` if it is too short for a header.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE {
+            return None;
+        }
+
+        Some(Self {
+            destination: MacAddress(bytes[0..6].try_into().ok()?),
+            source: MacAddress(bytes[6..12].try_into().ok()?),
+            ether_type: read_u16(bytes, 12),
+            payload: &bytes[HEADER_SIZE..],
+        })
+    }
+}
+
+/// Writes a header at the start of a frame.
+///
+/// # Arguments
+///
+/// * `frame` - The frame, at least `HEADER_SIZE` bytes long.
+/// * `destination` - The address of the receiver.
+/// * `source` - The address of the sender.
+/// * `ether_type` - The type of the payload.
+pub fn write_header(
+    frame: &mut [u8],
+    destination: MacAddress,
+    source: MacAddress,
+    ether_type: u16,
+) {
+    frame[0..6].copy_from_slice(&destination.0);
+    frame[6..12].copy_from_slice(&source.0);
+    write_u16(frame, 12, ether_type);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        let mut frame = [0; HEADER_SIZE + 2];
+        let source = MacAddress([0x52, 0x54, 0, 0x12, 0x34, 0x56]);
+
+        write_header(&mut frame, MacAddress::BROADCAST, source, ETHER_TYPE_ARP);
+        frame[HEADER_SIZE..].copy_from_slice(b"hi");
+
+        let parsed = EthernetFrame::parse(&frame).unwrap();
+        assert_eq!(parsed.destination, MacAddress::BROADCAST);
+        assert_eq!(parsed.source, source);
+        assert_eq!(parsed.ether_type, ETHER_TYPE_ARP);
+        assert_eq!(parsed.payload, b"hi");
+
+        assert_eq!(EthernetFrame::parse(&frame[..HEADER_SIZE - 1]), None);
+    }
+}
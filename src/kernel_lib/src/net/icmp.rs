@@ -0,0 +1,127 @@
+//! ICMP echo messages, the messages of `ping`.
+
+use super::{Checksum, read_u16, write_u16};
+
+/// The size of an echo message header.
+pub const HEADER_SIZE: usize = 8;
+
+/// The type of echo replies.
+const TYPE_ECHO_REPLY: u8 = 0;
+
+/// The type of echo requests.
+const TYPE_ECHO_REQUEST: u8 = 8;
+
+/// A received echo request or reply.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EchoMessage<'a> {
+    /// True for a request, false for a reply.
+    pub is_request: bool,
+
+    /// Identifies the sender's session, which replies echo back.
+    pub identifier: u16,
+
+    /// Numbers the requests of a session, which replies echo back.
+    pub sequence: u16,
+
+    /// The data, which replies echo back.
+    pub data: &'a [u8],
+}
+
+impl<'a> EchoMessage<'a> {
+    /// Parses an echo message and checks its checksum.
+    ///
+    /// # Returns
+    ///
+    /// The message, or `None` if it is truncated, its checksum is wrong, or
+    /// it is another kind of ICMP message.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE || bytes[1] != 0 {
+            return None;
+        }
+
+        let is_request = match bytes[0] {
+            TYPE_ECHO_REQUEST => true,
+            TYPE_ECHO_REPLY => false,
+            _ => return None,
+        };
+
+        if Checksum::new().add_bytes(bytes).finish() != 0 {
+            return None;
+        }
+
+        Some(Self {
+            is_request,
+            identifier: read_u16(bytes, 4),
+            sequence: read_u16(bytes, 6),
+            data: &bytes[HEADER_SIZE..],
+        })
+    }
+}
+
+/// Writes an echo message.
+///
+/// # Arguments
+///
+/// * `bytes` - Receives the message, at least `HEADER_SIZE` bytes longer than
+///   the data.
+/// * `is_request` - True for a request, false for a reply.
+/// * `identifier` - Identifies the session.
+/// * `sequence` - Numbers the request within the session.
+/// * `data` - The data to echo.
+///
+/// # Returns
+///
+/// The size of the message.
+pub fn write_echo(
+    bytes: &mut [u8],
+    is_request: bool,
+    identifier: u16,
+    sequence: u16,
+    data: &[u8],
+) -> usize {
+    let size = HEADER_SIZE + data.len();
+
+    bytes[0] = if is_request {
+        TYPE_ECHO_REQUEST
+    } else {
+        TYPE_ECHO_REPLY
+    };
+    bytes[1] = 0;
+    write_u16(bytes, 2, 0);
+    write_u16(bytes, 4, identifier);
+    write_u16(bytes, 6, sequence);
+    bytes[HEADER_SIZE..size].copy_from_slice(data);
+
+    let checksum = Checksum::new().add_bytes(&bytes[..size]).finish();
+    write_u16(bytes, 2, checksum);
+
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo_round_trip() {
+        let mut bytes = [0; 32];
+        let size = write_echo(&mut bytes, true, 0x1234, 3, b"ping!");
+        assert_eq!(size, HEADER_SIZE + 5);
+
+        let message = EchoMessage::parse(&bytes[..size]).unwrap();
+        assert!(message.is_request);
+        assert_eq!(message.identifier, 0x1234);
+        assert_eq!(message.sequence, 3);
+        assert_eq!(message.data, b"ping!");
+
+        bytes[HEADER_SIZE] ^= 1;
+        assert_eq!(EchoMessage::parse(&bytes[..size]), None);
+
+        let size = write_echo(&mut bytes, false, 1, 2, &[]);
+        assert!(!EchoMessage::parse(&bytes[..size]).unwrap().is_request);
+
+        // Destination unreachable is not an echo message.
+        bytes[0] = 3;
+        assert_eq!(EchoMessage::parse(&bytes[..size]), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! IPv4 packets.
+//!
+//! Packets are sent without options and never fragmented. Received fragments
+//! are not reassembled but rejected by `Ipv4Packet::parse`.
+
+use super::{Checksum, Ipv4Address, read_u16, write_u16};
+
+/// The size of a header without options.
+pub const HEADER_SIZE: usize = 20;
+
+/// The protocol number of ICMP.
+pub const PROTOCOL_ICMP: u8 = 1;
+
+/// The protocol number of UDP.
+pub const PROTOCOL_UDP: u8 = 17;
+
+/// The time to live of sent packets.
+const DEFAULT_TIME_TO_LIVE: u8 = 64;
+
+/// The flag asking routers not to fragment the packet.
+const FLAG_DONT_FRAGMENT: u16 = 0x4000;
+
+/// The flag marking that more fragments follow, and the mask of the fragment
+/// offset.
+const FLAG_MORE_FRAGMENTS: u16 = 0x2000;
+const FRAGMENT_OFFSET_MASK: u16 = 0x1FFF;
+
+/// A received packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Ipv4Packet<'a> {
+    pub source: Ipv4Address,
+    pub destination: Ipv4Address,
+    pub protocol: u8,
+
+    /// The payload, without any padding of the frame that carried it.
+    pub payload: &'a [u8],
+}
+
+impl<'a> Ipv4Packet<'a> {
+    /// Parses a packet and checks its header checksum.
+    ///
+    /// # Returns
+    ///
+    /// The packet, or `None` if it is malformed, its checksum is wrong, or it
+    /// is a fragment.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE || bytes[0] >> 4 != 4 {
+            return None;
+        }
+
+        let header_size = (bytes[0] & 0xF) as usize * 4;
+        let total_length = read_u16(bytes, 2) as usize;
+
+        if header_size < HEADER_SIZE || total_length < header_size || total_length > bytes.len() {
+            return None;
+        }
+
+        if Checksum::new().add_bytes(&bytes[..header_size]).finish() != 0 {
+            return None;
+        }
+
+        let fragment = read_u16(bytes, 6);
+        if fragment & (FLAG_MORE_FRAGMENTS | FRAGMENT_OFFSET_MASK) != 0 {
+            return None;
+        }
+
+        Some(Self {
+            source: Ipv4Address(bytes[12..16].try_into().ok()?),
+            destination: Ipv4Address(bytes[16..20].try_into().ok()?),
+            protocol: bytes[9],
+            payload: &bytes[header_size..total_length],
+        })
+    }
+}
+
+/// Writes a header without options at the start of a packet.
+///
+/// # Arguments
+///
+/// * `packet` - The packet, at least `HEADER_SIZE` bytes long.
+/// * `source` - The address of the sender.
+/// * `destination` - The address of the receiver.
+/// * `protocol` - The protocol of the payload.
+/// * `payload_length` - The length of the payload, which follows the header.
+/// * `identification` - A value that differs between the packets a host
+///   sends to the same destination.
+pub fn write_header(
+    packet: &mut [u8],
+    source: Ipv4Address,
+    destination: Ipv4Address,
+    protocol: u8,
+    payload_length: usize,
+    identification: u16,
+) {
+    packet[0] = 0x45;
+    packet[1] = 0;
+    write_u16(packet, 2, (HEADER_SIZE + payload_length) as u16);
+    write_u16(packet, 4, identification);
+    write_u16(packet, 6, FLAG_DONT_FRAGMENT);
+    packet[8] = DEFAULT_TIME_TO_LIVE;
+    packet[9] = protocol;
+    write_u16(packet, 10, 0);
+    packet[12..16].copy_from_slice(&source.0);
+    packet[16..20].copy_from_slice(&destination.0);
+
+    let checksum = Checksum::new().add_bytes(&packet[..HEADER_SIZE]).finish();
+    write_u16(packet, 10, checksum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        let source = Ipv4Address([10, 0, 2, 15]);
+        let destination = Ipv4Address([10, 0, 2, 2]);
+
+        // Frames may pad short packets.
+        let mut packet = [0; HEADER_SIZE + 8];
+        write_header(&mut packet, source, destination, PROTOCOL_UDP, 4, 7);
+        packet[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(b"data");
+
+        let parsed = Ipv4Packet::parse(&packet).unwrap();
+        assert_eq!(parsed.source, source);
+        assert_eq!(parsed.destination, destination);
+        assert_eq!(parsed.protocol, PROTOCOL_UDP);
+        assert_eq!(parsed.payload, b"data");
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_packets() {
+        let mut packet = [0; HEADER_SIZE];
+        write_header(
+            &mut packet,
+            Ipv4Address::UNSPECIFIED,
+            Ipv4Address::BROADCAST,
+            PROTOCOL_ICMP,
+            0,
+            1,
+        );
+        assert!(Ipv4Packet::parse(&packet).is_some());
+
+        let mut corrupt = packet;
+        corrupt[8] -= 1;
+        assert_eq!(Ipv4Packet::parse(&corrupt), None);
+
+        let mut fragment = packet;
+        write_u16(&mut fragment, 6, FLAG_MORE_FRAGMENTS);
+        write_u16(&mut fragment, 10, 0);
+        let checksum = Checksum::new().add_bytes(&fragment).finish();
+        write_u16(&mut fragment, 10, checksum);
+        assert_eq!(Ipv4Packet::parse(&fragment), None);
+
+        assert_eq!(Ipv4Packet::parse(&packet[..HEADER_SIZE - 1]), None);
+    }
+}
@@ -0,0 +1,251 @@
+//! Network protocol formats.
+//!
+//! Each submodule parses and builds the packets of one protocol in byte
+//! slices, without allocating, so the kernel's network stack only moves
+//! bytes between the device and its sockets. Multi-byte fields are in network
+//! byte order, big-endian.
+
+pub mod arp;
+pub mod dhcp;
+pub mod ethernet;
+pub mod icmp;
+pub mod ipv4;
+pub mod udp;
+
+use core::fmt;
+
+/// An Ethernet hardware address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl MacAddress {
+    /// The address every station receives frames for.
+    pub const BROADCAST: Self = Self([0xFF; 6]);
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, byte) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ":")?;
+            }
+
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An IPv4 address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Ipv4Address(pub [u8; 4]);
+
+impl Ipv4Address {
+    /// The address of a host that has none yet, 0.0.0.0.
+    pub const UNSPECIFIED: Self = Self([0; 4]);
+
+    /// The limited broadcast address, 255.255.255.255.
+    pub const BROADCAST: Self = Self([0xFF; 4]);
+
+    /// Parses a dotted decimal address such as "10.0.2.15".
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut octets = [0; 4];
+        let mut parts = text.split('.');
+
+        for octet in octets.iter_mut() {
+            let part = parts.next()?;
+
+            // Leading signs and empty parts are not allowed.
+            if part.is_empty() || !part.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+
+            *octet = part.parse().ok()?;
+        }
+
+        parts.next().is_none().then_some(Self(octets))
+    }
+
+    /// Parses an address with a prefix length, such as "10.0.2.15/24".
+    ///
+    /// # Returns
+    ///
+    /// The address and the prefix length.
+    pub fn parse_with_prefix(text: &str) -> Option<(Self, u8)> {
+        let (address, prefix_length) = text.split_once('/')?;
+        let prefix_length: u8 = prefix_length.parse().ok()?;
+
+        (prefix_length <= 32).then_some((Self::parse(address)?, prefix_length))
+    }
+
+    /// Returns the subnet mask of a prefix length.
+    pub fn mask(prefix_length: u8) -> Self {
+        let mask = u32::MAX.checked_shl(32 - prefix_length as u32).unwrap_or(0);
+
+        Self(mask.to_be_bytes())
+    }
+
+    /// Returns the prefix length of a subnet mask, or `None` if its ones are
+    /// not contiguous.
+    pub fn prefix_length(&self) -> Option<u8> {
+        let mask = u32::from_be_bytes(self.0);
+        let prefix_length = mask.leading_ones();
+
+        (mask.checked_shl(prefix_length).unwrap_or(0) == 0).then_some(prefix_length as u8)
+    }
+
+    /// Returns true if two addresses are in the same subnet.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The other address.
+    /// * `prefix_length` - The length of the subnet prefix.
+    pub fn is_in_subnet(&self, other: Self, prefix_length: u8) -> bool {
+        let mask = u32::from_be_bytes(Self::mask(prefix_length).0);
+
+        u32::from_be_bytes(self.0) & mask == u32::from_be_bytes(other.0) & mask
+    }
+}
+
+impl fmt::Display for Ipv4Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+/// The Internet checksum of RFC 1071, the ones' complement of the ones'
+/// complement sum of 16 bit words, used by IPv4, ICMP, and UDP.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Checksum {
+    sum: u64,
+
+    /// True if an odd number of bytes was added, so the next byte is the low
+    /// byte of a word.
+    is_odd: bool,
+}
+
+impl Checksum {
+    /// Creates a checksum of no bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds bytes to the checksum.
+    pub fn add_bytes(mut self, bytes: &[u8]) -> Self {
+        for &byte in bytes {
+            self.sum += if self.is_odd {
+                byte as u64
+            } else {
+                (byte as u64) << 8
+            };
+
+            self.is_odd = !self.is_odd;
+        }
+
+        self
+    }
+
+    /// Returns the checksum to place in a header.
+    pub fn finish(self) -> u16 {
+        let mut sum = self.sum;
+
+        while sum > 0xFFFF {
+            sum = (sum & 0xFFFF) + (sum >> 16);
+        }
+
+        !(sum as u16)
+    }
+}
+
+/// Reads a big-endian u16 at an offset.
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Reads a big-endian u32 at an offset.
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Writes a big-endian u16 at an offset.
+fn write_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+}
+
+/// Writes a big-endian u32 at an offset.
+fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4_address_parse_and_display() {
+        let address = Ipv4Address::parse("10.0.2.15").unwrap();
+        assert_eq!(address, Ipv4Address([10, 0, 2, 15]));
+        assert_eq!(address.to_string(), "10.0.2.15");
+
+        assert_eq!(Ipv4Address::parse("10.0.2"), None);
+        assert_eq!(Ipv4Address::parse("10.0.2.15.1"), None);
+        assert_eq!(Ipv4Address::parse("10.0.2.256"), None);
+        assert_eq!(Ipv4Address::parse("10.0..15"), None);
+        assert_eq!(Ipv4Address::parse("10.0.+2.15"), None);
+
+        assert_eq!(
+            Ipv4Address::parse_with_prefix("10.0.2.15/24"),
+            Some((address, 24))
+        );
+        assert_eq!(Ipv4Address::parse_with_prefix("10.0.2.15/33"), None);
+        assert_eq!(Ipv4Address::parse_with_prefix("10.0.2.15"), None);
+    }
+
+    #[test]
+    fn test_ipv4_subnets() {
+        assert_eq!(Ipv4Address::mask(24), Ipv4Address([255, 255, 255, 0]));
+        assert_eq!(Ipv4Address::mask(0), Ipv4Address::UNSPECIFIED);
+        assert_eq!(Ipv4Address::mask(32), Ipv4Address::BROADCAST);
+
+        assert_eq!(Ipv4Address([255, 255, 240, 0]).prefix_length(), Some(20));
+        assert_eq!(Ipv4Address::UNSPECIFIED.prefix_length(), Some(0));
+        assert_eq!(Ipv4Address([255, 0, 255, 0]).prefix_length(), None);
+
+        let address = Ipv4Address([10, 0, 2, 15]);
+        assert!(address.is_in_subnet(Ipv4Address([10, 0, 2, 2]), 24));
+        assert!(!address.is_in_subnet(Ipv4Address([10, 0, 3, 2]), 24));
+        assert!(address.is_in_subnet(Ipv4Address([192, 168, 0, 1]), 0));
+    }
+
+    #[test]
+    fn test_mac_address_display() {
+        assert_eq!(
+            MacAddress([0x52, 0x54, 0, 0x12, 0x34, 0x56]).to_string(),
+            "52:54:00:12:34:56"
+        );
+    }
+
+    #[test]
+    fn test_checksum_matches_rfc_1071_example() {
+        let bytes = [0x00, 0x01, 0xF2, 0x03, 0xF4, 0xF5, 0xF6, 0xF7];
+        assert_eq!(Checksum::new().add_bytes(&bytes).finish(), !0xDDF2);
+
+        // Splitting the bytes at an odd offset does not change the sum.
+        let split = Checksum::new()
+            .add_bytes(&bytes[..3])
+            .add_bytes(&bytes[3..])
+            .finish();
+        assert_eq!(split, !0xDDF2);
+
+        // An odd length is padded with a zero byte.
+        assert_eq!(Checksum::new().add_bytes(&[0x12]).finish(), !0x1200);
+    }
+}
@@ -0,0 +1,128 @@
+//! UDP datagrams.
+
+use super::{Checksum, Ipv4Address, ipv4::PROTOCOL_UDP, read_u16, write_u16};
+
+/// The size of the header.
+pub const HEADER_SIZE: usize = 8;
+
+/// A received datagram.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UdpDatagram<'a> {
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> UdpDatagram<'a> {
+    /// Parses a datagram and checks its checksum, if the sender computed one.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The payload of the IPv4 packet that carried the datagram.
+    /// * `source` - The source address of the packet.
+    /// * `destination` - The destination address of the packet.
+    ///
+    /// # Returns
+    ///
+    /// The datagram, or `None` if it is malformed or its checksum is wrong.
+    pub fn parse(bytes: &'a [u8], source: Ipv4Address, destination: Ipv4Address) -> Option<Self> {
+        if bytes.len() < HEADER_SIZE {
+            return None;
+        }
+
+        let length = read_u16(bytes, 4) as usize;
+        if length < HEADER_SIZE || length > bytes.len() {
+            return None;
+        }
+
+        let bytes = &bytes[..length];
+
+        // A checksum of zero means the sender did not compute one.
+        if read_u16(bytes, 6) != 0 && checksum(bytes, source, destination) != 0 {
+            return None;
+        }
+
+        Some(Self {
+            source_port: read_u16(bytes, 0),
+            destination_port: read_u16(bytes, 2),
+            payload: &bytes[HEADER_SIZE..],
+        })
+    }
+}
+
+/// Writes a header at the start of a datagram and computes its checksum.
+///
+/// # Arguments
+///
+/// * `datagram` - The datagram, with the payload already after the header.
+///   Its length is the size of the datagram.
+/// * `source` - The source address of the packet that will carry it.
+/// * `destination` - The destination address of the packet.
+/// * `source_port` - The port of the sender.
+/// * `destination_port` - The port of the receiver.
+pub fn write_header(
+    datagram: &mut [u8],
+    source: Ipv4Address,
+    destination: Ipv4Address,
+    source_port: u16,
+    destination_port: u16,
+) {
+    write_u16(datagram, 0, source_port);
+    write_u16(datagram, 2, destination_port);
+    write_u16(datagram, 4, datagram.len() as u16);
+    write_u16(datagram, 6, 0);
+
+    // A computed checksum of zero is sent as all ones, since zero means none.
+    let checksum = match checksum(datagram, source, destination) {
+        0 => 0xFFFF,
+        checksum => checksum,
+    };
+
+    write_u16(datagram, 6, checksum);
+}
+
+/// Returns the checksum of a datagram and the IPv4 pseudo-header before it.
+fn checksum(datagram: &[u8], source: Ipv4Address, destination: Ipv4Address) -> u16 {
+    let length = (datagram.len() as u16).to_be_bytes();
+
+    Checksum::new()
+        .add_bytes(&source.0)
+        .add_bytes(&destination.0)
+        .add_bytes(&[0, PROTOCOL_UDP])
+        .add_bytes(&length)
+        .add_bytes(datagram)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datagram_round_trip() {
+        let source = Ipv4Address([10, 0, 2, 15]);
+        let destination = Ipv4Address([10, 0, 2, 2]);
+
+        let mut datagram = [0; HEADER_SIZE + 5];
+        datagram[HEADER_SIZE..].copy_from_slice(b"hello");
+        write_header(&mut datagram, source, destination, 1234, 53);
+
+        let parsed = UdpDatagram::parse(&datagram, source, destination).unwrap();
+        assert_eq!(parsed.source_port, 1234);
+        assert_eq!(parsed.destination_port, 53);
+        assert_eq!(parsed.payload, b"hello");
+
+        // The pseudo-header covers the addresses.
+        let other = Ipv4Address([10, 0, 2, 3]);
+        assert_eq!(UdpDatagram::parse(&datagram, source, other), None);
+
+        // Without a checksum, any addresses are accepted.
+        write_u16(&mut datagram, 6, 0);
+        assert!(UdpDatagram::parse(&datagram, source, other).is_some());
+
+        assert_eq!(
+            UdpDatagram::parse(&datagram[..HEADER_SIZE + 4], source, destination),
+            None
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! A cryptographically secure random number generator built on ChaCha20.
+//!
+//! `EntropyPool` keeps a 256 bit ChaCha20 key. Entropy is mixed in by adding
+//! it to the key and deriving a new key from a ChaCha20 block. Random bytes
+//! are the keystream of the current key, which is replaced after every
+//! request by a block that is never output, so earlier output cannot be
+//! reconstructed from a later state of the pool.
+
+/// The size of a ChaCha20 key.
+pub const KEY_SIZE: usize = 32;
+
+/// The size of a ChaCha20 block.
+pub const BLOCK_SIZE: usize = 64;
+
+/// The number of credited entropy bytes after which the pool is seeded.
+pub const SEED_SIZE: usize = KEY_SIZE;
+
+/// The first words of the ChaCha20 state, "expand 32-byte k".
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646E, 0x7962_2D32, 0x6B20_6574];
+
+/// The nonce of the blocks that mix entropy into the key. Output blocks
+/// never use it, since the last four bytes of their nonce are zero.
+const MIX_NONCE: [u8; 12] = [0xFF; 12];
+
+/// Runs a ChaCha20 quarter round on four words of the state.
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// Computes a ChaCha20 block as described in RFC 8439.
+///
+/// # Arguments
+///
+/// * `key` - The key.
+/// * `counter` - The block counter.
+/// * `nonce` - The nonce.
+pub fn chacha20_block(key: &[u8; KEY_SIZE], counter: u32, nonce: &[u8; 12]) -> [u8; BLOCK_SIZE] {
+    let word = |bytes: &[u8], index: usize| {
+        u32::from_le_bytes([
+            bytes[index * 4],
+            bytes[index * 4 + 1],
+            bytes[index * 4 + 2],
+            bytes[index * 4 + 3],
+        ])
+    };
+
+    let mut initial_state = [0; 16];
+    initial_state[..4].copy_from_slice(&CONSTANTS);
+
+    for index in 0..8 {
+        initial_state[4 + index] = word(key, index);
+    }
+
+    initial_state[12] = counter;
+
+    for index in 0..3 {
+        initial_state[13 + index] = word(nonce, index);
+    }
+
+    let mut state = initial_state;
+
+    // Ten double rounds of a column round and a diagonal round.
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut block = [0; BLOCK_SIZE];
+
+    for (index, (word, initial_word)) in state.iter().zip(initial_state).enumerate() {
+        block[index * 4..index * 4 + 4]
+            .copy_from_slice(&word.wrapping_add(initial_word).to_le_bytes());
+    }
+
+    block
+}
+
+/// A pool that gathers entropy and produces random bytes from it.
+#[derive(Debug, Clone)]
+pub struct EntropyPool {
+    key: [u8; KEY_SIZE],
+
+    /// The number of requests served, which is the nonce of the blocks of
+    /// the next one.
+    generation: u64,
+
+    /// The number of bytes mixed in that were credited as entropy.
+    credited_bytes: usize,
+}
+
+impl EntropyPool {
+    /// Creates a pool without entropy.
+    pub const fn new() -> Self {
+        Self {
+            key: [0; KEY_SIZE],
+            generation: 0,
+            credited_bytes: 0,
+        }
+    }
+
+    /// Returns true once `SEED_SIZE` bytes of entropy have been credited.
+    pub fn is_seeded(&self) -> bool {
+        self.credited_bytes >= SEED_SIZE
+    }
+
+    /// Mixes bytes into the pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes to mix in. Bytes that are not random do no
+    ///   harm.
+    /// * `credited_bytes` - How many bytes of entropy the bytes are worth,
+    ///   such as their length for bytes from a hardware generator, or 0 for
+    ///   guessable values like timestamps.
+    pub fn add_entropy(&mut self, bytes: &[u8], credited_bytes: usize) {
+        for chunk in bytes.chunks(KEY_SIZE) {
+            for (key_byte, byte) in self.key.iter_mut().zip(chunk) {
+                *key_byte ^= byte;
+            }
+
+            let block = chacha20_block(&self.key, 0, &MIX_NONCE);
+            self.key.copy_from_slice(&block[..KEY_SIZE]);
+        }
+
+        self.credited_bytes = self.credited_bytes.saturating_add(credited_bytes);
+    }
+
+    /// Fills a buffer with random bytes and replaces the key.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The buffer to fill.
+    pub fn fill_bytes(&mut self, buffer: &mut [u8]) {
+        let mut nonce = [0; 12];
+        nonce[..8].copy_from_slice(&self.generation.to_le_bytes());
+
+        // Block 0 becomes the next key, so it is never output.
+        for (index, chunk) in buffer.chunks_mut(BLOCK_SIZE).enumerate() {
+            let block = chacha20_block(&self.key, index as u32 + 1, &nonce);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+
+        let block = chacha20_block(&self.key, 0, &nonce);
+        self.key.copy_from_slice(&block[..KEY_SIZE]);
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Returns a random u64 and replaces the key.
+    pub fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+
+        u64::from_le_bytes(bytes)
+    }
+}
+
+impl Default for EntropyPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chacha20_block_matches_rfc_8439() {
+        let key: [u8; KEY_SIZE] = core::array::from_fn(|index| index as u8);
+        let nonce = [0, 0, 0, 0x09, 0, 0, 0, 0x4A, 0, 0, 0, 0];
+
+        let expected = [
+            0x10, 0xF1, 0xE7, 0xE4, 0xD1, 0x3B, 0x59, 0x15, 0x50, 0x0F, 0xDD, 0x1F, 0xA3, 0x20,
+            0x71, 0xC4, 0xC7, 0xD1, 0xF4, 0xC7, 0x33, 0xC0, 0x68, 0x03, 0x04, 0x22, 0xAA, 0x9A,
+            0xC3, 0xD4, 0x6C, 0x4E, 0xD2, 0x82, 0x64, 0x46, 0x07, 0x9F, 0xAA, 0x09, 0x14, 0xC2,
+            0xD7, 0x05, 0xD9, 0x8B, 0x02, 0xA2, 0xB5, 0x12, 0x9C, 0xD1, 0xDE, 0x16, 0x4E, 0xB9,
+            0xCB, 0xD0, 0x83, 0xE8, 0xA2, 0x50, 0x3C, 0x4E,
+        ];
+
+        assert_eq!(chacha20_block(&key, 1, &nonce), expected);
+    }
+
+    #[test]
+    fn test_pool_output_depends_on_entropy() {
+        let mut first = EntropyPool::new();
+        let mut second = EntropyPool::new();
+
+        first.add_entropy(b"seed", 4);
+        second.add_entropy(b"seed", 4);
+        assert!(!first.is_seeded());
+
+        let mut first_bytes = [0; 100];
+        let mut second_bytes = [0; 100];
+        first.fill_bytes(&mut first_bytes);
+        second.fill_bytes(&mut second_bytes);
+        assert_eq!(first_bytes, second_bytes);
+
+        // The key is replaced after every request.
+        first.fill_bytes(&mut second_bytes);
+        assert_ne!(first_bytes, second_bytes);
+
+        let mut third = EntropyPool::new();
+        third.add_entropy(b"other seed", 0);
+        assert_ne!(third.next_u64(), EntropyPool::new().next_u64());
+
+        third.add_entropy(&[0x5A; SEED_SIZE], SEED_SIZE);
+        assert!(third.is_seeded());
+    }
+}
@@ -0,0 +1,153 @@
+//! Fixed capacity double-ended queues.
+//!
+//! A `RingBuffer` keeps its values in a fixed array, so it can live in a
+//! `static` behind a lock. Unlike `sync::BoundedQueue` it is not lock-free,
+//! but values can be put back at the front, such as a request a device could
+//! not take yet.
+
+/// Up to `CAPACITY` values in first in, first out order.
+#[derive(Debug, Copy, Clone)]
+pub struct RingBuffer<T: Copy, const CAPACITY: usize> {
+    values: [Option<T>; CAPACITY],
+
+    /// The index in `values` of the front value.
+    head: usize,
+
+    /// The number of values, which follow `head` and wrap around.
+    count: usize,
+}
+
+impl<T: Copy, const CAPACITY: usize> RingBuffer<T, CAPACITY> {
+    /// Creates an empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            values: [None; CAPACITY],
+            head: 0,
+            count: 0,
+        }
+    }
+
+    /// Returns the number of values in the buffer.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns true if the buffer holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns true if the buffer holds `CAPACITY` values.
+    pub fn is_full(&self) -> bool {
+        self.count == CAPACITY
+    }
+
+    /// Adds a value at the back.
+    ///
+    /// # Returns
+    ///
+    /// The value back if the buffer is full.
+    pub fn push_back(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+
+        self.values[(self.head + self.count) % CAPACITY] = Some(value);
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// Adds a value at the front, so it is popped next.
+    ///
+    /// # Returns
+    ///
+    /// The value back if the buffer is full.
+    pub fn push_front(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+
+        self.head = (self.head + CAPACITY - 1) % CAPACITY;
+        self.values[self.head] = Some(value);
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// Removes the value at the front.
+    ///
+    /// # Returns
+    ///
+    /// The value, or `None` if the buffer is empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let value = self.values[self.head].take();
+        self.head = (self.head + 1) % CAPACITY;
+        self.count -= 1;
+
+        value
+    }
+}
+
+impl<T: Copy, const CAPACITY: usize> Default for RingBuffer<T, CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_come_out_in_order() {
+        let mut buffer = RingBuffer::<u32, 4>::new();
+
+        buffer.push_back(1).unwrap();
+        buffer.push_back(2).unwrap();
+        assert_eq!(buffer.pop_front(), Some(1));
+
+        // The values wrap around the end of the array.
+        buffer.push_back(3).unwrap();
+        buffer.push_back(4).unwrap();
+        buffer.push_back(5).unwrap();
+
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.pop_front(), Some(2));
+        assert_eq!(buffer.pop_front(), Some(3));
+        assert_eq!(buffer.pop_front(), Some(4));
+        assert_eq!(buffer.pop_front(), Some(5));
+        assert_eq!(buffer.pop_front(), None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_push_front_is_popped_next() {
+        let mut buffer = RingBuffer::<u32, 4>::new();
+
+        buffer.push_back(1).unwrap();
+        buffer.push_back(2).unwrap();
+        buffer.push_front(0).unwrap();
+
+        assert_eq!(buffer.pop_front(), Some(0));
+        assert_eq!(buffer.pop_front(), Some(1));
+        assert_eq!(buffer.pop_front(), Some(2));
+    }
+
+    #[test]
+    fn test_full_buffer_rejects_values() {
+        let mut buffer = RingBuffer::<u32, 2>::new();
+
+        buffer.push_back(1).unwrap();
+        buffer.push_front(0).unwrap();
+
+        assert!(buffer.is_full());
+        assert_eq!(buffer.push_back(2), Err(2));
+        assert_eq!(buffer.push_front(3), Err(3));
+        assert_eq!(buffer.pop_front(), Some(0));
+    }
+}
@@ -0,0 +1,244 @@
+//! Reader for the symbol table embedded in the kernel image.
+//!
+//! The build generates the table from the linked kernel ELF with
+//! `scripts/generate-symbol-table.sh` and links it into the `.kernel_symbols`
+//! section. All values are little endian:
+//!
+//! ```text
+//! offset 0:  magic, "KSYM" (u32)
+//! offset 4:  symbol count (u32)
+//! offset 8:  entries sorted by address, 16 bytes each:
+//!              address (u64), size in bytes (u32), name offset (u32)
+//! after the entries: the names, each terminated by a zero byte
+//! ```
+//!
+//! Name offsets are relative to the first name. A size of zero means the size
+//! is unknown, which is the case for labels defined in assembly.
+
+/// The value of the first word of a symbol table.
+pub const SYMBOL_TABLE_MAGIC: u32 = u32::from_le_bytes(*b"KSYM");
+
+/// The size of the header in front of the entries.
+const HEADER_SIZE: usize = 8;
+
+/// The size of a single entry.
+const ENTRY_SIZE: usize = 16;
+
+/// A function found in a symbol table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    /// The demangled name of the function.
+    pub name: &'a str,
+
+    /// The address of the first instruction of the function.
+    pub address: u64,
+
+    /// The size of the function in bytes, or zero if it is unknown.
+    pub size: u64,
+}
+
+/// A parsed view of a symbol table.
+#[derive(Debug, Clone, Copy)]
+pub struct SymbolTable<'a> {
+    entries: &'a [u8],
+    names: &'a [u8],
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Validates the header of a symbol table.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The contents of the symbol table section.
+    ///
+    /// # Returns
+    ///
+    /// The symbol table, or `None` if the bytes are empty, do not start with
+    /// `SYMBOL_TABLE_MAGIC`, or are too short for the entries they claim.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        let magic = read_u32(bytes, 0)?;
+        let symbol_count = read_u32(bytes, 4)? as usize;
+
+        if magic != SYMBOL_TABLE_MAGIC {
+            return None;
+        }
+
+        let names_offset = symbol_count
+            .checked_mul(ENTRY_SIZE)?
+            .checked_add(HEADER_SIZE)?;
+
+        Some(Self {
+            entries: bytes.get(HEADER_SIZE..names_offset)?,
+            names: &bytes[names_offset..],
+        })
+    }
+
+    /// Returns the number of symbols in the table.
+    pub fn len(&self) -> usize {
+        self.entries.len() / ENTRY_SIZE
+    }
+
+    /// Returns true if the table holds no symbols.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the symbol at an index of the table.
+    ///
+    /// # Returns
+    ///
+    /// The symbol, or `None` if the index is out of range or the name is not
+    /// valid.
+    pub fn get(&self, index: usize) -> Option<Symbol<'a>> {
+        let offset = index.checked_mul(ENTRY_SIZE)?;
+
+        let address = read_u64(self.entries, offset)?;
+        let size = read_u32(self.entries, offset + 8)? as u64;
+        let name_offset = read_u32(self.entries, offset + 12)? as usize;
+
+        let name_bytes = self.names.get(name_offset..)?;
+        let name_length = name_bytes.iter().position(|&byte| byte == 0)?;
+        let name = core::str::from_utf8(&name_bytes[..name_length]).ok()?;
+
+        Some(Symbol {
+            name,
+            address,
+            size,
+        })
+    }
+
+    /// Finds the function that contains an address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Any address, such as a return address from a backtrace.
+    ///
+    /// # Returns
+    ///
+    /// The symbol with the highest address at or below `address`, or `None`
+    /// if there is none or `address` lies past the end of its known size.
+    pub fn lookup(&self, address: u64) -> Option<Symbol<'a>> {
+        let address_at =
+            |index: usize| read_u64(self.entries, index * ENTRY_SIZE).unwrap_or(u64::MAX);
+
+        // Binary search for the number of symbols starting at or before the
+        // address.
+        let mut low = 0;
+        let mut high = self.len();
+
+        while low < high {
+            let middle = low + (high - low) / 2;
+
+            if address_at(middle) <= address {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        let symbol = self.get(low.checked_sub(1)?)?;
+
+        if symbol.size != 0 && address - symbol.address >= symbol.size {
+            return None;
+        }
+
+        Some(symbol)
+    }
+}
+
+/// Reads a little endian u32 at an offset, or `None` if it is out of bounds.
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let field = bytes.get(offset..offset.checked_add(4)?)?;
+
+    Some(u32::from_le_bytes(field.try_into().ok()?))
+}
+
+/// Reads a little endian u64 at an offset, or `None` if it is out of bounds.
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let field = bytes.get(offset..offset.checked_add(8)?)?;
+
+    Some(u64::from_le_bytes(field.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Builds a table in the format the build script emits.
+    fn build_table(symbols: &[(u64, u32, &str)]) -> Vec<u8> {
+        let mut table = Vec::new();
+        let mut names = Vec::new();
+
+        table.extend_from_slice(&SYMBOL_TABLE_MAGIC.to_le_bytes());
+        table.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+
+        for &(address, size, name) in symbols {
+            table.extend_from_slice(&address.to_le_bytes());
+            table.extend_from_slice(&size.to_le_bytes());
+            table.extend_from_slice(&(names.len() as u32).to_le_bytes());
+
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+
+        table.extend_from_slice(&names);
+        table
+    }
+
+    #[test]
+    fn test_lookup() {
+        let bytes = build_table(&[
+            (0x1000, 0x40, "kernel::kernel_main"),
+            (0x1040, 0, "_kernel_entrypoint"),
+            (0x1100, 0x20, "kernel::trap::handle_trap"),
+        ]);
+        let table = SymbolTable::parse(&bytes).unwrap();
+
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.lookup(0xfff), None);
+        assert_eq!(table.lookup(0x1000).unwrap().name, "kernel::kernel_main");
+        assert_eq!(table.lookup(0x1042).unwrap().name, "_kernel_entrypoint");
+
+        // Symbols without a size extend up to the next symbol.
+        assert_eq!(table.lookup(0x10ff).unwrap().name, "_kernel_entrypoint");
+
+        let symbol = table.lookup(0x111f).unwrap();
+        assert_eq!(
+            symbol,
+            Symbol {
+                name: "kernel::trap::handle_trap",
+                address: 0x1100,
+                size: 0x20
+            }
+        );
+
+        // Addresses past the end of a sized symbol are not symbolized.
+        assert_eq!(table.lookup(0x1120), None);
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_tables() {
+        assert!(SymbolTable::parse(&[]).is_none());
+
+        let mut bytes = build_table(&[(0x1000, 0x40, "kernel::kernel_main")]);
+        assert!(SymbolTable::parse(&bytes[..HEADER_SIZE + ENTRY_SIZE - 1]).is_none());
+
+        bytes[0] ^= 0xff;
+        assert!(SymbolTable::parse(&bytes).is_none());
+
+        let empty = build_table(&[]);
+        let table = SymbolTable::parse(&empty).unwrap();
+        assert!(table.is_empty());
+        assert_eq!(table.lookup(0x1000), None);
+    }
+
+    #[test]
+    fn test_invalid_name_offset() {
+        let mut bytes = build_table(&[(0x1000, 0x40, "kernel::kernel_main")]);
+        bytes[HEADER_SIZE + 12..HEADER_SIZE + 16].copy_from_slice(&0x1000u32.to_le_bytes());
+
+        let table = SymbolTable::parse(&bytes).unwrap();
+        assert_eq!(table.lookup(0x1000), None);
+    }
+}
@@ -0,0 +1,306 @@
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A single slot in a `BoundedQueue`.
+struct Slot<T> {
+    /// Tracks the state of the slot relative to the queue positions.
+    ///
+    /// A slot at index `i` with sequence `s` is free for the producer claiming
+    /// position `s` when `s == position`, and holds a value for the consumer at
+    /// position `p` when `s == p + 1`.
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A fixed capacity, lock-free, multi-producer multi-consumer queue.
+///
+/// The queue never allocates, so it can be placed in a `static` and used from
+/// trap handlers. Each slot carries a sequence number that lets producers and
+/// consumers claim positions with a single compare-and-swap, following Dmitry
+/// Vyukov's bounded queue design.
+///
+/// `CAPACITY` must be a power of two.
+pub struct BoundedQueue<T, const CAPACITY: usize> {
+    slots: [Slot<T>; CAPACITY],
+
+    /// The next position a producer will write.
+    enqueue_position: AtomicUsize,
+
+    /// The next position a consumer will read.
+    dequeue_position: AtomicUsize,
+}
+
+unsafe impl<T: Send, const CAPACITY: usize> Sync for BoundedQueue<T, CAPACITY> {}
+unsafe impl<T: Send, const CAPACITY: usize> Send for BoundedQueue<T, CAPACITY> {}
+
+impl<T, const CAPACITY: usize> BoundedQueue<T, CAPACITY> {
+    const MASK: usize = {
+        assert!(
+            CAPACITY.is_power_of_two(),
+            "CAPACITY must be a power of two."
+        );
+
+        CAPACITY - 1
+    };
+
+    /// Creates an empty queue.
+    pub const fn new() -> Self {
+        // Referencing the mask forces the capacity check at compile time.
+        let _ = Self::MASK;
+
+        let mut slots = [const {
+            Slot {
+                sequence: AtomicUsize::new(0),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }; CAPACITY];
+
+        let mut index = 0;
+        while index < CAPACITY {
+            slots[index].sequence = AtomicUsize::new(index);
+            index += 1;
+        }
+
+        Self {
+            slots,
+            enqueue_position: AtomicUsize::new(0),
+            dequeue_position: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the maximum number of values the queue can hold.
+    pub const fn capacity(&self) -> usize {
+        CAPACITY
+    }
+
+    /// Adds a value to the back of the queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to add.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the value was added, or `Err(value)` with the value handed
+    /// back if the queue is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut position = self.enqueue_position.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[position & Self::MASK];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let difference = sequence.wrapping_sub(position) as isize;
+
+            if difference == 0 {
+                // The slot is free. Try to claim the position.
+                match self.enqueue_position.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe {
+                            (*slot.value.get()).write(value);
+                        }
+
+                        // Publish the value to the consumer of this position.
+                        slot.sequence
+                            .store(position.wrapping_add(1), Ordering::Release);
+
+                        return Ok(());
+                    }
+                    Err(current_position) => position = current_position,
+                }
+            } else if difference < 0 {
+                // The slot still holds a value from one lap ago.
+                return Err(value);
+            } else {
+                // Another producer claimed this position first.
+                position = self.enqueue_position.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Removes the value at the front of the queue.
+    ///
+    /// # Returns
+    ///
+    /// The removed value, or `None` if the queue is empty.
+    pub fn pop(&self) -> Option<T> {
+        let mut position = self.dequeue_position.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[position & Self::MASK];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let difference = sequence.wrapping_sub(position.wrapping_add(1)) as isize;
+
+            if difference == 0 {
+                // The slot holds a value. Try to claim the position.
+                match self.dequeue_position.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+
+                        // Hand the slot back to the producer one lap ahead.
+                        slot.sequence
+                            .store(position.wrapping_add(CAPACITY), Ordering::Release);
+
+                        return Some(value);
+                    }
+                    Err(current_position) => position = current_position,
+                }
+            } else if difference < 0 {
+                // No producer has written this position yet.
+                return None;
+            } else {
+                // Another consumer claimed this position first.
+                position = self.dequeue_position.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<T, const CAPACITY: usize> Default for BoundedQueue<T, CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const CAPACITY: usize> Drop for BoundedQueue<T, CAPACITY> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread, vec::Vec};
+
+    #[test]
+    fn test_pop_empty_returns_none() {
+        let queue: BoundedQueue<usize, 4> = BoundedQueue::new();
+
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_push_pop_preserves_order() {
+        let queue: BoundedQueue<usize, 4> = BoundedQueue::new();
+
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        queue.push(3).unwrap();
+
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_push_full_returns_value() {
+        let queue: BoundedQueue<usize, 2> = BoundedQueue::new();
+
+        queue.push(10).unwrap();
+        queue.push(20).unwrap();
+
+        assert_eq!(queue.push(30), Err(30));
+
+        // Freeing a slot makes room again.
+        assert_eq!(queue.pop(), Some(10));
+        queue.push(30).unwrap();
+        assert_eq!(queue.pop(), Some(20));
+        assert_eq!(queue.pop(), Some(30));
+    }
+
+    #[test]
+    fn test_wraps_around_many_laps() {
+        let queue: BoundedQueue<usize, 4> = BoundedQueue::new();
+
+        for value in 0..1000 {
+            queue.push(value).unwrap();
+            assert_eq!(queue.pop(), Some(value));
+        }
+
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_drop_releases_remaining_values() {
+        let counter = Arc::new(());
+
+        {
+            let queue: BoundedQueue<Arc<()>, 4> = BoundedQueue::new();
+            queue.push(counter.clone()).unwrap();
+            queue.push(counter.clone()).unwrap();
+
+            assert_eq!(Arc::strong_count(&counter), 3);
+        }
+
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_concurrent_producers_single_consumer() {
+        const PRODUCER_COUNT: usize = 4;
+        const VALUES_PER_PRODUCER: usize = 10_000;
+
+        let queue: Arc<BoundedQueue<usize, 64>> = Arc::new(BoundedQueue::new());
+
+        let producers: Vec<_> = (0..PRODUCER_COUNT)
+            .map(|producer| {
+                let queue = queue.clone();
+
+                thread::spawn(move || {
+                    for index in 0..VALUES_PER_PRODUCER {
+                        let mut value = producer * VALUES_PER_PRODUCER + index;
+
+                        while let Err(rejected) = queue.push(value) {
+                            value = rejected;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // Every value must arrive exactly once, and each producer's values must
+        // arrive in the order they were pushed.
+        let mut received = vec![false; PRODUCER_COUNT * VALUES_PER_PRODUCER];
+        let mut next_index_per_producer = [0; PRODUCER_COUNT];
+
+        for _ in 0..PRODUCER_COUNT * VALUES_PER_PRODUCER {
+            let value = loop {
+                if let Some(value) = queue.pop() {
+                    break value;
+                }
+
+                thread::yield_now();
+            };
+
+            let producer = value / VALUES_PER_PRODUCER;
+            let index = value % VALUES_PER_PRODUCER;
+
+            assert!(!received[value]);
+            assert_eq!(index, next_index_per_producer[producer]);
+
+            received[value] = true;
+            next_index_per_producer[producer] += 1;
+        }
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        assert_eq!(queue.pop(), None);
+    }
+}
@@ -0,0 +1,60 @@
+//! Saving and restoring the supervisor interrupt enable bit of the calling
+//! hart.
+//!
+//! Off riscv64, such as in host tests, interrupts are never enabled and these
+//! functions do nothing.
+
+/// The supervisor interrupt enable bit in the sstatus CSR.
+pub const SSTATUS_SIE: usize = 1 << 1;
+
+/// Disables interrupts on the calling hart.
+///
+/// # Returns
+///
+/// True if interrupts were enabled before the call. Pass it to
+/// `restore_interrupts` to undo the call.
+#[inline(always)]
+pub fn disable_interrupts() -> bool {
+    #[cfg(target_arch = "riscv64")]
+    {
+        let previous_sstatus: usize;
+        unsafe {
+            core::arch::asm!(
+                "csrrci {}, sstatus, {}",
+                out(reg) previous_sstatus,
+                const SSTATUS_SIE,
+                options(nomem, nostack)
+            );
+        }
+
+        previous_sstatus & SSTATUS_SIE != 0
+    }
+
+    #[cfg(not(target_arch = "riscv64"))]
+    {
+        false
+    }
+}
+
+/// Enables interrupts on the calling hart if they were enabled before the
+/// matching `disable_interrupts` call.
+///
+/// # Arguments
+///
+/// * `were_enabled` - The value returned by `disable_interrupts`.
+#[inline(always)]
+pub fn restore_interrupts(were_enabled: bool) {
+    #[cfg(target_arch = "riscv64")]
+    if were_enabled {
+        unsafe {
+            core::arch::asm!(
+                "csrsi sstatus, {}",
+                const SSTATUS_SIE,
+                options(nomem, nostack)
+            );
+        }
+    }
+
+    #[cfg(not(target_arch = "riscv64"))]
+    let _ = were_enabled;
+}
@@ -0,0 +1,18 @@
+//! Synchronization primitives that do not depend on kernel services.
+
+mod bounded_queue;
+pub mod interrupts;
+mod mpsc_channel;
+mod once;
+mod ref_count;
+mod spin_lock;
+
+pub use bounded_queue::BoundedQueue;
+pub use interrupts::{disable_interrupts, restore_interrupts};
+pub use mpsc_channel::MpscChannel;
+pub use once::{Lazy, Once};
+pub use ref_count::{Ref, RefCount, RefCounted, WeakRef};
+pub use spin_lock::{
+    HartIdSource, SpinLock, SpinLockGuard, SpinLockIrqSave, SpinLockIrqSaveGuard,
+    set_hart_id_source,
+};
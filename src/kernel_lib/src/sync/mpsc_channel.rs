@@ -0,0 +1,317 @@
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A single slot in an `MpscChannel`.
+struct Slot<T> {
+    /// Tracks the state of the slot relative to the channel positions.
+    ///
+    /// A slot with sequence `s` is free for the sender claiming position `s`
+    /// when `s == position`, and holds a message for the receiver at position
+    /// `p` when `s == p + 1`.
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A fixed capacity, lock-free, multi-producer single-consumer channel.
+///
+/// Any number of harts and trap handlers can send messages at once, while
+/// only one of them receives at a time. Senders claim positions with a
+/// compare-and-swap like `BoundedQueue` does, but the receiver owns the read
+/// position, so receiving takes no atomic read-modify-write at all. The
+/// channel never allocates, so it can be placed in a `static` and used from
+/// trap handlers.
+///
+/// `CAPACITY` must be a power of two.
+pub struct MpscChannel<T, const CAPACITY: usize> {
+    slots: [Slot<T>; CAPACITY],
+
+    /// The next position a sender will write.
+    send_position: AtomicUsize,
+
+    /// The next position the receiver will read. Only written by the
+    /// receiver.
+    receive_position: AtomicUsize,
+}
+
+unsafe impl<T: Send, const CAPACITY: usize> Sync for MpscChannel<T, CAPACITY> {}
+unsafe impl<T: Send, const CAPACITY: usize> Send for MpscChannel<T, CAPACITY> {}
+
+impl<T, const CAPACITY: usize> MpscChannel<T, CAPACITY> {
+    const MASK: usize = {
+        assert!(
+            CAPACITY.is_power_of_two(),
+            "CAPACITY must be a power of two."
+        );
+
+        CAPACITY - 1
+    };
+
+    /// Creates an empty channel.
+    pub const fn new() -> Self {
+        // Referencing the mask forces the capacity check at compile time.
+        let _ = Self::MASK;
+
+        let mut slots = [const {
+            Slot {
+                sequence: AtomicUsize::new(0),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }; CAPACITY];
+
+        let mut index = 0;
+        while index < CAPACITY {
+            slots[index].sequence = AtomicUsize::new(index);
+            index += 1;
+        }
+
+        Self {
+            slots,
+            send_position: AtomicUsize::new(0),
+            receive_position: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the maximum number of messages the channel can hold.
+    pub const fn capacity(&self) -> usize {
+        CAPACITY
+    }
+
+    /// Adds a message to the back of the channel. Can be called from any hart
+    /// and from trap handlers.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The message to send.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the message was added, or `Err(value)` with the message
+    /// handed back if the channel is full.
+    pub fn send(&self, value: T) -> Result<(), T> {
+        let mut position = self.send_position.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[position & Self::MASK];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let difference = sequence.wrapping_sub(position) as isize;
+
+            if difference == 0 {
+                // The slot is free. Try to claim the position.
+                match self.send_position.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe {
+                            (*slot.value.get()).write(value);
+                        }
+
+                        // Publish the message to the receiver.
+                        slot.sequence
+                            .store(position.wrapping_add(1), Ordering::Release);
+
+                        return Ok(());
+                    }
+                    Err(current_position) => position = current_position,
+                }
+            } else if difference < 0 {
+                // The receiver has not taken the message from one lap ago.
+                return Err(value);
+            } else {
+                // Another sender claimed this position first.
+                position = self.send_position.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Removes the message at the front of the channel.
+    ///
+    /// A message whose sender claimed its position but has not finished
+    /// writing it blocks the messages behind it, so `None` is returned until
+    /// the write completes.
+    ///
+    /// # Returns
+    ///
+    /// The removed message, or `None` if the channel is empty.
+    ///
+    /// # Safety
+    ///
+    /// Only one caller may receive from the channel at a time, such as the
+    /// hart that owns it or the holder of a lock that guards the receiving
+    /// side.
+    pub unsafe fn receive(&self) -> Option<T> {
+        let position = self.receive_position.load(Ordering::Relaxed);
+        let slot = &self.slots[position & Self::MASK];
+
+        if slot.sequence.load(Ordering::Acquire) != position.wrapping_add(1) {
+            return None;
+        }
+
+        let value = unsafe { (*slot.value.get()).assume_init_read() };
+
+        self.receive_position
+            .store(position.wrapping_add(1), Ordering::Relaxed);
+
+        // Hand the slot back to the sender one lap ahead.
+        slot.sequence
+            .store(position.wrapping_add(CAPACITY), Ordering::Release);
+
+        Some(value)
+    }
+
+    /// Returns true if no message is ready to be received. Called by anyone
+    /// other than the receiver, the answer may be stale by the time it
+    /// returns.
+    pub fn is_empty(&self) -> bool {
+        let position = self.receive_position.load(Ordering::Acquire);
+        let slot = &self.slots[position & Self::MASK];
+
+        slot.sequence.load(Ordering::Acquire) != position.wrapping_add(1)
+    }
+}
+
+impl<T, const CAPACITY: usize> Default for MpscChannel<T, CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const CAPACITY: usize> Drop for MpscChannel<T, CAPACITY> {
+    fn drop(&mut self) {
+        // The exclusive borrow makes this the only receiver.
+        while unsafe { self.receive() }.is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread, vec::Vec};
+
+    #[test]
+    fn test_receive_empty_returns_none() {
+        let channel: MpscChannel<usize, 4> = MpscChannel::new();
+
+        assert!(channel.is_empty());
+        assert_eq!(unsafe { channel.receive() }, None);
+    }
+
+    #[test]
+    fn test_send_receive_preserves_order() {
+        let channel: MpscChannel<usize, 4> = MpscChannel::new();
+
+        channel.send(1).unwrap();
+        channel.send(2).unwrap();
+        channel.send(3).unwrap();
+
+        assert!(!channel.is_empty());
+        assert_eq!(unsafe { channel.receive() }, Some(1));
+        assert_eq!(unsafe { channel.receive() }, Some(2));
+        assert_eq!(unsafe { channel.receive() }, Some(3));
+        assert_eq!(unsafe { channel.receive() }, None);
+        assert!(channel.is_empty());
+    }
+
+    #[test]
+    fn test_send_full_returns_value() {
+        let channel: MpscChannel<usize, 2> = MpscChannel::new();
+
+        channel.send(10).unwrap();
+        channel.send(20).unwrap();
+
+        assert_eq!(channel.send(30), Err(30));
+
+        // Receiving frees a slot for the next lap.
+        assert_eq!(unsafe { channel.receive() }, Some(10));
+        channel.send(30).unwrap();
+        assert_eq!(unsafe { channel.receive() }, Some(20));
+        assert_eq!(unsafe { channel.receive() }, Some(30));
+    }
+
+    #[test]
+    fn test_wraps_around_many_laps() {
+        let channel: MpscChannel<usize, 4> = MpscChannel::new();
+
+        for value in 0..1000 {
+            channel.send(value).unwrap();
+            assert_eq!(unsafe { channel.receive() }, Some(value));
+        }
+
+        assert_eq!(unsafe { channel.receive() }, None);
+    }
+
+    #[test]
+    fn test_drop_releases_remaining_values() {
+        let counter = Arc::new(());
+
+        {
+            let channel: MpscChannel<Arc<()>, 4> = MpscChannel::new();
+            channel.send(counter.clone()).unwrap();
+            channel.send(counter.clone()).unwrap();
+
+            assert_eq!(Arc::strong_count(&counter), 3);
+        }
+
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn test_concurrent_senders_single_receiver() {
+        const SENDER_COUNT: usize = 4;
+        const VALUES_PER_SENDER: usize = 10_000;
+
+        let channel: Arc<MpscChannel<usize, 64>> = Arc::new(MpscChannel::new());
+
+        let senders: Vec<_> = (0..SENDER_COUNT)
+            .map(|sender| {
+                let channel = channel.clone();
+
+                thread::spawn(move || {
+                    for index in 0..VALUES_PER_SENDER {
+                        let mut value = sender * VALUES_PER_SENDER + index;
+
+                        while let Err(rejected) = channel.send(value) {
+                            value = rejected;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // Every value must arrive exactly once, and each sender's values must
+        // arrive in the order they were sent.
+        let mut received = vec![false; SENDER_COUNT * VALUES_PER_SENDER];
+        let mut next_index_per_sender = [0; SENDER_COUNT];
+
+        for _ in 0..SENDER_COUNT * VALUES_PER_SENDER {
+            let value = loop {
+                if let Some(value) = unsafe { channel.receive() } {
+                    break value;
+                }
+
+                thread::yield_now();
+            };
+
+            let sender = value / VALUES_PER_SENDER;
+            let index = value % VALUES_PER_SENDER;
+
+            assert!(!received[value]);
+            assert_eq!(index, next_index_per_sender[sender]);
+
+            received[value] = true;
+            next_index_per_sender[sender] += 1;
+        }
+
+        for sender in senders {
+            sender.join().unwrap();
+        }
+
+        assert_eq!(unsafe { channel.receive() }, None);
+    }
+}
@@ -0,0 +1,205 @@
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ops::Deref,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// The value has not been initialized and no hart is initializing it.
+const INCOMPLETE: u8 = 0;
+
+/// A hart is running the initializer.
+const RUNNING: u8 = 1;
+
+/// The value is initialized.
+const COMPLETE: u8 = 2;
+
+/// A cell that is written at most once and can be placed in a `static`.
+///
+/// The first call to `call_once` runs its initializer while other callers spin
+/// until the value is ready. If the initializer panics, the cell stays
+/// uninitialized and every later caller spins forever, which only matters if
+/// the kernel survives the panic. An initializer must not use its own cell.
+pub struct Once<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+unsafe impl<T: Send> Send for Once<T> {}
+
+impl<T> Once<T> {
+    /// Creates an uninitialized cell.
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(INCOMPLETE),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Initializes the cell if it is not initialized yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `initializer` - Creates the value. Only called by the first caller.
+    ///
+    /// # Returns
+    ///
+    /// The value of the cell, whichever caller created it.
+    pub fn call_once(&self, initializer: impl FnOnce() -> T) -> &T {
+        match self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                unsafe {
+                    (*self.value.get()).write(initializer());
+                }
+
+                self.state.store(COMPLETE, Ordering::Release);
+            }
+            Err(_) => {
+                while self.state.load(Ordering::Acquire) != COMPLETE {
+                    core::hint::spin_loop();
+                }
+            }
+        }
+
+        // Either branch ends with the state complete.
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+
+    /// Returns the value, or `None` if the cell is not initialized yet.
+    pub fn get(&self) -> Option<&T> {
+        if self.is_completed() {
+            // The value is never written again once the state is complete.
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if the cell holds its value.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
+
+impl<T> Default for Once<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Once<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == COMPLETE {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// A value created by a function the first time it is used.
+///
+/// # Examples
+///
+/// ```
+/// use kernel_lib::sync::Lazy;
+///
+/// static TABLE: Lazy<[u32; 4]> = Lazy::new(|| [1, 2, 4, 8]);
+///
+/// assert_eq!(TABLE[3], 8);
+/// ```
+pub struct Lazy<T, F = fn() -> T> {
+    once: Once<T>,
+    initializer: F,
+}
+
+unsafe impl<T: Send + Sync, F: Sync> Sync for Lazy<T, F> {}
+
+impl<T, F: Fn() -> T> Lazy<T, F> {
+    /// Creates a value that is initialized by `initializer` when first used.
+    pub const fn new(initializer: F) -> Self {
+        Self {
+            once: Once::new(),
+            initializer,
+        }
+    }
+
+    /// Initializes the value if it is not initialized yet and returns it.
+    pub fn force(this: &Self) -> &T {
+        this.once.call_once(|| (this.initializer)())
+    }
+}
+
+impl<T, F: Fn() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Self::force(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{Arc, atomic::AtomicUsize},
+        thread,
+        vec::Vec,
+    };
+
+    #[test]
+    fn test_call_once_runs_initializer_once() {
+        let once = Once::new();
+
+        assert_eq!(once.get(), None);
+        assert_eq!(*once.call_once(|| 1), 1);
+        assert_eq!(*once.call_once(|| 2), 1);
+        assert_eq!(once.get(), Some(&1));
+        assert!(once.is_completed());
+    }
+
+    #[test]
+    fn test_concurrent_call_once() {
+        static INITIALIZER_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let once = Arc::new(Once::new());
+
+        let threads: Vec<_> = (0..4)
+            .map(|index| {
+                let once = Arc::clone(&once);
+
+                thread::spawn(move || {
+                    *once.call_once(|| {
+                        INITIALIZER_CALLS.fetch_add(1, Ordering::Relaxed);
+                        index
+                    })
+                })
+            })
+            .collect();
+
+        let results: Vec<_> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+
+        assert_eq!(INITIALIZER_CALLS.load(Ordering::Relaxed), 1);
+        assert!(results.iter().all(|&result| result == results[0]));
+    }
+
+    #[test]
+    fn test_lazy_initializes_on_first_use() {
+        static INITIALIZER_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let lazy = Lazy::new(|| {
+            INITIALIZER_CALLS.fetch_add(1, Ordering::Relaxed);
+            42
+        });
+
+        assert_eq!(INITIALIZER_CALLS.load(Ordering::Relaxed), 0);
+        assert_eq!(*lazy, 42);
+        assert_eq!(*lazy, 42);
+        assert_eq!(INITIALIZER_CALLS.load(Ordering::Relaxed), 1);
+    }
+}
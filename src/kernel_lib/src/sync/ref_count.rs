@@ -0,0 +1,489 @@
+use core::{
+    fmt,
+    ops::Deref,
+    sync::atomic::{AtomicU64, Ordering, fence},
+};
+
+/// The bits of a `RefCount` state that hold the number of references.
+const COUNT_MASK: u64 = 0x7FFF_FFFF;
+
+/// The `RefCount` state bit set from when the last reference is dropped until
+/// the object is freed, which keeps it from being claimed in between.
+const RELEASING: u64 = 1 << 31;
+
+/// The shift of the generation in a `RefCount` state.
+const GENERATION_SHIFT: u32 = 32;
+
+/// The number of references above which a reference count panics instead of
+/// counting on, so an overflow is caught before the count wraps to zero.
+const MAX_COUNT: u64 = COUNT_MASK / 2;
+
+/// An intrusive reference count for an object in a fixed pool.
+///
+/// The kernel has no heap, so objects shared across harts, such as processes,
+/// inodes, and virtual memory areas, live in static tables. An object embeds
+/// a `RefCount` and is free while the count is zero. Claiming a free object
+/// starts a new generation, which lets a `WeakRef` tell the object it was
+/// created for from a later one that reuses the slot.
+///
+/// Dropping the last reference leaves the object releasing rather than free,
+/// so its owner can free what it holds before `finish_release` lets it be
+/// claimed again.
+///
+/// The count, the releasing flag, and the generation share one atomic, so
+/// they are always read and changed together. A count that would drop below
+/// zero or grow past `MAX_COUNT` panics, since either means a reference was
+/// dropped twice or leaked.
+pub struct RefCount {
+    state: AtomicU64,
+}
+
+impl RefCount {
+    /// Creates the reference count of a free object.
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the number of references.
+    pub fn count(&self) -> usize {
+        (self.state.load(Ordering::Acquire) & COUNT_MASK) as usize
+    }
+
+    /// Returns the generation of the object, which changes every time it is
+    /// claimed.
+    pub fn generation(&self) -> u32 {
+        (self.state.load(Ordering::Acquire) >> GENERATION_SHIFT) as u32
+    }
+
+    /// Takes the first reference to a free object and starts a new
+    /// generation.
+    ///
+    /// # Returns
+    ///
+    /// True if the object was free and is now claimed by the caller.
+    pub fn try_claim(&self) -> bool {
+        self.state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                if state & (COUNT_MASK | RELEASING) != 0 {
+                    return None;
+                }
+
+                let generation = ((state >> GENERATION_SHIFT) as u32).wrapping_add(1);
+
+                Some((generation as u64) << GENERATION_SHIFT | 1)
+            })
+            .is_ok()
+    }
+
+    /// Adds a reference to an object the caller already holds a reference
+    /// to.
+    ///
+    /// # Panics
+    ///
+    /// If the object is free or the count would grow past `MAX_COUNT`.
+    pub fn acquire(&self) {
+        // Like `Arc::clone`, the new reference is derived from an existing one,
+        // so no ordering is needed.
+        let previous = self.state.fetch_add(1, Ordering::Relaxed) & COUNT_MASK;
+
+        if previous == 0 {
+            panic!("Acquired a reference to a free object.");
+        }
+
+        if previous >= MAX_COUNT {
+            panic!("Reference count overflow.");
+        }
+    }
+
+    /// Adds a reference to an object if it is still the generation the
+    /// caller expects and has not been freed.
+    ///
+    /// # Arguments
+    ///
+    /// * `generation` - The generation the caller saw when it recorded the
+    ///   object.
+    ///
+    /// # Returns
+    ///
+    /// True if a reference was added.
+    pub fn try_acquire(&self, generation: u32) -> bool {
+        self.state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                let count = state & COUNT_MASK;
+
+                let is_match = count != 0
+                    && count < MAX_COUNT
+                    && (state >> GENERATION_SHIFT) as u32 == generation;
+
+                is_match.then_some(state + 1)
+            })
+            .is_ok()
+    }
+
+    /// Drops a reference.
+    ///
+    /// # Returns
+    ///
+    /// True if it was the last reference, so the caller must free the object
+    /// and then call `finish_release`.
+    ///
+    /// # Panics
+    ///
+    /// If the object has no references.
+    pub fn release(&self) -> bool {
+        let previous = self
+            .state
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |state| {
+                match state & COUNT_MASK {
+                    0 => None,
+                    1 => Some((state - 1) | RELEASING),
+                    _ => Some(state - 1),
+                }
+            })
+            .unwrap_or_else(|_| panic!("Released a reference to a free object."));
+
+        if previous & COUNT_MASK != 1 {
+            return false;
+        }
+
+        // Pairs with the release decrements of the other references, so the
+        // caller sees everything they did before it frees the object.
+        fence(Ordering::Acquire);
+
+        true
+    }
+
+    /// Marks a released object free, so it can be claimed again.
+    ///
+    /// # Panics
+    ///
+    /// If the object is not releasing.
+    pub fn finish_release(&self) {
+        let previous = self.state.fetch_and(!RELEASING, Ordering::Release);
+
+        if previous & RELEASING == 0 {
+            panic!("Finished releasing an object that is not releasing.");
+        }
+    }
+}
+
+impl Default for RefCount {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RefCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.load(Ordering::Acquire);
+
+        f.debug_struct("RefCount")
+            .field("count", &(state & COUNT_MASK))
+            .field("is_releasing", &(state & RELEASING != 0))
+            .field("generation", &(state >> GENERATION_SHIFT))
+            .finish()
+    }
+}
+
+/// An object in a static pool that is shared through `Ref`s.
+pub trait RefCounted: Sync + 'static {
+    /// Returns the reference count embedded in the object.
+    fn ref_count(&self) -> &RefCount;
+
+    /// Frees what the object holds once its last `Ref` is dropped. The object
+    /// cannot be claimed again until this returns.
+    fn release(&'static self);
+}
+
+/// A counted reference to a pooled object, the kernel's `Arc`.
+///
+/// Cloning adds a reference, and dropping the last one calls
+/// `RefCounted::release`.
+pub struct Ref<T: RefCounted> {
+    object: &'static T,
+}
+
+impl<T: RefCounted> Ref<T> {
+    /// Claims a free object.
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - The object, usually a slot of a static table.
+    ///
+    /// # Returns
+    ///
+    /// The first reference to the object, or `None` if it is in use.
+    pub fn claim(object: &'static T) -> Option<Self> {
+        object.ref_count().try_claim().then(|| Self { object })
+    }
+
+    /// Adds a reference to an object that is in use, such as one found by
+    /// searching its pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - The object.
+    ///
+    /// # Returns
+    ///
+    /// A new reference to the object, or `None` if it is free or being
+    /// released.
+    pub fn try_acquire(object: &'static T) -> Option<Self> {
+        let ref_count = object.ref_count();
+
+        ref_count
+            .try_acquire(ref_count.generation())
+            .then(|| Self { object })
+    }
+
+    /// Creates a weak reference to the object.
+    pub fn downgrade(this: &Self) -> WeakRef<T> {
+        WeakRef {
+            object: this.object,
+            generation: this.object.ref_count().generation(),
+        }
+    }
+
+    /// Returns the number of references to the object.
+    pub fn count(this: &Self) -> usize {
+        this.object.ref_count().count()
+    }
+
+    /// Returns true if both references point to the same object.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        core::ptr::eq(this.object, other.object)
+    }
+}
+
+impl<T: RefCounted> Clone for Ref<T> {
+    fn clone(&self) -> Self {
+        self.object.ref_count().acquire();
+
+        Self {
+            object: self.object,
+        }
+    }
+}
+
+impl<T: RefCounted> Drop for Ref<T> {
+    fn drop(&mut self) {
+        if self.object.ref_count().release() {
+            self.object.release();
+            self.object.ref_count().finish_release();
+        }
+    }
+}
+
+impl<T: RefCounted> Deref for Ref<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.object
+    }
+}
+
+impl<T: RefCounted + fmt::Debug> fmt::Debug for Ref<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.object, f)
+    }
+}
+
+/// A reference to a pooled object that does not keep it alive, the kernel's
+/// `Weak`.
+///
+/// It records the generation of the object, so it cannot be upgraded once
+/// the object is freed, even if the slot has been claimed again since.
+pub struct WeakRef<T: RefCounted> {
+    object: &'static T,
+    generation: u32,
+}
+
+impl<T: RefCounted> WeakRef<T> {
+    /// Returns a counted reference to the object if it still exists.
+    pub fn upgrade(&self) -> Option<Ref<T>> {
+        self.object
+            .ref_count()
+            .try_acquire(self.generation)
+            .then(|| Ref {
+                object: self.object,
+            })
+    }
+}
+
+impl<T: RefCounted> Clone for WeakRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: RefCounted> Copy for WeakRef<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{boxed::Box, sync::atomic::AtomicUsize, thread, vec::Vec};
+
+    /// A pooled object that counts how often it was released.
+    struct Object {
+        ref_count: RefCount,
+        release_count: AtomicUsize,
+    }
+
+    impl RefCounted for Object {
+        fn ref_count(&self) -> &RefCount {
+            &self.ref_count
+        }
+
+        fn release(&'static self) {
+            self.release_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn new_object() -> &'static Object {
+        Box::leak(Box::new(Object {
+            ref_count: RefCount::new(),
+            release_count: AtomicUsize::new(0),
+        }))
+    }
+
+    #[test]
+    fn test_claim_then_drop_releases_once() {
+        let object = new_object();
+
+        let reference = Ref::claim(object).unwrap();
+        assert_eq!(Ref::count(&reference), 1);
+
+        drop(reference);
+
+        assert_eq!(object.ref_count.count(), 0);
+        assert_eq!(object.release_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_claim_fails_while_in_use() {
+        let object = new_object();
+
+        let _reference = Ref::claim(object).unwrap();
+
+        assert!(Ref::claim(object).is_none());
+    }
+
+    #[test]
+    fn test_clone_keeps_object_alive() {
+        let object = new_object();
+
+        let first = Ref::claim(object).unwrap();
+        let second = first.clone();
+
+        assert!(Ref::ptr_eq(&first, &second));
+        assert_eq!(Ref::count(&second), 2);
+
+        drop(first);
+        assert_eq!(object.release_count.load(Ordering::Relaxed), 0);
+
+        drop(second);
+        assert_eq!(object.release_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_try_acquire_only_live_objects() {
+        let object = new_object();
+
+        assert!(Ref::try_acquire(object).is_none());
+
+        let reference = Ref::claim(object).unwrap();
+        let acquired = Ref::try_acquire(object).unwrap();
+        assert_eq!(Ref::count(&acquired), 2);
+
+        drop(acquired);
+        drop(reference);
+        assert!(Ref::try_acquire(object).is_none());
+    }
+
+    #[test]
+    fn test_weak_upgrade_while_alive() {
+        let object = new_object();
+
+        let reference = Ref::claim(object).unwrap();
+        let weak = Ref::downgrade(&reference);
+
+        let upgraded = weak.upgrade().unwrap();
+        assert_eq!(Ref::count(&upgraded), 2);
+    }
+
+    #[test]
+    fn test_weak_upgrade_fails_after_release() {
+        let object = new_object();
+
+        let weak = Ref::downgrade(&Ref::claim(object).unwrap());
+
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_weak_upgrade_fails_after_slot_reused() {
+        let object = new_object();
+
+        let weak = Ref::downgrade(&Ref::claim(object).unwrap());
+        let _reused = Ref::claim(object).unwrap();
+
+        assert!(weak.upgrade().is_none());
+        assert_eq!(object.ref_count.count(), 1);
+    }
+
+    #[test]
+    fn test_claim_fails_while_releasing() {
+        let ref_count = RefCount::new();
+
+        assert!(ref_count.try_claim());
+        assert!(ref_count.release());
+        assert!(!ref_count.try_claim());
+
+        ref_count.finish_release();
+        assert!(ref_count.try_claim());
+    }
+
+    #[test]
+    #[should_panic(expected = "Released a reference to a free object.")]
+    fn test_release_free_object_panics() {
+        RefCount::new().release();
+    }
+
+    #[test]
+    #[should_panic(expected = "Acquired a reference to a free object.")]
+    fn test_acquire_free_object_panics() {
+        RefCount::new().acquire();
+    }
+
+    #[test]
+    fn test_concurrent_clones_release_once() {
+        const THREAD_COUNT: usize = 4;
+        const CLONES_PER_THREAD: usize = 10_000;
+
+        let object = new_object();
+        let reference = Ref::claim(object).unwrap();
+
+        let threads: Vec<_> = (0..THREAD_COUNT)
+            .map(|_| {
+                let reference = reference.clone();
+
+                thread::spawn(move || {
+                    for _ in 0..CLONES_PER_THREAD {
+                        drop(reference.clone());
+                    }
+                })
+            })
+            .collect();
+
+        drop(reference);
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(object.ref_count.count(), 0);
+        assert_eq!(object.release_count.load(Ordering::Relaxed), 1);
+    }
+}
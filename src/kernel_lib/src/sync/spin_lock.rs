@@ -0,0 +1,404 @@
+use super::interrupts::{disable_interrupts, restore_interrupts};
+use core::{
+    cell::UnsafeCell,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+/// Returns the ID of the calling hart. Registered by the kernel with
+/// `set_hart_id_source` so that locks can track their owners.
+pub type HartIdSource = fn() -> usize;
+
+/// The registered `HartIdSource`, or null if there is none.
+static HART_ID_SOURCE: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Registers the function locks use to identify the calling hart.
+///
+/// Until one is registered, debug builds cannot detect a hart taking a lock it
+/// already holds.
+///
+/// # Arguments
+///
+/// * `source` - Returns the ID of the calling hart.
+pub fn set_hart_id_source(source: HartIdSource) {
+    HART_ID_SOURCE.store(source as *mut (), Ordering::Release);
+}
+
+/// Returns the ID of the calling hart, or `None` if no `HartIdSource` is
+/// registered.
+fn current_hart_id() -> Option<usize> {
+    let source = HART_ID_SOURCE.load(Ordering::Acquire);
+
+    if source.is_null() {
+        return None;
+    }
+
+    // The pointer was created from a `HartIdSource` in `set_hart_id_source`.
+    let source: HartIdSource = unsafe { core::mem::transmute(source) };
+
+    Some(source())
+}
+
+/// A mutual exclusion lock that busy waits.
+///
+/// The lock does not poison: a guard dropped while unwinding, or never dropped
+/// because its hart halted, leaves the value as it was. In debug builds the
+/// lock records the hart that holds it and panics when that hart tries to take
+/// it again instead of spinning forever.
+///
+/// A `SpinLock` must not be taken by a trap handler if the code it interrupts
+/// may hold it. Use `SpinLockIrqSave` for those locks.
+pub struct SpinLock<T: ?Sized> {
+    is_locked: AtomicBool,
+
+    /// One more than the ID of the hart holding the lock, or zero if it is
+    /// free or the holder is unknown.
+    #[cfg(debug_assertions)]
+    owner: AtomicUsize,
+
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for SpinLock<T> {}
+unsafe impl<T: ?Sized + Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    /// Creates an unlocked lock holding a value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            is_locked: AtomicBool::new(false),
+            #[cfg(debug_assertions)]
+            owner: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Consumes the lock and returns the value it held.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> SpinLock<T> {
+    /// Takes the lock, spinning until it is free.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the calling hart already holds the lock.
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        #[cfg(debug_assertions)]
+        if let Some(hart_id) = current_hart_id()
+            && self.owner.load(Ordering::Relaxed) == hart_id + 1
+        {
+            panic!(
+                "Deadlock: hart {} tried to take a spin lock it already holds.",
+                hart_id
+            );
+        }
+
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+
+            // Wait for the lock to look free before trying to write to it
+            // again, so waiting harts do not fight over the cache line.
+            while self.is_locked.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Takes the lock if it is free.
+    ///
+    /// # Returns
+    ///
+    /// The guard, or `None` if the lock is held.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        self.is_locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+
+        #[cfg(debug_assertions)]
+        self.owner.store(
+            current_hart_id().map_or(0, |hart_id| hart_id + 1),
+            Ordering::Relaxed,
+        );
+
+        Some(SpinLockGuard { lock: self })
+    }
+
+    /// Returns true if the lock is currently held. The answer may be stale by
+    /// the time it is used.
+    pub fn is_locked(&self) -> bool {
+        self.is_locked.load(Ordering::Relaxed)
+    }
+
+    /// Returns the value without locking, which the exclusive borrow makes
+    /// safe.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Releases the lock without a guard, such as from a panic handler that
+    /// must print through a lock the panicking code held.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may use the value through a guard taken before the call.
+    pub unsafe fn force_unlock(&self) {
+        self.unlock();
+    }
+
+    fn unlock(&self) {
+        #[cfg(debug_assertions)]
+        self.owner.store(0, Ordering::Relaxed);
+
+        self.is_locked.store(false, Ordering::Release);
+    }
+}
+
+impl<T: Default> Default for SpinLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Access to the value of a `SpinLock`. The lock is released when the guard
+/// is dropped.
+pub struct SpinLockGuard<'a, T: ?Sized> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T: ?Sized> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // The guard holds the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // The guard holds the lock.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
+
+/// A `SpinLock` that disables interrupts on the calling hart while it is
+/// held.
+///
+/// Data shared with trap handlers must use this lock. Otherwise a trap taken
+/// while the lock is held could try to take it again on the same hart and spin
+/// forever. The previous interrupt state is restored when the guard is
+/// dropped, so these locks nest.
+pub struct SpinLockIrqSave<T: ?Sized> {
+    lock: SpinLock<T>,
+}
+
+impl<T> SpinLockIrqSave<T> {
+    /// Creates an unlocked lock holding a value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            lock: SpinLock::new(value),
+        }
+    }
+
+    /// Consumes the lock and returns the value it held.
+    pub fn into_inner(self) -> T {
+        self.lock.into_inner()
+    }
+}
+
+impl<T: ?Sized> SpinLockIrqSave<T> {
+    /// Disables interrupts and takes the lock, spinning until it is free.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the calling hart already holds the lock.
+    pub fn lock(&self) -> SpinLockIrqSaveGuard<'_, T> {
+        let were_interrupts_enabled = disable_interrupts();
+
+        SpinLockIrqSaveGuard {
+            guard: ManuallyDrop::new(self.lock.lock()),
+            were_interrupts_enabled,
+        }
+    }
+
+    /// Disables interrupts and takes the lock if it is free.
+    ///
+    /// # Returns
+    ///
+    /// The guard, or `None` with interrupts restored if the lock is held.
+    pub fn try_lock(&self) -> Option<SpinLockIrqSaveGuard<'_, T>> {
+        let were_interrupts_enabled = disable_interrupts();
+
+        match self.lock.try_lock() {
+            Some(guard) => Some(SpinLockIrqSaveGuard {
+                guard: ManuallyDrop::new(guard),
+                were_interrupts_enabled,
+            }),
+            None => {
+                restore_interrupts(were_interrupts_enabled);
+                None
+            }
+        }
+    }
+
+    /// Returns true if the lock is currently held. The answer may be stale by
+    /// the time it is used.
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_locked()
+    }
+
+    /// Returns the value without locking, which the exclusive borrow makes
+    /// safe.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.lock.get_mut()
+    }
+
+    /// Releases the lock without a guard. Interrupts are left as they are.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may use the value through a guard taken before the call.
+    pub unsafe fn force_unlock(&self) {
+        unsafe { self.lock.force_unlock() };
+    }
+}
+
+impl<T: Default> Default for SpinLockIrqSave<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// Access to the value of a `SpinLockIrqSave`. The lock is released and
+/// interrupts are restored when the guard is dropped.
+pub struct SpinLockIrqSaveGuard<'a, T: ?Sized> {
+    guard: ManuallyDrop<SpinLockGuard<'a, T>>,
+    were_interrupts_enabled: bool,
+}
+
+impl<T: ?Sized> Deref for SpinLockIrqSaveGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for SpinLockIrqSaveGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized> Drop for SpinLockIrqSaveGuard<'_, T> {
+    fn drop(&mut self) {
+        // Release the lock before interrupts can arrive again.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+
+        restore_interrupts(self.were_interrupts_enabled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{Arc, atomic::AtomicUsize},
+        thread,
+        vec::Vec,
+    };
+
+    /// Gives every test thread its own hart ID, since the registered source
+    /// is shared by all tests.
+    fn thread_hart_id() -> usize {
+        static NEXT_HART_ID: AtomicUsize = AtomicUsize::new(0);
+
+        std::thread_local! {
+            static HART_ID: usize = NEXT_HART_ID.fetch_add(1, Ordering::Relaxed);
+        }
+
+        HART_ID.with(|hart_id| *hart_id)
+    }
+
+    #[test]
+    fn test_lock_and_try_lock() {
+        let lock = SpinLock::new(1);
+
+        {
+            let mut guard = lock.lock();
+            *guard += 1;
+
+            assert!(lock.is_locked());
+            assert!(lock.try_lock().is_none());
+        }
+
+        assert!(!lock.is_locked());
+        assert_eq!(*lock.try_lock().unwrap(), 2);
+        assert_eq!(lock.into_inner(), 2);
+    }
+
+    #[test]
+    fn test_irq_save_lock() {
+        let lock = SpinLockIrqSave::new([0u8; 4]);
+
+        {
+            let mut guard = lock.lock();
+            guard[1] = 7;
+
+            assert!(lock.try_lock().is_none());
+        }
+
+        assert_eq!(lock.try_lock().unwrap()[1], 7);
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    fn test_contended_counter() {
+        const THREAD_COUNT: usize = 4;
+        const INCREMENTS_PER_THREAD: usize = 10_000;
+
+        set_hart_id_source(thread_hart_id);
+
+        let lock = Arc::new(SpinLock::new(0usize));
+
+        let threads: Vec<_> = (0..THREAD_COUNT)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+
+                thread::spawn(move || {
+                    for _ in 0..INCREMENTS_PER_THREAD {
+                        *lock.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*lock.lock(), THREAD_COUNT * INCREMENTS_PER_THREAD);
+    }
+
+    #[test]
+    #[should_panic(expected = "Deadlock")]
+    fn test_relocking_on_same_hart_panics() {
+        set_hart_id_source(thread_hart_id);
+
+        let lock = SpinLock::new(());
+        let _guard = lock.lock();
+        let _second_guard = lock.lock();
+    }
+}
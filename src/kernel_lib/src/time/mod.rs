@@ -0,0 +1,96 @@
+//! Conversions between `Duration` and ticks of a fixed frequency counter,
+//! such as the RISC-V `time` CSR.
+
+use core::time::Duration;
+
+/// The number of nanoseconds in a second.
+const NANOSECONDS_PER_SECOND: u128 = 1_000_000_000;
+
+/// Converts a tick count to the time it spans.
+///
+/// # Arguments
+///
+/// * `ticks` - The number of ticks.
+/// * `frequency` - The frequency of the counter in hertz. Zero is treated as
+///   one.
+///
+/// # Returns
+///
+/// The duration, rounded down to whole nanoseconds.
+pub fn ticks_to_duration(ticks: u64, frequency: u64) -> Duration {
+    let frequency = frequency.max(1);
+
+    let seconds = ticks / frequency;
+    let remaining_ticks = (ticks % frequency) as u128;
+    let nanoseconds = remaining_ticks * NANOSECONDS_PER_SECOND / frequency as u128;
+
+    Duration::new(seconds, nanoseconds as u32)
+}
+
+/// Converts a duration to the number of ticks it spans.
+///
+/// # Arguments
+///
+/// * `duration` - The duration.
+/// * `frequency` - The frequency of the counter in hertz.
+///
+/// # Returns
+///
+/// The number of ticks, rounded up so a wait of this many ticks is never
+/// shorter than `duration`, and saturated at `u64::MAX`.
+pub fn duration_to_ticks(duration: Duration, frequency: u64) -> u64 {
+    let ticks = (duration.as_nanos() * frequency as u128).div_ceil(NANOSECONDS_PER_SECOND);
+
+    ticks.min(u64::MAX as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticks_to_duration() {
+        assert_eq!(ticks_to_duration(0, 10_000_000), Duration::ZERO);
+        assert_eq!(
+            ticks_to_duration(10_000_000, 10_000_000),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            ticks_to_duration(15_000_001, 10_000_000),
+            Duration::new(1, 500_000_100)
+        );
+        assert_eq!(ticks_to_duration(1, 3), Duration::from_nanos(333_333_333));
+
+        // Large tick counts must not overflow the intermediate product.
+        assert_eq!(
+            ticks_to_duration(u64::MAX, 1_000_000_000),
+            Duration::new(u64::MAX / 1_000_000_000, (u64::MAX % 1_000_000_000) as u32)
+        );
+
+        assert_eq!(ticks_to_duration(5, 0), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_duration_to_ticks() {
+        assert_eq!(duration_to_ticks(Duration::ZERO, 10_000_000), 0);
+        assert_eq!(
+            duration_to_ticks(Duration::from_millis(10), 10_000_000),
+            100_000
+        );
+
+        // Partial ticks round up.
+        assert_eq!(duration_to_ticks(Duration::from_nanos(1), 10_000_000), 1);
+        assert_eq!(duration_to_ticks(Duration::from_nanos(101), 10_000_000), 2);
+
+        assert_eq!(duration_to_ticks(Duration::MAX, 10_000_000), u64::MAX);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for ticks in [0, 1, 7, 9_999_999, 10_000_000, 123_456_789_012] {
+            let duration = ticks_to_duration(ticks, 10_000_000);
+
+            assert_eq!(duration_to_ticks(duration, 10_000_000), ticks);
+        }
+    }
+}
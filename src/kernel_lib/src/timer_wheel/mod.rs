@@ -0,0 +1,186 @@
+//! A hashed timer wheel for timers identified by small integers.
+//!
+//! Time is measured in ticks of a periodic timer. Each timer is kept in the
+//! slot for its expiry tick modulo the number of slots, so advancing the wheel
+//! by one tick only looks at the timers of a single slot. Timers further away
+//! than one turn of the wheel stay in their slot until their tick comes
+//! around.
+
+/// The number of timers a wheel can hold. Timer IDs must be below this value.
+pub const MAX_TIMERS: usize = 64;
+
+/// A timer wheel with `SLOT_COUNT` slots.
+pub struct TimerWheel<const SLOT_COUNT: usize> {
+    /// A bit for every timer waiting in each slot.
+    slots: [u64; SLOT_COUNT],
+
+    /// The tick at which each pending timer expires.
+    expiry_ticks: [u64; MAX_TIMERS],
+
+    /// The first tick that has not been processed by `advance`.
+    next_tick: u64,
+}
+
+impl<const SLOT_COUNT: usize> TimerWheel<SLOT_COUNT> {
+    /// Creates a wheel with no pending timers.
+    pub const fn new() -> Self {
+        assert!(SLOT_COUNT > 0, "A timer wheel needs at least one slot.");
+
+        Self {
+            slots: [0; SLOT_COUNT],
+            expiry_ticks: [0; MAX_TIMERS],
+            next_tick: 0,
+        }
+    }
+
+    /// Starts a timer, replacing the timer with the same ID if there is one.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the timer, below `MAX_TIMERS`.
+    /// * `expiry_tick` - The tick at which the timer expires. Ticks that have
+    ///   already been processed expire at the next call to `advance`.
+    pub fn insert(&mut self, id: usize, expiry_tick: u64) {
+        self.cancel(id);
+
+        let expiry_tick = expiry_tick.max(self.next_tick);
+
+        self.expiry_ticks[id] = expiry_tick;
+        self.slots[Self::slot_index(expiry_tick)] |= 1 << id;
+    }
+
+    /// Stops a timer.
+    ///
+    /// # Returns
+    ///
+    /// True if the timer was pending.
+    pub fn cancel(&mut self, id: usize) -> bool {
+        let was_pending = self.is_pending(id);
+
+        self.slots[Self::slot_index(self.expiry_ticks[id])] &= !(1 << id);
+
+        was_pending
+    }
+
+    /// Returns true if a timer is waiting to expire.
+    pub fn is_pending(&self, id: usize) -> bool {
+        self.slots[Self::slot_index(self.expiry_ticks[id])] & (1 << id) != 0
+    }
+
+    /// Expires every timer whose tick is at or before `current_tick`.
+    ///
+    /// # Arguments
+    ///
+    /// * `current_tick` - The tick that has just been reached. Earlier values
+    ///   than a previous call are ignored.
+    /// * `expire` - Called with the ID of every expired timer, after the
+    ///   timer has been removed from the wheel.
+    pub fn advance(&mut self, current_tick: u64, mut expire: impl FnMut(usize)) {
+        if current_tick < self.next_tick {
+            return;
+        }
+
+        // After a full turn every slot has been visited, however many ticks
+        // were missed.
+        let tick_count = (current_tick - self.next_tick + 1).min(SLOT_COUNT as u64);
+
+        for tick in self.next_tick..self.next_tick + tick_count {
+            let slot_index = Self::slot_index(tick);
+            let mut pending = self.slots[slot_index];
+
+            while pending != 0 {
+                let id = pending.trailing_zeros() as usize;
+                pending &= pending - 1;
+
+                if self.expiry_ticks[id] <= current_tick {
+                    self.slots[slot_index] &= !(1 << id);
+                    expire(id);
+                }
+            }
+        }
+
+        self.next_tick = current_tick + 1;
+    }
+
+    fn slot_index(tick: u64) -> usize {
+        (tick % SLOT_COUNT as u64) as usize
+    }
+}
+
+impl<const SLOT_COUNT: usize> Default for TimerWheel<SLOT_COUNT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{vec, vec::Vec};
+
+    fn advance(wheel: &mut TimerWheel<8>, current_tick: u64) -> Vec<usize> {
+        let mut expired = Vec::new();
+        wheel.advance(current_tick, |id| expired.push(id));
+        expired
+    }
+
+    #[test]
+    fn test_timers_expire_at_their_tick() {
+        let mut wheel = TimerWheel::<8>::new();
+
+        wheel.insert(1, 3);
+        wheel.insert(2, 5);
+        wheel.insert(3, 3);
+
+        assert_eq!(advance(&mut wheel, 2), vec![]);
+        assert_eq!(advance(&mut wheel, 3), vec![1, 3]);
+        assert!(wheel.is_pending(2));
+        assert_eq!(advance(&mut wheel, 4), vec![]);
+        assert_eq!(advance(&mut wheel, 5), vec![2]);
+        assert!(!wheel.is_pending(2));
+    }
+
+    #[test]
+    fn test_timers_beyond_one_turn() {
+        let mut wheel = TimerWheel::<8>::new();
+
+        // Both timers share a slot, but the second is a turn later.
+        wheel.insert(4, 2);
+        wheel.insert(5, 10);
+
+        assert_eq!(advance(&mut wheel, 2), vec![4]);
+        assert_eq!(advance(&mut wheel, 9), vec![]);
+        assert_eq!(advance(&mut wheel, 10), vec![5]);
+    }
+
+    #[test]
+    fn test_missed_ticks_and_past_expiry() {
+        let mut wheel = TimerWheel::<8>::new();
+
+        wheel.insert(0, 1);
+        wheel.insert(63, 20);
+
+        // Jumping many turns ahead still expires everything that is due.
+        assert_eq!(advance(&mut wheel, 100), vec![0, 63]);
+
+        // A timer in the past expires at the next advance.
+        wheel.insert(7, 50);
+        assert!(wheel.is_pending(7));
+        assert_eq!(advance(&mut wheel, 101), vec![7]);
+    }
+
+    #[test]
+    fn test_cancel_and_replace() {
+        let mut wheel = TimerWheel::<8>::new();
+
+        wheel.insert(1, 3);
+        assert!(wheel.cancel(1));
+        assert!(!wheel.cancel(1));
+
+        wheel.insert(2, 3);
+        wheel.insert(2, 6);
+
+        assert_eq!(advance(&mut wheel, 5), vec![]);
+        assert_eq!(advance(&mut wheel, 6), vec![2]);
+    }
+}
@@ -0,0 +1,662 @@
+//! A writable file system in memory with a fixed capacity.
+//!
+//! A `Tmpfs` keeps a table of `NODE_COUNT` files and directories and a pool
+//! of `BLOCK_COUNT` blocks of `BLOCK_SIZE` bytes that hold the contents of
+//! files, so it can live in a `static` behind a lock. Blocks are allocated as
+//! files are written, and parts of a file that were never written read as
+//! zeros.
+
+use core::fmt;
+
+/// The size of a block of file contents in bytes.
+pub const BLOCK_SIZE: usize = 1024;
+
+/// The most blocks a file can have.
+pub const MAX_FILE_BLOCKS: usize = 16;
+
+/// The largest size of a file in bytes.
+pub const MAX_FILE_SIZE: usize = BLOCK_SIZE * MAX_FILE_BLOCKS;
+
+/// The longest name of a file or directory in bytes.
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// The reasons an operation failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TmpfsError {
+    /// Nothing has the path or name, or the node was removed.
+    NotFound,
+
+    /// A path component or the node to list or create in is not a directory.
+    NotADirectory,
+
+    /// The node to read or write is a directory.
+    IsADirectory,
+
+    /// The directory has an entry with the name already.
+    AlreadyExists,
+
+    /// The directory to remove has entries.
+    NotEmpty,
+
+    /// The name is empty, ".", "..", or has a '/'.
+    InvalidName,
+
+    /// The name is longer than `MAX_NAME_LENGTH`.
+    NameTooLong,
+
+    /// All `NODE_COUNT` nodes are in use.
+    TooManyNodes,
+
+    /// Too few of the `BLOCK_COUNT` blocks are free.
+    OutOfSpace,
+
+    /// The file would be larger than `MAX_FILE_SIZE`.
+    FileTooLarge,
+}
+
+impl fmt::Display for TmpfsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "not found"),
+            Self::NotADirectory => write!(f, "not a directory"),
+            Self::IsADirectory => write!(f, "is a directory"),
+            Self::AlreadyExists => write!(f, "already exists"),
+            Self::NotEmpty => write!(f, "the directory is not empty"),
+            Self::InvalidName => write!(f, "invalid name"),
+            Self::NameTooLong => write!(f, "the name is longer than {} bytes", MAX_NAME_LENGTH),
+            Self::TooManyNodes => write!(f, "too many files"),
+            Self::OutOfSpace => write!(f, "out of space"),
+            Self::FileTooLarge => {
+                write!(f, "the file would be larger than {} bytes", MAX_FILE_SIZE)
+            }
+        }
+    }
+}
+
+/// The type of a node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeKind {
+    File,
+    Directory,
+}
+
+/// Identifies a node for as long as it exists.
+///
+/// The low 32 bits are the index of the node in the table and the high 32
+/// bits its generation, which changes when the node is removed, so the ID of
+/// a removed node does not name a node created later in its place.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NodeId(pub u64);
+
+impl NodeId {
+    fn new(index: usize, generation: u32) -> Self {
+        Self(((generation as u64) << 32) | index as u64)
+    }
+
+    fn index(self) -> usize {
+        self.0 as u32 as usize
+    }
+
+    fn generation(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+}
+
+/// An entry of the node table.
+#[derive(Debug, Copy, Clone)]
+struct Node {
+    is_used: bool,
+    generation: u32,
+    kind: NodeKind,
+
+    /// The index of the directory that holds the node. The root directory
+    /// is its own parent.
+    parent: usize,
+
+    name: [u8; MAX_NAME_LENGTH],
+    name_length: usize,
+
+    /// The size of a file in bytes. Always 0 for directories.
+    size: usize,
+
+    /// The indexes of the blocks of a file, in order. `None` for parts that
+    /// were never written.
+    blocks: [Option<u16>; MAX_FILE_BLOCKS],
+}
+
+impl Node {
+    const FREE: Self = Self {
+        is_used: false,
+        generation: 0,
+        kind: NodeKind::File,
+        parent: 0,
+        name: [0; MAX_NAME_LENGTH],
+        name_length: 0,
+        size: 0,
+        blocks: [None; MAX_FILE_BLOCKS],
+    };
+
+    fn name(&self) -> &str {
+        // Names are copied from `&str`s whole.
+        core::str::from_utf8(&self.name[..self.name_length]).unwrap_or("")
+    }
+}
+
+/// A file system of up to `NODE_COUNT` nodes, including the root directory,
+/// and `BLOCK_COUNT` blocks of file contents.
+pub struct Tmpfs<const NODE_COUNT: usize, const BLOCK_COUNT: usize> {
+    nodes: [Node; NODE_COUNT],
+    blocks: [[u8; BLOCK_SIZE]; BLOCK_COUNT],
+    is_block_used: [bool; BLOCK_COUNT],
+}
+
+impl<const NODE_COUNT: usize, const BLOCK_COUNT: usize> Tmpfs<NODE_COUNT, BLOCK_COUNT> {
+    /// Creates a file system with only an empty root directory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `NODE_COUNT` is 0 or `BLOCK_COUNT` is more than a `u16`
+    /// can index.
+    pub const fn new() -> Self {
+        assert!(NODE_COUNT > 0 && BLOCK_COUNT <= u16::MAX as usize);
+
+        let mut nodes = [Node::FREE; NODE_COUNT];
+        nodes[0].is_used = true;
+        nodes[0].kind = NodeKind::Directory;
+
+        Self {
+            nodes,
+            blocks: [[0; BLOCK_SIZE]; BLOCK_COUNT],
+            is_block_used: [false; BLOCK_COUNT],
+        }
+    }
+
+    /// Returns the root directory.
+    pub fn root(&self) -> NodeId {
+        NodeId::new(0, self.nodes[0].generation)
+    }
+
+    /// Returns the number of blocks that are not in use.
+    pub fn free_block_count(&self) -> usize {
+        self.is_block_used
+            .iter()
+            .filter(|is_used| !**is_used)
+            .count()
+    }
+
+    /// Returns the type of a node.
+    pub fn kind(&self, node: NodeId) -> Result<NodeKind, TmpfsError> {
+        Ok(self.node(node)?.kind)
+    }
+
+    /// Returns the size of a file in bytes, or 0 for a directory.
+    pub fn size(&self, node: NodeId) -> Result<usize, TmpfsError> {
+        Ok(self.node(node)?.size)
+    }
+
+    /// Looks up a node by path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The names of the directories leading to the node and of
+    ///   the node, separated by '/'. Empty names are ignored, so "" and "/"
+    ///   are the root directory.
+    pub fn lookup(&self, path: &str) -> Result<NodeId, TmpfsError> {
+        let mut node = self.root();
+
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            node = self.find_child(node, name)?.ok_or(TmpfsError::NotFound)?;
+        }
+
+        Ok(node)
+    }
+
+    /// Calls a function for every entry of a directory, in no particular
+    /// order.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory.
+    /// * `callback` - Called with the name and the ID of each entry.
+    pub fn for_each_child(
+        &self,
+        directory: NodeId,
+        mut callback: impl FnMut(&str, NodeId),
+    ) -> Result<(), TmpfsError> {
+        let directory_index = self.directory(directory)?;
+
+        for (index, node) in self.children(directory_index) {
+            callback(node.name(), NodeId::new(index, node.generation));
+        }
+
+        Ok(())
+    }
+
+    /// Creates an empty file or directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory to create the node in.
+    /// * `name` - The name of the node.
+    /// * `kind` - The type of the node.
+    pub fn create(
+        &mut self,
+        directory: NodeId,
+        name: &str,
+        kind: NodeKind,
+    ) -> Result<NodeId, TmpfsError> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(TmpfsError::InvalidName);
+        }
+
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(TmpfsError::NameTooLong);
+        }
+
+        if self.find_child(directory, name)?.is_some() {
+            return Err(TmpfsError::AlreadyExists);
+        }
+
+        let (index, node) = self
+            .nodes
+            .iter_mut()
+            .enumerate()
+            .find(|(_, node)| !node.is_used)
+            .ok_or(TmpfsError::TooManyNodes)?;
+
+        *node = Node {
+            is_used: true,
+            generation: node.generation,
+            kind,
+            parent: directory.index(),
+            name: [0; MAX_NAME_LENGTH],
+            name_length: name.len(),
+            size: 0,
+            blocks: [None; MAX_FILE_BLOCKS],
+        };
+
+        node.name[..name.len()].copy_from_slice(name.as_bytes());
+
+        Ok(NodeId::new(index, node.generation))
+    }
+
+    /// Removes a file or an empty directory and frees its blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory that holds the node.
+    /// * `name` - The name of the node.
+    pub fn remove(&mut self, directory: NodeId, name: &str) -> Result<(), TmpfsError> {
+        let node = self
+            .find_child(directory, name)?
+            .ok_or(TmpfsError::NotFound)?;
+
+        let index = node.index();
+
+        if self.children(index).next().is_some() {
+            return Err(TmpfsError::NotEmpty);
+        }
+
+        self.free_blocks(index, 0);
+
+        let node = &mut self.nodes[index];
+        node.is_used = false;
+        node.generation = node.generation.wrapping_add(1);
+
+        Ok(())
+    }
+
+    /// Reads part of a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file.
+    /// * `offset` - The offset in the file to read from.
+    /// * `buffer` - Receives the bytes.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read, which is less than the length of `buffer`
+    /// only at the end of the file.
+    pub fn read(
+        &self,
+        file: NodeId,
+        offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<usize, TmpfsError> {
+        let node = self.file(file)?;
+        let length = buffer.len().min(node.size.saturating_sub(offset));
+
+        let mut done = 0;
+        while done < length {
+            let position = offset + done;
+            let offset_in_block = position % BLOCK_SIZE;
+            let chunk_length = (length - done).min(BLOCK_SIZE - offset_in_block);
+            let chunk = &mut buffer[done..done + chunk_length];
+
+            match node.blocks[position / BLOCK_SIZE] {
+                Some(block) => chunk.copy_from_slice(
+                    &self.blocks[block as usize][offset_in_block..offset_in_block + chunk_length],
+                ),
+                None => chunk.fill(0),
+            }
+
+            done += chunk_length;
+        }
+
+        Ok(length)
+    }
+
+    /// Writes part of a file, growing it if the write goes past its end.
+    ///
+    /// Nothing is written if the blocks the write needs cannot all be
+    /// allocated.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file.
+    /// * `offset` - The offset in the file to write at, which may be past
+    ///   its end.
+    /// * `data` - The bytes to write.
+    pub fn write(&mut self, file: NodeId, offset: usize, data: &[u8]) -> Result<(), TmpfsError> {
+        let index = file.index();
+        self.file(file)?;
+
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let end = offset
+            .checked_add(data.len())
+            .filter(|end| *end <= MAX_FILE_SIZE)
+            .ok_or(TmpfsError::FileTooLarge)?;
+
+        let block_range = offset / BLOCK_SIZE..end.div_ceil(BLOCK_SIZE);
+
+        let missing_block_count = self.nodes[index].blocks[block_range.clone()]
+            .iter()
+            .filter(|block| block.is_none())
+            .count();
+
+        if missing_block_count > self.free_block_count() {
+            return Err(TmpfsError::OutOfSpace);
+        }
+
+        for block_index in block_range {
+            if self.nodes[index].blocks[block_index].is_none() {
+                let block = self.allocate_block();
+                self.nodes[index].blocks[block_index] = Some(block);
+            }
+        }
+
+        let mut done = 0;
+        while done < data.len() {
+            let position = offset + done;
+            let offset_in_block = position % BLOCK_SIZE;
+            let chunk_length = (data.len() - done).min(BLOCK_SIZE - offset_in_block);
+
+            let block = self.nodes[index].blocks[position / BLOCK_SIZE]
+                .expect("Every block of the write was allocated.");
+
+            self.blocks[block as usize][offset_in_block..offset_in_block + chunk_length]
+                .copy_from_slice(&data[done..done + chunk_length]);
+
+            done += chunk_length;
+        }
+
+        let node = &mut self.nodes[index];
+        node.size = node.size.max(end);
+
+        Ok(())
+    }
+
+    /// Changes the size of a file. Shrinking discards the bytes past the new
+    /// end and frees their blocks; growing adds zeros.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file.
+    /// * `size` - The new size in bytes.
+    pub fn truncate(&mut self, file: NodeId, size: usize) -> Result<(), TmpfsError> {
+        let old_size = self.file(file)?.size;
+        let index = file.index();
+
+        if size > MAX_FILE_SIZE {
+            return Err(TmpfsError::FileTooLarge);
+        }
+
+        if size < old_size {
+            self.free_blocks(index, size.div_ceil(BLOCK_SIZE));
+
+            // Bytes past the end must read as zeros if the file grows again.
+            if let Some(block) = self.nodes[index]
+                .blocks
+                .get(size / BLOCK_SIZE)
+                .copied()
+                .flatten()
+            {
+                self.blocks[block as usize][size % BLOCK_SIZE..].fill(0);
+            }
+        }
+
+        self.nodes[index].size = size;
+
+        Ok(())
+    }
+
+    /// Returns a node if it exists.
+    fn node(&self, node: NodeId) -> Result<&Node, TmpfsError> {
+        self.nodes
+            .get(node.index())
+            .filter(|entry| entry.is_used && entry.generation == node.generation())
+            .ok_or(TmpfsError::NotFound)
+    }
+
+    /// Returns the index of a directory.
+    fn directory(&self, directory: NodeId) -> Result<usize, TmpfsError> {
+        match self.node(directory)?.kind {
+            NodeKind::Directory => Ok(directory.index()),
+            NodeKind::File => Err(TmpfsError::NotADirectory),
+        }
+    }
+
+    /// Returns a file.
+    fn file(&self, file: NodeId) -> Result<&Node, TmpfsError> {
+        let node = self.node(file)?;
+
+        match node.kind {
+            NodeKind::File => Ok(node),
+            NodeKind::Directory => Err(TmpfsError::IsADirectory),
+        }
+    }
+
+    /// Returns the indexes and nodes of the entries of a directory.
+    fn children(&self, directory_index: usize) -> impl Iterator<Item = (usize, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(move |(_, node)| node.is_used && node.parent == directory_index)
+    }
+
+    /// Looks up an entry of a directory by name.
+    fn find_child(&self, directory: NodeId, name: &str) -> Result<Option<NodeId>, TmpfsError> {
+        let directory_index = self.directory(directory)?;
+
+        Ok(self
+            .children(directory_index)
+            .find(|(_, node)| node.name() == name)
+            .map(|(index, node)| NodeId::new(index, node.generation)))
+    }
+
+    /// Allocates a zeroed block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no block is free, which callers check first.
+    fn allocate_block(&mut self) -> u16 {
+        let block = self
+            .is_block_used
+            .iter()
+            .position(|is_used| !is_used)
+            .expect("A block is free.");
+
+        self.is_block_used[block] = true;
+        self.blocks[block].fill(0);
+
+        block as u16
+    }
+
+    /// Frees the blocks of a node from a block index of the node on.
+    fn free_blocks(&mut self, index: usize, first_block_index: usize) {
+        for block in self.nodes[index].blocks[first_block_index.min(MAX_FILE_BLOCKS)..].iter_mut() {
+            if let Some(block) = block.take() {
+                self.is_block_used[block as usize] = false;
+            }
+        }
+    }
+}
+
+impl<const NODE_COUNT: usize, const BLOCK_COUNT: usize> Default for Tmpfs<NODE_COUNT, BLOCK_COUNT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type SmallTmpfs = Tmpfs<4, 3>;
+
+    #[test]
+    fn test_write_and_read_across_blocks_and_holes() {
+        let mut tmpfs = SmallTmpfs::new();
+        let file = tmpfs.create(tmpfs.root(), "file", NodeKind::File).unwrap();
+
+        tmpfs.write(file, BLOCK_SIZE - 2, b"abcd").unwrap();
+        assert_eq!(tmpfs.size(file), Ok(BLOCK_SIZE + 2));
+        assert_eq!(tmpfs.free_block_count(), 1);
+
+        let mut buffer = [0xFF; 8];
+        assert_eq!(tmpfs.read(file, BLOCK_SIZE - 4, &mut buffer), Ok(6));
+        assert_eq!(&buffer[..6], b"\0\0abcd");
+
+        // A write past the end leaves a hole that reads as zeros.
+        tmpfs.write(file, BLOCK_SIZE * 2 + 1, b"z").unwrap();
+        assert_eq!(tmpfs.free_block_count(), 0);
+        assert_eq!(tmpfs.read(file, BLOCK_SIZE * 2 - 1, &mut buffer), Ok(3));
+        assert_eq!(&buffer[..3], b"\0\0z");
+    }
+
+    #[test]
+    fn test_lookup_and_list_directories() {
+        let mut tmpfs = SmallTmpfs::new();
+        let directory = tmpfs
+            .create(tmpfs.root(), "dir", NodeKind::Directory)
+            .unwrap();
+        let file = tmpfs.create(directory, "file", NodeKind::File).unwrap();
+
+        assert_eq!(tmpfs.lookup("/dir//file"), Ok(file));
+        assert_eq!(tmpfs.lookup("/"), Ok(tmpfs.root()));
+        assert_eq!(tmpfs.lookup("dir/missing"), Err(TmpfsError::NotFound));
+        assert_eq!(tmpfs.lookup("dir/file/x"), Err(TmpfsError::NotADirectory));
+
+        let mut names = Vec::new();
+        tmpfs
+            .for_each_child(tmpfs.root(), |name, node| {
+                names.push((name.to_string(), node))
+            })
+            .unwrap();
+        assert_eq!(names, [("dir".to_string(), directory)]);
+    }
+
+    #[test]
+    fn test_create_rejects_bad_names_and_full_tables() {
+        let mut tmpfs = SmallTmpfs::new();
+        let root = tmpfs.root();
+
+        assert_eq!(
+            tmpfs.create(root, "..", NodeKind::File),
+            Err(TmpfsError::InvalidName)
+        );
+        assert_eq!(
+            tmpfs.create(root, "a/b", NodeKind::File),
+            Err(TmpfsError::InvalidName)
+        );
+        assert_eq!(
+            tmpfs.create(root, &"x".repeat(MAX_NAME_LENGTH + 1), NodeKind::File),
+            Err(TmpfsError::NameTooLong)
+        );
+
+        let file = tmpfs.create(root, "a", NodeKind::File).unwrap();
+        assert_eq!(
+            tmpfs.create(root, "a", NodeKind::File),
+            Err(TmpfsError::AlreadyExists)
+        );
+        assert_eq!(
+            tmpfs.create(file, "b", NodeKind::File),
+            Err(TmpfsError::NotADirectory)
+        );
+
+        tmpfs.create(root, "b", NodeKind::File).unwrap();
+        tmpfs.create(root, "c", NodeKind::File).unwrap();
+        assert_eq!(
+            tmpfs.create(root, "d", NodeKind::File),
+            Err(TmpfsError::TooManyNodes)
+        );
+
+        assert_eq!(
+            tmpfs.write(file, 0, &[1; BLOCK_SIZE * 4]),
+            Err(TmpfsError::OutOfSpace)
+        );
+        assert_eq!(tmpfs.size(file), Ok(0));
+        assert_eq!(
+            tmpfs.write(file, MAX_FILE_SIZE, b"x"),
+            Err(TmpfsError::FileTooLarge)
+        );
+    }
+
+    #[test]
+    fn test_remove_frees_blocks_and_invalidates_the_id() {
+        let mut tmpfs = SmallTmpfs::new();
+        let root = tmpfs.root();
+        let directory = tmpfs.create(root, "dir", NodeKind::Directory).unwrap();
+        let file = tmpfs.create(directory, "file", NodeKind::File).unwrap();
+        tmpfs.write(file, 0, &[7; BLOCK_SIZE * 2]).unwrap();
+
+        assert_eq!(tmpfs.remove(root, "dir"), Err(TmpfsError::NotEmpty));
+
+        tmpfs.remove(directory, "file").unwrap();
+        assert_eq!(tmpfs.free_block_count(), 3);
+        assert_eq!(tmpfs.size(file), Err(TmpfsError::NotFound));
+
+        let new_file = tmpfs.create(directory, "new", NodeKind::File).unwrap();
+        assert_ne!(new_file, file);
+        assert_eq!(tmpfs.read(file, 0, &mut [0; 1]), Err(TmpfsError::NotFound));
+
+        tmpfs.remove(directory, "new").unwrap();
+        tmpfs.remove(root, "dir").unwrap();
+        assert_eq!(tmpfs.lookup("dir"), Err(TmpfsError::NotFound));
+    }
+
+    #[test]
+    fn test_truncate_discards_and_zeroes_the_tail() {
+        let mut tmpfs = SmallTmpfs::new();
+        let file = tmpfs.create(tmpfs.root(), "file", NodeKind::File).unwrap();
+        tmpfs.write(file, 0, &[9; BLOCK_SIZE + 10]).unwrap();
+
+        tmpfs.truncate(file, 4).unwrap();
+        assert_eq!(tmpfs.free_block_count(), 2);
+
+        tmpfs.truncate(file, 8).unwrap();
+        let mut buffer = [0xFF; 8];
+        assert_eq!(tmpfs.read(file, 0, &mut buffer), Ok(8));
+        assert_eq!(buffer, [9, 9, 9, 9, 0, 0, 0, 0]);
+
+        assert_eq!(
+            tmpfs.truncate(file, MAX_FILE_SIZE + 1),
+            Err(TmpfsError::FileTooLarge)
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Sorted lists of virtual memory areas.
+//!
+//! A virtual memory area (VMA) is a page aligned range of virtual addresses
+//! that an address space has reserved for some purpose, whether or not any of
+//! its pages are mapped yet. The list keeps its areas sorted by address in a
+//! fixed array, so lookups and inserts need no heap.
+
+use core::{fmt, ops::Range};
+
+/// A range of virtual addresses with attributes chosen by the owner of the
+/// list, such as the access its pages allow.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VirtualMemoryArea<T> {
+    /// The first address of the area.
+    pub start: usize,
+
+    /// The address just past the end of the area.
+    pub end: usize,
+
+    pub attributes: T,
+}
+
+impl<T> VirtualMemoryArea<T> {
+    /// Returns true if the area contains an address.
+    pub fn contains(&self, address: usize) -> bool {
+        (self.start..self.end).contains(&address)
+    }
+}
+
+/// The reasons an area could not be inserted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AreaError {
+    /// The area is empty or ends before it starts.
+    Empty,
+
+    /// The area overlaps an area already in the list.
+    Overlaps,
+
+    /// The list already holds `CAPACITY` areas.
+    Full,
+}
+
+impl fmt::Display for AreaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "the area is empty"),
+            Self::Overlaps => write!(f, "the area overlaps another area"),
+            Self::Full => write!(f, "the area list is full"),
+        }
+    }
+}
+
+/// Up to `CAPACITY` areas that do not overlap, sorted by start address.
+#[derive(Debug, Copy, Clone)]
+pub struct VirtualMemoryAreaList<T: Copy, const CAPACITY: usize> {
+    areas: [Option<VirtualMemoryArea<T>>; CAPACITY],
+
+    /// The number of areas, which are the first `count` entries of `areas`.
+    count: usize,
+}
+
+impl<T: Copy, const CAPACITY: usize> VirtualMemoryAreaList<T, CAPACITY> {
+    /// Creates an empty list.
+    pub const fn new() -> Self {
+        Self {
+            areas: [None; CAPACITY],
+            count: 0,
+        }
+    }
+
+    /// Returns the number of areas in the list.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns true if the list holds no areas.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the areas in order of their start address.
+    pub fn iter(&self) -> impl Iterator<Item = &VirtualMemoryArea<T>> {
+        self.areas[..self.count].iter().flatten()
+    }
+
+    /// Returns the area that contains an address, or `None` if no area does.
+    pub fn find(&self, address: usize) -> Option<&VirtualMemoryArea<T>> {
+        self.iter()
+            .take_while(|area| area.start <= address)
+            .find(|area| area.contains(address))
+    }
+
+    /// Adds an area to the list.
+    ///
+    /// # Arguments
+    ///
+    /// * `area` - The area, which must not overlap any area in the list.
+    pub fn insert(&mut self, area: VirtualMemoryArea<T>) -> Result<(), AreaError> {
+        if area.start >= area.end {
+            return Err(AreaError::Empty);
+        }
+
+        // The index of the first area that starts after the new one.
+        let index = self
+            .iter()
+            .position(|existing| existing.start >= area.end)
+            .unwrap_or(self.count);
+
+        // The area before it is the only one the new area can overlap, since
+        // the areas are sorted and do not overlap each other.
+        let overlaps_previous =
+            index > 0 && self.areas[index - 1].is_some_and(|previous| previous.end > area.start);
+
+        if overlaps_previous {
+            return Err(AreaError::Overlaps);
+        }
+
+        if self.count == CAPACITY {
+            return Err(AreaError::Full);
+        }
+
+        self.areas[index..=self.count].rotate_right(1);
+        self.areas[index] = Some(area);
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// Finds the lowest free range of addresses that is large enough for an
+    /// area and lies entirely within a window.
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - The size of the area in bytes.
+    /// * `window` - The addresses the area must lie within.
+    ///
+    /// # Returns
+    ///
+    /// The start address of the free range, or `None` if the window has no
+    /// gap that large.
+    pub fn find_free_range(&self, length: usize, window: Range<usize>) -> Option<usize> {
+        let mut candidate = window.start;
+
+        for area in self.iter() {
+            if area.end <= candidate {
+                continue;
+            }
+
+            if area.start >= candidate.checked_add(length)? {
+                break;
+            }
+
+            candidate = area.end;
+        }
+
+        (candidate.checked_add(length)? <= window.end).then_some(candidate)
+    }
+}
+
+impl<T: Copy, const CAPACITY: usize> Default for VirtualMemoryAreaList<T, CAPACITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(start: usize, end: usize) -> VirtualMemoryArea<u8> {
+        VirtualMemoryArea {
+            start,
+            end,
+            attributes: 0,
+        }
+    }
+
+    #[test]
+    fn test_insert_keeps_areas_sorted() {
+        let mut list = VirtualMemoryAreaList::<u8, 4>::new();
+
+        list.insert(area(0x3000, 0x4000)).unwrap();
+        list.insert(area(0x1000, 0x2000)).unwrap();
+        list.insert(area(0x2000, 0x3000)).unwrap();
+
+        let starts: Vec<usize> = list.iter().map(|area| area.start).collect();
+        assert_eq!(starts, [0x1000, 0x2000, 0x3000]);
+        assert_eq!(list.len(), 3);
+    }
+
+    #[test]
+    fn test_insert_rejects_overlaps_empty_areas_and_full_lists() {
+        let mut list = VirtualMemoryAreaList::<u8, 2>::new();
+
+        list.insert(area(0x2000, 0x4000)).unwrap();
+
+        assert_eq!(list.insert(area(0x1000, 0x3000)), Err(AreaError::Overlaps));
+        assert_eq!(list.insert(area(0x3000, 0x5000)), Err(AreaError::Overlaps));
+        assert_eq!(list.insert(area(0x2800, 0x3000)), Err(AreaError::Overlaps));
+        assert_eq!(list.insert(area(0x5000, 0x5000)), Err(AreaError::Empty));
+
+        list.insert(area(0x4000, 0x5000)).unwrap();
+        assert_eq!(list.insert(area(0x8000, 0x9000)), Err(AreaError::Full));
+        assert_eq!(list.len(), 2);
+    }
+
+    #[test]
+    fn test_find_returns_the_containing_area() {
+        let mut list = VirtualMemoryAreaList::<u8, 4>::new();
+
+        list.insert(area(0x1000, 0x2000)).unwrap();
+        list.insert(area(0x4000, 0x6000)).unwrap();
+
+        assert_eq!(list.find(0x1FFF).map(|area| area.start), Some(0x1000));
+        assert_eq!(list.find(0x5000).map(|area| area.start), Some(0x4000));
+        assert_eq!(list.find(0x2000), None);
+        assert_eq!(list.find(0x0FFF), None);
+        assert_eq!(list.find(0x6000), None);
+    }
+
+    #[test]
+    fn test_find_free_range_takes_the_lowest_gap() {
+        let mut list = VirtualMemoryAreaList::<u8, 4>::new();
+
+        list.insert(area(0x1000, 0x2000)).unwrap();
+        list.insert(area(0x3000, 0x4000)).unwrap();
+
+        assert_eq!(list.find_free_range(0x1000, 0x1000..0x10000), Some(0x2000));
+        assert_eq!(list.find_free_range(0x2000, 0x1000..0x10000), Some(0x4000));
+        assert_eq!(list.find_free_range(0x1000, 0..0x10000), Some(0));
+        assert_eq!(list.find_free_range(0x1000, 0x3000..0x5000), Some(0x4000));
+        assert_eq!(list.find_free_range(0x2000, 0x3000..0x5000), None);
+        assert_eq!(
+            list.find_free_range(0x1000, usize::MAX - 0x800..usize::MAX),
+            None
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Wrappers for the SBI Debug Console (DBCN) extension, and the
+//! `debug_print!` and `debug_println!` macros that write through it.
+//!
+//! The SBI implementation accesses console buffers by their physical address.
+//! `DebugConsoleWriter` writes one byte at a time instead, so it works with
+//! paging on or off and never needs to translate the caller's buffers.
+
+use super::{
+    SbiResult,
+    sbi_calls::{sbi_call_1, sbi_call_3},
+    sbi_error::to_sbi_result,
+};
+use core::fmt::{self, Write};
+
+const DEBUG_CONSOLE_EXTENSION_ID: i32 = 0x4442434E;
+
+const CONSOLE_WRITE_ID: i32 = 0x0;
+const CONSOLE_READ_ID: i32 = 0x1;
+const CONSOLE_WRITE_BYTE_ID: i32 = 0x2;
+
+/// Writes bytes to the debug console.
+///
+/// # Arguments
+///
+/// * `physical_address` - The physical address of the bytes.
+/// * `length` - The number of bytes.
+///
+/// # Returns
+///
+/// The number of bytes written, which may be fewer than `length`.
+#[inline(always)]
+pub fn console_write(physical_address: usize, length: usize) -> SbiResult<usize> {
+    to_sbi_result(sbi_call_3(
+        DEBUG_CONSOLE_EXTENSION_ID as isize,
+        CONSOLE_WRITE_ID as isize,
+        length,
+        physical_address,
+        0,
+    ))
+}
+
+/// Reads the bytes waiting on the debug console without blocking.
+///
+/// # Arguments
+///
+/// * `physical_address` - The physical address of the buffer to fill.
+/// * `length` - The number of bytes of the buffer.
+///
+/// # Returns
+///
+/// The number of bytes read, which is zero if no input is waiting.
+#[inline(always)]
+pub fn console_read(physical_address: usize, length: usize) -> SbiResult<usize> {
+    to_sbi_result(sbi_call_3(
+        DEBUG_CONSOLE_EXTENSION_ID as isize,
+        CONSOLE_READ_ID as isize,
+        length,
+        physical_address,
+        0,
+    ))
+}
+
+/// Writes a single byte to the debug console, blocking until it is written.
+#[inline(always)]
+pub fn console_write_byte(byte: u8) -> SbiResult<()> {
+    to_sbi_result(sbi_call_1(
+        DEBUG_CONSOLE_EXTENSION_ID as isize,
+        CONSOLE_WRITE_BYTE_ID as isize,
+        byte as usize,
+    ))
+    .map(|_| ())
+}
+
+/// A formatter that writes directly to the SBI debug console.
+pub struct DebugConsoleWriter;
+
+impl Write for DebugConsoleWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            console_write_byte(byte).map_err(|_| fmt::Error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Prints formatted text to the SBI debug console without heap allocations.
+///
+/// This macro works similar to `format!` but writes directly to the debug
+/// console.
+///
+/// # Examples
+///
+/// ```ignore
+/// debug_print!("Hello, {}!", "world");
+/// debug_println!("Value = {}", 42);
+/// ```
+#[macro_export]
+macro_rules! debug_print {
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        use $crate::debug_console::DebugConsoleWriter;
+        let _ = write!(DebugConsoleWriter, $($arg)*);
+    }};
+}
+
+/// Prints formatted text to the SBI debug console, followed by a newline.
+///
+/// This macro works similar to `format!` but writes directly to the debug
+/// console.
+///
+/// # Examples
+///
+/// ```ignore
+/// debug_println!("Hello, {}!", "world");
+/// debug_println!("Value = {}", 42);
+/// ```
+#[macro_export]
+macro_rules! debug_println {
+    () => {
+        $crate::debug_print!("\n")
+    };
+    ($($arg:tt)*) => {{
+        $crate::debug_print!($($arg)*);
+        $crate::debug_print!("\n");
+    }};
+}
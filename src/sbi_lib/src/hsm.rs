@@ -0,0 +1,140 @@
+//! Wrappers for the SBI Hart State Management (HSM) extension.
+//!
+//! The HSM extension lets supervisor software start, stop, suspend, and query
+//! the state of individual harts.
+
+use super::{
+    SbiError, SbiResult,
+    sbi_calls::{sbi_call_1, sbi_call_3},
+    sbi_error::to_sbi_result,
+};
+
+const HART_STATE_MANAGEMENT_EXTENSION_ID: i32 = 0x48534D;
+
+const HART_START_ID: i32 = 0x0;
+const HART_STOP_ID: i32 = 0x1;
+const HART_GET_STATUS_ID: i32 = 0x2;
+const HART_SUSPEND_ID: i32 = 0x3;
+
+/// The default retentive suspend type. The hart resumes execution after the
+/// `hart_suspend` call with all register state preserved.
+pub const SUSPEND_TYPE_DEFAULT_RETENTIVE: u32 = 0x0000_0000;
+
+/// The default non-retentive suspend type. The hart resumes at the resume
+/// address in supervisor mode as if it had been freshly started.
+pub const SUSPEND_TYPE_DEFAULT_NON_RETENTIVE: u32 = 0x8000_0000;
+
+/// The states a hart can be in according to the HSM extension.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HartState {
+    Started,
+    Stopped,
+    StartPending,
+    StopPending,
+    Suspended,
+    SuspendPending,
+    ResumePending,
+    Unknown(usize),
+}
+
+impl HartState {
+    /// Decodes the value returned by `hart_get_status`.
+    pub const fn from_raw(value: usize) -> Self {
+        match value {
+            0 => Self::Started,
+            1 => Self::Stopped,
+            2 => Self::StartPending,
+            3 => Self::StopPending,
+            4 => Self::Suspended,
+            5 => Self::SuspendPending,
+            6 => Self::ResumePending,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+/// Requests that the SBI implementation start executing the target hart in
+/// supervisor mode at the given physical address.
+///
+/// The target hart starts with paging disabled, interrupts disabled, `a0` set
+/// to its hart ID, and `a1` set to `opaque`.
+///
+/// # Arguments
+///
+/// * `hart_id` - The hart to start.
+/// * `start_address` - The physical address where the hart begins executing.
+/// * `opaque` - An arbitrary value passed to the hart in `a1`.
+#[inline(always)]
+pub fn hart_start(hart_id: usize, start_address: usize, opaque: usize) -> SbiResult<()> {
+    to_sbi_result(sbi_call_3(
+        HART_STATE_MANAGEMENT_EXTENSION_ID as isize,
+        HART_START_ID as isize,
+        hart_id,
+        start_address,
+        opaque,
+    ))
+    .map(|_| ())
+}
+
+/// Stops the calling hart and returns ownership of it to the SBI
+/// implementation. This call does not return when it succeeds.
+///
+/// # Returns
+///
+/// The reason the hart could not be stopped.
+#[inline(always)]
+pub fn hart_stop() -> SbiError {
+    let result = to_sbi_result(sbi_call_1(
+        HART_STATE_MANAGEMENT_EXTENSION_ID as isize,
+        HART_STOP_ID as isize,
+        0,
+    ));
+
+    // A successful stop never returns, so any return is a failure.
+    match result {
+        Ok(_) => SbiError::Failed,
+        Err(error) => error,
+    }
+}
+
+/// Queries the current state of a hart.
+///
+/// # Arguments
+///
+/// * `hart_id` - The hart to query.
+///
+/// # Returns
+///
+/// The state of the hart, or `SbiError::InvalidParam` if the hart does not
+/// exist.
+#[inline(always)]
+pub fn hart_get_status(hart_id: usize) -> SbiResult<HartState> {
+    to_sbi_result(sbi_call_1(
+        HART_STATE_MANAGEMENT_EXTENSION_ID as isize,
+        HART_GET_STATUS_ID as isize,
+        hart_id,
+    ))
+    .map(HartState::from_raw)
+}
+
+/// Puts the calling hart into a platform specific suspend state.
+///
+/// # Arguments
+///
+/// * `suspend_type` - Either a retentive or non-retentive suspend type, such
+///   as `SUSPEND_TYPE_DEFAULT_RETENTIVE`.
+/// * `resume_address` - The physical address where the hart resumes after a
+///   non-retentive suspend. Ignored for retentive suspends.
+/// * `opaque` - Passed to the hart in `a1` when it resumes from a
+///   non-retentive suspend.
+#[inline(always)]
+pub fn hart_suspend(suspend_type: u32, resume_address: usize, opaque: usize) -> SbiResult<()> {
+    to_sbi_result(sbi_call_3(
+        HART_STATE_MANAGEMENT_EXTENSION_ID as isize,
+        HART_SUSPEND_ID as isize,
+        suspend_type as usize,
+        resume_address,
+        opaque,
+    ))
+    .map(|_| ())
+}
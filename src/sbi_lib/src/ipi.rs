@@ -0,0 +1,37 @@
+//! Wrappers for the SBI IPI extension.
+//!
+//! The IPI extension raises a supervisor software interrupt on a set of harts.
+
+use super::{SbiResult, sbi_calls::sbi_call_2, sbi_error::to_sbi_result};
+
+const IPI_EXTENSION_ID: i32 = 0x735049;
+
+const SEND_IPI_ID: i32 = 0x0;
+
+/// Raises a supervisor software interrupt on every hart selected by the mask.
+///
+/// # Arguments
+///
+/// * `hart_mask` - A bit mask of harts, where bit `n` selects hart
+///   `hart_mask_base + n`.
+/// * `hart_mask_base` - The hart ID that bit 0 of `hart_mask` refers to. A
+///   value of `usize::MAX` selects every hart and ignores `hart_mask`.
+#[inline(always)]
+pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) -> SbiResult<()> {
+    to_sbi_result(sbi_call_2(
+        IPI_EXTENSION_ID as isize,
+        SEND_IPI_ID as isize,
+        hart_mask,
+        hart_mask_base,
+    ))
+    .map(|_| ())
+}
+
+/// Raises a supervisor software interrupt on a single hart.
+///
+/// # Arguments
+///
+/// * `hart_id` - The hart to interrupt.
+pub fn send_ipi_to_hart(hart_id: usize) -> SbiResult<()> {
+    send_ipi(1, hart_id)
+}
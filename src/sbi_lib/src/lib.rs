@@ -0,0 +1,19 @@
+//! Calls into the SBI implementation, shared by the boot stage and the
+//! kernel.
+//!
+//! The `sbi_call_*` shims issue the `ecall`, and each extension module wraps
+//! them into typed functions that return a `SbiResult`.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod debug_console;
+pub mod hsm;
+pub mod ipi;
+pub mod pmu;
+pub mod rfence;
+pub mod sbi_calls;
+pub mod sbi_error;
+pub mod system_reset;
+pub mod timer;
+
+pub use sbi_error::{SbiError, SbiResult};
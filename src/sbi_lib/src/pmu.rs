@@ -0,0 +1,232 @@
+//! Wrappers for the SBI Performance Monitoring Unit (PMU) extension.
+//!
+//! The hardware performance counters can only be configured from machine
+//! mode, so the PMU extension lets supervisor software find a counter that
+//! can count an event, start and stop it, and read counters the SBI
+//! implementation keeps in firmware. Hardware counters are read directly
+//! through their CSRs, whose numbers `counter_get_info` reports.
+
+use super::{
+    SbiResult,
+    sbi_calls::{sbi_call_1, sbi_call_3, sbi_call_4, sbi_call_5},
+    sbi_error::to_sbi_result,
+};
+
+const PMU_EXTENSION_ID: i32 = 0x504D55;
+
+const NUM_COUNTERS_ID: i32 = 0x0;
+const COUNTER_GET_INFO_ID: i32 = 0x1;
+const COUNTER_CONFIG_MATCHING_ID: i32 = 0x2;
+const COUNTER_START_ID: i32 = 0x3;
+const COUNTER_STOP_ID: i32 = 0x4;
+const COUNTER_FW_READ_ID: i32 = 0x5;
+
+/// A `counter_config_matching` flag that uses the counters selected by the
+/// mask as they are instead of searching them for one that can count the
+/// event.
+pub const CONFIG_FLAG_SKIP_MATCH: usize = 1 << 0;
+
+/// A `counter_config_matching` flag that clears the counter.
+pub const CONFIG_FLAG_CLEAR_VALUE: usize = 1 << 1;
+
+/// A `counter_config_matching` flag that starts the counter once it is
+/// configured.
+pub const CONFIG_FLAG_AUTO_START: usize = 1 << 2;
+
+/// A `counter_config_matching` flag that stops the counter from counting
+/// events in user mode.
+pub const CONFIG_FLAG_SET_UINH: usize = 1 << 5;
+
+/// A `counter_config_matching` flag that stops the counter from counting
+/// events in supervisor mode.
+pub const CONFIG_FLAG_SET_SINH: usize = 1 << 6;
+
+/// A `counter_config_matching` flag that stops the counter from counting
+/// events in machine mode.
+pub const CONFIG_FLAG_SET_MINH: usize = 1 << 7;
+
+/// A `counter_start` flag that sets the counter to the initial value first.
+pub const START_FLAG_SET_INIT_VALUE: usize = 1 << 0;
+
+/// A `counter_stop` flag that releases the counter and the event it was
+/// configured for.
+pub const STOP_FLAG_RESET: usize = 1 << 0;
+
+/// The event type of the general hardware events, such as cycles and retired
+/// instructions.
+pub const EVENT_TYPE_HARDWARE_GENERAL: usize = 0;
+
+/// The event type of the hardware cache events.
+pub const EVENT_TYPE_HARDWARE_CACHE: usize = 1;
+
+/// The event type of the events the SBI implementation counts in firmware.
+pub const EVENT_TYPE_FIRMWARE: usize = 15;
+
+/// Builds an event index from an event type and code.
+///
+/// # Arguments
+///
+/// * `event_type` - One of the `EVENT_TYPE_*` constants.
+/// * `event_code` - The event within the type.
+pub const fn event_index(event_type: usize, event_code: usize) -> usize {
+    (event_type << 16) | (event_code & 0xffff)
+}
+
+/// What a counter is and how to read it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CounterInfo {
+    raw: usize,
+}
+
+impl CounterInfo {
+    /// Decodes the value returned by `counter_get_info`.
+    pub const fn from_raw(raw: usize) -> Self {
+        Self { raw }
+    }
+
+    /// Returns true if the SBI implementation keeps the counter in firmware,
+    /// where it is read with `counter_fw_read`.
+    pub const fn is_firmware(&self) -> bool {
+        self.raw >> (usize::BITS - 1) != 0
+    }
+
+    /// Returns the CSR number of a hardware counter.
+    pub const fn csr(&self) -> usize {
+        self.raw & 0xfff
+    }
+
+    /// Returns the number of bits a hardware counter holds.
+    pub const fn width(&self) -> u32 {
+        ((self.raw >> 12) & 0x3f) as u32 + 1
+    }
+}
+
+/// Returns the number of counters, hardware and firmware together. Counters
+/// are numbered from zero.
+#[inline(always)]
+pub fn num_counters() -> SbiResult<usize> {
+    to_sbi_result(sbi_call_1(
+        PMU_EXTENSION_ID as isize,
+        NUM_COUNTERS_ID as isize,
+        0,
+    ))
+}
+
+/// Describes a counter.
+///
+/// # Arguments
+///
+/// * `counter_index` - The counter, below `num_counters`.
+#[inline(always)]
+pub fn counter_get_info(counter_index: usize) -> SbiResult<CounterInfo> {
+    to_sbi_result(sbi_call_1(
+        PMU_EXTENSION_ID as isize,
+        COUNTER_GET_INFO_ID as isize,
+        counter_index,
+    ))
+    .map(CounterInfo::from_raw)
+}
+
+/// Finds an unused counter that can count an event and configures it for
+/// the event on the calling hart.
+///
+/// # Arguments
+///
+/// * `counter_index_base` - The counter that bit 0 of `counter_index_mask`
+///   refers to.
+/// * `counter_index_mask` - A bit mask of the counters to choose from.
+/// * `config_flags` - `CONFIG_FLAG_*` constants.
+/// * `event_index` - The event to count, built with `event_index`.
+/// * `event_data` - Extra configuration of some event types, zero otherwise.
+///
+/// # Returns
+///
+/// The index of the configured counter, or `SbiError::NotSupported` if no
+/// counter can count the event.
+#[inline(always)]
+pub fn counter_config_matching(
+    counter_index_base: usize,
+    counter_index_mask: usize,
+    config_flags: usize,
+    event_index: usize,
+    event_data: u64,
+) -> SbiResult<usize> {
+    to_sbi_result(sbi_call_5(
+        PMU_EXTENSION_ID as isize,
+        COUNTER_CONFIG_MATCHING_ID as isize,
+        counter_index_base,
+        counter_index_mask,
+        config_flags,
+        event_index,
+        event_data as usize,
+    ))
+}
+
+/// Starts counters on the calling hart.
+///
+/// # Arguments
+///
+/// * `counter_index_base` - The counter that bit 0 of `counter_index_mask`
+///   refers to.
+/// * `counter_index_mask` - A bit mask of the counters to start.
+/// * `start_flags` - `START_FLAG_*` constants.
+/// * `initial_value` - The value the counters start from with
+///   `START_FLAG_SET_INIT_VALUE`.
+#[inline(always)]
+pub fn counter_start(
+    counter_index_base: usize,
+    counter_index_mask: usize,
+    start_flags: usize,
+    initial_value: u64,
+) -> SbiResult<()> {
+    to_sbi_result(sbi_call_4(
+        PMU_EXTENSION_ID as isize,
+        COUNTER_START_ID as isize,
+        counter_index_base,
+        counter_index_mask,
+        start_flags,
+        initial_value as usize,
+    ))
+    .map(|_| ())
+}
+
+/// Stops counters on the calling hart. Their values stay readable until
+/// they are configured again.
+///
+/// # Arguments
+///
+/// * `counter_index_base` - The counter that bit 0 of `counter_index_mask`
+///   refers to.
+/// * `counter_index_mask` - A bit mask of the counters to stop.
+/// * `stop_flags` - `STOP_FLAG_*` constants.
+#[inline(always)]
+pub fn counter_stop(
+    counter_index_base: usize,
+    counter_index_mask: usize,
+    stop_flags: usize,
+) -> SbiResult<()> {
+    to_sbi_result(sbi_call_3(
+        PMU_EXTENSION_ID as isize,
+        COUNTER_STOP_ID as isize,
+        counter_index_base,
+        counter_index_mask,
+        stop_flags,
+    ))
+    .map(|_| ())
+}
+
+/// Reads a counter the SBI implementation keeps in firmware.
+///
+/// # Arguments
+///
+/// * `counter_index` - A firmware counter, as reported by
+///   `counter_get_info`.
+#[inline(always)]
+pub fn counter_fw_read(counter_index: usize) -> SbiResult<u64> {
+    to_sbi_result(sbi_call_1(
+        PMU_EXTENSION_ID as isize,
+        COUNTER_FW_READ_ID as isize,
+        counter_index,
+    ))
+    .map(|value| value as u64)
+}
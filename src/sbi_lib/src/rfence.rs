@@ -0,0 +1,98 @@
+//! Wrappers for the SBI Remote Fence (RFENCE) extension.
+//!
+//! The RFENCE extension executes fence instructions on other harts, which is
+//! needed after page table or instruction memory changes because each hart
+//! caches translations and instructions privately. The remote fences have
+//! completed on every selected hart by the time the call returns.
+
+use super::{
+    SbiResult,
+    sbi_calls::{sbi_call_2, sbi_call_4, sbi_call_5},
+    sbi_error::to_sbi_result,
+};
+
+const RFENCE_EXTENSION_ID: i32 = 0x52464E43;
+
+const REMOTE_FENCE_I_ID: i32 = 0x0;
+const REMOTE_SFENCE_VMA_ID: i32 = 0x1;
+const REMOTE_SFENCE_VMA_ASID_ID: i32 = 0x2;
+
+/// Executes `fence.i` on every hart selected by the mask.
+///
+/// # Arguments
+///
+/// * `hart_mask` - A bit mask of harts, where bit `n` selects hart
+///   `hart_mask_base + n`.
+/// * `hart_mask_base` - The hart ID that bit 0 of `hart_mask` refers to. A
+///   value of `usize::MAX` selects every hart and ignores `hart_mask`.
+#[inline(always)]
+pub fn remote_fence_i(hart_mask: usize, hart_mask_base: usize) -> SbiResult<()> {
+    to_sbi_result(sbi_call_2(
+        RFENCE_EXTENSION_ID as isize,
+        REMOTE_FENCE_I_ID as isize,
+        hart_mask,
+        hart_mask_base,
+    ))
+    .map(|_| ())
+}
+
+/// Executes `sfence.vma` for a range of virtual addresses on every hart
+/// selected by the mask, covering all address spaces.
+///
+/// # Arguments
+///
+/// * `hart_mask` - A bit mask of harts, where bit `n` selects hart
+///   `hart_mask_base + n`.
+/// * `hart_mask_base` - The hart ID that bit 0 of `hart_mask` refers to.
+/// * `start_address` - The first virtual address to flush.
+/// * `size` - The number of bytes to flush. A start address and size of zero,
+///   or a size of `usize::MAX`, flush the entire address space.
+#[inline(always)]
+pub fn remote_sfence_vma(
+    hart_mask: usize,
+    hart_mask_base: usize,
+    start_address: usize,
+    size: usize,
+) -> SbiResult<()> {
+    to_sbi_result(sbi_call_4(
+        RFENCE_EXTENSION_ID as isize,
+        REMOTE_SFENCE_VMA_ID as isize,
+        hart_mask,
+        hart_mask_base,
+        start_address,
+        size,
+    ))
+    .map(|_| ())
+}
+
+/// Executes `sfence.vma` for a range of virtual addresses in a single address
+/// space on every hart selected by the mask.
+///
+/// # Arguments
+///
+/// * `hart_mask` - A bit mask of harts, where bit `n` selects hart
+///   `hart_mask_base + n`.
+/// * `hart_mask_base` - The hart ID that bit 0 of `hart_mask` refers to.
+/// * `start_address` - The first virtual address to flush.
+/// * `size` - The number of bytes to flush, or `usize::MAX` for the entire
+///   address space.
+/// * `asid` - The address space identifier to flush.
+#[inline(always)]
+pub fn remote_sfence_vma_asid(
+    hart_mask: usize,
+    hart_mask_base: usize,
+    start_address: usize,
+    size: usize,
+    asid: usize,
+) -> SbiResult<()> {
+    to_sbi_result(sbi_call_5(
+        RFENCE_EXTENSION_ID as isize,
+        REMOTE_SFENCE_VMA_ASID_ID as isize,
+        hart_mask,
+        hart_mask_base,
+        start_address,
+        size,
+        asid,
+    ))
+    .map(|_| ())
+}
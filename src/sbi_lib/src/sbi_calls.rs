@@ -1,5 +1,3 @@
-#![allow(dead_code)]
-
 #[inline(always)]
 pub fn sbi_call_1(extension_id: isize, function_id: isize, arg0: usize) -> (isize, usize) {
     let error: isize;
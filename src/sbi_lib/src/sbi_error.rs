@@ -0,0 +1,97 @@
+//! Typed results for SBI calls.
+//!
+//! Every SBI function returns a pair of an error code and a value in `a0` and
+//! `a1`. The wrappers in this module's siblings convert that pair into a
+//! `SbiResult` so that callers can use `?` and match on named errors rather
+//! than comparing raw codes.
+
+use core::fmt;
+
+/// The standard error codes defined by the SBI specification.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SbiError {
+    Failed,
+    NotSupported,
+    InvalidParam,
+    Denied,
+    InvalidAddress,
+    AlreadyAvailable,
+    AlreadyStarted,
+    AlreadyStopped,
+    NoSharedMemory,
+    InvalidState,
+    BadRange,
+    Timeout,
+    Io,
+    Unknown(isize),
+}
+
+/// The result of an SBI call.
+pub type SbiResult<T> = Result<T, SbiError>;
+
+impl SbiError {
+    /// Decodes a non-zero SBI error code.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The raw error code returned in `a0`.
+    ///
+    /// # Returns
+    ///
+    /// The decoded error. Codes that the specification does not define are
+    /// returned as `Unknown` with the raw code.
+    pub const fn from_code(code: isize) -> Self {
+        match code {
+            -1 => Self::Failed,
+            -2 => Self::NotSupported,
+            -3 => Self::InvalidParam,
+            -4 => Self::Denied,
+            -5 => Self::InvalidAddress,
+            -6 => Self::AlreadyAvailable,
+            -7 => Self::AlreadyStarted,
+            -8 => Self::AlreadyStopped,
+            -9 => Self::NoSharedMemory,
+            -10 => Self::InvalidState,
+            -11 => Self::BadRange,
+            -12 => Self::Timeout,
+            -13 => Self::Io,
+            _ => Self::Unknown(code),
+        }
+    }
+}
+
+impl fmt::Display for SbiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed => write!(f, "failed"),
+            Self::NotSupported => write!(f, "not supported"),
+            Self::InvalidParam => write!(f, "invalid parameter"),
+            Self::Denied => write!(f, "denied"),
+            Self::InvalidAddress => write!(f, "invalid address"),
+            Self::AlreadyAvailable => write!(f, "already available"),
+            Self::AlreadyStarted => write!(f, "already started"),
+            Self::AlreadyStopped => write!(f, "already stopped"),
+            Self::NoSharedMemory => write!(f, "shared memory not available"),
+            Self::InvalidState => write!(f, "invalid state"),
+            Self::BadRange => write!(f, "bad range"),
+            Self::Timeout => write!(f, "timed out"),
+            Self::Io => write!(f, "input/output error"),
+            Self::Unknown(code) => write!(f, "unknown error {}", code),
+        }
+    }
+}
+
+/// Converts the raw error code and value returned by an SBI call into a
+/// `SbiResult`.
+///
+/// # Arguments
+///
+/// * `(error, value)` - The pair returned by one of the `sbi_call_*`
+///   functions.
+pub fn to_sbi_result((error, value): (isize, usize)) -> SbiResult<usize> {
+    if error == 0 {
+        Ok(value)
+    } else {
+        Err(SbiError::from_code(error))
+    }
+}
@@ -0,0 +1,83 @@
+//! Wrappers for the SBI System Reset (SRST) extension.
+//!
+//! The SRST extension lets supervisor software shut down or reboot the whole
+//! system. Under QEMU a shutdown exits the emulator.
+
+use super::{SbiError, sbi_calls::sbi_call_2, sbi_error::to_sbi_result};
+
+const SYSTEM_RESET_EXTENSION_ID: i32 = 0x53525354;
+
+const SYSTEM_RESET_ID: i32 = 0x0;
+
+/// Powers off the system.
+pub const RESET_TYPE_SHUTDOWN: u32 = 0x0000_0000;
+
+/// Physically power cycles the system.
+pub const RESET_TYPE_COLD_REBOOT: u32 = 0x0000_0001;
+
+/// Reboots the harts and some parts of the system without a power cycle.
+pub const RESET_TYPE_WARM_REBOOT: u32 = 0x0000_0002;
+
+/// The reset was requested without a particular reason.
+pub const RESET_REASON_NO_REASON: u32 = 0x0000_0000;
+
+/// The reset was requested because of a system failure.
+pub const RESET_REASON_SYSTEM_FAILURE: u32 = 0x0000_0001;
+
+/// Resets the system. This call does not return when it succeeds.
+///
+/// # Arguments
+///
+/// * `reset_type` - The kind of reset, such as `RESET_TYPE_SHUTDOWN`.
+/// * `reset_reason` - Why the reset was requested, such as
+///   `RESET_REASON_NO_REASON`.
+///
+/// # Returns
+///
+/// The reason the reset could not be performed.
+#[inline(always)]
+pub fn system_reset(reset_type: u32, reset_reason: u32) -> SbiError {
+    let result = to_sbi_result(sbi_call_2(
+        SYSTEM_RESET_EXTENSION_ID as isize,
+        SYSTEM_RESET_ID as isize,
+        reset_type as usize,
+        reset_reason as usize,
+    ));
+
+    // A successful reset never returns, so any return is a failure.
+    match result {
+        Ok(_) => SbiError::Failed,
+        Err(error) => error,
+    }
+}
+
+/// Powers off the system.
+///
+/// # Arguments
+///
+/// * `reset_reason` - Why the shutdown was requested.
+///
+/// # Returns
+///
+/// The reason the shutdown could not be performed.
+pub fn shutdown(reset_reason: u32) -> SbiError {
+    system_reset(RESET_TYPE_SHUTDOWN, reset_reason)
+}
+
+/// Power cycles the system.
+///
+/// # Returns
+///
+/// The reason the reboot could not be performed.
+pub fn cold_reboot() -> SbiError {
+    system_reset(RESET_TYPE_COLD_REBOOT, RESET_REASON_NO_REASON)
+}
+
+/// Reboots the system without a power cycle.
+///
+/// # Returns
+///
+/// The reason the reboot could not be performed.
+pub fn warm_reboot() -> SbiError {
+    system_reset(RESET_TYPE_WARM_REBOOT, RESET_REASON_NO_REASON)
+}
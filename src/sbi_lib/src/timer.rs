@@ -0,0 +1,30 @@
+//! Wrappers for the SBI Timer (TIME) extension.
+//!
+//! The TIME extension lets supervisor software program the next timer event
+//! on the calling hart without access to the machine mode timer registers.
+
+use super::{SbiResult, sbi_calls::sbi_call_1, sbi_error::to_sbi_result};
+
+const TIMER_EXTENSION_ID: i32 = 0x54494D45;
+
+const SET_TIMER_ID: i32 = 0x0;
+
+/// Programs the clock for the next timer event on the calling hart.
+///
+/// The supervisor timer interrupt becomes pending once the `time` CSR reaches
+/// `stime_value`, and the pending bit is cleared by the next call to this
+/// function. To cancel a timer event without raising an interrupt, pass
+/// `u64::MAX`.
+///
+/// # Arguments
+///
+/// * `stime_value` - The absolute time, in timebase ticks, of the next event.
+#[inline(always)]
+pub fn set_timer(stime_value: u64) -> SbiResult<()> {
+    to_sbi_result(sbi_call_1(
+        TIMER_EXTENSION_ID as isize,
+        SET_TIMER_ID as isize,
+        stime_value as usize,
+    ))
+    .map(|_| ())
+}
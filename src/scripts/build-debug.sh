@@ -8,13 +8,25 @@ set -x
 
 cd "$(dirname "$0")/.."
 
-export RUSTFLAGS="-C relocation-model=pic --emit=asm"
+# The kernel is linked at the base address of the virtual memory layout the
+# boot stage and the kernel share. It is linked as a position independent
+# executable, so the boot stage can slide it higher.
+KERNEL_BASE_VIRTUAL_ADDRESS=$(sed -n \
+    's/^pub const KERNEL_BASE_VIRTUAL_ADDRESS: usize = \(0x[0-9A-Fa-f_]*\);.*/\1/p' \
+    common_lib/src/layout/mod.rs | tr -d _)
 
+test -n "$KERNEL_BASE_VIRTUAL_ADDRESS"
+
+export RUSTFLAGS="-C relocation-model=pic --emit=asm -C force-frame-pointers=yes"
+
+# KERNEL_FEATURES selects kernel features, such as "kernel-tests" to build a
+# kernel that runs its tests and exits QEMU.
 cargo build \
     --target riscv64gc-unknown-none-elf \
-    --package kernel
+    --package kernel \
+    --features "${KERNEL_FEATURES:-}"
 
-export RUSTFLAGS="-C relocation-model=static --emit=asm"
+export RUSTFLAGS="-C relocation-model=static --emit=asm -C force-frame-pointers=yes"
 
 cargo build \
     --target riscv64gc-unknown-none-elf \
@@ -23,32 +35,63 @@ cargo build \
 riscv64-unknown-elf-ld \
     --gc-sections \
     --no-print-gc-sections \
+    -pie \
+    --no-dynamic-linker \
     -T kernel/linker.ld \
-    -o target/riscv64gc-unknown-none-elf/debug/libkernel.elf \
+    --defsym=_kernel_base_virtual_address=$KERNEL_BASE_VIRTUAL_ADDRESS \
+    -o target/riscv64gc-unknown-none-elf/debug/libkernel.unsymbolized.elf \
     target/riscv64gc-unknown-none-elf/debug/libkernel.a
 
-riscv64-unknown-elf-objcopy \
-    -O binary \
+# Link the kernel a second time with its symbol table embedded for symbolized
+# backtraces. The table is the last section, so no code may move.
+scripts/generate-symbol-table.sh \
+    target/riscv64gc-unknown-none-elf/debug/libkernel.unsymbolized.elf \
+    target/riscv64gc-unknown-none-elf/debug/kernel_symbols.S
+
+riscv64-unknown-elf-as \
+    -march=rv64gc \
+    -mabi=lp64d \
+    -o target/riscv64gc-unknown-none-elf/debug/kernel_symbols.o \
+    target/riscv64gc-unknown-none-elf/debug/kernel_symbols.S
+
+riscv64-unknown-elf-ld \
+    --gc-sections \
+    --no-print-gc-sections \
+    -pie \
+    --no-dynamic-linker \
+    -T kernel/linker.ld \
+    --defsym=_kernel_base_virtual_address=$KERNEL_BASE_VIRTUAL_ADDRESS \
+    -o target/riscv64gc-unknown-none-elf/debug/libkernel.elf \
+    target/riscv64gc-unknown-none-elf/debug/libkernel.a \
+    target/riscv64gc-unknown-none-elf/debug/kernel_symbols.o
+
+cmp \
+    <(riscv64-unknown-elf-nm --numeric-sort target/riscv64gc-unknown-none-elf/debug/libkernel.unsymbolized.elf | grep -i " t ") \
+    <(riscv64-unknown-elf-nm --numeric-sort target/riscv64gc-unknown-none-elf/debug/libkernel.elf | grep -i " t ")
+
+# Embed the kernel ELF in the boot stage, which loads its segments and maps
+# each of them with the permissions in its program header.
+printf '    .section .kernel_elf, "a"\n    .incbin "%s"\n' \
     target/riscv64gc-unknown-none-elf/debug/libkernel.elf \
-    target/riscv64gc-unknown-none-elf/debug/libkernel.bin
+    > target/riscv64gc-unknown-none-elf/debug/kernel_elf.S
 
-KERNEL_SIZE=$(stat -c %s target/riscv64gc-unknown-none-elf/debug/libkernel.bin)
+riscv64-unknown-elf-as \
+    -march=rv64gc \
+    -mabi=lp64d \
+    -o target/riscv64gc-unknown-none-elf/debug/kernel_elf.o \
+    target/riscv64gc-unknown-none-elf/debug/kernel_elf.S
 
 riscv64-unknown-elf-ld \
     --gc-sections \
     --no-print-gc-sections \
     -T boot/linker.ld \
-    --defsym=_kernel_size=$KERNEL_SIZE \
     -o target/riscv64gc-unknown-none-elf/debug/libboot.elf \
-    target/riscv64gc-unknown-none-elf/debug/libboot.a
+    target/riscv64gc-unknown-none-elf/debug/libboot.a \
+    target/riscv64gc-unknown-none-elf/debug/kernel_elf.o
 
 riscv64-unknown-elf-objcopy \
     -O binary \
     target/riscv64gc-unknown-none-elf/debug/libboot.elf \
-    target/riscv64gc-unknown-none-elf/debug/libboot.bin
-
-cat target/riscv64gc-unknown-none-elf/debug/libboot.bin \
-    target/riscv64gc-unknown-none-elf/debug/libkernel.bin \
-    > target/riscv64gc-unknown-none-elf/debug/kernel.bin
+    target/riscv64gc-unknown-none-elf/debug/kernel.bin
 
 echo "BUILD SUCCESSFUL"
\ No newline at end of file
@@ -0,0 +1,71 @@
+#!/bin/bash
+
+# Generates an assembly file holding the kernel symbol table read by the
+# kernel's symbols module. See kernel_lib/src/symbol_table for the format.
+#
+# Usage: generate-symbol-table.sh <kernel elf> <output assembly file>
+
+# Exit immediately if a command exits with a non-zero status.
+set -e
+
+KERNEL_ELF="$1"
+OUTPUT="$2"
+
+# List the defined code symbols sorted by address with demangled names. Each
+# line is "address size type name", where the size is missing for labels
+# defined in assembly. Names may contain spaces.
+riscv64-unknown-elf-nm \
+    --defined-only \
+    --numeric-sort \
+    --print-size \
+    --demangle \
+    "$KERNEL_ELF" \
+    | awk '
+        BEGIN {
+            count = 0
+            name_offset = 0
+        }
+        $0 ~ /^[0-9a-f]+ [0-9a-f]+ [tT] / {
+            address = $1; size = $2; name = $0
+            sub(/^[^ ]+ [^ ]+ [^ ]+ /, "", name)
+        }
+        $0 ~ /^[0-9a-f]+ [tT] / {
+            address = $1; size = "0"; name = $0
+            sub(/^[^ ]+ [^ ]+ /, "", name)
+        }
+        $0 !~ /^[0-9a-f]+ ([0-9a-f]+ )?[tT] / || name ~ /^\$/ {
+            next
+        }
+        {
+            addresses[count] = address
+            sizes[count] = size
+            name_offsets[count] = name_offset
+            name_offset += length(name) + 1
+
+            # Rust names never contain these, but they would end the string.
+            gsub(/["\\]/, "_", name)
+            names[count] = name
+
+            count++
+        }
+        END {
+            print "    .section .kernel_symbols, \"a\""
+            print "    .balign 8"
+            print ""
+            print "    .word 0x4d59534b    # Magic, \"KSYM\"."
+            print "    .word " count "    # Symbol count."
+            print ""
+
+            for (i = 0; i < count; i++) {
+                print "    .quad 0x" addresses[i]
+                print "    .word 0x" sizes[i]
+                print "    .word " name_offsets[i]
+            }
+
+            print ""
+
+            for (i = 0; i < count; i++) {
+                print "    .asciz \"" names[i] "\""
+            }
+        }
+    ' > "$OUTPUT"
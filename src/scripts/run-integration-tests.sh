@@ -0,0 +1,125 @@
+#!/bin/bash
+
+# Boots the kernel in QEMU and checks its console output. The kernel is built
+# with the "kernel-tests" feature, so it runs its kernel tests once it is
+# initialized and exits QEMU with their outcome.
+#
+# The run passes if every marker in EXPECTED_MARKERS appears on the console in
+# order, nothing in FAILURE_MARKERS appears, and QEMU exits with status zero
+# before the timeout.
+#
+# Usage: run-integration-tests.sh [timeout in seconds]
+
+# Exit immediately if a command exits with a non-zero status.
+set -e
+
+cd "$(dirname "$0")/.."
+
+TIMEOUT_SECONDS="${1:-60}"
+OPENSBI_FIRMWARE=/opt/opensbi/share/opensbi/lp64/generic/firmware/fw_jump.bin
+KERNEL_BINARY=target/riscv64gc-unknown-none-elf/debug/kernel.bin
+CONSOLE_LOG=target/riscv64gc-unknown-none-elf/debug/integration-tests.log
+
+# The lines the boot stage and the kernel print on the way to a passing run.
+EXPECTED_MARKERS=(
+    "Kernel booting on hart ID:"
+    "MMU activated with"
+    "Welcome to the kernel!"
+    "Running "
+    "Kernel test result: ok."
+)
+
+# Lines that fail the run as soon as they appear.
+FAILURE_MARKERS=(
+    "BOOT PANIC"
+    "KERNEL PANIC"
+    "Kernel test result: FAILED."
+)
+
+command -v qemu-system-riscv64 >/dev/null 2>&1 || { echo "QEMU RISC-V not installed"; exit 1; }
+[ -f "$OPENSBI_FIRMWARE" ] || { echo "OpenSBI firmware not found"; exit 1; }
+
+KERNEL_FEATURES=kernel-tests scripts/build-debug.sh
+
+# Returns success if any failure marker is in the console log.
+has_failure_marker() {
+    local marker
+
+    for marker in "${FAILURE_MARKERS[@]}"; do
+        grep -q -F "$marker" "$CONSOLE_LOG" && return 0
+    done
+
+    return 1
+}
+
+qemu-system-riscv64 \
+    -nographic \
+    -machine virt \
+    -cpu rv64 \
+    -smp 1 \
+    -m 256M \
+    -no-reboot \
+    -bios "$OPENSBI_FIRMWARE" \
+    -kernel "$KERNEL_BINARY" \
+    < /dev/null \
+    > "$CONSOLE_LOG" 2>&1 &
+
+QEMU_PID=$!
+FAILURE=""
+
+# Poll the console instead of waiting for QEMU, since a panicking boot stage
+# halts the hart and leaves QEMU running.
+SECONDS=0
+while kill -0 "$QEMU_PID" 2>/dev/null; do
+    if has_failure_marker; then
+        FAILURE="the console reported a failure"
+        break
+    fi
+
+    if [ "$SECONDS" -ge "$TIMEOUT_SECONDS" ]; then
+        FAILURE="QEMU did not exit within $TIMEOUT_SECONDS seconds"
+        break
+    fi
+
+    sleep 0.2
+done
+
+if [ -n "$FAILURE" ]; then
+    kill "$QEMU_PID" 2>/dev/null || true
+fi
+
+QEMU_STATUS=0
+wait "$QEMU_PID" || QEMU_STATUS=$?
+
+cat "$CONSOLE_LOG"
+echo
+
+if [ -z "$FAILURE" ] && has_failure_marker; then
+    FAILURE="the console reported a failure"
+fi
+
+# Every marker has to appear after the one before it.
+PREVIOUS_LINE=0
+for marker in "${EXPECTED_MARKERS[@]}"; do
+    [ -n "$FAILURE" ] && break
+
+    LINE=$(tail -n "+$((PREVIOUS_LINE + 1))" "$CONSOLE_LOG" | grep -n -m 1 -F "$marker" | cut -d : -f 1)
+
+    if [ -z "$LINE" ]; then
+        FAILURE="\"$marker\" did not appear on the console"
+        break
+    fi
+
+    PREVIOUS_LINE=$((PREVIOUS_LINE + LINE))
+done
+
+if [ -z "$FAILURE" ] && [ "$QEMU_STATUS" -ne 0 ]; then
+    FAILURE="QEMU exited with status $QEMU_STATUS"
+fi
+
+if [ -n "$FAILURE" ]; then
+    echo "INTEGRATION TESTS FAILED: $FAILURE."
+    exit 1
+fi
+
+echo "INTEGRATION TESTS PASSED"